@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use merkle::merkel::{Data, TreeArena, TreeConfig};
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Builds per run, as many small trees as a service builds in a few seconds, one per block
+const BUILDS: usize = 10_000;
+const LEAVES: usize = 128;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn build_all(blocks: &[Vec<Data>], arena: Option<&mut TreeArena>) {
+    let config = TreeConfig::default();
+    match arena {
+        Some(arena) => blocks.iter().for_each(|block| {
+            black_box(config.construct_in(block, arena).unwrap().root());
+        }),
+        None => blocks.iter().for_each(|block| {
+            black_box(config.construct(block).unwrap().root());
+        }),
+    }
+}
+
+fn bench_arena(c: &mut Criterion) {
+    let blocks: Vec<Vec<Data>> = (0..BUILDS)
+        .map(|block| (0..LEAVES).map(|i| ((block * LEAVES + i) as u64).to_le_bytes().to_vec()).collect())
+        .collect();
+    let mut arena = TreeArena::with_capacity(LEAVES);
+    for (name, arena) in [("construct", None), ("construct_in", Some(&mut arena))] {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        build_all(&blocks, arena);
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!("{}: {} allocations for {} builds of {} leaves", name, allocations, BUILDS, LEAVES);
+    }
+
+    let mut group = c.benchmark_group("arena");
+    group.sample_size(10);
+    group.bench_function("construct", |b| b.iter(|| build_all(&blocks, None)));
+    group.bench_function("construct_in", |b| b.iter(|| build_all(&blocks, Some(&mut arena))));
+    group.finish();
+}
+
+criterion_group!(benches, bench_arena);
+criterion_main!(benches);
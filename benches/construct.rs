@@ -0,0 +1,21 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use merkle::merkel::MerkleTree;
+
+use std::hint::black_box;
+
+/// Sizes just below, at and above a power of two, where padding has the most effect
+const SIZES: [usize; 3] = [(1 << 16) - 1, 1 << 16, (1 << 16) + 1];
+
+fn bench_construct(c: &mut Criterion) {
+    let mut group = c.benchmark_group("construct");
+    for size in SIZES {
+        let data: Vec<Vec<u8>> = (0..size).map(|i| (i as u64).to_le_bytes().to_vec()).collect();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.iter(|| black_box(MerkleTree::construct(data)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_construct);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use merkle::merkel::{MerkleTree, ProofCache};
+
+use std::hint::black_box;
+
+const LEAVES: usize = 1 << 16;
+
+fn bench_proof_cache(c: &mut Criterion) {
+    let data: Vec<Vec<u8>> = (0..LEAVES).map(|i| (i as u64).to_le_bytes().to_vec()).collect();
+    let tree = MerkleTree::construct(&data);
+    let cache = ProofCache::new(tree.clone(), 1024);
+    for i in 0..1024 {
+        cache.get_or_generate(i).unwrap();
+    }
+
+    let mut group = c.benchmark_group("proof");
+    group.bench_function("generate", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % 1024;
+            black_box(tree.prove_by_index(i).unwrap().into_owned())
+        })
+    });
+    group.bench_function("cached", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % 1024;
+            black_box(cache.get_or_generate(i).unwrap())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_proof_cache);
+criterion_main!(benches);
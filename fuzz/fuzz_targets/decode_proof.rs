@@ -0,0 +1,36 @@
+//! Feeds arbitrary bytes to every proof decoder, run with `cargo fuzz run decode_proof`
+//!
+//! The first byte picks the decoder. Decoding may fail but must not panic, and a proof that
+//! decodes must encode back to something that decodes to the same proof.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use merkle::merkel::{DefaultHashes, OwnedProof};
+
+fuzz_target!(|input: &[u8]| {
+    let Some((decoder, bytes)) = input.split_first() else {
+        return;
+    };
+    match decoder % 3 {
+        0 => {
+            let defaults = DefaultHashes::for_leaf(&Vec::new(), 8);
+            if let Ok(proof) = OwnedProof::from_bytes_compressed(bytes, &defaults) {
+                let encoded = proof.to_bytes_compressed(&defaults).unwrap();
+                assert_eq!(OwnedProof::from_bytes_compressed(&encoded, &defaults).unwrap(), proof);
+            }
+        }
+        1 => {
+            if let Ok(proof) = OwnedProof::from_cbor(bytes) {
+                assert_eq!(OwnedProof::from_cbor(&proof.to_cbor().unwrap()).unwrap(), proof);
+            }
+        }
+        _ => {
+            if let Ok(proof) = serde_json::from_slice::<OwnedProof>(bytes) {
+                let json = serde_json::to_vec(&proof).unwrap();
+                assert_eq!(serde_json::from_slice::<OwnedProof>(&json).unwrap(), proof);
+            }
+        }
+    }
+});
@@ -0,0 +1,19 @@
+//! Runs the whole pipeline on cases decoded from arbitrary bytes, run with
+//! `cargo fuzz run pipeline_replay`
+//!
+//! Each input becomes a `PipelineCase` through `PipelineCase::from_fuzz_bytes`, which is built,
+//! proved, tampered with and serialized. A case that breaks an invariant panics with the case as
+//! JSON, ready to be fixed and committed under `tests/corpus` for `tests/pipeline_replay.rs`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use merkle::util::pipeline::PipelineCase;
+
+fuzz_target!(|input: &[u8]| {
+    let case = PipelineCase::from_fuzz_bytes(input);
+    if let Err(violation) = case.run() {
+        panic!("{}\n{}", violation, serde_json::to_string_pretty(&case).unwrap());
+    }
+});
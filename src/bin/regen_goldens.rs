@@ -0,0 +1,55 @@
+//! `regen-goldens`: rewrites the golden root registry checked by `tests/golden_roots.rs`
+//!
+//! Only for deliberate format changes, since every root it moves is a root users can no longer
+//! rebuild; it refuses to run without `--i-know-this-breaks-compat`. Run it with
+//! `cargo run --features test-utils --bin regen-goldens -- --i-know-this-breaks-compat`.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use merkle::util::golden::{golden_roots, read_golden_roots, write_golden_roots, GOLDEN_ROOTS_FILE};
+
+const USAGE: &str = "usage: regen-goldens --i-know-this-breaks-compat [--out <registry.json>]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (mut confirmed, mut out) = (false, PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(GOLDEN_ROOTS_FILE));
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.clone().next()) {
+            ("--i-know-this-breaks-compat", _) => confirmed = true,
+            ("--out", Some(path)) => {
+                out = PathBuf::from(path);
+                args.next();
+            }
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(1);
+            }
+        }
+    }
+    if !confirmed {
+        eprintln!("refusing to rewrite {}: every root it changes breaks compatibility", out.display());
+        eprintln!("{}", USAGE);
+        return ExitCode::from(1);
+    }
+
+    let roots = match golden_roots() {
+        Ok(roots) => roots,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::from(e.exit_status());
+        }
+    };
+    // a registry that cannot be read is replaced whole
+    let old = read_golden_roots(&out).unwrap_or_default();
+    for root in roots.iter().filter(|root| !old.contains(root)) {
+        println!("changed: {} is now {}", root.name(), hex::encode(&root.root));
+    }
+    if let Err(e) = write_golden_roots(&out, &roots) {
+        eprintln!("error: {}", e);
+        return ExitCode::from(e.exit_status());
+    }
+    println!("wrote {} golden roots to {}", roots.len(), out.display());
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,13 @@
+//! Merkle trees over hashes, and the proofs of their leaves
+//!
+//! Output is deterministic: the same input gives the same roots, proofs, reports and JSON, byte
+//! for byte, on every run. Hash maps are only used for lookups, and every list derived from one,
+//! such as `LintReport::duplicates`, is sorted as its docs say before it is returned.
+
+#![deny(clippy::unwrap_used, clippy::expect_used)]
+
+pub mod merkel;
+pub mod prelude;
+pub mod util;
+
+pub use merkel::{self_test_hashers, SelfTestError};
@@ -1,87 +1,1963 @@
-use sha2::{Sha256, Digest};
-use hex;
-
 use std::env;
 use std::fs::File;
-use std::io::{Result, Write};
+use std::io::{BufRead, BufReader, Result, Write};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use merkle::merkel::{
+    detect_scheme, fold_root, BuildMetrics, CanonicalOrdering, Data, EmptyLeafPolicy, Hash, HashAlgorithm,
+    HashDirection, LeafMode, MerkleHasher, MerkleTree, OwnedProof, ProofVersion, RootSet, TreeConfig, MAX_PROOF_STEPS,
+};
+use merkle::self_test_hashers;
+use merkle::util::archive::ProofArchive;
+use merkle::util::bundle::{verify_bundle_parallel, ProofBundle};
+use merkle::util::diff::{diff_files, DiffKind, DiffOptions};
+use merkle::util::encoder::LeafEncoder;
+use merkle::util::encoding::{Encoding, HashEncoding};
+use merkle::util::error::{ExitStatus, LibError};
+use merkle::util::generate::{
+    decode_hashes_lenient_with, decode_hashes_with, generate_string, read_data_lines, read_hashes_from_file,
+    RejectedLine,
+};
+use merkle::util::hex_policy::HexPolicy;
+use merkle::util::io::{remove_orphaned_temp, write_atomic};
+use merkle::util::lint::{lint_hashes_of_len, LineIssue, LintReport, MAX_REPORTED_LINES};
+use merkle::util::spill::canonicalize_to_tempfiles_with;
+
+#[cfg(feature = "http")]
+mod serve;
+
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Human,
+    Json,
+}
+
+const HELP: &str = "\
+merkle: Merkle trees over hash files, with inclusion proofs
+
+usage: merkle <command> [args]
+
+commands:
+  root <file>...          print the root of hash files read in order, or follow one with --watch
+                          --input sqlite:<db>?query=<sql> reads the hashes from a query with ORDER BY
+                          instead, or sqlite:<db>?table=<table>[&column=<column>] (needs the `sqlite` feature)
+                          --input-format raw-fixed:<width> reads one file of back-to-back binary hashes
+                          instead (needs the `mmap` feature)
+  chunk-root <file>       print the root of the fixed-size chunks of any file
+  prove <file>            print the inclusion proof of a leaf, or with --leaves-file one per line of NDJSON
+  verify-proof <proof>    check a proof from `merkle prove`, or a bundle with --bundle, against trusted roots
+  prove-data <file>       print the inclusion proof of a line of a text file
+  verify-data <proof>     check a proof from `merkle prove-data`
+  compare <file>          print the first line of a text file that differs from a hash file
+  diff <old> <new>        list the leaves that differ between two hash files
+  detect --input <file>   find how another implementation built a --root from the leaves of a file
+  verify-bundle <bundle>  check every proof of a large bundle against a trusted root on --threads threads
+  export-proofs <file>    write a bundle of the proofs of every leaf of a hash file
+  archive build|get       precompute every proof of a hash file into an archive, or read one back
+  lint <file>             report problems in a hash file
+  stats <file>            print how many nodes the tree of a hash file has and the memory they take
+  generate <file>         write random hashes to a file
+  export-solidity <json>  write airdrop claims and their verifier contract (needs the `solidity` feature)
+  sign-root <file>        sign the root and leaf count of a hash file (needs the `sign` feature)
+  verify-signed           check a hash file against a signed root (needs the `sign` feature)
+  serve <file>            serve the root and proofs over HTTP (needs the `http` feature)
 
-mod util;
-use util::error::LibError;
-use util::generate::{generate_string, read_hashes_from_file};
+options:
+  -h, --help              print this help
+  -V, --version           print the version
+  --hash <hasher>         sha256 (default), sha512, ripemd160, sha256-rfc6962 or sha256d, for commands reading hashes
+  --encoding <encoding>   hex (default), base64url, base58btc or multibase, for hashes of root, prove and verify-proof
+  --lenient-hex           accept uppercase hex and surrounding whitespace in hashes, for commands reading hashes
+  --allow-empty           hash blank lines as empty leaves rather than refuse them, for commands reading text
+  --keep-trailing-empty   hash blank lines at the end of a file of text too, rather than drop them
+  --error-json            print errors to stderr as JSON objects with a code, a line and a message
+  --skip-self-test        do not check the hashers against known answers before running the command
+
+exit codes:
+  0  success
+  1  bad arguments, or input that does not parse
+  2  a proof, signature, comparison or lint did not pass
+  3  the leaf, index or epoch is not in the tree
+  4  a file could not be read or written
+  5  the input is of another format version or hasher
+  6  lines were rejected under --fail-on-reject
+  7  the input exceeds a limit
+  8  a hasher failed its self-test
+";
+
+/// Set by `--error-json`, which any command accepts
+static ERROR_JSON: AtomicBool = AtomicBool::new(false);
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+async fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().collect();
+    let before = args.len();
+    args.retain(|arg| arg != "--error-json");
+    ERROR_JSON.store(args.len() < before, Ordering::Relaxed);
+    let before = args.len();
+    args.retain(|arg| arg != "--skip-self-test");
+    if args.len() == before {
+        if let Err(e) = self_test_hashers() {
+            return lib_error(e.into());
+        }
+    }
+
+    match run(&args).await {
+        Ok(code) => code,
+        Err(e) => lib_error(LibError::Io(e)),
+    }
+}
+
+async fn run(args: &[String]) -> Result<ExitCode> {
 
     match args.get(1).map(|s| s.as_str()) {
-        Some("1") => entry_point_1().await?,
-        Some("2") => entry_point_1().await?,
-        _ => basic_entry().await?,
+        Some("-h" | "--help" | "help") => print!("{}", HELP),
+        Some("-V" | "--version") => println!("merkle {}", env!("CARGO_PKG_VERSION")),
+        Some(mode @ ("1" | "2")) => {
+            eprintln!("warning: `merkle {}` is deprecated, use `merkle generate ts_hashes.json`", mode);
+            return generate_entry(&["ts_hashes.json".to_string()]).await;
+        }
+        Some("generate") => return generate_entry(&args[2..]).await,
+        Some("lint") => return lint_entry(&args[2..]).await,
+        Some("stats") => return stats_entry(&args[2..]).await,
+        Some("root") => return root_entry(&args[2..]).await,
+        Some("chunk-root") => return chunk_root_entry(&args[2..]).await,
+        Some("prove") => return prove_entry(&args[2..]).await,
+        Some("verify-proof") => return verify_proof_entry(&args[2..]).await,
+        Some("prove-data") => return prove_data_entry(&args[2..]).await,
+        Some("verify-data") => return verify_data_entry(&args[2..]).await,
+        Some("compare") => return compare_entry(&args[2..]).await,
+        Some("diff") => return diff_entry(&args[2..]).await,
+        Some("detect") => return detect_entry(&args[2..]).await,
+        Some("verify-bundle") => return verify_bundle_entry(&args[2..]).await,
+        Some("export-proofs") => return export_proofs_entry(&args[2..]).await,
+        Some("archive") => return archive_entry(&args[2..]).await,
+        #[cfg(feature = "solidity")]
+        Some("export-solidity") => return export_solidity_entry(&args[2..]).await,
+        #[cfg(not(feature = "solidity"))]
+        Some("export-solidity") => {
+            return Ok(usage_error("`export-solidity` needs merkle to be built with the `solidity` feature"))
+        }
+        #[cfg(feature = "sign")]
+        Some("sign-root") => return sign_root_entry(&args[2..]).await,
+        #[cfg(feature = "sign")]
+        Some("verify-signed") => return verify_signed_entry(&args[2..]).await,
+        #[cfg(not(feature = "sign"))]
+        Some(command @ ("sign-root" | "verify-signed")) => {
+            return Ok(usage_error(&format!("`{}` needs merkle to be built with the `sign` feature", command)))
+        }
+        #[cfg(feature = "http")]
+        Some("serve") => return serve_entry(&args[2..]).await,
+        #[cfg(not(feature = "http"))]
+        Some("serve") => return Ok(usage_error("`serve` needs merkle to be built with the `http` feature")),
+        Some(other) => return Ok(usage_error(&format!("unknown command `{}`, see `merkle --help`", other))),
+        None => {
+            eprintln!(
+                "warning: running `merkle` without a command is deprecated, \
+                 use `merkle root ts_hashes.json --hash sha256d`"
+            );
+            basic_entry().await?
+        }
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
-async fn basic_entry() -> Result<()>{
+/// Prints an error to stderr, as `error: <message>` or as JSON under `--error-json`
+fn report_error(code: &str, line: Option<usize>, message: &str) {
+    if ERROR_JSON.load(Ordering::Relaxed) {
+        eprintln!("{}", serde_json::json!({ "code": code, "line": line, "message": message }));
+    } else {
+        eprintln!("error: {}", message);
+    }
+}
 
-    let tx_hashes = read_hashes_from_file("ts_hashes.json")?;
-    println!("hashes: {:?}", tx_hashes);
-    let tx_hashes: Vec<Vec<u8>> = tx_hashes.iter()
-        .map(|s| hex::decode(s).expect("Invalid hex"))
+fn usage_error(message: &str) -> ExitCode {
+    report_error("USAGE", None, message);
+    ExitStatus::Usage.into()
+}
+
+fn lib_error(error: LibError) -> ExitCode {
+    report_error(error.code(), error.line(), &error.to_string());
+    error.into()
+}
+
+/// Arguments of a subcommand: positionals, `--name value` options and `--name` switches
+struct ParsedArgs {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+    switches: Vec<String>,
+}
+
+impl ParsedArgs {
+    fn parse(args: &[String], options: &[&str], switches: &[&str]) -> std::result::Result<Self, String> {
+        let mut parsed = ParsedArgs {
+            positional: vec![],
+            options: vec![],
+            switches: vec![],
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if options.contains(&arg.as_str()) {
+                let value = args.next().ok_or(format!("{} expects a value", arg))?;
+                parsed.options.push((arg.clone(), value.clone()));
+            } else if switches.contains(&arg.as_str()) {
+                parsed.switches.push(arg.clone());
+            } else if arg.starts_with("--") {
+                return Err(format!("unknown flag {}", arg));
+            } else {
+                parsed.positional.push(arg.clone());
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Values of every occurrence of an option, in order
+    fn options(&self, name: &str) -> impl Iterator<Item = &str> + '_ {
+        let name = name.to_string();
+        self.options.iter().filter(move |(n, _)| *n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Value of the last occurrence of an option
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|s| s == name)
+    }
+
+    fn output(&self) -> std::result::Result<OutputFormat, String> {
+        match self.option("--output") {
+            None | Some("human") => Ok(OutputFormat::Human),
+            Some("json") => Ok(OutputFormat::Json),
+            Some(other) => Err(format!("--output expects `human` or `json`, got `{}`", other)),
+        }
+    }
+
+    fn hasher(&self) -> std::result::Result<HashAlgorithm, String> {
+        match self.option("--hash") {
+            None => Ok(HashAlgorithm::default()),
+            Some(name) => HashAlgorithm::from_id(name).ok_or_else(|| {
+                let ids: Vec<String> = HashAlgorithm::ALL.iter().map(|hasher| format!("`{}`", hasher.id())).collect();
+                format!("--hash expects one of {}, got `{}`", ids.join(", "), name)
+            }),
+        }
+    }
+
+    fn encoding(&self) -> std::result::Result<Encoding, String> {
+        match self.option("--encoding") {
+            None => Ok(Encoding::default()),
+            Some(name) => Encoding::from_id(name).ok_or_else(|| {
+                let ids: Vec<String> = Encoding::ALL.iter().map(|encoding| format!("`{}`", encoding.id())).collect();
+                format!("--encoding expects one of {}, got `{}`", ids.join(", "), name)
+            }),
+        }
+    }
+
+    fn hex_policy(&self) -> HexPolicy {
+        if self.switch("--lenient-hex") {
+            HexPolicy::Lenient
+        } else {
+            HexPolicy::Strict
+        }
+    }
+}
+
+/// `merkle lint <file> [--hash <hasher>] [--output human|json] [--warn-only]`
+async fn lint_entry(args: &[String]) -> Result<ExitCode> {
+    let args = match ParsedArgs::parse(args, &["--hash", "--output"], &["--warn-only"]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let ([file], Ok(output), Ok(hasher)) = (args.positional.as_slice(), args.output(), args.hasher()) else {
+        return Ok(usage_error(
+            "usage: merkle lint <file> [--hash <hasher>] [--output human|json] [--warn-only]",
+        ));
+    };
+    let warn_only = args.switch("--warn-only");
+
+    let report = lint_hashes_of_len(BufReader::new(File::open(file)?), hasher.output_len())
+        .map_err(std::io::Error::other)?;
+    match output {
+        OutputFormat::Human => print_lint_report(&report),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+
+    if report.has_errors() && !warn_only {
+        return Ok(ExitStatus::VerificationFailed.into());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_lint_report(report: &LintReport) {
+    fn issue(name: &str, issue: &LineIssue) {
+        if issue.count == 0 {
+            return;
+        }
+        let lines: Vec<String> = issue.lines.iter().map(|l| l.to_string()).collect();
+        let more = if issue.count > issue.lines.len() { ", ..." } else { "" };
+        println!("{:<16}{} (lines {}{})", name, issue.count, lines.join(", "), more);
+    }
+
+    println!(
+        "{:<16}{} ({} valid, {} blank)",
+        "lines:", report.total_lines, report.valid_lines, report.blank_lines
+    );
+    issue("invalid hex:", &report.invalid_hex);
+    issue("wrong length:", &report.wrong_length);
+    issue("non-UTF-8:", &report.non_utf8);
+    if report.trailing_blank_lines > 0 {
+        println!("{:<16}{} blank lines at the end of the file", "warning:", report.trailing_blank_lines);
+    }
+    if report.crlf_lines > 0 {
+        println!("{:<16}{} lines end in CRLF", "warning:", report.crlf_lines);
+    }
+    if report.byte_order_mark {
+        println!("{:<16}file starts with a UTF-8 byte order mark", "warning:");
+    }
+    for duplicate in &report.duplicates {
+        println!(
+            "{:<16}{} appears {} times, first on line {}",
+            "duplicate:", duplicate.hash, duplicate.count, duplicate.first_line
+        );
+    }
+    println!("{:<16}{}", "errors:", report.error_count());
+}
+
+/// `merkle stats <file> [--hash <hasher>] [--lenient-hex] [--output human|json]`
+///
+/// Builds the tree of a hash file and prints its `MemoryReport`.
+async fn stats_entry(args: &[String]) -> Result<ExitCode> {
+    let args = match ParsedArgs::parse(args, &["--hash", "--output"], &["--lenient-hex"]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let ([file], Ok(output), Ok(hasher)) = (args.positional.as_slice(), args.output(), args.hasher()) else {
+        return Ok(usage_error("usage: merkle stats <file> [--hash <hasher>] [--lenient-hex] [--output human|json]"));
+    };
+
+    let report = match hash_file_tree_with(file, hasher, args.hex_policy()) {
+        Ok(tree) => tree.memory_usage(),
+        Err(e) => return Ok(lib_error(e)),
+    };
+    match output {
+        OutputFormat::Human => {
+            println!("{:<16}{}", "leaves:", report.leaf_count);
+            println!("{:<16}{}", "nodes:", report.node_count);
+            println!("{:<16}{} bytes", "hashes:", report.hash_bytes);
+            println!("{:<16}{} bytes", "retained data:", report.retained_data_bytes);
+            println!("{:<16}{} bytes (estimated)", "overhead:", report.overhead_bytes_estimate);
+            println!("{:<16}{} bytes", "total:", report.total_bytes());
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `merkle root <file> [--format hex|csv] [--input-format raw-fixed:<width>] [--hash <hasher>]
+/// [--output human|json] [--show-levels] [--dry-run] [--quarantine <file> [--fail-on-reject]]
+/// [--sort-leaves [--memory-budget <bytes>]] [--metrics]`
+///
+/// In `hex` format every line is a leaf hash, as long as the hashes of `--hash`. In `csv` format
+/// every line is a record whose comma-separated fields are combined into one leaf with `LeafEncoder`.
+/// `--input-format raw-fixed:<width>` reads a single binary file of back-to-back leaf hashes of
+/// `<width>` bytes instead.
+/// `--dry-run` only counts the lines and reports what building the tree would take.
+/// `--sort-leaves` sorts the leaves by hash first, so the order of the lines does not matter. With
+/// `--memory-budget` a single hash file is sorted through run files in the temporary directory,
+/// holding at most that many bytes of leaves at once while sorting.
+///
+/// The root is followed by the fingerprint of its `TreeDescriptor`, which the JSON output has in full
+/// under `descriptor`, so the root can be rebuilt without guessing how it was made. `--metrics` adds
+/// a line of `BuildMetrics`, the elapsed time, rates and peak memory of the construction, which the
+/// JSON output has under `metrics`.
+///
+/// With `--quarantine`, hex lines that cannot be decoded are written to the quarantine file as
+/// `<file>:<line>\t<reason>\t<content>` and the root is built over the other lines, with a warning.
+/// `--fail-on-reject` makes any rejected line fail with `ExitStatus::Rejected`, exit code 6, without a root.
+async fn root_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str = "usage: merkle root <file>...|--input <source> [--format hex|csv] \
+                         [--input-format raw-fixed:<width>] [--hash <hasher>] [--output human|json] \
+                         [--show-levels] [--watch] [--dry-run] [--quarantine <file> [--fail-on-reject]] \
+                         [--lenient-hex] [--sort-leaves [--memory-budget <bytes>]] [--metrics] \
+                         [--encoding <encoding>]";
+    let switches =
+        ["--show-levels", "--watch", "--dry-run", "--fail-on-reject", "--lenient-hex", "--sort-leaves", "--metrics"];
+    let options = [
+        "--format",
+        "--hash",
+        "--output",
+        "--quarantine",
+        "--input",
+        "--input-format",
+        "--memory-budget",
+        "--encoding",
+    ];
+    let args = match ParsedArgs::parse(args, &options, &switches) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let (files, Ok(output), Ok(hasher)) = (args.positional.as_slice(), args.output(), args.hasher()) else {
+        return Ok(usage_error(USAGE));
+    };
+    let encoding = match args.encoding() {
+        Ok(encoding) => encoding,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let input = args.option("--input");
+    if files.is_empty() == input.is_none() {
+        return Ok(usage_error(USAGE));
+    }
+    let show_levels = args.switch("--show-levels");
+    if args.switch("--dry-run") && (show_levels || args.switch("--watch")) {
+        return Ok(usage_error("--dry-run cannot show levels or watch a file"));
+    }
+    let quarantine = args.option("--quarantine");
+    if args.switch("--fail-on-reject") && quarantine.is_none() {
+        return Ok(usage_error("--fail-on-reject needs a --quarantine file"));
+    }
+    let format = args.option("--format").unwrap_or("hex");
+    if quarantine.is_some() && (format != "hex" || args.switch("--watch") || args.switch("--dry-run")) {
+        return Ok(usage_error("--quarantine only supports hash files, without --watch or --dry-run"));
+    }
+    let unsupported = quarantine.is_some() || args.switch("--watch") || args.switch("--dry-run");
+    if input.is_some() && (format != "hex" || unsupported) {
+        return Ok(usage_error("--input only supports hashes, without --quarantine, --watch or --dry-run"));
+    }
+    let raw_width = match args.option("--input-format") {
+        None => None,
+        Some(input_format) => match input_format.strip_prefix("raw-fixed:").map(str::parse::<usize>) {
+            Some(Ok(width)) if width > 0 => Some(width),
+            _ => return Ok(usage_error(&format!("--input-format expects raw-fixed:<width>, got `{}`", input_format))),
+        },
+    };
+    let text_only = args.option("--format").is_some() || input.is_some() || unsupported;
+    if raw_width.is_some() && (text_only || files.len() != 1) {
+        return Ok(usage_error(
+            "--input-format only supports a single file, without --format, --input, --quarantine, --watch or --dry-run",
+        ));
+    }
+    let memory_budget = match args.option("--memory-budget").map(str::parse::<usize>) {
+        None => None,
+        Some(Ok(budget)) => Some(budget),
+        Some(Err(_)) => return Ok(usage_error("--memory-budget expects a number of bytes")),
+    };
+    let sort_leaves = args.switch("--sort-leaves");
+    if memory_budget.is_some() && (!sort_leaves || text_only || raw_width.is_some() || files.len() != 1) {
+        return Ok(usage_error(
+            "--memory-budget needs --sort-leaves and a single hash file, without --format, --input, --input-format, \
+             --quarantine, --watch or --dry-run",
+        ));
+    }
+    let metrics = args.switch("--metrics");
+    let unmeasured =
+        raw_width.is_some() || memory_budget.is_some() || args.switch("--watch") || args.switch("--dry-run");
+    if metrics && unmeasured {
+        return Ok(usage_error("--metrics cannot be used with --input-format, --memory-budget, --watch or --dry-run"));
+    }
+    if let Some(quarantine) = quarantine {
+        remove_orphaned_temp(quarantine)?;
+    }
+    if args.switch("--watch") {
+        let hex_only = args.option("--format").is_none_or(|format| format == "hex") && is_default_hasher(&hasher);
+        if !hex_only || show_levels || sort_leaves || files.len() > 1 {
+            return Ok(usage_error("--watch only supports a single hash file and cannot show levels or sort leaves"));
+        }
+        if encoding != Encoding::Hex {
+            return Ok(usage_error("--watch only prints hex roots"));
+        }
+        return watch_entry(&files[0], output).await;
+    }
+
+    // the leaves of all files in argument order, so the order of the files is part of the root
+    let config = match format {
+        "hex" => TreeConfig::new(LeafMode::PreHashed).with_hasher(hasher),
+        "csv" => TreeConfig::new(LeafMode::HashData).with_hasher(hasher),
+        other => return Ok(usage_error(&format!("--format expects `hex` or `csv`, got `{}`", other))),
+    };
+    let config = if sort_leaves { config.with_canonical_ordering(CanonicalOrdering::ByLeafHash) } else { config };
+    if args.switch("--dry-run") {
+        return dry_run(files, &config, output);
+    }
+    let (mut leaves, mut sources, mut rejected) = (vec![], vec![], vec![]);
+    let policy = args.hex_policy();
+    if let Some(input) = input {
+        leaves = match read_input(input, policy) {
+            Ok(leaves) => leaves,
+            Err(code) => return Ok(code),
+        };
+    }
+    let text_files = if raw_width.is_some() || memory_budget.is_some() { &[] } else { files };
+    for file in text_files {
+        let reader = BufReader::new(File::open(file)?);
+        let file_leaves = match format {
+            "hex" if quarantine.is_some() => match decode_hashes_lenient_with(reader, hasher.output_len(), policy) {
+                Ok((file_leaves, file_rejected)) => {
+                    rejected.extend(file_rejected.into_iter().map(|line| (file, line)));
+                    file_leaves
+                }
+                Err(e) => return Ok(lib_error(e)),
+            },
+            "hex" => match decode_hashes_with(reader, policy) {
+                Ok(file_leaves) => file_leaves,
+                Err(e) => return Ok(lib_error(e)),
+            },
+            _ => encode_csv_lines(reader)?,
+        };
+        sources.push(serde_json::json!({ "file": file, "leaf_count": file_leaves.len() }));
+        leaves.extend(file_leaves);
+    }
+    if let Some(quarantine) = quarantine {
+        write_quarantine(quarantine, &rejected)?;
+        if !rejected.is_empty() {
+            let total = leaves.len() + rejected.len();
+            if args.switch("--fail-on-reject") {
+                let message = format!("rejected {} of {} lines, written to {}", rejected.len(), total, quarantine);
+                report_error("REJECTED_LINES", rejected.first().map(|(_, line)| line.line), &message);
+                return Ok(ExitStatus::Rejected.into());
+            }
+            eprintln!(
+                "warning: rejected {} of {} lines, the root only covers the other {}; they were written to {}",
+                rejected.len(),
+                total,
+                leaves.len(),
+                quarantine
+            );
+            for (file, line) in rejected.iter().take(MAX_REPORTED_LINES) {
+                eprintln!("warning:   {}:{}: {}", file, line.line, line.reason);
+            }
+            if rejected.len() > MAX_REPORTED_LINES {
+                eprintln!("warning:   and {} more", rejected.len() - MAX_REPORTED_LINES);
+            }
+        }
+    }
+    let built = match (raw_width, memory_budget) {
+        (Some(width), _) => read_fixed_width(&files[0], width, &config).map(|tree| (tree, None)),
+        (None, Some(budget)) => sort_with_budget(&files[0], budget, policy, &config).map(|tree| (tree, None)),
+        (None, None) if metrics => {
+            config.construct_with_metrics(&leaves).map(|(tree, metrics)| (tree, Some(metrics))).map_err(lib_error)
+        }
+        (None, None) => config.construct(&leaves).map(|tree| (tree, None)).map_err(lib_error),
+    };
+    let (tree, metrics) = match built {
+        Ok(built) => built,
+        Err(code) => return Ok(code),
+    };
+    let descriptor = match config.descriptor(&tree) {
+        Ok(descriptor) => descriptor.with_encoding(encoding),
+        Err(e) => return Ok(lib_error(e)),
+    };
+
+    match output {
+        OutputFormat::Human => {
+            if show_levels {
+                print_levels(&tree, encoding);
+            }
+            println!("{}", tree.root().encode(encoding));
+            println!("fingerprint: {}", descriptor.fingerprint());
+            if let Some(metrics) = metrics {
+                println!("built: {}", metrics);
+            }
+        }
+        OutputFormat::Json => {
+            let mut json = root_json(&tree, show_levels, encoding);
+            json["descriptor"] = serde_json::to_value(&descriptor)?;
+            if sources.len() > 1 {
+                json["sources"] = serde_json::json!(sources);
+            }
+            if quarantine.is_some() {
+                json["rejected"] = serde_json::json!(rejected.len());
+            }
+            if let Some(metrics) = metrics {
+                json["metrics"] = metrics_json(&metrics);
+            }
+            println!("{}", json)
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The `metrics` of `merkle root --metrics --output json`, with the rates worked out
+fn metrics_json(metrics: &BuildMetrics) -> serde_json::Value {
+    serde_json::json!({
+        "leaf_count": metrics.leaf_count,
+        "leaf_hashes": metrics.leaf_hashes,
+        "node_hashes": metrics.node_hashes,
+        "elapsed_secs": metrics.elapsed.as_secs_f64(),
+        "leaves_per_second": metrics.leaves_per_second(),
+        "hashes_per_second": metrics.hashes_per_second(),
+        "peak_rss_bytes": metrics.peak_rss_bytes,
+    })
+}
+
+/// Builds the tree of `merkle root --sort-leaves --memory-budget` over the hashes of `file`
+fn sort_with_budget(
+    file: &str,
+    budget: usize,
+    policy: HexPolicy,
+    config: &TreeConfig,
+) -> std::result::Result<MerkleTree, ExitCode> {
+    let reader = BufReader::new(File::open(file).map_err(|e| lib_error(e.into()))?);
+    let runs = canonicalize_to_tempfiles_with(reader, policy, &env::temp_dir(), budget).map_err(lib_error)?;
+    runs.build(*config).map_err(lib_error)
+}
+
+/// Builds the tree of a `merkle root --input-format raw-fixed:<width>` file, reporting errors itself
+#[cfg(feature = "mmap")]
+fn read_fixed_width(file: &str, width: usize, config: &TreeConfig) -> std::result::Result<MerkleTree, ExitCode> {
+    merkle::util::mmap::from_fixed_width_file_with(file, width, config).map_err(lib_error)
+}
+
+/// Builds the tree of a `merkle root --input-format raw-fixed:<width>` file, reporting errors itself
+#[cfg(not(feature = "mmap"))]
+fn read_fixed_width(_: &str, _: usize, _: &TreeConfig) -> std::result::Result<MerkleTree, ExitCode> {
+    Err(usage_error("--input-format needs merkle to be built with the `mmap` feature"))
+}
+
+/// Reads the hashes of a `merkle root --input` source, `sqlite:<db>?query=<sql>` or
+/// `sqlite:<db>?table=<table>[&column=<column>]`, reporting errors itself
+#[cfg(feature = "sqlite")]
+fn read_input(input: &str, policy: HexPolicy) -> std::result::Result<Vec<Hash>, ExitCode> {
+    use merkle::util::sqlite::{for_each_sqlite_hash, open_sqlite};
+
+    let Some((path, source)) = input.strip_prefix("sqlite:").and_then(|rest| rest.split_once('?')) else {
+        return Err(usage_error("--input expects sqlite:<db>?query=<sql> or sqlite:<db>?table=<table>"));
+    };
+    let (source, column) = match (source.strip_prefix("query="), source.strip_prefix("table=")) {
+        (Some(query), _) => (query, None),
+        (_, Some(table)) => match table.split_once("&column=") {
+            Some((table, column)) => (table, Some(column)),
+            None => (table, None),
+        },
+        _ => return Err(usage_error("--input expects sqlite:<db>?query=<sql> or sqlite:<db>?table=<table>")),
+    };
+    let mut leaves = Vec::new();
+    let read = open_sqlite(path)
+        .and_then(|connection| for_each_sqlite_hash(&connection, source, column, policy, |hash| leaves.push(hash)));
+    match read {
+        Ok(_) => Ok(leaves),
+        Err(e) => Err(lib_error(e)),
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn read_input(_: &str, _: HexPolicy) -> std::result::Result<Vec<Hash>, ExitCode> {
+    Err(usage_error("--input needs merkle to be built with the `sqlite` feature"))
+}
+
+/// Writes the lines `merkle root --quarantine` rejected, one `<file>:<line>\t<reason>\t<content>` each
+fn write_quarantine(path: &str, rejected: &[(&String, RejectedLine)]) -> Result<()> {
+    write_atomic(path, |file| {
+        let mut out = std::io::BufWriter::new(file);
+        for (file, line) in rejected {
+            writeln!(out, "{}:{}\t{}\t{}", file, line.line, line.reason, line.content)?;
+        }
+        out.flush()
+    })
+}
+
+/// `merkle root <file>... --dry-run`: estimates the build from the line count and a timed sample
+///
+/// The lines of every file are counted without decoding them, and the time is extrapolated from
+/// building a tree over the first `CALIBRATION_SAMPLES` lines of the first file.
+fn dry_run(files: &[String], config: &TreeConfig, output: OutputFormat) -> Result<ExitCode> {
+    use merkle::util::estimate::{calibrate, count_lines, estimate_build, LineCount, CALIBRATION_SAMPLES};
+
+    let mut total = LineCount::default();
+    for file in files {
+        let count = count_lines(BufReader::new(File::open(file)?)).map_err(std::io::Error::other)?;
+        total.lines += count.lines;
+        total.bytes += count.bytes;
+    }
+    let estimate = match estimate_build(total.lines, total.average_len(), config) {
+        Ok(estimate) => estimate,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let calibration = match calibrate(BufReader::new(File::open(&files[0])?), config, CALIBRATION_SAMPLES) {
+        Ok(calibration) => calibration,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let duration = estimate.est_duration(&calibration);
+
+    match output {
+        OutputFormat::Human => {
+            println!("dry run, nothing was built");
+            println!("{:<16}{}", "leaves:", estimate.leaf_count);
+            println!("{:<16}{}", "depth:", estimate.depth);
+            println!("{:<16}{}", "nodes:", estimate.nodes);
+            println!("{:<16}{}", "hashes:", estimate.est_hashes);
+            let (size, unit) = match estimate.est_memory_bytes {
+                bytes if bytes < 1 << 20 => (bytes.div_ceil(1 << 10), "KiB"),
+                bytes => (bytes.div_ceil(1 << 20), "MiB"),
+            };
+            println!("{:<16}{} bytes (about {} {})", "memory:", estimate.est_memory_bytes, size, unit);
+            match duration {
+                Some(duration) => println!(
+                    "{:<16}about {:.3} s (calibrated on {} leaves)",
+                    "time:",
+                    duration.as_secs_f64(),
+                    calibration.leaves
+                ),
+                None => println!("{:<16}nothing to hash", "time:"),
+            }
+        }
+        OutputFormat::Json => {
+            let mut json = serde_json::json!(estimate);
+            json["est_seconds"] = serde_json::json!(duration.map(|duration| duration.as_secs_f64()));
+            json["calibration_leaves"] = serde_json::json!(calibration.leaves);
+            println!("{}", json)
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `merkle root <file> --watch`: prints the root again whenever the hash file changes, until Ctrl-C
+#[cfg(feature = "watch")]
+async fn watch_entry(file: &str, output: OutputFormat) -> Result<ExitCode> {
+    use merkle::util::watch::{watch_root, RootUpdate};
+    use std::ops::ControlFlow;
+
+    let print = move |update: std::result::Result<RootUpdate, LibError>| {
+        match (update, output) {
+            (Err(e), _) => eprintln!("error: {}", e),
+            (Ok(update), OutputFormat::Human) if update.previous_root.is_none() => {
+                println!("{}", hex::encode(&update.root))
+            }
+            (Ok(update), OutputFormat::Human) if update.changed() => {
+                let leaves: Vec<String> = update.changed_leaves.iter().map(usize::to_string).collect();
+                println!("{} changed (leaves {})", hex::encode(&update.root), leaves.join(", "));
+            }
+            (Ok(update), OutputFormat::Human) => println!("{} unchanged", hex::encode(&update.root)),
+            (Ok(update), OutputFormat::Json) => println!(
+                "{}",
+                serde_json::json!({
+                    "root": hex::encode(&update.root),
+                    "leaf_count": update.leaf_count,
+                    "changed": update.changed(),
+                    "changed_leaves": update.changed_leaves,
+                })
+            ),
+        }
+        ControlFlow::Continue(())
+    };
+
+    let file = file.to_string();
+    let watch = tokio::task::spawn_blocking(move || watch_root(file, print));
+    tokio::select! {
+        result = watch => match result? {
+            Ok(()) => Ok(ExitCode::SUCCESS),
+            Err(e) => Ok(lib_error(e)),
+        },
+        // the watch loop blocks its thread, which would keep the runtime from shutting down
+        _ = tokio::signal::ctrl_c() => std::process::exit(0),
+    }
+}
+
+#[cfg(not(feature = "watch"))]
+async fn watch_entry(_file: &str, _output: OutputFormat) -> Result<ExitCode> {
+    Ok(usage_error("--watch needs merkle to be built with the `watch` feature"))
+}
+
+/// Chunk size used by `merkle chunk-root` without `--chunk-size`
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Number of leaves above which `merkle chunk-root` asks for `--yes` before building the tree
+const CONFIRM_LEAVES: u64 = 1 << 20;
+
+/// `merkle chunk-root <file> [--chunk-size n] [--min-chunk-size n] [--yes] [--output human|json]`
+///
+/// Prints the root of a tree over the fixed-size chunks of any file. A split into more than
+/// `CONFIRM_LEAVES` leaves is only built with `--yes`, after reporting its size.
+async fn chunk_root_entry(args: &[String]) -> Result<ExitCode> {
+    use merkle::util::file::{estimated_tree_bytes, ChunkLimits, MerkleFile};
+
+    const USAGE: &str =
+        "usage: merkle chunk-root <file> [--chunk-size n] [--min-chunk-size n] [--yes] [--output human|json]";
+    let args = match ParsedArgs::parse(args, &["--chunk-size", "--min-chunk-size", "--output"], &["--yes"]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let ([file], Ok(output)) = (args.positional.as_slice(), args.output()) else {
+        return Ok(usage_error(USAGE));
+    };
+    let size_option = |name: &str, default: usize| args.option(name).map_or(Ok(default), str::parse::<usize>);
+    let (Ok(chunk_size), Ok(min_chunk_size)) = (
+        size_option("--chunk-size", DEFAULT_CHUNK_SIZE),
+        size_option("--min-chunk-size", ChunkLimits::default().min_chunk_size()),
+    ) else {
+        return Ok(usage_error("--chunk-size and --min-chunk-size expect a number of bytes"));
+    };
+
+    let limits = ChunkLimits::default().with_min_chunk_size(min_chunk_size);
+    let leaves = match limits.check(std::fs::metadata(file)?.len(), chunk_size) {
+        Ok(leaves) => leaves,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    if leaves > CONFIRM_LEAVES {
+        let bytes = estimated_tree_bytes(leaves).unwrap_or(u64::MAX);
+        eprintln!(
+            "{} splits into {} leaves of {} bytes, using about {} MiB for the tree",
+            file,
+            leaves,
+            chunk_size,
+            bytes.div_ceil(1 << 20)
+        );
+        if !args.switch("--yes") {
+            return Ok(usage_error("pass --yes to build a tree this large"));
+        }
+    }
+
+    let tree = match MerkleFile::from_path_with_limits(file, chunk_size, &limits) {
+        Ok(tree) => tree,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    match output {
+        OutputFormat::Human => println!("{}", hex::encode(tree.root())),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "root": hex::encode(tree.root()),
+                "leaf_count": tree.tree().len(),
+                "chunk_size": chunk_size,
+                "file_len": tree.len(),
+            })
+        ),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `merkle export-solidity --input <leaves.csv> --out <verifier.sol> <claims.json>`
+///
+/// Reads `address,amount` lines, writes every claim with its proof to the claims file and a
+/// contract checking them against the root to the Solidity file, then prints the root.
+#[cfg(feature = "solidity")]
+async fn export_solidity_entry(args: &[String]) -> Result<ExitCode> {
+    use merkle::util::io::write_file_atomic;
+    use merkle::util::solidity::{build_claims, read_claims_csv, render_verifier};
+
+    const USAGE: &str = "usage: merkle export-solidity --input <leaves.csv> --out <verifier.sol> <claims.json>";
+    let args = match ParsedArgs::parse(args, &["--input", "--out"], &[]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let (claims_path, input, out) = (args.positional.as_slice(), args.option("--input"), args.option("--out"));
+    let ([claims_path], Some(input), Some(out)) = (claims_path, input, out) else {
+        return Ok(usage_error(USAGE));
+    };
+    remove_orphaned_temp(claims_path)?;
+    remove_orphaned_temp(out)?;
+
+    let claims = match read_claims_csv(BufReader::new(File::open(input)?)).and_then(|claims| build_claims(&claims)) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    write_file_atomic(claims_path, serde_json::to_string_pretty(&claims)? + "\n")?;
+    write_file_atomic(out, render_verifier(&claims.root))?;
+    println!("0x{}", hex::encode(&claims.root));
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `merkle sign-root <file> --key <key.priv> [--out <root.sig>]`
+///
+/// Signs the commitment to the root and leaf count of a hash file and writes the signed root as
+/// JSON, to stdout or the `--out` file. Exits with 1 if the key does not parse.
+#[cfg(feature = "sign")]
+async fn sign_root_entry(args: &[String]) -> Result<ExitCode> {
+    use merkle::util::io::write_file_atomic;
+    use merkle::util::sign::{read_signing_key, sign_commitment};
+
+    const USAGE: &str = "usage: merkle sign-root <file> --key <key.priv> [--out <root.sig>]";
+    let args = match ParsedArgs::parse(args, &["--key", "--out"], &[]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let ([file], Some(key)) = (args.positional.as_slice(), args.option("--key")) else {
+        return Ok(usage_error(USAGE));
+    };
+    if let Some(out) = args.option("--out") {
+        remove_orphaned_temp(out)?;
+    }
+
+    let key = match read_signing_key(&std::fs::read_to_string(key)?) {
+        Ok(key) => key,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let tree = match hash_file_tree(file) {
+        Ok(tree) => tree,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let signed = serde_json::to_string_pretty(&sign_commitment(&key, &tree.root(), tree.len() as u64))? + "\n";
+    match args.option("--out") {
+        Some(out) => write_file_atomic(out, signed)?,
+        None => print!("{}", signed),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `merkle verify-signed --input <hashes> --root-sig <root.sig> --pubkey <key.pub>`
+///
+/// Checks the signature of a signed root, then that the hash file has that root and leaf count.
+/// Exits with 2 if the signature is bad or the input does not match the signed root, and 1 if the
+/// key does not parse.
+#[cfg(feature = "sign")]
+async fn verify_signed_entry(args: &[String]) -> Result<ExitCode> {
+    use merkle::util::sign::{read_verifying_key, verify_tree, SignedRoot};
+
+    const USAGE: &str = "usage: merkle verify-signed --input <hashes> --root-sig <root.sig> --pubkey <key.pub>";
+    let args = match ParsedArgs::parse(args, &["--input", "--root-sig", "--pubkey"], &[]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let (input, signature, key) = (args.option("--input"), args.option("--root-sig"), args.option("--pubkey"));
+    let ([], Some(input), Some(signature), Some(key)) = (args.positional.as_slice(), input, signature, key) else {
+        return Ok(usage_error(USAGE));
+    };
+
+    let key = match read_verifying_key(&std::fs::read_to_string(key)?) {
+        Ok(key) => key,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let signed: SignedRoot = match serde_json::from_reader(BufReader::new(File::open(signature)?)) {
+        Ok(signed) => signed,
+        Err(e) => return Ok(lib_error(LibError::InvalidFormat(e.to_string()))),
+    };
+    let tree = match hash_file_tree(input) {
+        Ok(tree) => tree,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    match verify_tree(&key, &signed, &tree) {
+        Ok(()) => {
+            println!("valid: {} has the signed root {} over {} leaves", input, hex::encode(&signed.root), tree.len());
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(e) => Ok(lib_error(e)),
+    }
+}
+
+/// `merkle serve <file> [--addr host:port] [--cache n] [--max-proofs n] [--max-batch n] [--allow-ranges]`
+///
+/// Builds the tree from a hash file, like `merkle root`, and serves its root and proofs over HTTP.
+/// Requests are held to the `ServiceLimits` given: `--max-proofs` proofs per request, batches of
+/// `--max-batch` indices, and range requests only with `--allow-ranges`.
+#[cfg(feature = "http")]
+async fn serve_entry(args: &[String]) -> Result<ExitCode> {
+    use merkle::merkel::{ProofCache, ProofService, ServiceLimits};
+    use std::sync::Arc;
+
+    const USAGE: &str = "usage: merkle serve <file> [--addr host:port] [--cache n] [--max-proofs n] [--max-batch n] \
+                         [--allow-ranges]";
+    let options = ["--addr", "--cache", "--max-proofs", "--max-batch"];
+    let args = match ParsedArgs::parse(args, &options, &["--allow-ranges"]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let [file] = args.positional.as_slice() else {
+        return Ok(usage_error(USAGE));
+    };
+    let Ok(capacity) = args.option("--cache").unwrap_or("1024").parse() else {
+        return Ok(usage_error("--cache expects a number of proofs"));
+    };
+    let defaults = ServiceLimits::default();
+    let max_proofs = args.option("--max-proofs").map_or(Ok(defaults.max_proofs_per_call), str::parse);
+    let Ok(max_proofs_per_call) = max_proofs else {
+        return Ok(usage_error("--max-proofs expects a number of proofs"));
+    };
+    let max_batch = args.option("--max-batch").map_or(Ok(defaults.max_batch_indices), str::parse);
+    let Ok(max_batch_indices) = max_batch else {
+        return Ok(usage_error("--max-batch expects a number of indices"));
+    };
+    let allow_range_proofs = args.switch("--allow-ranges");
+    let limits = ServiceLimits { max_proofs_per_call, max_batch_indices, allow_range_proofs };
+
+    let tree = match hash_file_tree(file) {
+        Ok(tree) => tree,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let listener = tokio::net::TcpListener::bind(args.option("--addr").unwrap_or("127.0.0.1:8080")).await?;
+    serve::run(listener, Arc::new(ProofService::with_cache(ProofCache::new(tree, capacity), limits))).await?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn root_json(tree: &MerkleTree, show_levels: bool, encoding: Encoding) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "root": tree.root().encode(encoding),
+        "leaf_count": tree.len(),
+    });
+    if tree.hasher() != HashAlgorithm::default() {
+        json["hasher"] = serde_json::json!(tree.hasher());
+    }
+    if show_levels {
+        let levels: Vec<Vec<String>> = tree
+            .levels()
+            .iter()
+            .map(|level| level.iter().map(|hash| hash.encode(encoding)).collect())
+            .collect();
+        json["levels"] = serde_json::json!(levels);
+    }
+    json
+}
+
+fn print_levels(tree: &MerkleTree, encoding: Encoding) {
+    for (k, level) in tree.levels().iter().enumerate() {
+        println!("level {} ({} nodes)", k, level.len());
+        for hash in level {
+            println!("  {}", hash.encode(encoding));
+        }
+    }
+}
+
+fn encode_csv_lines<R: BufRead>(reader: R) -> Result<Vec<Data>> {
+    let mut encoder = LeafEncoder::new();
+    reader
+        .lines()
+        .map(|line| {
+            for field in line?.split(',') {
+                encoder.push_str(field);
+            }
+            Ok(encoder.finish())
+        })
+        .collect()
+}
+
+/// Builds a tree whose leaves are the hashes listed in a hash file
+fn hash_file_tree(file: &str) -> std::result::Result<MerkleTree, LibError> {
+    hash_file_tree_with(file, HashAlgorithm::default(), HexPolicy::Strict)
+}
+
+/// Like `hash_file_tree`, for a file of hashes of another hasher, spelled as `policy` accepts
+fn hash_file_tree_with(
+    file: &str,
+    hasher: HashAlgorithm,
+    policy: HexPolicy,
+) -> std::result::Result<MerkleTree, LibError> {
+    let leaves = decode_hashes_with(BufReader::new(File::open(file)?), policy)?;
+    TreeConfig::new(LeafMode::PreHashed).with_hasher(hasher).construct(&leaves)
+}
+
+/// Reads a `ProofFile` or `DataProofFile`, failing with `LibError::UnsupportedVersion` rather than
+/// a format error if it is of a version this build cannot read
+fn read_proof_file<T: serde::de::DeserializeOwned>(file: &str) -> Result<std::result::Result<T, LibError>> {
+    let text = std::fs::read_to_string(file)?;
+    let json = serde_json::from_str::<serde_json::Value>(&text).ok();
+    let version = json.as_ref().and_then(|json| json["version"].as_u64());
+    if let Some(Err(e)) = version.map(ProofVersion::from_number) {
+        return Ok(Err(e));
+    }
+    // version 1 proofs are a list of steps, later ones an object holding it
+    let steps = json.as_ref().and_then(|json| json["proof"].as_array().or(json["proof"]["steps"].as_array()));
+    if let Some(got) = steps.map(Vec::len).filter(|&got| got > MAX_PROOF_STEPS) {
+        return Ok(Err(LibError::ProofTooDeep { max: MAX_PROOF_STEPS, got }));
+    }
+    Ok(serde_json::from_str(&text).map_err(|e| LibError::InvalidFormat(e.to_string())))
+}
+
+/// A proof as written by `merkle prove --output json` and read by `merkle verify-proof`
+#[derive(Serialize, Deserialize)]
+struct ProofFile {
+    /// Missing from files written before formats were versioned
+    #[serde(default)]
+    version: ProofVersion,
+    index: usize,
+    /// Size of the tree, missing from files written before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    leaf_count: Option<u64>,
+    /// SHA-256 in files written before every file recorded its hasher
+    #[serde(default)]
+    hasher: HashAlgorithm,
+    /// `LeafMode::PreHashed` for the trees of hash files, the only ones `verify-proof` checks;
+    /// missing from files written before leaf modes were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    leaf_mode: Option<LeafMode>,
+    #[serde(with = "merkle::util::hex_serde")]
+    leaf: Hash,
+    #[serde(with = "merkle::util::hex_serde")]
+    root: Hash,
+    proof: OwnedProof,
+}
+
+fn is_default_hasher(hasher: &HashAlgorithm) -> bool {
+    *hasher == HashAlgorithm::default()
+}
+
+/// Fails with `LibError::HasherMismatch` unless a proof file records the hasher and leaf mode of
+/// `config`; a file written before leaf modes were recorded is taken to be of its leaf mode
+fn check_proof_file(
+    config: &TreeConfig,
+    hasher: HashAlgorithm,
+    leaf_mode: Option<LeafMode>,
+) -> std::result::Result<(), LibError> {
+    config.check_artifact("proof file", hasher.id(), Some(leaf_mode.unwrap_or(config.leaf_mode())))
+}
+
+/// `merkle prove <file> (--index <n> | --leaves-file <leaves>) [--hash <hasher>] [--output human|json]
+/// [--lenient-hex] [--encoding <encoding>]`
+///
+/// With `--leaves-file`, proves every leaf hash listed one per line in that file, writing one JSON
+/// object per line: the proof as `--output json` prints it, or the error for that line. `--encoding`
+/// spells the hashes of the human output; proof files stay hex, which `verify-proof` reads.
+async fn prove_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str = "usage: merkle prove <file> (--index <n> | --leaves-file <leaves>) [--hash <hasher>] \
+                         [--output human|json] [--lenient-hex] [--encoding <encoding>]";
+    let options = ["--index", "--leaves-file", "--hash", "--output", "--encoding"];
+    let args = match ParsedArgs::parse(args, &options, &["--lenient-hex"]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let ([file], Ok(output), Ok(hasher)) = (args.positional.as_slice(), args.output(), args.hasher()) else {
+        return Ok(usage_error(USAGE));
+    };
+    let encoding = match args.encoding() {
+        Ok(encoding) => encoding,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    // proof files are read back by `verify-proof`, which only reads hex
+    let writes_json = output == OutputFormat::Json || args.option("--leaves-file").is_some();
+    if encoding != Encoding::Hex && writes_json {
+        return Ok(usage_error("--encoding only applies to human output, proof files are always hex"));
+    }
+    let index = match (args.option("--index"), args.option("--leaves-file")) {
+        (Some(index), None) => index,
+        (None, Some(leaves)) if args.option("--output").is_none() => {
+            return prove_leaves(file, leaves, hasher, args.hex_policy())
+        }
+        (None, Some(_)) => return Ok(usage_error("--leaves-file always writes one JSON object per line")),
+        _ => return Ok(usage_error(USAGE)),
+    };
+    let Ok(index) = index.parse::<usize>() else {
+        return Ok(usage_error("--index expects a leaf index"));
+    };
+
+    let tree = match hash_file_tree_with(file, hasher, args.hex_policy()) {
+        Ok(tree) => tree,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let proof = match tree.prove_by_index(index) {
+        Ok(proof) => proof.into_owned(),
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let proof = ProofFile {
+        version: ProofVersion::CURRENT,
+        index,
+        leaf_count: Some(tree.len() as u64),
+        hasher,
+        leaf_mode: Some(LeafMode::PreHashed),
+        leaf: tree.leaves()[index].clone(),
+        root: tree.root(),
+        proof,
+    };
+
+    print_proof(&proof, output, encoding)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `merkle prove <file> --leaves-file <leaves>`: one NDJSON line per non-blank line of `leaves`
+///
+/// Lines that are not a hash of `hasher`, or not a leaf of the tree, get an `error` object with the
+/// `code` and `message` of `--error-json` instead of a proof, without stopping the others. Exits
+/// with the status of the first such line, if any.
+fn prove_leaves(file: &str, leaves: &str, hasher: HashAlgorithm, policy: HexPolicy) -> Result<ExitCode> {
+    let tree = match hash_file_tree_with(file, hasher, policy) {
+        Ok(tree) => tree,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    // the lines naming a leaf, and the error of every other line by its number
+    let (mut wanted, mut failed) = (Vec::new(), Vec::new());
+    for (i, line) in BufReader::new(File::open(leaves)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match policy.parse_hash(&line, hasher.output_len()) {
+            Ok(leaf) => wanted.push((i + 1, leaf)),
+            Err(e) => failed.push((i + 1, e)),
+        }
+    }
+
+    let hashes: Vec<Hash> = wanted.iter().map(|(_, leaf)| leaf.clone()).collect();
+    let proven = tree.prove_many_leaves(&hashes);
+    let mut results: Vec<(usize, std::result::Result<serde_json::Value, LibError>)> = wanted
+        .into_iter()
+        .zip(proven)
+        .map(|((line, leaf), result)| {
+            let proof = result.map(|(index, proof)| ProofFile {
+                version: ProofVersion::CURRENT,
+                index,
+                leaf_count: Some(tree.len() as u64),
+                hasher,
+                leaf_mode: Some(LeafMode::PreHashed),
+                leaf,
+                root: tree.root(),
+                proof,
+            });
+            (line, proof.map(|proof| serde_json::json!(proof)))
+        })
         .collect();
+    results.extend(failed.into_iter().map(|(line, e)| (line, Err(e))));
+    results.sort_by_key(|(line, _)| *line);
 
-    if tx_hashes.is_empty() {
-        eprintln!("No valid values found in the input file.");
-        return Ok(());
+    let mut status = ExitCode::SUCCESS;
+    let mut out = std::io::stdout().lock();
+    for (line, result) in results {
+        let json = match result {
+            Ok(mut proof) => {
+                proof["line"] = serde_json::json!(line);
+                proof
+            }
+            Err(e) => {
+                if status == ExitCode::SUCCESS {
+                    status = e.exit_status().into();
+                }
+                serde_json::json!({ "line": line, "error": { "code": e.code(), "message": e.to_string() } })
+            }
+        };
+        writeln!(out, "{}", json)?;
     }
-    println!("hashes: {:?}", tx_hashes);
-    let merkle_root = merkle(tx_hashes);
-    println!("Merkle Root: {}", hex::encode(merkle_root));
-    Ok(())
+    Ok(status)
 }
-async fn entry_point_1() -> Result<()>{
 
-    let mut file = File::create("ts_hashes.json")?;
-    for _ in 0..10 {
-        let s = generate_string(64);
-        writeln!(file, "{}", s)?;
+fn print_proof(proof: &ProofFile, output: OutputFormat, encoding: Encoding) -> Result<()> {
+    match output {
+        OutputFormat::Human => {
+            println!("leaf {} {}", proof.index, proof.leaf.encode(encoding));
+            for (direction, hash) in proof.proof.hashes() {
+                let side = match direction {
+                    HashDirection::Left => "left ",
+                    HashDirection::Duplicate => "self ",
+                    _ => "right",
+                };
+                println!("  {} {}", side, hash.to_vec().encode(encoding));
+            }
+            println!("root {}", proof.root.encode(encoding));
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(proof)?),
     }
     Ok(())
 }
 
-fn merkle(mut hash_list: Vec<Vec<u8>>) -> Vec<u8> {
-    let mut round = 0;
-    while hash_list.len() > 1 {
-        round += 1;
-        println!("\nRound {}: : {}", round, hash_list.len());
+/// `merkle verify-proof <proof.json> (--root <hex|prefix>)... [--roots-file <roots.json>] [--tree <file>]
+/// [--output human|json] [--encoding <encoding>]`
+///
+/// Checks the proof written by `merkle prove` against roots the caller trusts, rather than the
+/// root recorded in the file. `--root` may be repeated, and `--roots-file` adds the roots of a
+/// serialized `RootSet`; the proof is accepted if it leads to any of them. A `--root` shorter
+/// than any hash is a prefix, resolved against `--roots-file` as git resolves abbreviated
+/// commits; the proof is then checked against the resolved roots only, and a prefix that starts
+/// no root or several fails with 3 or 1. Exits with 2 if the proof does not verify.
+///
+/// The proof must show the leaf at its index in a tree of the size the file records, or of the
+/// size of the `--tree` file. A file recording no size, as written before sizes were, is only
+/// checked against the roots, with a warning that its index is not. A failure against a single
+/// root is explained from the tree size in the file, and down to the differing step when `--tree`
+/// names the hash file it came from. A file recording a leaf mode other than pre-hashed fails
+/// with 5.
+/// `--encoding` is the spelling of the `--root` values and the roots printed back; prefixes are
+/// only resolved in hex.
+async fn verify_proof_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str = "usage: merkle verify-proof (<proof.json> | --bundle <bundle>) (--root <hex|prefix>)... \
+                         [--roots-file <roots.json>] [--tree <file>] [--output human|json] [--lenient-hex] \
+                         [--encoding <encoding>]";
+    let options = ["--root", "--roots-file", "--tree", "--bundle", "--output", "--encoding"];
+    let args = match ParsedArgs::parse(args, &options, &["--lenient-hex"]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let Ok(output) = args.output() else {
+        return Ok(usage_error(USAGE));
+    };
+    let encoding = match args.encoding() {
+        Ok(encoding) => encoding,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let file = match (args.positional.as_slice(), args.option("--bundle")) {
+        ([file], None) => Some(file),
+        ([], Some(_)) => None,
+        _ => return Ok(usage_error(USAGE)),
+    };
+    if args.option("--root").is_none() && args.option("--roots-file").is_none() {
+        return Ok(usage_error(USAGE));
+    }
 
-        let mut new_hash_list = Vec::new();
-        for i in (0..hash_list.len()).step_by(2) {
-            let left = &hash_list[i];
-            let right = if i + 1 < hash_list.len() {
-                &hash_list[i + 1]
-            } else {
-                left
+    let file_roots: Option<RootSet> = match args.option("--roots-file") {
+        Some(roots_file) => match serde_json::from_reader(BufReader::new(File::open(roots_file)?)) {
+            Ok(set) => Some(set),
+            Err(e) => return Ok(lib_error(LibError::InvalidFormat(e.to_string()))),
+        },
+        None => None,
+    };
+    let shortest_hex = HashAlgorithm::ALL.iter().map(|hasher| hasher.output_len() * 2).min().unwrap_or(0);
+    let mut roots = RootSet::new();
+    let mut resolved_prefix = false;
+    for root in args.options("--root") {
+        if encoding != Encoding::Hex {
+            match Hash::decode(encoding, root) {
+                Ok(root) => roots.insert(root, ""),
+                Err(e) => return Ok(usage_error(&format!("--root expects a {} hash: {}", encoding, e))),
             };
+            continue;
+        }
+        if root.len() < shortest_hex {
+            let Some(set) = &file_roots else {
+                return Ok(usage_error("--root <prefix> needs --roots-file to resolve it against"));
+            };
+            if root.is_empty() || !root.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Ok(usage_error("--root expects a hex hash or prefix"));
+            }
+            let hash = match set.resolve_prefix(root) {
+                Ok(hash) => hash,
+                Err(e) => return Ok(lib_error(e.into())),
+            };
+            let metadata = (0..set.len()).filter_map(|index| set.get(index)).find(|(candidate, _)| **candidate == hash);
+            roots.insert(hash.clone(), metadata.map_or("", |(_, metadata)| metadata));
+            resolved_prefix = true;
+            continue;
+        }
+        let Ok(root) = args.hex_policy().parse(root) else {
+            return Ok(usage_error("--root expects a hex hash or prefix"));
+        };
+        roots.insert(root, "");
+    }
+    // the file is only a lookup table once a prefix has picked roots out of it
+    if let Some(set) = file_roots.filter(|_| !resolved_prefix) {
+        for index in 0..set.len() {
+            let (root, metadata) = set.get(index).unwrap();
+            roots.insert(root.clone(), metadata);
+        }
+    }
 
-            let mut combined = Vec::new();
-            combined.extend_from_slice(left);
-            combined.extend_from_slice(right);
-            let hash = hash2(&combined);
-            new_hash_list.push(hash);
+    let Some(file) = file else {
+        return verify_bundle(args.option("--bundle").unwrap(), &roots, output, encoding);
+    };
+
+    let proof: ProofFile = match read_proof_file(file)? {
+        Ok(proof) => proof,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    // the leaf is checked as a hash, under the hasher the file records
+    let config = TreeConfig::new(LeafMode::PreHashed).with_hasher(proof.hasher);
+    if let Err(e) = check_proof_file(&config, proof.hasher, proof.leaf_mode) {
+        return Ok(lib_error(e));
+    }
+    let steps = proof.proof.as_proof();
+    let matched = match roots.verify_proof_hash_with(&proof.hasher, &proof.leaf, &steps) {
+        Ok(matched) => matched,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let tree = args.option("--tree").map(|tree| hash_file_tree_with(tree, proof.hasher, args.hex_policy()));
+    let tree = match tree.transpose() {
+        Ok(tree) => tree,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let leaf_count = match proof.leaf_count.map(usize::try_from).transpose() {
+        Ok(leaf_count) => leaf_count,
+        Err(_) => return Ok(lib_error(LibError::Overflow("leaf count"))),
+    };
+    // a proof leading to a root may still show its leaf at another index than the file claims
+    let tree_size = leaf_count.or(proof.proof.tree_size()).or(tree.as_ref().map(MerkleTree::len));
+    let reached = matched;
+    let matched = matched.filter(|&i| {
+        tree_size.is_none_or(|size| {
+            let root = roots.get(i).unwrap().0;
+            MerkleTree::verify_proof_hash_at_index_with(&proof.hasher, &proof.leaf, &steps, proof.index, size, root)
+        })
+    });
+    // failures are only explained by replaying SHA-256 proofs
+    let failure = match (matched, roots.len()) {
+        (None, 1) if is_default_hasher(&proof.hasher) => {
+            let root = roots.get(0).unwrap().0;
+            match (&tree, tree_size) {
+                (Some(tree), _) => tree.explain_proof(&proof.leaf, &steps, proof.index, root).err(),
+                (None, Some(size)) => {
+                    MerkleTree::verify_proof_detailed(&proof.leaf, &steps, proof.index, size, root).err()
+                }
+                (None, None) => None,
+            }
         }
-        hash_list = new_hash_list;
+        _ => None,
+    };
+
+    match (output, matched) {
+        (OutputFormat::Human, Some(i)) => {
+            let (root, metadata) = roots.get(i).unwrap();
+            if tree_size.is_some() {
+                print!("valid: leaf {} is included under root {}", proof.index, root.encode(encoding));
+            } else {
+                eprintln!("warning: the proof file records no tree size, so the index of its leaf is not checked");
+                print!("valid: the leaf is included under root {}", root.encode(encoding));
+            }
+            match (roots.len(), metadata) {
+                (1, _) => println!(),
+                (n, "") => println!(" (candidate {} of {})", i + 1, n),
+                (n, metadata) => println!(" (candidate {} of {}, {})", i + 1, n, metadata),
+            }
+        }
+        (OutputFormat::Human, None) if reached.is_some() => {
+            let root = roots.get(reached.unwrap_or(0)).unwrap().0.encode(encoding);
+            let size = tree_size.unwrap_or(0);
+            println!("invalid: the proof leads to root {}, but not from leaf {} of {} leaves", root, proof.index, size);
+            if let Some(failure) = &failure {
+                failure.to_string().lines().for_each(|line| println!("  {}", line));
+            }
+        }
+        (OutputFormat::Human, None) if roots.len() == 1 => {
+            println!(
+                "invalid: the proof for leaf {} does not lead to root {}",
+                proof.index,
+                roots.get(0).unwrap().0.encode(encoding)
+            );
+            if let Some(failure) = &failure {
+                failure.to_string().lines().for_each(|line| println!("  {}", line));
+            }
+        }
+        (OutputFormat::Human, None) => println!(
+            "invalid: the proof for leaf {} does not lead to any of the {} candidate roots",
+            proof.index,
+            roots.len()
+        ),
+        (OutputFormat::Json, _) => {
+            let mut json = serde_json::json!({
+                "valid": matched.is_some(),
+                "index": proof.index,
+                "index_checked": tree_size.is_some(),
+                "root_index": matched,
+            });
+            if let Some(failure) = &failure {
+                json["explanation"] = serde_json::json!(failure.to_string().lines().collect::<Vec<_>>());
+            }
+            println!("{}", json)
+        }
+    }
+    Ok(if matched.is_some() { ExitCode::SUCCESS } else { ExitStatus::VerificationFailed.into() })
+}
+
+/// `merkle verify-proof --bundle <bundle>`: finds the bundle root among the trusted roots, then
+/// checks every row against the context of that root
+fn verify_bundle(file: &str, roots: &RootSet, output: OutputFormat, encoding: Encoding) -> Result<ExitCode> {
+    let bundle = match ProofBundle::read(BufReader::new(File::open(file)?), false) {
+        Ok(bundle) => bundle,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let matched = roots.roots().position(|root| root == bundle.root());
+    if let Some(trusted) = matched.and_then(|i| roots.get(i)) {
+        if let Err(e) = bundle.header().context(trusted.0).and_then(|context| bundle.verify_with(&context)) {
+            return Ok(lib_error(e));
+        }
+    }
+    let root = bundle.root().encode(encoding);
+    match (output, matched) {
+        (OutputFormat::Human, Some(_)) => {
+            println!("valid: all {} proofs of the bundle lead to root {}", bundle.rows().len(), root)
+        }
+        (OutputFormat::Human, None) => println!("invalid: the bundle root {} is not a trusted root", root),
+        (OutputFormat::Json, _) => println!(
+            "{}",
+            serde_json::json!({
+                "valid": matched.is_some(),
+                "rows": bundle.rows().len(),
+                "root": root,
+                "root_index": matched,
+            })
+        ),
+    }
+    Ok(if matched.is_some() { ExitCode::SUCCESS } else { ExitStatus::VerificationFailed.into() })
+}
+
+/// `merkle verify-bundle <bundle> --root <hex> [--threads <n>] [--output human|json] [--lenient-hex]`
+///
+/// Checks every row of a bundle against a trusted root with `verify_bundle_parallel`, streaming
+/// the bundle rather than reading it whole as `verify-proof --bundle` does, and reports every row
+/// that fails instead of stopping at the first. `--threads` defaults to one per core. Exits with 2
+/// if any row does not parse or verify.
+async fn verify_bundle_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str =
+        "usage: merkle verify-bundle <bundle> --root <hex> [--threads <n>] [--output human|json] [--lenient-hex]";
+    let args = match ParsedArgs::parse(args, &["--root", "--threads", "--output"], &["--lenient-hex"]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let (Ok(output), [file], Some(root)) = (args.output(), args.positional.as_slice(), args.option("--root")) else {
+        return Ok(usage_error(USAGE));
+    };
+    let Ok(root) = args.hex_policy().parse(root) else {
+        return Ok(usage_error("--root expects a hex hash"));
+    };
+    let Ok(threads) = args.option("--threads").map_or(Ok(0), str::parse) else {
+        return Ok(usage_error("--threads expects a number"));
+    };
+
+    let report = match verify_bundle_parallel(BufReader::new(File::open(file)?), &root, threads) {
+        Ok(report) => report,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let root = hex::encode(&root);
+    match output {
+        OutputFormat::Human if report.is_valid() => {
+            println!("valid: all {} proofs of the bundle lead to root {}", report.valid, root)
+        }
+        OutputFormat::Human => {
+            let failed = report.invalid.len() + report.malformed.len();
+            println!("invalid: {} of {} rows of the bundle do not lead to root {}", failed, report.rows, root);
+            for line in report.invalid.iter().take(MAX_REPORTED_LINES) {
+                println!("  line {}: the proof does not lead to the root", line);
+            }
+            if report.invalid.len() > MAX_REPORTED_LINES {
+                println!("  and {} more proofs that do not verify", report.invalid.len() - MAX_REPORTED_LINES);
+            }
+            for row in report.malformed.iter().take(MAX_REPORTED_LINES) {
+                println!("  line {}: {}", row.line, row.error);
+            }
+            if report.malformed.len() > MAX_REPORTED_LINES {
+                println!("  and {} more rows that do not parse", report.malformed.len() - MAX_REPORTED_LINES);
+            }
+        }
+        OutputFormat::Json => {
+            let json = serde_json::json!({
+                "valid": report.is_valid(),
+                "rows": report.rows,
+                "valid_rows": report.valid,
+                "invalid_lines": report.invalid,
+                "malformed": report.malformed,
+                "root": root,
+            });
+            println!("{}", json)
+        }
+    }
+    Ok(if report.is_valid() { ExitCode::SUCCESS } else { ExitStatus::VerificationFailed.into() })
+}
+
+/// `merkle export-proofs <file> [--hash <hasher>] [--out <bundle>] [--lenient-hex]`
+///
+/// Writes the proof of every leaf of a hash file as a `ProofBundle`, to stdout or the `--out`
+/// file. `SOURCE_DATE_EPOCH` overrides the creation time, for reproducible bundles.
+async fn export_proofs_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str = "usage: merkle export-proofs <file> [--hash <hasher>] [--out <bundle>] [--lenient-hex]";
+    let args = match ParsedArgs::parse(args, &["--hash", "--out"], &["--lenient-hex"]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let ([file], Ok(hasher)) = (args.positional.as_slice(), args.hasher()) else {
+        return Ok(usage_error(USAGE));
+    };
+    if let Some(out) = args.option("--out") {
+        remove_orphaned_temp(out)?;
+    }
+
+    let tree = hash_file_tree_with(file, hasher, args.hex_policy());
+    let bundle = match tree.and_then(|tree| ProofBundle::from_tree(&tree, LeafMode::PreHashed)) {
+        Ok(bundle) => bundle,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let bundle = match env::var("SOURCE_DATE_EPOCH").ok().map(|epoch| epoch.parse()) {
+        Some(Ok(epoch)) => bundle.with_created_at(epoch),
+        Some(Err(_)) => return Ok(usage_error("SOURCE_DATE_EPOCH expects a number of seconds")),
+        None => bundle,
+    };
+    let written = match args.option("--out") {
+        Some(out) => write_atomic(out, |file| bundle.write(std::io::BufWriter::new(file))),
+        None => bundle.write(std::io::stdout().lock()),
+    };
+    if let Err(e) = written {
+        return Ok(lib_error(e));
+    }
+    if let Some(out) = args.option("--out") {
+        println!("wrote {} proofs under root {} to {}", bundle.rows().len(), hex::encode(bundle.root()), out);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `merkle archive build <file> --out <archive> [--hash <hasher>] [--lenient-hex]` and
+/// `merkle archive get <archive> --index <n> [--output human|json]`
+///
+/// `build` writes the proof of every leaf of a hash file to a `ProofArchive`, and `get` reads one
+/// back without loading the archive, printed like `merkle prove` prints it.
+async fn archive_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str = "usage: merkle archive build <file> --out <archive> [--hash <hasher>] [--lenient-hex]\n       \
+                         merkle archive get <archive> --index <n> [--output human|json]";
+    let (command, args) = match args.split_first() {
+        Some((command, args)) if command == "build" || command == "get" => (command.as_str(), args),
+        _ => return Ok(usage_error(USAGE)),
+    };
+    let options: &[&str] = if command == "build" { &["--out", "--hash"] } else { &["--index", "--output"] };
+    let switches: &[&str] = if command == "build" { &["--lenient-hex"] } else { &[] };
+    let args = match ParsedArgs::parse(args, options, switches) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+
+    if command == "build" {
+        let ([file], Some(out), Ok(hasher)) = (args.positional.as_slice(), args.option("--out"), args.hasher()) else {
+            return Ok(usage_error(USAGE));
+        };
+        remove_orphaned_temp(out)?;
+        let build = |tree: MerkleTree| ProofArchive::build(&tree, LeafMode::PreHashed, out);
+        let archive = hash_file_tree_with(file, hasher, args.hex_policy()).and_then(build);
+        return match archive {
+            Ok(archive) => {
+                println!("wrote {} proofs under root {} to {}", archive.len(), hex::encode(archive.root()), out);
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(e) => Ok(lib_error(e)),
+        };
+    }
+
+    let ([file], Some(index), Ok(output)) = (args.positional.as_slice(), args.option("--index"), args.output()) else {
+        return Ok(usage_error(USAGE));
+    };
+    let Ok(index) = index.parse::<usize>() else {
+        return Ok(usage_error("--index expects a leaf index"));
+    };
+    let archive = match ProofArchive::open(file) {
+        Ok(archive) => archive,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let (leaf, proof) = match archive.get_with_leaf(index) {
+        Ok(entry) => entry,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let proof = ProofFile {
+        version: ProofVersion::CURRENT,
+        index,
+        leaf_count: Some(archive.len() as u64),
+        hasher: archive.hasher(),
+        leaf_mode: archive.leaf_mode(),
+        leaf,
+        root: archive.root().clone(),
+        proof,
+    };
+    print_proof(&proof, output, Encoding::Hex)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// A proof as written by `merkle prove-data` and read by `merkle verify-data`
+#[derive(Serialize, Deserialize)]
+struct DataProofFile {
+    #[serde(default)]
+    version: ProofVersion,
+    index: usize,
+    /// Always SHA-256 and `LeafMode::HashData`; missing from files written before they were
+    /// recorded
+    #[serde(default)]
+    hasher: HashAlgorithm,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    leaf_mode: Option<LeafMode>,
+    data: String,
+    #[serde(with = "merkle::util::hex_serde")]
+    root: Hash,
+    proof: OwnedProof,
+}
+
+/// Switches of every command reading lines of text as leaves, see `read_data_leaves`
+const TEXT_SWITCHES: [&str; 2] = ["--allow-empty", "--keep-trailing-empty"];
+
+/// Reads one leaf of data per line, refusing a blank line as a leaf unless `--allow-empty` is given
+///
+/// A blank line is most often a stray newline, whose empty leaf would silently change the root.
+/// Blank lines at the end of the file are dropped, so a file ends in one newline, two or none
+/// has the same root; `--keep-trailing-empty` keeps them as empty leaves instead.
+fn read_data_leaves<R: BufRead>(reader: R, args: &ParsedArgs) -> std::result::Result<Vec<Data>, ExitCode> {
+    let mut lines = read_data_lines(reader).map_err(lib_error)?;
+    let content = lines.iter().rposition(|line| !line.is_empty()).map_or(0, |last| last + 1);
+    if !args.switch("--keep-trailing-empty") {
+        lines.truncate(content);
+    }
+    let policy = if args.switch("--allow-empty") { EmptyLeafPolicy::Allow } else { EmptyLeafPolicy::Reject };
+    match policy.check(&lines[..content]) {
+        Ok(()) => Ok(lines),
+        Err(LibError::EmptyLeaf { index }) => {
+            let message = format!("line {} is empty; pass --allow-empty to hash it as an empty leaf", index + 1);
+            report_error("EMPTY_LEAF", Some(index + 1), &message);
+            Err(ExitStatus::Usage.into())
+        }
+        Err(e) => Err(lib_error(e)),
+    }
+}
+
+/// `merkle prove-data <file> --leaf <string> [--allow-empty] [--keep-trailing-empty]`
+///
+/// Every line of the file is a raw UTF-8 string hashed as leaf data. Prints the proof of the
+/// first line equal to the leaf as JSON, or exits with 3 if no line is.
+async fn prove_data_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str = "usage: merkle prove-data <file> --leaf <string> [--allow-empty] [--keep-trailing-empty]";
+    let args = match ParsedArgs::parse(args, &["--leaf"], &TEXT_SWITCHES) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let ([file], Some(leaf)) = (args.positional.as_slice(), args.option("--leaf")) else {
+        return Ok(usage_error(USAGE));
+    };
+
+    let lines = match read_data_leaves(BufReader::new(File::open(file)?), &args) {
+        Ok(lines) => lines,
+        Err(code) => return Ok(code),
+    };
+    let tree = match TreeConfig::new(LeafMode::HashData).construct(&lines) {
+        Ok(tree) => tree,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let data = leaf.as_bytes().to_vec();
+    let Some(proof) = tree.prove(&data) else {
+        report_error("LEAF_NOT_FOUND", None, &format!("`{}` is not a line of {}", leaf, file));
+        return Ok(ExitStatus::NotFound.into());
+    };
+
+    let index = match proof.implied_index() {
+        Ok(index) => index,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let proof = DataProofFile {
+        version: ProofVersion::CURRENT,
+        index,
+        hasher: tree.hasher(),
+        leaf_mode: Some(LeafMode::HashData),
+        data: leaf.to_string(),
+        root: tree.root(),
+        proof: proof.into_owned(),
+    };
+    println!("{}", serde_json::to_string_pretty(&proof)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `merkle verify-data <proof.json> --leaf <string> --root <hex> [--lenient-hex]`
+///
+/// Checks that the proof written by `merkle prove-data` shows the leaf under the trusted root.
+/// Exits with 2 if it does not, and with 5 for a file recording a hasher or leaf mode other than
+/// SHA-256 data leaves.
+async fn verify_data_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str = "usage: merkle verify-data <proof.json> --leaf <string> --root <hex> [--lenient-hex]";
+    let args = match ParsedArgs::parse(args, &["--leaf", "--root"], &["--lenient-hex"]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let ([file], Some(leaf), Some(root)) = (args.positional.as_slice(), args.option("--leaf"), args.option("--root")) else {
+        return Ok(usage_error(USAGE));
+    };
+    let Ok(root) = args.hex_policy().parse(root) else {
+        return Ok(usage_error("--root expects a hex hash"));
+    };
+
+    let proof: DataProofFile = match read_proof_file(file)? {
+        Ok(proof) => proof,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    if let Err(e) = check_proof_file(&TreeConfig::new(LeafMode::HashData), proof.hasher, proof.leaf_mode) {
+        return Ok(lib_error(e));
+    }
+    let data = leaf.as_bytes().to_vec();
+    if MerkleTree::verify_proof(&data, &proof.proof.as_proof(), &root) {
+        println!("valid: `{}` is leaf {} under root {}", leaf, proof.index, hex::encode(&root));
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("invalid: the proof does not show `{}` under root {}", leaf, hex::encode(&root));
+        Ok(ExitStatus::VerificationFailed.into())
     }
-    hash_list[0].clone()
 }
 
-fn hash2(data: &[u8]) -> Vec<u8> {
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    let first_hash = hasher.finalize_reset();
+/// `merkle compare <file> --tree <hashes> [--output human|json] [--allow-empty] [--keep-trailing-empty]`
+///
+/// Finds the first line of a text file whose hash differs from the leaf at the same position of
+/// the reference hash file. Exits with 2 if any line differs or the number of lines does not match.
+async fn compare_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str = "usage: merkle compare <file> --tree <hashes> [--output human|json] [--allow-empty] \
+                         [--keep-trailing-empty]";
+    let args = match ParsedArgs::parse(args, &["--tree", "--output"], &TEXT_SWITCHES) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let (positional, reference, output) = (args.positional.as_slice(), args.option("--tree"), args.output());
+    let ([file], Some(reference), Ok(output)) = (positional, reference, output) else {
+        return Ok(usage_error(USAGE));
+    };
 
-    hasher.update(&first_hash);
-    let second_hash = hasher.finalize();
+    let lines = match read_data_leaves(BufReader::new(File::open(file)?), &args) {
+        Ok(lines) => lines,
+        Err(code) => return Ok(code),
+    };
+    let reference = match hash_file_tree(reference) {
+        Ok(reference) => reference,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    let index = MerkleTree::find_divergence(&lines, &reference);
+    let matches = index.is_none() && lines.len() == reference.len();
+    match output {
+        OutputFormat::Human => match index {
+            Some(index) => println!("line {} differs from the reference (leaf {})", index + 1, index),
+            None if matches => println!("identical: all {} lines match the reference", lines.len()),
+            None => println!(
+                "{} has {} lines but the reference has {} leaves, the common lines match",
+                file,
+                lines.len(),
+                reference.len()
+            ),
+        },
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "matches": matches,
+                "first_difference": index,
+                "line": index.map(|index| index + 1),
+                "line_count": lines.len(),
+                "reference_leaf_count": reference.len(),
+            })
+        ),
+    }
+    Ok(if matches { ExitCode::SUCCESS } else { ExitStatus::VerificationFailed.into() })
+}
 
-    second_hash.to_vec()
+/// `merkle diff <old> <new> [--hash <hasher>] [--output human|json] [--lenient-hex]`
+///
+/// Compares the trees of two hash files. Exits with 2 unless both hold the same leaves.
+async fn diff_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str = "usage: merkle diff <old> <new> [--hash <hasher>] [--output human|json] [--lenient-hex]";
+    let args = match ParsedArgs::parse(args, &["--hash", "--output"], &["--lenient-hex"]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let ([old, new], Ok(output), Ok(hasher)) = (args.positional.as_slice(), args.output(), args.hasher()) else {
+        return Ok(usage_error(USAGE));
+    };
+
+    let options = DiffOptions::default().with_hasher(hasher).with_hex_policy(args.hex_policy());
+    let report = match diff_files(old, new, &options) {
+        Ok(report) => report,
+        Err(e) => return Ok(lib_error(e)),
+    };
+    match output {
+        OutputFormat::Human => {
+            let (old_count, new_count) = (report.old_leaf_count, report.new_leaf_count);
+            let (added, removed) = (report.added, report.removed);
+            match report.kind {
+                DiffKind::Identical => println!("identical: both files hold the same {} leaves", old_count),
+                DiffKind::Extended => {
+                    println!("extended: {} appends {} leaves to the {} of {}", new, added, old_count, old)
+                }
+                DiffKind::Truncated => {
+                    println!("truncated: {} drops the last {} of the {} leaves of {}", new, removed, old_count, old)
+                }
+                _ => {
+                    let label = if report.kind == DiffKind::Changed { "changed" } else { "diverged" };
+                    let common = old_count.min(new_count);
+                    println!("{}: {} of {} common leaves differ", label, report.differing_count, common);
+                    let mut listed: Vec<String> = report.differing.iter().map(usize::to_string).collect();
+                    if report.differing_count > listed.len() {
+                        listed.push(format!("and {} more", report.differing_count - listed.len()));
+                    }
+                    println!("  differing leaves: {}", listed.join(", "));
+                    if added + removed > 0 {
+                        println!("  {} leaves added, {} removed", added, removed);
+                    }
+                }
+            }
+            println!("  old root: {} ({} leaves)", hex::encode(&report.old_root), old_count);
+            println!("  new root: {} ({} leaves)", hex::encode(&report.new_root), new_count);
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string(&report)?),
+    }
+    Ok(if report.kind == DiffKind::Identical { ExitCode::SUCCESS } else { ExitStatus::VerificationFailed.into() })
+}
+
+/// `merkle detect --input <file> --root <hex> [--format hex|lines] [--output human|json] [--lenient-hex]
+/// [--allow-empty] [--keep-trailing-empty]`
+///
+/// Finds the hasher, leaf mode, odd leaf policy and pair order with which the entries of a file, one
+/// hex string per line or with `--format lines` one line of text, build the claimed root, as
+/// `detect_scheme` does. Exits with 2 if none does.
+async fn detect_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str = "usage: merkle detect --input <file> --root <hex> [--format hex|lines] \
+                         [--output human|json] [--lenient-hex] [--allow-empty] [--keep-trailing-empty]";
+    let switches = ["--lenient-hex", TEXT_SWITCHES[0], TEXT_SWITCHES[1]];
+    let args = match ParsedArgs::parse(args, &["--input", "--root", "--format", "--output"], &switches) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let (Ok(output), [], Some(file), Some(root)) =
+        (args.output(), args.positional.as_slice(), args.option("--input"), args.option("--root"))
+    else {
+        return Ok(usage_error(USAGE));
+    };
+    let Ok(root) = args.hex_policy().parse(root) else {
+        return Ok(usage_error("--root expects a hex hash"));
+    };
+    let reader = BufReader::new(File::open(file)?);
+    let leaves = match args.option("--format").unwrap_or("hex") {
+        "hex" => decode_hashes_with(reader, args.hex_policy()).map_err(lib_error),
+        "lines" => read_data_leaves(reader, &args),
+        other => return Ok(usage_error(&format!("--format expects `hex` or `lines`, got `{}`", other))),
+    };
+    let leaves = match leaves {
+        Ok(leaves) => leaves,
+        Err(code) => return Ok(code),
+    };
+
+    let guess = detect_scheme(&leaves, &root);
+    match (output, guess) {
+        (OutputFormat::Human, Some(guess)) => {
+            let json = serde_json::to_value(guess)?;
+            println!("detected: the {} entries build root {} with", leaves.len(), hex::encode(&root));
+            for field in ["hasher", "leaf_mode", "odd_leaf_policy", "pair_order"] {
+                println!("  {}: {}", field.replace('_', " "), json[field].as_str().unwrap_or_default());
+            }
+            if guess.config().is_none() {
+                println!("  trees of this pair order cannot be built here, so proofs from this crate do not apply");
+            }
+        }
+        (OutputFormat::Human, None) => {
+            println!("undetected: no known scheme builds root {} from the {} entries", hex::encode(&root), leaves.len())
+        }
+        (OutputFormat::Json, _) => println!(
+            "{}",
+            serde_json::json!({
+                "detected": guess.is_some(),
+                "scheme": guess,
+                "supported": guess.is_some_and(|guess| guess.config().is_some()),
+            })
+        ),
+    }
+    Ok(if guess.is_some() { ExitCode::SUCCESS } else { ExitStatus::VerificationFailed.into() })
+}
+
+/// The historical mode: prints the double SHA-256 root of `ts_hashes.json`, as `merkle root --hash sha256d` does
+async fn basic_entry() -> Result<()>{
+
+    let tx_hashes = read_hashes_from_file("ts_hashes.json").map_err(std::io::Error::other)?;
+    println!("hashes: {:?}", tx_hashes);
+    let tx_hashes = match tx_hashes.iter().map(hex::decode).collect::<std::result::Result<Vec<_>, _>>() {
+        Ok(tx_hashes) => tx_hashes,
+        Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+    };
+
+    println!("hashes: {:?}", tx_hashes);
+    match fold_root(tx_hashes, HashAlgorithm::Sha256d) {
+        Ok(merkle_root) => println!("Merkle Root: {}", hex::encode(merkle_root)),
+        Err(LibError::EmptyInput) => eprintln!("No valid values found in the input file."),
+        Err(e) => return Err(std::io::Error::other(e)),
+    }
+    Ok(())
+}
+/// `merkle generate <file> [--count n] [--hash <hasher>]`: writes `n` (default 10) random hashes of
+/// the width of the hasher, one per line
+async fn generate_entry(args: &[String]) -> Result<ExitCode> {
+    const USAGE: &str = "usage: merkle generate <file> [--count n] [--hash <hasher>]";
+    let args = match ParsedArgs::parse(args, &["--count", "--hash"], &[]) {
+        Ok(args) => args,
+        Err(message) => return Ok(usage_error(&message)),
+    };
+    let ([file], Ok(hasher)) = (args.positional.as_slice(), args.hasher()) else {
+        return Ok(usage_error(USAGE));
+    };
+    let Ok(count) = args.option("--count").unwrap_or("10").parse::<usize>() else {
+        return Ok(usage_error("--count expects a non-negative integer"));
+    };
+
+    remove_orphaned_temp(file)?;
+    write_atomic(file, |file| {
+        let mut out = std::io::BufWriter::new(file);
+        for _ in 0..count {
+            writeln!(out, "{}", generate_string(2 * hasher.output_len()))?;
+        }
+        out.flush()
+    })?;
+    Ok(ExitCode::SUCCESS)
 }
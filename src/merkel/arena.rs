@@ -0,0 +1,200 @@
+use super::{
+    checked_node_count, to_usize, Data, Hash, HashAlgorithm, LeafMode, MerkleHasher, MerkleTree, OddLeafPolicy,
+    TreeConfig, MAX_HASH_LEN,
+};
+use crate::util::error::LibError;
+
+/// Most levels a tree can have, its depth being at most `usize::BITS`
+const MAX_LEVELS: usize = usize::BITS as usize + 1;
+
+/// Scratch space for building many small trees one after another, see `TreeConfig::construct_in`
+///
+/// Every node of a build is written to one buffer, which is cleared but not freed before the
+/// next build, so once it has grown to the largest tree built a build allocates nothing. A tree
+/// built in the arena borrows it, so it cannot be built over while the tree is still in use.
+#[derive(Debug, Clone, Default)]
+pub struct TreeArena {
+    hashes: Vec<u8>,
+    /// Index of the first node and number of nodes of each level, from the leaves up
+    levels: Vec<(usize, usize)>,
+    width: usize,
+    policy: OddLeafPolicy,
+    hasher: HashAlgorithm,
+}
+
+/// A tree built in a `TreeArena`, borrowing its nodes from it
+#[derive(Debug, Clone, Copy)]
+pub struct ArenaTree<'a> {
+    arena: &'a TreeArena,
+}
+
+impl TreeArena {
+    pub fn new() -> Self {
+        TreeArena::default()
+    }
+
+    /// An arena that builds trees of up to `leaves` leaves, of any hasher, without allocating
+    pub fn with_capacity(leaves: usize) -> Self {
+        TreeArena {
+            hashes: Vec::with_capacity(leaves.saturating_mul(2 * MAX_HASH_LEN)),
+            levels: Vec::with_capacity(MAX_LEVELS),
+            ..TreeArena::default()
+        }
+    }
+
+    fn node(&self, node: usize) -> &[u8] {
+        &self.hashes[node * self.width..(node + 1) * self.width]
+    }
+}
+
+impl TreeConfig {
+    /// Like `construct`, building the tree in `arena` in place of the last tree built there
+    ///
+    /// Fails as `construct` does, leaving the arena empty. Roots and nodes are those `construct`
+    /// gives for the same input. Under a canonical ordering the sorted leaf hashes are collected
+    /// first, so those builds allocate.
+    pub fn construct_in<'a>(&self, input: &[Data], arena: &'a mut TreeArena) -> Result<ArenaTree<'a>, LibError> {
+        arena.hashes.clear();
+        arena.levels.clear();
+        if input.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        let width = self.hasher().output_len();
+        match self.leaf_mode() {
+            LeafMode::HashData => self.empty_leaf_policy().check(input)?,
+            LeafMode::PreHashed => {
+                if let Some(index) = input.iter().position(|leaf| leaf.len() != width) {
+                    return Err(LibError::InvalidLeafLength { index, expected: width, found: input[index].len() });
+                }
+            }
+        }
+        let nodes = to_usize(checked_node_count(input.len() as u64)?, "node count")?;
+        let bytes = nodes.checked_mul(width).ok_or(LibError::Overflow("node count"))?;
+        arena.hashes.resize(bytes, 0);
+        (arena.width, arena.policy, arena.hasher) = (width, self.odd_leaf_policy(), self.hasher());
+
+        if self.canonical_ordering().is_some() {
+            for (hash, out) in self.leaf_hashes(input)?.iter().zip(arena.hashes.chunks_exact_mut(width)) {
+                out.copy_from_slice(hash);
+            }
+        } else {
+            for (data, out) in input.iter().zip(arena.hashes.chunks_exact_mut(width)) {
+                match self.leaf_mode() {
+                    LeafMode::HashData => arena.hasher.leaf_hash_into(data, out),
+                    LeafMode::PreHashed => out.copy_from_slice(data),
+                }
+            }
+        }
+        let (mut start, mut len) = (0, input.len());
+        arena.levels.push((start, len));
+        while len > 1 {
+            let parents = len.div_ceil(2);
+            let (below, above) = arena.hashes.split_at_mut((start + len) * width);
+            let mut children = below[start * width..].chunks_exact(width);
+            for out in above.chunks_exact_mut(width).take(parents) {
+                let Some(left) = children.next() else { break };
+                match (children.next(), arena.policy) {
+                    (Some(right), _) => arena.hasher.node_hash_into(left, right, out),
+                    (None, OddLeafPolicy::Duplicate) => arena.hasher.node_hash_into(left, left, out),
+                    (None, OddLeafPolicy::Promote) => out.copy_from_slice(left),
+                }
+            }
+            (start, len) = (start + len, parents);
+            arena.levels.push((start, len));
+        }
+        Ok(ArenaTree { arena })
+    }
+}
+
+impl<'a> ArenaTree<'a> {
+    pub fn root(&self) -> &'a [u8] {
+        let levels = &self.arena.levels;
+        self.arena.node(levels[levels.len() - 1].0)
+    }
+
+    /// Number of leaves the tree was built from
+    pub fn len(&self) -> usize {
+        self.arena.levels[0].1
+    }
+
+    /// Always false, since a tree has at least one leaf
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of levels above the leaves
+    pub fn depth(&self) -> usize {
+        self.arena.levels.len() - 1
+    }
+
+    /// Hash of the node at `index` of `level`, level 0 being the leaves
+    pub fn node(&self, level: usize, index: usize) -> Option<&'a [u8]> {
+        let &(start, len) = self.arena.levels.get(level)?;
+        (index < len).then(|| self.arena.node(start + index))
+    }
+
+    /// Copies the tree out of the arena, allocating every hash
+    pub fn to_tree(&self) -> MerkleTree {
+        let levels = self
+            .arena
+            .levels
+            .iter()
+            .map(|&(start, len)| (start..start + len).map(|node| self.arena.node(node).to_vec()).collect::<Vec<Hash>>())
+            .collect();
+        MerkleTree::from_levels_with(levels, self.arena.policy, self.arena.hasher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| (i as u64).to_le_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_matches_construct() {
+        let mut arena = TreeArena::new();
+        for hasher in HashAlgorithm::ALL {
+            for policy in [OddLeafPolicy::Duplicate, OddLeafPolicy::Promote] {
+                let config = TreeConfig::default().with_hasher(hasher).with_odd_leaf_policy(policy);
+                for n in [1, 2, 3, 5, 8, 13, 128] {
+                    let data = example_data(n);
+                    let expected = config.construct(&data).unwrap();
+                    let tree = config.construct_in(&data, &mut arena).unwrap();
+                    assert_eq!((tree.root(), tree.len(), tree.depth()), (&expected.root()[..], n, expected.depth()));
+                    assert_eq!(tree.to_tree().levels(), expected.levels(), "{:?} {:?} {}", hasher, policy, n);
+                    assert_eq!(tree.node(0, n - 1), Some(&expected.leaves()[n - 1][..]));
+                    assert_eq!(tree.node(0, n), None);
+
+                    let pre_hashed =
+                        TreeConfig::new(LeafMode::PreHashed).with_hasher(hasher).with_odd_leaf_policy(policy);
+                    let tree = pre_hashed.construct_in(expected.leaves(), &mut arena).unwrap();
+                    assert_eq!(tree.root(), &expected.root()[..]);
+                }
+            }
+        }
+        assert_eq!(HashAlgorithm::Sha512.output_len(), MAX_HASH_LEN);
+    }
+
+    #[test]
+    fn test_reuses_its_buffer() {
+        let mut arena = TreeArena::with_capacity(128);
+        let capacity = arena.hashes.capacity();
+        let (small, large) = (example_data(7), example_data(128));
+        let config = TreeConfig::default().with_hasher(HashAlgorithm::Sha512);
+        for data in [&large, &small, &large] {
+            let root = config.construct_in(data, &mut arena).unwrap().root().to_vec();
+            assert_eq!(root, config.construct(data).unwrap().root());
+        }
+        assert_eq!(arena.hashes.capacity(), capacity);
+
+        assert!(matches!(TreeConfig::default().construct_in(&[], &mut arena), Err(LibError::EmptyInput)));
+        let pre_hashed = TreeConfig::new(LeafMode::PreHashed).construct_in(&small, &mut arena);
+        assert!(matches!(pre_hashed, Err(LibError::InvalidLeafLength { index: 0, expected: 32, found: 8 })));
+        assert!(arena.levels.is_empty());
+        let sha256 = TreeConfig::default().construct_in(&small, &mut arena).unwrap();
+        assert_eq!(sha256.node(0, 0), Some(&HashAlgorithm::Sha256.leaf_hash(&small[0])[..]));
+    }
+}
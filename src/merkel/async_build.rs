@@ -0,0 +1,123 @@
+use futures::{Stream, StreamExt};
+
+use super::{hash_data, parent_level, Data, Hash, HashAlgorithm, MerkleTree, OddLeafPolicy};
+use crate::util::error::LibError;
+
+/// Number of hashes computed between yields by `MerkleTree::from_stream`
+pub const DEFAULT_YIELD_EVERY: usize = 1024;
+
+impl MerkleTree {
+    /// Constructs the same tree as `construct`, yielding to the tokio runtime every
+    /// `yield_every` hashes so a large build does not starve other tasks on the executor
+    pub async fn construct_async(input: &[Data], yield_every: usize) -> Result<MerkleTree, LibError> {
+        if input.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        let yield_every = yield_every.max(1);
+
+        let mut leaves = Vec::with_capacity(input.len());
+        for chunk in input.chunks(yield_every) {
+            leaves.extend(chunk.iter().map(hash_data));
+            tokio::task::yield_now().await;
+        }
+        Ok(build_levels(leaves, yield_every).await)
+    }
+
+    /// Constructs a tree from leaf data produced by an async source, in stream order
+    pub async fn from_stream<S>(stream: S) -> Result<MerkleTree, LibError>
+    where
+        S: Stream<Item = Data>,
+    {
+        let mut stream = std::pin::pin!(stream);
+        let mut leaves = Vec::new();
+        while let Some(data) = stream.next().await {
+            leaves.push(hash_data(&data));
+            if leaves.len() % DEFAULT_YIELD_EVERY == 0 {
+                tokio::task::yield_now().await;
+            }
+        }
+        if leaves.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        Ok(build_levels(leaves, DEFAULT_YIELD_EVERY).await)
+    }
+}
+
+async fn build_levels(leaves: Vec<Hash>, yield_every: usize) -> MerkleTree {
+    let mut levels = vec![leaves];
+    while levels[levels.len() - 1].len() > 1 {
+        let nodes = &levels[levels.len() - 1];
+        let mut parents = Vec::with_capacity(nodes.len().div_ceil(2));
+        // chunks of an even length keep every pair together
+        for chunk in nodes.chunks(yield_every.saturating_mul(2)) {
+            parents.extend(parent_level(chunk, OddLeafPolicy::Duplicate, HashAlgorithm::Sha256));
+            tokio::task::yield_now().await;
+        }
+        levels.push(parents);
+    }
+    MerkleTree::from_levels(levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| (i as u64).to_le_bytes().to_vec()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_same_root_as_sync() {
+        for n in [1, 2, 3, 5, 8, 1000, 1025] {
+            let data = example_data(n);
+            let expected = MerkleTree::construct(&data).root();
+            for yield_every in [0, 1, 7, 64] {
+                let tree = MerkleTree::construct_async(&data, yield_every).await.unwrap();
+                assert_eq!(tree.root(), expected);
+            }
+            let streamed = MerkleTree::from_stream(futures::stream::iter(data.clone())).await.unwrap();
+            assert_eq!(streamed.root(), expected);
+            assert_eq!(streamed.leaves(), MerkleTree::construct(&data).leaves());
+        }
+
+        assert!(matches!(MerkleTree::construct_async(&[], 10).await, Err(LibError::EmptyInput)));
+        let empty = futures::stream::iter(Vec::<Data>::new());
+        assert!(matches!(MerkleTree::from_stream(empty).await, Err(LibError::EmptyInput)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_hashes_each_node_once() {
+        use crate::merkel::merkel::tests::NODE_HASHES;
+
+        let data = example_data(1025);
+        let before = NODE_HASHES.with(|count| count.get());
+        let tree = MerkleTree::construct_async(&data, 7).await.unwrap();
+        assert_eq!(NODE_HASHES.with(|count| count.get()) - before, tree.node_count() - data.len());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_other_tasks_run_during_build() {
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let watchdog = {
+            let ticks = Arc::clone(&ticks);
+            tokio::spawn(async move {
+                loop {
+                    ticks.fetch_add(1, Ordering::Relaxed);
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+
+        // on a single-threaded runtime the watchdog only runs when the build yields
+        let data = example_data(100_000);
+        let tree = MerkleTree::construct_async(&data, 1000).await.unwrap();
+        let during_build = ticks.load(Ordering::Relaxed);
+        watchdog.abort();
+
+        assert!(during_build >= 100, "watchdog ran only {} times", during_build);
+        assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+    }
+}
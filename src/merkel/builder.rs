@@ -0,0 +1,163 @@
+use std::io::Read;
+
+use super::{CanonicalOrdering, Data, EmptyLeafPolicy, Hash, LeafMode, MerkleHasher, MerkleTree, TreeConfig};
+use crate::util::error::LibError;
+
+/// Builds a tree as `TreeConfig::construct` does, from entries added one at a time
+///
+/// Only the leaf hashes are kept. `add_leaf_from_reader` hashes an entry as it is read, so a
+/// leaf of many megabytes read from disk is never held in memory whole.
+#[derive(Debug, Clone)]
+pub struct MerkleBuilder {
+    config: TreeConfig,
+    leaves: Vec<Hash>,
+}
+
+impl MerkleBuilder {
+    pub fn new(config: TreeConfig) -> Self {
+        MerkleBuilder { config, leaves: vec![] }
+    }
+
+    /// Adds an entry held in memory, returning its leaf hash
+    ///
+    /// Fails as `TreeConfig::construct` would on the entry: with `LibError::EmptyLeaf` under
+    /// `EmptyLeafPolicy::Reject`, and with `LibError::InvalidLeafLength` for a pre-hashed entry
+    /// that is not one hash long.
+    pub fn add_leaf(&mut self, entry: &Data) -> Result<Hash, LibError> {
+        self.add_leaf_from_reader(entry.as_slice())
+    }
+
+    /// Adds the entry `reader` yields, returning its leaf hash
+    ///
+    /// The entry is hashed as it is read, with the leaf prefix of the configured hasher, so the
+    /// leaf is the one `add_leaf` gives for the same bytes. A pre-hashed entry is the leaf hash
+    /// itself, and at most one byte more than a hash is buffered to find it is too long. Fails as
+    /// `add_leaf` does, or with `LibError::Io` if the reader fails.
+    pub fn add_leaf_from_reader<R: Read>(&mut self, mut reader: R) -> Result<Hash, LibError> {
+        let index = self.leaves.len();
+        let leaf = match self.config.leaf_mode() {
+            LeafMode::PreHashed => {
+                let expected = self.config.hasher().output_len();
+                let mut leaf = Vec::with_capacity(expected + 1);
+                (&mut reader).take(expected as u64 + 1).read_to_end(&mut leaf)?;
+                if leaf.len() != expected {
+                    let rest = std::io::copy(&mut reader, &mut std::io::sink())?;
+                    let found = usize::try_from(leaf.len() as u64 + rest).unwrap_or(usize::MAX);
+                    return Err(LibError::InvalidLeafLength { index, expected, found });
+                }
+                leaf
+            }
+            _ => {
+                let (leaf, len) = self.config.hasher().leaf_hash_reader(reader)?;
+                if len == 0 && self.config.empty_leaf_policy() == EmptyLeafPolicy::Reject {
+                    return Err(LibError::EmptyLeaf { index });
+                }
+                leaf
+            }
+        };
+        self.leaves.push(leaf.clone());
+        Ok(leaf)
+    }
+
+    /// Number of entries added so far
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// The tree over the entries added, failing with `LibError::EmptyInput` without any
+    ///
+    /// Under `CanonicalOrdering::ByLeafHash` the leaves are sorted. Entries added from readers
+    /// are not kept, so `CanonicalOrdering::ByData` of raw-data entries fails with
+    /// `LibError::InvalidFormat`; pre-hashed entries are their own leaves and are sorted.
+    pub fn build(self) -> Result<MerkleTree, LibError> {
+        let MerkleBuilder { config, mut leaves } = self;
+        if leaves.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        match (config.canonical_ordering(), config.leaf_mode()) {
+            (None, _) => {}
+            (Some(CanonicalOrdering::ByData), LeafMode::HashData) => {
+                return Err(LibError::InvalidFormat(
+                    "ordering by data needs the entries, which a builder does not keep; use TreeConfig::construct"
+                        .into(),
+                ))
+            }
+            (Some(_), _) => leaves.sort(),
+        }
+        Ok(MerkleTree::build(leaves, config.odd_leaf_policy(), config.hasher()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{HashAlgorithm, OddLeafPolicy};
+
+    use std::fs::{self, File};
+    use std::io::BufReader;
+
+    /// A 20 MB blob of bytes that do not repeat with the chunk size
+    fn blob() -> Vec<u8> {
+        (0..20_000_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_reader_matches_memory() {
+        let path = std::env::temp_dir().join(format!("merkle-builder-{}", std::process::id()));
+        let blob = blob();
+        fs::write(&path, &blob).unwrap();
+        let entries: Vec<Data> = vec![b"small".to_vec(), blob, vec![], b"last".to_vec()];
+
+        for hasher in [HashAlgorithm::Sha256, HashAlgorithm::Sha256Rfc6962] {
+            let config = TreeConfig::default().with_hasher(hasher).with_odd_leaf_policy(OddLeafPolicy::Promote);
+            let (mut streamed, mut in_memory) = (MerkleBuilder::new(config), MerkleBuilder::new(config));
+            for (i, entry) in entries.iter().enumerate() {
+                let leaf = match i {
+                    1 => streamed.add_leaf_from_reader(BufReader::new(File::open(&path).unwrap())).unwrap(),
+                    _ => streamed.add_leaf_from_reader(entry.as_slice()).unwrap(),
+                };
+                assert_eq!(leaf, in_memory.add_leaf(entry).unwrap(), "{:?} leaf {}", hasher, i);
+                assert_eq!(leaf, hasher.leaf_hash(entry));
+            }
+            let root = config.construct(&entries).unwrap().root();
+            assert_eq!((streamed.build().unwrap().root(), in_memory.build().unwrap().root()), (root.clone(), root));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_checks_entries_as_construct_does() {
+        let reject = TreeConfig::default().with_empty_leaf_policy(EmptyLeafPolicy::Reject);
+        let mut builder = MerkleBuilder::new(reject);
+        builder.add_leaf(&b"a".to_vec()).unwrap();
+        assert!(matches!(builder.add_leaf_from_reader(&b""[..]), Err(LibError::EmptyLeaf { index: 1 })));
+        assert_eq!(builder.len(), 1);
+
+        let pre_hashed = TreeConfig::new(LeafMode::PreHashed).with_hasher(HashAlgorithm::Ripemd160);
+        let mut builder = MerkleBuilder::new(pre_hashed);
+        let long = builder.add_leaf_from_reader(&[7u8; 1000][..]);
+        assert!(matches!(long, Err(LibError::InvalidLeafLength { index: 0, expected: 20, found: 1000 })));
+        assert!(matches!(builder.add_leaf(&vec![7; 19]), Err(LibError::InvalidLeafLength { found: 19, .. })));
+        let leaves: Vec<Data> = (0..3u8).rev().map(|i| vec![i; 20]).collect();
+        for leaf in &leaves {
+            assert_eq!(&builder.add_leaf_from_reader(leaf.as_slice()).unwrap(), leaf);
+        }
+        let sorted = pre_hashed.with_canonical_ordering(CanonicalOrdering::ByData);
+        let mut sorted_builder = MerkleBuilder::new(sorted);
+        for leaf in &leaves {
+            sorted_builder.add_leaf(leaf).unwrap();
+        }
+        assert_eq!(sorted_builder.build().unwrap().root(), sorted.construct(&leaves).unwrap().root());
+        assert_eq!(builder.build().unwrap().root(), pre_hashed.construct(&leaves).unwrap().root());
+
+        let by_data = TreeConfig::default().with_canonical_ordering(CanonicalOrdering::ByData);
+        let mut builder = MerkleBuilder::new(by_data);
+        builder.add_leaf(&b"a".to_vec()).unwrap();
+        assert!(matches!(builder.build(), Err(LibError::InvalidFormat(_))));
+        assert!(matches!(MerkleBuilder::new(TreeConfig::default()).build(), Err(LibError::EmptyInput)));
+    }
+}
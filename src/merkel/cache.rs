@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use super::{LeafIndex, MerkleTree, OwnedProof};
+use crate::util::error::LibError;
+
+/// Hit/miss counters of a `ProofCache` or a `SubtreeCache`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Number of proofs currently cached
+    pub len: usize,
+}
+
+/// Least-recently-used cache of proofs for a static tree
+///
+/// The cache is `Send + Sync`, so it can be shared behind an `Arc` between request handlers.
+/// A capacity of 0 disables caching and every lookup generates a fresh proof.
+pub struct ProofCache {
+    tree: MerkleTree,
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    /// Only looked up, never iterated; what is evicted is decided by `recency`
+    entries: HashMap<usize, (Arc<OwnedProof>, u64)>,
+    /// Leaf indices by the tick they were last used at, oldest first
+    recency: BTreeMap<u64, usize>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl ProofCache {
+    pub fn new(tree: MerkleTree, capacity: usize) -> Self {
+        ProofCache {
+            tree,
+            capacity,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// The tree proofs are generated from
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Returns the cached proof for the leaf at `index`, generating and caching it on a miss
+    ///
+    /// Proofs are shared, so a hit costs a reference count increment rather than a copy.
+    pub fn get_or_generate(&self, index: impl Into<LeafIndex>) -> Result<Arc<OwnedProof>, LibError> {
+        let index = index.into().saturating_usize();
+        if let Some(proof) = self.lock().get(index) {
+            return Ok(proof);
+        }
+
+        // generate without holding the lock so other lookups are not blocked
+        let proof = Arc::new(self.tree.prove_by_index(index)?.into_owned());
+        self.lock().insert(index, Arc::clone(&proof), self.capacity);
+        Ok(proof)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.lock();
+        CacheStats {
+            len: state.entries.len(),
+            ..state.stats
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        // the state is always left consistent, so a panic elsewhere does not invalidate it
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl CacheState {
+    fn get(&mut self, index: usize) -> Option<Arc<OwnedProof>> {
+        self.tick += 1;
+        let Some((proof, last_used)) = self.entries.get_mut(&index) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.recency.remove(last_used);
+        self.recency.insert(self.tick, index);
+        *last_used = self.tick;
+        self.stats.hits += 1;
+        Some(Arc::clone(proof))
+    }
+
+    fn insert(&mut self, index: usize, proof: Arc<OwnedProof>, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, last_used)) = self.entries.insert(index, (proof, self.tick)) {
+            // another thread generated the same proof concurrently
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(self.tick, index);
+
+        while self.entries.len() > capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_cache(n: usize, capacity: usize) -> ProofCache {
+        let data: Vec<Vec<u8>> = (0..n).map(|i| vec![i as u8]).collect();
+        ProofCache::new(MerkleTree::construct(&data), capacity)
+    }
+
+    #[test]
+    fn test_hits_and_misses() {
+        let cache = example_cache(8, 4);
+        let proof = cache.get_or_generate(3).unwrap();
+        assert_eq!(*proof, cache.tree().prove_by_index(3).unwrap().into_owned());
+        assert_eq!(cache.get_or_generate(3).unwrap(), proof);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.len), (1, 1, 1));
+
+        assert!(matches!(cache.get_or_generate(8), Err(LibError::IndexOutOfRange { .. })));
+        assert_eq!(cache.stats().misses, 2);
+        assert_eq!(cache.stats().len, 1);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = example_cache(8, 2);
+        cache.get_or_generate(0).unwrap();
+        cache.get_or_generate(1).unwrap();
+        // touching 0 makes 1 the eviction candidate
+        cache.get_or_generate(0).unwrap();
+        cache.get_or_generate(2).unwrap();
+
+        let stats = cache.stats();
+        assert_eq!((stats.len, stats.evictions), (2, 1));
+
+        cache.get_or_generate(0).unwrap();
+        assert_eq!(cache.stats().hits, 2);
+        cache.get_or_generate(1).unwrap();
+        assert_eq!(cache.stats().misses, 4);
+    }
+
+    #[test]
+    fn test_zero_capacity_never_caches() {
+        let cache = example_cache(4, 0);
+        cache.get_or_generate(1).unwrap();
+        cache.get_or_generate(1).unwrap();
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.len), (0, 2, 0));
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ProofCache>();
+
+        let cache = Arc::new(example_cache(16, 16));
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let cache = Arc::clone(&cache);
+                std::thread::spawn(move || {
+                    for i in 0..16 {
+                        let proof = cache.get_or_generate((i + t) % 16).unwrap();
+                        let data = vec![((i + t) % 16) as u8];
+                        assert!(MerkleTree::verify_proof(&data, &proof.as_proof(), &cache.tree().root()));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits + stats.misses, 64);
+        assert_eq!(stats.len, 16);
+    }
+}
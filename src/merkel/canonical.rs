@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+
+use super::{hash_data, Data, Hash, MerkleTree, TreeConfig};
+use crate::util::error::LibError;
+
+/// Order entries are put in before they become leaves, so that the root does not depend on the
+/// order they arrived in; see `TreeConfig::with_canonical_ordering`
+///
+/// Equal entries keep their order among themselves, which does not change the root since they
+/// give equal leaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum CanonicalOrdering {
+    /// By leaf hash, as the tree's hasher computes it
+    ByLeafHash,
+    /// By the bytes of the entries, an entry before the longer entries it is a prefix of
+    ByData,
+}
+
+/// Sorts `leaves` into `ordering`, hashing as `MerkleTree::construct` does for `ByLeafHash`
+///
+/// `TreeConfig::with_canonical_ordering` applies the same order with the configured hasher.
+pub fn canonicalize_leaves(mut leaves: Vec<Data>, ordering: CanonicalOrdering) -> Vec<Data> {
+    match ordering {
+        CanonicalOrdering::ByLeafHash => leaves.sort_by_cached_key(hash_data),
+        CanonicalOrdering::ByData => leaves.sort(),
+    }
+    leaves
+}
+
+/// Indices of `input` in canonical order: the entry at canonical position `i` is `input[order[i]]`
+pub(crate) fn canonical_order<D: AsRef<[u8]>>(ordering: CanonicalOrdering, input: &[D], hashes: &[Hash]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..input.len()).collect();
+    match ordering {
+        CanonicalOrdering::ByLeafHash => order.sort_by(|&a, &b| hashes[a].cmp(&hashes[b])),
+        CanonicalOrdering::ByData => order.sort_by(|&a, &b| input[a].as_ref().cmp(input[b].as_ref())),
+    }
+    order
+}
+
+impl TreeConfig {
+    /// Like `construct`, also returning the leaf index each entry of `input` ended up at, so
+    /// `positions[i]` is the index to prove `input[i]` by
+    ///
+    /// Without a canonical ordering every entry stays where it is.
+    pub fn construct_canonical(&self, input: &[Data]) -> Result<(MerkleTree, Vec<usize>), LibError> {
+        let hashes = self.arrival_leaf_hashes(input)?;
+        let order = match self.canonical_ordering() {
+            Some(ordering) => canonical_order(ordering, input, &hashes),
+            None => (0..input.len()).collect(),
+        };
+        let mut positions = vec![0; input.len()];
+        for (position, &index) in order.iter().enumerate() {
+            positions[index] = position;
+        }
+        let leaves = order.iter().map(|&index| hashes[index].clone()).collect();
+        Ok((MerkleTree::build(leaves, self.odd_leaf_policy(), self.hasher()), positions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{HashAlgorithm, LeafMode, MerkleHasher};
+
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    fn items() -> Vec<Data> {
+        ["block-7", "block-12", "block", "alpha", "omega", "block-7"].map(|item| item.as_bytes().to_vec()).to_vec()
+    }
+
+    /// The items in three arrival orders: as they are, reversed and shuffled
+    fn arrivals() -> [Vec<Data>; 3] {
+        let mut shuffled = items();
+        shuffled.shuffle(&mut StdRng::seed_from_u64(178));
+        [items(), items().into_iter().rev().collect(), shuffled]
+    }
+
+    #[test]
+    fn test_root_is_independent_of_arrival_order() {
+        for ordering in [CanonicalOrdering::ByLeafHash, CanonicalOrdering::ByData] {
+            let config = TreeConfig::default().with_canonical_ordering(ordering);
+            let expected = MerkleTree::construct(&canonicalize_leaves(items(), ordering)).root();
+            for input in arrivals() {
+                assert_eq!(config.construct(&input).unwrap().root(), expected, "{:?}", ordering);
+                let (tree, positions) = config.construct_canonical(&input).unwrap();
+                assert_eq!(tree.root(), expected);
+                for (index, item) in input.iter().enumerate() {
+                    let proof = tree.prove_by_index(positions[index]).unwrap();
+                    assert!(MerkleTree::verify_proof(item, &proof, &expected));
+                    // the leftmost copy of a repeated item is found by data
+                    assert!(MerkleTree::verify_proof(item, &tree.prove(item).unwrap(), &expected));
+                }
+            }
+        }
+        let sorted = canonicalize_leaves(items(), CanonicalOrdering::ByData);
+        assert_eq!(&sorted[..3], [b"alpha".to_vec(), b"block".to_vec(), b"block-12".to_vec()]);
+    }
+
+    #[test]
+    fn test_orderings_use_the_configured_hasher() {
+        let config = TreeConfig::default().with_hasher(HashAlgorithm::Sha512);
+        let by_hash = config.with_canonical_ordering(CanonicalOrdering::ByLeafHash);
+        let tree = by_hash.construct(&items()).unwrap();
+        assert!(tree.leaves().windows(2).all(|pair| pair[0] <= pair[1]));
+        let mut expected: Vec<Hash> = items().iter().map(|item| HashAlgorithm::Sha512.leaf_hash(item)).collect();
+        expected.sort();
+        assert_eq!(tree.leaves(), &expected[..]);
+
+        // pre-hashed leaves are ordered as they are
+        let pre_hashed = TreeConfig::new(LeafMode::PreHashed).with_canonical_ordering(CanonicalOrdering::ByLeafHash);
+        let (tree, positions) = pre_hashed.construct_canonical(&[vec![2; 32], vec![1; 32], vec![3; 32]]).unwrap();
+        assert_eq!((tree.leaves()[0].clone(), positions), (vec![1; 32], vec![1, 0, 2]));
+        let (_, identity) = TreeConfig::default().construct_canonical(&items()).unwrap();
+        assert_eq!(identity, (0..6).collect::<Vec<_>>());
+        assert_eq!(config.canonical_ordering(), None);
+    }
+}
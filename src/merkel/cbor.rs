@@ -0,0 +1,605 @@
+//! Deterministic CBOR encoding of proofs and trees.
+//!
+//! Every artifact is a definite-length map whose text keys are always written in the same
+//! (bytewise sorted) order, so equal values encode to identical bytes across runs. Each map
+//! carries a `version`, which is taken to be 1 when missing from older payloads.
+
+use ciborium::Value;
+
+use std::io::{Read, Write};
+
+use super::{
+    check_directions, check_hash_lens, check_hash_widths, check_step_count, Hash, HashAlgorithm, HashDirection,
+    LeafMode, MerkleHasher, MerkleTree, OwnedProof, ProofOrder, ProofVersion, TreeConfig, TreeDescriptor, TreeShape,
+    CRATE_VERSION,
+};
+use crate::util::error::LibError;
+
+impl OwnedProof {
+    /// Encodes the proof as canonical CBOR
+    ///
+    /// The hasher and leaf mode are recorded when the proof is tagged with them, see
+    /// `TreeConfig::tag_proof`. The order is only recorded for `ProofOrder::RootToLeaf` proofs, so others encode as they did
+    /// before proofs declared one.
+    pub fn to_cbor(&self) -> Result<Vec<u8>, LibError> {
+        let mut hashes: Vec<Value> = self
+            .hashes()
+            .iter()
+            .map(|(direction, hash)| Value::Array(vec![direction_to_value(*direction), Value::Bytes(hash.to_vec())]))
+            .collect();
+        let mut entries = Vec::new();
+        if let Some(hasher) = self.hasher() {
+            entries.push((text("hasher"), text(hasher.id())));
+        }
+        if self.order() == ProofOrder::RootToLeaf {
+            hashes.reverse();
+        }
+        entries.push((text("hashes"), Value::Array(hashes)));
+        if let Some(leaf_mode) = self.leaf_mode() {
+            entries.push((text("leaf_mode"), text(leaf_mode.id())));
+        }
+        if self.order() == ProofOrder::RootToLeaf {
+            entries.push((text("order"), text("root-to-leaf")));
+        }
+        if let Some(size) = self.tree_size() {
+            entries.push((text("tree_size"), Value::Integer((size as u64).into())));
+        }
+        entries.push((text("version"), version_to_value()));
+        let value = Value::Map(entries);
+
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&value, &mut bytes).map_err(invalid)?;
+        Ok(bytes)
+    }
+
+    /// Decodes a proof written by `to_cbor`, rejecting unknown fields, versions and hashers
+    ///
+    /// A recorded hasher must be one `HashAlgorithm::from_id` knows, and tags the proof; every step
+    /// must then be of its `output_len`. A recorded leaf mode tags it too, but is only checked by
+    /// `TreeConfig::load_proof_cbor`.
+    pub fn from_cbor(mut bytes: &[u8]) -> Result<OwnedProof, LibError> {
+        let value: Value = ciborium::from_reader(&mut bytes).map_err(invalid)?;
+        if !bytes.is_empty() {
+            return Err(LibError::InvalidFormat(format!("{} trailing bytes after proof", bytes.len())));
+        }
+
+        let (version, value) = take_version(value)?;
+        let (tree_size, value) = take_entry(value, "tree_size")?;
+        let (order, value) = take_entry(value, "order")?;
+        let order = match order {
+            None => ProofOrder::LeafToRoot,
+            Some(Value::Text(order)) if order == "root-to-leaf" => ProofOrder::RootToLeaf,
+            Some(_) => return Err(LibError::InvalidFormat("order must be `root-to-leaf` when present".into())),
+        };
+        let (hasher, value) = take_entry(value, "hasher")?;
+        let hasher = hasher.map(read_hasher).transpose()?;
+        let (leaf_mode, value) = take_entry(value, "leaf_mode")?;
+        let leaf_mode = read_leaf_mode(leaf_mode)?;
+        let [hashes] = fields(value, ["hashes"])?;
+        let hashes = into_array(hashes, "hashes")?;
+        check_step_count(hashes.len())?;
+        let hashes = hashes
+            .into_iter()
+            .map(|entry| match into_array(entry, "hashes entry")?.as_slice() {
+                [direction, Value::Bytes(hash)] => Ok((direction_from_value(direction)?, hash.clone())),
+                _ => Err(LibError::InvalidFormat("proof entry must be [direction, hash]".into())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        check_directions(version, &hashes)?;
+        check_hash_lens(hashes.iter().map(|(_, hash)| hash))?;
+        if let Some(hasher) = hasher {
+            check_hash_widths(hasher, hashes.iter().map(|(_, hash)| hash))?;
+        }
+        let proof = OwnedProof::from_ordered_steps(hashes, order);
+        let proof = match hasher {
+            Some(hasher) => proof.with_hasher(hasher),
+            None => proof,
+        };
+        let proof = match leaf_mode {
+            Some(leaf_mode) => proof.with_leaf_mode(leaf_mode),
+            None => proof,
+        };
+        match tree_size {
+            None => Ok(proof),
+            Some(size) => {
+                let size = into_u64(size, "tree_size")?;
+                Ok(proof.with_tree_size(super::to_usize(size, "tree size")?))
+            }
+        }
+    }
+}
+
+impl MerkleTree {
+    /// Writes the hasher, leaf hashes and root of the tree as canonical CBOR, with the version of
+    /// this crate
+    ///
+    /// The leaf mode is not known to the tree and goes unrecorded; `TreeConfig::save_cbor` records
+    /// it. The shape is only recorded for `TreeShape::LeftBalanced` trees, so a paired tree reads
+    /// the same in versions that did not record shapes.
+    pub fn save_cbor<W: Write>(&self, writer: W) -> Result<(), LibError> {
+        save_tree(self, None, writer)
+    }
+
+    /// Reads a tree written by `save_cbor`, rebuilding it and checking the stored root
+    ///
+    /// A recorded leaf mode is accepted but not checked; `TreeConfig::load_cbor` checks it.
+    pub fn load_cbor<R: Read>(reader: R) -> Result<MerkleTree, LibError> {
+        Ok(load_tree(reader, None)?.0)
+    }
+
+    /// Like `load_cbor`, also returning the descriptor recorded with the tree
+    ///
+    /// The leaf mode and crate version are those of the writer, None where it did not record them.
+    pub fn load_cbor_with_descriptor<R: Read>(reader: R) -> Result<(MerkleTree, TreeDescriptor), LibError> {
+        load_tree(reader, None)
+    }
+}
+
+impl TreeConfig {
+    /// Like `MerkleTree::save_cbor`, also recording the leaf mode of this configuration
+    ///
+    /// Fails with `LibError::HasherMismatch` for a tree built with another hasher.
+    pub fn save_cbor<W: Write>(&self, tree: &MerkleTree, writer: W) -> Result<(), LibError> {
+        self.check_artifact("tree", tree.hasher().id(), Some(self.leaf_mode()))?;
+        save_tree(tree, Some(self.leaf_mode()), writer)
+    }
+
+    /// Like `MerkleTree::load_cbor`, first failing with `LibError::HasherMismatch` unless the tree
+    /// was saved by `TreeConfig::save_cbor` under the hasher and leaf mode of this configuration
+    pub fn load_cbor<R: Read>(&self, reader: R) -> Result<MerkleTree, LibError> {
+        Ok(load_tree(reader, Some(self))?.0)
+    }
+
+    /// Like `OwnedProof::from_cbor`, also failing with `LibError::HasherMismatch` unless the proof
+    /// is tagged with the hasher and leaf mode of this configuration, see `TreeConfig::check_proof`
+    pub fn load_proof_cbor(&self, bytes: &[u8]) -> Result<OwnedProof, LibError> {
+        let proof = OwnedProof::from_cbor(bytes)?;
+        self.check_proof("CBOR proof", &proof)?;
+        Ok(proof)
+    }
+}
+
+fn save_tree<W: Write>(tree: &MerkleTree, leaf_mode: Option<LeafMode>, writer: W) -> Result<(), LibError> {
+    let leaves = tree.leaves().iter().map(|h| Value::Bytes(h.clone())).collect();
+    let mut entries = vec![(text("crate_version"), text(CRATE_VERSION)), (text("hasher"), text(tree.hasher().id()))];
+    if let Some(leaf_mode) = leaf_mode {
+        entries.push((text("leaf_mode"), text(leaf_mode.id())));
+    }
+    entries.extend([(text("leaves"), Value::Array(leaves)), (text("root"), Value::Bytes(tree.root()))]);
+    if tree.shape() == TreeShape::LeftBalanced {
+        entries.push((text("shape"), text("left-balanced")));
+    }
+    entries.push((text("version"), version_to_value()));
+    ciborium::into_writer(&Value::Map(entries), writer).map_err(|e| match e {
+        ciborium::ser::Error::Io(e) => LibError::Io(e),
+        ciborium::ser::Error::Value(e) => LibError::InvalidFormat(e),
+    })
+}
+
+fn load_tree<R: Read>(reader: R, config: Option<&TreeConfig>) -> Result<(MerkleTree, TreeDescriptor), LibError> {
+    let value: Value = ciborium::from_reader(reader).map_err(invalid)?;
+
+    let (ProofVersion::V1 | ProofVersion::V2, value) = take_version(value)?;
+    let (crate_version, value) = take_entry(value, "crate_version")?;
+    let crate_version = match crate_version {
+        None => None,
+        Some(Value::Text(version)) => Some(version),
+        Some(_) => return Err(LibError::InvalidFormat("crate_version must be text".into())),
+    };
+    let (leaf_mode, value) = take_entry(value, "leaf_mode")?;
+    let leaf_mode = read_leaf_mode(leaf_mode)?;
+    let (shape, value) = take_entry(value, "shape")?;
+    let shape = match shape {
+        None => TreeShape::Paired,
+        Some(Value::Text(shape)) if shape == "left-balanced" => TreeShape::LeftBalanced,
+        Some(_) => return Err(LibError::InvalidFormat("shape must be `left-balanced` when present".into())),
+    };
+    let [hasher, leaves, root] = fields(value, ["hasher", "leaves", "root"])?;
+    if let (Some(config), Value::Text(id)) = (config, &hasher) {
+        config.check_artifact("CBOR tree", id, leaf_mode)?;
+    }
+    let hasher = read_hasher(hasher)?;
+    let leaves = into_array(leaves, "leaves")?
+        .into_iter()
+        .map(|leaf| into_bytes(leaf, "leaf"))
+        .collect::<Result<Vec<Hash>, _>>()?;
+    let root = into_bytes(root, "root")?;
+    if leaves.is_empty() {
+        return Err(LibError::InvalidFormat("tree has no leaves".into()));
+    }
+
+    let tree = MerkleTree::build(leaves, shape.odd_leaf_policy(), hasher);
+    if tree.root() != root {
+        return Err(LibError::InvalidFormat("stored root does not match the leaves".into()));
+    }
+    let descriptor = TreeDescriptor { leaf_mode, crate_version, ..tree.descriptor() };
+    Ok((tree, descriptor))
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+fn invalid<E: std::fmt::Display>(e: E) -> LibError {
+    LibError::InvalidFormat(format!("CBOR: {}", e))
+}
+
+/// Splits a map into the values of exactly the given keys, in that order
+fn fields<const N: usize>(value: Value, keys: [&str; N]) -> Result<[Value; N], LibError> {
+    let Value::Map(entries) = value else {
+        return Err(LibError::InvalidFormat("expected a CBOR map".into()));
+    };
+
+    let mut values: [Option<Value>; N] = std::array::from_fn(|_| None);
+    for (key, value) in entries {
+        let Value::Text(key) = key else {
+            return Err(LibError::InvalidFormat("map keys must be text".into()));
+        };
+        let slot = keys
+            .iter()
+            .position(|k| *k == key)
+            .ok_or_else(|| LibError::InvalidFormat(format!("unknown field `{}`", key)))?;
+        if values[slot].replace(value).is_some() {
+            return Err(LibError::InvalidFormat(format!("duplicate field `{}`", key)));
+        }
+    }
+
+    let mut missing = keys.iter().zip(&values).filter(|(_, v)| v.is_none());
+    if let Some((key, _)) = missing.next() {
+        return Err(LibError::InvalidFormat(format!("missing field `{}`", key)));
+    }
+    Ok(values.map(|v| v.unwrap_or(Value::Null)))
+}
+
+fn version_to_value() -> Value {
+    Value::Integer(ProofVersion::CURRENT.number().into())
+}
+
+/// Removes the `version` entry from a map, defaulting to version 1 for unversioned payloads
+fn take_version(value: Value) -> Result<(ProofVersion, Value), LibError> {
+    let (version, value) = take_entry(value, "version")?;
+    let version = match version {
+        None => ProofVersion::V1,
+        Some(version) => ProofVersion::from_number(into_u64(version, "version")?)?,
+    };
+    Ok((version, value))
+}
+
+/// Removes an optional entry from a map
+fn take_entry(value: Value, key: &str) -> Result<(Option<Value>, Value), LibError> {
+    let Value::Map(mut entries) = value else {
+        return Err(LibError::InvalidFormat("expected a CBOR map".into()));
+    };
+    let entry = entries
+        .iter()
+        .position(|(k, _)| k.as_text() == Some(key))
+        .map(|position| entries.remove(position).1);
+    Ok((entry, Value::Map(entries)))
+}
+
+fn into_u64(value: Value, what: &str) -> Result<u64, LibError> {
+    value
+        .as_integer()
+        .and_then(|n| u64::try_from(n).ok())
+        .ok_or_else(|| LibError::InvalidFormat(format!("{} must be an unsigned integer", what)))
+}
+
+fn read_leaf_mode(value: Option<Value>) -> Result<Option<LeafMode>, LibError> {
+    match value {
+        None => Ok(None),
+        Some(Value::Text(id)) => {
+            let unknown = || LibError::InvalidFormat(format!("unknown leaf mode `{}`", id));
+            Ok(Some(LeafMode::from_id(&id).ok_or_else(unknown)?))
+        }
+        Some(_) => Err(LibError::InvalidFormat("leaf_mode must be text".into())),
+    }
+}
+
+fn read_hasher(value: Value) -> Result<HashAlgorithm, LibError> {
+    match value {
+        Value::Text(found) => HashAlgorithm::from_id(&found).ok_or(LibError::UnknownHasher(found)),
+        _ => Err(LibError::InvalidFormat("hasher must be text".into())),
+    }
+}
+
+fn into_array(value: Value, what: &str) -> Result<Vec<Value>, LibError> {
+    match value {
+        Value::Array(items) => Ok(items),
+        _ => Err(LibError::InvalidFormat(format!("{} must be an array", what))),
+    }
+}
+
+fn into_bytes(value: Value, what: &str) -> Result<Vec<u8>, LibError> {
+    match value {
+        Value::Bytes(bytes) => Ok(bytes),
+        _ => Err(LibError::InvalidFormat(format!("{} must be a byte string", what))),
+    }
+}
+
+fn direction_to_value(direction: HashDirection) -> Value {
+    match direction {
+        HashDirection::Left => Value::Integer(0.into()),
+        HashDirection::Right => Value::Integer(1.into()),
+        HashDirection::Duplicate => Value::Integer(2.into()),
+    }
+}
+
+fn direction_from_value(value: &Value) -> Result<HashDirection, LibError> {
+    match value.as_integer().map(i128::from) {
+        Some(0) => Ok(HashDirection::Left),
+        Some(1) => Ok(HashDirection::Right),
+        Some(2) => Ok(HashDirection::Duplicate),
+        _ => Err(LibError::InvalidFormat("direction must be 0 (left), 1 (right) or 2 (duplicate)".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::HASHER_ID;
+
+    fn example_tree(n: usize) -> MerkleTree {
+        let data: Vec<Vec<u8>> = (0..n).map(|i| vec![i as u8]).collect();
+        MerkleTree::construct(&data)
+    }
+
+    #[test]
+    fn test_proof_round_trip() {
+        let tree = example_tree(5);
+        for i in 0..5 {
+            let proof = tree.prove_by_index(i).unwrap().into_owned();
+            let bytes = proof.to_cbor().unwrap();
+            assert_eq!(bytes, proof.to_cbor().unwrap());
+
+            let decoded = OwnedProof::from_cbor(&bytes).unwrap();
+            assert_eq!(decoded, proof);
+            assert!(MerkleTree::verify_proof(&vec![i as u8], &decoded.as_proof(), &tree.root()));
+        }
+
+        // a tagged proof records its hasher and leaf mode
+        let tagged = TreeConfig::new(LeafMode::PreHashed).tag_proof(tree.prove_by_index(1).unwrap().into_owned());
+        let decoded = OwnedProof::from_cbor(&tagged.to_cbor().unwrap()).unwrap();
+        assert_eq!((decoded.leaf_mode(), &decoded), (Some(LeafMode::PreHashed), &tagged));
+    }
+
+    #[test]
+    fn test_pinned_proof_encoding() {
+        let tree = example_tree(2);
+        let proof = tree.prove_by_index(0).unwrap().into_owned().with_hasher(tree.hasher());
+        let expected = concat!(
+            "a4",                             // map(4)
+            "66686173686572", "66736861323536", // "hasher": "sha256"
+            "66686173686573", "81",             // "hashes": array(1)
+            "8201", "5820",                     // [1, bytes(32)
+            "4bf5122f344554c53bde2ebb8cd2b7e3d1600ad631c385a5d7cce23c7785459a",
+            "69747265655f73697a65", "02",       // "tree_size": 2
+            "6776657273696f6e", "02",           // "version": 2
+        );
+        assert_eq!(hex::encode(proof.to_cbor().unwrap()), expected);
+        // an untagged proof records no hasher
+        let untagged = tree.prove_by_index(0).unwrap().into_owned().to_cbor().unwrap();
+        assert_eq!(hex::encode(untagged), format!("a3{}", &expected[30..]));
+    }
+
+    #[test]
+    fn test_tree_round_trip() {
+        let tree = example_tree(7);
+        let mut first = Vec::new();
+        tree.save_cbor(&mut first).unwrap();
+        let mut second = Vec::new();
+        tree.save_cbor(&mut second).unwrap();
+        assert_eq!(first, second);
+
+        let loaded = MerkleTree::load_cbor(first.as_slice()).unwrap();
+        assert_eq!(loaded.root(), tree.root());
+        assert_eq!(loaded.leaves(), tree.leaves());
+
+        // the hasher is recorded, so trees of other widths load with their own
+        for hasher in [HashAlgorithm::Sha512, HashAlgorithm::Ripemd160] {
+            let data: Vec<Vec<u8>> = (0..7).map(|i| vec![i as u8]).collect();
+            let tree = crate::merkel::TreeConfig::default().with_hasher(hasher).construct(&data).unwrap();
+            let mut bytes = Vec::new();
+            tree.save_cbor(&mut bytes).unwrap();
+            let loaded = MerkleTree::load_cbor(bytes.as_slice()).unwrap();
+            assert_eq!((loaded.hasher(), loaded.root()), (hasher, tree.root()));
+        }
+
+        // so is a left-balanced shape, whose root differs from the paired one
+        let data: Vec<Vec<u8>> = (0..7).map(|i| vec![i as u8]).collect();
+        let tree = TreeConfig::default().with_shape(TreeShape::LeftBalanced).construct(&data).unwrap();
+        let mut bytes = Vec::new();
+        tree.save_cbor(&mut bytes).unwrap();
+        let loaded = MerkleTree::load_cbor(bytes.as_slice()).unwrap();
+        assert_eq!((loaded.shape(), loaded.root()), (TreeShape::LeftBalanced, tree.root()));
+        assert_ne!(loaded.root(), example_tree(7).root());
+    }
+
+    #[test]
+    fn test_tagged_tree_round_trip() {
+        let tree = example_tree(5);
+        let config = TreeConfig::default();
+        let mut bytes = Vec::new();
+        config.save_cbor(&tree, &mut bytes).unwrap();
+        assert_eq!(config.load_cbor(bytes.as_slice()).unwrap().root(), tree.root());
+        assert_eq!(MerkleTree::load_cbor(bytes.as_slice()).unwrap().root(), tree.root());
+
+        let pre_hashed = TreeConfig::new(LeafMode::PreHashed);
+        let err = pre_hashed.load_cbor(bytes.as_slice()).err().unwrap();
+        let expected = "hasher mismatch in CBOR tree: expected `sha256/pre-hashed`, found `sha256/hash-data`";
+        assert_eq!(err.to_string(), expected);
+        let mut untagged = Vec::new();
+        tree.save_cbor(&mut untagged).unwrap();
+        assert!(matches!(
+            config.load_cbor(untagged.as_slice()),
+            Err(LibError::HasherMismatch { found, .. }) if found == "sha256/unrecorded"
+        ));
+
+        let sha512 = TreeConfig::default().with_hasher(HashAlgorithm::Sha512);
+        assert!(matches!(sha512.save_cbor(&tree, Vec::new()), Err(LibError::HasherMismatch { artifact: "tree", .. })));
+    }
+
+    #[test]
+    fn test_descriptor_round_trip() {
+        let data: Vec<Vec<u8>> = (0..7).map(|i| vec![i as u8]).collect();
+        for config in [
+            TreeConfig::default(),
+            TreeConfig::new(LeafMode::PreHashed).with_hasher(HashAlgorithm::Sha512),
+            TreeConfig::default().with_shape(TreeShape::LeftBalanced),
+        ] {
+            let input = match config.leaf_mode() {
+                LeafMode::PreHashed => data.iter().map(|d| config.hasher().leaf_hash(d)).collect(),
+                _ => data.clone(),
+            };
+            let tree = config.construct(&input).unwrap();
+            let mut bytes = Vec::new();
+            config.save_cbor(&tree, &mut bytes).unwrap();
+            let (loaded, descriptor) = MerkleTree::load_cbor_with_descriptor(bytes.as_slice()).unwrap();
+            assert_eq!(descriptor, config.descriptor(&tree).unwrap());
+            assert_eq!(loaded.descriptor(), tree.descriptor());
+        }
+
+        // without a configuration the leaf mode goes unrecorded, and older trees have no version
+        let tree = example_tree(5);
+        let mut bytes = Vec::new();
+        tree.save_cbor(&mut bytes).unwrap();
+        assert_eq!(MerkleTree::load_cbor_with_descriptor(bytes.as_slice()).unwrap().1, tree.descriptor());
+        let Value::Map(mut entries) = ciborium::from_reader(bytes.as_slice()).unwrap() else { unreachable!() };
+        entries.retain(|(key, _)| key.as_text() != Some("crate_version"));
+        bytes.clear();
+        ciborium::into_writer(&Value::Map(entries), &mut bytes).unwrap();
+        let (_, descriptor) = MerkleTree::load_cbor_with_descriptor(bytes.as_slice()).unwrap();
+        assert_eq!(descriptor, TreeDescriptor { crate_version: None, ..tree.descriptor() });
+    }
+
+    #[test]
+    fn test_rejects_unknown_fields_and_hashers() {
+        let proof = example_tree(4).prove_by_index(1).unwrap().into_owned();
+        let encode = |entries: Vec<(Value, Value)>| {
+            let mut bytes = Vec::new();
+            ciborium::into_writer(&Value::Map(entries), &mut bytes).unwrap();
+            bytes
+        };
+
+        let extra = encode(vec![
+            (text("hasher"), text(HASHER_ID)),
+            (text("hashes"), Value::Array(vec![])),
+            (text("index"), Value::Integer(1.into())),
+        ]);
+        assert!(matches!(OwnedProof::from_cbor(&extra), Err(LibError::InvalidFormat(m)) if m.contains("index")));
+
+        let foreign = encode(vec![
+            (text("hasher"), text("keccak256")),
+            (text("hashes"), Value::Array(vec![])),
+        ]);
+        assert!(matches!(OwnedProof::from_cbor(&foreign), Err(LibError::UnknownHasher(found)) if found == "keccak256"));
+        let Value::Map(mut entries) = ciborium::from_reader(proof.to_cbor().unwrap().as_slice()).unwrap() else {
+            unreachable!()
+        };
+        entries.insert(0, (text("hasher"), text(HashAlgorithm::Sha512.id())));
+        assert!(matches!(
+            OwnedProof::from_cbor(&encode(entries)),
+            Err(LibError::InvalidHashLength { expected: 64, found: 32 })
+        ));
+
+        let mut trailing = proof.to_cbor().unwrap();
+        trailing.push(0);
+        assert!(OwnedProof::from_cbor(&trailing).is_err());
+
+        let mut tree_bytes = Vec::new();
+        example_tree(4).save_cbor(&mut tree_bytes).unwrap();
+        // the last byte of the root, which is followed by `"version": 1`
+        let last = tree_bytes.len() - 10;
+        tree_bytes[last] ^= 1;
+        assert!(matches!(MerkleTree::load_cbor(tree_bytes.as_slice()), Err(LibError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn test_rejects_hostile_input() {
+        let prefix = [&[0xa2, 0x66][..], b"hasher", &[0x66], b"sha256", &[0x66], b"hashes"].concat();
+        let decode = |body: &[u8]| OwnedProof::from_cbor(&[&prefix[..], body].concat()).unwrap_err().to_string();
+
+        // lengths claimed far beyond the input are read into, not allocated up front
+        let huge_array = [0x9b, 0, 0, 0x10, 0, 0, 0, 0, 0, 0x82, 0x00];
+        assert!(decode(&huge_array).contains("UnexpectedEof"), "{}", decode(&huge_array));
+        let huge_hash = [0x81, 0x82, 0x00, 0x5b, 0, 0, 0x10, 0, 0, 0, 0, 0, 1, 2, 3];
+        assert!(decode(&huge_hash).contains("UnexpectedEof"), "{}", decode(&huge_hash));
+        assert!(decode(&[0x81; 1000]).contains("RecursionLimitExceeded"));
+
+        let steps = [&[0x98, 65][..], &[0x82, 0x00, 0x41, 0x00].repeat(65)].concat();
+        assert_eq!(decode(&steps), "a proof has at most 64 steps, found 65");
+        assert!(decode(&[0x81, 0x82, 0x07, 0x41, 0x00]).contains("direction must be 0 (left), 1 (right) or 2"));
+    }
+
+    #[test]
+    fn test_versions() {
+        let tree = example_tree(3);
+        let proof = tree.prove_by_index(1).unwrap().into_owned();
+        let encode = |entries: Vec<(Value, Value)>| {
+            let mut bytes = Vec::new();
+            ciborium::into_writer(&Value::Map(entries), &mut bytes).unwrap();
+            bytes
+        };
+        let hashes = |proof: &OwnedProof| {
+            let Value::Map(entries) = ciborium::from_reader(proof.to_cbor().unwrap().as_slice()).unwrap() else {
+                unreachable!()
+            };
+            entries.into_iter().find(|(key, _)| key.as_text() == Some("hashes")).unwrap().1
+        };
+
+        let unversioned = encode(vec![(text("hasher"), text(HASHER_ID)), (text("hashes"), hashes(&proof))]);
+        let decoded = OwnedProof::from_cbor(&unversioned).unwrap();
+        assert_eq!(decoded, OwnedProof::new(proof.hashes().to_vec()).with_hasher(HashAlgorithm::Sha256));
+        // the lone last leaf is paired with itself, which version 1 cannot express
+        let lone = tree.prove_by_index(2).unwrap().into_owned();
+        let unversioned = encode(vec![(text("hasher"), text(HASHER_ID)), (text("hashes"), hashes(&lone))]);
+        let err = OwnedProof::from_cbor(&unversioned).unwrap_err();
+        assert!(err.to_string().contains("duplicate steps need format version 2"), "{}", err);
+
+        // a future version may add fields, but must fail on its version before those are looked at
+        let future = encode(vec![
+            (text("hasher"), text(HASHER_ID)),
+            (text("hashes"), hashes(&proof)),
+            (text("index"), Value::Integer(2.into())),
+            (text("version"), Value::Integer(3.into())),
+        ]);
+        assert!(matches!(
+            OwnedProof::from_cbor(&future),
+            Err(LibError::UnsupportedVersion { found: 3, supported }) if supported == vec![1, 2]
+        ));
+
+        let leaves = tree.leaves().iter().map(|h| Value::Bytes(h.clone())).collect();
+        let unversioned_tree = encode(vec![
+            (text("hasher"), text(HASHER_ID)),
+            (text("leaves"), Value::Array(leaves)),
+            (text("root"), Value::Bytes(tree.root())),
+        ]);
+        assert_eq!(MerkleTree::load_cbor(unversioned_tree.as_slice()).unwrap().root(), tree.root());
+
+        let negative = encode(vec![(text("version"), Value::Integer((-1).into()))]);
+        assert!(matches!(OwnedProof::from_cbor(&negative), Err(LibError::InvalidFormat(m)) if m.contains("version")));
+    }
+
+    #[test]
+    fn test_root_to_leaf_order() {
+        let tree = example_tree(6);
+        let proof = tree.prove_by_index(3).unwrap().into_owned();
+        let root_first = proof.reversed();
+        let bytes = root_first.to_cbor().unwrap();
+        let Value::Map(entries) = ciborium::from_reader(bytes.as_slice()).unwrap() else { unreachable!() };
+        let keys: Vec<_> = entries.iter().map(|(key, _)| key.as_text().unwrap()).collect();
+        assert_eq!(keys, ["hashes", "order", "tree_size", "version"]);
+        let Some(Value::Array(listed)) = entries.iter().find(|(key, _)| key.as_text() == Some("hashes")).map(|e| &e.1)
+        else {
+            unreachable!()
+        };
+        let first = &proof.hashes()[proof.hashes().len() - 1].1;
+        assert_eq!(listed[0].as_array().unwrap()[1], Value::Bytes(first.to_vec()));
+
+        let decoded = OwnedProof::from_cbor(&bytes).unwrap();
+        assert_eq!(decoded, root_first);
+        assert!(MerkleTree::verify_proof_at_index(&vec![3], &decoded.as_proof(), 3, 6, &tree.root()));
+        // leaf-to-root proofs encode as they did before the order was recorded
+        assert_eq!(OwnedProof::from_cbor(&proof.to_cbor().unwrap()).unwrap().order(), ProofOrder::LeafToRoot);
+        assert_eq!(proof.to_cbor().unwrap().len() + 19, bytes.len());
+    }
+}
@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+
+use super::{hash_data, Data, Hash, LeafMode, MerkleTree, OwnedProof, ProofVersion, TreeConfig};
+
+/// Proof that data is in an inner tree whose root is itself a leaf of an outer tree
+///
+/// Used when the roots of small batch trees are anchored as leaves of a larger tree.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ChainedProofRepr", into = "ChainedProofRepr")]
+pub struct ChainedProof {
+    inner: OwnedProof,
+    inner_root: Hash,
+    outer: OwnedProof,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChainedProofRepr {
+    /// Missing from payloads written before formats were versioned
+    #[serde(default)]
+    version: ProofVersion,
+    inner: OwnedProof,
+    #[serde(with = "crate::util::hex_serde")]
+    inner_root: Hash,
+    outer: OwnedProof,
+}
+
+impl ChainedProof {
+    pub fn new(inner: OwnedProof, inner_root: Hash, outer: OwnedProof) -> Self {
+        ChainedProof {
+            inner,
+            inner_root,
+            outer,
+        }
+    }
+
+    /// Chains the proof of some data in `inner_tree` with the proof of its root in `outer_tree`
+    ///
+    /// Returns `None` if either index is out of range.
+    pub fn from_trees(inner_tree: &MerkleTree, inner_index: usize, outer_tree: &MerkleTree, outer_index: usize) -> Option<Self> {
+        Some(ChainedProof {
+            inner: inner_tree.prove_by_index(inner_index).ok()?.into_owned(),
+            inner_root: inner_tree.root(),
+            outer: outer_tree.prove_by_index(outer_index).ok()?.into_owned(),
+        })
+    }
+
+    /// Proof of the data within the inner tree
+    pub fn inner(&self) -> &OwnedProof {
+        &self.inner
+    }
+
+    pub fn inner_root(&self) -> &Hash {
+        &self.inner_root
+    }
+
+    /// Proof of the inner root within the outer tree
+    pub fn outer(&self) -> &OwnedProof {
+        &self.outer
+    }
+}
+
+impl From<ChainedProofRepr> for ChainedProof {
+    fn from(repr: ChainedProofRepr) -> Self {
+        match repr.version {
+            ProofVersion::V1 | ProofVersion::V2 => ChainedProof::new(repr.inner, repr.inner_root, repr.outer),
+        }
+    }
+}
+
+impl From<ChainedProof> for ChainedProofRepr {
+    fn from(proof: ChainedProof) -> Self {
+        ChainedProofRepr {
+            version: ProofVersion::CURRENT,
+            inner: proof.inner,
+            inner_root: proof.inner_root,
+            outer: proof.outer,
+        }
+    }
+}
+
+impl MerkleTree {
+    /// Verifies a chained proof whose inner root was hashed as raw data to become an outer leaf
+    ///
+    /// Use `TreeConfig::verify_chained` if the outer tree was built from pre-hashed leaves.
+    pub fn verify_chained(data: &Data, chained: &ChainedProof, outer_root: &Hash) -> bool {
+        TreeConfig::default().verify_chained(data, chained, outer_root)
+    }
+}
+
+impl TreeConfig {
+    /// Verifies the data against the inner root, then the inner root against the outer root
+    ///
+    /// The data is always hashed as raw data; the leaf mode says how the inner root became a leaf
+    /// of the outer tree.
+    pub fn verify_chained(&self, data: &Data, chained: &ChainedProof, outer_root: &Hash) -> bool {
+        let inner_leaf = match self.leaf_mode() {
+            LeafMode::HashData => hash_data(&chained.inner_root),
+            LeafMode::PreHashed => chained.inner_root.clone(),
+        };
+        MerkleTree::verify_proof(data, &chained.inner.as_proof(), &chained.inner_root)
+            && MerkleTree::verify_proof_hash(&inner_leaf, &chained.outer.as_proof(), outer_root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::HashDirection;
+
+    fn batch(tag: u8, n: u8) -> Vec<Data> {
+        (0..n).map(|i| vec![tag, i]).collect()
+    }
+
+    #[test]
+    fn test_chained_in_both_leaf_modes() {
+        let batches: Vec<MerkleTree> = (0..3).map(|tag| MerkleTree::construct(&batch(tag, 5))).collect();
+        let roots: Vec<Data> = batches.iter().map(MerkleTree::root).collect();
+
+        for mode in [LeafMode::HashData, LeafMode::PreHashed] {
+            let config = TreeConfig::new(mode);
+            let day = config.construct(&roots).unwrap();
+            let chained = ChainedProof::from_trees(&batches[2], 4, &day, 2).unwrap();
+
+            assert!(config.verify_chained(&vec![2, 4], &chained, &day.root()));
+            assert!(!config.verify_chained(&vec![2, 3], &chained, &day.root()));
+            let other = if mode == LeafMode::HashData { LeafMode::PreHashed } else { LeafMode::HashData };
+            assert!(!TreeConfig::new(other).verify_chained(&vec![2, 4], &chained, &day.root()));
+        }
+
+        let day = MerkleTree::construct(&roots);
+        let chained = ChainedProof::from_trees(&batches[0], 1, &day, 0).unwrap();
+        assert!(MerkleTree::verify_chained(&vec![0, 1], &chained, &day.root()));
+        assert!(ChainedProof::from_trees(&batches[0], 5, &day, 0).is_none());
+        assert!(ChainedProof::from_trees(&batches[0], 0, &day, 3).is_none());
+    }
+
+    #[test]
+    fn test_swapped_inner_root_fails() {
+        let batches: Vec<MerkleTree> = (0..2).map(|tag| MerkleTree::construct(&batch(tag, 4))).collect();
+        let day = MerkleTree::construct(&batches.iter().map(MerkleTree::root).collect::<Vec<_>>());
+
+        // the data really is in batch 0, but the outer proof is for batch 1's root
+        let mut chained = ChainedProof::from_trees(&batches[0], 2, &day, 0).unwrap();
+        chained.outer = day.prove_by_index(1).unwrap().into_owned();
+        assert!(!MerkleTree::verify_chained(&vec![0, 2], &chained, &day.root()));
+
+        // claiming batch 1's root breaks the inner proof instead
+        let mut chained = ChainedProof::from_trees(&batches[0], 2, &day, 0).unwrap();
+        chained.inner_root = batches[1].root();
+        chained.outer = day.prove_by_index(1).unwrap().into_owned();
+        assert!(!MerkleTree::verify_chained(&vec![0, 2], &chained, &day.root()));
+    }
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let inner = MerkleTree::construct(&batch(7, 3));
+        let outer = TreeConfig::new(LeafMode::PreHashed).construct(&[inner.root(), vec![0; 32]]).unwrap();
+        let chained = ChainedProof::from_trees(&inner, 2, &outer, 0).unwrap();
+
+        let json = serde_json::to_string(&chained).unwrap();
+        let loaded: ChainedProof = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, chained);
+        assert!(TreeConfig::new(LeafMode::PreHashed).verify_chained(&vec![7, 2], &loaded, &outer.root()));
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["inner_root"], hex::encode(inner.root()));
+        assert_eq!((value["version"].as_u64(), value["outer"]["version"].as_u64()), (Some(2), Some(2)));
+        assert_eq!(value["outer"]["steps"][0]["direction"], "right");
+    }
+
+    #[test]
+    fn test_versions() {
+        let inner = MerkleTree::construct(&batch(7, 3));
+        let outer = MerkleTree::construct(&[inner.root(), vec![0; 32]]);
+        let chained = ChainedProof::from_trees(&inner, 2, &outer, 0).unwrap();
+        let mut value = serde_json::to_value(&chained).unwrap();
+
+        // written before formats were versioned: no version anywhere, bare step lists and lone
+        // nodes paired with themselves marked right
+        let mut legacy = value.clone();
+        legacy.as_object_mut().unwrap().remove("version");
+        for proof in ["inner", "outer"] {
+            legacy[proof] = legacy[proof]["steps"].take();
+            for step in legacy[proof].as_array_mut().unwrap() {
+                if step["direction"] == "duplicate" {
+                    step["direction"] = "right".into();
+                }
+            }
+        }
+        let untagged = |proof: &OwnedProof| {
+            let steps = proof.hashes().iter().map(|(direction, hash)| match direction {
+                HashDirection::Duplicate => (HashDirection::Right, *hash),
+                _ => (*direction, *hash),
+            });
+            OwnedProof::new(steps.collect())
+        };
+        let expected = ChainedProof::new(untagged(chained.inner()), inner.root(), untagged(chained.outer()));
+        assert_eq!(serde_json::from_value::<ChainedProof>(legacy).unwrap(), expected);
+
+        value["version"] = 3.into();
+        value["anchor"] = "block 812".into();
+        let err = serde_json::from_value::<ChainedProof>(value).unwrap_err().to_string();
+        assert!(err.contains("unsupported format version 3"), "{}", err);
+    }
+}
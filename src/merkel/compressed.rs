@@ -0,0 +1,391 @@
+use super::{
+    check_hash_widths, check_step_count, Data, Hash, HashAlgorithm, HashDirection, LeafMode, MerkleHasher, OwnedProof,
+    ProofVersion, Sha256Hasher, TreeConfig,
+};
+use crate::util::error::LibError;
+
+/// Tag bit set when the sibling is on the left
+const LEFT: u8 = 0x80;
+/// Tag of a sibling written out in full; any lower tag refers to a default level
+const EXPLICIT: u8 = 0x7f;
+
+/// Roots of subtrees in which every leaf holds the same default value, by level (0 = the leaf)
+///
+/// In a tree where most leaves hold a known constant, most siblings on any proof path are such
+/// default subtrees, which `OwnedProof::to_bytes_compressed` writes as one-byte references.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultHashes {
+    levels: Vec<Hash>,
+}
+
+impl DefaultHashes {
+    /// Precomputes the default subtree roots of levels 0 to `depth`
+    pub fn for_leaf(value: &Data, depth: usize) -> Self {
+        DefaultHashes::for_leaf_with(&Sha256Hasher, value, depth)
+    }
+
+    /// Like `for_leaf`, for trees built with another hasher
+    pub fn for_leaf_with<H: MerkleHasher + ?Sized>(hasher: &H, value: &Data, depth: usize) -> Self {
+        let mut levels = vec![hasher.leaf_hash(value)];
+        for level in 0..depth {
+            levels.push(hasher.node_hash(&levels[level], &levels[level]));
+        }
+        DefaultHashes { levels }
+    }
+
+    /// Length of every hash, and so of the siblings written out in full
+    pub fn hash_len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Root of a default subtree with `2^level` leaves
+    pub fn level(&self, level: usize) -> Option<&Hash> {
+        self.levels.get(level)
+    }
+
+    /// Number of levels, one more than the depth
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+}
+
+impl OwnedProof {
+    /// Encodes the proof with every sibling that is a default subtree root replaced by its level
+    ///
+    /// The tree size a proof may be tagged with is not encoded.
+    ///
+    /// The bytes are the format version, the number of steps, a bitmap of the
+    /// `HashDirection::Duplicate` steps with the first step in the lowest bit, and then one tag per
+    /// step: its high bit set for a left sibling, and below it either the default level or `0x7f`
+    /// followed by the full hash. Version 1 has no bitmap. A proof tagged with its hasher or leaf
+    /// mode ends with both ids, each as a length byte and its text, empty where it is untagged;
+    /// see `TreeConfig::tag_proof`. Only the first 127 levels can be referenced. Fails with `LibError::ProofTooDeep` for a proof of more than `MAX_PROOF_STEPS`
+    /// steps and `LibError::InvalidFormat` for a sibling that is not as long as the default hashes.
+    pub fn to_bytes_compressed(&self, defaults: &DefaultHashes) -> Result<Vec<u8>, LibError> {
+        check_step_count(self.hashes().len())?;
+        let steps = self.hashes().len() as u8;
+        let mut bytes = vec![ProofVersion::CURRENT.number() as u8, steps];
+        let mut duplicates = vec![0u8; usize::from(steps).div_ceil(8)];
+        for (step, (direction, _)) in self.hashes().iter().enumerate() {
+            if *direction == HashDirection::Duplicate {
+                duplicates[step / 8] |= 1 << (step % 8);
+            }
+        }
+        bytes.extend(duplicates);
+        for (step, (direction, hash)) in self.hashes().iter().enumerate() {
+            let side = if direction.to_bit() { LEFT } else { 0 };
+            match defaults.levels.iter().take(EXPLICIT as usize).position(|default| default == hash) {
+                Some(level) => bytes.push(side | level as u8),
+                None => {
+                    let len = defaults.hash_len();
+                    if hash.len() != len {
+                        let message = format!("step {} is {} bytes long, expected {}", step, hash.len(), len);
+                        return Err(LibError::InvalidFormat(message));
+                    }
+                    bytes.push(side | EXPLICIT);
+                    bytes.extend_from_slice(hash);
+                }
+            }
+        }
+        if self.hasher().is_some() || self.leaf_mode().is_some() {
+            for id in [self.hasher().map(|hasher| hasher.id()), self.leaf_mode().map(LeafMode::id)] {
+                let id = id.unwrap_or_default();
+                bytes.push(id.len() as u8);
+                bytes.extend_from_slice(id.as_bytes());
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a proof written by `to_bytes_compressed` with the same default hashes
+    ///
+    /// Other defaults are not detected here, but give a proof that no longer verifies; a recorded
+    /// hasher must be one `HashAlgorithm::from_id` knows, of the width of the defaults. Hostile input
+    /// fails with `LibError::ProofTooDeep` for more steps than `MAX_PROOF_STEPS`, and otherwise
+    /// with `LibError::InvalidFormat`: more steps than there are bytes left, duplicate bits past the
+    /// last step, and truncated hashes.
+    pub fn from_bytes_compressed(bytes: &[u8], defaults: &DefaultHashes) -> Result<OwnedProof, LibError> {
+        let invalid = |message: &str| LibError::InvalidFormat(format!("compressed proof: {}", message));
+        let [version, steps, steps_bytes @ ..] = bytes else {
+            return Err(invalid("missing header"));
+        };
+        let steps = usize::from(*steps);
+        check_step_count(steps)?;
+        let mut rest = steps_bytes;
+        let version = ProofVersion::from_number(u64::from(*version))?;
+        let duplicates = if version.has_duplicate_steps() {
+            let (duplicates, tail) = rest.split_at_checked(steps.div_ceil(8)).ok_or_else(|| invalid("truncated"))?;
+            rest = tail;
+            if steps % 8 != 0 && duplicates.last().is_some_and(|last| last >> (steps % 8) != 0) {
+                return Err(invalid("duplicate bits set past the last step"));
+            }
+            duplicates
+        } else {
+            &[]
+        };
+
+        // every step takes at least its tag byte
+        if rest.len() < steps {
+            return Err(invalid("truncated"));
+        }
+        let mut hashes = Vec::with_capacity(steps);
+        for step in 0..steps {
+            let [tag, tail @ ..] = rest else {
+                return Err(invalid("truncated"));
+            };
+            let duplicate = duplicates.get(step / 8).is_some_and(|bits| bits & (1 << (step % 8)) != 0);
+            let direction = match (HashDirection::from_bit(tag & LEFT != 0), duplicate) {
+                (HashDirection::Left, true) => {
+                    return Err(invalid(&format!("step {} is a duplicate on the left", step + 1)))
+                }
+                (_, true) => HashDirection::Duplicate,
+                (direction, false) => direction,
+            };
+            let hash = match tag & !LEFT {
+                EXPLICIT => {
+                    let (hash, tail) = tail.split_at_checked(defaults.hash_len()).ok_or_else(|| invalid("truncated"))?;
+                    rest = tail;
+                    hash.to_vec()
+                }
+                level => {
+                    rest = tail;
+                    let level = usize::from(level);
+                    defaults
+                        .level(level)
+                        .ok_or_else(|| invalid(&format!("no default hash for level {}", level)))?
+                        .clone()
+                }
+            };
+            hashes.push((direction, hash));
+        }
+
+        let mut tags = [None, None];
+        if !rest.is_empty() {
+            for tag in &mut tags {
+                let [len, tail @ ..] = rest else {
+                    return Err(invalid("truncated tags"));
+                };
+                let (id, tail) = tail.split_at_checked(usize::from(*len)).ok_or_else(|| invalid("truncated tags"))?;
+                rest = tail;
+                let id = std::str::from_utf8(id).map_err(|_| invalid("tags must be UTF-8"))?;
+                *tag = Some(id).filter(|id| !id.is_empty());
+            }
+        }
+        if !rest.is_empty() {
+            return Err(invalid(&format!("{} trailing bytes", rest.len())));
+        }
+        let hasher = match tags[0] {
+            Some(id) => Some(HashAlgorithm::from_id(id).ok_or_else(|| LibError::UnknownHasher(id.to_string()))?),
+            None => None,
+        };
+        let leaf_mode = match tags[1] {
+            Some(id) => Some(LeafMode::from_id(id).ok_or_else(|| invalid(&format!("unknown leaf mode `{}`", id)))?),
+            None => None,
+        };
+
+        let mut proof = match hasher {
+            Some(hasher) => {
+                check_hash_widths(hasher, hashes.iter().map(|(_, hash)| hash))?;
+                OwnedProof::new(hashes).with_hasher(hasher)
+            }
+            None => OwnedProof::new(hashes),
+        };
+        if let Some(leaf_mode) = leaf_mode {
+            proof = proof.with_leaf_mode(leaf_mode);
+        }
+        Ok(proof)
+    }
+}
+
+impl TreeConfig {
+    /// Like `OwnedProof::from_bytes_compressed`, also failing with `LibError::HasherMismatch` unless
+    /// the proof is tagged with the hasher and leaf mode of this configuration
+    pub fn load_proof_compressed(&self, bytes: &[u8], defaults: &DefaultHashes) -> Result<OwnedProof, LibError> {
+        let proof = OwnedProof::from_bytes_compressed(bytes, defaults)?;
+        self.check_proof("compressed proof", &proof)?;
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{MerkleTree, HASH_LEN};
+
+    const EMPTY_ACCOUNT: &[u8] = b"empty account";
+
+    /// 64 leaves, all holding the default value except leaves 5 and 40
+    fn mostly_default() -> Vec<Data> {
+        (0..64)
+            .map(|i| match i {
+                5 | 40 => format!("account {}", i).into_bytes(),
+                _ => EMPTY_ACCOUNT.to_vec(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_default_levels() {
+        let defaults = DefaultHashes::for_leaf(&EMPTY_ACCOUNT.to_vec(), 6);
+        assert_eq!(defaults.len(), 7);
+        let all_default = MerkleTree::construct(&vec![EMPTY_ACCOUNT.to_vec(); 64]);
+        assert_eq!(defaults.level(6), Some(&all_default.root()));
+        assert_eq!(defaults.level(0), Some(&all_default.leaves()[0]));
+        assert_eq!(defaults.level(7), None);
+    }
+
+    #[test]
+    fn test_round_trip_shrinks_proofs() {
+        let data = mostly_default();
+        let tree = MerkleTree::construct(&data);
+        let defaults = DefaultHashes::for_leaf(&EMPTY_ACCOUNT.to_vec(), tree.depth());
+
+        for index in [0, 5, 17, 40, 63] {
+            let proof = tree.prove_by_index(index).unwrap().into_owned();
+            let bytes = proof.to_bytes_compressed(&defaults).unwrap();
+            let decoded = OwnedProof::from_bytes_compressed(&bytes, &defaults).unwrap();
+            assert_eq!(decoded.hashes(), proof.hashes());
+            assert!(MerkleTree::verify_proof(&data[index], &decoded.as_proof(), &tree.root()));
+        }
+
+        // leaf 5 has only one non-default sibling, the half of the tree holding leaf 40, so
+        // instead of 6 full steps only one is written out
+        let proof = tree.prove_by_index(5).unwrap().into_owned();
+        assert_eq!(proof.hashes().len(), 6);
+        assert_eq!(proof.to_bytes_compressed(&defaults).unwrap().len(), 3 + 6 + HASH_LEN);
+        let foreign = DefaultHashes::for_leaf(&b"unused".to_vec(), tree.depth());
+        assert_eq!(proof.to_bytes_compressed(&foreign).unwrap().len(), 3 + 6 * (1 + HASH_LEN));
+
+        let bytes = proof.to_bytes_compressed(&defaults).unwrap();
+        let decoded = OwnedProof::from_bytes_compressed(&bytes, &foreign).unwrap();
+        assert!(!MerkleTree::verify_proof(&data[5], &decoded.as_proof(), &tree.root()));
+    }
+
+    #[test]
+    fn test_round_trip_with_other_widths() {
+        let data = mostly_default();
+        for hasher in [HashAlgorithm::Sha512, HashAlgorithm::Ripemd160] {
+            let tree = TreeConfig::default().with_hasher(hasher).construct(&data).unwrap();
+            let defaults = DefaultHashes::for_leaf_with(&hasher, &EMPTY_ACCOUNT.to_vec(), tree.depth());
+            assert_eq!(defaults.hash_len(), hasher.output_len());
+
+            let proof = tree.prove_by_index(5).unwrap().into_owned();
+            let bytes = proof.to_bytes_compressed(&defaults).unwrap();
+            assert_eq!(bytes.len(), 3 + 6 + hasher.output_len());
+            let decoded = OwnedProof::from_bytes_compressed(&bytes, &defaults).unwrap();
+            let root = decoded.as_proof().root_from_with(&hasher, &hasher.leaf_hash(&data[5]));
+            assert_eq!(root, tree.root());
+
+            // a tagged proof ends with its hasher and leaf mode, which must fit the defaults
+            let tagged = TreeConfig::default().with_hasher(hasher).tag_proof(proof.clone());
+            let bytes = tagged.to_bytes_compressed(&defaults).unwrap();
+            assert_eq!(bytes[bytes.len() - 10..], *b"\x09hash-data");
+            let decoded = OwnedProof::from_bytes_compressed(&bytes, &defaults).unwrap();
+            assert_eq!((decoded.hasher(), decoded.leaf_mode()), (Some(hasher), Some(LeafMode::HashData)));
+            assert_eq!(decoded.hashes(), tagged.hashes());
+            let mislabeled = proof.with_hasher(HashAlgorithm::Sha256).to_bytes_compressed(&defaults).unwrap();
+            assert!(matches!(
+                OwnedProof::from_bytes_compressed(&mislabeled, &defaults),
+                Err(LibError::InvalidHashLength { expected: 32, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_duplicate_steps_round_trip() {
+        let data: Vec<Data> = (0..5).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let defaults = DefaultHashes::for_leaf(&vec![0], tree.depth());
+        let proof = tree.prove_by_index(4).unwrap().into_owned();
+        let bytes = proof.to_bytes_compressed(&defaults).unwrap();
+        assert_eq!(bytes[..3], [2, 3, 0b011]);
+        assert_eq!(OwnedProof::from_bytes_compressed(&bytes, &defaults).unwrap().hashes(), proof.hashes());
+
+        // version 1 has no bitmap and writes lone nodes as right steps
+        let legacy = [&[1, 3][..], &bytes[3..]].concat();
+        let decoded = OwnedProof::from_bytes_compressed(&legacy, &defaults).unwrap();
+        assert!(decoded.hashes()[..2].iter().all(|(direction, _)| *direction == HashDirection::Right));
+        assert!(MerkleTree::verify_proof(&data[4], &decoded.as_proof(), &tree.root()));
+
+        let mut left = bytes.clone();
+        left[2] = 0b100;
+        let err = OwnedProof::from_bytes_compressed(&left, &defaults).unwrap_err();
+        assert!(err.to_string().contains("step 3 is a duplicate on the left"), "{}", err);
+    }
+
+    #[test]
+    fn test_rejects_malformed_bytes() {
+        let tree = MerkleTree::construct(&mostly_default());
+        let defaults = DefaultHashes::for_leaf(&EMPTY_ACCOUNT.to_vec(), tree.depth());
+        let bytes = tree.prove_by_index(5).unwrap().into_owned().to_bytes_compressed(&defaults).unwrap();
+
+        for truncated in [&bytes[..1], &bytes[..bytes.len() - 1]] {
+            assert!(matches!(OwnedProof::from_bytes_compressed(truncated, &defaults), Err(LibError::InvalidFormat(_))));
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(OwnedProof::from_bytes_compressed(&trailing, &defaults).is_err());
+
+        let shallow = DefaultHashes::for_leaf(&EMPTY_ACCOUNT.to_vec(), 2);
+        let err = OwnedProof::from_bytes_compressed(&bytes, &shallow).unwrap_err();
+        assert!(err.to_string().contains("no default hash for level 3"), "{}", err);
+
+        let mut future = bytes.clone();
+        future[0] = 3;
+        assert!(matches!(
+            OwnedProof::from_bytes_compressed(&future, &defaults),
+            Err(LibError::UnsupportedVersion { found: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_unencodable_proofs_are_errors() {
+        let defaults = DefaultHashes::for_leaf(&EMPTY_ACCOUNT.to_vec(), 4);
+        let encode = |hashes: Vec<(HashDirection, Hash)>| OwnedProof::new(hashes).to_bytes_compressed(&defaults);
+
+        // both used to panic: more steps than a step count byte holds, and a sibling of another width
+        let long = encode(vec![(HashDirection::Right, vec![7; HASH_LEN]); 300]).unwrap_err();
+        assert_eq!(long.to_string(), "a proof has at most 64 steps, found 300");
+        let level = defaults.level(1).unwrap().clone();
+        let short = encode(vec![(HashDirection::Left, level), (HashDirection::Right, vec![7; 20])]);
+        assert_eq!(short.unwrap_err().to_string(), "invalid format: step 1 is 20 bytes long, expected 32");
+    }
+
+    #[test]
+    fn test_rejects_hostile_bytes() {
+        let tree = MerkleTree::construct(&mostly_default());
+        let defaults = DefaultHashes::for_leaf(&EMPTY_ACCOUNT.to_vec(), tree.depth());
+        let decode = |bytes: &[u8]| OwnedProof::from_bytes_compressed(bytes, &defaults).map_err(|e| e.to_string());
+
+        let oversized = [&[2, 255][..], &[0; 10]].concat();
+        assert_eq!(decode(&oversized).unwrap_err(), "a proof has at most 64 steps, found 255");
+        let claimed = [&[2, 64][..], &[0; 10]].concat();
+        assert_eq!(decode(&claimed).unwrap_err(), "invalid format: compressed proof: truncated");
+
+        // a 6-step proof has room for 2 more bits in its bitmap, which must stay clear
+        let mut bytes = tree.prove_by_index(5).unwrap().into_owned().to_bytes_compressed(&defaults).unwrap();
+        bytes[2] |= 0x40;
+        let err = decode(&bytes).unwrap_err();
+        assert_eq!(err, "invalid format: compressed proof: duplicate bits set past the last step");
+
+        // garbage never panics, and whatever decodes encodes back to the same bytes
+        bytes[2] &= !0x40;
+        for _ in 0..10_000 {
+            let mut garbage = bytes.clone();
+            for _ in 0..rand::random_range(1..4) {
+                let at = rand::random_range(0..garbage.len());
+                garbage[at] = rand::random();
+            }
+            garbage.truncate(rand::random_range(0..=garbage.len()));
+            if let Ok(proof) = OwnedProof::from_bytes_compressed(&garbage, &defaults) {
+                if garbage[0] == 2 {
+                    assert_eq!(proof.to_bytes_compressed(&defaults).unwrap(), garbage);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,488 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    canonical_order, CanonicalOrdering, Data, Hash, HashAlgorithm, MerkleHasher, MerkleTree, OwnedProof, Proof,
+    MAX_PROOF_STEPS,
+};
+use crate::util::error::LibError;
+
+/// How input entries become leaf hashes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum LeafMode {
+    /// Entries are raw data and are hashed to produce the leaves (`MerkleTree::construct`)
+    #[default]
+    HashData,
+    /// Entries already are the leaf hashes (`MerkleTree::from_leaf_hashes`)
+    PreHashed,
+}
+
+/// What happens to the lone last node of an odd-sized level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum OddLeafPolicy {
+    /// The node is paired with itself (Bitcoin style)
+    #[default]
+    Duplicate,
+    /// The node is carried up to the next level unchanged, so its proof skips that level
+    Promote,
+}
+
+/// Whether an empty entry may be hashed as a leaf in `LeafMode::HashData`
+///
+/// Leaves are arbitrary bytes, not text: any byte, `0x00` included, and sequences that are not
+/// UTF-8 are hashed as they are. An empty entry is a leaf like any other, but in line-based input
+/// it usually is a stray blank line, so builders that read lines can refuse it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum EmptyLeafPolicy {
+    /// The empty entry is hashed as any other, to the hash of no bytes
+    #[default]
+    Allow,
+    /// `TreeConfig::construct` fails with `LibError::EmptyLeaf`
+    Reject,
+}
+
+/// How the leaves are split into subtrees, see `TreeConfig::with_shape`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum TreeShape {
+    /// Nodes are paired level by level and the lone last node of an odd level with itself, which
+    /// is `OddLeafPolicy::Duplicate`
+    #[default]
+    Paired,
+    /// The first `k` leaves form the left subtree, for `k` the largest power of two below the leaf
+    /// count, and both sides are split the same way, as in RFC 6962 and Tendermint
+    ///
+    /// Carrying the lone last node of every odd level up unchanged puts each node where this
+    /// recursion does, so this is the tree `OddLeafPolicy::Promote` builds.
+    LeftBalanced,
+}
+
+impl TreeShape {
+    pub(crate) fn of(policy: OddLeafPolicy) -> TreeShape {
+        match policy {
+            OddLeafPolicy::Duplicate => TreeShape::Paired,
+            OddLeafPolicy::Promote => TreeShape::LeftBalanced,
+        }
+    }
+
+    pub(crate) fn odd_leaf_policy(self) -> OddLeafPolicy {
+        match self {
+            TreeShape::Paired => OddLeafPolicy::Duplicate,
+            TreeShape::LeftBalanced => OddLeafPolicy::Promote,
+        }
+    }
+}
+
+impl LeafMode {
+    /// Name of the mode as recorded in saved artifacts, the same as its serde name
+    pub fn id(self) -> &'static str {
+        match self {
+            LeafMode::HashData => "hash-data",
+            LeafMode::PreHashed => "pre-hashed",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<LeafMode> {
+        [LeafMode::HashData, LeafMode::PreHashed].into_iter().find(|mode| mode.id() == id)
+    }
+
+    fn other(self) -> LeafMode {
+        match self {
+            LeafMode::HashData => LeafMode::PreHashed,
+            LeafMode::PreHashed => LeafMode::HashData,
+        }
+    }
+}
+
+/// Options describing how a tree is built from its input and how its proofs are verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TreeConfig {
+    leaf_mode: LeafMode,
+    odd_leaf_policy: OddLeafPolicy,
+    hasher: HashAlgorithm,
+    empty_leaf_policy: EmptyLeafPolicy,
+    canonical_ordering: Option<CanonicalOrdering>,
+    /// Most steps `verify_proof` accepts, at most `MAX_PROOF_STEPS`
+    max_proof_depth: usize,
+}
+
+/// Outcome of `TreeConfig::verify`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Verification {
+    /// Whether the input produces the expected root under the configured mode
+    pub matches: bool,
+    pub warnings: Vec<VerifyWarning>,
+}
+
+/// Hints that the input was probably verified under the wrong leaf mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VerifyWarning {
+    /// Every entry is exactly one hash long, yet entries are being hashed as raw data
+    LooksPreHashed,
+    /// The root does not match under the configured mode but does under this one
+    MatchesInOtherMode(LeafMode),
+}
+
+impl EmptyLeafPolicy {
+    /// Fails with `LibError::EmptyLeaf` for the first empty entry of `input` under `Reject`
+    pub fn check(self, input: &[Data]) -> Result<(), LibError> {
+        match input.iter().position(Vec::is_empty) {
+            Some(index) if self == EmptyLeafPolicy::Reject => Err(LibError::EmptyLeaf { index }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl TreeConfig {
+    pub fn new(leaf_mode: LeafMode) -> Self {
+        TreeConfig {
+            leaf_mode,
+            odd_leaf_policy: OddLeafPolicy::default(),
+            hasher: HashAlgorithm::default(),
+            empty_leaf_policy: EmptyLeafPolicy::default(),
+            canonical_ordering: None,
+            max_proof_depth: MAX_PROOF_STEPS,
+        }
+    }
+
+    pub fn with_odd_leaf_policy(mut self, policy: OddLeafPolicy) -> Self {
+        self.odd_leaf_policy = policy;
+        self
+    }
+
+    /// Builds trees of another shape, replacing the odd leaf policy it stands for
+    ///
+    /// The Tendermint and RFC 6962 trees are `TreeShape::LeftBalanced` with
+    /// `HashAlgorithm::Sha256Rfc6962`.
+    pub fn with_shape(mut self, shape: TreeShape) -> Self {
+        self.odd_leaf_policy = shape.odd_leaf_policy();
+        self
+    }
+
+    /// Builds trees with another hasher; pre-hashed leaves must then be of its output length
+    pub fn with_hasher(mut self, hasher: HashAlgorithm) -> Self {
+        self.hasher = hasher;
+        self
+    }
+
+    /// Builds trees that allow or reject empty entries; pre-hashed leaves are never empty
+    pub fn with_empty_leaf_policy(mut self, policy: EmptyLeafPolicy) -> Self {
+        self.empty_leaf_policy = policy;
+        self
+    }
+
+    /// Builds trees over the entries sorted into `ordering` rather than in the order given
+    ///
+    /// See `construct_canonical` for where each entry ends up.
+    pub fn with_canonical_ordering(mut self, ordering: CanonicalOrdering) -> Self {
+        self.canonical_ordering = Some(ordering);
+        self
+    }
+
+    /// Verifies proofs of at most `depth` steps, such as the depth of the one tree expected
+    ///
+    /// Decoded proofs never have more than `MAX_PROOF_STEPS` steps, so a larger depth is lowered
+    /// to it.
+    pub fn with_max_proof_depth(mut self, depth: usize) -> Self {
+        self.max_proof_depth = depth.min(MAX_PROOF_STEPS);
+        self
+    }
+
+    pub fn leaf_mode(&self) -> LeafMode {
+        self.leaf_mode
+    }
+
+    pub fn odd_leaf_policy(&self) -> OddLeafPolicy {
+        self.odd_leaf_policy
+    }
+
+    pub fn hasher(&self) -> HashAlgorithm {
+        self.hasher
+    }
+
+    pub fn empty_leaf_policy(&self) -> EmptyLeafPolicy {
+        self.empty_leaf_policy
+    }
+
+    pub fn shape(&self) -> TreeShape {
+        TreeShape::of(self.odd_leaf_policy)
+    }
+
+    pub fn canonical_ordering(&self) -> Option<CanonicalOrdering> {
+        self.canonical_ordering
+    }
+
+    pub fn max_proof_depth(&self) -> usize {
+        self.max_proof_depth
+    }
+
+    /// Builds a tree from the input according to the leaf mode
+    ///
+    /// Entries are bytes, hashed as they are whether or not they are text; see `EmptyLeafPolicy`.
+    pub fn construct(&self, input: &[Data]) -> Result<MerkleTree, LibError> {
+        Ok(MerkleTree::build(self.leaf_hashes(input)?, self.odd_leaf_policy, self.hasher))
+    }
+
+    /// Verifies that the input produces the given root hash under the configured leaf mode
+    ///
+    /// Unlike `MerkleTree::verify_ordered`, pre-hashed input is not hashed again. In pre-hashed mode an
+    /// entry that is not exactly one hash long is an error; in raw-data mode suspicious input is
+    /// reported through the returned warnings instead.
+    pub fn verify(&self, input: &[Data], root_hash: &Hash) -> Result<Verification, LibError> {
+        let matches = &self.construct(input)?.root() == root_hash;
+
+        let mut warnings = vec![];
+        if self.leaf_mode == LeafMode::HashData && input.iter().all(|d| d.len() == self.hasher.output_len()) {
+            warnings.push(VerifyWarning::LooksPreHashed);
+        }
+        if !matches {
+            let other = TreeConfig { leaf_mode: self.leaf_mode.other(), ..*self };
+            if other.construct(input).is_ok_and(|tree| &tree.root() == root_hash) {
+                warnings.push(VerifyWarning::MatchesInOtherMode(other.leaf_mode));
+            }
+        }
+        Ok(Verification { matches, warnings })
+    }
+
+    /// Whether `proof` leads from the entry to `root_hash` under the leaf mode and hasher
+    ///
+    /// A proof of more steps than the maximum proof depth fails with `LibError::ProofTooDeep`
+    /// before anything is hashed, rather than verifying as false.
+    pub fn verify_proof(&self, entry: &Data, proof: &Proof, root_hash: &Hash) -> Result<bool, LibError> {
+        let steps = proof.steps().len();
+        if steps > self.max_proof_depth {
+            return Err(LibError::ProofTooDeep { max: self.max_proof_depth, got: steps });
+        }
+        let leaf_hash = match self.leaf_mode {
+            LeafMode::HashData => self.hasher.leaf_hash(entry),
+            LeafMode::PreHashed => entry.clone(),
+        };
+        Ok(MerkleTree::verify_proof_hash_with(&self.hasher, &leaf_hash, proof, root_hash))
+    }
+
+    /// Checks the hasher id and leaf mode recorded in a saved artifact against this configuration
+    ///
+    /// Fails with `LibError::HasherMismatch` when either differs, naming both sides as
+    /// `hasher/leaf-mode`. An artifact written before leaf modes were recorded has `None`, shown
+    /// as `unrecorded`, and matches no configuration.
+    pub fn check_artifact(
+        &self,
+        artifact: &'static str,
+        hasher: &str,
+        leaf_mode: Option<LeafMode>,
+    ) -> Result<(), LibError> {
+        if hasher == self.hasher.id() && leaf_mode == Some(self.leaf_mode) {
+            return Ok(());
+        }
+        Err(LibError::HasherMismatch {
+            artifact,
+            expected: format!("{}/{}", self.hasher.id(), self.leaf_mode.id()),
+            found: format!("{}/{}", hasher, leaf_mode.map_or("unrecorded", LeafMode::id)),
+        })
+    }
+
+    /// Tags a proof with the hasher and leaf mode of this configuration, which every proof format
+    /// then records
+    pub fn tag_proof(&self, proof: OwnedProof) -> OwnedProof {
+        proof.with_hasher(self.hasher).with_leaf_mode(self.leaf_mode)
+    }
+
+    /// `check_artifact` of the hasher and leaf mode a proof is tagged with
+    ///
+    /// An untagged hasher is shown as `unrecorded` too, so only a proof tagged by `tag_proof` under
+    /// this configuration passes.
+    pub fn check_proof(&self, artifact: &'static str, proof: &OwnedProof) -> Result<(), LibError> {
+        self.check_artifact(artifact, proof.hasher().map_or("unrecorded", |hasher| hasher.id()), proof.leaf_mode())
+    }
+
+    /// Reads a proof from its JSON form, failing with `LibError::HasherMismatch` unless it is tagged
+    /// with the hasher and leaf mode of this configuration
+    pub fn load_proof_json(&self, json: &str) -> Result<OwnedProof, LibError> {
+        let proof = serde_json::from_str(json).map_err(|e| LibError::InvalidFormat(format!("proof: {}", e)))?;
+        self.check_proof("JSON proof", &proof)?;
+        Ok(proof)
+    }
+
+    /// The leaf hashes of the entries, in canonical order under a canonical ordering
+    pub(super) fn leaf_hashes(&self, input: &[Data]) -> Result<Vec<Hash>, LibError> {
+        let mut hashes = self.arrival_leaf_hashes(input)?;
+        match self.canonical_ordering {
+            Some(CanonicalOrdering::ByLeafHash) => hashes.sort(),
+            Some(ordering) => {
+                let order = canonical_order(ordering, input, &hashes);
+                hashes = order.into_iter().map(|index| std::mem::take(&mut hashes[index])).collect();
+            }
+            None => {}
+        }
+        Ok(hashes)
+    }
+
+    /// The leaf hashes of the entries in the order given
+    pub(super) fn arrival_leaf_hashes(&self, input: &[Data]) -> Result<Vec<Hash>, LibError> {
+        if input.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        match self.leaf_mode {
+            LeafMode::HashData => {
+                self.empty_leaf_policy.check(input)?;
+                Ok(input.iter().map(|data| self.hasher.leaf_hash(data)).collect())
+            }
+            LeafMode::PreHashed => {
+                let expected = self.hasher.output_len();
+                input
+                    .iter()
+                    .enumerate()
+                    .map(|(index, leaf)| match leaf.len() {
+                        found if found == expected => Ok(leaf.clone()),
+                        found => Err(LibError::InvalidLeafLength { index, expected, found }),
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+impl Default for TreeConfig {
+    fn default() -> Self {
+        TreeConfig::new(LeafMode::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{HashDirection, OwnedProof};
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| vec![i as u8]).collect()
+    }
+
+    #[test]
+    fn test_verify_both_modes() {
+        let data = example_data(5);
+        let tree = MerkleTree::construct(&data);
+        let raw = TreeConfig::new(LeafMode::HashData).verify(&data, &tree.root()).unwrap();
+        assert_eq!(raw, Verification { matches: true, warnings: vec![] });
+
+        let leaves = tree.leaves().to_vec();
+        let pre_hashed = TreeConfig::new(LeafMode::PreHashed);
+        assert_eq!(pre_hashed.construct(&leaves).unwrap().root(), tree.root());
+        let result = pre_hashed.verify(&leaves, &tree.root()).unwrap();
+        assert_eq!(result, Verification { matches: true, warnings: vec![] });
+
+        // the legacy verifier hashes its input again and cannot verify pre-hashed leaves
+        assert!(!MerkleTree::verify_ordered(&leaves, &tree.root()));
+    }
+
+    #[test]
+    fn test_detects_cross_mode_mistakes() {
+        let tree = MerkleTree::construct(&example_data(4));
+        let leaves = tree.leaves().to_vec();
+
+        let result = TreeConfig::default().verify(&leaves, &tree.root()).unwrap();
+        assert!(!result.matches);
+        assert_eq!(
+            result.warnings,
+            vec![VerifyWarning::LooksPreHashed, VerifyWarning::MatchesInOtherMode(LeafMode::PreHashed)]
+        );
+
+        let hashed_twice = MerkleTree::construct(&leaves);
+        let result = TreeConfig::new(LeafMode::PreHashed).verify(&leaves, &hashed_twice.root()).unwrap();
+        assert_eq!(result.warnings, vec![VerifyWarning::MatchesInOtherMode(LeafMode::HashData)]);
+
+        let err = TreeConfig::new(LeafMode::PreHashed).verify(&example_data(4), &tree.root());
+        assert!(matches!(
+            err,
+            Err(LibError::InvalidLeafLength { index: 0, expected: 32, found: 1 })
+        ));
+        assert!(matches!(TreeConfig::default().verify(&[], &tree.root()), Err(LibError::EmptyInput)));
+    }
+
+    #[test]
+    fn test_binary_leaves() {
+        // random bytes, not UTF-8, and runs of zeros that a C string would cut at the first byte
+        let random = |_| (0..rand::random_range(8..100)).map(|_| rand::random()).collect();
+        let mut leaves: Vec<Data> = (0..200).map(random).collect();
+        leaves.extend((1..=64).map(|len| vec![0; len]));
+        leaves.extend((1..=32).map(|len| (0..len).map(|i| if i % 5 == 0 { 0xff } else { 0 }).collect()));
+        let tree = TreeConfig::new(LeafMode::HashData).construct(&leaves).unwrap();
+        assert_eq!(tree.root(), MerkleTree::construct(&leaves).root());
+        assert_eq!(tree.leaves()[200], HashAlgorithm::Sha256.leaf_hash(&[0]));
+        assert_ne!(tree.leaves()[200], tree.leaves()[201]);
+        for (data, result) in leaves.iter().zip(tree.prove_many(&leaves)) {
+            assert!(MerkleTree::verify_proof(data, &result.unwrap().1.as_proof(), &tree.root()));
+        }
+    }
+
+    #[test]
+    fn test_empty_leaf_policy() {
+        let data: Vec<Data> = [&b"a"[..], b"", b"b"].map(<[u8]>::to_vec).to_vec();
+        // computed independently with Python's `hashlib`, the empty leaf being `sha256(b"")`
+        let tree = TreeConfig::default().construct(&data).unwrap();
+        assert_eq!(hex::encode(tree.root()), "4adb534d72aca56fd1858c139d653b1b857d2e4db52ce1d5062f26d02b91ea2f");
+        assert_eq!(hex::encode(&tree.leaves()[1]), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(TreeConfig::default().empty_leaf_policy(), EmptyLeafPolicy::Allow);
+
+        let reject = TreeConfig::default().with_empty_leaf_policy(EmptyLeafPolicy::Reject);
+        assert!(matches!(reject.construct(&data), Err(LibError::EmptyLeaf { index: 1 })));
+        assert_eq!(reject.construct(&data[..1]).unwrap().root(), MerkleTree::construct(&data[..1]).root());
+        // an empty pre-hashed leaf is the wrong length whatever the policy
+        let pre_hashed = TreeConfig::new(LeafMode::PreHashed).with_empty_leaf_policy(EmptyLeafPolicy::Reject);
+        assert!(matches!(pre_hashed.construct(&[vec![]]), Err(LibError::InvalidLeafLength { found: 0, .. })));
+        assert_eq!(serde_json::to_value(EmptyLeafPolicy::Reject).unwrap(), "reject");
+    }
+
+    #[test]
+    fn test_check_artifact() {
+        let config = TreeConfig::new(LeafMode::PreHashed).with_hasher(HashAlgorithm::Sha512);
+        assert!(config.check_artifact("tree", "sha512", Some(LeafMode::PreHashed)).is_ok());
+        let err = config.check_artifact("tree", "sha512", Some(LeafMode::HashData)).unwrap_err();
+        assert_eq!(err.to_string(), "hasher mismatch in tree: expected `sha512/pre-hashed`, found `sha512/hash-data`");
+        let err = config.check_artifact("archive", "sha256", None).unwrap_err();
+        let expected = "hasher mismatch in archive: expected `sha512/pre-hashed`, found `sha256/unrecorded`";
+        assert_eq!(err.to_string(), expected);
+
+        for mode in [LeafMode::HashData, LeafMode::PreHashed] {
+            assert_eq!(LeafMode::from_id(mode.id()), Some(mode));
+            assert_eq!(serde_json::to_value(mode).unwrap(), mode.id());
+        }
+        assert_eq!(LeafMode::from_id("raw"), None);
+    }
+
+    #[test]
+    fn test_max_proof_depth() {
+        // a path of `MAX_PROOF_STEPS` steps, the depth of a tree of `usize::MAX` leaves, and its root
+        let (entry, sibling) = (b"deep".to_vec(), vec![7; 32]);
+        let config = TreeConfig::default().with_hasher(HashAlgorithm::Sha512);
+        let deep = |steps: usize| OwnedProof::new(vec![(HashDirection::Right, sibling.clone()); steps]);
+        let root = (0..MAX_PROOF_STEPS).fold(config.hasher().leaf_hash(&entry), |running, _| {
+            config.hasher().node_hash(&running, &sibling)
+        });
+        assert_eq!(config.max_proof_depth(), MAX_PROOF_STEPS);
+        let longest = deep(MAX_PROOF_STEPS);
+        assert!(config.verify_proof(&entry, &longest.as_proof(), &root).unwrap());
+        assert!(!config.verify_proof(&b"other".to_vec(), &longest.as_proof(), &root).unwrap());
+
+        let too_many = MAX_PROOF_STEPS + 1;
+        let too_deep = config.verify_proof(&entry, &deep(too_many).as_proof(), &root);
+        assert!(matches!(too_deep, Err(LibError::ProofTooDeep { max: MAX_PROOF_STEPS, got }) if got == too_many));
+        let shallow = config.with_max_proof_depth(10).verify_proof(&entry, &longest.as_proof(), &root);
+        assert!(matches!(shallow, Err(LibError::ProofTooDeep { max: 10, got: MAX_PROOF_STEPS })));
+        assert_eq!(config.with_max_proof_depth(1000).max_proof_depth(), MAX_PROOF_STEPS);
+        assert_eq!(TreeConfig::default(), TreeConfig::new(LeafMode::HashData));
+
+        let tree = MerkleTree::construct(&example_data(5));
+        let pre_hashed = TreeConfig::new(LeafMode::PreHashed).with_max_proof_depth(tree.depth());
+        let proof = tree.prove_by_index(4).unwrap();
+        assert!(pre_hashed.verify_proof(&tree.leaves()[4], &proof, &tree.root()).unwrap());
+    }
+}
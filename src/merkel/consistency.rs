@@ -0,0 +1,187 @@
+use super::{
+    tree_depth, Hash, IndexedProof, MerkleHasher, MerkleTree, OddLeafPolicy, OwnedProof, Sha256Hasher, TreeSize,
+    TreeSnapshot,
+};
+use crate::util::error::LibError;
+
+/// Proof that a tree of `old_size` leaves holds the first leaves of a tree of `new_size` leaves
+///
+/// Under `OddLeafPolicy::Duplicate` the root of the smaller tree is in general not a node of the
+/// larger one, so unlike RFC 6962 the proof carries the roots of the largest perfect subtrees that
+/// cover the first `old_size` leaves, one per set bit of `old_size` from the largest, each with the
+/// sibling hashes leading from it to the new root. The old root is recomputed from those subtrees
+/// and every subtree is checked at its own position in the new tree.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConsistencyProof {
+    old_size: usize,
+    new_size: usize,
+    subtrees: Vec<(Hash, Vec<Hash>)>,
+}
+
+impl ConsistencyProof {
+    pub fn old_size(&self) -> usize {
+        self.old_size
+    }
+
+    pub fn new_size(&self) -> usize {
+        self.new_size
+    }
+
+    /// Roots of the covering subtrees, largest first, with their sibling hashes up to the new root
+    pub fn subtrees(&self) -> &[(Hash, Vec<Hash>)] {
+        &self.subtrees
+    }
+
+    /// Checks that `old_root` is the root of the first `old_size` leaves of the tree of `new_root`
+    pub fn verify(&self, old_root: &Hash, new_root: &Hash) -> bool {
+        self.verify_with(&Sha256Hasher, old_root, new_root)
+    }
+
+    /// Like `verify`, for trees built with another hasher
+    pub fn verify_with<H: MerkleHasher + ?Sized>(&self, hasher: &H, old_root: &Hash, new_root: &Hash) -> bool {
+        let levels = subtree_levels(self.old_size);
+        if self.old_size == 0 || self.old_size > self.new_size || levels.len() != self.subtrees.len() {
+            return false;
+        }
+        for (&level, (subtree, path)) in levels.iter().zip(&self.subtrees) {
+            // the levels above `level` form a tree of their own over the nodes of `level`
+            let index = (self.old_size >> level) - 1;
+            let Ok(indexed) = IndexedProof::new(index, ((self.new_size - 1) >> level) + 1, path.clone()) else {
+                return false;
+            };
+            if &indexed.to_proof().as_proof().root_from_with(hasher, subtree) != new_root {
+                return false;
+            }
+        }
+
+        // the smallest subtree is the lone last node of its level, so it is paired with itself, and
+        // at every level above a subtree on the left joins the path if that bit of `old_size` is set
+        let (lowest, mut hash) = (levels[levels.len() - 1], self.subtrees[levels.len() - 1].0.clone());
+        for level in lowest..tree_depth(self.old_size) {
+            hash = match levels.iter().position(|&l| l == level) {
+                Some(at) if level > lowest => hasher.node_hash(&self.subtrees[at].0, &hash),
+                _ => hasher.node_hash(&hash, &hash),
+            };
+        }
+        &hash == old_root
+    }
+}
+
+impl MerkleTree {
+    /// Proves that the first `old_size` leaves of this tree form the tree a root was published for
+    ///
+    /// Fails with `LibError::EmptyInput` for an `old_size` of 0, `LibError::IndexOutOfRange` past
+    /// the size of this tree and `LibError::InvalidFormat` for a tree not built with
+    /// `OddLeafPolicy::Duplicate`.
+    pub fn prove_consistency(&self, old_size: impl Into<TreeSize>) -> Result<ConsistencyProof, LibError> {
+        let prove = |index: usize| Ok((self.leaves()[index].clone(), self.prove_by_index(index)?.into_owned()));
+        prove_consistency(old_size.into().saturating_usize(), self.len(), self.odd_leaf_policy(), self.hasher(), prove)
+    }
+}
+
+impl TreeSnapshot {
+    /// Like `MerkleTree::prove_consistency`, against the root of this snapshot
+    pub fn prove_consistency(&self, old_size: impl Into<TreeSize>) -> Result<ConsistencyProof, LibError> {
+        let prove = |index: usize| {
+            let leaf = self.leaf(index).ok_or(LibError::IndexOutOfRange { index, size: self.len() })?;
+            Ok((leaf.clone(), self.prove_by_index(index)?.into_owned()))
+        };
+        prove_consistency(old_size.into().saturating_usize(), self.len(), self.odd_leaf_policy(), self.hasher(), prove)
+    }
+}
+
+/// Levels of the largest perfect subtrees covering the first `size` leaves, largest first
+fn subtree_levels(size: usize) -> Vec<usize> {
+    (0..usize::BITS as usize).rev().filter(|&level| (size >> level) & 1 == 1).collect()
+}
+
+fn prove_consistency<H: MerkleHasher>(
+    old_size: usize,
+    new_size: usize,
+    policy: OddLeafPolicy,
+    hasher: H,
+    prove: impl Fn(usize) -> Result<(Hash, OwnedProof), LibError>,
+) -> Result<ConsistencyProof, LibError> {
+    if old_size == 0 {
+        return Err(LibError::EmptyInput);
+    }
+    if old_size > new_size {
+        return Err(LibError::IndexOutOfRange { index: old_size, size: new_size });
+    }
+    if policy != OddLeafPolicy::Duplicate {
+        return Err(LibError::InvalidFormat("consistency proofs need OddLeafPolicy::Duplicate".into()));
+    }
+
+    let mut subtrees = Vec::new();
+    for level in subtree_levels(old_size) {
+        // the first leaf of the subtree: the lower steps of its proof lead to the subtree root and
+        // the others from there to the root
+        let (leaf, proof) = prove(((old_size >> level) - 1) << level)?;
+        let (below, above) = proof.hashes().split_at(level);
+        let root = OwnedProof::new(below.to_vec()).as_proof().root_from_with(&hasher, &leaf);
+        subtrees.push((root, above.iter().map(|(_, hash)| hash.to_vec()).collect()));
+    }
+    Ok(ConsistencyProof { old_size, new_size, subtrees })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{Data, HashAlgorithm, TreeConfig};
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| (i as u32).to_be_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_every_prefix_is_consistent() {
+        let data = example_data(40);
+        for new_size in 1..=40 {
+            let tree = MerkleTree::construct(&data[..new_size]);
+            for old_size in 1..=new_size {
+                let old_root = MerkleTree::construct(&data[..old_size]).root();
+                let proof = tree.prove_consistency(old_size).unwrap();
+                assert_eq!(proof.subtrees().len(), old_size.count_ones() as usize);
+                assert!(proof.verify(&old_root, &tree.root()), "{} of {}", old_size, new_size);
+                assert!(!proof.verify(&tree.root(), &old_root) || old_size == new_size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_other_histories() {
+        let data = example_data(13);
+        let tree = MerkleTree::construct(&data);
+        let old_root = MerkleTree::construct(&data[..5]).root();
+        let proof = tree.prove_consistency(5).unwrap();
+        assert!(proof.verify(&old_root, &tree.root()));
+
+        // a rewritten old leaf, a tampered subtree and a proof claiming another old size
+        let mut rewritten = data[..5].to_vec();
+        rewritten[4] = vec![0xff];
+        assert!(!proof.verify(&MerkleTree::construct(&rewritten).root(), &tree.root()));
+        let mut tampered = proof.clone();
+        tampered.subtrees[1].0[0] ^= 1;
+        assert!(!tampered.verify(&old_root, &tree.root()));
+        let mut shifted = proof.clone();
+        shifted.old_size = 6;
+        assert!(!shifted.verify(&old_root, &tree.root()));
+
+        assert!(matches!(tree.prove_consistency(0), Err(LibError::EmptyInput)));
+        assert!(matches!(tree.prove_consistency(14), Err(LibError::IndexOutOfRange { index: 14, size: 13 })));
+        let promoted = TreeConfig::default().with_odd_leaf_policy(OddLeafPolicy::Promote).construct(&data).unwrap();
+        assert!(matches!(promoted.prove_consistency(5), Err(LibError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn test_snapshots_and_other_hashers() {
+        let config = TreeConfig::default().with_hasher(HashAlgorithm::Sha512);
+        let data = example_data(9);
+        let old = config.construct(&data[..3]).unwrap().snapshot();
+        let new = data[3..].iter().fold(old.clone(), |snapshot, leaf| snapshot.push(leaf));
+        let proof = new.prove_consistency(3).unwrap();
+        assert_eq!(proof, config.construct(&data).unwrap().prove_consistency(3).unwrap());
+        assert!(proof.verify_with(&HashAlgorithm::Sha512, &old.root(), &new.root()));
+        assert!(!proof.verify(&old.root(), &new.root()));
+    }
+}
@@ -0,0 +1,333 @@
+use super::explain::check_path;
+use super::{
+    tree_depth, ChainedProof, Data, FailurePoint, Hash, LeafIndex, LeafMode, MerkleHasher, MerkleTree, OwnedProof,
+    ProofFailure, TreeDescriptor,
+};
+use crate::util::error::LibError;
+
+/// The fixed parameters of verifying proofs against one tree: its root, size, hasher, odd leaf
+/// policy and leaf mode, taken from its descriptor
+///
+/// Verifying many proofs otherwise means passing the same root and tree size to every call, and
+/// a root of one tree is easily passed with the size of another. A proof tagged with another tree
+/// size, hasher or leaf mode, or an artifact that describes another tree, fails with
+/// `LibError::ContextMismatch`.
+///
+/// Proofs are checked against their index as `MerkleTree::verify_proof_at_index` does, under the
+/// hasher and odd leaf policy of the descriptor, so proofs of `OddLeafPolicy::Promote` trees are
+/// checked against their audit paths. A descriptor without a leaf mode is taken as
+/// `LeafMode::HashData`, the mode of `MerkleTree::construct`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyContext {
+    descriptor: TreeDescriptor,
+    leaf_mode: LeafMode,
+    tree_size: usize,
+}
+
+impl VerifyContext {
+    pub fn new(descriptor: TreeDescriptor) -> Self {
+        VerifyContext {
+            leaf_mode: descriptor.leaf_mode.unwrap_or(LeafMode::HashData),
+            tree_size: usize::try_from(descriptor.leaf_count).unwrap_or(usize::MAX),
+            descriptor,
+        }
+    }
+
+    pub fn descriptor(&self) -> &TreeDescriptor {
+        &self.descriptor
+    }
+
+    pub fn root(&self) -> &Hash {
+        &self.descriptor.root
+    }
+
+    pub fn tree_size(&self) -> usize {
+        self.tree_size
+    }
+
+    /// Leaf hash of an entry under the leaf mode and hasher
+    pub fn leaf_hash(&self, entry: &Data) -> Hash {
+        match self.leaf_mode {
+            LeafMode::HashData => self.descriptor.hasher.leaf_hash(entry),
+            LeafMode::PreHashed => entry.clone(),
+        }
+    }
+
+    /// Whether `proof` shows the entry at `index` of the tree
+    ///
+    /// Fails with `LibError::ContextMismatch` for a proof tagged with another tree size, hasher or
+    /// leaf mode, and with `LibError::ProofTooDeep` for more steps than the tree is deep, before
+    /// anything is hashed. A leaf mode is compared where both the proof and the descriptor record
+    /// one, as `check_descriptor` compares them.
+    pub fn verify(&self, entry: &Data, index: impl Into<LeafIndex>, proof: &OwnedProof) -> Result<bool, LibError> {
+        self.verify_leaf_hash(&self.leaf_hash(entry), index, proof)
+    }
+
+    /// Like `verify`, from an already hashed leaf
+    pub fn verify_leaf_hash(
+        &self,
+        leaf_hash: &Hash,
+        index: impl Into<LeafIndex>,
+        proof: &OwnedProof,
+    ) -> Result<bool, LibError> {
+        self.check_proof(proof)?;
+        let depth = tree_depth(self.tree_size);
+        if proof.hashes().len() > depth {
+            return Err(LibError::ProofTooDeep { max: depth, got: proof.hashes().len() });
+        }
+        Ok(self.check_path(leaf_hash, index.into().saturating_usize(), proof).is_ok())
+    }
+
+    /// Like `verify`, explaining a failure as `MerkleTree::verify_proof_detailed` does
+    ///
+    /// A proof tagged with another tree size fails at `FailurePoint::TreeSize`.
+    pub fn verify_detailed(
+        &self,
+        entry: &Data,
+        index: impl Into<LeafIndex>,
+        proof: &OwnedProof,
+    ) -> Result<(), ProofFailure> {
+        let index = index.into().saturating_usize();
+        let result = self.check_path(&self.leaf_hash(entry), index, proof);
+        match proof.tree_size() {
+            Some(tagged) if tagged != self.tree_size => {
+                let mut failure = result.err().unwrap_or_else(|| ProofFailure {
+                    index,
+                    tree_size: self.tree_size,
+                    proof_len: proof.hashes().len(),
+                    expected_len: Some(proof.hashes().len()),
+                    misdirected_step: None,
+                    expected_root: self.root().clone(),
+                    computed_root: self.root().clone(),
+                    point: None,
+                });
+                failure.point = Some(FailurePoint::TreeSize(tagged));
+                Err(failure)
+            }
+            _ => result,
+        }
+    }
+
+    /// `verify` of each `(index, entry, proof)`, in order
+    ///
+    /// Each item gets its own result, so one mismatched proof does not fail the batch.
+    pub fn verify_batch(&self, items: &[(usize, Data, OwnedProof)]) -> Vec<Result<bool, LibError>> {
+        items.iter().map(|(index, entry, proof)| self.verify(entry, *index, proof)).collect()
+    }
+
+    /// Whether the chained proof shows the data in an inner tree whose root is the leaf at `index`
+    /// of this tree, as `TreeConfig::verify_chained` does
+    ///
+    /// The inner proof is checked as `MerkleTree::verify_proof` does, and the inner root becomes a
+    /// leaf under the leaf mode and hasher of this context. The outer proof is held to this
+    /// context as `verify` holds proofs, failing with `LibError::ContextMismatch` the same way.
+    pub fn verify_chained(
+        &self,
+        data: &Data,
+        index: impl Into<LeafIndex>,
+        chained: &ChainedProof,
+    ) -> Result<bool, LibError> {
+        let outer = self.verify(chained.inner_root(), index, chained.outer())?;
+        Ok(outer && MerkleTree::verify_proof(data, &chained.inner().as_proof(), chained.inner_root()))
+    }
+
+    /// Checks that `descriptor` describes the tree of this context, naming the first field that
+    /// differs in a `LibError::ContextMismatch`
+    ///
+    /// The root, leaf count, hasher and odd leaf policy must match, and the leaf mode where both
+    /// record one. The crate version and canonical ordering do not change how proofs verify.
+    pub fn check_descriptor(&self, artifact: &'static str, descriptor: &TreeDescriptor) -> Result<(), LibError> {
+        let ours = &self.descriptor;
+        let mismatch = |field: &'static str, expected: String, found: String| {
+            Err(LibError::ContextMismatch { artifact, field, expected, found })
+        };
+        if descriptor.hasher != ours.hasher {
+            return mismatch("hasher", ours.hasher.id().into(), descriptor.hasher.id().into());
+        }
+        if descriptor.odd_leaf_policy != ours.odd_leaf_policy {
+            let policy = |d: &TreeDescriptor| format!("{:?}", d.odd_leaf_policy).to_lowercase();
+            return mismatch("odd leaf policy", policy(ours), policy(descriptor));
+        }
+        if let (Some(expected), Some(found)) = (ours.leaf_mode, descriptor.leaf_mode) {
+            if expected != found {
+                return mismatch("leaf mode", expected.id().into(), found.id().into());
+            }
+        }
+        if descriptor.leaf_count != ours.leaf_count {
+            return mismatch("leaf count", ours.leaf_count.to_string(), descriptor.leaf_count.to_string());
+        }
+        if descriptor.root != ours.root {
+            return mismatch("root", hex::encode(&ours.root), hex::encode(&descriptor.root));
+        }
+        Ok(())
+    }
+
+    /// Checks the tree size, hasher and leaf mode a proof may be tagged with
+    fn check_proof(&self, proof: &OwnedProof) -> Result<(), LibError> {
+        let mismatch = |field: &'static str, expected: String, found: String| {
+            Err(LibError::ContextMismatch { artifact: "proof", field, expected, found })
+        };
+        match proof.hasher() {
+            Some(tagged) if tagged != self.descriptor.hasher => {
+                return mismatch("hasher", self.descriptor.hasher.id().into(), tagged.id().into());
+            }
+            _ => {}
+        }
+        if let (Some(expected), Some(tagged)) = (self.descriptor.leaf_mode, proof.leaf_mode()) {
+            if tagged != expected {
+                return mismatch("leaf mode", expected.id().into(), tagged.id().into());
+            }
+        }
+        match proof.tree_size() {
+            Some(tagged) if tagged != self.tree_size => {
+                mismatch("tree size", self.tree_size.to_string(), tagged.to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_path(&self, leaf_hash: &Hash, index: usize, proof: &OwnedProof) -> Result<(), ProofFailure> {
+        let hasher = &self.descriptor.hasher;
+        let policy = self.descriptor.odd_leaf_policy;
+        check_path(hasher, policy, leaf_hash, &proof.as_proof(), index, self.tree_size, self.root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{HashAlgorithm, HashDirection, OddLeafPolicy, TreeConfig};
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| format!("entry {}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_same_as_free_functions() {
+        let data = example_data(11);
+        let tree = MerkleTree::construct(&data);
+        let context = VerifyContext::new(tree.descriptor());
+        for (index, entry) in data.iter().enumerate() {
+            let proof = tree.prove_by_index(index).unwrap().into_owned();
+            let mut tampered = proof.hashes().to_vec();
+            tampered[0].0 = tampered[0].0.opposite();
+            let untagged = OwnedProof::new(proof.hashes().to_vec());
+            for proof in [proof, untagged, OwnedProof::new(tampered).with_tree_size(11)] {
+                for claimed in [index, index ^ 1, 11] {
+                    let free = MerkleTree::verify_proof_at_index(entry, &proof.as_proof(), claimed, 11, &tree.root());
+                    assert_eq!(context.verify(entry, claimed, &proof).unwrap(), free, "{} {}", index, claimed);
+                    let detailed = MerkleTree::verify_proof_detailed(
+                        &tree.leaves()[index],
+                        &proof.as_proof(),
+                        claimed,
+                        11,
+                        &tree.root(),
+                    );
+                    assert_eq!(context.verify_detailed(entry, claimed, &proof), detailed);
+                }
+            }
+        }
+
+        // other hashers, leaf modes and policies, where there is no free function with the index
+        for policy in [OddLeafPolicy::Duplicate, OddLeafPolicy::Promote] {
+            let config =
+                TreeConfig::new(LeafMode::PreHashed).with_hasher(HashAlgorithm::Sha512).with_odd_leaf_policy(policy);
+            let leaves: Vec<Data> = data.iter().map(|entry| HashAlgorithm::Sha512.leaf_hash(entry)).collect();
+            let tree = config.construct(&leaves).unwrap();
+            let context = VerifyContext::new(config.descriptor(&tree).unwrap());
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = tree.prove_by_index(index).unwrap().into_owned();
+                assert!(config.verify_proof(leaf, &proof.as_proof(), &tree.root()).unwrap());
+                assert!(context.verify(leaf, index, &proof).unwrap(), "{:?} {}", policy, index);
+                // a proof of another leaf does not show this one, wherever the entry is claimed to be
+                let other = tree.prove_by_index((index + 1) % 11).unwrap().into_owned();
+                assert!(!context.verify(leaf, index, &other).unwrap());
+            }
+            // the last leaf, claimed one place to the left
+            let proof = tree.prove_by_index(10).unwrap().into_owned();
+            assert!(!context.verify(&leaves[10], 9, &proof).unwrap());
+        }
+
+        let items: Vec<(usize, Data, OwnedProof)> =
+            (0..3).map(|i| (i, data[i].clone(), tree.prove_by_index(i).unwrap().into_owned())).collect();
+        let results = context.verify_batch(&[items[0].clone(), (0, data[0].clone(), items[1].2.clone())]);
+        assert!(matches!(results.as_slice(), [Ok(true), Ok(false)]));
+    }
+
+    #[test]
+    fn test_rejects_other_trees() {
+        let data = example_data(11);
+        let tree = MerkleTree::construct(&data);
+        let context = VerifyContext::new(tree.descriptor());
+        let larger = MerkleTree::construct(&example_data(12));
+
+        let proof = larger.prove_by_index(3).unwrap().into_owned();
+        let error = context.verify(&data[3], 3, &proof).unwrap_err();
+        assert!(matches!(error, LibError::ContextMismatch { field: "tree size", .. }));
+        assert_eq!(
+            error.to_string(),
+            "proof does not match the verification context: its tree size is 12, expected 11"
+        );
+        assert_eq!(error.code(), "CONTEXT_MISMATCH");
+        let failure = context.verify_detailed(&data[3], 3, &proof).unwrap_err();
+        assert_eq!(failure.point, Some(FailurePoint::TreeSize(12)));
+        assert!(failure.to_string().ends_with("\nthe proof is tagged with a tree of 12 leaves, not 11"), "{}", failure);
+        // the tag alone is wrong, so the failure holds the context's root
+        let relabelled = tree.prove_by_index(3).unwrap().into_owned().with_tree_size(12);
+        let failure = context.verify_detailed(&data[3], 3, &relabelled).unwrap_err();
+        assert_eq!((failure.computed_root, failure.point), (tree.root(), Some(FailurePoint::TreeSize(12))));
+
+        let proof = tree.prove_by_index(3).unwrap().into_owned();
+        let error = context.verify(&data[3], 3, &proof.clone().with_hasher(HashAlgorithm::Sha512)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "proof does not match the verification context: its hasher is sha512, expected sha256"
+        );
+        let strict = VerifyContext::new(TreeConfig::default().descriptor(&tree).unwrap());
+        let pre_hashed = TreeConfig::new(LeafMode::PreHashed).tag_proof(proof.clone());
+        let error = strict.verify(&data[3], 3, &pre_hashed).unwrap_err();
+        assert!(matches!(error, LibError::ContextMismatch { field: "leaf mode", .. }));
+        assert!(strict.verify(&data[3], 3, &TreeConfig::default().tag_proof(proof)).unwrap());
+
+        let deep = OwnedProof::new(vec![(HashDirection::Right, tree.root()); 5]);
+        assert!(matches!(context.verify(&data[0], 0, &deep), Err(LibError::ProofTooDeep { max: 4, got: 5 })));
+
+        assert!(context.check_descriptor("tree", &tree.descriptor()).is_ok());
+        let error = context.check_descriptor("bundle", &larger.descriptor()).unwrap_err();
+        assert!(matches!(error, LibError::ContextMismatch { artifact: "bundle", field: "leaf count", .. }));
+        let sha512 = TreeConfig::default().with_hasher(HashAlgorithm::Sha512).construct(&data).unwrap();
+        let error = context.check_descriptor("bundle", &sha512.descriptor()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "bundle does not match the verification context: its hasher is sha512, expected sha256"
+        );
+        let pre_hashed = TreeConfig::new(LeafMode::PreHashed).descriptor(&tree).unwrap();
+        let error = strict.check_descriptor("tree", &pre_hashed).unwrap_err();
+        assert!(matches!(error, LibError::ContextMismatch { field: "leaf mode", .. }));
+        assert!(context.check_descriptor("tree", &pre_hashed).is_ok());
+    }
+
+    #[test]
+    fn test_chained() {
+        let data = example_data(5);
+        let inner = MerkleTree::construct(&data);
+        for leaf_mode in [LeafMode::HashData, LeafMode::PreHashed] {
+            // the inner root is the third of four outer entries
+            let mut entries: Vec<Data> = (0..4u8).map(|i| vec![i; 32]).collect();
+            entries[2] = inner.root();
+            let config = TreeConfig::new(leaf_mode);
+            let outer = config.construct(&entries).unwrap();
+            let chained = ChainedProof::from_trees(&inner, 1, &outer, 2).unwrap();
+            let context = VerifyContext::new(config.descriptor(&outer).unwrap());
+            assert!(config.verify_chained(&data[1], &chained, &outer.root()));
+            assert!(context.verify_chained(&data[1], 2, &chained).unwrap());
+            assert!(!context.verify_chained(&data[1], 3, &chained).unwrap());
+            assert!(!context.verify_chained(&data[2], 2, &chained).unwrap());
+
+            let outer_proof = chained.outer().clone().with_tree_size(5);
+            let elsewhere = ChainedProof::new(chained.inner().clone(), inner.root(), outer_proof);
+            let error = context.verify_chained(&data[1], 2, &elsewhere);
+            assert!(matches!(error, Err(LibError::ContextMismatch { field: "tree size", .. })));
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+
+use std::fmt::Write;
+
+use super::{
+    CanonicalOrdering, Hash, HashAlgorithm, LeafMode, MerkleHasher, MerkleTree, OddLeafPolicy, TreeConfig, TreeShape,
+};
+use crate::util::encoding::{Encoding, HashEncoding};
+use crate::util::error::LibError;
+
+/// Version of this crate, as recorded in the descriptors of the trees it builds
+pub const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Everything needed to rebuild a root from its leaves, so a root is never handed on bare
+///
+/// Saved CBOR trees and proof bundles record it, so artifacts of this crate do not have to be
+/// guessed at with `detect_scheme`.
+///
+/// Serialized, the root is written in `encoding` and the encoding is recorded next to it unless it
+/// is hex, so a root in one encoding is never read back as another: reading fails if the root does
+/// not decode in the recorded encoding to a hash of the recorded hasher.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "TreeDescriptorRepr", into = "TreeDescriptorRepr")]
+#[non_exhaustive]
+pub struct TreeDescriptor {
+    pub root: Hash,
+    pub leaf_count: u64,
+    pub hasher: HashAlgorithm,
+    /// How the entries became leaves, None when it is not known; see `TreeConfig::descriptor`
+    pub leaf_mode: Option<LeafMode>,
+    pub odd_leaf_policy: OddLeafPolicy,
+    pub shape: TreeShape,
+    /// Version of the crate that built the tree, None for artifacts written before it was recorded
+    pub crate_version: Option<String>,
+    /// Order the entries were sorted into before they became leaves, None when they were not
+    pub canonical_ordering: Option<CanonicalOrdering>,
+    /// How the root is spelled when the descriptor is serialized, hex unless chosen otherwise
+    pub encoding: Encoding,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TreeDescriptorRepr {
+    root: String,
+    leaf_count: u64,
+    hasher: HashAlgorithm,
+    leaf_mode: Option<LeafMode>,
+    odd_leaf_policy: OddLeafPolicy,
+    shape: TreeShape,
+    crate_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    canonical_ordering: Option<CanonicalOrdering>,
+    #[serde(default, skip_serializing_if = "Encoding::is_hex")]
+    encoding: Encoding,
+}
+
+impl TreeDescriptor {
+    /// The same descriptor, serializing its root in `encoding`
+    pub fn with_encoding(self, encoding: Encoding) -> Self {
+        TreeDescriptor { encoding, ..self }
+    }
+
+    /// A short name of the scheme and the root, such as `sha256d/dup/32:0727b3…`
+    ///
+    /// The hasher, the odd leaf policy (`dup` or `promote`), the leaf count and the first three
+    /// bytes of the root. It is for telling roots apart at a glance, not for checking them.
+    pub fn fingerprint(&self) -> String {
+        let policy = match self.odd_leaf_policy {
+            OddLeafPolicy::Duplicate => "dup",
+            OddLeafPolicy::Promote => "promote",
+        };
+        let mut fingerprint = format!("{}/{}/{}:", self.hasher.id(), policy, self.leaf_count);
+        for byte in self.root.iter().take(3) {
+            let _ = write!(fingerprint, "{:02x}", byte);
+        }
+        fingerprint.push('…');
+        fingerprint
+    }
+}
+
+impl MerkleTree {
+    /// Describes the tree; its leaf mode is not known to it and is None
+    pub fn descriptor(&self) -> TreeDescriptor {
+        TreeDescriptor {
+            root: self.root(),
+            leaf_count: self.len() as u64,
+            hasher: self.hasher(),
+            leaf_mode: None,
+            odd_leaf_policy: self.odd_leaf_policy(),
+            shape: self.shape(),
+            crate_version: Some(CRATE_VERSION.to_string()),
+            canonical_ordering: None,
+            encoding: Encoding::Hex,
+        }
+    }
+}
+
+impl From<TreeDescriptor> for TreeDescriptorRepr {
+    fn from(descriptor: TreeDescriptor) -> Self {
+        TreeDescriptorRepr {
+            root: descriptor.root.encode(descriptor.encoding),
+            leaf_count: descriptor.leaf_count,
+            hasher: descriptor.hasher,
+            leaf_mode: descriptor.leaf_mode,
+            odd_leaf_policy: descriptor.odd_leaf_policy,
+            shape: descriptor.shape,
+            crate_version: descriptor.crate_version,
+            canonical_ordering: descriptor.canonical_ordering,
+            encoding: descriptor.encoding,
+        }
+    }
+}
+
+impl TryFrom<TreeDescriptorRepr> for TreeDescriptor {
+    type Error = LibError;
+
+    fn try_from(repr: TreeDescriptorRepr) -> Result<Self, LibError> {
+        let root = Hash::decode(repr.encoding, &repr.root)?;
+        if root.len() != repr.hasher.output_len() {
+            let (id, expected) = (repr.hasher.id(), repr.hasher.output_len());
+            let message = format!("{} root is {} bytes, expected {}", id, root.len(), expected);
+            return Err(LibError::InvalidFormat(message));
+        }
+        Ok(TreeDescriptor {
+            root,
+            leaf_count: repr.leaf_count,
+            hasher: repr.hasher,
+            leaf_mode: repr.leaf_mode,
+            odd_leaf_policy: repr.odd_leaf_policy,
+            shape: repr.shape,
+            crate_version: repr.crate_version,
+            canonical_ordering: repr.canonical_ordering,
+            encoding: repr.encoding,
+        })
+    }
+}
+
+impl TreeConfig {
+    /// Like `MerkleTree::descriptor`, also naming the leaf mode and canonical ordering of this
+    /// configuration
+    ///
+    /// Fails with `LibError::HasherMismatch` for a tree built with another hasher.
+    pub fn descriptor(&self, tree: &MerkleTree) -> Result<TreeDescriptor, LibError> {
+        self.check_artifact("tree", tree.hasher().id(), Some(self.leaf_mode()))?;
+        Ok(TreeDescriptor {
+            leaf_mode: Some(self.leaf_mode()),
+            canonical_ordering: self.canonical_ordering(),
+            ..tree.descriptor()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::Data;
+
+    fn example_data(n: u8) -> Vec<Data> {
+        (0..n).map(|i| vec![i]).collect()
+    }
+
+    #[test]
+    fn test_descriptor_and_fingerprint() {
+        let config = TreeConfig::default().with_hasher(HashAlgorithm::Sha256d);
+        let tree = config.construct(&example_data(32)).unwrap();
+        let descriptor = config.descriptor(&tree).unwrap();
+        assert_eq!(descriptor.leaf_mode, Some(LeafMode::HashData));
+        assert_eq!(descriptor.fingerprint(), format!("sha256d/dup/32:{}…", &hex::encode(tree.root())[..6]));
+        assert_eq!(TreeDescriptor { leaf_mode: None, ..descriptor.clone() }, tree.descriptor());
+
+        let json = serde_json::to_value(&descriptor).unwrap();
+        let scheme = (&json["hasher"], &json["leaf_mode"], &json["shape"]);
+        assert_eq!(scheme, (&"sha256d".into(), &"hash-data".into(), &"paired".into()));
+        assert_eq!(json["crate_version"], CRATE_VERSION);
+        assert_eq!(serde_json::from_value::<TreeDescriptor>(json).unwrap(), descriptor);
+
+        let balanced = TreeConfig::default().with_shape(TreeShape::LeftBalanced);
+        let tree = balanced.construct(&example_data(5)).unwrap();
+        assert!(balanced.descriptor(&tree).unwrap().fingerprint().starts_with("sha256/promote/5:"));
+        assert!(matches!(config.descriptor(&tree), Err(LibError::HasherMismatch { .. })));
+
+        let canonical = TreeConfig::default().with_canonical_ordering(CanonicalOrdering::ByData);
+        let descriptor = canonical.descriptor(&canonical.construct(&example_data(3)).unwrap()).unwrap();
+        let json = serde_json::to_value(&descriptor).unwrap();
+        assert_eq!(json["canonical_ordering"], "by-data");
+        assert_eq!(serde_json::from_value::<TreeDescriptor>(json).unwrap(), descriptor);
+        assert!(serde_json::to_value(tree.descriptor()).unwrap().get("canonical_ordering").is_none());
+    }
+
+    #[test]
+    fn test_encoded_root() {
+        let tree = MerkleTree::construct(&example_data(5));
+        let hex = serde_json::to_value(tree.descriptor()).unwrap();
+        assert!(hex.get("encoding").is_none());
+        for encoding in Encoding::ALL {
+            let descriptor = TreeDescriptor { encoding, ..tree.descriptor() };
+            let json = serde_json::to_value(&descriptor).unwrap();
+            assert_eq!(json["root"], tree.root().encode(encoding));
+            if encoding != Encoding::Hex {
+                assert_eq!(json["encoding"], encoding.id());
+            }
+            assert_eq!(serde_json::from_value::<TreeDescriptor>(json).unwrap(), descriptor);
+        }
+
+        // a root spelled in another encoding than the one recorded is refused, even where its
+        // digits are valid in both
+        let mut mixed = hex.clone();
+        mixed["encoding"] = "base64url".into();
+        let error = serde_json::from_value::<TreeDescriptor>(mixed).unwrap_err().to_string();
+        assert_eq!(error, "invalid format: sha256 root is 48 bytes, expected 32");
+        let mut mixed = hex.clone();
+        mixed["root"] = tree.root().encode(Encoding::Base58Btc).into();
+        assert!(serde_json::from_value::<TreeDescriptor>(mixed).unwrap_err().to_string().starts_with("invalid hex"));
+        // a multibase root names its own base
+        let mut prefixed = hex;
+        prefixed["encoding"] = "multibase".into();
+        prefixed["root"] = format!("f{}", tree.root().encode(Encoding::Hex)).into();
+        let descriptor = serde_json::from_value::<TreeDescriptor>(prefixed).unwrap();
+        assert_eq!(descriptor, TreeDescriptor { encoding: Encoding::Multibase, ..tree.descriptor() });
+    }
+}
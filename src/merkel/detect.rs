@@ -0,0 +1,162 @@
+use serde::Serialize;
+
+use super::{parent_level_with, Data, Hash, HashAlgorithm, LeafMode, MerkleHasher, OddLeafPolicy, TreeConfig};
+
+/// How the two children of a node are ordered before they are hashed into it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum PairOrder {
+    /// `left || right`, as every tree of this crate
+    #[default]
+    LeftRight,
+    /// `right || left`, a mirror image of the tree
+    RightLeft,
+    /// The smaller child first, as in OpenZeppelin's `MerkleProof`
+    Sorted,
+}
+
+/// A way of building a tree that reproduces a root, see `detect_scheme`
+///
+/// Domain separation and double hashing are part of the hasher, as `HashAlgorithm::Sha256Rfc6962`
+/// and `HashAlgorithm::Sha256d`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct SchemeGuess {
+    pub hasher: HashAlgorithm,
+    pub leaf_mode: LeafMode,
+    pub odd_leaf_policy: OddLeafPolicy,
+    pub pair_order: PairOrder,
+}
+
+impl SchemeGuess {
+    /// The config building trees of this scheme, None for a pair order this crate does not build
+    pub fn config(&self) -> Option<TreeConfig> {
+        let config = TreeConfig::new(self.leaf_mode).with_hasher(self.hasher);
+        (self.pair_order == PairOrder::LeftRight).then(|| config.with_odd_leaf_policy(self.odd_leaf_policy))
+    }
+
+    fn root(&self, leaves: &[Data]) -> Option<Hash> {
+        let hasher = self.hasher;
+        let mut level = TreeConfig::new(self.leaf_mode).with_hasher(hasher).leaf_hashes(leaves).ok()?;
+        while level.len() > 1 {
+            level = parent_level_with(&level, self.odd_leaf_policy, |left, right| match self.pair_order {
+                PairOrder::LeftRight => hasher.node_hash(left, right),
+                PairOrder::RightLeft => hasher.node_hash(right, left),
+                PairOrder::Sorted => hasher.node_hash(left.min(right), left.max(right)),
+            });
+        }
+        level.pop()
+    }
+}
+
+/// Finds how a tree over `leaves` was built, if it was built in a way this crate knows, from the
+/// root it is claimed to have
+///
+/// Tries every combination of hasher, leaf mode, odd leaf policy and pair order, so it hashes the
+/// input up to 60 times; it is meant for diagnosing roots that do not match, not for every build.
+/// Combinations are tried from the crate's defaults outwards, so when several reproduce the root,
+/// as both odd leaf policies do for a power of two leaves, the most usual one is returned. None if
+/// none does, or for no leaves.
+pub fn detect_scheme(leaves: &[Data], claimed_root: &Hash) -> Option<SchemeGuess> {
+    let orders = [PairOrder::LeftRight, PairOrder::RightLeft, PairOrder::Sorted];
+    let policies = [OddLeafPolicy::Duplicate, OddLeafPolicy::Promote];
+    let hashers = HashAlgorithm::ALL.into_iter().filter(|hasher| hasher.output_len() == claimed_root.len());
+    hashers
+        .flat_map(|hasher| orders.map(|pair_order| (hasher, pair_order)))
+        .flat_map(|(hasher, pair_order)| {
+            [LeafMode::HashData, LeafMode::PreHashed].into_iter().flat_map(move |leaf_mode| {
+                policies.map(|odd_leaf_policy| SchemeGuess { hasher, leaf_mode, odd_leaf_policy, pair_order })
+            })
+        })
+        .find(|scheme| scheme.root(leaves).as_ref() == Some(claimed_root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_data(n: u8) -> Vec<Data> {
+        (0..n).map(|i| vec![i; 32]).collect()
+    }
+
+    fn scheme(hasher: HashAlgorithm, leaf_mode: LeafMode, policy: OddLeafPolicy, order: PairOrder) -> SchemeGuess {
+        SchemeGuess { hasher, leaf_mode, odd_leaf_policy: policy, pair_order: order }
+    }
+
+    fn default_scheme() -> SchemeGuess {
+        scheme(HashAlgorithm::Sha256, LeafMode::HashData, OddLeafPolicy::Duplicate, PairOrder::LeftRight)
+    }
+
+    /// The root of `scheme` over `leaves`, computed without the tree builder
+    fn reference_root(leaves: &[Data], scheme: &SchemeGuess) -> Hash {
+        let mut level: Vec<Hash> = match scheme.leaf_mode {
+            LeafMode::HashData => leaves.iter().map(|data| scheme.hasher.leaf_hash(data)).collect(),
+            _ => leaves.to_vec(),
+        };
+        while level.len() > 1 {
+            let lone = if level.len() & 1 == 1 { level.pop() } else { None };
+            let mut parents: Vec<Hash> = level.chunks(2).map(|pair| parent(scheme, &pair[0], &pair[1])).collect();
+            parents.extend(lone.map(|lone| match scheme.odd_leaf_policy {
+                OddLeafPolicy::Duplicate => parent(scheme, &lone, &lone),
+                _ => lone,
+            }));
+            level = parents;
+        }
+        level.remove(0)
+    }
+
+    fn parent(scheme: &SchemeGuess, left: &Hash, right: &Hash) -> Hash {
+        let (left, right) = match scheme.pair_order {
+            PairOrder::LeftRight => (left, right),
+            PairOrder::RightLeft => (right, left),
+            PairOrder::Sorted => (left.min(right), left.max(right)),
+        };
+        scheme.hasher.node_hash(left, right)
+    }
+
+    #[test]
+    fn test_detects_schemes() {
+        use HashAlgorithm::*;
+        use LeafMode::*;
+        use OddLeafPolicy::*;
+        use PairOrder::*;
+
+        let data = example_data(7);
+        let schemes = [
+            scheme(Sha256, HashData, Duplicate, LeftRight),
+            scheme(Sha256, HashData, Duplicate, RightLeft),
+            scheme(Sha256, PreHashed, Promote, Sorted),
+            scheme(Sha256d, HashData, Duplicate, LeftRight),
+            scheme(Sha256Rfc6962, PreHashed, Promote, LeftRight),
+            scheme(Sha512, HashData, Promote, RightLeft),
+            scheme(Ripemd160, HashData, Duplicate, Sorted),
+        ];
+        for expected in schemes {
+            let root = reference_root(&data, &expected);
+            assert_eq!(detect_scheme(&data, &root), Some(expected), "{:?}", expected);
+            if let Some(config) = expected.config() {
+                assert_eq!(config.construct(&data).unwrap().root(), root);
+            }
+        }
+        assert_eq!(schemes[1].config(), None);
+    }
+
+    #[test]
+    fn test_ambiguous_and_unknown_roots() {
+        // both odd leaf policies build the same tree over a power of two leaves
+        let data = example_data(8);
+        let promoted = SchemeGuess { odd_leaf_policy: OddLeafPolicy::Promote, ..default_scheme() };
+        let guess = detect_scheme(&data, &reference_root(&data, &promoted)).unwrap();
+        assert_eq!(guess, SchemeGuess { odd_leaf_policy: OddLeafPolicy::Duplicate, ..promoted });
+
+        let random: Hash = rand::random::<[u8; 32]>().to_vec();
+        assert_eq!(detect_scheme(&data, &random), None);
+        assert_eq!(detect_scheme(&data, &vec![0; 20]), None);
+        // leaves that are not hashes are only tried as data
+        let short: Vec<Data> = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let mirrored = SchemeGuess { pair_order: PairOrder::RightLeft, ..default_scheme() };
+        assert_eq!(detect_scheme(&short, &reference_root(&short, &mirrored)), Some(mirrored));
+        assert_eq!(detect_scheme(&[], &random), None);
+    }
+}
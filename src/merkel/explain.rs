@@ -0,0 +1,296 @@
+use std::fmt;
+
+use super::{
+    expected_proof_len, is_lone_on_path, left_balanced_directions, Hash, HashDirection, LeafIndex, MerkleHasher,
+    MerkleTree, OddLeafPolicy, Proof, Sha256Hasher, TreeSize,
+};
+
+/// Why a proof did not verify, as reported by `MerkleTree::verify_proof_detailed`
+///
+/// Steps are counted from 0 here and from 1 in the `Display` output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ProofFailure {
+    pub index: usize,
+    pub tree_size: usize,
+    pub proof_len: usize,
+    /// Proof length of a leaf at `index`, None if the index is out of range
+    pub expected_len: Option<usize>,
+    /// First step whose sibling is on the wrong side for `index`
+    pub misdirected_step: Option<usize>,
+    pub expected_root: Hash,
+    pub computed_root: Hash,
+    /// Where the proof first departs from the tree, only known when checked with `explain_proof`
+    /// unless it is `FailurePoint::Order`
+    pub point: Option<FailurePoint>,
+}
+
+/// First part of a proof that differs from the tree it claims to come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FailurePoint {
+    /// The leaf hash is not the tree's leaf at that index
+    Leaf,
+    /// The sibling hash of this step differs, or the proof ends or continues here unlike the tree's
+    Step(usize),
+    /// The proof matches the tree, so the root it was checked against is the wrong one
+    Root,
+    /// The steps lead to the expected root when taken in the opposite order, as they do in a proof
+    /// listed root-to-leaf but declared `ProofOrder::LeafToRoot`
+    Order,
+    /// The proof is tagged with a tree of this many leaves, not the size it was checked against,
+    /// as `VerifyContext::verify_detailed` reports
+    TreeSize(usize),
+}
+
+impl MerkleTree {
+    /// Like `verify_proof_at_index` for an already hashed leaf, but explains a failure
+    ///
+    /// Without the tree there is no telling which sibling is wrong, so `point` is left empty; use
+    /// `explain_proof` when the tree is available. It is `FailurePoint::Order` for steps that do
+    /// verify in the opposite order, which needs no tree to tell.
+    pub fn verify_proof_detailed(
+        leaf_hash: &Hash,
+        proof: &Proof,
+        index: impl Into<LeafIndex>,
+        tree_size: impl Into<TreeSize>,
+        root_hash: &Hash,
+    ) -> Result<(), ProofFailure> {
+        let (index, tree_size) = (index.into().saturating_usize(), tree_size.into().saturating_usize());
+        check_path(&Sha256Hasher, OddLeafPolicy::Duplicate, leaf_hash, proof, index, tree_size, root_hash)
+    }
+
+    /// Checks a proof for the leaf at `index` against this tree, pointing at the first part of it
+    /// that differs from the tree when it does not verify against `root_hash`
+    pub fn explain_proof(
+        &self,
+        leaf_hash: &Hash,
+        proof: &Proof,
+        index: impl Into<LeafIndex>,
+        root_hash: &Hash,
+    ) -> Result<(), ProofFailure> {
+        let index = index.into();
+        let Err(mut failure) = MerkleTree::verify_proof_detailed(leaf_hash, proof, index, self.len(), root_hash) else {
+            return Ok(());
+        };
+        if failure.point == Some(FailurePoint::Order) {
+            return Err(failure);
+        }
+        let Ok(actual) = self.prove_by_index(index) else {
+            return Err(failure);
+        };
+
+        let steps = proof.len().max(actual.len());
+        // the tree marks a lone node `Duplicate`, which an older proof may have marked `Right`
+        fn legacy<'h>(step: Option<&(HashDirection, &'h [u8])>) -> Option<(HashDirection, &'h [u8])> {
+            step.map(|&(direction, hash)| match direction {
+                HashDirection::Duplicate => (HashDirection::Right, hash),
+                direction => (direction, hash),
+            })
+        }
+        let differing = (0..steps).find(|&step| legacy(proof.steps().get(step)) != legacy(actual.steps().get(step)));
+        failure.point = Some(match differing {
+            _ if self.leaves()[index.saturating_usize()] != *leaf_hash => FailurePoint::Leaf,
+            Some(step) => FailurePoint::Step(step),
+            None => FailurePoint::Root,
+        });
+        Err(failure)
+    }
+}
+
+/// `MerkleTree::verify_proof_detailed` for a tree of any hasher and odd leaf policy
+pub(crate) fn check_path<H: MerkleHasher + ?Sized>(
+    hasher: &H,
+    policy: OddLeafPolicy,
+    leaf_hash: &Hash,
+    proof: &Proof,
+    index: usize,
+    tree_size: usize,
+    root_hash: &Hash,
+) -> Result<(), ProofFailure> {
+    let expected_len = (index < tree_size).then(|| expected_proof_len(tree_size, index, policy));
+    let misdirected_step = match policy {
+        // a lone node paired with itself is marked `Duplicate`, or `Right` before format version 2
+        OddLeafPolicy::Duplicate => proof.steps().iter().enumerate().find_map(|(step, (direction, _))| {
+            let expected = if (index >> step) & 1 == 1 {
+                HashDirection::Left
+            } else if is_lone_on_path(tree_size, index, step) && *direction == HashDirection::Duplicate {
+                HashDirection::Duplicate
+            } else {
+                HashDirection::Right
+            };
+            (*direction != expected).then_some(step)
+        }),
+        // a lone node is promoted and has no step; steps past the path are left to the length
+        OddLeafPolicy::Promote => {
+            let expected = if index < tree_size { left_balanced_directions(tree_size, index) } else { vec![] };
+            proof.steps().iter().zip(&expected).position(|((direction, _), expected)| direction != expected)
+        }
+    };
+    let computed_root = proof.root_from_with(hasher, leaf_hash);
+
+    if expected_len == Some(proof.len()) && misdirected_step.is_none() && &computed_root == root_hash {
+        return Ok(());
+    }
+    let reversed = &computed_root != root_hash
+        && proof.len() > 1
+        && &Proof::from_hashes(proof.steps().iter().rev().copied().collect()).root_from_with(hasher, leaf_hash)
+            == root_hash;
+    Err(ProofFailure {
+        index,
+        tree_size,
+        proof_len: proof.len(),
+        expected_len,
+        misdirected_step,
+        expected_root: root_hash.clone(),
+        computed_root,
+        point: reversed.then_some(FailurePoint::Order),
+    })
+}
+
+impl fmt::Display for ProofFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.expected_len {
+            None => write!(f, "leaf {} is out of range for a tree of {} leaves", self.index, self.tree_size)?,
+            Some(expected) if expected != self.proof_len => write!(
+                f,
+                "the proof has {} steps, but leaf {} of a tree of {} leaves needs {}",
+                self.proof_len, self.index, self.tree_size, expected
+            )?,
+            Some(_) => write!(f, "the proof has the expected {} steps", self.proof_len)?,
+        }
+        if let Some(step) = self.misdirected_step {
+            write!(f, "\nstep {} puts its sibling on the wrong side for leaf {}", step + 1, self.index)?;
+        }
+        if self.computed_root == self.expected_root {
+            write!(f, "\nthe proof does lead to root {}", hex::encode(&self.expected_root))?;
+        } else {
+            write!(f, "\nexpected root {}", hex::encode(&self.expected_root))?;
+            write!(f, "\ncomputed root {}", hex::encode(&self.computed_root))?;
+        }
+        match self.point {
+            Some(FailurePoint::Leaf) => write!(f, "\nthe leaf hash differs from the tree's leaf {}", self.index),
+            Some(FailurePoint::Step(step)) => write!(f, "\nstep {} differs from the tree", step + 1),
+            Some(FailurePoint::Root) => write!(f, "\nthe proof matches the tree, so the expected root is wrong"),
+            Some(FailurePoint::Order) => write!(
+                f,
+                "\nthe steps do lead to the expected root in the opposite order; the proof is likely listed \
+                 root-to-leaf but declared leaf-to-root, or the other way round"
+            ),
+            Some(FailurePoint::TreeSize(tagged)) => {
+                write!(f, "\nthe proof is tagged with a tree of {} leaves, not {}", tagged, self.tree_size)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{Data, OwnedProof, ProofOrder};
+
+    fn example_tree(n: usize) -> MerkleTree {
+        let data: Vec<Data> = (0..n).map(|i| vec![i as u8]).collect();
+        MerkleTree::construct(&data)
+    }
+
+    fn tampered(proof: &OwnedProof, step: usize) -> OwnedProof {
+        let mut hashes = proof.hashes().to_vec();
+        hashes[step].1[0] ^= 1;
+        OwnedProof::new(hashes)
+    }
+
+    #[test]
+    fn test_valid_proof() {
+        let tree = example_tree(7);
+        for index in 0..7 {
+            let proof = tree.prove_by_index(index).unwrap();
+            let leaf = &tree.leaves()[index];
+            assert_eq!(MerkleTree::verify_proof_detailed(leaf, &proof, index, 7, &tree.root()), Ok(()));
+            assert_eq!(tree.explain_proof(leaf, &proof, index, &tree.root()), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_points_at_the_tampered_part() {
+        let tree = example_tree(7);
+        let proof = tree.prove_by_index(5).unwrap().into_owned();
+        let leaf = tree.leaves()[5].clone();
+
+        let mut wrong_leaf = leaf.clone();
+        wrong_leaf[0] ^= 1;
+        let failure = tree.explain_proof(&wrong_leaf, &proof.as_proof(), 5, &tree.root()).unwrap_err();
+        assert_eq!(failure.point, Some(FailurePoint::Leaf));
+        assert_eq!((failure.expected_len, failure.misdirected_step), (Some(3), None));
+        assert_ne!(failure.computed_root, failure.expected_root);
+
+        let failure = tree.explain_proof(&leaf, &tampered(&proof, 1).as_proof(), 5, &tree.root()).unwrap_err();
+        assert_eq!(failure.point, Some(FailurePoint::Step(1)));
+        assert!(failure.to_string().ends_with("\nstep 2 differs from the tree"), "{}", failure);
+
+        let other_root = example_tree(6).root();
+        let failure = tree.explain_proof(&leaf, &proof.as_proof(), 5, &other_root).unwrap_err();
+        assert_eq!(failure.point, Some(FailurePoint::Root));
+        assert_eq!((failure.expected_root, failure.computed_root), (other_root, tree.root()));
+    }
+
+    #[test]
+    fn test_shape_diagnostics() {
+        let tree = example_tree(7);
+        let leaf = &tree.leaves()[5];
+        let proof = tree.prove_by_index(5).unwrap();
+
+        // a proof for leaf 5 presented as leaf 4 still reaches the root, but its first step is misdirected
+        let failure = MerkleTree::verify_proof_detailed(leaf, &proof, 4, 7, &tree.root()).unwrap_err();
+        assert_eq!((failure.misdirected_step, failure.point), (Some(0), None));
+        assert_eq!(failure.computed_root, tree.root());
+        assert_eq!(
+            failure.to_string(),
+            format!(
+                "the proof has the expected 3 steps\nstep 1 puts its sibling on the wrong side for leaf 4\n\
+                 the proof does lead to root {}",
+                hex::encode(tree.root())
+            )
+        );
+
+        let failure = MerkleTree::verify_proof_detailed(leaf, &proof, 5, 9, &tree.root()).unwrap_err();
+        assert_eq!(failure.expected_len, Some(4));
+        assert!(failure.to_string().starts_with("the proof has 3 steps, but leaf 5 of a tree of 9 leaves needs 4"));
+
+        let failure = MerkleTree::verify_proof_detailed(leaf, &proof, 7, 7, &tree.root()).unwrap_err();
+        assert_eq!(failure.expected_len, None);
+        assert!(failure.to_string().starts_with("leaf 7 is out of range for a tree of 7 leaves"));
+        assert_eq!(failure.point, None);
+    }
+
+    #[test]
+    fn test_mislabeled_order() {
+        let tree = example_tree(8);
+        let leaf = tree.leaves()[5].clone();
+        let proof = tree.prove_by_index(5).unwrap().into_owned();
+        // steps listed from the root down, read as if from the leaf up
+        let mut steps = proof.hashes().to_vec();
+        steps.reverse();
+        let mislabeled = OwnedProof::new(steps).with_tree_size(8);
+
+        let failure = tree.explain_proof(&leaf, &mislabeled.as_proof(), 5, &tree.root()).unwrap_err();
+        assert_eq!(failure.point, Some(FailurePoint::Order));
+        assert!(
+            failure.to_string().ends_with(
+                "\nthe steps do lead to the expected root in the opposite order; the proof is likely listed \
+                 root-to-leaf but declared leaf-to-root, or the other way round"
+            ),
+            "{}",
+            failure
+        );
+        let without_tree = MerkleTree::verify_proof_detailed(&leaf, &mislabeled.as_proof(), 5, 8, &tree.root());
+        assert_eq!(without_tree, Err(failure));
+        let relabeled = OwnedProof::from_ordered_steps(mislabeled.hashes().to_vec(), ProofOrder::RootToLeaf);
+        assert_eq!(tree.explain_proof(&leaf, &relabeled.with_tree_size(8).as_proof(), 5, &tree.root()), Ok(()));
+
+        // a tampered proof does not verify either way round
+        let failure = tree.explain_proof(&leaf, &tampered(&proof, 1).as_proof(), 5, &tree.root()).unwrap_err();
+        assert_eq!(failure.point, Some(FailurePoint::Step(1)));
+    }
+}
@@ -0,0 +1,194 @@
+use super::{hash_data, Data, Hash, MerkleTree, OwnedProof};
+use crate::util::error::LibError;
+
+/// A two-level commitment: one tree per shard plus a top tree over the shard roots
+///
+/// The shard roots are used as the top tree's leaf hashes directly, without hashing them again.
+#[derive(Clone)]
+pub struct MerkleForest {
+    shards: Vec<MerkleTree>,
+    top: MerkleTree,
+}
+
+/// Proof of a leaf within its shard, plus the proof of that shard's root within the top tree
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CompositeProof {
+    shard_index: usize,
+    leaf_index: usize,
+    leaf: OwnedProof,
+    shard: OwnedProof,
+}
+
+impl CompositeProof {
+    pub fn new(shard_index: usize, leaf_index: usize, leaf: OwnedProof, shard: OwnedProof) -> Self {
+        CompositeProof {
+            shard_index,
+            leaf_index,
+            leaf,
+            shard,
+        }
+    }
+
+    pub fn shard_index(&self) -> usize {
+        self.shard_index
+    }
+
+    pub fn leaf_index(&self) -> usize {
+        self.leaf_index
+    }
+
+    /// Proof of the leaf within its shard
+    pub fn leaf(&self) -> &OwnedProof {
+        &self.leaf
+    }
+
+    /// Proof of the shard root within the top tree
+    pub fn shard(&self) -> &OwnedProof {
+        &self.shard
+    }
+}
+
+impl MerkleForest {
+    /// Builds a forest from already constructed shard trees
+    ///
+    /// Panics if `shards` is empty.
+    pub fn new(shards: Vec<MerkleTree>) -> Self {
+        let top = MerkleTree::from_leaf_hashes(shards.iter().map(MerkleTree::root).collect());
+        MerkleForest { shards, top }
+    }
+
+    /// Builds one tree per shard of input data
+    ///
+    /// Panics if there are no shards or any shard is empty.
+    pub fn construct(shards: &[Vec<Data>]) -> Self {
+        MerkleForest::new(shards.iter().map(|d| MerkleTree::construct(d)).collect())
+    }
+
+    /// Gets the root hash of the top tree, committing to every shard
+    pub fn root(&self) -> Hash {
+        self.top.root()
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn shard(&self, index: usize) -> Option<&MerkleTree> {
+        self.shards.get(index)
+    }
+
+    /// The tree over the shard roots
+    pub fn top(&self) -> &MerkleTree {
+        &self.top
+    }
+
+    /// Replaces one shard, recomputing only the top tree
+    pub fn replace_shard(&mut self, index: usize, tree: MerkleTree) -> Result<(), LibError> {
+        let size = self.shards.len();
+        let shard = self
+            .shards
+            .get_mut(index)
+            .ok_or(LibError::IndexOutOfRange { index, size })?;
+        *shard = tree;
+        self.top = MerkleTree::from_leaf_hashes(self.shards.iter().map(MerkleTree::root).collect());
+        Ok(())
+    }
+
+    /// Rebuilds one shard from new data, leaving the other shard trees untouched
+    ///
+    /// Fails with `LibError::EmptyInput` if `data` is empty.
+    pub fn rebuild_shard(&mut self, index: usize, data: &[Data]) -> Result<(), LibError> {
+        if index >= self.shards.len() {
+            return Err(LibError::IndexOutOfRange {
+                index,
+                size: self.shards.len(),
+            });
+        }
+        if data.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        self.replace_shard(index, MerkleTree::construct(data))
+    }
+
+    /// Proves the leaf at `leaf_index` of shard `shard_index` against the forest root
+    pub fn prove(&self, shard_index: usize, leaf_index: usize) -> Result<CompositeProof, LibError> {
+        let shard = self.shards.get(shard_index).ok_or(LibError::IndexOutOfRange {
+            index: shard_index,
+            size: self.shards.len(),
+        })?;
+        Ok(CompositeProof::new(
+            shard_index,
+            leaf_index,
+            shard.prove_by_index(leaf_index)?.into_owned(),
+            self.top.prove_by_index(shard_index)?.into_owned(),
+        ))
+    }
+
+    /// Verifies that `data` is in a shard whose root is committed to by `forest_root`
+    pub fn verify_composite(data: &Data, proof: &CompositeProof, forest_root: &Hash) -> bool {
+        let shard_root = proof.leaf.as_proof().root_from(&hash_data(data));
+        MerkleTree::verify_proof_hash(&shard_root, &proof.shard.as_proof(), forest_root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shard_data(shard: usize, n: usize) -> Vec<Data> {
+        (0..n).map(|i| vec![shard as u8, i as u8]).collect()
+    }
+
+    fn example_forest() -> MerkleForest {
+        MerkleForest::construct(&[shard_data(0, 4), shard_data(1, 5), shard_data(2, 8), shard_data(3, 1)])
+    }
+
+    #[test]
+    fn test_composite_proofs() {
+        let forest = example_forest();
+        assert_eq!(forest.shard_count(), 4);
+        assert_eq!(forest.top().leaves()[1], forest.shard(1).unwrap().root());
+
+        for (shard, n) in [(0, 4), (1, 5), (2, 8), (3, 1)] {
+            for leaf in 0..n {
+                let proof = forest.prove(shard, leaf).unwrap();
+                assert!(MerkleForest::verify_composite(&vec![shard as u8, leaf as u8], &proof, &forest.root()));
+                assert!(!MerkleForest::verify_composite(&vec![9, 9], &proof, &forest.root()));
+            }
+        }
+
+        // a proof for the right leaf but claimed under the wrong shard does not verify
+        let mut proof = forest.prove(1, 2).unwrap();
+        proof.shard = forest.prove(2, 2).unwrap().shard;
+        assert!(!MerkleForest::verify_composite(&vec![1, 2], &proof, &forest.root()));
+
+        assert!(matches!(forest.prove(4, 0), Err(LibError::IndexOutOfRange { index: 4, size: 4 })));
+        assert!(matches!(forest.prove(3, 1), Err(LibError::IndexOutOfRange { index: 1, size: 1 })));
+    }
+
+    #[test]
+    fn test_rebuild_one_shard() {
+        let mut forest = example_forest();
+        let before: Vec<Hash> = (0..4).map(|i| forest.shard(i).unwrap().root()).collect();
+        let root_before = forest.root();
+
+        let mut data = shard_data(2, 8);
+        data[5] = vec![0xff];
+        forest.rebuild_shard(2, &data).unwrap();
+
+        for (i, root) in before.iter().enumerate() {
+            assert_eq!(forest.shard(i).unwrap().root() == *root, i != 2);
+        }
+        assert_ne!(forest.root(), root_before);
+
+        let proof = forest.prove(2, 5).unwrap();
+        assert!(MerkleForest::verify_composite(&vec![0xff], &proof, &forest.root()));
+        let old_proof = forest.prove(0, 0).unwrap();
+        assert!(!MerkleForest::verify_composite(&vec![0, 0], &old_proof, &root_before));
+
+        assert!(forest.rebuild_shard(7, &data).is_err());
+        assert!(matches!(forest.rebuild_shard(1, &[]), Err(LibError::EmptyInput)));
+        let shards = [shard_data(0, 4), shard_data(1, 5), data, shard_data(3, 1)];
+        assert_eq!(forest.root(), MerkleForest::construct(&shards).root());
+    }
+}
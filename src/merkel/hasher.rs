@@ -0,0 +1,567 @@
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+use std::io::{ErrorKind, Read};
+
+use super::{Hash, HASHER_ID};
+
+/// Bytes read at a time by `HashAlgorithm::leaf_hash_reader`
+const READ_CHUNK: usize = 64 * 1024;
+
+/// The two hash functions a tree is built from
+///
+/// `construct`, `prove` and `verify_proof` compute every leaf and node through these methods,
+/// so hashes computed outside the crate with the same hasher always agree with the tree.
+pub trait MerkleHasher {
+    /// Identifier recorded in serialized trees and proofs
+    fn id(&self) -> &'static str;
+
+    /// Length in bytes of every leaf and node hash
+    fn output_len(&self) -> usize;
+
+    /// Hash of a leaf's raw data
+    ///
+    /// ```
+    /// use merkle::merkel::{MerkleHasher, Sha256Hasher};
+    ///
+    /// let leaf = Sha256Hasher.leaf_hash(b"");
+    /// assert_eq!(hex::encode(leaf), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    /// ```
+    fn leaf_hash(&self, data: &[u8]) -> Hash;
+
+    /// Hash of a parent node from its left and right children
+    ///
+    /// ```
+    /// use merkle::merkel::{MerkleHasher, MerkleTree, Sha256Hasher};
+    ///
+    /// let (a, b) = (Sha256Hasher.leaf_hash(b"a"), Sha256Hasher.leaf_hash(b"b"));
+    /// let tree = MerkleTree::construct(&[b"a".to_vec(), b"b".to_vec()]);
+    /// assert_eq!(tree.root(), Sha256Hasher.node_hash(&a, &b));
+    /// ```
+    fn node_hash(&self, left: &[u8], right: &[u8]) -> Hash;
+}
+
+/// Plain SHA-256: `leaf = sha256(data)` and `node = sha256(left || right)`
+///
+/// Leaves and nodes are not domain-separated, so a 64-byte leaf can equal the concatenation of
+/// two child hashes; commit to leaves of another length, or encode them with `LeafEncoder`,
+/// when this matters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sha256Hasher;
+
+impl MerkleHasher for Sha256Hasher {
+    fn id(&self) -> &'static str {
+        HASHER_ID
+    }
+
+    fn output_len(&self) -> usize {
+        32
+    }
+
+    fn leaf_hash(&self, data: &[u8]) -> Hash {
+        sha2::Sha256::digest(data).to_vec()
+    }
+
+    fn node_hash(&self, left: &[u8], right: &[u8]) -> Hash {
+        digest_pair::<sha2::Sha256>(left, right)
+    }
+}
+
+/// SHA-512 with 64-byte hashes, combined like `Sha256Hasher`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sha512Hasher;
+
+impl MerkleHasher for Sha512Hasher {
+    fn id(&self) -> &'static str {
+        "sha512"
+    }
+
+    fn output_len(&self) -> usize {
+        64
+    }
+
+    fn leaf_hash(&self, data: &[u8]) -> Hash {
+        sha2::Sha512::digest(data).to_vec()
+    }
+
+    fn node_hash(&self, left: &[u8], right: &[u8]) -> Hash {
+        digest_pair::<sha2::Sha512>(left, right)
+    }
+}
+
+/// RIPEMD-160 with 20-byte hashes, combined like `Sha256Hasher`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ripemd160Hasher;
+
+impl MerkleHasher for Ripemd160Hasher {
+    fn id(&self) -> &'static str {
+        "ripemd160"
+    }
+
+    fn output_len(&self) -> usize {
+        20
+    }
+
+    fn leaf_hash(&self, data: &[u8]) -> Hash {
+        ripemd::Ripemd160::digest(data).to_vec()
+    }
+
+    fn node_hash(&self, left: &[u8], right: &[u8]) -> Hash {
+        digest_pair::<ripemd::Ripemd160>(left, right)
+    }
+}
+
+/// SHA-256 domain-separated as in RFC 6962 and Tendermint: `leaf = sha256(0x00 || data)` and
+/// `node = sha256(0x01 || left || right)`
+///
+/// Those ecosystems split trees with `TreeShape::LeftBalanced`; together the two reproduce their
+/// roots and audit paths.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rfc6962Hasher;
+
+impl MerkleHasher for Rfc6962Hasher {
+    fn id(&self) -> &'static str {
+        "sha256-rfc6962"
+    }
+
+    fn output_len(&self) -> usize {
+        32
+    }
+
+    fn leaf_hash(&self, data: &[u8]) -> Hash {
+        sha2::Sha256::new().chain_update([0x00]).chain_update(data).finalize().to_vec()
+    }
+
+    fn node_hash(&self, left: &[u8], right: &[u8]) -> Hash {
+        sha2::Sha256::new().chain_update([0x01]).chain_update(left).chain_update(right).finalize().to_vec()
+    }
+}
+
+/// Double SHA-256 as Bitcoin computes it: `leaf = sha256(sha256(data))` and
+/// `node = sha256(sha256(left || right))`
+///
+/// Bitcoin commits to txids, which are already leaf hashes, so its trees are built in
+/// `LeafMode::PreHashed` from txids in internal byte order, the reverse of how they are displayed.
+/// Odd levels are padded with `OddLeafPolicy::Duplicate`, and a block of one transaction has its
+/// txid as the root.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sha256dHasher;
+
+impl MerkleHasher for Sha256dHasher {
+    fn id(&self) -> &'static str {
+        "sha256d"
+    }
+
+    fn output_len(&self) -> usize {
+        32
+    }
+
+    fn leaf_hash(&self, data: &[u8]) -> Hash {
+        sha2::Sha256::digest(sha2::Sha256::digest(data)).to_vec()
+    }
+
+    fn node_hash(&self, left: &[u8], right: &[u8]) -> Hash {
+        sha2::Sha256::digest(digest_pair::<sha2::Sha256>(left, right)).to_vec()
+    }
+}
+
+/// A cheap, insecure stand-in for a real hasher, for tests of tree structure at large scale
+///
+/// Hashes are 32 bytes: each 8-byte word of the input is xored into one of four lanes, which is
+/// then multiplied and rotated, and the lanes are mixed with each other at the end. Leaves and
+/// nodes start from different lanes, and the order of the words matters, so swapped children give
+/// another hash. Collisions and preimages are easy to construct, so it is only built for this
+/// crate's tests and with the `test-utils` feature, for tests of padding, indexing and proof shapes
+/// over millions of leaves where SHA-256 would take most of the time.
+#[cfg(any(test, feature = "test-utils"))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XorFoldHasher;
+
+#[cfg(any(test, feature = "test-utils"))]
+impl MerkleHasher for XorFoldHasher {
+    fn id(&self) -> &'static str {
+        "xor-fold-insecure"
+    }
+
+    fn output_len(&self) -> usize {
+        32
+    }
+
+    fn leaf_hash(&self, data: &[u8]) -> Hash {
+        xor_fold(0, &[data])
+    }
+
+    fn node_hash(&self, left: &[u8], right: &[u8]) -> Hash {
+        xor_fold(1, &[left, right])
+    }
+}
+
+/// Odd multipliers of the lanes of `XorFoldHasher`, so multiplying by them loses nothing
+#[cfg(any(test, feature = "test-utils"))]
+const XOR_FOLD_MIX: [u64; 4] =
+    [0x9e37_79b9_7f4a_7c15, 0xbf58_476d_1ce4_e5b9, 0x94d0_49bb_1331_11eb, 0xd6e8_feb8_6659_fd93];
+
+#[cfg(any(test, feature = "test-utils"))]
+fn xor_fold(domain: u64, parts: &[&[u8]]) -> Hash {
+    let mut lanes = XOR_FOLD_MIX;
+    lanes[0] ^= domain;
+    let (mut lane, mut len) = (0, 0);
+    for part in parts {
+        for chunk in part.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            lanes[lane] = (lanes[lane] ^ u64::from_le_bytes(word)).wrapping_mul(XOR_FOLD_MIX[lane]).rotate_left(29);
+            lane = (lane + 1) & 3;
+        }
+        len += part.len();
+    }
+    // the length tells trailing zeros from the padding of the last word
+    lanes[0] ^= len as u64;
+    for round in 0..8 {
+        let (lane, previous) = (round & 3, (round + 3) & 3);
+        lanes[lane] ^= lanes[previous].rotate_left(17).wrapping_mul(XOR_FOLD_MIX[lane]);
+    }
+    let mut hash = Vec::with_capacity(32);
+    for lane in lanes {
+        hash.extend_from_slice(&lane.to_le_bytes());
+    }
+    hash
+}
+
+fn digest_pair<D: Digest>(left: &[u8], right: &[u8]) -> Hash {
+    let mut hasher = D::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+fn digest_pair_into<D: Digest>(left: &[u8], right: &[u8], out: &mut [u8]) {
+    out.copy_from_slice(&D::new().chain_update(left).chain_update(right).finalize());
+}
+
+/// Feeds everything `reader` yields to `digest`, returning the digest and the number of bytes read
+fn digest_reader<D: Digest, R: Read>(mut digest: D, mut reader: R) -> std::io::Result<(Hash, u64)> {
+    let (mut chunk, mut len) = (vec![0; READ_CHUNK], 0u64);
+    loop {
+        match reader.read(&mut chunk) {
+            Ok(0) => return Ok((digest.finalize().to_vec(), len)),
+            Ok(n) => {
+                digest.update(&chunk[..n]);
+                len += n as u64;
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The hasher a tree is built with, see `TreeConfig::with_hasher`
+///
+/// Serialized by its `MerkleHasher::id`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    Ripemd160,
+    Sha256Rfc6962,
+    Sha256d,
+    /// `XorFoldHasher`, which is insecure; it is left out of `ALL`, so `from_id` and the command
+    /// line do not know it
+    #[cfg(any(test, feature = "test-utils"))]
+    #[serde(rename = "xor-fold-insecure")]
+    XorFold,
+}
+
+impl HashAlgorithm {
+    /// Every supported hasher, the default first
+    pub const ALL: [HashAlgorithm; 5] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha512,
+        HashAlgorithm::Ripemd160,
+        HashAlgorithm::Sha256Rfc6962,
+        HashAlgorithm::Sha256d,
+    ];
+
+    /// The hasher with the given `MerkleHasher::id`
+    pub fn from_id(id: &str) -> Option<HashAlgorithm> {
+        HashAlgorithm::ALL.into_iter().find(|hasher| hasher.id() == id)
+    }
+
+    /// Like `MerkleHasher::leaf_hash`, writing the hash to `out` instead of allocating it
+    ///
+    /// `out` must be `output_len` bytes long.
+    pub(crate) fn leaf_hash_into(self, data: &[u8], out: &mut [u8]) {
+        match self {
+            HashAlgorithm::Sha256 => out.copy_from_slice(&sha2::Sha256::digest(data)),
+            HashAlgorithm::Sha512 => out.copy_from_slice(&sha2::Sha512::digest(data)),
+            HashAlgorithm::Ripemd160 => out.copy_from_slice(&ripemd::Ripemd160::digest(data)),
+            HashAlgorithm::Sha256Rfc6962 => {
+                out.copy_from_slice(&sha2::Sha256::new().chain_update([0x00]).chain_update(data).finalize())
+            }
+            HashAlgorithm::Sha256d => out.copy_from_slice(&sha2::Sha256::digest(sha2::Sha256::digest(data))),
+            #[cfg(any(test, feature = "test-utils"))]
+            HashAlgorithm::XorFold => out.copy_from_slice(&xor_fold(0, &[data])),
+        }
+    }
+
+    /// Like `MerkleHasher::leaf_hash` of everything `reader` yields, which is read in chunks
+    /// rather than whole, returned with the number of bytes read
+    pub(crate) fn leaf_hash_reader<R: Read>(self, reader: R) -> std::io::Result<(Hash, u64)> {
+        match self {
+            HashAlgorithm::Sha256 => digest_reader(sha2::Sha256::new(), reader),
+            HashAlgorithm::Sha512 => digest_reader(sha2::Sha512::new(), reader),
+            HashAlgorithm::Ripemd160 => digest_reader(ripemd::Ripemd160::new(), reader),
+            HashAlgorithm::Sha256Rfc6962 => digest_reader(sha2::Sha256::new().chain_update([0x00]), reader),
+            HashAlgorithm::Sha256d => {
+                let (inner, len) = digest_reader(sha2::Sha256::new(), reader)?;
+                Ok((sha2::Sha256::digest(inner).to_vec(), len))
+            }
+            #[cfg(any(test, feature = "test-utils"))]
+            HashAlgorithm::XorFold => {
+                let mut data = Vec::new();
+                let len = { reader }.read_to_end(&mut data)?;
+                Ok((xor_fold(0, &[&data]), len as u64))
+            }
+        }
+    }
+
+    /// Like `MerkleHasher::node_hash`, writing the hash to `out` instead of allocating it
+    pub(crate) fn node_hash_into(self, left: &[u8], right: &[u8], out: &mut [u8]) {
+        match self {
+            HashAlgorithm::Sha256 => digest_pair_into::<sha2::Sha256>(left, right, out),
+            HashAlgorithm::Sha512 => digest_pair_into::<sha2::Sha512>(left, right, out),
+            HashAlgorithm::Ripemd160 => digest_pair_into::<ripemd::Ripemd160>(left, right, out),
+            HashAlgorithm::Sha256Rfc6962 => out.copy_from_slice(
+                &sha2::Sha256::new().chain_update([0x01]).chain_update(left).chain_update(right).finalize(),
+            ),
+            HashAlgorithm::Sha256d => {
+                let inner = sha2::Sha256::new().chain_update(left).chain_update(right).finalize();
+                out.copy_from_slice(&sha2::Sha256::digest(inner))
+            }
+            #[cfg(any(test, feature = "test-utils"))]
+            HashAlgorithm::XorFold => out.copy_from_slice(&xor_fold(1, &[left, right])),
+        }
+    }
+
+    fn hasher(self) -> &'static dyn MerkleHasher {
+        match self {
+            HashAlgorithm::Sha256 => &Sha256Hasher,
+            HashAlgorithm::Sha512 => &Sha512Hasher,
+            HashAlgorithm::Ripemd160 => &Ripemd160Hasher,
+            HashAlgorithm::Sha256Rfc6962 => &Rfc6962Hasher,
+            HashAlgorithm::Sha256d => &Sha256dHasher,
+            #[cfg(any(test, feature = "test-utils"))]
+            HashAlgorithm::XorFold => &XorFoldHasher,
+        }
+    }
+}
+
+impl MerkleHasher for HashAlgorithm {
+    fn id(&self) -> &'static str {
+        self.hasher().id()
+    }
+
+    fn output_len(&self) -> usize {
+        self.hasher().output_len()
+    }
+
+    fn leaf_hash(&self, data: &[u8]) -> Hash {
+        self.hasher().leaf_hash(data)
+    }
+
+    fn node_hash(&self, left: &[u8], right: &[u8]) -> Hash {
+        self.hasher().node_hash(left, right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{Data, LeafMode, MerkleTree, OwnedProof, TreeConfig};
+
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_sha256_vectors() {
+        let hasher = Sha256Hasher;
+        assert_eq!((hasher.id(), hasher.output_len()), ("sha256", 32));
+        assert_eq!(
+            hex::encode(hasher.leaf_hash(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex::encode(hasher.node_hash(&[0; 32], &[0; 32])),
+            "f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a92759fb4b"
+        );
+    }
+
+    #[test]
+    fn test_rfc6962_vectors() {
+        let hasher = Rfc6962Hasher;
+        assert_eq!(HashAlgorithm::from_id(hasher.id()), Some(HashAlgorithm::Sha256Rfc6962));
+        assert_eq!(serde_json::to_value(HashAlgorithm::Sha256Rfc6962).unwrap(), hasher.id());
+        let empty = hasher.leaf_hash(b"");
+        assert_eq!(hex::encode(&empty), "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d");
+        // the leaf prefix keeps a leaf from passing for the node over its two halves
+        assert_ne!(hasher.node_hash(&empty, &empty), Sha256Hasher.node_hash(&empty, &empty));
+        assert_ne!(hasher.leaf_hash(&[empty.clone(), empty.clone()].concat()), hasher.node_hash(&empty, &empty));
+    }
+
+    #[test]
+    fn test_sha256d_matches_bitcoin() {
+        let hasher = Sha256dHasher;
+        assert_eq!(HashAlgorithm::from_id(hasher.id()), Some(HashAlgorithm::Sha256d));
+        assert_eq!(serde_json::to_value(HashAlgorithm::Sha256d).unwrap(), "sha256d");
+        assert_eq!(hasher.leaf_hash(b"abc"), Sha256Hasher.leaf_hash(&Sha256Hasher.leaf_hash(b"abc")));
+
+        // the txids and merkle root of block 100000, in display order
+        let txids = [
+            "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+            "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+            "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+            "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+        ];
+        let internal = |hex: &str| hex::decode(hex).unwrap().into_iter().rev().collect::<Hash>();
+        let leaves: Vec<Hash> = txids.iter().map(|txid| internal(txid)).collect();
+        let config = TreeConfig::new(LeafMode::PreHashed).with_hasher(HashAlgorithm::Sha256d);
+        let root = config.construct(&leaves).unwrap().root();
+        assert_eq!(root, internal("f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766"));
+
+        // a block of one transaction has its txid as the root
+        assert_eq!(config.construct(&leaves[..1]).unwrap().root(), leaves[0]);
+    }
+
+    #[test]
+    fn test_other_width_vectors() {
+        let data: Vec<Data> = (0..4u8).map(|i| vec![i]).collect();
+        let cases = [
+            (
+                HashAlgorithm::Sha512,
+                64,
+                "c428a2175038ee608a034e45ccdca62e18ee3838dfa811103f095017672d8a5c\
+                 5ed4864aa617bbac511fd9cf831dac143bf0b774a474e0f2b7615e4e299b9965",
+            ),
+            (HashAlgorithm::Ripemd160, 20, "ccea70dd43925d78dfe05ed23761ebca4828f501"),
+        ];
+        for (hasher, len, root) in cases {
+            assert_eq!(HashAlgorithm::from_id(hasher.id()), Some(hasher));
+            assert_eq!(hasher.output_len(), len);
+            let tree = TreeConfig::default().with_hasher(hasher).construct(&data).unwrap();
+            assert_eq!((tree.hasher(), hex::encode(tree.root())), (hasher, root.to_string()));
+            assert!(tree.levels().iter().flatten().all(|hash| hash.len() == len));
+        }
+        assert_eq!(hex::encode(Ripemd160Hasher.leaf_hash(b"")), "9c1185a5c5e9fc54612808977ee8f548b2258d31");
+        assert_eq!(HashAlgorithm::from_id("keccak256"), None);
+    }
+
+    #[test]
+    fn test_proofs_round_trip_across_widths() {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i; 3]).collect();
+        for hasher in HashAlgorithm::ALL {
+            let tree = TreeConfig::default().with_hasher(hasher).construct(&data).unwrap();
+            let leaves = tree.leaves().to_vec();
+            assert_eq!(tree.prove(&data[2]).unwrap().into_owned(), tree.prove_by_index(2).unwrap().into_owned());
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                let json = serde_json::to_string(&tree.prove_by_index(index).unwrap().into_owned()).unwrap();
+                let proof: OwnedProof = serde_json::from_str(&json).unwrap();
+                assert!(MerkleTree::verify_proof_hash_with(&hasher, leaf, &proof.as_proof(), &tree.root()));
+                for other in HashAlgorithm::ALL.into_iter().filter(|other| *other != hasher) {
+                    assert!(!MerkleTree::verify_proof_hash_with(&other, leaf, &proof.as_proof(), &tree.root()));
+                }
+            }
+
+            // pre-hashed leaves must be of the hasher's width
+            let pre_hashed = TreeConfig::new(LeafMode::PreHashed).with_hasher(hasher);
+            assert_eq!(pre_hashed.construct(&leaves).unwrap().root(), tree.root());
+            let other = HashAlgorithm::ALL.into_iter().find(|other| other.output_len() != hasher.output_len()).unwrap();
+            let other_leaves = TreeConfig::default().with_hasher(other).construct(&data).unwrap().leaves().to_vec();
+            assert!(matches!(
+                pre_hashed.construct(&other_leaves),
+                Err(crate::util::error::LibError::InvalidLeafLength { index: 0, .. })
+            ));
+        }
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_proofs_round_trip_across_widths() {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i; 3]).collect();
+        for hasher in HashAlgorithm::ALL {
+            let tree = TreeConfig::default().with_hasher(hasher).construct(&data).unwrap();
+            for (index, leaf) in tree.leaves().iter().enumerate() {
+                let proof = tree.prove_by_index(index).unwrap().into_owned().with_hasher(tree.hasher());
+                let decoded = OwnedProof::from_cbor(&proof.to_cbor().unwrap()).unwrap();
+                assert_eq!((decoded.hasher(), &decoded), (Some(hasher), &proof));
+                assert!(MerkleTree::verify_proof_hash_with(&hasher, leaf, &decoded.as_proof(), &tree.root()));
+            }
+
+            // a proof tagged with a hasher of another width does not decode
+            let other = HashAlgorithm::ALL.into_iter().find(|other| other.output_len() != hasher.output_len()).unwrap();
+            let mislabeled = tree.prove_by_index(0).unwrap().into_owned().with_hasher(other).to_cbor().unwrap();
+            assert!(matches!(
+                OwnedProof::from_cbor(&mislabeled),
+                Err(crate::util::error::LibError::InvalidHashLength { expected, found })
+                    if (expected, found) == (other.output_len(), hasher.output_len())
+            ));
+        }
+    }
+
+    #[test]
+    fn test_external_root_matches_construct() {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i; 3]).collect();
+        let hasher = Sha256Hasher;
+
+        // duplicate the lone last node of every odd level, like the tree does
+        let mut level: Vec<Hash> = data.iter().map(|d| hasher.leaf_hash(d)).collect();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| hasher.node_hash(&pair[0], pair.last().unwrap()))
+                .collect();
+        }
+        let tree = MerkleTree::construct(&data);
+        assert_eq!(level[0], tree.root());
+
+        let proof = tree.prove(&data[6]).unwrap();
+        assert_eq!(proof.root_from(&hasher.leaf_hash(&data[6])), tree.root());
+    }
+
+    #[test]
+    fn test_xor_fold_tells_inputs_apart() {
+        let hasher = XorFoldHasher;
+        assert_eq!((hasher.id(), hasher.output_len()), ("xor-fold-insecure", 32));
+        assert_eq!(HashAlgorithm::from_id(hasher.id()), None);
+        assert!(!HashAlgorithm::ALL.contains(&HashAlgorithm::XorFold));
+        assert_eq!(serde_json::to_value(HashAlgorithm::XorFold).unwrap(), hasher.id());
+
+        // trailing zeros, leaves spelled as nodes and swapped children all give other hashes
+        let inputs: Vec<Data> = vec![vec![], vec![0], vec![0; 8], vec![0; 9], vec![1], vec![0, 1], vec![1, 0]];
+        let mut hashes: Vec<Hash> = inputs.iter().map(|input| hasher.leaf_hash(input)).collect();
+        let (a, b) = (hasher.leaf_hash(b"a"), hasher.leaf_hash(b"b"));
+        hashes.extend([hasher.node_hash(&a, &b), hasher.node_hash(&b, &a), hasher.leaf_hash(&[a, b].concat())]);
+        let count = hashes.len();
+        hashes.sort();
+        hashes.dedup();
+        assert_eq!(hashes.len(), count);
+        let distinct: HashSet<Hash> = (0..100_000u32).map(|i| hasher.leaf_hash(&i.to_le_bytes())).collect();
+        assert_eq!(distinct.len(), 100_000);
+
+        // the tree hashes through `HashAlgorithm`, whose buffered paths must agree with it
+        let data: Vec<Data> = (0..13u8).map(|i| vec![i; i as usize]).collect();
+        let config = TreeConfig::default().with_hasher(HashAlgorithm::XorFold);
+        let tree = config.construct(&data).unwrap();
+        let mut level: Vec<Hash> = data.iter().map(|d| hasher.leaf_hash(d)).collect();
+        while level.len() > 1 {
+            level = level.chunks(2).map(|pair| hasher.node_hash(&pair[0], pair.last().unwrap())).collect();
+        }
+        assert_eq!(tree.root(), level[0]);
+        let mut out = vec![0; 32];
+        HashAlgorithm::XorFold.leaf_hash_into(&data[5], &mut out);
+        assert_eq!(out, tree.leaves()[5]);
+        assert_eq!(HashAlgorithm::XorFold.leaf_hash_reader(&data[12][..]).unwrap(), (tree.leaves()[12].clone(), 12));
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+use std::num::TryFromIntError;
+
+use super::tree_depth;
+
+/// Position of a leaf in its tree, from 0 at the left
+///
+/// APIs that take a leaf index and a tree size accept `impl Into<LeafIndex>` and
+/// `impl Into<TreeSize>`, so a bare `usize` still works for either, but a `TreeSize` passed as the
+/// index does not compile. The same arithmetic applies to the nodes of any level, with the node
+/// count of that level as the size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LeafIndex(pub u64);
+
+/// Number of leaves of a tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TreeSize(pub u64);
+
+impl LeafIndex {
+    /// The node paired with this one at its level, whether or not the tree is wide enough to have it
+    pub fn sibling(self) -> LeafIndex {
+        LeafIndex(self.0 ^ 1)
+    }
+
+    /// Like `sibling`, None for the lone last node of an odd-sized level
+    pub fn sibling_in(self, size: TreeSize) -> Option<LeafIndex> {
+        Some(self.sibling()).filter(|&sibling| size.contains(sibling))
+    }
+
+    /// Index of the parent in the level above
+    pub fn parent(self) -> LeafIndex {
+        LeafIndex(self.0 / 2)
+    }
+
+    /// Whether this node is the left child of its parent
+    pub fn is_left(self) -> bool {
+        self.0 & 1 == 0
+    }
+
+    /// The index as a `usize`, saturated on platforms it does not fit, where it is past any tree
+    pub(crate) fn saturating_usize(self) -> usize {
+        usize::try_from(self.0).unwrap_or(usize::MAX)
+    }
+}
+
+impl TreeSize {
+    /// Number of levels above the leaves, the length of every proof under `OddLeafPolicy::Duplicate`
+    pub fn depth(self) -> usize {
+        match usize::try_from(self.0) {
+            Ok(size) => tree_depth(size),
+            Err(_) => (u64::BITS - (self.0 - 1).leading_zeros()) as usize,
+        }
+    }
+
+    /// Number of nodes in the level above a level of this many nodes
+    pub fn parent(self) -> TreeSize {
+        TreeSize(self.0.div_ceil(2))
+    }
+
+    pub fn contains(self, index: LeafIndex) -> bool {
+        index.0 < self.0
+    }
+
+    /// Index of the last leaf, None for an empty tree
+    pub fn last(self) -> Option<LeafIndex> {
+        self.0.checked_sub(1).map(LeafIndex)
+    }
+
+    /// The size as a `usize`, saturated on platforms it does not fit
+    pub(crate) fn saturating_usize(self) -> usize {
+        usize::try_from(self.0).unwrap_or(usize::MAX)
+    }
+}
+
+impl fmt::Display for LeafIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for TreeSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// only from `usize`, so an untyped integer literal still passes as an `impl Into<LeafIndex>`
+impl From<usize> for LeafIndex {
+    fn from(index: usize) -> Self {
+        LeafIndex(index as u64)
+    }
+}
+
+impl From<usize> for TreeSize {
+    fn from(size: usize) -> Self {
+        TreeSize(size as u64)
+    }
+}
+
+impl From<LeafIndex> for u64 {
+    fn from(index: LeafIndex) -> Self {
+        index.0
+    }
+}
+
+impl From<TreeSize> for u64 {
+    fn from(size: TreeSize) -> Self {
+        size.0
+    }
+}
+
+impl TryFrom<LeafIndex> for usize {
+    type Error = TryFromIntError;
+
+    fn try_from(index: LeafIndex) -> Result<Self, Self::Error> {
+        usize::try_from(index.0)
+    }
+}
+
+impl TryFrom<TreeSize> for usize {
+    type Error = TryFromIntError;
+
+    fn try_from(size: TreeSize) -> Result<Self, Self::Error> {
+        usize::try_from(size.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::MerkleTree;
+
+    #[test]
+    fn test_depth_and_levels() {
+        let depths = [(1, 0), (2, 1), (3, 2), (4, 2), (5, 3), (7, 3), (8, 3), (9, 4), (1000, 10), (1024, 10)];
+        for (size, depth) in depths {
+            assert_eq!(TreeSize(size).depth(), depth, "{} leaves", size);
+        }
+        assert_eq!((TreeSize(0).depth(), TreeSize(u64::MAX).depth()), (0, 64));
+
+        // the level sizes of a tree of 11 leaves
+        let mut size = TreeSize(11);
+        let mut sizes = vec![size];
+        while size.0 > 1 {
+            size = size.parent();
+            sizes.push(size);
+        }
+        assert_eq!(sizes, [11, 6, 3, 2, 1].map(TreeSize));
+        assert_eq!(sizes.len() - 1, TreeSize(11).depth());
+        assert_eq!((TreeSize(11).last(), TreeSize(0).last()), (Some(LeafIndex(10)), None));
+    }
+
+    #[test]
+    fn test_siblings_and_parents_match_the_tree() {
+        for n in [1usize, 2, 5, 6, 7, 11, 13] {
+            let data: Vec<Vec<u8>> = (0..n as u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::construct(&data);
+            for leaf in 0..n {
+                // walk from every leaf to the root, so every node is visited
+                let mut id = tree.leaf_id(leaf).unwrap();
+                let (mut index, mut size) = (LeafIndex::from(leaf), TreeSize::from(n));
+                while let Some(parent) = tree.parent(id) {
+                    // the lone last node of an odd level is its own sibling in the tree
+                    let sibling = tree.sibling(id).map(|sibling| LeafIndex::from(sibling.index()));
+                    assert_eq!(sibling, Some(index.sibling_in(size).unwrap_or(index)), "{:?} of {} leaves", id, n);
+                    (id, index, size) = (parent, index.parent(), size.parent());
+                    assert_eq!(LeafIndex::from(id.index()), index);
+                    assert_eq!(TreeSize::from(tree.levels()[id.level()].len()), size);
+                }
+                assert_eq!((id, size), (tree.root_id(), TreeSize(1)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_right_edge() {
+        let size = TreeSize(7);
+        assert_eq!((LeafIndex(6).sibling(), LeafIndex(6).sibling_in(size)), (LeafIndex(7), None));
+        assert_eq!(LeafIndex(5).sibling_in(size), Some(LeafIndex(4)));
+        assert!(LeafIndex(6).is_left() && !LeafIndex(5).is_left());
+        assert!(size.contains(LeafIndex(6)) && !size.contains(LeafIndex(7)));
+
+        // the path of the last leaf of 13 is lone at levels 0 and 1, and paired from level 2
+        let (mut index, mut size, mut lone) = (LeafIndex(12), TreeSize(13), Vec::new());
+        while size.0 > 1 {
+            lone.push(index.sibling_in(size).is_none());
+            (index, size) = (index.parent(), size.parent());
+        }
+        assert_eq!(lone, [true, true, false, false]);
+        assert_eq!(index, LeafIndex(0));
+    }
+
+    #[test]
+    fn test_conversions() {
+        let index: LeafIndex = 12usize.into();
+        assert_eq!((index, u64::from(index), usize::try_from(index)), (LeafIndex(12), 12, Ok(12)));
+        assert_eq!((index.to_string(), TreeSize(13).to_string()), ("12".to_string(), "13".to_string()));
+        assert_eq!(TreeSize::from(13usize), TreeSize(13));
+        assert!(LeafIndex(3) < LeafIndex(4));
+    }
+}
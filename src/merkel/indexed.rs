@@ -0,0 +1,249 @@
+use super::{
+    check_hash_lens, expected_proof_len, is_lone_on_path, tree_depth, Hash, HashDirection, LeafIndex, OddLeafPolicy,
+    OwnedProof, ProofOrder, TreeSize,
+};
+use crate::util::error::LibError;
+
+/// A proof as the index of its leaf, the size of its tree and the sibling hashes, without directions
+///
+/// In a tree built with `OddLeafPolicy::Duplicate` the direction of every step follows from the
+/// position of the path at that level: a left sibling for an odd node, a virtual
+/// `HashDirection::Duplicate` sibling for the lone last node of an odd-sized level, and a right
+/// sibling otherwise. The hashes of virtual siblings are kept, so nothing is lost either way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IndexedProof {
+    index: usize,
+    tree_size: usize,
+    hashes: Vec<Hash>,
+}
+
+impl IndexedProof {
+    /// Fails with `LibError::IndexOutOfRange` for an index past the tree,
+    /// `LibError::InvalidFormat` unless there is one hash per level of the tree and
+    /// `LibError::InvalidHashLength` for a hash longer than `MAX_HASH_LEN`
+    pub fn new(
+        index: impl Into<LeafIndex>,
+        tree_size: impl Into<TreeSize>,
+        hashes: Vec<Hash>,
+    ) -> Result<Self, LibError> {
+        let (index, tree_size) = (index.into().saturating_usize(), tree_size.into().saturating_usize());
+        if index >= tree_size {
+            return Err(LibError::IndexOutOfRange { index, size: tree_size });
+        }
+        let depth = tree_depth(tree_size);
+        if hashes.len() != depth {
+            return Err(LibError::InvalidFormat(format!(
+                "a proof in a tree of {} leaves has {} hashes, found {}",
+                tree_size,
+                depth,
+                hashes.len()
+            )));
+        }
+        check_hash_lens(hashes.iter())?;
+        Ok(IndexedProof { index, tree_size, hashes })
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn tree_size(&self) -> usize {
+        self.tree_size
+    }
+
+    /// The sibling hashes, ordered from the leaf up to the root
+    pub fn hashes(&self) -> &[Hash] {
+        &self.hashes
+    }
+
+    /// The proof with its directions spelled out, tagged with the tree size
+    pub fn to_proof(&self) -> OwnedProof {
+        let hashes = self
+            .hashes
+            .iter()
+            .enumerate()
+            .map(|(level, hash)| (direction_at(self.tree_size, self.index, level), hash.clone()))
+            .collect();
+        OwnedProof::new(hashes).with_tree_size(self.tree_size)
+    }
+}
+
+impl OwnedProof {
+    /// The proof in indexed form, see `IndexedProof`
+    ///
+    /// The proof must be tagged with its tree size and shaped like a proof from a tree built with
+    /// `OddLeafPolicy::Duplicate`, failing with `LibError::InvalidFormat` otherwise. Lone nodes
+    /// marked `Right`, as in proofs before format version 2, are read as `Duplicate`.
+    pub fn to_indexed(&self) -> Result<IndexedProof, LibError> {
+        let tree_size = self
+            .tree_size()
+            .ok_or_else(|| LibError::InvalidFormat("the proof is not tagged with its tree size".into()))?;
+        let index = self.as_proof().implied_index()?;
+        let hashes = self.hashes().iter().map(|(_, hash)| hash.to_vec()).collect();
+        let indexed = IndexedProof::new(index, tree_size, hashes)?;
+        for (level, (direction, _)) in self.hashes().iter().enumerate() {
+            let expected = direction_at(tree_size, index, level);
+            let legacy = expected == HashDirection::Duplicate && *direction == HashDirection::Right;
+            if *direction != expected && !legacy {
+                return Err(LibError::InvalidFormat(format!(
+                    "step {} of the proof of leaf {} is {:?}, not {:?}",
+                    level + 1,
+                    index,
+                    direction,
+                    expected
+                )));
+            }
+        }
+        Ok(indexed)
+    }
+
+    /// A proof of a `TreeShape::LeftBalanced` tree as RFC 6962 and Tendermint send it: the index of
+    /// the leaf, the size of the tree and the sibling hashes from the leaf up, without directions
+    ///
+    /// Levels where the path passes through a lone last node have no sibling. The path is listed
+    /// from the leaf up, so the proof is declared `ProofOrder::LeafToRoot`. Fails with
+    /// `LibError::IndexOutOfRange` for an index past the tree, `LibError::InvalidFormat` unless
+    /// there is one hash per remaining level and `LibError::InvalidHashLength` for a hash longer
+    /// than `MAX_HASH_LEN`.
+    pub fn from_audit_path(
+        index: impl Into<LeafIndex>,
+        tree_size: impl Into<TreeSize>,
+        hashes: Vec<Hash>,
+    ) -> Result<OwnedProof, LibError> {
+        let (index, tree_size) = (index.into().saturating_usize(), tree_size.into().saturating_usize());
+        if index >= tree_size {
+            return Err(LibError::IndexOutOfRange { index, size: tree_size });
+        }
+        let expected = expected_proof_len(tree_size, index, OddLeafPolicy::Promote);
+        if hashes.len() != expected {
+            return Err(LibError::InvalidFormat(format!(
+                "the audit path of leaf {} in a left-balanced tree of {} leaves has {} hashes, found {}",
+                index,
+                tree_size,
+                expected,
+                hashes.len()
+            )));
+        }
+        check_hash_lens(hashes.iter())?;
+        let steps = left_balanced_directions(tree_size, index).into_iter().zip(hashes).collect();
+        Ok(OwnedProof::from_ordered_steps(steps, ProofOrder::LeafToRoot).with_tree_size(tree_size))
+    }
+}
+
+/// Directions of the steps of the proof of leaf `index` in a `TreeShape::LeftBalanced` tree,
+/// from the leaf up, with no step at the levels where the path passes through a lone last node
+pub(crate) fn left_balanced_directions(tree_size: usize, index: usize) -> Vec<HashDirection> {
+    let (mut len, mut node, mut directions) = (tree_size, index, vec![]);
+    while len > 1 {
+        if !(len % 2 == 1 && node == len - 1) {
+            directions.push(if node % 2 == 1 { HashDirection::Left } else { HashDirection::Right });
+        }
+        len = len.div_ceil(2);
+        node /= 2;
+    }
+    directions
+}
+
+/// Direction of the step at `level` of the proof of leaf `index`
+fn direction_at(tree_size: usize, index: usize, level: usize) -> HashDirection {
+    if (index >> level) & 1 == 1 {
+        HashDirection::Left
+    } else if is_lone_on_path(tree_size, index, level) {
+        HashDirection::Duplicate
+    } else {
+        HashDirection::Right
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{Data, MerkleTree, TreeConfig, TreeShape};
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| vec![i as u8]).collect()
+    }
+
+    #[test]
+    fn test_every_edge_round_trips() {
+        for size in [3, 5, 6, 7] {
+            let data = example_data(size);
+            let tree = MerkleTree::construct(&data);
+            for (index, leaf) in data.iter().enumerate() {
+                let proof = tree.prove_by_index(index).unwrap().into_owned();
+                for (level, (direction, hash)) in proof.hashes().iter().enumerate() {
+                    // a virtual sibling is a copy of the node on the path itself
+                    let lone = is_lone_on_path(size, index, level);
+                    assert_eq!(*direction == HashDirection::Duplicate, lone, "size {} leaf {}", size, index);
+                    if lone {
+                        assert_eq!(Some(hash.as_slice()), tree.levels()[level].last().map(Vec::as_slice));
+                    }
+                }
+                assert!(MerkleTree::verify_proof_at_index(leaf, &proof.as_proof(), index, size, &tree.root()));
+
+                let indexed = proof.to_indexed().unwrap();
+                assert_eq!((indexed.index(), indexed.tree_size()), (index, size));
+                assert_eq!(indexed.to_proof(), proof);
+                let json = serde_json::to_string(&proof).unwrap();
+                assert_eq!(serde_json::from_str::<OwnedProof>(&json).unwrap().to_indexed().unwrap(), indexed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_duplicate_steps_are_positional() {
+        let data = example_data(5);
+        let tree = MerkleTree::construct(&data);
+        let proof = tree.prove_by_index(4).unwrap().into_owned();
+        let directions: Vec<_> = proof.hashes().iter().map(|(direction, _)| *direction).collect();
+        assert_eq!(directions, [HashDirection::Duplicate, HashDirection::Duplicate, HashDirection::Left]);
+
+        // marked right, as before format version 2, the proof still converts and verifies
+        let legacy: Vec<_> = proof
+            .hashes()
+            .iter()
+            .map(|(direction, hash)| match direction {
+                HashDirection::Duplicate => (HashDirection::Right, *hash),
+                _ => (*direction, *hash),
+            })
+            .collect();
+        let legacy = OwnedProof::new(legacy).with_tree_size(5);
+        assert!(MerkleTree::verify_proof_at_index(&data[4], &legacy.as_proof(), 4, 5, &tree.root()));
+        assert_eq!(legacy.to_indexed().unwrap().to_proof(), proof);
+
+        // a duplicate where the path has a real sibling verifies as a right step, but not in place
+        let mut hashes = tree.prove_by_index(0).unwrap().into_owned().hashes().to_vec();
+        hashes[0].0 = HashDirection::Duplicate;
+        let misplaced = OwnedProof::new(hashes).with_tree_size(5);
+        assert!(MerkleTree::verify_proof(&data[0], &misplaced.as_proof(), &tree.root()));
+        assert!(!MerkleTree::verify_proof_at_index(&data[0], &misplaced.as_proof(), 0, 5, &tree.root()));
+        let err = misplaced.to_indexed().unwrap_err();
+        assert_eq!(err.to_string(), "invalid format: step 1 of the proof of leaf 0 is Duplicate, not Right");
+
+        let untagged = OwnedProof::new(proof.hashes().to_vec());
+        assert!(matches!(untagged.to_indexed(), Err(LibError::InvalidFormat(_))));
+        assert!(matches!(IndexedProof::new(5, 5, vec![]), Err(LibError::IndexOutOfRange { index: 5, size: 5 })));
+        assert!(matches!(IndexedProof::new(0, 5, vec![]), Err(LibError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn test_audit_paths_of_left_balanced_trees() {
+        let config = TreeConfig::default().with_shape(TreeShape::LeftBalanced);
+        for size in 1..=17 {
+            let data = example_data(size);
+            let tree = config.construct(&data).unwrap();
+            for (index, leaf) in data.iter().enumerate() {
+                let proof = tree.prove_by_index(index).unwrap().into_owned();
+                let path = proof.hashes().iter().map(|(_, hash)| hash.to_vec()).collect();
+                let rebuilt = OwnedProof::from_audit_path(index, size, path).unwrap();
+                assert_eq!(rebuilt.hashes(), proof.hashes(), "size {} leaf {}", size, index);
+                assert!(MerkleTree::verify_proof(leaf, &rebuilt.as_proof(), &tree.root()));
+            }
+        }
+
+        let from = |index, size, hashes| OwnedProof::from_audit_path(index, size, hashes).unwrap_err().to_string();
+        assert_eq!(from(5, 5, vec![]), "leaf index 5 is out of range for a tree of 5 leaves");
+        let expected = "the audit path of leaf 4 in a left-balanced tree of 5 leaves has 1 hashes, found 3";
+        assert_eq!(from(4, 5, vec![vec![0; 32]; 3]), format!("invalid format: {}", expected));
+    }
+}
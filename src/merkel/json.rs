@@ -0,0 +1,234 @@
+use serde_json::{Map, Number, Value};
+
+use std::fmt::Write;
+
+use super::{Data, MerkleTree};
+use crate::util::error::LibError;
+
+/// Largest integer every JSON number of that magnitude can stand for exactly as a double
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// The bytes of `value` in the canonical form of RFC 8785 (JSON Canonicalization Scheme)
+///
+/// Object members are sorted by the UTF-16 code units of their names. Nothing is written
+/// between tokens. Strings are UTF-8, escaping only `"`, `\` and control characters. Numbers
+/// are written as ECMAScript prints the double they stand for, so `1.0`, `1` and `1e0` are all
+/// `1`. Two values that differ only in member order or number spelling therefore give the same
+/// leaf. Integers above 2^53 - 1 in magnitude fail with `LibError::InvalidFormat`, since a
+/// double cannot hold them exactly, and so do non-finite numbers.
+pub fn canonical_json_leaf(value: &Value) -> Result<Data, LibError> {
+    let mut out = String::new();
+    write_value(&mut out, value)?;
+    Ok(out.into_bytes())
+}
+
+impl MerkleTree {
+    /// Builds a tree over the canonical bytes of each value, see `canonical_json_leaf`
+    ///
+    /// Fails with `LibError::EmptyInput` without values, or as `canonical_json_leaf` does on the
+    /// first value that has no canonical form.
+    pub fn from_json_values(values: &[Value]) -> Result<MerkleTree, LibError> {
+        if values.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        let leaves = values.iter().map(canonical_json_leaf).collect::<Result<Vec<Data>, LibError>>()?;
+        Ok(MerkleTree::construct(&leaves))
+    }
+}
+
+fn write_value(out: &mut String, value: &Value) -> Result<(), LibError> {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => write_number(out, n)?,
+        Value::String(s) => write_string(out, s),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, item)?;
+            }
+            out.push(']');
+        }
+        Value::Object(members) => write_object(out, members)?,
+    }
+    Ok(())
+}
+
+fn write_object(out: &mut String, members: &Map<String, Value>) -> Result<(), LibError> {
+    let mut sorted: Vec<(Vec<u16>, &String, &Value)> =
+        members.iter().map(|(name, value)| (name.encode_utf16().collect(), name, value)).collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+    out.push('{');
+    for (i, (_, name, value)) in sorted.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_string(out, name);
+        out.push(':');
+        write_value(out, value)?;
+    }
+    out.push('}');
+    Ok(())
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_number(out: &mut String, n: &Number) -> Result<(), LibError> {
+    let unsafe_integer = || LibError::InvalidFormat(format!("{} is too large to be held exactly by a double", n));
+    if let Some(u) = n.as_u64() {
+        if u > MAX_SAFE_INTEGER {
+            return Err(unsafe_integer());
+        }
+        let _ = write!(out, "{}", u);
+    } else if let Some(i) = n.as_i64() {
+        if i.unsigned_abs() > MAX_SAFE_INTEGER {
+            return Err(unsafe_integer());
+        }
+        let _ = write!(out, "{}", i);
+    } else {
+        match n.as_f64() {
+            Some(f) if f.is_finite() => write_double(out, f),
+            _ => return Err(LibError::InvalidFormat(format!("{} is not a finite number", n))),
+        }
+    }
+    Ok(())
+}
+
+/// Writes `f` as ECMAScript's `Number.prototype.toString` does
+fn write_double(out: &mut String, f: f64) {
+    if f == 0.0 {
+        // negative zero too
+        out.push('0');
+        return;
+    }
+    if f < 0.0 {
+        out.push('-');
+    }
+    // the shortest digits that round-trip, as `d.ddde<exponent>`
+    let scientific = format!("{:e}", f.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let k = digits.len() as i32;
+    // the decimal point goes after `n` digits
+    let n = exponent.parse::<i32>().unwrap_or(0) + 1;
+    if k <= n && n <= 21 {
+        out.push_str(&digits);
+        out.extend(std::iter::repeat_n('0', (n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        out.push_str(&digits[..n as usize]);
+        out.push('.');
+        out.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        out.push_str("0.");
+        out.extend(std::iter::repeat_n('0', -n as usize));
+        out.push_str(&digits);
+    } else {
+        out.push_str(&digits[..1]);
+        if k > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        let _ = write!(out, "e{}{}", if n > 0 { "+" } else { "-" }, (n - 1).abs());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn canonical(value: &Value) -> String {
+        String::from_utf8(canonical_json_leaf(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_member_order_does_not_matter() {
+        let a: Value = serde_json::from_str(r#"{"b": [1, {"y": 2, "x": 1}], "a": "text", "c": null}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"c":null,"a":"text","b":[1.0,{"x":1e0,"y":2}]}"#).unwrap();
+        assert_eq!(canonical(&a), r#"{"a":"text","b":[1,{"x":1,"y":2}],"c":null}"#);
+        assert_eq!(canonical_json_leaf(&a).unwrap(), canonical_json_leaf(&b).unwrap());
+        let other = json!({ "a": "text", "b": [1, { "x": 1, "y": 3 }], "c": null });
+        let roots = [&[a.clone(), json!(true)], &[b, json!(true)], &[other, json!(true)]]
+            .map(|values| MerkleTree::from_json_values(values).unwrap().root());
+        assert_eq!(roots[0], roots[1]);
+        assert_ne!(roots[0], roots[2]);
+    }
+
+    #[test]
+    fn test_rfc8785_examples() {
+        // the sorting example of section 3.2.3, ordered by UTF-16 rather than by UTF-8
+        let names = json!({
+            "\u{20ac}": "Euro Sign",
+            "\r": "Carriage Return",
+            "\u{fb33}": "Hebrew Letter Dalet With Dagesh",
+            "1": "One",
+            "\u{1f600}": "Emoji: Grinning Face",
+            "\u{80}": "Control",
+            "\u{f6}": "Latin Small Letter O With Diaeresis",
+        });
+        let expected = concat!(
+            "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"\u{80}\":\"Control\",",
+            "\"\u{f6}\":\"Latin Small Letter O With Diaeresis\",\"\u{20ac}\":\"Euro Sign\",",
+            "\"\u{1f600}\":\"Emoji: Grinning Face\",\"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}",
+        );
+        assert_eq!(canonical(&names), expected);
+
+        let strings = json!(["\u{0}\u{1f}\"\\/\u{8}\t\n\u{c}\r", "\u{7f}\u{2028}"]);
+        assert_eq!(canonical(&strings), "[\"\\u0000\\u001f\\\"\\\\/\\b\\t\\n\\f\\r\",\"\u{7f}\u{2028}\"]");
+
+        let numbers: Value = serde_json::from_str(
+            "[0, -0.0, 100, 1e21, 1e20, 123.456, 0.000001, 1e-7, -1.5e300, 5e-324, 9007199254740991, \
+             -9007199254740991, 333333333.3333332, 1.7976931348623157e308]",
+        )
+        .unwrap();
+        let expected = "[0,0,100,1e+21,100000000000000000000,123.456,0.000001,1e-7,-1.5e+300,5e-324,9007199254740991,\
+                        -9007199254740991,333333333.3333332,1.7976931348623157e+308]";
+        assert_eq!(canonical(&numbers), expected);
+    }
+
+    #[test]
+    fn test_rejects_inexact_numbers() {
+        for number in ["9007199254740992", "-9007199254740992", "18446744073709551615"] {
+            let value: Value = serde_json::from_str(number).unwrap();
+            assert!(matches!(canonical_json_leaf(&value), Err(LibError::InvalidFormat(_))), "{}", number);
+        }
+        let nested = json!({ "ok": 1, "too large": [u64::MAX] });
+        assert!(matches!(MerkleTree::from_json_values(&[json!(1), nested]), Err(LibError::InvalidFormat(_))));
+        assert!(matches!(MerkleTree::from_json_values(&[]), Err(LibError::EmptyInput)));
+        // serde_json holds no non-finite numbers, and cannot build one from a float
+        assert_eq!(json!(f64::NAN), Value::Null);
+    }
+
+    #[test]
+    fn test_pinned_root() {
+        // worked out with Python's json.dumps(sort_keys=True, separators=(",", ":"), ensure_ascii=False),
+        // with integral floats as integers and exponents without leading zeros
+        let documents = include_str!("../../tests/fixtures/json_leaves.json");
+        let documents: Vec<Value> = serde_json::from_str(documents).unwrap();
+        let tree = MerkleTree::from_json_values(&documents).unwrap();
+        assert_eq!(hex::encode(tree.root()), "40918e67cd6281b1fd741cf2304048b429bd85fe9b2eee0e62ecf6111c6f8489");
+        let third = r#"{"balance":-7,"id":3,"meta":{"ratio":1e+21,"score":1e-7,"since":"2021-06-01"},"name":"Zoë"}"#;
+        assert_eq!(canonical(&documents[2]), third);
+    }
+}
@@ -0,0 +1,112 @@
+use serde::Serialize;
+
+use std::mem::{size_of, size_of_val};
+
+use super::{Hash, MerkleTree, SourceId};
+
+/// Memory held by a `MerkleTree`, see `MerkleTree::memory_usage`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MemoryReport {
+    /// Hashes of every level, leaves and root included
+    pub node_count: usize,
+    pub leaf_count: usize,
+    /// Bytes of the node hashes themselves
+    pub hash_bytes: usize,
+    /// Bytes spent around the hashes and data: the tree itself, the vectors holding each level and
+    /// each hash, spare capacity, and the sources of `construct_from_sources`
+    ///
+    /// What the allocator adds to every allocation is not counted.
+    pub overhead_bytes_estimate: usize,
+    /// Bytes of leaf data kept by `construct_retaining`
+    pub retained_data_bytes: usize,
+}
+
+impl MemoryReport {
+    /// Every byte of the report
+    pub fn total_bytes(&self) -> usize {
+        self.hash_bytes + self.overhead_bytes_estimate + self.retained_data_bytes
+    }
+}
+
+impl MerkleTree {
+    /// Counts the nodes of the tree and the bytes they and any retained data take
+    ///
+    /// The counts are taken from the levels as they are held, one vector of hashes per level, so
+    /// they change with that layout.
+    pub fn memory_usage(&self) -> MemoryReport {
+        let levels = self.levels();
+        let hashes = || levels.iter().flatten();
+        let node_count = levels.iter().map(Vec::len).sum();
+        let hash_bytes = hashes().map(Vec::len).sum();
+        let mut overhead = size_of::<MerkleTree>()
+            + size_of_val(levels)
+            + levels.iter().map(|level| level.capacity() * size_of::<Hash>()).sum::<usize>()
+            + hashes().map(|hash| hash.capacity() - hash.len()).sum::<usize>();
+
+        let retained = self.retained().unwrap_or_default();
+        let retained_data_bytes = retained.iter().map(|data| data.len()).sum();
+        overhead += size_of_val(retained)
+            + retained.iter().map(|data| data.capacity() - data.len()).sum::<usize>();
+        overhead += self.sources().iter().map(|(id, _)| size_of::<(SourceId, usize)>() + id.capacity()).sum::<usize>();
+
+        MemoryReport {
+            node_count,
+            leaf_count: self.len(),
+            hash_bytes,
+            overhead_bytes_estimate: overhead,
+            retained_data_bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::merkel::Retained;
+    use crate::merkel::{HashAlgorithm, LeafMode, MerkleHasher, TreeConfig};
+
+    fn example_data(n: usize) -> Vec<Vec<u8>> {
+        (0..n).map(|i| format!("entry {}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_counts_every_node() {
+        let data = example_data(7);
+        let tree = MerkleTree::construct(&data);
+        let report = tree.memory_usage();
+        // 7 leaves, then 4, 2 and the root
+        assert_eq!((report.node_count, report.leaf_count), (14, 7));
+        assert_eq!(report.hash_bytes, 32 * report.node_count);
+        assert_eq!(report.retained_data_bytes, 0);
+        assert!(report.overhead_bytes_estimate >= size_of::<MerkleTree>() + 14 * size_of::<Hash>());
+        assert_eq!(report.total_bytes(), report.hash_bytes + report.overhead_bytes_estimate);
+
+        let leaves: Vec<Hash> = data.iter().map(|entry| HashAlgorithm::Sha512.leaf_hash(entry)).collect();
+        let config = TreeConfig::new(LeafMode::PreHashed).with_hasher(HashAlgorithm::Sha512);
+        let sha512 = config.construct(&leaves).unwrap().memory_usage();
+        assert_eq!((sha512.node_count, sha512.hash_bytes), (14, 64 * 14));
+        assert_eq!(MerkleTree::construct(&example_data(1)).memory_usage().node_count, 1);
+    }
+
+    #[test]
+    fn test_retained_data_and_sources() {
+        let data = example_data(5);
+        let plain = MerkleTree::construct(&data).memory_usage();
+
+        let mut retaining = MerkleTree::construct_retaining(data.clone());
+        let retained = retaining.memory_usage();
+        let data_bytes: usize = data.iter().map(Vec::len).sum();
+        assert_eq!(retained, MemoryReport { retained_data_bytes: data_bytes, ..retained });
+        assert_eq!((retained.node_count, retained.hash_bytes), (plain.node_count, plain.hash_bytes));
+        assert_eq!(retained.overhead_bytes_estimate, plain.overhead_bytes_estimate + 5 * size_of::<Retained>());
+        retaining.wipe_leaf_data();
+        assert_eq!(retaining.memory_usage(), plain);
+
+        let sources = vec![("a".to_string(), data[..2].to_vec()), ("bc".to_string(), data[2..].to_vec())];
+        let from_sources = MerkleTree::construct_from_sources(&sources).unwrap().memory_usage();
+        let ids = sources.iter().map(|(id, _)| size_of::<(SourceId, usize)>() + id.capacity()).sum::<usize>();
+        assert_eq!(MemoryReport { overhead_bytes_estimate: plain.overhead_bytes_estimate, ..from_sources }, plain);
+        // collecting the leaves of every source may also leave spare capacity
+        assert!(from_sources.overhead_bytes_estimate >= plain.overhead_bytes_estimate + ids);
+    }
+}
@@ -1,141 +1,1289 @@
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
 use sha2::Digest;
-use hex;
-// use rand::Rng;
+use smallvec::SmallVec;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::raw::verify_proof_sha256;
+use super::{
+    HashAlgorithm, LeafIndex, LeafMode, MerkleHasher, OddLeafPolicy, ProofVersion, Sha256Hasher, TreeShape, TreeSize,
+};
+use crate::util::error::LibError;
 
 pub type Data = Vec<u8>;
 pub type Hash = Vec<u8>;
+/// Name of the input a leaf came from, see `MerkleTree::construct_from_sources`
+pub type SourceId = String;
+
+/// Length in bytes of every leaf and node hash of the default SHA-256 hasher
+///
+/// Trees built with another `HashAlgorithm` have hashes of its `MerkleHasher::output_len`.
+pub const HASH_LEN: usize = 32;
+
+/// Length in bytes of the widest hash of any hasher, that of `HashAlgorithm::Sha512`
+pub const MAX_HASH_LEN: usize = 64;
+
+/// Number of proof steps kept inline before a proof spills to the heap, enough for 2^40 leaves
+pub const PROOF_INLINE_STEPS: usize = 40;
+
+/// Most steps a decoded proof may have, the depth of a tree of `usize::MAX` leaves
+pub const MAX_PROOF_STEPS: usize = usize::BITS as usize;
+
+/// The steps of a borrowed proof, stored inline so generating a proof does not allocate
+pub(crate) type ProofSteps<'a> = SmallVec<[(HashDirection, &'a [u8]); PROOF_INLINE_STEPS]>;
+
+/// The steps of an owned proof, with their hashes stored inline too, so owning a proof copies
+/// them without allocating
+type OwnedSteps = SmallVec<[(HashDirection, StepHash); PROOF_INLINE_STEPS]>;
 
-#[derive(Clone)] 
+/// Identifier of the default hash function used for leaves and nodes, recorded in serialized artifacts
+pub const HASHER_ID: &str = "sha256";
+
+/// Leaf data kept after construction, zeroed on drop with the `zeroize` feature
+#[cfg(feature = "zeroize")]
+pub(crate) type Retained = zeroize::Zeroizing<Vec<u8>>;
+#[cfg(not(feature = "zeroize"))]
+pub(crate) type Retained = Vec<u8>;
+
+#[cfg(feature = "zeroize")]
+pub(crate) fn retain(data: Data) -> Retained {
+    zeroize::Zeroizing::new(data)
+}
+
+#[cfg(not(feature = "zeroize"))]
+pub(crate) fn retain(data: Data) -> Retained {
+    data
+}
+
+/// A Merkle tree stored as a flat list of levels, from the leaf hashes up to the root.
+///
+/// Levels with an odd number of nodes are not padded in storage: the last node is
+/// paired with itself when its parent is computed, or promoted unchanged under
+/// `OddLeafPolicy::Promote`.
+#[derive(Clone)]
 pub struct MerkleTree {
-    hash: Vec<u8>,
-    left: Option<Box<MerkleTree>>,
-    right: Option<Box<MerkleTree>>,
+    levels: Vec<Vec<Hash>>,
+    policy: OddLeafPolicy,
+    hasher: HashAlgorithm,
+    /// Raw leaf data, only kept by `construct_retaining`
+    data: Option<Vec<Retained>>,
+    /// Each source with the index just past its last leaf, in leaf order
+    sources: Vec<(SourceId, usize)>,
 }
 
 /// Which side to put Hash on when concatinating proof hashes
-#[derive(Debug, Clone, Copy, PartialEq)]
+///
+/// `Left` orders before `Right`, and `Right` before `Duplicate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
 pub enum HashDirection {
     Left,
     Right,
+    /// The node is the lone last one of an odd-sized level and is paired with itself
+    ///
+    /// The step's hash is a virtual sibling, a copy of the node, and is concatenated on the right
+    /// like `Right`. Proofs before format version 2 marked these steps `Right`, which still verifies.
+    Duplicate,
+}
+
+impl HashDirection {
+    /// Side of the running hash, opposite its sibling
+    ///
+    /// A `Duplicate` sibling is on the right, so the node it copies is on the left.
+    pub fn opposite(self) -> HashDirection {
+        match self {
+            HashDirection::Left => HashDirection::Right,
+            HashDirection::Right | HashDirection::Duplicate => HashDirection::Left,
+        }
+    }
+
+    /// Parent of the running hash and a sibling on this side, in the order `hasher` expects
+    pub fn combine<H: MerkleHasher + ?Sized>(self, hasher: &H, running: &[u8], sibling: &[u8]) -> Hash {
+        match self {
+            HashDirection::Left => hasher.node_hash(sibling, running),
+            HashDirection::Right | HashDirection::Duplicate => hasher.node_hash(running, sibling),
+        }
+    }
+
+    /// The side as one bit, set for a left sibling, which is how binary encodings store it
+    ///
+    /// `Duplicate` maps to the bit of `Right`; encodings that keep it record duplicate steps apart.
+    pub fn to_bit(self) -> bool {
+        self == HashDirection::Left
+    }
+
+    /// Inverse of `to_bit`, giving `Right` for a clear bit
+    pub fn from_bit(left: bool) -> HashDirection {
+        if left {
+            HashDirection::Left
+        } else {
+            HashDirection::Right
+        }
+    }
+}
+
+/// Order in which a serialized proof lists its steps
+///
+/// Proofs are built and verified from the leaf up, the order `MerkleTree::prove_by_index` finds
+/// the siblings in, and `LeafToRoot` is the default. Some systems list the siblings from the root
+/// down instead. An `OwnedProof` declared `RootToLeaf` is written and read in that order, but
+/// holds and verifies its steps from the leaf up like any other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProofOrder {
+    #[default]
+    LeafToRoot,
+    RootToLeaf,
 }
 
-#[derive(Debug, Default)]
+impl ProofOrder {
+    /// The other order
+    pub fn reversed(self) -> ProofOrder {
+        match self {
+            ProofOrder::LeafToRoot => ProofOrder::RootToLeaf,
+            ProofOrder::RootToLeaf => ProofOrder::LeafToRoot,
+        }
+    }
+
+    fn is_leaf_to_root(&self) -> bool {
+        *self == ProofOrder::LeafToRoot
+    }
+}
+
+/// The sibling hashes leading from a leaf to the root, optionally tagged with the size of the tree
+///
+/// Proofs from `MerkleTree::prove_by_index` carry the number of leaves of their tree. An empty
+/// proof, the proof of the only leaf of a one-leaf tree, only verifies when tagged with size 1,
+/// so a hash cannot be passed off as the root of a tree over itself.
+///
+/// The steps always run from the leaf up, the order verification applies them in; an
+/// `OwnedProof` can be written and read root-first, see `ProofOrder`.
+///
+/// Proofs compare structurally, step by step and then by their tree size.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub struct Proof<'a> {
     /// The hashes to use when verifying the proof
     /// The first element of the tuple is which side the hash should be on when concatinating
-    hashes: Vec<(HashDirection, &'a Hash)>,
+    hashes: ProofSteps<'a>,
+    tree_size: Option<usize>,
 }
 
-impl MerkleTree {
-    fn new_leaf(data: &Data) -> Self {
-        MerkleTree {
-            hash: hash_data(data),
-            left: None,
-            right: None,
+/// A sibling hash of an `OwnedProof`, held inline in at most `MAX_HASH_LEN` bytes
+///
+/// Derefs to its bytes, and compares, orders, hashes and debug-prints as they do, so it stands in
+/// for the `Hash` it was made from.
+#[derive(Clone, Copy)]
+pub struct StepHash {
+    bytes: [u8; MAX_HASH_LEN],
+    len: u8,
+}
+
+impl StepHash {
+    /// A copy of `hash`, failing with `LibError::InvalidHashLength` if it is longer than
+    /// `MAX_HASH_LEN`
+    pub fn new(hash: &[u8]) -> Result<StepHash, LibError> {
+        if hash.len() > MAX_HASH_LEN {
+            return Err(LibError::InvalidHashLength { expected: MAX_HASH_LEN, found: hash.len() });
         }
+        Ok(StepHash::copy(hash))
     }
 
-    fn new_parent(left: MerkleTree, right: MerkleTree) -> Self {
-        // println!("Hashing {:?} and {:?} = {:?}", hex::encode(&left.hash), hex::encode(&right.hash),hex::encode(hash_concat(&left.hash, &right.hash)));
-        MerkleTree {
-            hash: hash_concat(&left.hash, &right.hash),
-            left: Some(Box::new(left)),
-            right: Some(Box::new(right)),
+    /// A copy of `hash`, which must fit
+    fn copy(hash: &[u8]) -> StepHash {
+        assert!(hash.len() <= MAX_HASH_LEN, "a proof hash of {} bytes is longer than any hasher's", hash.len());
+        let mut bytes = [0; MAX_HASH_LEN];
+        bytes[..hash.len()].copy_from_slice(hash);
+        StepHash { bytes, len: hash.len() as u8 }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..usize::from(self.len)]
+    }
+}
+
+impl std::ops::Deref for StepHash {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl std::ops::DerefMut for StepHash {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes[..usize::from(self.len)]
+    }
+}
+
+impl AsRef<[u8]> for StepHash {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl fmt::Debug for StepHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+impl PartialEq for StepHash {
+    fn eq(&self, other: &StepHash) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for StepHash {}
+
+impl PartialEq<Hash> for StepHash {
+    fn eq(&self, other: &Hash) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl PartialEq<StepHash> for Hash {
+    fn eq(&self, other: &StepHash) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl PartialOrd for StepHash {
+    fn partial_cmp(&self, other: &StepHash) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for StepHash {
+    fn cmp(&self, other: &StepHash) -> std::cmp::Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl std::hash::Hash for StepHash {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+/// A proof that owns its hashes, so it can outlive the tree it came from
+///
+/// The steps are held inline as `StepHash`es, so a proof of up to `PROOF_INLINE_STEPS` steps
+/// is owned without allocating.
+///
+/// Serializes as `{"version", "steps"}` with a list of `{"direction", "hash"}` steps and hex
+/// hashes, plus `"tree_size"`, `"hasher"` and `"leaf_mode"` for proofs tagged with them; a
+/// recorded hasher is checked against the width of every step. The steps are listed from the leaf up unless
+/// `"order"` is `"root-to-leaf"`, see `ProofOrder`. The bare list of steps written before formats
+/// were versioned is still accepted.
+///
+/// Like `Proof`, owned proofs compare structurally. They order lexicographically by their steps,
+/// then untagged before tagged and by tree size, then by declared order and by the hasher and leaf
+/// mode they are tagged with, which gives a canonical order to sort them in.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(into = "ProofRepr")]
+pub struct OwnedProof {
+    /// From the leaf up, whatever the declared order
+    hashes: OwnedSteps,
+    tree_size: Option<usize>,
+    order: ProofOrder,
+    hasher: Option<HashAlgorithm>,
+    leaf_mode: Option<LeafMode>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProofRepr {
+    version: ProofVersion,
+    steps: Vec<ProofStep>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tree_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "ProofOrder::is_leaf_to_root")]
+    order: ProofOrder,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hasher: Option<HashAlgorithm>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    leaf_mode: Option<LeafMode>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProofStep {
+    direction: HashDirection,
+    #[serde(with = "crate::util::hex_serde")]
+    hash: Hash,
+}
+
+impl<'a> Proof<'a> {
+    pub(crate) fn from_hashes(hashes: ProofSteps<'a>) -> Self {
+        Proof { hashes, tree_size: None }
+    }
+
+    /// Tags the proof with the number of leaves of its tree
+    pub fn with_tree_size(mut self, tree_size: impl Into<TreeSize>) -> Self {
+        self.tree_size = Some(tree_size.into().saturating_usize());
+        self
+    }
+
+    /// Number of leaves of the tree the proof came from, if it was tagged with it
+    pub fn tree_size(&self) -> Option<usize> {
+        self.tree_size
+    }
+
+    /// Whether the number of steps is possible for the tagged tree size; see `Proof`
+    ///
+    /// Untagged proofs must not be empty nor longer than `MAX_PROOF_STEPS`. A tree of two or more
+    /// leaves gives at least one step under either odd leaf policy.
+    pub(crate) fn fits_tree_size(&self) -> bool {
+        match self.tree_size {
+            Some(size) => size > 0 && (size == 1) == self.hashes.is_empty() && self.hashes.len() <= tree_depth(size),
+            None => !self.hashes.is_empty() && self.hashes.len() <= MAX_PROOF_STEPS,
+        }
+    }
+
+    pub(crate) fn steps(&self) -> &[(HashDirection, &'a [u8])] {
+        &self.hashes
+    }
+
+    /// Copies the proof hashes out of the tree, without allocating for a proof of up to
+    /// `PROOF_INLINE_STEPS` steps
+    ///
+    /// Panics if a hash is longer than `MAX_HASH_LEN`, which no hasher's are.
+    pub fn into_owned(self) -> OwnedProof {
+        OwnedProof {
+            hashes: self.hashes.into_iter().map(|(d, h)| (d, StepHash::copy(h))).collect(),
+            tree_size: self.tree_size,
+            order: ProofOrder::LeafToRoot,
+            hasher: None,
+            leaf_mode: None,
+        }
+    }
+
+    /// Number of sibling hashes in the proof
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Leaf index encoded by the directions: a sibling on the left means the path went right
+    ///
+    /// A lone last node paired with itself is at an even index, whether its step is marked
+    /// `HashDirection::Duplicate` or `Right`.
+    /// Fails with `LibError::Overflow` if a left sibling is deeper than a `usize` index can reach.
+    pub fn implied_index(&self) -> Result<usize, LibError> {
+        self.hashes
+            .iter()
+            .enumerate()
+            .filter(|(_, (direction, _))| *direction == HashDirection::Left)
+            .try_fold(0usize, |index, (level, _)| {
+                let bit = u32::try_from(level).ok().and_then(|level| 1usize.checked_shl(level));
+                bit.map(|bit| index | bit).ok_or(LibError::Overflow("proof index"))
+            })
+    }
+
+    /// Computes the root reached by applying this proof to the given leaf hash
+    pub fn root_from(&self, leaf_hash: &Hash) -> Hash {
+        self.root_from_with(&Sha256Hasher, leaf_hash)
+    }
+
+    /// Like `root_from`, for a proof from a tree built with another hasher
+    pub fn root_from_with<H: MerkleHasher + ?Sized>(&self, hasher: &H, leaf_hash: &Hash) -> Hash {
+        let mut hash = leaf_hash.clone();
+
+        for (direction, proof_hash) in &self.hashes {
+            hash = direction.combine(hasher, &hash, proof_hash);
+        }
+        hash
+    }
+}
+
+impl OwnedProof {
+    /// A proof of the steps `hashes`, from the leaf up
+    ///
+    /// Panics if a hash is longer than `MAX_HASH_LEN`; decoders check with `StepHash::new` first.
+    pub fn new<H: AsRef<[u8]>>(hashes: Vec<(HashDirection, H)>) -> Self {
+        OwnedProof::from_ordered_steps(hashes, ProofOrder::LeafToRoot)
+    }
+
+    /// A proof of the steps `hashes`, listed in `order`, which is kept for serializing it
+    ///
+    /// `OwnedProof::new` is this with `ProofOrder::LeafToRoot`, and panics where it does.
+    pub fn from_ordered_steps<H: AsRef<[u8]>>(hashes: Vec<(HashDirection, H)>, order: ProofOrder) -> Self {
+        let mut hashes: OwnedSteps = hashes.iter().map(|(d, h)| (*d, StepHash::copy(h.as_ref()))).collect();
+        if order == ProofOrder::RootToLeaf {
+            hashes.reverse();
+        }
+        OwnedProof { hashes, tree_size: None, order, hasher: None, leaf_mode: None }
+    }
+
+    /// Tags the proof with the number of leaves of its tree
+    pub fn with_tree_size(mut self, tree_size: impl Into<TreeSize>) -> Self {
+        self.tree_size = Some(tree_size.into().saturating_usize());
+        self
+    }
+
+    /// Number of leaves of the tree the proof came from, if it was tagged with it
+    pub fn tree_size(&self) -> Option<usize> {
+        self.tree_size
+    }
+
+    /// Tags the proof with the hasher of its tree, which every proof format records
+    ///
+    /// The hashes are not checked here; decoding a tagged proof checks they are of its width.
+    /// `TreeConfig::tag_proof` tags both the hasher and the leaf mode.
+    pub fn with_hasher(mut self, hasher: HashAlgorithm) -> Self {
+        self.hasher = Some(hasher);
+        self
+    }
+
+    /// Hasher of the tree the proof came from, if it was tagged with it
+    pub fn hasher(&self) -> Option<HashAlgorithm> {
+        self.hasher
+    }
+
+    /// Tags the proof with the leaf mode of its tree, which every proof format records
+    pub fn with_leaf_mode(mut self, leaf_mode: LeafMode) -> Self {
+        self.leaf_mode = Some(leaf_mode);
+        self
+    }
+
+    /// Leaf mode of the tree the proof came from, if it was tagged with it
+    pub fn leaf_mode(&self) -> Option<LeafMode> {
+        self.leaf_mode
+    }
+
+    /// The sibling hashes, ordered from the leaf up to the root whatever the declared order
+    pub fn hashes(&self) -> &[(HashDirection, StepHash)] {
+        &self.hashes
+    }
+
+    /// The order the proof lists its steps in when serialized
+    pub fn order(&self) -> ProofOrder {
+        self.order
+    }
+
+    /// The same proof declared in the other order, so it serializes with its steps reversed
+    ///
+    /// It verifies as this proof does, and reversing it again gives this proof back.
+    pub fn reversed(&self) -> OwnedProof {
+        OwnedProof { order: self.order.reversed(), ..self.clone() }
+    }
+
+    /// Borrows the proof in the form accepted by `MerkleTree::verify_proof`
+    pub fn as_proof(&self) -> Proof<'_> {
+        Proof { hashes: self.hashes.iter().map(|(d, h)| (*d, h.as_slice())).collect(), tree_size: self.tree_size }
+    }
+}
+
+impl TryFrom<ProofRepr> for OwnedProof {
+    type Error = LibError;
+
+    fn try_from(repr: ProofRepr) -> Result<Self, LibError> {
+        check_step_count(repr.steps.len())?;
+        let hashes: Vec<_> = repr.steps.into_iter().map(|s| (s.direction, s.hash)).collect();
+        check_directions(repr.version, &hashes)?;
+        check_hash_lens(hashes.iter().map(|(_, hash)| hash))?;
+        if let Some(hasher) = repr.hasher {
+            check_hash_widths(hasher, hashes.iter().map(|(_, hash)| hash))?;
+        }
+        let proof = OwnedProof::from_ordered_steps(hashes, repr.order);
+        Ok(OwnedProof {
+            tree_size: repr.tree_size.map(|size| to_usize(size, "tree size")).transpose()?,
+            hasher: repr.hasher,
+            leaf_mode: repr.leaf_mode,
+            ..proof
+        })
+    }
+}
+
+impl From<OwnedProof> for ProofRepr {
+    fn from(proof: OwnedProof) -> Self {
+        let mut steps: Vec<_> =
+            proof.hashes.into_iter().map(|(direction, hash)| ProofStep { direction, hash: hash.to_vec() }).collect();
+        if proof.order == ProofOrder::RootToLeaf {
+            steps.reverse();
+        }
+        ProofRepr {
+            version: ProofVersion::CURRENT,
+            steps,
+            tree_size: proof.tree_size.map(|size| size as u64),
+            order: proof.order,
+            hasher: proof.hasher,
+            leaf_mode: proof.leaf_mode,
         }
     }
+}
+
+/// Rejects decoded proofs longer than any tree allows with `LibError::ProofTooDeep`, before
+/// anything is allocated for their steps
+pub(crate) fn check_step_count(steps: usize) -> Result<(), LibError> {
+    if steps > MAX_PROOF_STEPS {
+        return Err(LibError::ProofTooDeep { max: MAX_PROOF_STEPS, got: steps });
+    }
+    Ok(())
+}
+
+/// Rejects decoded proofs with a hash longer than any hasher's, which `OwnedProof` cannot hold,
+/// with `LibError::InvalidHashLength`
+pub(crate) fn check_hash_lens<'h>(mut hashes: impl Iterator<Item = &'h Hash>) -> Result<(), LibError> {
+    match hashes.find(|hash| hash.len() > MAX_HASH_LEN) {
+        Some(hash) => Err(LibError::InvalidHashLength { expected: MAX_HASH_LEN, found: hash.len() }),
+        None => Ok(()),
+    }
+}
+
+/// Rejects hashes that are not of the width of `hasher`, the hasher a proof is tagged with
+pub(crate) fn check_hash_widths<'h>(
+    hasher: HashAlgorithm,
+    mut hashes: impl Iterator<Item = &'h Hash>,
+) -> Result<(), LibError> {
+    let expected = hasher.output_len();
+    match hashes.find(|hash| hash.len() != expected) {
+        Some(hash) => Err(LibError::InvalidHashLength { expected, found: hash.len() }),
+        None => Ok(()),
+    }
+}
+
+/// Rejects `HashDirection::Duplicate` steps in proofs of a format version that predates them
+pub(crate) fn check_directions(version: ProofVersion, hashes: &[(HashDirection, Hash)]) -> Result<(), LibError> {
+    if !version.has_duplicate_steps() && hashes.iter().any(|(direction, _)| *direction == HashDirection::Duplicate) {
+        return Err(LibError::InvalidFormat(format!(
+            "duplicate steps need format version 2, found version {}",
+            version.number()
+        )));
+    }
+    Ok(())
+}
+
+impl<'de> Deserialize<'de> for OwnedProof {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ReprVisitor;
 
+        impl<'de> Visitor<'de> for ReprVisitor {
+            type Value = ProofRepr;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a versioned proof or a list of proof steps")
+            }
+
+            // an unversioned list of steps, upgraded to version 1
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<ProofRepr, A::Error> {
+                let steps = Vec::deserialize(SeqAccessDeserializer::new(seq))?;
+                Ok(ProofRepr {
+                    version: ProofVersion::V1,
+                    steps,
+                    tree_size: None,
+                    order: ProofOrder::LeafToRoot,
+                    hasher: None,
+                    leaf_mode: None,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<ProofRepr, A::Error> {
+                ProofRepr::deserialize(MapAccessDeserializer::new(map))
+            }
+        }
+
+        let repr = deserializer.deserialize_any(ReprVisitor)?;
+        OwnedProof::try_from(repr).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Handle to a node of a `MerkleTree`, addressed by level (0 = leaves) and position within the level.
+///
+/// Ids are only handed out by the tree they belong to, so they always point at a stored node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    level: usize,
+    index: usize,
+}
+
+impl NodeId {
+    /// Level of the node, counting up from the leaves at level 0
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Position of the node within its level
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl MerkleTree {
     /// Gets root hash for this tree
     pub fn root(&self) -> Hash {
-        self.hash.clone()
+        self.levels[self.levels.len() - 1][0].clone()
+    }
+
+    /// Number of leaves the tree was built from
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Returns true if the tree has no leaves
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of levels above the leaves, i.e. the length of every proof
+    pub fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    /// Leaf hashes in input order
+    pub fn leaves(&self) -> &[Hash] {
+        &self.levels[0]
+    }
+
+    /// Hashes of every level, from the leaves (first) up to the root (last)
+    pub fn levels(&self) -> &[Vec<Hash>] {
+        &self.levels
     }
 
     /// Constructs a Merkle tree from given input data
-    fn construct(input: &[Data]) -> MerkleTree {
-        let mut nodes: Vec<MerkleTree> = input.iter().map(|d| MerkleTree::new_leaf(d)).collect();
-
-        while nodes.len() > 1 {
-            let mut new_nodes = Vec::new();
-            for chunk in nodes.chunks(2) {
-                if chunk.len() == 2 {
-                    new_nodes.push(MerkleTree::new_parent(chunk[0].clone(), chunk[1].clone()));
-                } else {
-                    new_nodes.push(MerkleTree::new_parent(chunk[0].clone(), chunk[0].clone()));
-                }
-            }
-            nodes = new_nodes; 
+    ///
+    /// Panics if `input` is empty; `TreeConfig::construct` returns `LibError::EmptyInput` instead.
+    pub fn construct(input: &[Data]) -> MerkleTree {
+        MerkleTree::from_leaf_hashes(input.iter().map(hash_data).collect())
+    }
+
+    /// Constructs a Merkle tree from anything that can be viewed as leaf bytes
+    ///
+    /// Use `LeafEncoder` to turn multi-field records into leaves without ambiguity.
+    /// Panics if `items` is empty.
+    pub fn from_items<I>(items: I) -> MerkleTree
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        MerkleTree::from_leaf_hashes(items.into_iter().map(|item| hash_bytes(item.as_ref())).collect())
+    }
+
+    /// Constructs a Merkle tree that also keeps the raw leaf data, see `leaf_data`
+    ///
+    /// With the `zeroize` feature the kept data is zeroed when it is wiped or the tree is dropped.
+    /// Panics if `input` is empty.
+    pub fn construct_retaining(input: Vec<Data>) -> MerkleTree {
+        let mut tree = MerkleTree::construct(&input);
+        tree.data = Some(input.into_iter().map(retain).collect());
+        tree
+    }
+
+    /// Raw data of the leaf at `index`, if the tree was built with `construct_retaining` and
+    /// the data has not been wiped
+    pub fn leaf_data(&self, index: usize) -> Option<&[u8]> {
+        self.data.as_ref()?.get(index).map(|data| data.as_slice())
+    }
+
+    /// Retained leaf data, see `construct_retaining`
+    pub(crate) fn retained(&self) -> Option<&[Retained]> {
+        self.data.as_deref()
+    }
+
+    /// Each source with the index just past its last leaf, see `construct_from_sources`
+    pub(crate) fn sources(&self) -> &[(SourceId, usize)] {
+        &self.sources
+    }
+
+    /// Drops the retained leaf data, keeping every hash so proofs still work
+    pub fn wipe_leaf_data(&mut self) {
+        self.data = None;
+    }
+
+    /// Constructs a Merkle tree over the data of several sources and remembers where each leaf
+    /// came from, see `source_of`
+    ///
+    /// The leaves are the data of the first source in order, then of the second, and so on, so
+    /// reordering sources changes the root unless it leaves the sequence of leaves unchanged.
+    /// Sources may be empty and are not required to have distinct ids.
+    pub fn construct_from_sources(sources: &[(SourceId, Vec<Data>)]) -> Result<MerkleTree, LibError> {
+        let leaves: Vec<Hash> = sources.iter().flat_map(|(_, data)| data.iter().map(hash_data)).collect();
+        if leaves.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        let mut tree = MerkleTree::from_leaf_hashes(leaves);
+        let mut end = 0;
+        for (id, data) in sources {
+            end += data.len();
+            tree.sources.push((id.clone(), end));
+        }
+        Ok(tree)
+    }
+
+    /// Source of the leaf at `index`, if the tree was built with `construct_from_sources`
+    pub fn source_of(&self, index: usize) -> Option<&SourceId> {
+        let position = self.sources.partition_point(|(_, end)| *end <= index);
+        self.sources.get(position).filter(|_| index < self.len()).map(|(id, _)| id)
+    }
+
+    /// Constructs a Merkle tree and returns the hashes of all its levels, leaves first
+    ///
+    /// Panics if `input` is empty.
+    pub fn construct_with_levels(input: &[Data]) -> Vec<Vec<Hash>> {
+        MerkleTree::construct(input).levels
+    }
+
+    /// Constructs a Merkle tree from already hashed leaves
+    ///
+    /// Panics if `leaves` is empty.
+    pub fn from_leaf_hashes(leaves: Vec<Hash>) -> MerkleTree {
+        MerkleTree::build(leaves, OddLeafPolicy::Duplicate, HashAlgorithm::Sha256)
+    }
+
+    /// Builds the levels above `leaves` under the given policy and hasher; see `TreeConfig` for the
+    /// public entry point
+    pub(crate) fn build(leaves: Vec<Hash>, policy: OddLeafPolicy, hasher: HashAlgorithm) -> MerkleTree {
+        MerkleTree::build_with(leaves, policy, hasher, |left, right| hash_pair(hasher, left, right))
+    }
+
+    /// Like `build`, computing each parent with `pair`, which must hash as `hasher` does
+    pub(crate) fn build_with<F>(leaves: Vec<Hash>, policy: OddLeafPolicy, hasher: HashAlgorithm, mut pair: F) -> Self
+    where
+        F: FnMut(&Hash, &Hash) -> Hash,
+    {
+        assert!(!leaves.is_empty(), "cannot construct a merkle tree without leaves");
+        let mut levels = vec![leaves];
+
+        while levels[levels.len() - 1].len() > 1 {
+            levels.push(parent_level_with(&levels[levels.len() - 1], policy, &mut pair));
+        }
+        MerkleTree {
+            levels,
+            policy,
+            hasher,
+            data: None,
+            sources: vec![],
         }
-        nodes.remove(0) 
     }
-    
 
-    /// Verifies that the given input data produces the given root hash
+    /// Wraps duplicate-padded levels computed elsewhere, from the leaves (first) up to the single root (last)
+    pub(crate) fn from_levels(levels: Vec<Vec<Hash>>) -> MerkleTree {
+        MerkleTree::from_levels_with(levels, OddLeafPolicy::Duplicate, HashAlgorithm::Sha256)
+    }
+
+    /// Like `from_levels`, for levels computed under another policy or hasher
+    pub(crate) fn from_levels_with(levels: Vec<Vec<Hash>>, policy: OddLeafPolicy, hasher: HashAlgorithm) -> MerkleTree {
+        debug_assert!(levels.last().is_some_and(|root| root.len() == 1));
+        MerkleTree {
+            levels,
+            policy,
+            hasher,
+            data: None,
+            sources: vec![],
+        }
+    }
+
+    /// How the lone last node of odd-sized levels was handled when building the tree
+    pub fn odd_leaf_policy(&self) -> OddLeafPolicy {
+        self.policy
+    }
+
+    /// Shape of the tree, following from its odd leaf policy
+    pub fn shape(&self) -> TreeShape {
+        TreeShape::of(self.policy)
+    }
+
+    /// Hasher the leaves and nodes of the tree were computed with
+    pub fn hasher(&self) -> HashAlgorithm {
+        self.hasher
+    }
+
+    /// Verifies that the given input data, in this order, produces the given root hash
+    ///
+    /// The root commits to the whole dataset and its order: the same entries in another order
+    /// fail, unless the reordering only swaps identical entries, which leaves the input as it was.
+    /// This is no membership test; prove single entries with `prove` for that, or commit to the
+    /// entries in any order with `MultisetCommitment` and check them with `verify_multiset`.
+    /// Under `OddLeafPolicy::Duplicate` the root does not pin the number of entries either: an odd
+    /// number of entries followed by a repeat of the last has the root of the entries without it.
+    ///
+    /// The input is always hashed as raw data, so this cannot verify a tree built with
+    /// `from_leaf_hashes`; use `TreeConfig::verify` with `LeafMode::PreHashed` for that. Empty
+    /// input has no root and never verifies.
+    pub fn verify_ordered(input: &[Data], root_hash: &Hash) -> bool {
+        !input.is_empty() && &MerkleTree::construct(input).root() == root_hash
+    }
+
+    /// Verifies that the given input data produces the given root hash, as `verify_ordered`
+    #[deprecated(note = "checks the order of the input as well as its entries; \
+                         use `verify_ordered`, or `verify_multiset` to ignore the order")]
     pub fn verify(input: &[Data], root_hash: &Hash) -> bool {
-        let tree = MerkleTree::construct(input);
-        &tree.root() == root_hash
+        MerkleTree::verify_ordered(input, root_hash)
+    }
+
+    /// Smallest index at which the leaves of `input` differ from those of `reference`
+    ///
+    /// Meant for when `verify_ordered` fails: instead of bisecting the input, this builds the candidate
+    /// tree and follows only the subtrees whose hashes differ. Returns None if every leaf present in
+    /// both trees matches, including when only the number of leaves differs; compare `len` for that.
+    pub fn find_divergence(input: &[Data], reference: &MerkleTree) -> Option<usize> {
+        if input.is_empty() {
+            return None;
+        }
+        MerkleTree::construct(input).first_difference(reference)
+    }
+
+    /// Smallest leaf index at which this tree and `other` hold different leaf hashes
+    ///
+    /// Subtrees covering the same leaves with equal hashes are skipped, so this takes time in
+    /// proportion to the number of differing leaves times the depth. Leaves past the end of the
+    /// shorter tree are not compared.
+    pub fn first_difference(&self, other: &MerkleTree) -> Option<usize> {
+        self.first_difference_below(other, self.depth().max(other.depth()), 0)
+    }
+
+    fn first_difference_below(&self, other: &MerkleTree, level: usize, index: usize) -> Option<usize> {
+        let common = self.len().min(other.len());
+        let start = index.checked_shl(level as u32).filter(|&start| start < common)?;
+        // a block overhanging the shorter tree has hashes that cannot be compared
+        let end = start.saturating_add(1usize.checked_shl(level as u32).unwrap_or(usize::MAX));
+        let id = NodeId { level, index };
+        if (self.len() == other.len() || end <= common) && self.node(id).is_some() && self.node(id) == other.node(id) {
+            return None;
+        }
+        if level == 0 {
+            return Some(index);
+        }
+        self.first_difference_below(other, level - 1, 2 * index)
+            .or_else(|| self.first_difference_below(other, level - 1, 2 * index + 1))
     }
 
     /// Verifies that the given data and proof_path correctly produce the given root_hash
+    ///
+    /// An empty proof only verifies when tagged with a tree size of 1, see `Proof`.
     pub fn verify_proof(data: &Data, proof: &Proof, root_hash: &Hash) -> bool {
-        let mut hash = hash_data(data);
+        verify_proof_sha256(&sha2::Sha256::digest(data).into(), proof, root_hash)
+    }
 
-        for (direction, proof_hash) in &proof.hashes {
-            hash = match direction {
-                HashDirection::Left => hash_concat(proof_hash, &hash),
-                HashDirection::Right => hash_concat(&hash, proof_hash),
-            };
+    /// Like `verify_proof`, but also checks that the proof is for the leaf at `index` of a tree
+    /// with `tree_size` leaves
+    ///
+    /// Plain `verify_proof` ignores positions, so the same data could be shown at a fabricated
+    /// index, e.g. a padded last leaf at the index just past the end of the tree.
+    /// Only proofs from trees using `OddLeafPolicy::Duplicate` have the expected length.
+    ///
+    /// `HashDirection::Duplicate` steps are only accepted where the path passes through the lone
+    /// last node of a level. Such a node may also be marked `Right`, as proofs before format
+    /// version 2 did.
+    pub fn verify_proof_at_index(
+        data: &Data,
+        proof: &Proof,
+        index: impl Into<LeafIndex>,
+        tree_size: impl Into<TreeSize>,
+        root_hash: &Hash,
+    ) -> bool {
+        let (index, tree_size) = (index.into().saturating_usize(), tree_size.into().saturating_usize());
+        index < tree_size
+            && proof.tree_size.is_none_or(|size| size == tree_size)
+            && proof.len() == tree_depth(tree_size)
+            && follows_path(proof, index, tree_size)
+            && MerkleTree::verify_proof(data, proof, root_hash)
+    }
+
+    /// Like `verify_proof_at_index`, starting from an already hashed leaf of a tree built with
+    /// any hasher
+    pub fn verify_proof_hash_at_index_with<H: MerkleHasher + ?Sized>(
+        hasher: &H,
+        leaf_hash: &Hash,
+        proof: &Proof,
+        index: impl Into<LeafIndex>,
+        tree_size: impl Into<TreeSize>,
+        root_hash: &Hash,
+    ) -> bool {
+        let (index, tree_size) = (index.into().saturating_usize(), tree_size.into().saturating_usize());
+        index < tree_size
+            && proof.tree_size.is_none_or(|size| size == tree_size)
+            && proof.len() == tree_depth(tree_size)
+            && follows_path(proof, index, tree_size)
+            && MerkleTree::verify_proof_hash_with(hasher, leaf_hash, proof, root_hash)
+    }
+
+    /// Verifies that the proof leads from the data to `anchor`, a trusted node at `anchor_level`
+    ///
+    /// For proofs from `prove_to_level`, which stop below the root. A path has no step at the
+    /// levels where it passes through a promoted node, so the proof may have fewer steps than
+    /// `anchor_level`, but never more. A proof tagged with its tree size must not claim an anchor
+    /// above the root of that tree.
+    pub fn verify_proof_to_anchor(data: &Data, proof: &Proof, anchor: &Hash, anchor_level: usize) -> bool {
+        proof.len() <= anchor_level
+            && anchor_level <= MAX_PROOF_STEPS
+            && proof.tree_size.is_none_or(|size| anchor_level <= tree_depth(size))
+            && &proof.root_from(&hash_data(data)) == anchor
+    }
+
+    /// Like `verify_proof_to_anchor`, but also checks that the proof is for the leaf at `index` of
+    /// a tree with `tree_size` leaves, as `verify_proof_at_index` does for proofs to the root
+    ///
+    /// The anchor is then node `index >> anchor_level` of its level. The proof must have one step
+    /// per level below the anchor, so as with `verify_proof_at_index` only proofs from trees using
+    /// `OddLeafPolicy::Duplicate` pass.
+    pub fn verify_proof_to_anchor_at_index(
+        data: &Data,
+        proof: &Proof,
+        index: impl Into<LeafIndex>,
+        tree_size: impl Into<TreeSize>,
+        anchor: &Hash,
+        anchor_level: usize,
+    ) -> bool {
+        let (index, tree_size) = (index.into().saturating_usize(), tree_size.into().saturating_usize());
+        index < tree_size
+            && anchor_level <= tree_depth(tree_size)
+            && proof.tree_size.is_none_or(|size| size == tree_size)
+            && proof.len() == anchor_level
+            && follows_path(proof, index, tree_size)
+            && MerkleTree::verify_proof_to_anchor(data, proof, anchor, anchor_level)
+    }
+
+    /// Like `verify_proof`, but starting from an already hashed leaf
+    ///
+    /// Neither allocates for a proof of 32-byte hashes, see `verify_proof_raw`.
+    pub fn verify_proof_hash(leaf_hash: &Hash, proof: &Proof, root_hash: &Hash) -> bool {
+        match <&[u8; HASH_LEN]>::try_from(leaf_hash.as_slice()) {
+            Ok(leaf_hash) => verify_proof_sha256(leaf_hash, proof, root_hash),
+            Err(_) => MerkleTree::verify_proof_hash_with(&Sha256Hasher, leaf_hash, proof, root_hash),
         }
-        &hash == root_hash
+    }
+
+    /// Like `verify_proof_hash`, for a proof from a tree built with another hasher
+    pub fn verify_proof_hash_with<H: MerkleHasher + ?Sized>(
+        hasher: &H,
+        leaf_hash: &Hash,
+        proof: &Proof,
+        root_hash: &Hash,
+    ) -> bool {
+        proof.fits_tree_size() && &proof.root_from_with(hasher, leaf_hash) == root_hash
     }
 
     /// Returns a list of hashes that can be used to prove that the given data is in this tree
-    pub fn prove(&self, data: &Data) -> Option<Proof> {
-        let mut proof = Proof::default();
-        if self.find_proof(data, &mut proof) {
-            Some(proof)
-        } else {
-            None
+    ///
+    /// If the data occurs more than once, the proof is for its leftmost occurrence.
+    pub fn prove(&self, data: &Data) -> Option<Proof<'_>> {
+        let leaf_hash = self.hasher.leaf_hash(data);
+        let index = self.levels[0].iter().position(|h| h == &leaf_hash)?;
+        self.prove_by_index(index).ok()
+    }
+
+    /// Proofs of a batch of data, each with the index of the leftmost leaf it occurs at
+    ///
+    /// The leaves are scanned once for the whole batch. Results are in the order of `items`, and an
+    /// item not in the tree gets a `LibError::LeafNotFound` of its own rather than failing the
+    /// batch; an item repeated in `items` gets the same proof each time.
+    pub fn prove_many(&self, items: &[Data]) -> Vec<Result<(usize, OwnedProof), LibError>> {
+        let leaves: Vec<Hash> = items.iter().map(|data| self.hasher.leaf_hash(data)).collect();
+        self.prove_many_leaves(&leaves)
+    }
+
+    /// Like `prove_many`, for items that already are leaf hashes
+    pub fn prove_many_leaves(&self, leaves: &[Hash]) -> Vec<Result<(usize, OwnedProof), LibError>> {
+        // only looked up, so the order of the results does not depend on the map
+        let mut found: HashMap<&Hash, Option<usize>> = leaves.iter().map(|leaf| (leaf, None)).collect();
+        let mut missing = found.len();
+        for (index, leaf) in self.levels[0].iter().enumerate() {
+            if missing == 0 {
+                break;
+            }
+            if let Some(slot @ None) = found.get_mut(leaf) {
+                *slot = Some(index);
+                missing -= 1;
+            }
         }
+        leaves
+            .iter()
+            .map(|leaf| match found.get(leaf).copied().flatten() {
+                Some(index) => Ok((index, self.prove_by_index(index)?.into_owned())),
+                None => Err(LibError::LeafNotFound(hex::encode(leaf))),
+            })
+            .collect()
     }
 
-    fn find_proof<'a>(&'a self, data: &Data, proof: &mut Proof<'a>) -> bool {
-        if &self.hash == &hash_data(data) {
-            return true;
+    /// Returns the proof for the leaf at the given index, tagged with the size of the tree
+    ///
+    /// The only leaf of a one-leaf tree is also its root, so its proof is empty.
+    pub fn prove_by_index(&self, index: impl Into<LeafIndex>) -> Result<Proof<'_>, LibError> {
+        let (proof, _) = self.path_to_level(index.into().saturating_usize(), self.depth())?;
+        Ok(proof.with_tree_size(self.len()))
+    }
+
+    /// Returns the proof for the leaf at `index` up to its ancestor at `stop_level`, and the hash
+    /// of that ancestor
+    ///
+    /// The proof is that of `prove_by_index` without the steps at `stop_level` and above, for
+    /// verifiers that trust the ancestor, see `verify_proof_to_anchor`. It is not tagged with the
+    /// tree size, since it does not reach the root. Fails with `LibError::LevelOutOfRange` for a
+    /// level above the root.
+    pub fn prove_to_level(
+        &self,
+        index: impl Into<LeafIndex>,
+        stop_level: usize,
+    ) -> Result<(Proof<'_>, &Hash), LibError> {
+        let index = index.into().saturating_usize();
+        if index < self.len() && stop_level > self.depth() {
+            return Err(LibError::LevelOutOfRange { level: stop_level, depth: self.depth() });
         }
+        let (proof, anchor) = self.path_to_level(index, stop_level)?;
+        Ok((proof, self.node_hash(anchor)))
+    }
+
+    /// The untagged path of the leaf at `index` up to `stop_level`, and the node it stops at
+    fn path_to_level(&self, index: usize, stop_level: usize) -> Result<(Proof<'_>, NodeId), LibError> {
+        let mut id = self.leaf_id(index).ok_or(LibError::IndexOutOfRange {
+            index,
+            size: self.len(),
+        })?;
 
-        if let Some(ref left) = self.left {
-            if left.find_proof(data, proof) {
-                proof.hashes.push((HashDirection::Right, &self.right.as_ref().unwrap().hash));
-                return true;
+        let mut proof = Proof::default();
+        while let Some(parent) = self.parent(id).filter(|_| id.level < stop_level) {
+            // a promoted node has no sibling and adds nothing at this level
+            if let Some(sibling) = self.sibling(id) {
+                let direction = if sibling == id {
+                    HashDirection::Duplicate
+                } else if id.index % 2 == 0 {
+                    HashDirection::Right
+                } else {
+                    HashDirection::Left
+                };
+                proof.hashes.push((direction, self.node_hash(sibling)));
             }
+            id = parent;
         }
+        Ok((proof, id))
+    }
 
-        if let Some(ref right) = self.right {
-            if right.find_proof(data, proof) {
-                proof.hashes.push((HashDirection::Left, &self.left.as_ref().unwrap().hash));
-                return true;
-            }
+    /// Number of sibling hashes in the proof of the leaf at `index`, without generating it
+    pub fn proof_len(&self, index: impl Into<LeafIndex>) -> Result<usize, LibError> {
+        let index = index.into().saturating_usize();
+        if index >= self.len() {
+            return Err(LibError::IndexOutOfRange {
+                index,
+                size: self.len(),
+            });
         }
+        Ok(expected_proof_len(self.len(), index, self.policy))
+    }
+
+    /// Id of the leaf at the given index
+    pub fn leaf_id(&self, index: usize) -> Option<NodeId> {
+        self.id(0, index)
+    }
+
+    /// Id of the root node
+    pub fn root_id(&self) -> NodeId {
+        NodeId {
+            level: self.depth(),
+            index: 0,
+        }
+    }
+
+    /// Hash stored at the given node
+    pub fn node(&self, id: NodeId) -> Option<&Hash> {
+        self.levels.get(id.level)?.get(id.index)
+    }
 
-        false
+    /// Parent of the given node, or None for the root
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        if id.level >= self.depth() {
+            return None;
+        }
+        self.id(id.level + 1, id.index / 2)
+    }
+
+    /// Node paired with the given one when hashing their parent, or None for the root
+    ///
+    /// The last node of an odd-sized level is paired with itself, so it is its own sibling,
+    /// unless the tree promotes lone nodes, in which case it has none.
+    pub fn sibling(&self, id: NodeId) -> Option<NodeId> {
+        if id.level >= self.depth() {
+            return None;
+        }
+        self.id(id.level, id.index ^ 1).or_else(|| match self.policy {
+            OddLeafPolicy::Duplicate => self.id(id.level, id.index),
+            OddLeafPolicy::Promote => None,
+        })
     }
+
+    /// Left and right children of the given node, or None for a leaf
+    ///
+    /// A parent of a lone node at the end of an odd-sized level has that node as both children.
+    pub fn children(&self, id: NodeId) -> Option<(NodeId, NodeId)> {
+        if id.level == 0 {
+            return None;
+        }
+        let left = self.id(id.level - 1, id.index.checked_mul(2)?)?;
+        let right = self.id(id.level - 1, left.index + 1).unwrap_or(left);
+        Some((left, right))
+    }
+
+    fn id(&self, level: usize, index: usize) -> Option<NodeId> {
+        let id = NodeId { level, index };
+        self.node(id).map(|_| id)
+    }
+
+    fn node_hash(&self, id: NodeId) -> &Hash {
+        &self.levels[id.level][id.index]
+    }
+}
+
+/// Hashes each pair of nodes into their parent, handling a lone last node according to `policy`
+///
+/// This is the only place odd levels are padded; every constructor, sync or async, goes through it.
+pub(crate) fn parent_level(nodes: &[Hash], policy: OddLeafPolicy, hasher: HashAlgorithm) -> Vec<Hash> {
+    parent_level_with(nodes, policy, |left, right| hash_pair(hasher, left, right))
+}
+
+/// Like `parent_level`, computing each parent with `pair`
+pub(crate) fn parent_level_with(
+    nodes: &[Hash],
+    policy: OddLeafPolicy,
+    mut pair: impl FnMut(&Hash, &Hash) -> Hash,
+) -> Vec<Hash> {
+    nodes
+        .chunks(2)
+        .map(|chunk| match (chunk, policy) {
+            ([left, right], _) => pair(left, right),
+            ([lone], OddLeafPolicy::Duplicate) => pair(lone, lone),
+            ([lone], OddLeafPolicy::Promote) => lone.clone(),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Number of levels above the leaves of a tree with `leaf_count` leaves
+///
+/// Computed from the highest bit of `leaf_count - 1`, since rounding up to the next power of two
+/// overflows for counts above half the range of `usize`.
+pub(crate) fn tree_depth(leaf_count: usize) -> usize {
+    (usize::BITS - leaf_count.saturating_sub(1).leading_zeros()) as usize
+}
+
+/// Whether the directions of `proof` are those of the path of leaf `index` in a tree of `tree_size`
+/// leaves, for as many levels as the proof has steps
+///
+/// `HashDirection::Duplicate` steps are only accepted where the path passes through a lone node.
+fn follows_path(proof: &Proof, index: usize, tree_size: usize) -> bool {
+    let bit = u32::try_from(proof.len()).ok().and_then(|len| 1usize.checked_shl(len));
+    let below = bit.map_or(usize::MAX, |bit| bit - 1);
+    proof.implied_index().is_ok_and(|implied| implied == index & below)
+        && proof.steps().iter().enumerate().all(|(level, (direction, _))| {
+            *direction != HashDirection::Duplicate || is_lone_on_path(tree_size, index, level)
+        })
+}
+
+/// Whether the path of leaf `index` passes through the lone last node of an odd-sized `level`
+///
+/// That node is paired with itself under `OddLeafPolicy::Duplicate`, giving a
+/// `HashDirection::Duplicate` step, and has no step at all under `OddLeafPolicy::Promote`.
+pub(crate) fn is_lone_on_path(tree_size: usize, index: usize, level: usize) -> bool {
+    let shift = |value: usize| u32::try_from(level).ok().and_then(|level| value.checked_shr(level));
+    let (Some(last), Some(node)) = (shift(tree_size.saturating_sub(1)), shift(index)) else {
+        return false;
+    };
+    node == last && last > 0 && last % 2 == 0
+}
+
+/// Number of sibling hashes in the proof of leaf `index` of a tree with `tree_size` leaves
+///
+/// Under `OddLeafPolicy::Duplicate` this is the tree depth for every leaf. Under
+/// `OddLeafPolicy::Promote` every level where the path passes through a lone last node is skipped,
+/// so some leaves at the right edge get shorter proofs. `index` is expected to be below `tree_size`.
+pub fn expected_proof_len(tree_size: impl Into<TreeSize>, index: impl Into<LeafIndex>, policy: OddLeafPolicy) -> usize {
+    let (tree_size, index) = (tree_size.into().saturating_usize(), index.into().saturating_usize());
+    if policy == OddLeafPolicy::Duplicate {
+        return tree_depth(tree_size);
+    }
+    let (mut len, mut index, mut hashes) = (tree_size, index, 0);
+    while len > 1 {
+        if !(len % 2 == 1 && index == len - 1) {
+            hashes += 1;
+        }
+        len = len.div_ceil(2);
+        index /= 2;
+    }
+    hashes
+}
+
+/// Root of the tree `MerkleTree::build` would build over `leaves` with `OddLeafPolicy::Duplicate`,
+/// folding one level into the next without keeping the levels
+///
+/// A single leaf is its own root, as Bitcoin's rule for blocks of one transaction has it with
+/// `HashAlgorithm::Sha256d`. Fails with `LibError::EmptyInput` without leaves.
+pub fn fold_root(mut leaves: Vec<Hash>, hasher: HashAlgorithm) -> Result<Hash, LibError> {
+    while leaves.len() > 1 {
+        leaves = parent_level(&leaves, OddLeafPolicy::Duplicate, hasher);
+    }
+    leaves.pop().ok_or(LibError::EmptyInput)
+}
+
+/// Number of hashes over all levels of a tree with `leaf_count` leaves, under either odd leaf policy
+///
+/// Counted in `u64` so sizes can be checked before allocating on any target; fails with
+/// `LibError::Overflow` if even that is too small.
+pub fn checked_node_count(leaf_count: u64) -> Result<u64, LibError> {
+    let (mut len, mut total) = (leaf_count, 0u64);
+    while len > 0 {
+        total = total.checked_add(len).ok_or(LibError::Overflow("node count"))?;
+        if len == 1 {
+            break;
+        }
+        len = len.div_ceil(2);
+    }
+    Ok(total)
+}
+
+/// Converts a size or index read from a serialized format, which are stored as `u64`
+pub(crate) fn to_usize(value: u64, what: &'static str) -> Result<usize, LibError> {
+    usize::try_from(value).map_err(|_| LibError::Overflow(what))
+}
+
+/// Leaf hash of the tree's hasher, see `MerkleHasher::leaf_hash`
+pub(crate) fn hash_data(data: &Data) -> Hash {
+    hash_bytes(data)
+}
+
+fn hash_bytes(data: &[u8]) -> Hash {
+    Sha256Hasher.leaf_hash(data)
 }
 
-fn hash_data(data: &Data) -> Hash {
-    sha2::Sha256::digest(data).to_vec()
+#[cfg(test)]
+pub(crate) fn hash_concat(h1: &Hash, h2: &Hash) -> Hash {
+    hash_pair(HashAlgorithm::Sha256, h1, h2)
 }
 
-fn hash_concat(h1: &Hash, h2: &Hash) -> Hash {
-    let h3 = h1.iter().chain(h2).copied().collect();
-    hash_data(&h3)
+pub(crate) fn hash_pair(hasher: HashAlgorithm, h1: &Hash, h2: &Hash) -> Hash {
+    #[cfg(test)]
+    tests::NODE_HASHES.with(|count| count.set(count.get() + 1));
+    hasher.node_hash(h1, h2)
 }
 
 
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
-    // const CHARSET: &[u8] = b"0123456789abcdef";
-    
+    use crate::merkel::{MerkleBuilder, TreeConfig};
+    use crate::util::generate::random_leaves;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use std::cell::Cell;
+
+    thread_local! {
+        /// Number of node hashes computed on this thread
+        pub(crate) static NODE_HASHES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Runs `f` and returns how many node hashes it computed
+    pub(crate) fn count_node_hashes<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let before = NODE_HASHES.with(Cell::get);
+        let result = f();
+        (result, NODE_HASHES.with(Cell::get) - before)
+    }
+
     fn example_data(n: usize) -> Vec<Data> {
         let mut data = vec![];
         for i in 0..n {
@@ -144,26 +1292,8 @@ mod tests {
         data
     }
 
-    //for btc transactions hashes
-    // fn generate_hash_data(n:usize, length: usize) -> Vec<Data> {
-    //     let mut rng = rand::thread_rng();
-    //     (0..n)
-    //     .map(|_| {
-    //         (0..length)
-    //             .map(|_| {
-    //                 let idx = rng.gen_range(0..CHARSET.len());
-    //                 CHARSET[idx]
-    //             })
-    //             .collect::<Vec<u8>>() 
-    //     })
-    //     .collect() // 
-    // }
-
-
     #[test]
     fn test_constructions() {
-
-        // let data1 = generate_hash_data(8,64);
         let data = example_data(4);
         let tree = MerkleTree::construct(&data);
         println!("Root Hash: {:?}", hex::encode(tree.root()));
@@ -192,13 +1322,37 @@ mod tests {
         let data = example_data(4);
         let tree = MerkleTree::construct(&data);
         let root_hash = tree.root();
-        assert!(MerkleTree::verify(&data, &root_hash));
+        assert!(MerkleTree::verify_ordered(&data, &root_hash));
 
 
         let data = example_data(8);
         let tree = MerkleTree::construct(&data);
         let root_hash = tree.root();
-        assert!(MerkleTree::verify(&data, &root_hash));
+        assert!(MerkleTree::verify_ordered(&data, &root_hash));
+        assert!(!MerkleTree::verify_ordered(&[], &root_hash));
+        #[allow(deprecated)]
+        let shim = (MerkleTree::verify(&data, &root_hash), MerkleTree::verify(&[], &root_hash));
+        assert_eq!(shim, (true, false));
+    }
+
+    #[test]
+    fn test_verify_is_ordered() {
+        // two identical entries: swapping them gives the same input, and so the same root
+        let (a, b) = (b"a".to_vec(), b"b".to_vec());
+        let same = vec![a.clone(), a.clone()];
+        let root = MerkleTree::construct(&same).root();
+        assert!(MerkleTree::verify_ordered(&same, &root));
+        // any reordering that moves a different entry does not verify
+        let data = vec![a.clone(), a.clone(), b.clone()];
+        let root = MerkleTree::construct(&data).root();
+        for other in [vec![a.clone(), b.clone(), a.clone()], vec![b.clone(), a.clone(), a.clone()]] {
+            assert!(!MerkleTree::verify_ordered(&other, &root), "{:?}", other);
+        }
+        // nor does a subset or a superset, except for a repeat of the lone last entry, which the
+        // tree duplicates anyway
+        assert!(!MerkleTree::verify_ordered(&data[..2], &root));
+        assert!(!MerkleTree::verify_ordered(&[data.clone(), vec![a]].concat(), &root));
+        assert!(MerkleTree::verify_ordered(&[data.clone(), vec![b]].concat(), &root));
     }
 
     // #[test]
@@ -224,4 +1378,687 @@ mod tests {
         assert!(!proof.hashes.is_empty());
         assert!(MerkleTree::verify_proof(&data[3], &proof, &tree.root()));
     }
+
+    #[test]
+    fn test_prove_many() {
+        let mut data = example_data(9);
+        data[7] = data[2].clone();
+        let tree = MerkleTree::construct(&data);
+        let absent = b"not a leaf".to_vec();
+        let items = [data[5].clone(), absent.clone(), data[2].clone(), data[5].clone(), data[8].clone()];
+
+        let results = tree.prove_many(&items);
+        assert_eq!(results.len(), items.len());
+        // the repeated leaf is proven at its leftmost index, and the repeated item twice alike
+        let indices: Vec<Option<usize>> = results.iter().map(|result| result.as_ref().ok().map(|ok| ok.0)).collect();
+        assert_eq!(indices, [Some(5), None, Some(2), Some(5), Some(8)]);
+        for (item, result) in items.iter().zip(&results) {
+            if let Ok((index, proof)) = result {
+                assert_eq!(proof, &tree.prove_by_index(*index).unwrap().into_owned());
+                assert!(MerkleTree::verify_proof(item, &proof.as_proof(), &tree.root()));
+            }
+        }
+        let missing = hex::encode(tree.hasher().leaf_hash(&absent));
+        assert!(matches!(&results[1], Err(LibError::LeafNotFound(leaf)) if *leaf == missing));
+
+        let leaves = [tree.leaves()[8].clone(), vec![0; 32]];
+        let by_leaf: Vec<bool> = tree.prove_many_leaves(&leaves).iter().map(Result::is_ok).collect();
+        assert_eq!(by_leaf, [true, false]);
+        assert!(tree.prove_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_random_txid_trees() {
+        let mut rng = StdRng::seed_from_u64(0x7478_6964);
+        for n in 1..=64 {
+            let data = random_leaves(n, 64, &mut rng);
+            let tree = MerkleTree::construct(&data);
+            assert_eq!((tree.len(), tree.depth()), (n, n.next_power_of_two().trailing_zeros() as usize));
+            assert!(MerkleTree::verify_ordered(&data, &tree.root()));
+
+            for (index, leaf) in data.iter().enumerate() {
+                let proof = tree.prove_by_index(index).unwrap();
+                assert_eq!((proof.len(), proof.tree_size()), (tree.depth(), Some(n)));
+                assert!(MerkleTree::verify_proof_at_index(leaf, &proof, index, n, &tree.root()));
+
+                let json = serde_json::to_string(&proof.into_owned()).unwrap();
+                let decoded: OwnedProof = serde_json::from_str(&json).unwrap();
+                assert!(MerkleTree::verify_proof_at_index(leaf, &decoded.as_proof(), index, n, &tree.root()));
+            }
+        }
+
+        // a seeded generator gives the same leaves, and so the same tree, every time
+        let again = random_leaves(64, 64, &mut StdRng::seed_from_u64(1));
+        assert_eq!(again, random_leaves(64, 64, &mut StdRng::seed_from_u64(1)));
+        assert!(again.iter().all(|leaf| leaf.len() == 64 && hex::decode(leaf).is_ok()));
+    }
+
+    #[test]
+    fn test_verify_proof_at_index() {
+        let data = example_data(8);
+        let tree = MerkleTree::construct(&data);
+        for (index, leaf) in data.iter().enumerate() {
+            let proof = tree.prove_by_index(index).unwrap();
+            assert_eq!(proof.implied_index().unwrap(), index);
+            assert!(MerkleTree::verify_proof_at_index(leaf, &proof, index, 8, &tree.root()));
+            assert!(!MerkleTree::verify_proof_at_index(leaf, &proof, index ^ 1, 8, &tree.root()));
+        }
+
+        // duplicate data verifies at its other position only with that position's own proof
+        let mut data = example_data(4);
+        data[3] = data[0].clone();
+        let tree = MerkleTree::construct(&data);
+        let proof = tree.prove_by_index(0).unwrap();
+        assert!(MerkleTree::verify_proof(&data[3], &proof, &tree.root()));
+        assert!(!MerkleTree::verify_proof_at_index(&data[3], &proof, 3, 4, &tree.root()));
+        assert!(MerkleTree::verify_proof_at_index(&data[3], &tree.prove_by_index(3).unwrap(), 3, 4, &tree.root()));
+    }
+
+    #[test]
+    fn test_verify_proof_at_index_with_padding() {
+        let data = example_data(5);
+        let tree = MerkleTree::construct(&data);
+        let proof = tree.prove_by_index(4).unwrap();
+        assert_eq!(proof.implied_index().unwrap(), 4);
+        assert!(MerkleTree::verify_proof_at_index(&data[4], &proof, 4, 5, &tree.root()));
+
+        // the padded leaf is its own sibling, so flipping its direction still reaches the root,
+        // but only at index 5, which does not exist
+        let mut flipped = proof.into_owned();
+        flipped.hashes[0].0 = HashDirection::Left;
+        let flipped = flipped.as_proof();
+        assert_eq!(flipped.implied_index().unwrap(), 5);
+        assert!(MerkleTree::verify_proof(&data[4], &flipped, &tree.root()));
+        assert!(!MerkleTree::verify_proof_at_index(&data[4], &flipped, 5, 5, &tree.root()));
+        assert!(!MerkleTree::verify_proof_at_index(&data[4], &flipped, 4, 5, &tree.root()));
+
+        // a proof of the right shape but for a different tree size is rejected
+        let proof = tree.prove_by_index(4).unwrap();
+        assert!(!MerkleTree::verify_proof_at_index(&data[4], &proof, 4, 9, &tree.root()));
+    }
+
+    #[test]
+    fn test_verify_proof_hash_at_index_with() {
+        let data = example_data(5);
+        for hasher in HashAlgorithm::ALL {
+            let tree = TreeConfig::default().with_hasher(hasher).construct(&data).unwrap();
+            let (leaf, root) = (&tree.leaves()[4], tree.root());
+            let verify = |proof: &Proof, index, tree_size| {
+                MerkleTree::verify_proof_hash_at_index_with(&hasher, leaf, proof, index, tree_size, &root)
+            };
+            let proof = tree.prove_by_index(4).unwrap();
+            assert!(verify(&proof, 4, 5), "{:?}", hasher);
+            assert!(!verify(&proof, 0, 5) && !verify(&proof, 4, 6));
+            // the padded last leaf shown at the index past the end, as `verify_proof_at_index` rejects it
+            let mut flipped = proof.into_owned();
+            flipped.hashes[0].0 = HashDirection::Left;
+            assert!(MerkleTree::verify_proof_hash_with(&hasher, leaf, &flipped.as_proof(), &root));
+            assert!(!verify(&flipped.as_proof(), 5, 5) && !verify(&flipped.as_proof(), 5, 6));
+        }
+    }
+
+    #[test]
+    fn test_prove_to_level() {
+        let data: Vec<Data> = (0..1024u32).map(|i| i.to_le_bytes().to_vec()).collect();
+        let tree = MerkleTree::construct(&data);
+        // the light client's cache: every node 3 levels above the leaves
+        let anchors = tree.levels()[3].clone();
+        for (index, leaf) in data.iter().enumerate() {
+            let (proof, anchor) = tree.prove_to_level(index, 3).unwrap();
+            assert_eq!((proof.len(), proof.tree_size(), anchor), (3, None, &anchors[index >> 3]));
+            assert!(MerkleTree::verify_proof_to_anchor(leaf, &proof, anchor, 3), "leaf {}", index);
+            assert!(MerkleTree::verify_proof_to_anchor_at_index(leaf, &proof, index, 1024, anchor, 3));
+            assert!(!MerkleTree::verify_proof_to_anchor(leaf, &proof, &anchors[(index >> 3) ^ 1], 3));
+            assert!(!MerkleTree::verify_proof_to_anchor_at_index(leaf, &proof, index ^ 1, 1024, anchor, 3));
+            assert!(!MerkleTree::verify_proof_to_anchor_at_index(leaf, &proof, index, 1024, anchor, 4));
+            assert!(!MerkleTree::verify_proof_to_anchor(leaf, &proof, anchor, 2));
+
+            // the rest of the full proof leads on from the anchor to the root
+            let full = tree.prove_by_index(index).unwrap();
+            assert_eq!(proof.steps(), &full.steps()[..3]);
+            let rest = OwnedProof::new(full.steps()[3..].to_vec());
+            assert!(MerkleTree::verify_proof_hash(anchor, &rest.as_proof(), &tree.root()));
+            assert!(MerkleTree::verify_proof(leaf, &full, &tree.root()));
+        }
+
+        let (proof, root) = tree.prove_to_level(5, tree.depth()).unwrap();
+        assert_eq!((proof.with_tree_size(1024), root), (tree.prove_by_index(5).unwrap(), &tree.root()));
+        let (proof, leaf) = tree.prove_to_level(5, 0).unwrap();
+        assert!(proof.is_empty() && MerkleTree::verify_proof_to_anchor(&data[5], &proof, leaf, 0));
+        let above = tree.prove_to_level(5, 11);
+        assert!(matches!(above, Err(LibError::LevelOutOfRange { level: 11, depth: 10 })));
+        assert!(matches!(tree.prove_to_level(1024, 11), Err(LibError::IndexOutOfRange { .. })));
+    }
+
+    #[test]
+    fn test_prove_to_level_at_edges() {
+        // leaf 4 of 5 is paired with itself at the levels below its anchor
+        let data = example_data(5);
+        let tree = MerkleTree::construct(&data);
+        let (proof, anchor) = tree.prove_to_level(4, 2).unwrap();
+        assert_eq!(proof.steps().iter().map(|(d, _)| *d).collect::<Vec<_>>(), [HashDirection::Duplicate; 2]);
+        assert!(MerkleTree::verify_proof_to_anchor_at_index(&data[4], &proof, 4, 5, anchor, 2));
+        assert!(!MerkleTree::verify_proof_to_anchor_at_index(&data[4], &proof, 4, 9, anchor, 2));
+
+        // promoted nodes have no step, so proofs may stop short of their level
+        let promote = TreeConfig::default().with_odd_leaf_policy(OddLeafPolicy::Promote);
+        let tree = promote.construct(&data).unwrap();
+        let (proof, anchor) = tree.prove_to_level(4, 2).unwrap();
+        assert!(proof.is_empty() && anchor == &tree.leaves()[4]);
+        assert!(MerkleTree::verify_proof_to_anchor(&data[4], &proof, anchor, 2));
+        assert!(!MerkleTree::verify_proof_to_anchor_at_index(&data[4], &proof, 4, 5, anchor, 2));
+        let tagged = tree.prove_by_index(0).unwrap();
+        assert!(!MerkleTree::verify_proof_to_anchor(&data[0], &tagged, &tree.root(), 4));
+        assert!(MerkleTree::verify_proof_to_anchor(&data[0], &tagged, &tree.root(), 3));
+    }
+
+    #[test]
+    fn test_padding_hashes_each_node_once() {
+        // a lone node is paired with itself, so padding costs one hash per level and every
+        // stored node is computed exactly once, by `construct` and by the streaming
+        // `MerkleBuilder` alike. Only the count matters, so the large tree is hashed with the
+        // cheap `XorFoldHasher`
+        let size = (1 << 20) + 1;
+        let data: Vec<Data> = (0..size as u32).map(|i| i.to_le_bytes().to_vec()).collect();
+        let config = TreeConfig::default().with_hasher(HashAlgorithm::XorFold);
+        let (tree, hashes) = count_node_hashes(|| config.construct(&data).unwrap());
+        assert_eq!(hashes, tree.node_count() - size);
+        assert_eq!(hashes, (1 << 20) + 20);
+        let mut builder = MerkleBuilder::new(config);
+        for entry in &data {
+            builder.add_leaf(entry).unwrap();
+        }
+        let (built, built_hashes) = count_node_hashes(|| builder.build().unwrap());
+        assert_eq!((built.root(), built_hashes), (tree.root(), hashes));
+
+        for size in [1, 2, 3, 5, 33, 1000] {
+            let data = example_data(size);
+            let (tree, hashes) = count_node_hashes(|| MerkleTree::construct(&data));
+            assert_eq!(hashes, tree.node_count() - size, "size {}", size);
+        }
+    }
+
+    #[test]
+    fn test_wipe_leaf_data() {
+        let data = example_data(5);
+        let mut tree = MerkleTree::construct_retaining(data.clone());
+        assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+        assert_eq!(tree.leaf_data(3), Some(&data[3][..]));
+        assert_eq!(tree.leaf_data(5), None);
+
+        tree.wipe_leaf_data();
+        assert_eq!(tree.leaf_data(3), None);
+        for (index, leaf) in data.iter().enumerate() {
+            assert!(MerkleTree::verify_proof(leaf, &tree.prove_by_index(index).unwrap(), &tree.root()));
+        }
+        assert_eq!(MerkleTree::construct(&data).leaf_data(0), None);
+    }
+
+    #[test]
+    fn test_proof_len_matches_proofs() {
+        for policy in [OddLeafPolicy::Duplicate, OddLeafPolicy::Promote] {
+            let config = TreeConfig::default().with_odd_leaf_policy(policy);
+            for size in 1..=33 {
+                let data = example_data(size);
+                let tree = config.construct(&data).unwrap();
+                for (index, leaf) in data.iter().enumerate() {
+                    let proof = tree.prove_by_index(index).unwrap();
+                    assert_eq!(expected_proof_len(size, index, policy), proof.len(), "{:?} {} {}", policy, size, index);
+                    assert_eq!(tree.proof_len(index).unwrap(), proof.len());
+                    assert!(MerkleTree::verify_proof(leaf, &proof, &tree.root()));
+                }
+                assert!(matches!(tree.proof_len(size), Err(LibError::IndexOutOfRange { .. })));
+            }
+        }
+
+        // the lone leaf 4 of a 5-leaf tree is promoted twice before it meets a sibling
+        assert_eq!(expected_proof_len(5, 4, OddLeafPolicy::Promote), 1);
+        assert_eq!(expected_proof_len(5, 4, OddLeafPolicy::Duplicate), 3);
+        assert_eq!(expected_proof_len(1, 0, OddLeafPolicy::Promote), 0);
+    }
+
+    #[test]
+    fn test_promote_policy_root() {
+        let data = example_data(3);
+        let tree = TreeConfig::default().with_odd_leaf_policy(OddLeafPolicy::Promote).construct(&data).unwrap();
+        let leaves: Vec<Hash> = data.iter().map(hash_data).collect();
+        assert_eq!(tree.root(), hash_concat(&hash_concat(&leaves[0], &leaves[1]), &leaves[2]));
+        assert_eq!(tree.odd_leaf_policy(), OddLeafPolicy::Promote);
+        assert_eq!(tree.sibling(tree.leaf_id(2).unwrap()), None);
+        assert_ne!(tree.root(), MerkleTree::construct(&data).root());
+    }
+
+    fn walk_proof(tree: &MerkleTree, index: usize) -> Vec<(HashDirection, &[u8])> {
+        let mut hashes = vec![];
+        let mut id = tree.leaf_id(index).unwrap();
+        while let Some(parent) = tree.parent(id) {
+            let sibling = tree.sibling(id).unwrap();
+            let (left, _) = tree.children(parent).unwrap();
+            let direction = match (sibling == id, left == id) {
+                (true, _) => HashDirection::Duplicate,
+                (false, true) => HashDirection::Right,
+                (false, false) => HashDirection::Left,
+            };
+            hashes.push((direction, &tree.node(sibling).unwrap()[..]));
+            id = parent;
+        }
+        assert_eq!(id, tree.root_id());
+        hashes
+    }
+
+    #[test]
+    fn test_navigation() {
+        let data = example_data(5);
+        let tree = MerkleTree::construct(&data);
+        assert_eq!(tree.depth(), 3);
+        assert!(tree.leaf_id(5).is_none());
+
+        let root = tree.root_id();
+        assert_eq!(tree.node(root), Some(&tree.root()));
+        assert!(tree.parent(root).is_none());
+        assert!(tree.sibling(root).is_none());
+
+        let leaf = tree.leaf_id(1).unwrap();
+        assert_eq!(tree.node(leaf), Some(&hash_data(&data[1])));
+        assert!(tree.children(leaf).is_none());
+        assert_eq!(tree.sibling(leaf), tree.leaf_id(0));
+
+        // the padded last leaf is paired with itself
+        let last = tree.leaf_id(4).unwrap();
+        assert_eq!(tree.sibling(last), Some(last));
+        let parent = tree.parent(last).unwrap();
+        assert_eq!((parent.level(), parent.index()), (1, 2));
+        assert_eq!(tree.children(parent), Some((last, last)));
+        assert_eq!(tree.node(parent), Some(&hash_concat(&hash_data(&data[4]), &hash_data(&data[4]))));
+
+        for id in [leaf, parent, root] {
+            if let Some((left, right)) = tree.children(id) {
+                let expected = hash_concat(tree.node(left).unwrap(), tree.node(right).unwrap());
+                assert_eq!(tree.node(id), Some(&expected));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sibling_walk_matches_prove_by_index() {
+        for n in 1..=9 {
+            let data = example_data(n);
+            let tree = MerkleTree::construct(&data);
+            for (i, leaf) in data.iter().enumerate() {
+                let proof = tree.prove_by_index(i).unwrap();
+                assert_eq!(walk_proof(&tree, i), proof.hashes.as_slice());
+                assert!(MerkleTree::verify_proof(leaf, &proof, &tree.root()));
+            }
+            assert!(matches!(
+                tree.prove_by_index(n),
+                Err(LibError::IndexOutOfRange { index, size }) if index == n && size == n
+            ));
+        }
+    }
+
+    #[test]
+    fn test_levels_hash_pairwise() {
+        let data: Vec<Data> = include_str!("../../tests/fixtures/hashes.txt")
+            .lines()
+            .map(|line| hex::decode(line).unwrap())
+            .collect();
+        let levels = MerkleTree::construct_with_levels(&data);
+
+        assert_eq!(levels.iter().map(Vec::len).collect::<Vec<_>>(), vec![7, 4, 2, 1]);
+        assert_eq!(levels[0], data.iter().map(hash_data).collect::<Vec<_>>());
+        for k in 1..levels.len() {
+            for (i, parent) in levels[k].iter().enumerate() {
+                let left = &levels[k - 1][2 * i];
+                let right = levels[k - 1].get(2 * i + 1).unwrap_or(left);
+                assert_eq!(parent, &hash_concat(left, right), "level {} node {}", k, i);
+            }
+        }
+        assert_eq!(levels[3][0], MerkleTree::construct(&data).root());
+        assert_eq!(MerkleTree::construct(&data).levels(), levels.as_slice());
+    }
+
+    #[test]
+    fn test_owned_proof_versions() {
+        let tree = MerkleTree::construct(&example_data(5));
+        let proof = tree.prove_by_index(4).unwrap().into_owned();
+        let value = serde_json::to_value(&proof).unwrap();
+        assert_eq!((&value["version"], &value["steps"][0]["direction"]), (&2.into(), &"duplicate".into()));
+        assert_eq!(serde_json::from_value::<OwnedProof>(value.clone()).unwrap(), proof);
+
+        assert_eq!((value["tree_size"].clone(), proof.tree_size()), (5.into(), Some(5)));
+
+        // the unversioned list of steps is read as version 1, without a tree size, which has no
+        // duplicate steps: they were written as right steps
+        let mut legacy = value["steps"].clone();
+        let err = serde_json::from_value::<OwnedProof>(legacy.clone()).unwrap_err().to_string();
+        assert!(err.contains("duplicate steps need format version 2, found version 1"), "{}", err);
+        for step in 0..2 {
+            assert_eq!(legacy[step]["direction"], "duplicate");
+            legacy[step]["direction"] = "right".into();
+        }
+        let legacy = serde_json::from_value::<OwnedProof>(legacy).unwrap();
+        assert_eq!(legacy.hashes()[2], proof.hashes()[2]);
+        assert!(MerkleTree::verify_proof_at_index(&example_data(5)[4], &legacy.as_proof(), 4, 5, &tree.root()));
+
+        let future = serde_json::json!({ "version": 3, "steps": value["steps"], "index": 4 });
+        let err = serde_json::from_value::<OwnedProof>(future).unwrap_err().to_string();
+        assert!(err.contains("unsupported format version 3, supported versions are [1, 2]"), "{}", err);
+        assert!(serde_json::from_value::<OwnedProof>(serde_json::json!({ "steps": [] })).is_err());
+    }
+
+    #[test]
+    fn test_owned_proof_orders() {
+        let data = example_data(7);
+        let tree = MerkleTree::construct(&data);
+        let proof = tree.prove_by_index(5).unwrap().into_owned();
+        let root_first = proof.reversed();
+        assert_eq!((proof.order(), root_first.order()), (ProofOrder::LeafToRoot, ProofOrder::RootToLeaf));
+        assert_eq!(root_first.hashes(), proof.hashes());
+        assert_eq!(root_first.reversed(), proof);
+
+        let leaf_first_json = serde_json::to_value(&proof).unwrap();
+        let root_first_json = serde_json::to_value(&root_first).unwrap();
+        assert!(leaf_first_json.get("order").is_none());
+        assert_eq!(root_first_json["order"], "root-to-leaf");
+        let mut steps = leaf_first_json["steps"].as_array().unwrap().clone();
+        steps.reverse();
+        assert_eq!(root_first_json["steps"], serde_json::Value::Array(steps));
+
+        for (json, expected) in [(leaf_first_json, &proof), (root_first_json.clone(), &root_first)] {
+            let decoded: OwnedProof = serde_json::from_value(json).unwrap();
+            assert_eq!(&decoded, expected);
+            assert!(MerkleTree::verify_proof_at_index(&data[5], &decoded.as_proof(), 5, 7, &tree.root()));
+        }
+        let listed = root_first_json["steps"].as_array().unwrap().iter().map(|step| {
+            let direction = serde_json::from_value(step["direction"].clone()).unwrap();
+            (direction, hex::decode(step["hash"].as_str().unwrap()).unwrap())
+        });
+        let imported = OwnedProof::from_ordered_steps(listed.collect(), ProofOrder::RootToLeaf).with_tree_size(7);
+        assert_eq!(imported, root_first);
+
+        // without its order the listing is read from the leaf up, and no longer verifies
+        let mut unlabeled = root_first_json;
+        unlabeled.as_object_mut().unwrap().remove("order");
+        let mislabeled: OwnedProof = serde_json::from_value(unlabeled).unwrap();
+        assert!(!MerkleTree::verify_proof_at_index(&data[5], &mislabeled.as_proof(), 5, 7, &tree.root()));
+        assert_eq!(mislabeled.reversed().hashes(), mislabeled.hashes());
+    }
+
+    #[test]
+    fn test_owned_proof_tags() {
+        let tree = MerkleTree::construct(&example_data(5));
+        let proof = tree.prove_by_index(1).unwrap().into_owned();
+        assert_eq!((proof.hasher(), proof.leaf_mode()), (None, None));
+        let untagged = serde_json::to_value(&proof).unwrap();
+        assert!(untagged.get("hasher").is_none() && untagged.get("leaf_mode").is_none());
+
+        let tagged = TreeConfig::default().tag_proof(proof.clone());
+        let value = serde_json::to_value(&tagged).unwrap();
+        assert_eq!((&value["hasher"], &value["leaf_mode"]), (&"sha256".into(), &"hash-data".into()));
+        assert_eq!(serde_json::from_value::<OwnedProof>(value.clone()).unwrap(), tagged);
+        assert!(tagged > proof);
+
+        // a recorded hasher fixes the width of every step
+        let mut wide = value.clone();
+        wide["hasher"] = "sha512".into();
+        let err = serde_json::from_value::<OwnedProof>(wide).unwrap_err().to_string();
+        assert!(err.contains("expected a 64-byte hash, found 32 bytes"), "{}", err);
+        let mut unknown = value;
+        unknown["hasher"] = "keccak256".into();
+        assert!(serde_json::from_value::<OwnedProof>(unknown).is_err());
+    }
+
+    #[test]
+    fn test_owned_proof_step_limit() {
+        let step = serde_json::json!({ "direction": "left", "hash": "00" });
+        let steps = |n: usize| serde_json::json!({ "version": 2, "steps": vec![step.clone(); n] });
+        let longest = serde_json::from_value::<OwnedProof>(steps(MAX_PROOF_STEPS)).unwrap();
+        assert_eq!(longest.hashes().len(), MAX_PROOF_STEPS);
+        let err = serde_json::from_value::<OwnedProof>(steps(MAX_PROOF_STEPS + 1)).unwrap_err().to_string();
+        let limit = format!("a proof has at most {} steps, found {}", MAX_PROOF_STEPS, MAX_PROOF_STEPS + 1);
+        assert!(err.contains(&limit), "{}", err);
+
+        // hashes are held inline, so one wider than any hasher's is refused rather than cut
+        let wide = serde_json::json!({ "version": 2, "steps": [{ "direction": "left", "hash": "00".repeat(65) }] });
+        assert!(serde_json::from_value::<OwnedProof>(wide).is_err());
+        let widest = OwnedProof::new(vec![(HashDirection::Left, vec![7; MAX_HASH_LEN])]);
+        assert_eq!(serde_json::from_value::<OwnedProof>(serde_json::to_value(&widest).unwrap()).unwrap(), widest);
+    }
+
+    #[test]
+    fn test_sizes_near_u32_max() {
+        let max = u32::MAX as u64;
+        assert_eq!(checked_node_count(0).unwrap(), 0);
+        assert_eq!(checked_node_count(1).unwrap(), 1);
+        assert_eq!(checked_node_count(max + 1).unwrap(), 2 * (max + 1) - 1);
+        // one leaf past a perfect tree adds a node on each of the 33 levels and a new root
+        assert_eq!(checked_node_count(max + 2).unwrap(), 2 * (max + 1) - 1 + 33 + 1);
+        assert_eq!(checked_node_count(max).unwrap(), 2 * (max + 1) - 2);
+        assert!(matches!(checked_node_count(u64::MAX), Err(LibError::Overflow("node count"))));
+
+        assert_eq!((tree_depth(1), tree_depth(2), tree_depth(3)), (0, 1, 2));
+        if let Ok(max) = usize::try_from(max) {
+            assert_eq!((tree_depth(max), tree_depth(max + 1), tree_depth(max + 2)), (32, 32, 33));
+            assert_eq!(expected_proof_len(max, max - 1, OddLeafPolicy::Duplicate), 32);
+            // the last of 2^32 - 1 leaves is lone on the leaf level only, the last of 2^32 + 1 on all
+            // but the top one
+            assert_eq!(expected_proof_len(max, max - 1, OddLeafPolicy::Promote), 31);
+            assert_eq!(expected_proof_len(max + 2, max + 1, OddLeafPolicy::Promote), 1);
+            assert_eq!(expected_proof_len(max + 2, 0, OddLeafPolicy::Promote), 33);
+        }
+        assert_eq!(tree_depth(usize::MAX), usize::BITS as usize);
+        assert_eq!(expected_proof_len(usize::MAX, 0, OddLeafPolicy::Duplicate), usize::BITS as usize);
+        assert_eq!(to_usize(u64::MAX, "leaf count").is_err(), usize::BITS < 64);
+    }
+
+    #[test]
+    fn test_implied_index_overflow() {
+        let hash = vec![0; HASH_LEN];
+        let steps = |n: usize| (0..n).map(|_| (HashDirection::Left, &hash[..])).collect::<ProofSteps>();
+        assert_eq!(Proof::from_hashes(steps(usize::BITS as usize)).implied_index().unwrap(), usize::MAX);
+        let too_deep = Proof::from_hashes(steps(usize::BITS as usize + 1));
+        assert!(matches!(too_deep.implied_index(), Err(LibError::Overflow("proof index"))));
+        assert!(!MerkleTree::verify_proof_at_index(&vec![], &too_deep, 0, usize::MAX, &hash));
+    }
+
+    #[test]
+    fn test_find_divergence() {
+        let mut data = example_data(5_000);
+        let reference = MerkleTree::construct(&data);
+        assert_eq!(MerkleTree::find_divergence(&data, &reference), None);
+
+        for index in [0, 4_095, 4_096, 4_999] {
+            let original = std::mem::replace(&mut data[index], b"tampered".to_vec());
+            assert_eq!(MerkleTree::find_divergence(&data, &reference), Some(index));
+            data[index] = original;
+        }
+
+        // only the length differs
+        assert_eq!(MerkleTree::find_divergence(&data[..4_000], &reference), None);
+        data.push(vec![1, 2, 3]);
+        assert_eq!(MerkleTree::find_divergence(&data, &reference), None);
+        data[3_210] = vec![];
+        assert_eq!(MerkleTree::find_divergence(&data, &reference), Some(3_210));
+        assert_eq!(MerkleTree::find_divergence(&data[..3_211], &reference), Some(3_210));
+        assert_eq!(MerkleTree::find_divergence(&[], &reference), None);
+    }
+
+    #[test]
+    fn test_first_difference_at_scale() {
+        // the search only compares hashes, so the cheap `XorFoldHasher` does for large trees
+        let config = TreeConfig::default().with_hasher(HashAlgorithm::XorFold);
+        let construct = |data: &[Data]| config.construct(data).unwrap();
+        let mut data = example_data(100_000);
+        let reference = construct(&data);
+        assert_eq!(construct(&data).first_difference(&reference), None);
+
+        for index in [0, 4_095, 65_536, 99_999] {
+            let original = std::mem::replace(&mut data[index], b"tampered".to_vec());
+            assert_eq!(construct(&data).first_difference(&reference), Some(index));
+            data[index] = original;
+        }
+
+        assert_eq!(construct(&data[..99_000]).first_difference(&reference), None);
+        data.push(vec![1, 2, 3]);
+        assert_eq!(construct(&data).first_difference(&reference), None);
+        data[98_765] = vec![];
+        assert_eq!(construct(&data).first_difference(&reference), Some(98_765));
+        assert_eq!(construct(&data[..98_766]).first_difference(&reference), Some(98_765));
+    }
+
+    #[test]
+    fn test_single_leaf_tree() {
+        let entry = vec![7; HASH_LEN];
+        for (mode, leaf) in [(LeafMode::HashData, hash_data(&entry)), (LeafMode::PreHashed, entry.clone())] {
+            let tree = TreeConfig::new(mode).construct(std::slice::from_ref(&entry)).unwrap();
+            assert_eq!((tree.root(), tree.depth(), tree.proof_len(0).unwrap()), (leaf.clone(), 0, 0));
+            let proof = tree.prove_by_index(0).unwrap();
+            assert_eq!((proof.len(), proof.tree_size()), (0, Some(1)));
+            assert!(MerkleTree::verify_proof_hash(&leaf, &proof, &tree.root()));
+
+            // the size survives serialization, and an empty proof means nothing without it
+            let owned = proof.into_owned();
+            let json = serde_json::to_value(&owned).unwrap();
+            assert_eq!(json, serde_json::json!({ "version": 2, "steps": [], "tree_size": 1 }));
+            let read: OwnedProof = serde_json::from_value(json).unwrap();
+            assert!(MerkleTree::verify_proof_hash(&leaf, &read.as_proof(), &tree.root()));
+            let untagged = serde_json::json!({ "version": 1, "steps": [] });
+            let untagged: OwnedProof = serde_json::from_value(untagged).unwrap();
+            assert!(!MerkleTree::verify_proof_hash(&leaf, &untagged.as_proof(), &tree.root()));
+            assert!(!MerkleTree::verify_proof_hash(&leaf, &Proof::default().with_tree_size(2), &tree.root()));
+        }
+
+        let tree = MerkleTree::construct(std::slice::from_ref(&entry));
+        let proof = tree.prove(&entry).unwrap();
+        assert!(MerkleTree::verify_proof(&entry, &proof, &tree.root()));
+        assert!(MerkleTree::verify_proof_at_index(&entry, &proof, 0, 1, &tree.root()));
+        assert!(!MerkleTree::verify_proof_at_index(&entry, &proof, 1, 1, &tree.root()));
+        assert!(!MerkleTree::verify_proof_at_index(&entry, &proof, 0, 2, &tree.root()));
+        assert!(MerkleTree::verify_proof_multi_root(&entry, &Proof::default(), &[tree.root()]).is_none());
+
+        // a non-empty proof cannot claim to come from a one-leaf tree
+        let pair = MerkleTree::construct(&[entry.clone(), vec![]]);
+        let proof = pair.prove_by_index(0).unwrap();
+        assert!(MerkleTree::verify_proof(&entry, &proof, &pair.root()));
+        assert!(!MerkleTree::verify_proof(&entry, &proof.with_tree_size(1), &pair.root()));
+    }
+
+    #[test]
+    fn test_construct_from_sources() {
+        let source = |id: &str, data: &[u8]| (id.to_string(), data.iter().map(|&b| vec![b]).collect::<Vec<Data>>());
+        let sources = vec![source("a", &[0, 1, 2]), source("b", &[]), source("c", &[3, 4])];
+        let tree = MerkleTree::construct_from_sources(&sources).unwrap();
+        assert_eq!(tree.root(), MerkleTree::construct(&example_data(5)).root());
+        let provenance: Vec<_> = (0..5).map(|i| tree.source_of(i).unwrap().as_str()).collect();
+        assert_eq!(provenance, ["a", "a", "a", "c", "c"]);
+        assert_eq!(tree.source_of(5), None);
+        assert_eq!(MerkleTree::construct(&example_data(5)).source_of(0), None);
+
+        // moving an empty source or regrouping the same leaves keeps the root
+        let reordered = vec![source("b", &[]), source("a", &[0, 1]), source("c", &[2, 3, 4])];
+        let regrouped = MerkleTree::construct_from_sources(&reordered).unwrap();
+        assert_eq!(regrouped.root(), tree.root());
+        assert_eq!(regrouped.source_of(2).unwrap(), "c");
+        // swapping sources with different leaves changes it
+        let swapped = vec![source("c", &[3, 4]), source("b", &[]), source("a", &[0, 1, 2])];
+        assert_ne!(MerkleTree::construct_from_sources(&swapped).unwrap().root(), tree.root());
+        let identical = vec![source("x", &[7]), source("y", &[7])];
+        let identical_swapped = vec![source("y", &[7]), source("x", &[7])];
+        assert_eq!(
+            MerkleTree::construct_from_sources(&identical).unwrap().root(),
+            MerkleTree::construct_from_sources(&identical_swapped).unwrap().root()
+        );
+
+        assert!(matches!(MerkleTree::construct_from_sources(&[source("a", &[])]), Err(LibError::EmptyInput)));
+    }
+
+    #[test]
+    fn test_direction_combine() {
+        use sha2::{Digest, Sha256};
+
+        let (running, sibling) = (hash_data(&vec![1]), hash_data(&vec![2]));
+        let concat = |first: &Hash, second: &Hash| {
+            let mut hasher = Sha256::new();
+            hasher.update(first);
+            hasher.update(second);
+            hasher.finalize().to_vec()
+        };
+        let left = HashDirection::Left.combine(&Sha256Hasher, &running, &sibling);
+        assert_eq!(left, concat(&sibling, &running));
+        for direction in [HashDirection::Right, HashDirection::Duplicate] {
+            assert_eq!(direction.combine(&Sha256Hasher, &running, &sibling), concat(&running, &sibling));
+        }
+        let ripemd = HashAlgorithm::Ripemd160.node_hash(&sibling, &running);
+        assert_eq!(HashDirection::Left.combine(&HashAlgorithm::Ripemd160, &running, &sibling), ripemd);
+
+        assert_eq!(HashDirection::Left.opposite(), HashDirection::Right);
+        assert_eq!(HashDirection::Right.opposite(), HashDirection::Left);
+        assert_eq!(HashDirection::Duplicate.opposite(), HashDirection::Left);
+        for direction in [HashDirection::Left, HashDirection::Right] {
+            assert_eq!(HashDirection::from_bit(direction.to_bit()), direction);
+            assert_eq!(direction.opposite().opposite(), direction);
+        }
+        assert!(!HashDirection::Duplicate.to_bit());
+    }
+
+    #[test]
+    fn test_standard_traits() {
+        use std::collections::{BTreeMap, HashSet};
+
+        let trees: Vec<MerkleTree> = (1..=4).map(|n| MerkleTree::construct(&example_data(n))).collect();
+        let mut roots: HashSet<Hash> = trees.iter().map(MerkleTree::root).collect();
+        assert!(!roots.insert(trees[2].root()));
+        assert_eq!(roots.len(), 4);
+
+        // roots keyed by hash iterate in byte order
+        let by_root: BTreeMap<Hash, usize> = trees.iter().map(|tree| (tree.root(), tree.len())).collect();
+        let mut sorted: Vec<Hash> = roots.into_iter().collect();
+        sorted.sort();
+        assert_eq!(by_root.keys().cloned().collect::<Vec<_>>(), sorted);
+
+        // proofs of the same leaf from the same tree are identical, borrowed or owned
+        let tree = &trees[3];
+        assert_eq!(tree.prove_by_index(1).unwrap(), tree.prove_by_index(1).unwrap());
+        assert_ne!(tree.prove_by_index(1).unwrap(), tree.prove_by_index(2).unwrap());
+        let borrowed: HashSet<Proof> = [0, 1, 1, 3].into_iter().map(|i| tree.prove_by_index(i).unwrap()).collect();
+        assert_eq!(borrowed.len(), 3);
+
+        let mut proofs: Vec<OwnedProof> =
+            [3, 1, 3, 0, 1].into_iter().map(|i| tree.prove_by_index(i).unwrap().into_owned()).collect();
+        proofs.sort();
+        proofs.dedup();
+        assert_eq!(proofs.len(), 3);
+        assert!(proofs.windows(2).all(|pair| pair[0] < pair[1]));
+        // untagged proofs order before tagged ones with the same steps
+        let untagged = OwnedProof::new(proofs[0].hashes().to_vec());
+        assert!(untagged < proofs[0]);
+
+        assert!(HashDirection::Left < HashDirection::Right);
+        assert_eq!(HashSet::from([HashDirection::Left, HashDirection::Left]).len(), 1);
+    }
+
+    #[test]
+    fn test_fold_root_matches_construction() {
+        assert!(matches!(fold_root(vec![], HashAlgorithm::Sha256d), Err(LibError::EmptyInput)));
+        for hasher in HashAlgorithm::ALL {
+            let config = TreeConfig::new(LeafMode::PreHashed).with_hasher(hasher);
+            for n in [1u8, 2, 3, 4, 5, 7, 8, 13] {
+                let leaves: Vec<Hash> = (0..n).map(|i| hasher.leaf_hash(&[i])).collect();
+                let root = fold_root(leaves.clone(), hasher).unwrap();
+                assert_eq!(root, config.construct(&leaves).unwrap().root(), "{} leaves of {}", n, hasher.id());
+                if n == 1 {
+                    assert_eq!(root, leaves[0]);
+                }
+            }
+        }
+
+        // two leaves, then three, where the last one is paired with itself so repeating it keeps the root
+        let (a, b, c) = (vec![0xaa; 32], vec![0xbb; 32], vec![0xcc; 32]);
+        let sha256d = HashAlgorithm::Sha256d;
+        let ab = sha256d.node_hash(&a, &b);
+        assert_eq!(fold_root(vec![a.clone(), b.clone()], sha256d).unwrap(), ab);
+        let root = sha256d.node_hash(&ab, &sha256d.node_hash(&c, &c));
+        assert_eq!(fold_root(vec![a.clone(), b.clone(), c.clone()], sha256d).unwrap(), root);
+        assert_eq!(fold_root(vec![a, b, c.clone(), c], sha256d).unwrap(), root);
+    }
 }
\ No newline at end of file
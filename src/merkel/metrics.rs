@@ -0,0 +1,223 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::{Data, LeafMode, MerkleTree, NodeEvent, TreeConfig};
+use crate::util::error::LibError;
+
+/// Counts and timings of one construction, see `TreeConfig::construct_with_metrics`
+///
+/// The counts are the same on every run over the same input; `elapsed` and `peak_rss_bytes` are not.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct BuildMetrics {
+    pub leaf_count: usize,
+    /// Entries hashed into leaves, none for pre-hashed entries
+    pub leaf_hashes: usize,
+    /// Nodes hashed from their children, leaving out lone nodes promoted unchanged
+    pub node_hashes: usize,
+    /// Wall time from the first entry to the root
+    pub elapsed: Duration,
+    /// Peak resident memory of the process when the root was reached, see `peak_rss_bytes`
+    pub peak_rss_bytes: Option<u64>,
+}
+
+impl BuildMetrics {
+    pub fn leaves_per_second(&self) -> f64 {
+        self.leaf_count as f64 / self.seconds()
+    }
+
+    /// Leaf and node hashes per second
+    pub fn hashes_per_second(&self) -> f64 {
+        (self.leaf_hashes + self.node_hashes) as f64 / self.seconds()
+    }
+
+    /// Elapsed seconds, at least a nanosecond so rates stay finite
+    fn seconds(&self) -> f64 {
+        self.elapsed.as_secs_f64().max(1e-9)
+    }
+}
+
+/// `7 leaves and 14 hashes in 1.25 ms (5.6k leaves/s, 11.2k hashes/s), peak RSS 3.1 MiB`
+impl fmt::Display for BuildMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} leaves and {} hashes in {} ({} leaves/s, {} hashes/s), peak RSS ",
+            self.leaf_count,
+            self.leaf_hashes + self.node_hashes,
+            HumanDuration(self.elapsed),
+            HumanCount(self.leaves_per_second()),
+            HumanCount(self.hashes_per_second()),
+        )?;
+        match self.peak_rss_bytes {
+            Some(bytes) => write!(f, "{:.1} MiB", bytes as f64 / (1 << 20) as f64),
+            None => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Collects `BuildMetrics` from the nodes reported to a `TreeConfig::on_node` callback
+///
+/// Timing starts when the collector is made, so make it just before the construction it measures.
+#[derive(Debug, Clone)]
+pub struct MetricsCollector {
+    start: Instant,
+    leaf_count: usize,
+    node_hashes: usize,
+}
+
+impl MetricsCollector {
+    pub fn start() -> Self {
+        MetricsCollector { start: Instant::now(), leaf_count: 0, node_hashes: 0 }
+    }
+
+    /// Counts one node; leaves are only counted if the callback was set up `with_leaves`
+    pub fn record(&mut self, event: &NodeEvent) {
+        match (event.level, event.right_child) {
+            (0, _) => self.leaf_count += 1,
+            (_, Some(_)) => self.node_hashes += 1,
+            // promoted unchanged
+            (_, None) => {}
+        }
+    }
+
+    /// The metrics of the construction, whose entries were `leaf_mode` entries
+    pub fn finish(self, leaf_mode: LeafMode) -> BuildMetrics {
+        let elapsed = self.start.elapsed();
+        BuildMetrics {
+            leaf_count: self.leaf_count,
+            leaf_hashes: if leaf_mode == LeafMode::HashData { self.leaf_count } else { 0 },
+            node_hashes: self.node_hashes,
+            elapsed,
+            peak_rss_bytes: peak_rss_bytes(),
+        }
+    }
+}
+
+impl TreeConfig {
+    /// Builds the tree `construct` builds, measuring the construction with a `MetricsCollector`
+    pub fn construct_with_metrics(&self, input: &[Data]) -> Result<(MerkleTree, BuildMetrics), LibError> {
+        let mut collector = MetricsCollector::start();
+        let tree = self.on_node(|event| collector.record(event)).with_leaves().construct(input)?;
+        Ok((tree, collector.finish(self.leaf_mode())))
+    }
+}
+
+/// Peak resident memory of this process so far, from `VmHWM` in `/proc/self/status`
+///
+/// `None` where there is no such file, as on systems other than Linux.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find_map(|line| line.strip_prefix("VmHWM:"))?;
+    let kib = line.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    kib.checked_mul(1024)
+}
+
+/// A duration in the largest unit that keeps it at least 1, to three significant digits
+struct HumanDuration(Duration);
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = self.0.as_nanos() as f64;
+        let (value, unit) = match nanos {
+            n if n >= 1e9 => (n / 1e9, "s"),
+            n if n >= 1e6 => (n / 1e6, "ms"),
+            n if n >= 1e3 => (n / 1e3, "µs"),
+            n => (n, "ns"),
+        };
+        write!(f, "{} {}", Significant(value), unit)
+    }
+}
+
+/// A count with a k, M or G suffix, to three significant digits
+struct HumanCount(f64);
+
+impl fmt::Display for HumanCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (value, suffix) = match self.0 {
+            n if n >= 1e9 => (n / 1e9, "G"),
+            n if n >= 1e6 => (n / 1e6, "M"),
+            n if n >= 1e3 => (n / 1e3, "k"),
+            n => (n, ""),
+        };
+        write!(f, "{}{}", Significant(value), suffix)
+    }
+}
+
+/// A number below 1000 to three significant digits, without trailing zeros
+struct Significant(f64);
+
+impl fmt::Display for Significant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decimals = match self.0 {
+            n if n >= 100.0 => 0,
+            n if n >= 10.0 => 1,
+            _ => 2,
+        };
+        let text = format!("{:.*}", decimals, self.0);
+        let text = if text.contains('.') { text.trim_end_matches('0').trim_end_matches('.') } else { &text };
+        f.write_str(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{HashAlgorithm, MerkleHasher, OddLeafPolicy};
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| vec![i as u8]).collect()
+    }
+
+    #[test]
+    fn test_counts() {
+        let data = example_data(7);
+        let config = TreeConfig::default();
+        let (tree, metrics) = config.construct_with_metrics(&data).unwrap();
+        assert_eq!(tree.levels(), config.construct(&data).unwrap().levels());
+        // 4 + 2 + 1 nodes above the leaves, the lone seventh leaf paired with itself
+        assert_eq!((metrics.leaf_count, metrics.leaf_hashes, metrics.node_hashes), (7, 7, 7));
+
+        // the lone leaf is promoted unchanged, which hashes nothing
+        let promoted = config.with_odd_leaf_policy(OddLeafPolicy::Promote);
+        let (_, metrics) = promoted.construct_with_metrics(&data).unwrap();
+        assert_eq!((metrics.leaf_count, metrics.leaf_hashes, metrics.node_hashes), (7, 7, 6));
+
+        let leaves: Vec<Data> = data.iter().map(|entry| HashAlgorithm::Sha256.leaf_hash(entry)).collect();
+        let (tree, metrics) = TreeConfig::new(LeafMode::PreHashed).construct_with_metrics(&leaves).unwrap();
+        assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+        assert_eq!((metrics.leaf_count, metrics.leaf_hashes, metrics.node_hashes), (7, 0, 7));
+        assert!(matches!(config.construct_with_metrics(&[]), Err(LibError::EmptyInput)));
+    }
+
+    #[test]
+    fn test_timing_is_plausible() {
+        let before = Instant::now();
+        let (_, metrics) = TreeConfig::default().construct_with_metrics(&example_data(200)).unwrap();
+        assert!(metrics.elapsed <= before.elapsed());
+        assert!(metrics.leaves_per_second() > 0.0 && metrics.leaves_per_second().is_finite());
+        assert!(metrics.hashes_per_second() > metrics.leaves_per_second());
+        if cfg!(target_os = "linux") {
+            // at least the binary itself is resident
+            assert!(metrics.peak_rss_bytes.is_some_and(|bytes| bytes > 1 << 20), "{:?}", metrics.peak_rss_bytes);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let metrics = BuildMetrics {
+            leaf_count: 7,
+            leaf_hashes: 7,
+            node_hashes: 7,
+            elapsed: Duration::from_micros(1250),
+            peak_rss_bytes: Some(3 << 20),
+        };
+        let shown = "7 leaves and 14 hashes in 1.25 ms (5.6k leaves/s, 11.2k hashes/s), peak RSS 3.0 MiB";
+        assert_eq!(metrics.to_string(), shown);
+        let metrics = BuildMetrics { elapsed: Duration::from_secs(2), peak_rss_bytes: None, ..metrics };
+        assert!(metrics.to_string().ends_with("in 2 s (3.5 leaves/s, 7 hashes/s), peak RSS unknown"));
+        let shown = [1.0, 999.4, 1500.0, 3.2e6].map(|count| HumanCount(count).to_string());
+        assert_eq!(shown, ["1", "999", "1.5k", "3.2M"]);
+        assert_eq!(HumanDuration(Duration::from_nanos(800)).to_string(), "800 ns");
+    }
+}
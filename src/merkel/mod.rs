@@ -1 +1,85 @@
-mod merkle;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+mod merkel;
+mod arena;
+mod async_build;
+mod builder;
+mod cache;
+mod canonical;
+mod chained;
+mod compressed;
+mod config;
+mod consistency;
+mod context;
+mod descriptor;
+mod detect;
+mod explain;
+mod forest;
+mod hasher;
+mod index;
+mod indexed;
+mod json;
+mod memory;
+mod metrics;
+mod multi;
+mod multiset;
+mod observe;
+mod pair;
+mod partial;
+mod pruned;
+mod raw;
+mod recommit;
+mod roots;
+mod selftest;
+mod service;
+mod sink;
+mod snapshot;
+mod sorted;
+mod subtree;
+mod sum;
+mod version;
+#[cfg(feature = "cbor")]
+mod cbor;
+
+pub use merkel::*;
+pub use arena::{ArenaTree, TreeArena};
+pub use async_build::DEFAULT_YIELD_EVERY;
+pub use builder::MerkleBuilder;
+pub use cache::{CacheStats, ProofCache};
+pub use canonical::{canonicalize_leaves, CanonicalOrdering};
+pub(crate) use canonical::canonical_order;
+pub use chained::ChainedProof;
+pub use compressed::DefaultHashes;
+pub use consistency::ConsistencyProof;
+pub use context::VerifyContext;
+pub use config::{EmptyLeafPolicy, LeafMode, OddLeafPolicy, TreeConfig, TreeShape, Verification, VerifyWarning};
+pub use descriptor::{TreeDescriptor, CRATE_VERSION};
+pub use detect::{detect_scheme, PairOrder, SchemeGuess};
+pub use explain::{FailurePoint, ProofFailure};
+pub use forest::{CompositeProof, MerkleForest};
+pub use hasher::{
+    HashAlgorithm, MerkleHasher, Rfc6962Hasher, Ripemd160Hasher, Sha256Hasher, Sha256dHasher, Sha512Hasher,
+};
+#[cfg(any(test, feature = "test-utils"))]
+pub use hasher::XorFoldHasher;
+pub use index::{LeafIndex, TreeSize};
+pub use indexed::IndexedProof;
+pub(crate) use indexed::left_balanced_directions;
+pub use json::canonical_json_leaf;
+pub use memory::MemoryReport;
+pub use metrics::{peak_rss_bytes, BuildMetrics, MetricsCollector};
+pub use multi::{MultiCommitment, MultiRoots};
+pub use multiset::MultisetCommitment;
+pub use observe::{NodeEvent, ObservedConfig};
+pub use pair::PairProof;
+pub use partial::PartialTree;
+pub use pruned::{PruneSpec, PrunedTree};
+pub use raw::verify_proof_raw;
+pub use roots::{ResolveError, RootSet, ShortHash, MIN_ABBREVIATION_LEN};
+pub use selftest::{self_test_hashers, SelfTestError};
+pub use service::{ProofService, ServiceError, ServiceLimits, ServiceStats};
+pub use sink::{ProofVerifySink, SinkItem, SinkSummary, SINK_BLOCKING_LEAF_LEN};
+pub use snapshot::{TreeSnapshot, SNAPSHOT_CHUNK};
+pub use sorted::{AbsenceProof, Neighbor, SortedMerkleTree};
+pub use subtree::SubtreeCache;
+pub use sum::{SumProof, SumStep, SumTree};
+pub use version::ProofVersion;
@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    to_usize, Data, Hash, HashAlgorithm, LeafIndex, MerkleHasher, MerkleTree, OddLeafPolicy, Proof, ProofVersion,
+};
+use crate::util::error::LibError;
+
+/// One tree per hasher over the same leaves, for anchoring a dataset where every target needs its
+/// own hash function
+///
+/// All trees pad odd levels with `OddLeafPolicy::Duplicate`, so each is the tree `TreeConfig`
+/// would build with that hasher alone.
+#[derive(Clone)]
+pub struct MultiCommitment {
+    trees: Vec<MerkleTree>,
+}
+
+/// The roots of a `MultiCommitment` with its leaf count, as published to every target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "MultiRootsRepr", into = "MultiRootsRepr")]
+pub struct MultiRoots {
+    leaf_count: usize,
+    roots: Vec<(HashAlgorithm, Hash)>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MultiRootsRepr {
+    version: ProofVersion,
+    leaf_count: u64,
+    roots: Vec<RootRepr>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RootRepr {
+    hasher: HashAlgorithm,
+    #[serde(with = "crate::util::hex_serde")]
+    root: Hash,
+}
+
+impl MultiCommitment {
+    /// Builds one tree per hasher, reading every leaf once and hashing it with each hasher in turn
+    ///
+    /// A hasher listed twice gets a single tree. Fails with `LibError::EmptyInput` without leaves
+    /// and `LibError::InvalidFormat` without hashers.
+    pub fn build(leaves: &[Data], hashers: &[HashAlgorithm]) -> Result<MultiCommitment, LibError> {
+        if leaves.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        let mut unique: Vec<HashAlgorithm> = Vec::with_capacity(hashers.len());
+        for &hasher in hashers {
+            if !unique.contains(&hasher) {
+                unique.push(hasher);
+            }
+        }
+        if unique.is_empty() {
+            return Err(LibError::InvalidFormat("a commitment needs at least one hasher".into()));
+        }
+
+        let mut leaf_hashes = vec![Vec::with_capacity(leaves.len()); unique.len()];
+        for leaf in leaves {
+            for (hashes, hasher) in leaf_hashes.iter_mut().zip(&unique) {
+                hashes.push(hasher.leaf_hash(leaf));
+            }
+        }
+        let trees = leaf_hashes
+            .into_iter()
+            .zip(unique)
+            .map(|(hashes, hasher)| MerkleTree::build(hashes, OddLeafPolicy::Duplicate, hasher))
+            .collect();
+        Ok(MultiCommitment { trees })
+    }
+
+    /// Number of leaves, the same in every tree
+    pub fn len(&self) -> usize {
+        self.trees.first().map_or(0, MerkleTree::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hashers of the trees, in the order they were first listed
+    pub fn hashers(&self) -> impl Iterator<Item = HashAlgorithm> + '_ {
+        self.trees.iter().map(MerkleTree::hasher)
+    }
+
+    /// The tree built with `hasher`, if it is one of the hashers of this commitment
+    pub fn tree(&self, hasher: HashAlgorithm) -> Option<&MerkleTree> {
+        self.trees.iter().find(|tree| tree.hasher() == hasher)
+    }
+
+    pub fn root(&self, hasher: HashAlgorithm) -> Option<Hash> {
+        self.tree(hasher).map(MerkleTree::root)
+    }
+
+    /// Proves the leaf at `index` in the tree built with `hasher`
+    ///
+    /// Fails with `LibError::UnknownHasher` for a hasher this commitment was not built with.
+    pub fn prove(&self, hasher: HashAlgorithm, index: impl Into<LeafIndex>) -> Result<Proof<'_>, LibError> {
+        let tree = self.tree(hasher).ok_or_else(|| LibError::UnknownHasher(hasher.id().to_string()))?;
+        tree.prove_by_index(index)
+    }
+
+    /// Every root with the leaf count, for publishing
+    pub fn roots(&self) -> MultiRoots {
+        MultiRoots {
+            leaf_count: self.len(),
+            roots: self.trees.iter().map(|tree| (tree.hasher(), tree.root())).collect(),
+        }
+    }
+}
+
+impl MultiRoots {
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Roots by hasher, in the order of the commitment
+    pub fn roots(&self) -> &[(HashAlgorithm, Hash)] {
+        &self.roots
+    }
+
+    pub fn root(&self, hasher: HashAlgorithm) -> Option<&Hash> {
+        self.roots.iter().find(|(h, _)| *h == hasher).map(|(_, root)| root)
+    }
+
+    /// Verifies a proof from the tree of `hasher` against the root published for it
+    ///
+    /// A proof tagged with a tree size must be tagged with the leaf count of the commitment.
+    pub fn verify_proof(&self, hasher: HashAlgorithm, data: &Data, proof: &Proof) -> bool {
+        self.root(hasher).is_some_and(|root| {
+            proof.tree_size().is_none_or(|size| size == self.leaf_count)
+                && MerkleTree::verify_proof_hash_with(&hasher, &hasher.leaf_hash(data), proof, root)
+        })
+    }
+}
+
+impl From<MultiRoots> for MultiRootsRepr {
+    fn from(roots: MultiRoots) -> Self {
+        MultiRootsRepr {
+            version: ProofVersion::CURRENT,
+            leaf_count: roots.leaf_count as u64,
+            roots: roots.roots.into_iter().map(|(hasher, root)| RootRepr { hasher, root }).collect(),
+        }
+    }
+}
+
+impl TryFrom<MultiRootsRepr> for MultiRoots {
+    type Error = LibError;
+
+    fn try_from(repr: MultiRootsRepr) -> Result<Self, LibError> {
+        let leaf_count = to_usize(repr.leaf_count, "leaf count")?;
+        if leaf_count == 0 || repr.roots.is_empty() {
+            return Err(LibError::InvalidFormat("a commitment has at least one leaf and one root".into()));
+        }
+        let mut roots: Vec<(HashAlgorithm, Hash)> = Vec::with_capacity(repr.roots.len());
+        for RootRepr { hasher, root } in repr.roots {
+            if roots.iter().any(|(h, _)| *h == hasher) {
+                return Err(LibError::InvalidFormat(format!("two roots for hasher {}", hasher.id())));
+            }
+            if root.len() != hasher.output_len() {
+                let (id, expected) = (hasher.id(), hasher.output_len());
+                let message = format!("{} root is {} bytes, expected {}", id, root.len(), expected);
+                return Err(LibError::InvalidFormat(message));
+            }
+            roots.push((hasher, root));
+        }
+        Ok(MultiRoots { leaf_count, roots })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::TreeConfig;
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| format!("record {}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_roots_match_single_hasher_trees() {
+        let data = example_data(11);
+        let commitment = MultiCommitment::build(&data, &HashAlgorithm::ALL).unwrap();
+        assert_eq!(commitment.len(), 11);
+        assert_eq!(commitment.hashers().collect::<Vec<_>>(), HashAlgorithm::ALL);
+
+        let roots = commitment.roots();
+        for hasher in HashAlgorithm::ALL {
+            let single = TreeConfig::default().with_hasher(hasher).construct(&data).unwrap();
+            assert_eq!(commitment.root(hasher), Some(single.root()));
+            assert_eq!(roots.root(hasher), Some(&single.root()));
+
+            for index in [0, 5, 10] {
+                let proof = commitment.prove(hasher, index).unwrap();
+                assert_eq!(proof, single.prove_by_index(index).unwrap());
+                assert!(roots.verify_proof(hasher, &data[index], &proof));
+                assert!(!roots.verify_proof(hasher, &data[(index + 1) % data.len()], &proof));
+            }
+        }
+
+        // a proof only verifies under the hasher of its own tree
+        let proof = commitment.prove(HashAlgorithm::Sha512, 3).unwrap();
+        assert!(!roots.verify_proof(HashAlgorithm::Sha256, &data[3], &proof));
+    }
+
+    #[test]
+    fn test_build_rejects_degenerate_input() {
+        let data = example_data(3);
+        assert!(matches!(MultiCommitment::build(&[], &HashAlgorithm::ALL), Err(LibError::EmptyInput)));
+        assert!(matches!(MultiCommitment::build(&data, &[]), Err(LibError::InvalidFormat(_))));
+
+        let twice = [HashAlgorithm::Ripemd160, HashAlgorithm::Sha256, HashAlgorithm::Ripemd160];
+        let commitment = MultiCommitment::build(&data, &twice).unwrap();
+        assert_eq!(commitment.hashers().count(), 2);
+        assert_eq!(commitment.roots().roots()[0].0, HashAlgorithm::Ripemd160);
+        let missing = commitment.prove(HashAlgorithm::Sha512, 0);
+        assert!(matches!(missing, Err(LibError::UnknownHasher(id)) if id == "sha512"));
+        assert_eq!(commitment.root(HashAlgorithm::Sha512), None);
+    }
+
+    #[test]
+    fn test_roots_serialization() {
+        let hashers = [HashAlgorithm::Sha256, HashAlgorithm::Ripemd160];
+        let commitment = MultiCommitment::build(&example_data(4), &hashers).unwrap();
+        let roots = commitment.roots();
+        let json = serde_json::to_value(&roots).unwrap();
+        assert_eq!(json["leaf_count"], 4);
+        assert_eq!(json["roots"][1]["hasher"], "ripemd160");
+        assert_eq!(json["roots"][0]["root"], hex::encode(commitment.root(HashAlgorithm::Sha256).unwrap()));
+        assert_eq!(serde_json::from_value::<MultiRoots>(json.clone()).unwrap(), roots);
+
+        let mut duplicated = json.clone();
+        duplicated["roots"][1]["hasher"] = "sha256".into();
+        let mut truncated = json.clone();
+        truncated["roots"][1]["root"] = "00".into();
+        let mut empty = json;
+        empty["leaf_count"] = 0.into();
+        for invalid in [duplicated, truncated, empty] {
+            assert!(serde_json::from_value::<MultiRoots>(invalid).is_err());
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use sha2::{Digest, Sha256};
+
+use super::{hash_data, Data, Hash, HashAlgorithm, MerkleTree, OddLeafPolicy, Proof};
+use crate::util::error::LibError;
+
+/// Commitment to the multiset of its entries: the same entries in any order, each repeated as
+/// often, give the same root
+///
+/// The SHA-256 leaf hashes of the entries are sorted and every run of equal hashes becomes one
+/// leaf `sha256(leaf_hash || count)`, with the count as 8 little-endian bytes, so the root also
+/// commits to how often each entry occurs. The tree over those leaves pads odd levels with
+/// `OddLeafPolicy::Duplicate`. Unlike `CanonicalOrdering::ByLeafHash`, which only sorts, the
+/// leaves are not those of the entries, and proofs are of an entry together with its count.
+#[derive(Clone)]
+pub struct MultisetCommitment {
+    tree: MerkleTree,
+    /// Distinct leaf hashes in ascending order, with the number of entries of each
+    counts: Vec<(Hash, u64)>,
+}
+
+impl MultisetCommitment {
+    /// Commits to the multiset of `entries`, failing with `LibError::EmptyInput` without any
+    pub fn construct(entries: &[Data]) -> Result<MultisetCommitment, LibError> {
+        if entries.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        let mut hashes: Vec<Hash> = entries.iter().map(hash_data).collect();
+        hashes.sort_unstable();
+        let mut counts: Vec<(Hash, u64)> = Vec::new();
+        for hash in hashes {
+            match counts.last_mut() {
+                Some((last, count)) if *last == hash => *count += 1,
+                _ => counts.push((hash, 1)),
+            }
+        }
+        let leaves = counts.iter().map(|(hash, count)| multiset_leaf(hash, *count)).collect();
+        let tree = MerkleTree::build(leaves, OddLeafPolicy::Duplicate, HashAlgorithm::Sha256);
+        Ok(MultisetCommitment { tree, counts })
+    }
+
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    /// The tree over the counted leaves, one leaf per distinct entry
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Number of entries committed to, each repeat counted
+    pub fn len(&self) -> u64 {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Always false, since a commitment has at least one entry
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Number of times `entry` occurs, 0 if it does not
+    pub fn count(&self, entry: &Data) -> u64 {
+        self.position(&hash_data(entry)).map_or(0, |position| self.counts[position].1)
+    }
+
+    /// Every distinct leaf hash with the number of entries of it, in ascending order of hash
+    pub fn counts(&self) -> &[(Hash, u64)] {
+        &self.counts
+    }
+
+    /// The report of repeated entries: the leaf hashes occurring more than once, with their
+    /// counts, in ascending order of hash
+    pub fn duplicates(&self) -> Vec<(Hash, u64)> {
+        self.counts.iter().filter(|(_, count)| *count > 1).cloned().collect()
+    }
+
+    /// Proves that `entry` occurs exactly `count` times
+    ///
+    /// Fails with `LibError::LeafNotFound` if it does not occur, or occurs another number of times.
+    pub fn prove(&self, entry: &Data, count: u64) -> Result<Proof<'_>, LibError> {
+        let hash = hash_data(entry);
+        match self.position(&hash) {
+            Some(position) if self.counts[position].1 == count => self.tree.prove_by_index(position),
+            _ => Err(LibError::LeafNotFound(format!("{} with count {}", hex::encode(&hash), count))),
+        }
+    }
+
+    /// Verifies that `proof` shows `entry` occurring `count` times under `root_hash`
+    pub fn verify(entry: &Data, count: u64, proof: &Proof, root_hash: &Hash) -> bool {
+        MerkleTree::verify_proof_hash(&multiset_leaf(&hash_data(entry), count), proof, root_hash)
+    }
+
+    fn position(&self, hash: &Hash) -> Option<usize> {
+        self.counts.binary_search_by(|(probe, _)| probe.cmp(hash)).ok()
+    }
+}
+
+impl MerkleTree {
+    /// Verifies that the entries of `input`, in any order, are those committed to by the
+    /// `MultisetCommitment` root `root_hash`, each repeated as often
+    ///
+    /// Empty input has no root and never verifies. A plain tree root never verifies this way, see
+    /// `verify_ordered` for those.
+    pub fn verify_multiset(input: &[Data], root_hash: &Hash) -> bool {
+        MultisetCommitment::construct(input).is_ok_and(|commitment| &commitment.root() == root_hash)
+    }
+}
+
+/// The leaf of an entry of leaf hash `hash` occurring `count` times
+fn multiset_leaf(hash: &Hash, count: u64) -> Hash {
+    Sha256::new().chain_update(hash).chain_update(count.to_le_bytes()).finalize().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::OwnedProof;
+
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    /// 20 entries over the 4 squares modulo 7, each repeated
+    fn feed() -> Vec<Data> {
+        (0..20u32).map(|i| format!("event {}", i * i % 7).into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_independent_of_order() {
+        let entries = feed();
+        let commitment = MultisetCommitment::construct(&entries).unwrap();
+        let mut rng = StdRng::seed_from_u64(185);
+        for _ in 0..10 {
+            let mut shuffled = entries.clone();
+            shuffled.shuffle(&mut rng);
+            assert_eq!(MultisetCommitment::construct(&shuffled).unwrap().root(), commitment.root());
+        }
+        assert_eq!((commitment.len(), commitment.tree().len()), (20, 4));
+        // a plain tree over the sorted leaf hashes does not fold the repeats
+        let sorted = MerkleTree::from_leaf_hashes(commitment.counts().iter().map(|(hash, _)| hash.clone()).collect());
+        assert_ne!(sorted.root(), commitment.root());
+    }
+
+    #[test]
+    fn test_counts_change_the_root() {
+        let mut entries = feed();
+        let commitment = MultisetCommitment::construct(&entries).unwrap();
+        entries.push(b"event 1".to_vec());
+        let more = MultisetCommitment::construct(&entries).unwrap();
+        assert_ne!(more.root(), commitment.root());
+        assert_eq!((commitment.count(&b"event 1".to_vec()), more.count(&b"event 1".to_vec())), (5, 6));
+        // the same distinct entries once each are another multiset again
+        let once: Vec<Data> = [0, 1, 2, 4].iter().map(|i| format!("event {}", i).into_bytes()).collect();
+        let distinct = MultisetCommitment::construct(&once).unwrap();
+        assert!(distinct.duplicates().is_empty());
+        assert_ne!(distinct.root(), commitment.root());
+
+        let mut expected: Vec<(Hash, u64)> = [(0, 3), (1, 5), (2, 6), (4, 6)]
+            .map(|(value, count)| (hash_data(&format!("event {}", value).into_bytes()), count))
+            .to_vec();
+        expected.sort();
+        assert_eq!(commitment.duplicates(), expected);
+        assert!(matches!(MultisetCommitment::construct(&[]), Err(LibError::EmptyInput)));
+    }
+
+    #[test]
+    fn test_proofs_round_trip() {
+        let entries = feed();
+        let commitment = MultisetCommitment::construct(&entries).unwrap();
+        let root = commitment.root();
+        for value in [0, 1, 2, 4] {
+            let entry = format!("event {}", value).into_bytes();
+            let count = commitment.count(&entry);
+            let proof = commitment.prove(&entry, count).unwrap();
+            let json = serde_json::to_string(&proof.clone().into_owned()).unwrap();
+            let decoded: OwnedProof = serde_json::from_str(&json).unwrap();
+            assert!(MultisetCommitment::verify(&entry, count, &decoded.as_proof(), &root), "value {}", value);
+            assert!(!MultisetCommitment::verify(&entry, count + 1, &proof, &root));
+            assert!(!MultisetCommitment::verify(&entry, count - 1, &proof, &root));
+            assert!(matches!(commitment.prove(&entry, count + 1), Err(LibError::LeafNotFound(_))));
+        }
+        assert_eq!(commitment.count(&b"event 3".to_vec()), 0);
+        let missing = commitment.prove(&b"event 3".to_vec(), 1).unwrap_err().to_string();
+        assert!(missing.ends_with(" with count 1 is not in the tree"), "{}", missing);
+    }
+
+    #[test]
+    fn test_verify_multiset_ignores_order_only() {
+        let (a, b) = (b"a".to_vec(), b"b".to_vec());
+        let data = vec![a.clone(), a.clone(), b.clone()];
+        let root = MultisetCommitment::construct(&data).unwrap().root();
+        let reordered = [vec![a.clone(), b.clone(), a.clone()], vec![b.clone(), a.clone(), a.clone()]];
+        for other in &reordered {
+            assert_eq!(MultisetCommitment::construct(other).unwrap().root(), root);
+            assert!(MerkleTree::verify_multiset(other, &root), "{:?}", other);
+            // the ordered root tells them apart
+            assert!(!MerkleTree::verify_ordered(other, &MerkleTree::construct(&data).root()));
+        }
+        // the counts of repeated entries are committed to, so dropping or adding a repeat fails
+        for other in [vec![a.clone(), b.clone()], vec![a.clone(), b.clone(), b.clone()], vec![a.clone(); 3]] {
+            assert!(!MerkleTree::verify_multiset(&other, &root), "{:?}", other);
+        }
+        assert!(!MerkleTree::verify_multiset(&[], &root));
+        assert!(!MerkleTree::verify_multiset(&data, &MerkleTree::construct(&data).root()));
+    }
+}
@@ -0,0 +1,158 @@
+use super::{parent_level, Data, Hash, MerkleTree, OddLeafPolicy, TreeConfig};
+use crate::util::error::LibError;
+
+/// One node of a tree under construction, as passed to the callback of `TreeConfig::on_node`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NodeEvent<'a> {
+    /// 0 for leaves, counted up to the root
+    pub level: usize,
+    pub index_in_level: usize,
+    pub hash: &'a Hash,
+    /// None for leaves
+    pub left_child: Option<&'a Hash>,
+    /// The left child again for a lone node paired with itself, None for leaves and for a lone node
+    /// promoted unchanged under `OddLeafPolicy::Promote`
+    pub right_child: Option<&'a Hash>,
+}
+
+/// A `TreeConfig` that reports every node it computes, see `TreeConfig::on_node`
+#[derive(Debug, Clone)]
+pub struct ObservedConfig<F> {
+    config: TreeConfig,
+    on_node: F,
+    leaves: bool,
+}
+
+impl TreeConfig {
+    /// Calls `on_node` for every node above the leaves as `construct` computes it, level by level
+    /// from the leaves up and left to right within a level
+    ///
+    /// The callback only sees references, so it cannot change the tree being built. `construct`
+    /// itself takes no callback and pays nothing for this.
+    pub fn on_node<F: FnMut(&NodeEvent)>(self, on_node: F) -> ObservedConfig<F> {
+        ObservedConfig {
+            config: self,
+            on_node,
+            leaves: false,
+        }
+    }
+}
+
+impl<F: FnMut(&NodeEvent)> ObservedConfig<F> {
+    /// Reports the leaves too, as level 0 before any other node
+    pub fn with_leaves(mut self) -> Self {
+        self.leaves = true;
+        self
+    }
+
+    /// Builds the tree `TreeConfig::construct` builds, reporting its nodes on the way
+    pub fn construct(&mut self, input: &[Data]) -> Result<MerkleTree, LibError> {
+        let (policy, hasher) = (self.config.odd_leaf_policy(), self.config.hasher());
+        let leaves = self.config.leaf_hashes(input)?;
+        if self.leaves {
+            for (index_in_level, hash) in leaves.iter().enumerate() {
+                (self.on_node)(&NodeEvent { level: 0, index_in_level, hash, left_child: None, right_child: None });
+            }
+        }
+
+        let mut levels = vec![leaves];
+        while levels[levels.len() - 1].len() > 1 {
+            let below = &levels[levels.len() - 1];
+            let parents = parent_level(below, policy, hasher);
+            for (index_in_level, hash) in parents.iter().enumerate() {
+                let left = &below[2 * index_in_level];
+                let right = match below.get(2 * index_in_level + 1) {
+                    Some(right) => Some(right),
+                    None if policy == OddLeafPolicy::Duplicate => Some(left),
+                    None => None,
+                };
+                (self.on_node)(&NodeEvent {
+                    level: levels.len(),
+                    index_in_level,
+                    hash,
+                    left_child: Some(left),
+                    right_child: right,
+                });
+            }
+            levels.push(parents);
+        }
+        Ok(MerkleTree::from_levels_with(levels, policy, hasher))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{HashAlgorithm, LeafMode, MerkleHasher};
+
+    /// An owned copy of a `NodeEvent`
+    type Recorded = (usize, usize, Hash, Option<Hash>, Option<Hash>);
+
+    fn record(config: TreeConfig, data: &[Data], leaves: bool) -> (MerkleTree, Vec<Recorded>) {
+        let mut events = Vec::new();
+        let mut observed = config.on_node(|event: &NodeEvent| {
+            let (left, right) = (event.left_child.cloned(), event.right_child.cloned());
+            events.push((event.level, event.index_in_level, event.hash.clone(), left, right));
+        });
+        if leaves {
+            observed = observed.with_leaves();
+        }
+        let tree = observed.construct(data).unwrap();
+        (tree, events)
+    }
+
+    fn example_data(n: u8) -> Vec<Data> {
+        (0..n).map(|i| vec![i]).collect()
+    }
+
+    #[test]
+    fn test_events_rebuild_the_levels() {
+        let config = TreeConfig::default();
+        let (tree, events) = record(config, &example_data(8), true);
+        assert_eq!(tree.levels(), config.construct(&example_data(8)).unwrap().levels());
+        assert_eq!(events.len(), 8 + 4 + 2 + 1);
+
+        // events arrive level by level and in order, so appending them rebuilds every level
+        let mut levels: Vec<Vec<Hash>> = Vec::new();
+        for (level, index, hash, left, right) in &events {
+            if *level == levels.len() {
+                levels.push(Vec::new());
+            }
+            assert_eq!((*level, *index), (levels.len() - 1, levels[*level].len()));
+            if *level > 0 {
+                let below = &levels[level - 1];
+                assert_eq!((left.as_ref(), right.as_ref()), (Some(&below[2 * index]), Some(&below[2 * index + 1])));
+                assert_eq!(*hash, HashAlgorithm::Sha256.node_hash(&below[2 * index], &below[2 * index + 1]));
+            } else {
+                assert_eq!((left, right), (&None, &None));
+            }
+            levels[*level].push(hash.clone());
+        }
+        assert_eq!(levels, tree.levels());
+    }
+
+    #[test]
+    fn test_lone_nodes_and_errors() {
+        let data = example_data(5);
+        let (tree, events) = record(TreeConfig::default(), &data, false);
+        assert_eq!(events.len(), 3 + 2 + 1);
+        assert!(events.iter().all(|event| event.0 > 0));
+        // the lone fifth leaf is paired with itself
+        let lone = &tree.levels()[0][4];
+        assert_eq!((events[2].3.as_ref(), events[2].4.as_ref()), (Some(lone), Some(lone)));
+
+        let promoted = TreeConfig::new(LeafMode::HashData).with_odd_leaf_policy(OddLeafPolicy::Promote);
+        let (tree, events) = record(promoted, &data, false);
+        assert_eq!(tree.levels(), promoted.construct(&data).unwrap().levels());
+        assert_eq!((tree.odd_leaf_policy(), events.len()), (OddLeafPolicy::Promote, 3 + 2 + 1));
+        assert_eq!((&events[2].2, events[2].4.as_ref()), (lone, None));
+
+        let (tree, events) = record(TreeConfig::default(), &data[..1], true);
+        assert_eq!((tree.len(), events.len()), (1, 1));
+        let mut calls = 0;
+        let empty = TreeConfig::default().on_node(|_: &NodeEvent| calls += 1).construct(&[]);
+        assert!(matches!(empty, Err(LibError::EmptyInput)));
+        assert_eq!(calls, 0);
+    }
+}
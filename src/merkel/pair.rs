@@ -0,0 +1,192 @@
+use super::{
+    hash_data, hash_pair, is_lone_on_path, tree_depth, Data, Hash, HashAlgorithm, HashDirection, MerkleTree,
+    OddLeafPolicy, OwnedProof, TreeSize,
+};
+use crate::util::error::LibError;
+
+/// Proof of both children of a parent, the leaves `2i` and `2i + 1`, sharing one path above them
+///
+/// Two proofs of adjacent leaves repeat every sibling above their parent; this holds the two leaf
+/// hashes and that path once, `depth + 1` hashes rather than `2 * depth`. At the padded right edge
+/// of an odd-sized tree the last leaf is paired with itself and the proof has no right leaf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairProof {
+    index: usize,
+    tree_size: usize,
+    left: Hash,
+    right: Option<Hash>,
+    upper: OwnedProof,
+}
+
+impl PairProof {
+    /// Index of the left leaf of the pair, always even
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn tree_size(&self) -> usize {
+        self.tree_size
+    }
+
+    pub fn left(&self) -> &Hash {
+        &self.left
+    }
+
+    /// Hash of the right leaf, None when the left leaf is the lone last leaf, paired with itself
+    pub fn right(&self) -> Option<&Hash> {
+        self.right.as_ref()
+    }
+
+    /// Proof of the parent of the pair, the siblings from the level above the leaves up
+    pub fn upper(&self) -> &OwnedProof {
+        &self.upper
+    }
+
+    /// Number of hashes held, the two leaves (or one at the padded edge) and the shared path
+    pub fn hash_count(&self) -> usize {
+        1 + usize::from(self.right.is_some()) + self.upper.hashes().len()
+    }
+}
+
+impl MerkleTree {
+    /// Proof of the pair of leaves `index` belongs to, see `PairProof`
+    ///
+    /// `index` may be either leaf of the pair. Fails with `LibError::IndexOutOfRange` for an index
+    /// past the tree or a tree of one leaf, which has no pairs, and `LibError::InvalidFormat` for a
+    /// tree built with `OddLeafPolicy::Promote`, whose lone nodes have no parent to share.
+    pub fn prove_pair(&self, index: usize) -> Result<PairProof, LibError> {
+        if self.odd_leaf_policy() != OddLeafPolicy::Duplicate {
+            return Err(LibError::InvalidFormat("pair proofs need a tree built with OddLeafPolicy::Duplicate".into()));
+        }
+        let size = self.len();
+        if index >= size || size < 2 {
+            return Err(LibError::IndexOutOfRange { index, size });
+        }
+        let index = index & !1;
+        let proof = self.prove_by_index(index)?;
+        // the first step is the right leaf, or the left one again at the padded edge
+        let Some(((direction, right), upper)) = proof.steps().split_first() else {
+            return Err(LibError::IndexOutOfRange { index, size });
+        };
+        Ok(PairProof {
+            index,
+            tree_size: size,
+            left: self.leaves()[index].clone(),
+            right: (*direction != HashDirection::Duplicate).then(|| right.to_vec()),
+            upper: OwnedProof::new(upper.iter().map(|(direction, hash)| (*direction, *hash)).collect()),
+        })
+    }
+
+    /// Verifies that `left_data` and `right_data` are the leaves of the pair at `index` of a tree
+    /// with `tree_size` leaves and the given root, as `verify_proof_at_index` does for a single leaf
+    ///
+    /// `right_data` is None exactly at the padded right edge. The tree size is the caller's, not the
+    /// proof's: the pair at the padded edge of a tree has the parent of a pair of equal leaves in a
+    /// tree one leaf larger, so a proof of that pair would otherwise show a leaf past the end. A
+    /// proof of another tree size never verifies. Leaves are hashed as by `verify_proof`, for
+    /// trees built with the default hasher.
+    pub fn verify_pair(
+        left_data: &Data,
+        right_data: Option<&Data>,
+        index: usize,
+        tree_size: impl Into<TreeSize>,
+        proof: &PairProof,
+        root_hash: &Hash,
+    ) -> bool {
+        let (size, pair) = (tree_size.into().saturating_usize(), index & !1);
+        if proof.tree_size != size {
+            return false;
+        }
+        let parents = size.div_ceil(2);
+        let upper = proof.upper.as_proof();
+        let right = match (right_data, &proof.right) {
+            (Some(data), Some(right)) if pair + 1 < size && &hash_data(data) == right => right,
+            (None, None) if pair + 1 == size => &proof.left,
+            _ => return false,
+        };
+        size >= 2
+            && proof.index == pair
+            && hash_data(left_data) == proof.left
+            && upper.len() == tree_depth(parents)
+            && upper.implied_index().is_ok_and(|implied| implied == pair / 2)
+            && upper.steps().iter().enumerate().all(|(level, (direction, _))| {
+                *direction != HashDirection::Duplicate || is_lone_on_path(parents, pair / 2, level)
+            })
+            && &upper.root_from(&hash_pair(HashAlgorithm::Sha256, &proof.left, right)) == root_hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_data(n: u8) -> Vec<Data> {
+        (0..n).map(|i| vec![i]).collect()
+    }
+
+    #[test]
+    fn test_pairs_verify_at_every_position() {
+        for n in 2..=11 {
+            let data = example_data(n);
+            let tree = MerkleTree::construct(&data);
+            let (root, size) = (tree.root(), data.len());
+            for index in 0..size {
+                let proof = tree.prove_pair(index).unwrap();
+                let pair = index & !1;
+                assert_eq!(proof, tree.prove_pair(pair).unwrap());
+                let right = data.get(pair + 1);
+                assert!(MerkleTree::verify_pair(&data[pair], right, index, size, &proof, &root), "{} of {}", index, n);
+
+                // both proofs of the pair repeat the path above the pair, the pair proof holds it once
+                let single: usize = [pair, pair + 1].iter().filter_map(|&i| tree.proof_len(i).ok()).sum();
+                let expected = if right.is_some() { tree.depth() + 1 } else { tree.depth() };
+                assert_eq!(proof.hash_count(), expected);
+                if right.is_some() && n > 2 {
+                    assert!(proof.hash_count() < single);
+                }
+
+                let other = vec![0xff];
+                assert!(!MerkleTree::verify_pair(&other, right, index, size, &proof, &root));
+                assert!(!MerkleTree::verify_pair(&data[pair], Some(&other), index, size, &proof, &root));
+                assert!(!MerkleTree::verify_pair(&data[pair], right, index + 2, size, &proof, &root));
+            }
+        }
+    }
+
+    #[test]
+    fn test_padded_edge_and_rejections() {
+        let data = example_data(5);
+        let tree = MerkleTree::construct(&data);
+        let root = tree.root();
+        let proof = tree.prove_pair(4).unwrap();
+        assert_eq!((proof.index(), proof.right(), proof.left()), (4, None, &tree.leaves()[4]));
+        assert!(MerkleTree::verify_pair(&data[4], None, 4, 5, &proof, &root));
+        // the lone last leaf cannot be shown with a partner, not even itself
+        assert!(!MerkleTree::verify_pair(&data[4], Some(&data[4]), 4, 5, &proof, &root));
+        let inner = tree.prove_pair(2).unwrap();
+        assert!(!MerkleTree::verify_pair(&data[2], None, 2, 5, &inner, &root));
+
+        assert!(matches!(tree.prove_pair(5), Err(LibError::IndexOutOfRange { index: 5, size: 5 })));
+        let single = MerkleTree::construct(&example_data(1));
+        assert!(matches!(single.prove_pair(0), Err(LibError::IndexOutOfRange { .. })));
+        let promoted = crate::merkel::TreeConfig::default().with_odd_leaf_policy(OddLeafPolicy::Promote);
+        let promoted = promoted.construct(&data).unwrap();
+        assert!(matches!(promoted.prove_pair(0), Err(LibError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn test_rejects_pairs_past_the_padded_edge() {
+        // the padded last leaf of [a, b, c] has the parent of the pair of [a, b, c, c]
+        let (a, b, c) = (vec![0xa], vec![0xb], vec![0xc]);
+        let three = MerkleTree::construct(&[a.clone(), b.clone(), c.clone()]);
+        let four = MerkleTree::construct(&[a, b, c.clone(), c.clone()]);
+        assert_eq!(three.root(), four.root());
+        let proof = four.prove_pair(2).unwrap();
+        assert!(MerkleTree::verify_pair(&c, Some(&c), 2, 4, &proof, &four.root()));
+        // which would show a leaf 3 of the tree of 3 leaves
+        assert!(!MerkleTree::verify_pair(&c, Some(&c), 2, 3, &proof, &three.root()));
+        let edge = three.prove_pair(2).unwrap();
+        assert!(MerkleTree::verify_pair(&c, None, 2, 3, &edge, &three.root()));
+        assert!(!MerkleTree::verify_pair(&c, None, 2, 4, &edge, &four.root()));
+    }
+}
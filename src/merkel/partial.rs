@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+
+use super::{parent_level, tree_depth, Data, Hash, HashAlgorithm, MerkleTree, OddLeafPolicy, ProofVersion};
+use crate::util::error::LibError;
+
+/// The levels of one shard of a tree built elsewhere, to be combined with `MerkleTree::join`
+///
+/// Every shard but the last must have the same power-of-two number of leaves, so that each shard
+/// is a whole subtree of the joined tree. The hashes are trusted as they are; only the shape of
+/// the levels and the placement of the shard are checked when joining.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "PartialTreeRepr", into = "PartialTreeRepr")]
+pub struct PartialTree {
+    shard_index: usize,
+    shard_count: usize,
+    /// Leaf hashes first, up to the shard's subroot
+    levels: Vec<Vec<Hash>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PartialTreeRepr {
+    #[serde(default)]
+    version: ProofVersion,
+    shard_index: usize,
+    shard_count: usize,
+    leaf_count: u64,
+    subroot: HexHash,
+    levels: Vec<Vec<HexHash>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HexHash(#[serde(with = "crate::util::hex_serde")] Hash);
+
+impl PartialTree {
+    pub fn shard_index(&self) -> usize {
+        self.shard_index
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shard_count
+    }
+
+    /// Number of leaves in this shard
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Root of the shard's own subtree
+    pub fn subroot(&self) -> &Hash {
+        &self.levels[self.levels.len() - 1][0]
+    }
+
+    /// Hashes of every level of the shard, from its leaves (first) up to its subroot (last)
+    pub fn levels(&self) -> &[Vec<Hash>] {
+        &self.levels
+    }
+}
+
+impl MerkleTree {
+    /// Builds shard `shard_index` of `shard_count` from that shard's leaf data
+    pub fn construct_partial(leaves: &[Data], shard_index: usize, shard_count: usize) -> Result<PartialTree, LibError> {
+        if shard_index >= shard_count {
+            return Err(LibError::IndexOutOfRange {
+                index: shard_index,
+                size: shard_count,
+            });
+        }
+        if leaves.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        Ok(PartialTree {
+            shard_index,
+            shard_count,
+            levels: MerkleTree::construct_with_levels(leaves),
+        })
+    }
+
+    /// Combines the shards of a tree, given in any order, into the tree a single build would give
+    ///
+    /// Fails with `LibError::InvalidFormat` if a shard is missing, duplicated or claims a different
+    /// shard count, or if the shards are not sized as `PartialTree` requires.
+    pub fn join(mut partials: Vec<PartialTree>) -> Result<MerkleTree, LibError> {
+        if partials.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        partials.sort_by_key(|partial| partial.shard_index);
+        let count = partials[0].shard_count;
+        for (i, partial) in partials.iter().enumerate() {
+            if partial.shard_count != count {
+                let message = format!("shards disagree on the shard count, {} or {}", count, partial.shard_count);
+                return Err(invalid(message));
+            }
+            match partial.shard_index {
+                index if index < i => return Err(invalid(format!("shard {} appears twice", index))),
+                index if index > i => return Err(invalid(format!("shard {} of {} is missing", i, count))),
+                _ => {}
+            }
+        }
+        if partials.len() < count {
+            return Err(invalid(format!("shard {} of {} is missing", partials.len(), count)));
+        }
+        let shard_size = partials[0].leaf_count();
+        if count > 1 && !shard_size.is_power_of_two() {
+            return Err(invalid(format!("shard size {} is not a power of two", shard_size)));
+        }
+        for (i, partial) in partials.iter().enumerate() {
+            let size = partial.leaf_count();
+            if size > shard_size || (size < shard_size && i + 1 < count) {
+                return Err(invalid(format!("shard {} has {} leaves, expected {}", i, size, shard_size)));
+            }
+        }
+
+        // the shards side by side make up every level up to their subroots
+        let height = tree_depth(shard_size);
+        let mut levels = vec![vec![]; height + 1];
+        for mut partial in partials {
+            // a short last shard reaches its subroot lower and is padded up to the shard height
+            while partial.levels.len() <= height {
+                let top = &partial.levels[partial.levels.len() - 1];
+                partial.levels.push(parent_level(top, OddLeafPolicy::Duplicate, HashAlgorithm::Sha256));
+            }
+            for (level, hashes) in levels.iter_mut().zip(partial.levels) {
+                level.extend(hashes);
+            }
+        }
+        while levels[levels.len() - 1].len() > 1 {
+            levels.push(parent_level(&levels[levels.len() - 1], OddLeafPolicy::Duplicate, HashAlgorithm::Sha256));
+        }
+        Ok(MerkleTree::from_levels(levels))
+    }
+}
+
+impl From<PartialTree> for PartialTreeRepr {
+    fn from(partial: PartialTree) -> Self {
+        PartialTreeRepr {
+            version: ProofVersion::CURRENT,
+            shard_index: partial.shard_index,
+            shard_count: partial.shard_count,
+            leaf_count: partial.leaf_count() as u64,
+            subroot: HexHash(partial.subroot().clone()),
+            levels: partial
+                .levels
+                .into_iter()
+                .map(|level| level.into_iter().map(HexHash).collect())
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<PartialTreeRepr> for PartialTree {
+    type Error = LibError;
+
+    /// Rejects partial trees whose levels are not shaped like a tree over `leaf_count` leaves
+    fn try_from(repr: PartialTreeRepr) -> Result<Self, LibError> {
+        let (ProofVersion::V1 | ProofVersion::V2) = repr.version;
+        let levels: Vec<Vec<Hash>> = repr
+            .levels
+            .into_iter()
+            .map(|level| level.into_iter().map(|hash| hash.0).collect())
+            .collect();
+        let leaf_count = super::to_usize(repr.leaf_count, "leaf count")?;
+        if leaf_count == 0 || levels.len() != tree_depth(leaf_count) + 1 {
+            return Err(invalid(format!("partial tree of {} leaves has {} levels", leaf_count, levels.len())));
+        }
+        for (k, level) in levels.iter().enumerate() {
+            if level.len() != leaf_count.div_ceil(1 << k) {
+                return Err(invalid(format!("level {} of the partial tree has {} hashes", k, level.len())));
+            }
+        }
+        if levels[levels.len() - 1][0] != repr.subroot.0 {
+            return Err(invalid("the subroot is not the top of the levels".to_string()));
+        }
+        if repr.shard_index >= repr.shard_count {
+            return Err(invalid(format!("shard {} of {} does not exist", repr.shard_index, repr.shard_count)));
+        }
+        Ok(PartialTree {
+            shard_index: repr.shard_index,
+            shard_count: repr.shard_count,
+            levels,
+        })
+    }
+}
+
+fn invalid(message: String) -> LibError {
+    LibError::InvalidFormat(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| (i as u32).to_be_bytes().to_vec()).collect()
+    }
+
+    fn partials(data: &[Data], shard_size: usize) -> Vec<PartialTree> {
+        let count = data.len().div_ceil(shard_size);
+        data.chunks(shard_size)
+            .enumerate()
+            .map(|(i, shard)| MerkleTree::construct_partial(shard, i, count).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_join_matches_monolithic_build() {
+        let data = example_data(1000);
+        let expected = MerkleTree::construct(&data);
+        let mut shards = partials(&data, 256);
+        assert_eq!(shards.iter().map(PartialTree::leaf_count).collect::<Vec<_>>(), [256, 256, 256, 232]);
+        // shards arrive in any order, possibly after a trip over the network
+        shards.reverse();
+        let shards: Vec<PartialTree> = shards
+            .iter()
+            .map(|shard| serde_json::from_str(&serde_json::to_string(shard).unwrap()).unwrap())
+            .collect();
+
+        let joined = MerkleTree::join(shards).unwrap();
+        assert_eq!(joined.root(), expected.root());
+        assert_eq!(joined.levels(), expected.levels());
+        for index in [0, 255, 256, 511, 767, 768, 999] {
+            let proof = joined.prove_by_index(index).unwrap();
+            assert_eq!(proof.into_owned(), expected.prove_by_index(index).unwrap().into_owned());
+            assert!(MerkleTree::verify_proof(&data[index], &joined.prove_by_index(index).unwrap(), &expected.root()));
+        }
+
+        // a short last shard below its own height, and a single shard
+        let data = example_data(1030);
+        assert_eq!(MerkleTree::join(partials(&data, 256)).unwrap().levels(), MerkleTree::construct(&data).levels());
+        assert_eq!(MerkleTree::join(partials(&data, 2048)).unwrap().root(), MerkleTree::construct(&data).root());
+    }
+
+    #[test]
+    fn test_join_rejects_bad_shards() {
+        let data = example_data(1000);
+        let message = |shards| MerkleTree::join(shards).err().unwrap().to_string();
+
+        let mut missing = partials(&data, 256);
+        missing.remove(1);
+        assert_eq!(message(missing), "invalid format: shard 1 of 4 is missing");
+        let mut missing = partials(&data, 256);
+        missing.pop();
+        assert_eq!(message(missing), "invalid format: shard 3 of 4 is missing");
+        let mut duplicated = partials(&data, 256);
+        duplicated[2] = duplicated[1].clone();
+        assert_eq!(message(duplicated), "invalid format: shard 1 appears twice");
+        let mut disagreeing = partials(&data, 256);
+        disagreeing[3] = MerkleTree::construct_partial(&data[768..], 3, 5).unwrap();
+        assert_eq!(message(disagreeing), "invalid format: shards disagree on the shard count, 4 or 5");
+        assert_eq!(message(partials(&data, 200)), "invalid format: shard size 200 is not a power of two");
+        let mut uneven = partials(&data[..768], 256);
+        uneven[1] = MerkleTree::construct_partial(&data[256..500], 1, 3).unwrap();
+        assert_eq!(message(uneven), "invalid format: shard 1 has 244 leaves, expected 256");
+        assert!(matches!(MerkleTree::join(vec![]), Err(LibError::EmptyInput)));
+        assert!(matches!(
+            MerkleTree::construct_partial(&data, 4, 4),
+            Err(LibError::IndexOutOfRange { index: 4, size: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_checks_shape() {
+        let shard = MerkleTree::construct_partial(&example_data(5), 0, 1).unwrap();
+        let mut value = serde_json::to_value(&shard).unwrap();
+        assert_eq!((value["leaf_count"].clone(), value["version"].clone()), (5.into(), 2.into()));
+
+        value["levels"][1].as_array_mut().unwrap().pop();
+        let err = serde_json::from_value::<PartialTree>(value.clone()).unwrap_err().to_string();
+        assert!(err.contains("level 1 of the partial tree has 2 hashes"), "{}", err);
+        value["levels"] = serde_json::to_value(&shard).unwrap()["levels"].clone();
+        value["subroot"] = "00".repeat(32).into();
+        let err = serde_json::from_value::<PartialTree>(value).unwrap_err().to_string();
+        assert!(err.contains("the subroot is not the top of the levels"), "{}", err);
+    }
+}
@@ -0,0 +1,335 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{
+    to_usize, tree_depth, Hash, HashDirection, LeafIndex, MerkleTree, NodeId, OddLeafPolicy, Proof, ProofSteps,
+    ProofVersion,
+};
+use crate::util::error::LibError;
+
+/// Which parts of a tree `MerkleTree::prune` keeps
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PruneSpec {
+    leaves: BTreeSet<usize>,
+    top_levels: usize,
+}
+
+/// A tree reduced to a few pinned leaves with their proof paths, plus optionally its top levels
+///
+/// Every other subtree is represented only by its covering subroot, i.e. the sibling hash on a
+/// pinned leaf's path, so the pinned leaves can still be proven against the original root.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "PrunedTreeRepr", into = "PrunedTreeRepr")]
+pub struct PrunedTree {
+    leaf_count: usize,
+    policy: OddLeafPolicy,
+    leaves: BTreeSet<usize>,
+    /// Retained hashes keyed by (level, index)
+    nodes: BTreeMap<(usize, usize), Hash>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PrunedTreeRepr {
+    /// Missing from payloads written before formats were versioned
+    #[serde(default)]
+    version: ProofVersion,
+    leaf_count: u64,
+    #[serde(default)]
+    odd_leaf_policy: OddLeafPolicy,
+    leaves: Vec<usize>,
+    nodes: Vec<PrunedNode>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PrunedNode {
+    level: usize,
+    index: usize,
+    #[serde(with = "crate::util::hex_serde")]
+    hash: Hash,
+}
+
+impl PruneSpec {
+    /// Keeps the given leaves and the hashes needed to prove them
+    pub fn new<I: IntoIterator<Item = usize>>(leaves: I) -> Self {
+        PruneSpec {
+            leaves: leaves.into_iter().collect(),
+            top_levels: 0,
+        }
+    }
+
+    /// Also keeps every node of the top `levels` levels, counting the root as the first
+    pub fn with_top_levels(mut self, levels: usize) -> Self {
+        self.top_levels = levels;
+        self
+    }
+}
+
+impl MerkleTree {
+    /// Number of hashes stored in the tree, over all levels
+    pub fn node_count(&self) -> usize {
+        (0..=self.depth()).map(|level| self.len().div_ceil(1 << level)).sum()
+    }
+
+    /// Drops everything that is not needed to prove the leaves listed in `keep`
+    pub fn prune(&self, keep: &PruneSpec) -> Result<PrunedTree, LibError> {
+        let mut nodes = BTreeMap::new();
+        let mut insert = |id: NodeId| {
+            if let Some(hash) = self.node(id) {
+                nodes.entry((id.level(), id.index())).or_insert_with(|| hash.clone());
+            }
+        };
+
+        insert(self.root_id());
+        for &index in &keep.leaves {
+            let mut id = self.leaf_id(index).ok_or(LibError::IndexOutOfRange {
+                index,
+                size: self.len(),
+            })?;
+            insert(id);
+            while let Some(parent) = self.parent(id) {
+                if let Some(sibling) = self.sibling(id) {
+                    insert(sibling);
+                }
+                insert(parent);
+                id = parent;
+            }
+        }
+
+        let mut frontier = vec![self.root_id()];
+        for _ in 1..keep.top_levels {
+            frontier = frontier
+                .iter()
+                .filter_map(|id| self.children(*id))
+                .flat_map(|(left, right)| if left == right { vec![left] } else { vec![left, right] })
+                .collect();
+            frontier.iter().for_each(|id| insert(*id));
+        }
+
+        Ok(PrunedTree {
+            leaf_count: self.len(),
+            policy: self.odd_leaf_policy(),
+            leaves: keep.leaves.clone(),
+            nodes,
+        })
+    }
+}
+
+impl PrunedTree {
+    pub fn root(&self) -> Hash {
+        self.nodes[&(tree_depth(self.leaf_count), 0)].clone()
+    }
+
+    /// Number of leaves of the original tree
+    pub fn len(&self) -> usize {
+        self.leaf_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    /// Indices of the leaves that can still be proven
+    pub fn retained_leaves(&self) -> impl Iterator<Item = usize> + '_ {
+        self.leaves.iter().copied()
+    }
+
+    /// Number of hashes kept after pruning
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns the proof for a retained leaf, or `LibError::Pruned` if it was discarded
+    pub fn prove_by_index(&self, index: impl Into<LeafIndex>) -> Result<Proof<'_>, LibError> {
+        let index = index.into().saturating_usize();
+        if index >= self.leaf_count {
+            return Err(LibError::IndexOutOfRange {
+                index,
+                size: self.leaf_count,
+            });
+        }
+        if !self.leaves.contains(&index) {
+            return Err(LibError::Pruned { index });
+        }
+        self.path(index)
+    }
+
+    fn path(&self, index: usize) -> Result<Proof<'_>, LibError> {
+        let mut hashes = ProofSteps::new();
+        let mut i = index;
+        for level in 0..tree_depth(self.leaf_count) {
+            let level_len = self.leaf_count.div_ceil(1 << level);
+            let (direction, sibling) = if i % 2 == 1 {
+                (HashDirection::Left, i - 1)
+            } else if i + 1 < level_len {
+                (HashDirection::Right, i + 1)
+            } else if self.policy == OddLeafPolicy::Duplicate {
+                (HashDirection::Duplicate, i)
+            } else {
+                i /= 2;
+                continue;
+            };
+            let hash = self
+                .nodes
+                .get(&(level, sibling))
+                .ok_or_else(|| LibError::InvalidFormat(format!("missing node {} at level {}", sibling, level)))?;
+            hashes.push((direction, hash));
+            i /= 2;
+        }
+        Ok(Proof::from_hashes(hashes).with_tree_size(self.leaf_count))
+    }
+}
+
+impl From<PrunedTree> for PrunedTreeRepr {
+    fn from(tree: PrunedTree) -> Self {
+        PrunedTreeRepr {
+            version: ProofVersion::CURRENT,
+            leaf_count: tree.leaf_count as u64,
+            odd_leaf_policy: tree.policy,
+            leaves: tree.leaves.into_iter().collect(),
+            nodes: tree
+                .nodes
+                .into_iter()
+                .map(|((level, index), hash)| PrunedNode { level, index, hash })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<PrunedTreeRepr> for PrunedTree {
+    type Error = LibError;
+
+    /// Rejects pruned trees whose retained paths do not lead back to the stored root
+    fn try_from(repr: PrunedTreeRepr) -> Result<Self, LibError> {
+        let policy = match repr.version {
+            ProofVersion::V1 | ProofVersion::V2 => repr.odd_leaf_policy,
+        };
+        let tree = PrunedTree {
+            leaf_count: to_usize(repr.leaf_count, "leaf count")?,
+            policy,
+            leaves: repr.leaves.into_iter().collect(),
+            nodes: repr.nodes.into_iter().map(|n| ((n.level, n.index), n.hash)).collect(),
+        };
+        let root = tree
+            .nodes
+            .get(&(tree_depth(tree.leaf_count), 0))
+            .ok_or_else(|| LibError::InvalidFormat("pruned tree has no root".into()))?;
+
+        for &index in &tree.leaves {
+            let leaf = tree
+                .nodes
+                .get(&(0, index))
+                .ok_or_else(|| LibError::InvalidFormat(format!("retained leaf {} is missing", index)))?;
+            if index >= tree.leaf_count || &tree.path(index)?.root_from(leaf) != root {
+                return Err(LibError::InvalidFormat(format!("retained leaf {} does not match the root", index)));
+            }
+        }
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{Data, TreeConfig};
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| (i as u32).to_le_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_pinned_leaves_still_verify() {
+        let data = example_data(1024);
+        let tree = MerkleTree::construct(&data);
+        let pruned = tree.prune(&PruneSpec::new([3, 500, 1023])).unwrap();
+
+        assert_eq!(pruned.root(), tree.root());
+        assert_eq!(pruned.retained_leaves().collect::<Vec<_>>(), vec![3, 500, 1023]);
+        for index in [3, 500, 1023] {
+            let proof = pruned.prove_by_index(index).unwrap();
+            assert_eq!(proof.into_owned(), tree.prove_by_index(index).unwrap().into_owned());
+            assert!(MerkleTree::verify_proof(&data[index], &pruned.prove_by_index(index).unwrap(), &tree.root()));
+        }
+        assert!(matches!(pruned.prove_by_index(4), Err(LibError::Pruned { index: 4 })));
+        assert!(matches!(pruned.prove_by_index(1024), Err(LibError::IndexOutOfRange { .. })));
+
+        // at most the leaf, a sibling and a parent per level for each pinned leaf
+        assert!(pruned.node_count() <= 3 * (1 + 2 * tree.depth()));
+        assert_eq!(tree.node_count(), 2047);
+
+        let full = tree.prune(&PruneSpec::new(0..1024)).unwrap();
+        assert_eq!(full.node_count(), tree.node_count());
+        let pruned_size = serde_json::to_vec(&pruned).unwrap().len();
+        let full_size = serde_json::to_vec(&full).unwrap().len();
+        assert!(pruned_size * 20 < full_size, "{} vs {}", pruned_size, full_size);
+    }
+
+    #[test]
+    fn test_top_levels_and_padding() {
+        let data = example_data(5);
+        let tree = MerkleTree::construct(&data);
+
+        let pruned = tree.prune(&PruneSpec::new([4]).with_top_levels(2)).unwrap();
+        assert!(MerkleTree::verify_proof(&data[4], &pruned.prove_by_index(4).unwrap(), &tree.root()));
+        // root, the two level-2 nodes, then leaf 4's own path and padded siblings
+        assert_eq!(pruned.node_count(), 1 + 2 + 2);
+
+        let top_only = tree.prune(&PruneSpec::default().with_top_levels(3)).unwrap();
+        assert_eq!(top_only.node_count(), 1 + 2 + 3);
+        assert!(matches!(top_only.prove_by_index(0), Err(LibError::Pruned { index: 0 })));
+
+        assert!(matches!(tree.prune(&PruneSpec::new([5])), Err(LibError::IndexOutOfRange { index: 5, .. })));
+    }
+
+    #[test]
+    fn test_promoted_tree() {
+        let data = example_data(11);
+        let config = TreeConfig::default().with_odd_leaf_policy(OddLeafPolicy::Promote);
+        let tree = config.construct(&data).unwrap();
+        let pruned = tree.prune(&PruneSpec::new([5, 10])).unwrap();
+
+        for index in [5, 10] {
+            let proof = pruned.prove_by_index(index).unwrap();
+            assert_eq!(proof.into_owned(), tree.prove_by_index(index).unwrap().into_owned());
+        }
+        let loaded: PrunedTree = serde_json::from_str(&serde_json::to_string(&pruned).unwrap()).unwrap();
+        assert_eq!(loaded, pruned);
+    }
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let data = example_data(37);
+        let tree = MerkleTree::construct(&data);
+        let pruned = tree.prune(&PruneSpec::new([0, 20, 36])).unwrap();
+
+        let json = serde_json::to_string(&pruned).unwrap();
+        let loaded: PrunedTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, pruned);
+        assert!(MerkleTree::verify_proof(&data[36], &loaded.prove_by_index(36).unwrap(), &tree.root()));
+
+        // a tampered covering subroot no longer leads to the root
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["nodes"][1]["hash"] = serde_json::Value::String(hex::encode([0u8; 32]));
+        assert!(serde_json::from_value::<PrunedTree>(value).is_err());
+    }
+
+    #[test]
+    fn test_versions() {
+        let tree = MerkleTree::construct(&example_data(6));
+        let pruned = tree.prune(&PruneSpec::new([4])).unwrap();
+        let mut value = serde_json::to_value(&pruned).unwrap();
+        assert_eq!(value["version"], 2);
+
+        // version 1 predates the odd leaf policy, which then defaults to duplication
+        let mut legacy = value.clone();
+        let fields = legacy.as_object_mut().unwrap();
+        fields.remove("version");
+        fields.remove("odd_leaf_policy");
+        assert_eq!(serde_json::from_value::<PrunedTree>(legacy).unwrap(), pruned);
+
+        value["version"] = 7.into();
+        let err = serde_json::from_value::<PrunedTree>(value).unwrap_err().to_string();
+        assert!(err.contains("unsupported format version 7"), "{}", err);
+    }
+}
@@ -0,0 +1,109 @@
+use sha2::{Digest, Sha256};
+
+use super::{Hash, HashDirection, Proof, Sha256Hasher, HASH_LEN};
+
+/// Verifies a proof of the default hasher held in fixed-size arrays, without allocating
+///
+/// The running hash is kept on the stack and each parent is hashed with the streaming `Digest`
+/// API, so this suits environments without a heap, such as a zkVM guest. `verify_proof` and
+/// `verify_proof_hash` fold their proofs the same way. Unlike them it has no tree size to check
+/// an empty path against, which proves a leaf equal to the root.
+pub fn verify_proof_raw(
+    leaf_hash: &[u8; HASH_LEN],
+    path: &[(HashDirection, [u8; HASH_LEN])],
+    root: &[u8; HASH_LEN],
+) -> bool {
+    &fold_raw(leaf_hash, path.iter().map(|(direction, sibling)| (*direction, sibling))) == root
+}
+
+/// The root a path leads to from `leaf_hash`, hashing as `Sha256Hasher::node_hash` does
+fn fold_raw<'a>(
+    leaf_hash: &[u8; HASH_LEN],
+    path: impl IntoIterator<Item = (HashDirection, &'a [u8; HASH_LEN])>,
+) -> [u8; HASH_LEN] {
+    let mut running = *leaf_hash;
+    for (direction, sibling) in path {
+        let (left, right) = match direction {
+            HashDirection::Left => (sibling, &running),
+            HashDirection::Right | HashDirection::Duplicate => (&running, sibling),
+        };
+        running = Sha256::new().chain_update(left).chain_update(right).finalize().into();
+    }
+    running
+}
+
+/// `MerkleTree::verify_proof_hash` of a 32-byte leaf hash, through `verify_proof_raw` unless a
+/// sibling of the proof is of another length
+pub(super) fn verify_proof_sha256(leaf_hash: &[u8; HASH_LEN], proof: &Proof, root_hash: &Hash) -> bool {
+    let Ok(root) = <&[u8; HASH_LEN]>::try_from(root_hash.as_slice()) else {
+        return false;
+    };
+    if !proof.fits_tree_size() {
+        return false;
+    }
+    if proof.steps().iter().any(|(_, sibling)| sibling.len() != HASH_LEN) {
+        // trees from `MerkleTree::from_leaf_hashes` may have leaves of any length
+        return &proof.root_from_with(&Sha256Hasher, &leaf_hash.to_vec()) == root_hash;
+    }
+    let path = proof.steps().iter().filter_map(|(direction, sibling)| Some((*direction, (*sibling).try_into().ok()?)));
+    &fold_raw(leaf_hash, path) == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{hash_data, MerkleTree, OwnedProof};
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn raw_path(proof: &Proof) -> Vec<(HashDirection, [u8; HASH_LEN])> {
+        proof.steps().iter().map(|(direction, hash)| (*direction, (*hash).try_into().unwrap())).collect()
+    }
+
+    #[test]
+    fn test_agrees_with_the_generic_verifier() {
+        let mut rng = StdRng::seed_from_u64(180);
+        for _ in 0..200 {
+            let size = rng.random_range(2..80);
+            let data: Vec<Vec<u8>> = (0..size).map(|_| rng.random::<[u8; 8]>().to_vec()).collect();
+            let tree = MerkleTree::construct(&data);
+            let index = rng.random_range(0..size);
+            let mut owned = tree.prove_by_index(index).unwrap().into_owned();
+            let (leaf, root) = (hash_data(&data[index]), tree.root());
+            if rng.random_bool(0.5) {
+                // tamper with one step, its hash or its side
+                let step = rng.random_range(0..owned.hashes().len());
+                let mut hashes = owned.hashes().to_vec();
+                match rng.random_bool(0.5) {
+                    true => hashes[step].1[rng.random_range(0..HASH_LEN)] ^= 1,
+                    false => hashes[step].0 = HashDirection::from_bit(!hashes[step].0.to_bit()),
+                }
+                owned = OwnedProof::new(hashes);
+            }
+            let proof = owned.as_proof();
+            let expected = MerkleTree::verify_proof_hash_with(&Sha256Hasher, &leaf, &proof, &root);
+            let raw = verify_proof_raw(&leaf[..].try_into().unwrap(), &raw_path(&proof), &root[..].try_into().unwrap());
+            assert_eq!(raw, expected, "leaf {} of {}", index, size);
+            assert_eq!(MerkleTree::verify_proof(&data[index], &proof, &root), expected);
+            assert_eq!(MerkleTree::verify_proof_hash(&leaf, &proof, &root), expected);
+        }
+    }
+
+    #[test]
+    fn test_odd_lengths_fall_back() {
+        // leaves of another length than a hash still verify as before
+        let tree = MerkleTree::from_leaf_hashes(vec![vec![1; 20], vec![2; 20], vec![3; 20]]);
+        for index in 0..3 {
+            let proof = tree.prove_by_index(index).unwrap();
+            assert!(MerkleTree::verify_proof_hash(&tree.leaves()[index], &proof, &tree.root()));
+        }
+        let single = MerkleTree::construct(&[vec![7]]);
+        let proof = single.prove_by_index(0).unwrap();
+        assert!(MerkleTree::verify_proof(&vec![7], &proof, &single.root()));
+        assert!(!MerkleTree::verify_proof(&vec![7], &OwnedProof::new::<Hash>(vec![]).as_proof(), &single.root()));
+        let leaf: [u8; HASH_LEN] = single.root()[..].try_into().unwrap();
+        assert!(verify_proof_raw(&leaf, &[], &leaf));
+        assert!(!MerkleTree::verify_proof_hash(&leaf.to_vec(), &proof, &vec![0; 20]));
+    }
+}
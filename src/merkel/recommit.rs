@@ -0,0 +1,92 @@
+use super::{parent_level_with, Data, Hash, MerkleHasher, MerkleTree};
+use crate::util::error::LibError;
+
+impl MerkleTree {
+    /// Checks that `data` reproduces the leaves of this tree and returns the root of the same
+    /// entries under the hasher `H2`, for moving a commitment to another hasher
+    ///
+    /// See `recommit_leaves_with`.
+    pub fn recommit_leaves<H2: MerkleHasher + Default>(&self, data: &[Data]) -> Result<Hash, LibError> {
+        self.recommit_leaves_with(&H2::default(), data)
+    }
+
+    /// Like `recommit_leaves`, with the new hasher given as a value
+    ///
+    /// Only the leaf hashes of this tree are needed, which must be the hashes of the entries under
+    /// its own hasher. Each entry is checked against its leaf and hashed with `hasher` in the same
+    /// pass, and the new leaves are paired under this tree's odd leaf policy. Fails with
+    /// `LibError::LeafMismatch` at the first entry that does not reproduce its leaf, or at the
+    /// first index only one of the two has if there are more or fewer entries than leaves.
+    pub fn recommit_leaves_with<H2: MerkleHasher + ?Sized>(
+        &self,
+        hasher: &H2,
+        data: &[Data],
+    ) -> Result<Hash, LibError> {
+        let old = self.hasher();
+        let mut leaves = Vec::with_capacity(data.len());
+        for (index, (entry, leaf)) in data.iter().zip(self.leaves()).enumerate() {
+            if old.leaf_hash(entry) != *leaf {
+                return Err(LibError::LeafMismatch { index });
+            }
+            leaves.push(hasher.leaf_hash(entry));
+        }
+        if data.len() != self.len() {
+            return Err(LibError::LeafMismatch { index: data.len().min(self.len()) });
+        }
+        while leaves.len() > 1 {
+            leaves = parent_level_with(&leaves, self.odd_leaf_policy(), |left, right| hasher.node_hash(left, right));
+        }
+        leaves.pop().ok_or(LibError::EmptyInput)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{HashAlgorithm, OddLeafPolicy, Sha256dHasher, Sha512Hasher, TreeConfig};
+
+    fn records(n: usize) -> Vec<Data> {
+        (0..n).map(|i| format!("record {}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_clean_migration() {
+        let data = records(500);
+        for policy in [OddLeafPolicy::Duplicate, OddLeafPolicy::Promote] {
+            let old = TreeConfig::default().with_odd_leaf_policy(policy).construct(&data).unwrap();
+            for hasher in [HashAlgorithm::Sha512, HashAlgorithm::Sha256d, HashAlgorithm::Ripemd160] {
+                let new = TreeConfig::default().with_odd_leaf_policy(policy).with_hasher(hasher);
+                let expected = new.construct(&data).unwrap().root();
+                assert_eq!(old.recommit_leaves_with(&hasher, &data).unwrap(), expected, "{:?} {:?}", policy, hasher);
+            }
+            // back again, from a tree that only kept the new leaves
+            let sha512 = TreeConfig::default().with_odd_leaf_policy(policy).with_hasher(HashAlgorithm::Sha512);
+            let migrated = sha512.construct(&data).unwrap();
+            assert_eq!(migrated.recommit_leaves_with(&HashAlgorithm::Sha256, &data).unwrap(), old.root());
+        }
+        let old = MerkleTree::construct(&data);
+        let sha512 = TreeConfig::default().with_hasher(HashAlgorithm::Sha512).construct(&data).unwrap();
+        assert_eq!(old.recommit_leaves::<Sha512Hasher>(&data).unwrap(), sha512.root());
+        let truncated = old.recommit_leaves::<Sha256dHasher>(&records(1));
+        assert!(matches!(truncated, Err(LibError::LeafMismatch { index: 1 })));
+    }
+
+    #[test]
+    fn test_tampered_record() {
+        let data = records(500);
+        let old = MerkleTree::construct(&data);
+        let mut tampered = data.clone();
+        tampered[137] = b"record 137, amended".to_vec();
+        tampered[400] = b"record 400, amended".to_vec();
+        let error = old.recommit_leaves::<Sha512Hasher>(&tampered).unwrap_err();
+        assert!(matches!(error, LibError::LeafMismatch { index: 137 }));
+        assert_eq!(error.to_string(), "the entries do not reproduce the leaves of the tree from index 137");
+
+        let swapped: Vec<Data> = [&data[..1], &data[2..3], &data[1..2], &data[3..]].concat();
+        assert!(matches!(old.recommit_leaves::<Sha512Hasher>(&swapped), Err(LibError::LeafMismatch { index: 1 })));
+        let extra = [data.clone(), records(1)].concat();
+        assert!(matches!(old.recommit_leaves::<Sha512Hasher>(&extra), Err(LibError::LeafMismatch { index: 500 })));
+        let short = &data[..499];
+        assert!(matches!(old.recommit_leaves::<Sha512Hasher>(short), Err(LibError::LeafMismatch { index: 499 })));
+    }
+}
@@ -0,0 +1,305 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use std::collections::VecDeque;
+
+use super::{check_step_count, hash_data, Data, Hash, MerkleHasher, MerkleTree, Proof, Sha256Hasher};
+use crate::util::error::LibError;
+
+/// Hex characters `RootSet::unambiguous_abbreviation` starts from before growing the prefix
+pub const MIN_ABBREVIATION_LEN: usize = 8;
+
+/// Abbreviated hex of a hash, for showing roots to people
+pub trait ShortHash {
+    /// The first `len` hex characters, or the whole hex of a shorter hash
+    fn short(&self, len: usize) -> String;
+}
+
+impl ShortHash for [u8] {
+    fn short(&self, len: usize) -> String {
+        let mut hex = hex::encode(&self[..len.div_ceil(2).min(self.len())]);
+        hex.truncate(len);
+        hex
+    }
+}
+
+/// Why a hex prefix did not name exactly one root of a `RootSet`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum ResolveError {
+    #[error("no root starts with `{prefix}`")]
+    NotFound { prefix: String },
+
+    #[error("`{prefix}` is the start of {} roots: {}", .candidates.len(), hex_list(.candidates))]
+    Ambiguous {
+        prefix: String,
+        /// The distinct roots starting with the prefix, oldest first
+        candidates: Vec<Hash>,
+    },
+}
+
+fn hex_list(hashes: &[Hash]) -> String {
+    hashes.iter().map(hex::encode).collect::<Vec<_>>().join(", ")
+}
+
+/// Recently published roots that proofs are still accepted against, oldest first
+///
+/// Each root carries free-form metadata, such as the date it was published.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootSet {
+    roots: VecDeque<RootEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct RootEntry {
+    #[serde(with = "crate::util::hex_serde")]
+    root: Hash,
+    metadata: String,
+}
+
+impl MerkleTree {
+    /// Verifies a proof against several candidate roots, returning the index of the first match
+    ///
+    /// Every root is compared in constant time and all of them are checked, so the timing does not
+    /// reveal which root matched.
+    pub fn verify_proof_multi_root(data: &Data, proof: &Proof, roots: &[Hash]) -> Option<usize> {
+        MerkleTree::verify_proof_hash_multi_root(&hash_data(data), proof, roots)
+    }
+
+    /// Like `verify_proof_multi_root`, but starting from an already hashed leaf
+    pub fn verify_proof_hash_multi_root(leaf_hash: &Hash, proof: &Proof, roots: &[Hash]) -> Option<usize> {
+        MerkleTree::verify_proof_hash_multi_root_with(&Sha256Hasher, leaf_hash, proof, roots)
+    }
+
+    /// Like `verify_proof_hash_multi_root`, for a proof from a tree built with another hasher
+    pub fn verify_proof_hash_multi_root_with<H: MerkleHasher + ?Sized>(
+        hasher: &H,
+        leaf_hash: &Hash,
+        proof: &Proof,
+        roots: &[Hash],
+    ) -> Option<usize> {
+        if !proof.fits_tree_size() {
+            return None;
+        }
+        let computed = proof.root_from_with(hasher, leaf_hash);
+        roots.iter().enumerate().fold(None, |found, (index, root)| {
+            if ct_eq(&computed, root) {
+                found.or(Some(index))
+            } else {
+                found
+            }
+        })
+    }
+}
+
+impl RootSet {
+    pub fn new() -> Self {
+        RootSet::default()
+    }
+
+    /// Adds a newly published root
+    pub fn insert(&mut self, root: Hash, metadata: impl Into<String>) {
+        self.roots.push_back(RootEntry {
+            root,
+            metadata: metadata.into(),
+        });
+    }
+
+    /// Keeps only the `n` most recently inserted roots
+    pub fn retain_last(&mut self, n: usize) {
+        let excess = self.roots.len().saturating_sub(n);
+        self.roots.drain(..excess);
+    }
+
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// Root and metadata at `index`, counting from the oldest root
+    pub fn get(&self, index: usize) -> Option<(&Hash, &str)> {
+        self.roots.get(index).map(|entry| (&entry.root, entry.metadata.as_str()))
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = &Hash> + '_ {
+        self.roots.iter().map(|entry| &entry.root)
+    }
+
+    /// The shortest prefix of the hex of `hash`, at least `MIN_ABBREVIATION_LEN` characters, that
+    /// starts no other root of the set, as git abbreviates commit ids
+    ///
+    /// The whole hex is returned if every shorter prefix is shared, and a root inserted twice does
+    /// not collide with itself.
+    pub fn unambiguous_abbreviation(&self, hash: &Hash) -> String {
+        let full = hex::encode(hash);
+        let others: Vec<String> = self.roots().filter(|root| *root != hash).map(hex::encode).collect();
+        (MIN_ABBREVIATION_LEN..full.len())
+            .map(|len| &full[..len])
+            .find(|prefix| !others.iter().any(|other| other.starts_with(prefix)))
+            .map_or_else(|| full.clone(), str::to_string)
+    }
+
+    /// The one root whose hex starts with `prefix`, ignoring case
+    ///
+    /// Fails with `ResolveError::NotFound` if no root does, and `ResolveError::Ambiguous` listing
+    /// them if several distinct roots do.
+    pub fn resolve_prefix(&self, prefix: &str) -> Result<Hash, ResolveError> {
+        let lower = prefix.to_ascii_lowercase();
+        let mut candidates: Vec<Hash> = vec![];
+        for root in self.roots().filter(|root| hex::encode(root).starts_with(&lower)) {
+            if !candidates.contains(root) {
+                candidates.push(root.clone());
+            }
+        }
+        match candidates.len() {
+            0 => Err(ResolveError::NotFound { prefix: prefix.to_string() }),
+            1 => Ok(candidates.remove(0)),
+            _ => Err(ResolveError::Ambiguous { prefix: prefix.to_string(), candidates }),
+        }
+    }
+
+    /// Index of the root the proof verifies against, or `LibError::EmptyRootSet` if there are none
+    pub fn verify_proof(&self, data: &Data, proof: &Proof) -> Result<Option<usize>, LibError> {
+        self.verify_proof_hash(&hash_data(data), proof)
+    }
+
+    /// Like `verify_proof`, but starting from an already hashed leaf
+    pub fn verify_proof_hash(&self, leaf_hash: &Hash, proof: &Proof) -> Result<Option<usize>, LibError> {
+        self.verify_proof_hash_with(&Sha256Hasher, leaf_hash, proof)
+    }
+
+    /// Like `verify_proof_hash`, for a proof from a tree built with another hasher
+    ///
+    /// A proof of more than `MAX_PROOF_STEPS` steps fails with `LibError::ProofTooDeep`.
+    pub fn verify_proof_hash_with<H: MerkleHasher + ?Sized>(
+        &self,
+        hasher: &H,
+        leaf_hash: &Hash,
+        proof: &Proof,
+    ) -> Result<Option<usize>, LibError> {
+        if self.is_empty() {
+            return Err(LibError::EmptyRootSet);
+        }
+        check_step_count(proof.steps().len())?;
+        let roots: Vec<Hash> = self.roots().cloned().collect();
+        Ok(MerkleTree::verify_proof_hash_multi_root_with(hasher, leaf_hash, proof, &roots))
+    }
+}
+
+/// Compares two hashes without exiting early on the first differing byte
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| vec![i as u8]).collect()
+    }
+
+    #[test]
+    fn test_match_on_second_root() {
+        let yesterday = MerkleTree::construct(&example_data(4));
+        let today = MerkleTree::construct(&example_data(5));
+        let proof = yesterday.prove_by_index(2).unwrap();
+        let data = vec![2];
+
+        let roots = [today.root(), yesterday.root()];
+        assert_eq!(MerkleTree::verify_proof_multi_root(&data, &proof, &roots), Some(1));
+        assert_eq!(MerkleTree::verify_proof_multi_root(&vec![9], &proof, &roots), None);
+        assert_eq!(MerkleTree::verify_proof_multi_root(&data, &proof, &roots[..1]), None);
+        // the first match wins if a root is listed twice
+        let repeated = [yesterday.root(), today.root(), yesterday.root()];
+        assert_eq!(MerkleTree::verify_proof_multi_root(&data, &proof, &repeated), Some(0));
+    }
+
+    #[test]
+    fn test_root_set() {
+        let trees: Vec<MerkleTree> = (1..=4).map(|n| MerkleTree::construct(&example_data(n))).collect();
+        let mut set = RootSet::new();
+        assert!(matches!(set.verify_proof(&vec![0], &trees[0].prove_by_index(0).unwrap()), Err(LibError::EmptyRootSet)));
+
+        for (day, tree) in trees.iter().enumerate() {
+            set.insert(tree.root(), format!("day {}", day));
+        }
+        set.retain_last(2);
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.get(0), Some((&trees[2].root(), "day 2")));
+
+        let proof = trees[3].prove_by_index(3).unwrap();
+        assert_eq!(set.verify_proof(&vec![3], &proof).unwrap(), Some(1));
+        // day 0 was rotated out
+        let old = trees[0].prove_by_index(0).unwrap();
+        assert_eq!(set.verify_proof(&vec![0], &old).unwrap(), None);
+
+        let json = serde_json::to_string(&set).unwrap();
+        assert_eq!(serde_json::from_str::<RootSet>(&json).unwrap(), set);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["roots"][1]["metadata"], "day 3");
+
+        set.retain_last(0);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn test_short_hash() {
+        let hash = hex::decode("e263b77a0f").unwrap();
+        assert_eq!((hash.short(4), hash.short(5), hash.short(0)), ("e263".into(), "e263b".into(), String::new()));
+        assert_eq!(hash.short(64), "e263b77a0f");
+    }
+
+    /// Roots that agree on their first 10 hex characters, and one that shares nothing with them
+    fn colliding_roots() -> (RootSet, [Hash; 3]) {
+        let hashes = ["e263b77a0f11", "e263b77a0f2e", "00aa"].map(|root| hex::decode(root).unwrap());
+        let mut set = RootSet::new();
+        for (epoch, hash) in hashes.iter().enumerate() {
+            set.insert(hash.clone(), format!("epoch {}", epoch));
+        }
+        (set, hashes)
+    }
+
+    #[test]
+    fn test_abbreviation_grows_until_unique() {
+        let (mut set, [first, second, short]) = colliding_roots();
+        assert_eq!(set.unambiguous_abbreviation(&first), "e263b77a0f1");
+        assert_eq!(set.unambiguous_abbreviation(&second), "e263b77a0f2");
+        // never below the minimum, nor past the whole hex
+        assert_eq!(set.unambiguous_abbreviation(&short), "00aa");
+        let alone = RootSet::new().unambiguous_abbreviation(&first);
+        assert_eq!(alone.len(), MIN_ABBREVIATION_LEN);
+        set.insert(hex::decode("e263b77a0f1100").unwrap(), "longer");
+        assert_eq!(set.unambiguous_abbreviation(&first), "e263b77a0f11");
+        // a repeated root is not a collision
+        set.insert(second.clone(), "again");
+        assert_eq!(set.unambiguous_abbreviation(&second), "e263b77a0f2");
+    }
+
+    #[test]
+    fn test_resolve_prefix() {
+        let (mut set, [first, second, short]) = colliding_roots();
+        for (prefix, hash) in [("e263b77a0f1", &first), ("E263B77A0F2E", &second), ("0", &short)] {
+            assert_eq!(set.resolve_prefix(prefix).as_ref(), Ok(hash));
+            assert_eq!(set.resolve_prefix(&set.unambiguous_abbreviation(hash)).as_ref(), Ok(hash));
+        }
+        let ambiguous = set.resolve_prefix("e263b77a0f").unwrap_err();
+        let expected = vec![first.clone(), second.clone()];
+        assert_eq!(ambiguous, ResolveError::Ambiguous { prefix: "e263b77a0f".into(), candidates: expected });
+        assert_eq!(ambiguous.to_string(), "`e263b77a0f` is the start of 2 roots: e263b77a0f11, e263b77a0f2e");
+        assert_eq!(set.resolve_prefix("ff"), Err(ResolveError::NotFound { prefix: "ff".into() }));
+        // repeats of one root are not ambiguous
+        set.insert(short.clone(), "again");
+        assert_eq!(set.resolve_prefix("00"), Ok(short));
+    }
+
+    #[test]
+    fn test_ct_eq() {
+        assert!(ct_eq(&[1, 2, 3], &[1, 2, 3]));
+        assert!(!ct_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!ct_eq(&[1, 2], &[1, 2, 3]));
+    }
+}
@@ -0,0 +1,172 @@
+use thiserror::Error;
+
+use super::{parent_level_with, Hash, HashAlgorithm, MerkleHasher, OddLeafPolicy};
+
+/// A hasher that gave a wrong digest for one of the known answers of `self_test_hashers`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("self-test of hasher {hasher} failed on {vector}: expected {expected}, found {found}")]
+#[non_exhaustive]
+pub struct SelfTestError {
+    /// The `MerkleHasher::id` of the hasher
+    pub hasher: &'static str,
+    /// Name of the failing vector, such as `leaf("abc")` or `root(a, b, c)`
+    pub vector: &'static str,
+    pub expected: String,
+    pub found: String,
+}
+
+/// Expected digests of one hasher: the leaf hashes of `""`, `"abc"` and a million `a`s, and the
+/// root over the leaves `a`, `b` and `c` under `OddLeafPolicy::Duplicate`
+struct KnownAnswers {
+    empty: &'static str,
+    abc: &'static str,
+    million_a: &'static str,
+    root: &'static str,
+}
+
+/// The plain hashes are the NIST and RIPEMD-160 reference vectors; the rest were computed with
+/// Python's `hashlib`
+const KNOWN_ANSWERS: [(HashAlgorithm, KnownAnswers); 5] = [
+    (
+        HashAlgorithm::Sha256,
+        KnownAnswers {
+            empty: "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            abc: "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            million_a: "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0",
+            root: "d31a37ef6ac14a2db1470c4316beb5592e6afd4465022339adafda76a18ffabe",
+        },
+    ),
+    (
+        HashAlgorithm::Sha512,
+        KnownAnswers {
+            empty: "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce\
+                    47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e",
+            abc: "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                  2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            million_a: "e718483d0ce769644e2e42c7bc15b4638e1f98b13b2044285632a803afa973eb\
+                        de0ff244877ea60a4cb0432ce577c31beb009c5c2c49aa2e4eadb217ad8cc09b",
+            root: "306a02acd4e8549ced9b336473d9e565853792215f724f87ab3b77f42a4eaf76\
+                   169f242b511f661ce0eb9abf2335a03dbeaa5d9fe7c55e7a8e6ba0160f356aa8",
+        },
+    ),
+    (
+        HashAlgorithm::Ripemd160,
+        KnownAnswers {
+            empty: "9c1185a5c5e9fc54612808977ee8f548b2258d31",
+            abc: "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc",
+            million_a: "52783243c1697bdbe16d37f97f68f08325dc1528",
+            root: "3ccf55487610a873fe229ce448f57c612f027087",
+        },
+    ),
+    (
+        HashAlgorithm::Sha256Rfc6962,
+        KnownAnswers {
+            empty: "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            abc: "609f6e36d2405585188d5cfd761f407c7cc46a7d3f314c88270469dde315fcd1",
+            million_a: "ecc773b954368267ca2642014818ad70868831b659ac9c55b178c166908cb56a",
+            root: "e9636069c740c9ff51625b01a0b040396d265a9b920cc6febdfa5ecc9f58ecce",
+        },
+    ),
+    (
+        HashAlgorithm::Sha256d,
+        KnownAnswers {
+            empty: "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456",
+            abc: "4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358",
+            million_a: "80d1189477563e1b5206b2749f1afe4807e5705e8bd77887a60187a712156688",
+            root: "74449b8328cb6e97d305adb2fca5e90993fdf9c667fa40cb625f40508da40cbf",
+        },
+    ),
+];
+
+/// Checks every hasher of `HashAlgorithm::ALL` against known answers, failing on the first wrong
+/// digest
+///
+/// A miscompiled or faulty hash backend would otherwise build wrong roots without any error, so
+/// applications on unfamiliar hardware can call this once at startup; the `merkle` binary does
+/// unless run with `--skip-self-test`. It hashes about 5 MB.
+pub fn self_test_hashers() -> Result<(), SelfTestError> {
+    let million_a = vec![b'a'; 1_000_000];
+    KNOWN_ANSWERS.iter().try_for_each(|(hasher, answers)| self_test(hasher, answers, &million_a))
+}
+
+fn self_test<H: MerkleHasher + ?Sized>(
+    hasher: &H,
+    answers: &KnownAnswers,
+    million_a: &[u8],
+) -> Result<(), SelfTestError> {
+    let mut level: Vec<Hash> = [b"a", b"b", b"c"].iter().map(|data| hasher.leaf_hash(*data)).collect();
+    while level.len() > 1 {
+        level = parent_level_with(&level, OddLeafPolicy::Duplicate, |left, right| hasher.node_hash(left, right));
+    }
+    let checks = [
+        ("leaf(\"\")", answers.empty, hasher.leaf_hash(b"")),
+        ("leaf(\"abc\")", answers.abc, hasher.leaf_hash(b"abc")),
+        ("leaf(1000000 x \"a\")", answers.million_a, hasher.leaf_hash(million_a)),
+        ("root(a, b, c)", answers.root, level.pop().unwrap_or_default()),
+    ];
+    for (vector, expected, found) in checks {
+        let found = hex::encode(found);
+        if found != expected {
+            return Err(SelfTestError { hasher: hasher.id(), vector, expected: expected.to_string(), found });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{Data, Sha256Hasher, TreeConfig};
+
+    /// SHA-256 that goes wrong on long inputs, as a faulty SIMD backend might
+    struct BrokenHasher;
+
+    impl MerkleHasher for BrokenHasher {
+        fn id(&self) -> &'static str {
+            "broken"
+        }
+
+        fn output_len(&self) -> usize {
+            32
+        }
+
+        fn leaf_hash(&self, data: &[u8]) -> Hash {
+            let mut hash = Sha256Hasher.leaf_hash(data);
+            if data.len() > 64 {
+                hash[0] ^= 1;
+            }
+            hash
+        }
+
+        fn node_hash(&self, left: &[u8], right: &[u8]) -> Hash {
+            Sha256Hasher.node_hash(left, right)
+        }
+    }
+
+    #[test]
+    fn test_every_hasher_passes() {
+        assert_eq!(self_test_hashers(), Ok(()));
+        assert!(HashAlgorithm::ALL.iter().all(|hasher| KNOWN_ANSWERS.iter().any(|(known, _)| known == hasher)));
+
+        // the roots agree with the tree builder
+        let data: Vec<Data> = [b"a", b"b", b"c"].iter().map(|data| data.to_vec()).collect();
+        for (hasher, answers) in &KNOWN_ANSWERS {
+            let tree = TreeConfig::default().with_hasher(*hasher).construct(&data).unwrap();
+            assert_eq!(hex::encode(tree.root()), answers.root, "{:?}", hasher);
+        }
+    }
+
+    #[test]
+    fn test_broken_hasher_is_named_with_its_vector() {
+        let sha256 = &KNOWN_ANSWERS[0].1;
+        let error = self_test(&BrokenHasher, sha256, &vec![b'a'; 1_000_000]).unwrap_err();
+        assert_eq!((error.hasher, error.vector), ("broken", "leaf(1000000 x \"a\")"));
+        assert_eq!((error.expected.as_str(), &error.found[..2]), (sha256.million_a, "cc"));
+        let message = error.to_string();
+        assert!(message.starts_with("self-test of hasher broken failed on leaf(1000000 x \"a\"): expected cdc76e"));
+
+        // a hasher checked against the answers of another fails on the first vector
+        let error = self_test(&HashAlgorithm::Sha256d, sha256, b"").unwrap_err();
+        assert_eq!((error.hasher, error.vector), ("sha256d", "leaf(\"\")"));
+    }
+}
@@ -0,0 +1,311 @@
+use thiserror::Error;
+
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::{MerkleTree, OwnedProof, ProofCache};
+use crate::util::error::LibError;
+
+/// What one call to a `ProofService` may ask for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceLimits {
+    /// Most proofs one call may return, counting each distinct index once
+    pub max_proofs_per_call: usize,
+    /// Most indices a batch may list, repeats included
+    pub max_batch_indices: usize,
+    /// Whether `ProofService::prove_range` is served at all
+    pub allow_range_proofs: bool,
+}
+
+impl Default for ServiceLimits {
+    fn default() -> Self {
+        ServiceLimits {
+            max_proofs_per_call: 256,
+            max_batch_indices: 1024,
+            allow_range_proofs: false,
+        }
+    }
+}
+
+/// Why a `ProofService` turned a request down
+///
+/// Every variant but `Tree` is the caller's fault, a bad request in HTTP terms; `Tree` is a
+/// failure of the tree itself.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ServiceError {
+    #[error("a batch lists at most {max} indices, found {found}")]
+    BatchTooLarge { max: usize, found: usize },
+
+    #[error("a call returns at most {max} proofs, {requested} were requested")]
+    TooManyProofs { max: usize, requested: usize },
+
+    #[error("{distinct} of the {found} indices of the batch are distinct, fewer than half")]
+    DuplicateHeavy { found: usize, distinct: usize },
+
+    #[error("leaf index {index} is out of range for a tree of {size} leaves")]
+    IndexOutOfRange { index: usize, size: usize },
+
+    #[error("range proofs are not served")]
+    RangeProofsDisabled,
+
+    #[error(transparent)]
+    Tree(LibError),
+}
+
+impl ServiceError {
+    /// A stable name for the reason, such as `BATCH_TOO_LARGE`, for responses and metrics
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServiceError::BatchTooLarge { .. } => "BATCH_TOO_LARGE",
+            ServiceError::TooManyProofs { .. } => "TOO_MANY_PROOFS",
+            ServiceError::DuplicateHeavy { .. } => "DUPLICATE_HEAVY",
+            ServiceError::IndexOutOfRange { .. } => "INDEX_OUT_OF_RANGE",
+            ServiceError::RangeProofsDisabled => "RANGE_PROOFS_DISABLED",
+            ServiceError::Tree(e) => e.code(),
+        }
+    }
+}
+
+/// Counters of a `ProofService`, for metrics scraping
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ServiceStats {
+    pub proofs_served: u64,
+    pub rejected_batch_too_large: u64,
+    pub rejected_too_many_proofs: u64,
+    pub rejected_duplicate_heavy: u64,
+    pub rejected_out_of_range: u64,
+    pub rejected_range_disabled: u64,
+}
+
+impl ServiceStats {
+    /// Requests turned down for any reason
+    pub fn rejected(&self) -> u64 {
+        self.rejected_batch_too_large
+            + self.rejected_too_many_proofs
+            + self.rejected_duplicate_heavy
+            + self.rejected_out_of_range
+            + self.rejected_range_disabled
+    }
+}
+
+/// Proofs of a static tree for callers that are not trusted, within `ServiceLimits`
+///
+/// Every request is checked against the limits and the size of the tree before any proof is
+/// generated, so a rejected request costs no hashing. Proofs come from a shared `ProofCache`, and
+/// the service is `Send + Sync` like the cache, to be shared behind an `Arc` between handlers.
+pub struct ProofService {
+    cache: ProofCache,
+    limits: ServiceLimits,
+    counters: Counters,
+}
+
+/// The counters behind `ServiceStats`
+#[derive(Default)]
+struct Counters {
+    served: AtomicU64,
+    batch_too_large: AtomicU64,
+    too_many_proofs: AtomicU64,
+    duplicate_heavy: AtomicU64,
+    out_of_range: AtomicU64,
+    range_disabled: AtomicU64,
+}
+
+impl ProofService {
+    /// Serves proofs of `tree` without caching them
+    pub fn new(tree: MerkleTree, limits: ServiceLimits) -> Self {
+        ProofService::with_cache(ProofCache::new(tree, 0), limits)
+    }
+
+    /// Serves proofs through `cache`
+    pub fn with_cache(cache: ProofCache, limits: ServiceLimits) -> Self {
+        ProofService { cache, limits, counters: Default::default() }
+    }
+
+    pub fn tree(&self) -> &MerkleTree {
+        self.cache.tree()
+    }
+
+    pub fn limits(&self) -> &ServiceLimits {
+        &self.limits
+    }
+
+    /// Hit and miss counters of the underlying cache
+    pub fn cache(&self) -> &ProofCache {
+        &self.cache
+    }
+
+    /// The proof of the leaf at `index`
+    pub fn prove(&self, index: usize) -> Result<Arc<OwnedProof>, ServiceError> {
+        let checked = self.check_index(index).and_then(|()| self.check_proof_count(1));
+        Ok(self.serve(checked.map(|()| vec![index]))?.remove(0).1)
+    }
+
+    /// The proofs of the leaves at `indices`, one for each distinct index in the order they are
+    /// first listed
+    ///
+    /// A batch is rejected if it lists more than `max_batch_indices` indices, if any is out of
+    /// range, if fewer than half of them are distinct, or if it needs more than
+    /// `max_proofs_per_call` proofs.
+    pub fn prove_batch(&self, indices: &[usize]) -> Result<Vec<(usize, Arc<OwnedProof>)>, ServiceError> {
+        self.serve(self.check_batch(indices))
+    }
+
+    /// The proofs of the leaves in `range`, rejected unless `allow_range_proofs` is set and the
+    /// range is within the tree and `max_proofs_per_call`
+    pub fn prove_range(&self, range: Range<usize>) -> Result<Vec<Arc<OwnedProof>>, ServiceError> {
+        let checked = if !self.limits.allow_range_proofs {
+            Err(ServiceError::RangeProofsDisabled)
+        } else if range.is_empty() {
+            Ok(())
+        } else {
+            self.check_index(range.end - 1).and_then(|()| self.check_proof_count(range.len()))
+        };
+        Ok(self.serve(checked.map(|()| range.collect()))?.into_iter().map(|(_, proof)| proof).collect())
+    }
+
+    pub fn stats(&self) -> ServiceStats {
+        let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let counters = &self.counters;
+        ServiceStats {
+            proofs_served: count(&counters.served),
+            rejected_batch_too_large: count(&counters.batch_too_large),
+            rejected_too_many_proofs: count(&counters.too_many_proofs),
+            rejected_duplicate_heavy: count(&counters.duplicate_heavy),
+            rejected_out_of_range: count(&counters.out_of_range),
+            rejected_range_disabled: count(&counters.range_disabled),
+        }
+    }
+
+    /// The distinct indices of the batch, in the order they are first listed
+    fn check_batch(&self, indices: &[usize]) -> Result<Vec<usize>, ServiceError> {
+        if indices.len() > self.limits.max_batch_indices {
+            return Err(ServiceError::BatchTooLarge { max: self.limits.max_batch_indices, found: indices.len() });
+        }
+        if let Some(&index) = indices.iter().find(|&&index| index >= self.tree().len()) {
+            return Err(ServiceError::IndexOutOfRange { index, size: self.tree().len() });
+        }
+        let mut seen = HashSet::with_capacity(indices.len());
+        let distinct: Vec<usize> = indices.iter().copied().filter(|index| seen.insert(*index)).collect();
+        if distinct.len() * 2 < indices.len() {
+            return Err(ServiceError::DuplicateHeavy { found: indices.len(), distinct: distinct.len() });
+        }
+        self.check_proof_count(distinct.len())?;
+        Ok(distinct)
+    }
+
+    fn check_index(&self, index: usize) -> Result<(), ServiceError> {
+        match self.tree().len() {
+            size if index >= size => Err(ServiceError::IndexOutOfRange { index, size }),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_proof_count(&self, requested: usize) -> Result<(), ServiceError> {
+        match self.limits.max_proofs_per_call {
+            max if requested > max => Err(ServiceError::TooManyProofs { max, requested }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts the outcome of the checks, then generates the proofs of the indices they passed
+    fn serve(&self, checked: Result<Vec<usize>, ServiceError>) -> Result<Vec<(usize, Arc<OwnedProof>)>, ServiceError> {
+        let indices = checked.inspect_err(|e| {
+            if let Some(counter) = self.counters.of_reject(e) {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        })?;
+        let proofs = indices
+            .into_iter()
+            .map(|index| Ok((index, self.cache.get_or_generate(index)?)))
+            .collect::<Result<Vec<_>, LibError>>()
+            .map_err(ServiceError::Tree)?;
+        self.counters.served.fetch_add(proofs.len() as u64, Ordering::Relaxed);
+        Ok(proofs)
+    }
+}
+
+impl Counters {
+    /// The counter of requests turned down for `error`, none for failures of the tree
+    fn of_reject(&self, error: &ServiceError) -> Option<&AtomicU64> {
+        match error {
+            ServiceError::BatchTooLarge { .. } => Some(&self.batch_too_large),
+            ServiceError::TooManyProofs { .. } => Some(&self.too_many_proofs),
+            ServiceError::DuplicateHeavy { .. } => Some(&self.duplicate_heavy),
+            ServiceError::IndexOutOfRange { .. } => Some(&self.out_of_range),
+            ServiceError::RangeProofsDisabled => Some(&self.range_disabled),
+            ServiceError::Tree(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::Data;
+
+    fn service(leaves: u16, limits: ServiceLimits) -> ProofService {
+        let data: Vec<Data> = (0..leaves).map(|i| i.to_le_bytes().to_vec()).collect();
+        ProofService::new(MerkleTree::construct(&data), limits)
+    }
+
+    fn limits() -> ServiceLimits {
+        ServiceLimits { max_proofs_per_call: 10, max_batch_indices: 16, allow_range_proofs: true }
+    }
+
+    #[test]
+    fn test_serves_within_limits() {
+        let service = service(100, limits());
+        assert_eq!(*service.prove(42).unwrap(), service.tree().prove_by_index(42).unwrap().into_owned());
+        let batch = service.prove_batch(&[7, 3, 7, 99]).unwrap();
+        assert_eq!(batch.iter().map(|(index, _)| *index).collect::<Vec<_>>(), [7, 3, 99]);
+        assert_eq!(*batch[1].1, service.tree().prove_by_index(3).unwrap().into_owned());
+        assert_eq!(service.prove_range(90..100).unwrap().len(), 10);
+        assert!(service.prove_range(5..5).unwrap().is_empty());
+        assert_eq!(service.stats(), ServiceStats { proofs_served: 14, ..ServiceStats::default() });
+    }
+
+    #[test]
+    fn test_each_limit_is_counted() {
+        let service = service(100, limits());
+        let rejected = |result: Result<Vec<(usize, Arc<OwnedProof>)>, ServiceError>| result.unwrap_err();
+        let e = rejected(service.prove_batch(&[0; 17]));
+        assert!(matches!(e, ServiceError::BatchTooLarge { max: 16, found: 17 }), "{}", e);
+        let e = rejected(service.prove_batch(&(0..11).collect::<Vec<_>>()));
+        assert!(matches!(e, ServiceError::TooManyProofs { max: 10, requested: 11 }), "{}", e);
+        assert!(matches!(service.prove_range(0..11), Err(ServiceError::TooManyProofs { requested: 11, .. })));
+        // 3 of 7 distinct is too few, 4 of 8 is enough
+        let e = rejected(service.prove_batch(&[1, 1, 1, 1, 2, 2, 3]));
+        assert_eq!(e.to_string(), "3 of the 7 indices of the batch are distinct, fewer than half");
+        assert_eq!(service.prove_batch(&[1, 1, 1, 1, 2, 2, 3, 4]).unwrap().len(), 4);
+        let e = rejected(service.prove_batch(&[5, 100]));
+        assert!(matches!(e, ServiceError::IndexOutOfRange { index: 100, size: 100 }), "{}", e);
+        assert!(matches!(service.prove(100), Err(ServiceError::IndexOutOfRange { .. })));
+        assert!(matches!(service.prove_range(95..101), Err(ServiceError::IndexOutOfRange { index: 100, .. })));
+
+        let expected = ServiceStats {
+            proofs_served: 4,
+            rejected_batch_too_large: 1,
+            rejected_too_many_proofs: 2,
+            rejected_duplicate_heavy: 1,
+            rejected_out_of_range: 3,
+            rejected_range_disabled: 0,
+        };
+        assert_eq!((service.stats(), service.stats().rejected()), (expected, 7));
+        // rejected requests generate nothing
+        assert_eq!(service.cache().stats().misses, 4);
+    }
+
+    #[test]
+    fn test_range_proofs_can_be_disabled() {
+        let service = service(300, ServiceLimits::default());
+        let e = service.prove_range(0..1).unwrap_err();
+        assert_eq!((e.code(), e.to_string().as_str()), ("RANGE_PROOFS_DISABLED", "range proofs are not served"));
+        assert_eq!(service.stats().rejected_range_disabled, 1);
+        assert_eq!(service.prove_batch(&(0..256).collect::<Vec<_>>()).unwrap().len(), 256);
+        assert_eq!(service.prove_batch(&(0..257).collect::<Vec<_>>()).unwrap_err().code(), "TOO_MANY_PROOFS");
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+
+use super::{
+    Data, Hash, HashAlgorithm, LeafMode, OddLeafPolicy, OwnedProof, ProofFailure, TreeDescriptor, TreeShape, TreeSize,
+    VerifyContext,
+};
+use crate::util::encoding::Encoding;
+
+/// Leaves at least this long are hashed and verified on a blocking thread by `ProofVerifySink`
+pub const SINK_BLOCKING_LEAF_LEN: usize = 64 * 1024;
+
+/// One message of a proof stream: a leaf, its index and its proof
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SinkItem {
+    pub index: usize,
+    /// Raw leaf data, hashed by the sink
+    pub leaf: Data,
+    pub proof: OwnedProof,
+}
+
+/// Counts kept by a `ProofVerifySink`, returned by `ProofVerifySink::finish`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SinkSummary {
+    pub verified: usize,
+    pub failed: usize,
+    /// Items whose index came earlier in the stream, also counted as verified or failed
+    pub duplicates: usize,
+}
+
+/// Verifies proofs arriving one at a time, such as the messages of a network stream, against a
+/// tree known in advance
+///
+/// Each call to `verify_next` finishes before the next item is taken, so a caller reading from a
+/// channel or a stream applies backpressure just by awaiting it. Large leaves are hashed on
+/// tokio's blocking pool to keep the executor responsive.
+#[derive(Debug, Clone)]
+pub struct ProofVerifySink {
+    context: VerifyContext,
+    blocking_leaf_len: usize,
+    seen: HashSet<usize>,
+    summary: SinkSummary,
+}
+
+impl ProofVerifySink {
+    /// A sink for proofs of raw data in a tree built by `MerkleTree::construct`
+    pub fn new(root: Hash, tree_size: impl Into<TreeSize>) -> Self {
+        ProofVerifySink::with_context(VerifyContext::new(TreeDescriptor {
+            root,
+            leaf_count: tree_size.into().saturating_usize() as u64,
+            hasher: HashAlgorithm::Sha256,
+            leaf_mode: Some(LeafMode::HashData),
+            odd_leaf_policy: OddLeafPolicy::Duplicate,
+            shape: TreeShape::Paired,
+            crate_version: None,
+            canonical_ordering: None,
+            encoding: Encoding::Hex,
+        }))
+    }
+
+    /// A sink for proofs of the tree `context` describes, in its leaf mode and hasher
+    pub fn with_context(context: VerifyContext) -> Self {
+        ProofVerifySink {
+            context,
+            blocking_leaf_len: SINK_BLOCKING_LEAF_LEN,
+            seen: HashSet::new(),
+            summary: SinkSummary::default(),
+        }
+    }
+
+    /// Moves leaves of at least `len` bytes to the blocking pool, instead of `SINK_BLOCKING_LEAF_LEN`
+    pub fn with_blocking_leaf_len(mut self, len: usize) -> Self {
+        self.blocking_leaf_len = len;
+        self
+    }
+
+    /// Verifies one item, explaining the failure as `VerifyContext::verify_detailed` does
+    ///
+    /// An index seen before is counted as a duplicate and still verified, so a repeated index
+    /// with a bad proof is an error like any other. A proof tagged with another tree size fails.
+    pub async fn verify_next(&mut self, item: SinkItem) -> Result<(), ProofFailure> {
+        if !self.seen.insert(item.index) {
+            self.summary.duplicates += 1;
+        }
+        let blocking = item.leaf.len() >= self.blocking_leaf_len;
+        let result = if blocking {
+            let context = self.context.clone();
+            let verify = move || context.verify_detailed(&item.leaf, item.index, &item.proof);
+            match tokio::task::spawn_blocking(verify).await {
+                Ok(result) => result,
+                Err(e) => std::panic::resume_unwind(e.into_panic()),
+            }
+        } else {
+            self.context.verify_detailed(&item.leaf, item.index, &item.proof)
+        };
+
+        match result {
+            Ok(()) => self.summary.verified += 1,
+            Err(_) => self.summary.failed += 1,
+        }
+        result
+    }
+
+    /// Counts so far
+    pub fn summary(&self) -> SinkSummary {
+        self.summary
+    }
+
+    /// Closes the sink, returning its counts
+    pub fn finish(self) -> SinkSummary {
+        self.summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{FailurePoint, MerkleTree, TreeConfig};
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| format!("message {}", i).into_bytes()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_stream_of_proofs() {
+        let data = example_data(1000);
+        let tree = MerkleTree::construct(&data);
+        let proofs: Vec<OwnedProof> = (0..1000).map(|i| tree.prove_by_index(i).unwrap().into_owned()).collect();
+        let item = |index: usize| SinkItem { index, leaf: data[index].clone(), proof: proofs[index].clone() };
+        let mut items: Vec<SinkItem> = (0..1000).map(item).collect();
+        // the wrong leaf, the proof of another leaf, and a repeat of an earlier index
+        items[13].leaf = b"forged".to_vec();
+        items[500].proof = proofs[501].clone();
+        items[999] = item(7);
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(8);
+        let producer = tokio::spawn(async move {
+            for item in items {
+                sender.send(item).await.unwrap();
+            }
+        });
+
+        let mut sink = ProofVerifySink::new(tree.root(), 1000);
+        let mut failures = Vec::new();
+        while let Some(item) = receiver.recv().await {
+            let index = item.index;
+            if let Err(failure) = sink.verify_next(item).await {
+                failures.push((index, failure));
+            }
+        }
+        producer.await.unwrap();
+
+        assert_eq!(failures.iter().map(|(index, _)| *index).collect::<Vec<_>>(), [13, 500]);
+        for (index, failure) in &failures {
+            assert_eq!(failure.index, *index);
+            assert_ne!(failure.computed_root, tree.root());
+        }
+        assert_eq!(sink.finish(), SinkSummary { verified: 998, failed: 2, duplicates: 1 });
+    }
+
+    #[tokio::test]
+    async fn test_large_leaves_and_range() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i; 100]).collect();
+        let tree = MerkleTree::construct(&data);
+        let mut sink = ProofVerifySink::new(tree.root(), 5).with_blocking_leaf_len(100);
+        for (index, leaf) in data.iter().enumerate() {
+            let proof = tree.prove_by_index(index).unwrap().into_owned();
+            sink.verify_next(SinkItem { index, leaf: leaf.clone(), proof }).await.unwrap();
+        }
+
+        let proof = tree.prove_by_index(4).unwrap().into_owned();
+        let failure = sink.verify_next(SinkItem { index: 5, leaf: data[4].clone(), proof }).await.unwrap_err();
+        assert_eq!(failure.expected_len, None);
+        assert_eq!(sink.summary(), SinkSummary { verified: 5, failed: 1, duplicates: 0 });
+    }
+
+    #[tokio::test]
+    async fn test_context() {
+        let data = example_data(7);
+        let config =
+            TreeConfig::default().with_hasher(HashAlgorithm::Sha512).with_odd_leaf_policy(OddLeafPolicy::Promote);
+        let tree = config.construct(&data).unwrap();
+        let mut sink = ProofVerifySink::with_context(VerifyContext::new(config.descriptor(&tree).unwrap()));
+        for (index, leaf) in data.iter().enumerate() {
+            let proof = tree.prove_by_index(index).unwrap().into_owned();
+            sink.verify_next(SinkItem { index, leaf: leaf.clone(), proof }).await.unwrap();
+        }
+
+        // the proof of a larger tree, and one relabelled as such
+        let larger = config.construct(&example_data(8)).unwrap();
+        let proof = larger.prove_by_index(0).unwrap().into_owned();
+        let failure = sink.verify_next(SinkItem { index: 0, leaf: data[0].clone(), proof }).await.unwrap_err();
+        assert_eq!(failure.point, Some(FailurePoint::TreeSize(8)));
+        let proof = tree.prove_by_index(0).unwrap().into_owned().with_tree_size(8);
+        let failure = sink.verify_next(SinkItem { index: 0, leaf: data[0].clone(), proof }).await.unwrap_err();
+        assert_eq!(failure.point, Some(FailurePoint::TreeSize(8)));
+        assert_eq!(sink.finish(), SinkSummary { verified: 7, failed: 2, duplicates: 2 });
+    }
+}
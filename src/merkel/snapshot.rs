@@ -0,0 +1,394 @@
+use std::sync::Arc;
+
+use super::{
+    parent_level, Data, Hash, HashAlgorithm, HashDirection, LeafIndex, MerkleHasher, MerkleTree, OddLeafPolicy, Proof,
+    ProofSteps,
+};
+use crate::util::error::LibError;
+
+/// Number of hashes in each shared chunk of a snapshot level
+pub const SNAPSHOT_CHUNK: usize = 256;
+
+/// Number of children of each branch above the chunks of a snapshot level
+const SNAPSHOT_FANOUT: usize = 32;
+
+/// An immutable view of a tree that can be advanced without disturbing its readers
+///
+/// Every level is split into chunks of `SNAPSHOT_CHUNK` hashes behind an `Arc`, held by a tree of
+/// `Arc` branches of 32 children each. `push` and `update` return a new snapshot that copies only
+/// the chunk and branches on the path of each changed node and shares all others with the
+/// snapshot it came from, so a write takes time in proportion to the depth of the tree, not its
+/// size, and readers holding an older snapshot keep getting proofs against its root. Cloning a
+/// snapshot only bumps a reference count.
+#[derive(Debug, Clone)]
+pub struct TreeSnapshot {
+    levels: Arc<[Level]>,
+    policy: OddLeafPolicy,
+    hasher: HashAlgorithm,
+}
+
+/// The hashes of one level of a snapshot, in a tree whose leaves are chunks
+#[derive(Debug, Clone)]
+struct Level {
+    root: Node,
+    /// Branches between the root and the chunks, 0 for a single chunk
+    height: usize,
+    len: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Chunk(Arc<Vec<Hash>>),
+    Branch(Arc<Vec<Node>>),
+}
+
+/// Number of hashes below a node `height` branches above the chunks
+fn span(height: usize) -> usize {
+    (0..height).fold(SNAPSHOT_CHUNK, |span, _| span.saturating_mul(SNAPSHOT_FANOUT))
+}
+
+impl Node {
+    fn empty(height: usize) -> Self {
+        match height {
+            0 => Node::Chunk(Arc::new(Vec::with_capacity(SNAPSHOT_CHUNK))),
+            _ => Node::Branch(Arc::new(Vec::with_capacity(SNAPSHOT_FANOUT))),
+        }
+    }
+
+    fn get(&self, height: usize, index: usize) -> Option<&Hash> {
+        match self {
+            Node::Chunk(chunk) => chunk.get(index),
+            Node::Branch(children) => {
+                let below = span(height - 1);
+                children.get(index / below)?.get(height - 1, index % below)
+            }
+        }
+    }
+
+    /// Sets the hash at `index`, copying this node and the nodes below it on the way if shared
+    fn set(&mut self, height: usize, index: usize, hash: Hash) {
+        match self {
+            Node::Chunk(chunk) => {
+                let chunk = Arc::make_mut(chunk);
+                match chunk.get_mut(index) {
+                    Some(slot) => *slot = hash,
+                    None => chunk.push(hash),
+                }
+            }
+            Node::Branch(children) => {
+                let (children, below) = (Arc::make_mut(children), span(height - 1));
+                if index / below == children.len() {
+                    children.push(Node::empty(height - 1));
+                }
+                children[index / below].set(height - 1, index % below, hash);
+            }
+        }
+    }
+}
+
+impl Level {
+    fn new(hashes: &[Hash]) -> Self {
+        let mut nodes: Vec<Node> =
+            hashes.chunks(SNAPSHOT_CHUNK).map(|chunk| Node::Chunk(Arc::new(chunk.to_vec()))).collect();
+        let mut height = 0;
+        while nodes.len() > 1 {
+            nodes = nodes.chunks(SNAPSHOT_FANOUT).map(|children| Node::Branch(Arc::new(children.to_vec()))).collect();
+            height += 1;
+        }
+        let root = nodes.pop().unwrap_or_else(|| Node::empty(0));
+        Level { root, height, len: hashes.len() }
+    }
+
+    fn get(&self, index: usize) -> Option<&Hash> {
+        if index >= self.len {
+            return None;
+        }
+        self.root.get(self.height, index)
+    }
+
+    /// Replaces the hash at `index`, or appends it when `index` is the length of the level
+    fn set(&mut self, index: usize, hash: Hash) {
+        debug_assert!(index <= self.len);
+        if index == self.len {
+            // a full level grows a new root above the old one
+            if index == span(self.height) {
+                let old = std::mem::replace(&mut self.root, Node::empty(0));
+                self.root = Node::Branch(Arc::new(vec![old]));
+                self.height += 1;
+            }
+            self.len += 1;
+        }
+        self.root.set(self.height, index, hash);
+    }
+}
+
+impl MerkleTree {
+    /// Copies the tree into a snapshot that can be advanced with `TreeSnapshot::push` and `update`
+    pub fn snapshot(&self) -> TreeSnapshot {
+        TreeSnapshot {
+            levels: self.levels().iter().map(|level| Level::new(level)).collect(),
+            policy: self.odd_leaf_policy(),
+            hasher: self.hasher(),
+        }
+    }
+}
+
+impl TreeSnapshot {
+    /// Gets the root hash of this snapshot
+    pub fn root(&self) -> Hash {
+        // snapshots are only taken of trees, which have at least one leaf and so a root
+        self.levels[self.depth()].get(0).cloned().unwrap_or_default()
+    }
+
+    /// Number of leaves
+    pub fn len(&self) -> usize {
+        self.levels[0].len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of levels above the leaves
+    pub fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    pub fn odd_leaf_policy(&self) -> OddLeafPolicy {
+        self.policy
+    }
+
+    pub fn hasher(&self) -> HashAlgorithm {
+        self.hasher
+    }
+
+    /// Hash of the leaf at `index`
+    pub fn leaf(&self, index: usize) -> Option<&Hash> {
+        self.levels[0].get(index)
+    }
+
+    /// Returns the proof for the leaf at the given index, against the root of this snapshot
+    pub fn prove_by_index(&self, index: impl Into<LeafIndex>) -> Result<Proof<'_>, LibError> {
+        let index = index.into().saturating_usize();
+        if index >= self.len() {
+            return Err(LibError::IndexOutOfRange {
+                index,
+                size: self.len(),
+            });
+        }
+        let mut hashes = ProofSteps::new();
+        let mut index = index;
+        for level in &self.levels[..self.depth()] {
+            let step = match level.get(index ^ 1) {
+                Some(sibling) if index.is_multiple_of(2) => Some((HashDirection::Right, &sibling[..])),
+                Some(sibling) => Some((HashDirection::Left, &sibling[..])),
+                None => match self.policy {
+                    OddLeafPolicy::Duplicate => level.get(index).map(|node| (HashDirection::Duplicate, &node[..])),
+                    // a promoted node has no sibling and adds nothing at this level
+                    OddLeafPolicy::Promote => None,
+                },
+            };
+            hashes.extend(step);
+            index /= 2;
+        }
+        Ok(Proof::from_hashes(hashes).with_tree_size(self.len()))
+    }
+
+    /// Returns a snapshot with `data` appended as a new last leaf
+    pub fn push(&self, data: &Data) -> TreeSnapshot {
+        self.with_leaf(self.len(), self.hasher.leaf_hash(data))
+    }
+
+    /// Like `push`, for a leaf already hashed with the hasher of this snapshot
+    pub(crate) fn push_leaf_hash(&self, leaf_hash: Hash) -> TreeSnapshot {
+        self.with_leaf(self.len(), leaf_hash)
+    }
+
+    /// Returns a snapshot with the leaf at `index` replaced by `data`
+    pub fn update(&self, index: usize, data: &Data) -> Result<TreeSnapshot, LibError> {
+        if index >= self.len() {
+            return Err(LibError::IndexOutOfRange {
+                index,
+                size: self.len(),
+            });
+        }
+        Ok(self.with_leaf(index, self.hasher.leaf_hash(data)))
+    }
+
+    /// Sets one leaf, at most one past the end, and recomputes the nodes on its path to the root
+    fn with_leaf(&self, index: usize, leaf_hash: Hash) -> TreeSnapshot {
+        let mut levels = self.levels.to_vec();
+        levels[0].set(index, leaf_hash);
+
+        let mut index = index;
+        let mut k = 0;
+        while levels[k].len > 1 {
+            let left = index & !1;
+            let children: Vec<Hash> = (left..levels[k].len.min(left + 2))
+                .filter_map(|i| levels[k].get(i).cloned())
+                .collect();
+            let Some(parent) = parent_level(&children, self.policy, self.hasher).pop() else {
+                break;
+            };
+            if k + 1 == levels.len() {
+                levels.push(Level::new(&[]));
+            }
+            levels[k + 1].set(index / 2, parent);
+            index /= 2;
+            k += 1;
+        }
+        TreeSnapshot {
+            levels: levels.into(),
+            policy: self.policy,
+            hasher: self.hasher,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::TreeConfig;
+
+    use std::sync::RwLock;
+    use std::thread;
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| (i as u32).to_be_bytes().to_vec()).collect()
+    }
+
+    fn assert_matches_tree(snapshot: &TreeSnapshot, tree: &MerkleTree) {
+        assert_eq!((snapshot.root(), snapshot.len(), snapshot.depth()), (tree.root(), tree.len(), tree.depth()));
+        for index in 0..tree.len() {
+            let proof = snapshot.prove_by_index(index).unwrap().into_owned();
+            assert_eq!(proof, tree.prove_by_index(index).unwrap().into_owned(), "leaf {} of {}", index, tree.len());
+        }
+    }
+
+    #[test]
+    fn test_push_and_update_match_rebuilt_tree() {
+        for policy in [OddLeafPolicy::Duplicate, OddLeafPolicy::Promote] {
+            let config = TreeConfig::default().with_odd_leaf_policy(policy);
+            let mut data = example_data(1);
+            let mut snapshot = config.construct(&data).unwrap().snapshot();
+            // crosses the chunk boundaries of the two lowest levels
+            for i in 1..600 {
+                data.push((i as u32).to_be_bytes().to_vec());
+                snapshot = snapshot.push(&data[i]);
+                if i % 97 == 0 {
+                    data[i / 2] = vec![0xff, i as u8];
+                    snapshot = snapshot.update(i / 2, &data[i / 2]).unwrap();
+                }
+                if i % 50 == 0 || i < 20 {
+                    assert_matches_tree(&snapshot, &config.construct(&data).unwrap());
+                }
+            }
+            assert!(matches!(snapshot.update(600, &vec![]), Err(LibError::IndexOutOfRange { index: 600, size: 600 })));
+        }
+    }
+
+    #[test]
+    fn test_snapshots_keep_the_hasher() {
+        let config = TreeConfig::default().with_hasher(HashAlgorithm::Ripemd160);
+        let mut data = example_data(5);
+        let snapshot = config.construct(&data).unwrap().snapshot().push(&vec![9]);
+        data.push(vec![9]);
+        assert_eq!(snapshot.hasher(), HashAlgorithm::Ripemd160);
+        assert_matches_tree(&snapshot, &config.construct(&data).unwrap());
+    }
+
+    #[test]
+    fn test_older_snapshots_are_unchanged() {
+        let data = example_data(700);
+        let old = MerkleTree::construct(&data).snapshot();
+        let new = old.push(&vec![1]).update(3, &vec![2]).unwrap();
+        assert_matches_tree(&old, &MerkleTree::construct(&data));
+        // only the chunks on the changed paths were copied
+        assert!(Arc::ptr_eq(chunk(&old.levels[0], 256), chunk(&new.levels[0], 256)));
+        assert!(!Arc::ptr_eq(chunk(&old.levels[0], 0), chunk(&new.levels[0], 0)));
+    }
+
+    /// The chunk holding the hash at `index` of `level`
+    fn chunk(level: &Level, index: usize) -> &Arc<Vec<Hash>> {
+        let (mut node, mut height, mut index) = (&level.root, level.height, index);
+        loop {
+            match node {
+                Node::Chunk(chunk) => return chunk,
+                Node::Branch(children) => {
+                    let below = span(height - 1);
+                    (node, height, index) = (&children[index / below], height - 1, index % below);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_writes_share_the_rest_of_each_level() {
+        // more leaves than one branch of chunks holds, so the leaf level has two levels of branches
+        let count = SNAPSHOT_CHUNK * SNAPSHOT_FANOUT * 2 + 5;
+        let data = example_data(count);
+        let old = MerkleTree::construct(&data).snapshot();
+        assert_eq!(old.levels[0].height, 2);
+        let Node::Branch(old_children) = &old.levels[0].root else { panic!("the leaf level is one chunk") };
+
+        let new = old.update(3, &vec![1]).unwrap().push(&vec![2]);
+        let Node::Branch(new_children) = &new.levels[0].root else { panic!("the leaf level is one chunk") };
+        // the branch over leaves 0.. was copied for the update, the middle one is still shared
+        let shared = |i: usize| match (&old_children[i], &new_children[i]) {
+            (Node::Branch(a), Node::Branch(b)) => Arc::ptr_eq(a, b),
+            _ => panic!("branch {} holds a chunk", i),
+        };
+        assert!(!Arc::ptr_eq(chunk(&old.levels[0], 3), chunk(&new.levels[0], 3)));
+        assert!(!shared(0) && shared(1));
+        assert!(Arc::ptr_eq(chunk(&old.levels[0], SNAPSHOT_CHUNK), chunk(&new.levels[0], SNAPSHOT_CHUNK)));
+
+        let mut data = data;
+        data[3] = vec![1];
+        data.push(vec![2]);
+        let tree = MerkleTree::construct(&data);
+        assert_eq!((new.root(), new.len()), (tree.root(), count + 1));
+        for index in [0, 3, SNAPSHOT_CHUNK * SNAPSHOT_FANOUT, count - 1, count] {
+            let proof = new.prove_by_index(index).unwrap().into_owned();
+            assert_eq!(proof, tree.prove_by_index(index).unwrap().into_owned(), "leaf {}", index);
+        }
+    }
+
+    #[test]
+    fn test_readers_see_consistent_snapshots() {
+        let data = example_data(300);
+        let published = RwLock::new(MerkleTree::construct(&data).snapshot());
+        let pushes = 2000;
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut snapshot = published.read().unwrap().clone();
+                for i in 0..pushes {
+                    snapshot = snapshot.push(&(i as u32).to_le_bytes().to_vec());
+                    if i % 3 == 0 {
+                        snapshot = snapshot.update(i % 300, &vec![i as u8]).unwrap();
+                    }
+                    *published.write().unwrap() = snapshot.clone();
+                }
+            });
+            for reader in 0..8 {
+                let published = &published;
+                scope.spawn(move || {
+                    let mut index = reader;
+                    loop {
+                        let snapshot = published.read().unwrap().clone();
+                        let root = snapshot.root();
+                        for _ in 0..16 {
+                            index = (index * 31 + 7) % snapshot.len();
+                            let proof = snapshot.prove_by_index(index).unwrap();
+                            let leaf = snapshot.leaf(index).unwrap();
+                            assert!(MerkleTree::verify_proof_hash(leaf, &proof, &root));
+                        }
+                        if snapshot.len() == 300 + pushes {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,219 @@
+use super::{retain, Data, Hash, MerkleTree, OwnedProof, Retained, TreeSize};
+use crate::util::error::LibError;
+
+/// A Merkle tree over strictly increasing leaves, which can also prove that data is absent
+///
+/// Leaves are ordered by their raw bytes. Absence of a value is shown by the two adjacent leaves
+/// that bracket it, or by the first or last leaf alone when the value lies outside the range.
+#[derive(Clone)]
+pub struct SortedMerkleTree {
+    tree: MerkleTree,
+    /// Zeroed on drop with the `zeroize` feature
+    leaves: Vec<Retained>,
+}
+
+/// A leaf next to an absent value, with its position and inclusion proof
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Neighbor {
+    index: usize,
+    data: Data,
+    proof: OwnedProof,
+}
+
+/// Proof that a value is not a leaf of a `SortedMerkleTree`
+///
+/// `predecessor` is None when the value is smaller than the first leaf and `successor` is None
+/// when it is larger than the last one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AbsenceProof {
+    predecessor: Option<Neighbor>,
+    successor: Option<Neighbor>,
+}
+
+impl Neighbor {
+    pub fn new(index: usize, data: Data, proof: OwnedProof) -> Self {
+        Neighbor { index, data, proof }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn data(&self) -> &Data {
+        &self.data
+    }
+
+    pub fn proof(&self) -> &OwnedProof {
+        &self.proof
+    }
+}
+
+impl AbsenceProof {
+    pub fn new(predecessor: Option<Neighbor>, successor: Option<Neighbor>) -> Self {
+        AbsenceProof { predecessor, successor }
+    }
+
+    pub fn predecessor(&self) -> Option<&Neighbor> {
+        self.predecessor.as_ref()
+    }
+
+    pub fn successor(&self) -> Option<&Neighbor> {
+        self.successor.as_ref()
+    }
+}
+
+impl SortedMerkleTree {
+    /// Constructs a tree from input that must already be sorted and free of duplicates
+    pub fn construct(sorted_input: &[Data]) -> Result<Self, LibError> {
+        if sorted_input.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        for (index, pair) in sorted_input.windows(2).enumerate() {
+            if pair[0] == pair[1] {
+                return Err(LibError::DuplicateLeaf { index: index + 1 });
+            }
+            if pair[0] > pair[1] {
+                return Err(LibError::UnsortedInput { index: index + 1 });
+            }
+        }
+        Ok(SortedMerkleTree {
+            tree: MerkleTree::construct(sorted_input),
+            leaves: sorted_input.iter().cloned().map(retain).collect(),
+        })
+    }
+
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Proves that `data` is not a leaf, failing with `LibError::LeafPresent` if it is
+    pub fn prove_absence(&self, data: &Data) -> Result<AbsenceProof, LibError> {
+        let position = match self.leaves.binary_search_by(|leaf| leaf.as_slice().cmp(data)) {
+            Ok(index) => return Err(LibError::LeafPresent { index }),
+            Err(position) => position,
+        };
+
+        let neighbor = |index: usize| -> Result<Neighbor, LibError> {
+            Ok(Neighbor::new(
+                index,
+                self.leaves[index].to_vec(),
+                self.tree.prove_by_index(index)?.into_owned(),
+            ))
+        };
+        Ok(AbsenceProof::new(
+            position.checked_sub(1).map(neighbor).transpose()?,
+            (position < self.len()).then(|| neighbor(position)).transpose()?,
+        ))
+    }
+
+    /// Verifies that `data` is absent from the sorted tree with the given root and size
+    ///
+    /// Checks that the neighbors bracket `data`, that they are adjacent (or the first/last leaf
+    /// when only one is given), and that both are included at their claimed positions.
+    pub fn verify_absence(data: &Data, proof: &AbsenceProof, root_hash: &Hash, tree_size: impl Into<TreeSize>) -> bool {
+        let tree_size = tree_size.into().saturating_usize();
+        let included =
+            |n: &Neighbor| MerkleTree::verify_proof_at_index(&n.data, &n.proof.as_proof(), n.index, tree_size, root_hash);
+
+        let bracketed = match (&proof.predecessor, &proof.successor) {
+            (Some(pred), Some(succ)) => pred.index + 1 == succ.index && &pred.data < data && data < &succ.data,
+            (None, Some(succ)) => succ.index == 0 && data < &succ.data,
+            (Some(pred), None) => pred.index + 1 == tree_size && &pred.data < data,
+            (None, None) => false,
+        };
+        bracketed
+            && proof.predecessor.iter().all(included)
+            && proof.successor.iter().all(included)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Leaves 10, 20, ..., 10 * n
+    fn example_tree(n: usize) -> SortedMerkleTree {
+        let data: Vec<Data> = (1..=n).map(|i| vec![(i * 10) as u8]).collect();
+        SortedMerkleTree::construct(&data).unwrap()
+    }
+
+    #[test]
+    fn test_rejects_unsorted_input() {
+        let unsorted = vec![vec![1], vec![3], vec![2]];
+        assert!(matches!(SortedMerkleTree::construct(&unsorted), Err(LibError::UnsortedInput { index: 2 })));
+        let duplicate = vec![vec![1], vec![1, 0], vec![1, 0]];
+        assert!(matches!(SortedMerkleTree::construct(&duplicate), Err(LibError::DuplicateLeaf { index: 2 })));
+        assert!(matches!(SortedMerkleTree::construct(&[]), Err(LibError::EmptyInput)));
+    }
+
+    #[test]
+    fn test_absent_between_leaves() {
+        for n in 2..=9 {
+            let tree = example_tree(n);
+            for i in 0..n - 1 {
+                let absent = vec![(i * 10 + 15) as u8];
+                let proof = tree.prove_absence(&absent).unwrap();
+                assert_eq!(proof.predecessor.as_ref().unwrap().index, i);
+                assert_eq!(proof.successor.as_ref().unwrap().index, i + 1);
+                assert!(SortedMerkleTree::verify_absence(&absent, &proof, &tree.root(), n));
+
+                // the same brackets do not prove absence of their own endpoints
+                let present = proof.successor.as_ref().unwrap().data.clone();
+                assert!(!SortedMerkleTree::verify_absence(&present, &proof, &tree.root(), n));
+            }
+        }
+    }
+
+    #[test]
+    fn test_absent_before_first_and_after_last() {
+        let tree = example_tree(5);
+
+        let small = vec![5];
+        let proof = tree.prove_absence(&small).unwrap();
+        assert!(proof.predecessor.is_none());
+        assert_eq!(proof.successor.as_ref().unwrap().index, 0);
+        assert!(SortedMerkleTree::verify_absence(&small, &proof, &tree.root(), 5));
+
+        let large = vec![200];
+        let proof = tree.prove_absence(&large).unwrap();
+        assert_eq!(proof.predecessor.as_ref().unwrap().index, 4);
+        assert!(proof.successor.is_none());
+        assert!(SortedMerkleTree::verify_absence(&large, &proof, &tree.root(), 5));
+        // the last leaf only bounds the range if the tree really ends there
+        assert!(!SortedMerkleTree::verify_absence(&large, &proof, &tree.root(), 6));
+
+        assert!(matches!(tree.prove_absence(&vec![30]), Err(LibError::LeafPresent { index: 2 })));
+    }
+
+    #[test]
+    fn test_rejects_non_adjacent_neighbors() {
+        let tree = example_tree(8);
+        let absent = vec![35];
+
+        // skip over leaf 40 by presenting leaves 30 and 50 as neighbors
+        let mut proof = tree.prove_absence(&absent).unwrap();
+        proof.successor = tree.prove_absence(&vec![45]).unwrap().successor;
+        assert!(!SortedMerkleTree::verify_absence(&vec![45], &proof, &tree.root(), 8));
+
+        // lying about the successor's index breaks its inclusion proof
+        let mut proof = tree.prove_absence(&vec![45]).unwrap();
+        proof.predecessor = tree.prove_absence(&absent).unwrap().predecessor;
+        proof.predecessor.as_mut().unwrap().index = 3;
+        assert!(!SortedMerkleTree::verify_absence(&vec![45], &proof, &tree.root(), 8));
+
+        let empty = AbsenceProof::new(None, None);
+        assert!(!SortedMerkleTree::verify_absence(&absent, &empty, &tree.root(), 8));
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::{hash_pair, CacheStats, Data, Hash, HashAlgorithm, MerkleTree, TreeConfig};
+use crate::util::error::LibError;
+
+type PairKey = (HashAlgorithm, Hash, Hash);
+
+/// Least-recently-used cache of parent hashes by the pair of children they were computed from
+///
+/// Kept between runs of `TreeConfig::construct_cached`, it lets a rebuild of mostly unchanged
+/// leaves skip hashing every subtree it has seen before: only the parents on the paths of changed
+/// leaves are hashed again. Entries are keyed by hasher too, so one cache can serve trees of
+/// different hashers. A capacity of 0 disables caching.
+#[derive(Debug, Clone)]
+pub struct SubtreeCache {
+    capacity: usize,
+    /// Only looked up, never iterated; what is evicted is decided by `recency`
+    entries: HashMap<PairKey, (Hash, u64)>,
+    /// Child pairs by the tick they were last used at, oldest first
+    recency: BTreeMap<u64, PairKey>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl SubtreeCache {
+    pub fn new(capacity: usize) -> Self {
+        SubtreeCache {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Counters since the cache was created; `len` is the number of parent hashes cached
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            len: self.entries.len(),
+            ..self.stats
+        }
+    }
+
+    /// The parent of `left` and `right` under `hasher`, from the cache or hashed and cached on a miss
+    fn parent(&mut self, hasher: HashAlgorithm, left: &Hash, right: &Hash) -> Hash {
+        self.tick += 1;
+        let key = (hasher, left.clone(), right.clone());
+        if let Some((parent, last_used)) = self.entries.get_mut(&key) {
+            self.recency.remove(last_used);
+            self.recency.insert(self.tick, key);
+            *last_used = self.tick;
+            self.stats.hits += 1;
+            return parent.clone();
+        }
+
+        self.stats.misses += 1;
+        let parent = hash_pair(hasher, left, right);
+        if self.capacity == 0 {
+            return parent;
+        }
+        self.entries.insert(key.clone(), (parent.clone(), self.tick));
+        self.recency.insert(self.tick, key);
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+        parent
+    }
+}
+
+impl TreeConfig {
+    /// Like `construct`, looking every parent up in `cache` before hashing it
+    ///
+    /// The tree is the one `construct` builds; only the number of hashes computed differs.
+    pub fn construct_cached(&self, input: &[Data], cache: &mut SubtreeCache) -> Result<MerkleTree, LibError> {
+        let hasher = self.hasher();
+        let pair = |left: &Hash, right: &Hash| cache.parent(hasher, left, right);
+        Ok(MerkleTree::build_with(self.leaf_hashes(input)?, self.odd_leaf_policy(), hasher, pair))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::tests::count_node_hashes;
+    use crate::merkel::{LeafMode, OddLeafPolicy};
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| format!("row {}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_rebuild_after_suffix_change() {
+        let n = 1 << 16;
+        let mut data = example_data(n);
+        // the counts do not depend on the hasher, so a cheap one keeps the test fast
+        let config = TreeConfig::default().with_hasher(HashAlgorithm::XorFold);
+        let mut cache = SubtreeCache::new(1 << 17);
+        let (first, hashed) = count_node_hashes(|| config.construct_cached(&data, &mut cache).unwrap());
+        assert_eq!(first.root(), config.construct(&data).unwrap().root());
+        assert_eq!((hashed, cache.stats().misses, cache.stats().len), (n - 1, n as u64 - 1, n - 1));
+
+        for row in &mut data[n - 100..] {
+            row.extend_from_slice(b" edited");
+        }
+        let (second, hashed) = count_node_hashes(|| config.construct_cached(&data, &mut cache).unwrap());
+        assert_eq!(second.levels(), config.construct(&data).unwrap().levels());
+        assert_ne!(second.root(), first.root());
+
+        // only the parents above the last 100 leaves are hashed again, one range per level
+        let changed: usize = (1..=16).map(|level| ((n - 1) >> level) - ((n - 100) >> level) + 1).sum();
+        assert_eq!(changed, 111);
+        let stats = cache.stats();
+        assert_eq!((hashed, stats.misses), (changed, (n - 1 + changed) as u64));
+        assert_eq!((stats.hits, stats.evictions), ((n - 1 - changed) as u64, 0));
+    }
+
+    #[test]
+    fn test_policies_hashers_and_capacity() {
+        let data = example_data(13);
+        let mut cache = SubtreeCache::new(1000);
+        for hasher in HashAlgorithm::ALL {
+            for policy in [OddLeafPolicy::Duplicate, OddLeafPolicy::Promote] {
+                let config = TreeConfig::new(LeafMode::HashData).with_hasher(hasher).with_odd_leaf_policy(policy);
+                let tree = config.construct_cached(&data, &mut cache).unwrap();
+                assert_eq!(tree.levels(), config.construct(&data).unwrap().levels());
+                assert_eq!((tree.hasher(), tree.odd_leaf_policy()), (hasher, policy));
+            }
+        }
+        assert!(matches!(TreeConfig::default().construct_cached(&[], &mut cache), Err(LibError::EmptyInput)));
+
+        // a full cache evicts one parent for every new one, and a capacity of 0 keeps nothing
+        let mut small = SubtreeCache::new(4);
+        let (_, hashed) = count_node_hashes(|| TreeConfig::default().construct_cached(&data, &mut small).unwrap());
+        assert_eq!((small.stats().len, small.stats().evictions), (4, hashed as u64 - 4));
+        let mut disabled = SubtreeCache::new(0);
+        for _ in 0..2 {
+            TreeConfig::default().construct_cached(&data, &mut disabled).unwrap();
+        }
+        assert_eq!((disabled.stats().len, disabled.stats().hits, disabled.capacity()), (0, 0, 0));
+    }
+}
@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{check_step_count, Data, Hash, HashDirection, LeafIndex};
+use crate::util::error::LibError;
+
+/// Merkle sum tree, whose nodes carry the sum of the values below them, as in proofs of reserves
+///
+/// A leaf is `(sha256(data || value), value)` and a parent of `left` and `right` is
+/// `(sha256(left.hash || left.sum || right.hash || right.sum), left.sum + right.sum)`, with values
+/// and sums as 8 big-endian bytes. The lone last node of an odd level is carried up unchanged, as
+/// under `OddLeafPolicy::Promote`, since pairing it with itself would count its value twice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SumTree {
+    /// `(hash, sum)` of every node, from the leaves (first) up to the root (last)
+    levels: Vec<Vec<(Hash, u64)>>,
+}
+
+/// Sibling of one level of a `SumProof`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SumStep {
+    /// Side the sibling is on; sum trees have no `HashDirection::Duplicate` steps
+    pub direction: HashDirection,
+    #[serde(with = "crate::util::hex_serde")]
+    pub hash: Hash,
+    pub sum: u64,
+}
+
+/// Proof that a leaf of some value is in a `SumTree`, see `SumTree::prove`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "SumProofRepr")]
+pub struct SumProof {
+    steps: Vec<SumStep>,
+}
+
+/// Serialized form of a `SumProof`, checked for its number of steps when decoded
+#[derive(Deserialize)]
+struct SumProofRepr {
+    steps: Vec<SumStep>,
+}
+
+impl TryFrom<SumProofRepr> for SumProof {
+    type Error = LibError;
+
+    fn try_from(repr: SumProofRepr) -> Result<Self, LibError> {
+        check_step_count(repr.steps.len())?;
+        Ok(SumProof { steps: repr.steps })
+    }
+}
+
+impl SumStep {
+    pub fn new(direction: HashDirection, hash: Hash, sum: u64) -> Self {
+        SumStep { direction, hash, sum }
+    }
+}
+
+impl SumProof {
+    pub fn new(steps: Vec<SumStep>) -> Self {
+        SumProof { steps }
+    }
+
+    /// Siblings from the leaf up, promoted levels having none
+    pub fn steps(&self) -> &[SumStep] {
+        &self.steps
+    }
+}
+
+impl SumTree {
+    /// Builds the tree of `leaves`, each some data and its value
+    ///
+    /// Fails with `LibError::EmptyInput` for no leaves and `LibError::SumOverflow` if the values
+    /// add up to more than `u64::MAX`.
+    pub fn construct(leaves: &[(Data, u64)]) -> Result<SumTree, LibError> {
+        if leaves.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        let mut levels = vec![leaves.iter().map(|(data, value)| (leaf_hash(data, *value), *value)).collect::<Vec<_>>()];
+        while levels[levels.len() - 1].len() > 1 {
+            let parents = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|chunk| match chunk {
+                    [left, right] => parent(left, right),
+                    lone => Ok(lone[0].clone()),
+                })
+                .collect::<Result<_, _>>()?;
+            levels.push(parents);
+        }
+        Ok(SumTree { levels })
+    }
+
+    /// Hash and sum of the root, the total of every value
+    pub fn root(&self) -> (Hash, u64) {
+        self.levels[self.levels.len() - 1][0].clone()
+    }
+
+    /// Number of leaves the tree was built from
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Always false, since a sum tree has at least one leaf
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hash and value of the leaf at `index`
+    pub fn leaf(&self, index: impl Into<LeafIndex>) -> Option<&(Hash, u64)> {
+        self.levels[0].get(index.into().saturating_usize())
+    }
+
+    /// Proof of the leaf at `index`, its siblings with their sums
+    pub fn prove(&self, index: impl Into<LeafIndex>) -> Result<SumProof, LibError> {
+        let index = index.into().saturating_usize();
+        if index >= self.len() {
+            return Err(LibError::IndexOutOfRange { index, size: self.len() });
+        }
+        let mut steps = Vec::new();
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let (direction, sibling) = match position & 1 {
+                0 => (HashDirection::Right, position + 1),
+                _ => (HashDirection::Left, position - 1),
+            };
+            // a promoted node has no sibling and adds nothing at this level
+            if let Some((hash, sum)) = level.get(sibling) {
+                steps.push(SumStep::new(direction, hash.clone(), *sum));
+            }
+            position /= 2;
+        }
+        Ok(SumProof { steps })
+    }
+
+    /// Whether `proof` leads from the leaf of `leaf` and `value` to the root hash and sum
+    ///
+    /// Every sum on the way is checked, so a proof whose sibling sums add up past `u64::MAX` fails
+    /// with `LibError::SumOverflow` rather than wrapping to a sum that might match. A sibling
+    /// whose sum was inflated changes the hash of its parent and does not verify. A proof of more
+    /// than `MAX_PROOF_STEPS` steps fails with `LibError::ProofTooDeep` before anything is hashed.
+    pub fn verify(
+        leaf: &Data,
+        value: u64,
+        proof: &SumProof,
+        root_hash: &Hash,
+        root_sum: u64,
+    ) -> Result<bool, LibError> {
+        check_step_count(proof.steps.len())?;
+        let mut running = (leaf_hash(leaf, value), value);
+        for step in &proof.steps {
+            let sibling = (step.hash.clone(), step.sum);
+            running = match step.direction {
+                HashDirection::Left => parent(&sibling, &running)?,
+                HashDirection::Right => parent(&running, &sibling)?,
+                _ => return Ok(false),
+            };
+        }
+        Ok(running.0 == *root_hash && running.1 == root_sum)
+    }
+}
+
+fn leaf_hash(data: &[u8], value: u64) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.update(value.to_be_bytes());
+    hasher.finalize().to_vec()
+}
+
+fn parent(left: &(Hash, u64), right: &(Hash, u64)) -> Result<(Hash, u64), LibError> {
+    let sum = left.1.checked_add(right.1).ok_or(LibError::SumOverflow)?;
+    let mut hasher = Sha256::new();
+    hasher.update(&left.0);
+    hasher.update(left.1.to_be_bytes());
+    hasher.update(&right.0);
+    hasher.update(right.1.to_be_bytes());
+    Ok((hasher.finalize().to_vec(), sum))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::MAX_PROOF_STEPS;
+
+    fn balances() -> Vec<(Data, u64)> {
+        [("alice", 100), ("bob", 250), ("carol", 5), ("dave", 1_000_000)]
+            .map(|(user, value)| (user.as_bytes().to_vec(), value))
+            .to_vec()
+    }
+
+    /// Computed independently with Python's `hashlib` and `struct.pack(">Q", ...)`
+    #[test]
+    fn test_pinned_vectors() {
+        let tree = SumTree::construct(&balances()).unwrap();
+        let (hash, sum) = tree.root();
+        assert_eq!(hex::encode(&hash), "e40dc428df512b4fb8b16d93b7e6cb2a7c583b24e50d1273c8a43ea3f5937f9a");
+        assert_eq!(sum, 1_000_355);
+        let carol = tree.leaf(2).unwrap();
+        assert_eq!(hex::encode(&carol.0), "57678efef8cd8f7ee2ef155b564abcd12b0f8a828ee2442c96ca779e9ade99c6");
+
+        let proof = tree.prove(2).unwrap();
+        let steps = proof.steps().iter().map(|step| (step.direction, hex::encode(&step.hash), step.sum));
+        let expected = [
+            (HashDirection::Right, "c0fb41d2941c132e5b19ffe35cfa11b3b1b777563065c138abdadbb6180dd50f", 1_000_000),
+            (HashDirection::Left, "83918b4e520e0290b62b73432894f1c4c0145e5ccdd4811b466c437aadb0705b", 350),
+        ];
+        assert!(steps.eq(expected.map(|(direction, hash, sum)| (direction, hash.to_string(), sum))));
+        assert!(SumTree::verify(&b"carol".to_vec(), 5, &proof, &hash, sum).unwrap());
+    }
+
+    #[test]
+    fn test_every_proof_verifies() {
+        for n in 1..=9 {
+            let leaves: Vec<(Data, u64)> = (0..n).map(|i| (vec![i as u8], 10 * i as u64 + 1)).collect();
+            let tree = SumTree::construct(&leaves).unwrap();
+            let (hash, sum) = tree.root();
+            assert_eq!((sum, tree.len()), (leaves.iter().map(|leaf| leaf.1).sum(), n));
+            for (index, (data, value)) in leaves.iter().enumerate() {
+                let proof = tree.prove(index).unwrap();
+                assert!(SumTree::verify(data, *value, &proof, &hash, sum).unwrap(), "leaf {} of {}", index, n);
+                assert!(!SumTree::verify(data, value + 1, &proof, &hash, sum + 1).unwrap());
+                assert!(!SumTree::verify(data, *value, &proof, &hash, sum - 1).unwrap());
+            }
+        }
+        assert!(matches!(SumTree::construct(&balances()).unwrap().prove(4), Err(LibError::IndexOutOfRange { .. })));
+        assert!(matches!(SumTree::construct(&[]), Err(LibError::EmptyInput)));
+    }
+
+    #[test]
+    fn test_rejects_inflated_sums() {
+        let leaves = balances();
+        let tree = SumTree::construct(&leaves).unwrap();
+        let (hash, sum) = tree.root();
+        let (data, value) = &leaves[0];
+        let mut proof = tree.prove(0).unwrap();
+
+        // a sibling claiming more than it holds changes the parent hash, even if the total matches
+        proof.steps[1].sum += 500;
+        assert!(!SumTree::verify(data, *value, &proof, &hash, sum).unwrap());
+        assert!(!SumTree::verify(data, *value, &proof, &hash, sum + 500).unwrap());
+        proof.steps[1].sum = u64::MAX;
+        assert!(matches!(SumTree::verify(data, *value, &proof, &hash, sum), Err(LibError::SumOverflow)));
+
+        let overflowing = [(b"a".to_vec(), u64::MAX), (b"b".to_vec(), 1)];
+        assert!(matches!(SumTree::construct(&overflowing), Err(LibError::SumOverflow)));
+        let json = serde_json::to_string(&tree.prove(3).unwrap()).unwrap();
+        assert_eq!(serde_json::from_str::<SumProof>(&json).unwrap(), tree.prove(3).unwrap());
+
+        let too_many = MAX_PROOF_STEPS + 1;
+        let deep = SumProof::new(vec![SumStep::new(HashDirection::Left, vec![0; 32], 0); too_many]);
+        let error = serde_json::from_str::<SumProof>(&serde_json::to_string(&deep).unwrap()).unwrap_err();
+        let limit = format!("a proof has at most {} steps, found {}", MAX_PROOF_STEPS, too_many);
+        assert!(error.to_string().starts_with(&limit), "{}", error);
+        let rejected = SumTree::verify(data, *value, &deep, &hash, sum);
+        assert!(matches!(rejected, Err(LibError::ProofTooDeep { max: MAX_PROOF_STEPS, got }) if got == too_many));
+    }
+}
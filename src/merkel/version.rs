@@ -0,0 +1,87 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::util::error::LibError;
+
+/// Version of the serialized proof and tree formats
+///
+/// Every format writes it as an integer `version` field. Payloads written before the field
+/// existed are read as version 1, which they are identical to; unknown versions are rejected
+/// instead of being guessed at, so an old reader fails loudly on a newer format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum ProofVersion {
+    #[default]
+    V1,
+    /// Adds `HashDirection::Duplicate` proof steps; every other format is unchanged from version 1
+    V2,
+}
+
+impl ProofVersion {
+    /// The version this library writes
+    pub const CURRENT: ProofVersion = ProofVersion::V2;
+
+    /// Every version this library reads, oldest first
+    pub const SUPPORTED: &'static [ProofVersion] = &[ProofVersion::V1, ProofVersion::V2];
+
+    pub fn number(self) -> u64 {
+        match self {
+            ProofVersion::V1 => 1,
+            ProofVersion::V2 => 2,
+        }
+    }
+
+    /// Whether proofs of this version can have `HashDirection::Duplicate` steps
+    pub fn has_duplicate_steps(self) -> bool {
+        self >= ProofVersion::V2
+    }
+
+    /// Looks up a version number, failing with `LibError::UnsupportedVersion` if it is unknown
+    pub fn from_number(found: u64) -> Result<Self, LibError> {
+        Self::SUPPORTED
+            .iter()
+            .copied()
+            .find(|version| version.number() == found)
+            .ok_or_else(|| LibError::UnsupportedVersion {
+                found,
+                supported: Self::SUPPORTED.iter().map(|version| version.number()).collect(),
+            })
+    }
+}
+
+impl Serialize for ProofVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.number())
+    }
+}
+
+impl<'de> Deserialize<'de> for ProofVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ProofVersion::from_number(u64::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(ProofVersion::CURRENT, *ProofVersion::SUPPORTED.last().unwrap());
+        for version in ProofVersion::SUPPORTED {
+            assert_eq!(ProofVersion::from_number(version.number()).unwrap(), *version);
+        }
+        assert!(matches!(
+            ProofVersion::from_number(99),
+            Err(LibError::UnsupportedVersion { found: 99, supported }) if supported == vec![1, 2]
+        ));
+        assert!(ProofVersion::V2.has_duplicate_steps() && !ProofVersion::V1.has_duplicate_steps());
+    }
+
+    #[test]
+    fn test_serde() {
+        assert_eq!(serde_json::to_string(&ProofVersion::V1).unwrap(), "1");
+        assert_eq!(serde_json::from_str::<ProofVersion>("2").unwrap(), ProofVersion::V2);
+        let err = serde_json::from_str::<ProofVersion>("3").unwrap_err().to_string();
+        assert!(err.contains("unsupported format version 3"), "{}", err);
+    }
+}
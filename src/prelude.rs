@@ -0,0 +1,8 @@
+//! The types most users need, for a single `use merkle::prelude::*;`
+
+pub use crate::merkel::{
+    ChainedProof, Data, Hash, HashAlgorithm, HashDirection, LeafIndex, LeafMode, MerkleHasher, MerkleTree, NodeId,
+    OwnedProof, Proof, ProofCache, Sha256Hasher, ShortHash, TreeConfig, TreeSize,
+};
+pub use crate::util::encoder::LeafEncoder;
+pub use crate::util::error::LibError;
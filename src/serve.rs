@@ -0,0 +1,141 @@
+//! `merkle serve`: a small HTTP front end over a tree built from a hash file
+//!
+//! Everything goes through the library's public API, so this doubles as a reference for
+//! serving proofs: owned proofs, their serde format, and a `ProofService` over a shared
+//! `ProofCache`, which holds requests from untrusted callers to its limits.
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use std::sync::Arc;
+
+use merkle::merkel::{Hash, LeafMode, OwnedProof, ProofService, ServiceError, VerifyContext};
+use merkle::util::encoding::Encoding;
+use merkle::util::error::LibError;
+
+type Response = (StatusCode, Json<Value>);
+
+/// Body of `POST /verify`
+#[derive(Deserialize)]
+struct VerifyRequest {
+    /// Leaf hash, as it appears in the hash file
+    #[serde(with = "merkle::util::hex_serde")]
+    leaf: Hash,
+    /// Index of the leaf, by default the one the proof's directions lead to
+    index: Option<usize>,
+    proof: OwnedProof,
+}
+
+/// Body of `POST /proofs`
+#[derive(Deserialize)]
+struct BatchRequest {
+    indices: Vec<usize>,
+}
+
+/// Routes: `GET /root`, `GET /proof/{index}`, `POST /proofs`, `GET /proofs/{start}/{end}`,
+/// `POST /verify` and `GET /metrics`
+pub fn router(service: Arc<ProofService>) -> Router {
+    Router::new()
+        .route("/root", get(root))
+        .route("/proof/{index}", get(proof))
+        .route("/proofs", post(batch))
+        .route("/proofs/{start}/{end}", get(range))
+        .route("/verify", post(verify))
+        .route("/metrics", get(metrics))
+        .with_state(service)
+}
+
+/// Serves the router until the process is stopped, announcing the bound address on stdout
+pub async fn run(listener: tokio::net::TcpListener, service: Arc<ProofService>) -> std::io::Result<()> {
+    println!("listening on {}", listener.local_addr()?);
+    axum::serve(listener, router(service)).await
+}
+
+async fn root(State(service): State<Arc<ProofService>>) -> Json<Value> {
+    Json(super::root_json(service.tree(), false, Encoding::Hex))
+}
+
+async fn proof(State(service): State<Arc<ProofService>>, Path(index): Path<usize>) -> Response {
+    match service.prove(index) {
+        Ok(proof) => (StatusCode::OK, Json(proof_json(&service, index, &proof))),
+        Err(e @ ServiceError::IndexOutOfRange { .. }) => service_error(StatusCode::NOT_FOUND, e),
+        Err(e) => service_error(status_of(&e), e),
+    }
+}
+
+/// Proofs of the distinct indices of the batch, in the order they are first listed
+async fn batch(State(service): State<Arc<ProofService>>, Json(request): Json<BatchRequest>) -> Response {
+    match service.prove_batch(&request.indices) {
+        Ok(proofs) => {
+            let proofs: Vec<Value> = proofs.iter().map(|(index, proof)| proof_json(&service, *index, proof)).collect();
+            (StatusCode::OK, Json(json!({ "proofs": proofs })))
+        }
+        Err(e) => service_error(status_of(&e), e),
+    }
+}
+
+/// Proofs of the leaves from `start` up to but not including `end`, if range proofs are allowed
+async fn range(State(service): State<Arc<ProofService>>, Path((start, end)): Path<(usize, usize)>) -> Response {
+    match service.prove_range(start..end) {
+        Ok(proofs) => {
+            let proofs: Vec<Value> =
+                (start..).zip(&proofs).map(|(index, proof)| proof_json(&service, index, proof)).collect();
+            (StatusCode::OK, Json(json!({ "proofs": proofs })))
+        }
+        Err(e) => service_error(status_of(&e), e),
+    }
+}
+
+/// Proofs longer than the tree is deep, or tagged with another tree size, are bad requests rather
+/// than invalid proofs
+async fn verify(State(service): State<Arc<ProofService>>, Json(request): Json<VerifyRequest>) -> Response {
+    let index = match request.index.map_or_else(|| request.proof.as_proof().implied_index(), Ok) {
+        Ok(index) => index,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e),
+    };
+    let mut descriptor = service.tree().descriptor();
+    descriptor.leaf_mode = Some(LeafMode::PreHashed);
+    match VerifyContext::new(descriptor).verify_leaf_hash(&request.leaf, index, &request.proof) {
+        Ok(valid) => (StatusCode::OK, Json(json!({ "valid": valid }))),
+        Err(e) => error(StatusCode::BAD_REQUEST, e),
+    }
+}
+
+/// The counters of the service, for scraping
+async fn metrics(State(service): State<Arc<ProofService>>) -> Json<Value> {
+    let stats = service.stats();
+    Json(json!({
+        "proofs_served": stats.proofs_served,
+        "rejected": {
+            "batch_too_large": stats.rejected_batch_too_large,
+            "too_many_proofs": stats.rejected_too_many_proofs,
+            "duplicate_heavy": stats.rejected_duplicate_heavy,
+            "out_of_range": stats.rejected_out_of_range,
+            "range_disabled": stats.rejected_range_disabled,
+        },
+    }))
+}
+
+fn proof_json(service: &ProofService, index: usize, proof: &OwnedProof) -> Value {
+    json!({ "index": index, "leaf": hex::encode(&service.tree().leaves()[index]), "proof": proof })
+}
+
+/// Requests the limits turn down are bad requests, and failures of the tree internal errors
+fn status_of(error: &ServiceError) -> StatusCode {
+    match error {
+        ServiceError::Tree(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    }
+}
+
+fn service_error(status: StatusCode, error: ServiceError) -> Response {
+    (status, Json(json!({ "error": error.to_string(), "code": error.code() })))
+}
+
+fn error(status: StatusCode, error: LibError) -> Response {
+    (status, Json(json!({ "error": error.to_string() })))
+}
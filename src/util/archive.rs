@@ -0,0 +1,387 @@
+//! Every proof of a tree precomputed into one file, read back a proof at a time
+//!
+//! The file starts with a header holding a magic tag, the format version, a tag naming the hasher
+//! and leaf mode as `hasher/leaf-mode`, the leaf count and the root. Archives written before leaf
+//! modes were recorded have a tag of the hasher id alone. An index of `leaf_count + 1` big-endian
+//! `u64` offsets follows, then one record per leaf: its hash and its proof as written by
+//! `OwnedProof::to_bytes_compressed`, with the default hashes of an empty leaf. Record `i` spans
+//! offsets `i` to `i + 1` of the data after the index, so `ProofArchive::get` reads two offsets and
+//! one record without loading the rest.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::error::LibError;
+use super::io::write_atomic;
+use crate::merkel::{
+    to_usize, tree_depth, DefaultHashes, Hash, HashAlgorithm, LeafIndex, LeafMode, MerkleHasher, MerkleTree, OwnedProof,
+    ProofVersion, TreeConfig, MAX_PROOF_STEPS,
+};
+
+/// First bytes of every archive
+pub const ARCHIVE_MAGIC: &[u8; 8] = b"MRKLARCH";
+
+/// An archive of the proofs of every leaf of a tree, opened with `ProofArchive::open`
+#[derive(Debug)]
+pub struct ProofArchive {
+    file: Mutex<File>,
+    version: ProofVersion,
+    hasher: HashAlgorithm,
+    leaf_mode: Option<LeafMode>,
+    leaf_count: usize,
+    root: Hash,
+    defaults: DefaultHashes,
+    index_start: u64,
+    data_start: u64,
+    data_len: u64,
+}
+
+impl ProofArchive {
+    /// Writes the proofs of every leaf of `tree` to `path`, then opens the archive
+    ///
+    /// The tree does not know how its leaves were made, so the caller names the `leaf_mode` recorded
+    /// in the header. The index is written last, over the space reserved for it after the header.
+    /// The archive replaces `path` only once it is complete, see `util::io::write_atomic`.
+    pub fn build<P: AsRef<Path>>(tree: &MerkleTree, leaf_mode: LeafMode, path: P) -> Result<ProofArchive, LibError> {
+        let (hasher, root) = (tree.hasher(), tree.root());
+        let leaf_count = tree.len() as u64;
+        let defaults = DefaultHashes::for_leaf_with(&hasher, &Vec::new(), tree_depth(tree.len()));
+
+        write_atomic(path.as_ref(), |file| {
+            let mut out = BufWriter::new(file);
+            let header = header_bytes(hasher, leaf_mode, leaf_count, &root);
+            out.write_all(&header)?;
+            let offsets_len = leaf_count.checked_add(1).ok_or(LibError::Overflow("archive index"))?;
+            let index_len = to_usize(offsets_len, "archive index")?
+                .checked_mul(8)
+                .ok_or(LibError::Overflow("archive index"))?;
+            out.write_all(&vec![0; index_len])?;
+
+            let mut offsets = Vec::with_capacity(index_len);
+            let mut offset = 0u64;
+            for (index, leaf) in tree.leaves().iter().enumerate() {
+                offsets.extend_from_slice(&offset.to_be_bytes());
+                let proof = tree.prove_by_index(index)?.into_owned().to_bytes_compressed(&defaults)?;
+                out.write_all(leaf)?;
+                out.write_all(&proof)?;
+                offset += (leaf.len() + proof.len()) as u64;
+            }
+            offsets.extend_from_slice(&offset.to_be_bytes());
+            out.seek(SeekFrom::Start(header.len() as u64))?;
+            out.write_all(&offsets)?;
+            out.into_inner().map_err(|e| e.into_error())?;
+            Ok::<_, LibError>(())
+        })?;
+        ProofArchive::open(path)
+    }
+
+    /// Opens an archive, reading its header and checking it against the length of the file
+    ///
+    /// Fails with `LibError::InvalidFormat` for a file that is not an archive or is truncated,
+    /// `LibError::UnsupportedVersion` for a version this build cannot read and
+    /// `LibError::UnknownHasher` for a hasher it does not know.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ProofArchive, LibError> {
+        let mut reader = BufReader::new(File::open(path.as_ref())?);
+        let mut magic = [0; 8];
+        read_header(&mut reader, &mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(invalid("not a proof archive"));
+        }
+        let [version, id_len] = read_array(&mut reader)?;
+        let version = ProofVersion::from_number(u64::from(version))?;
+        let mut tag = vec![0; usize::from(id_len)];
+        read_header(&mut reader, &mut tag)?;
+        let tag = String::from_utf8(tag).map_err(|_| invalid("the hasher id is not UTF-8"))?;
+        let (id, leaf_mode) = match tag.split_once('/') {
+            None => (tag.as_str(), None),
+            Some((id, mode)) => {
+                let unknown = || invalid(&format!("unknown leaf mode `{}`", mode));
+                (id, Some(LeafMode::from_id(mode).ok_or_else(unknown)?))
+            }
+        };
+        let hasher = HashAlgorithm::from_id(id).ok_or_else(|| LibError::UnknownHasher(id.to_string()))?;
+        let leaf_count = u64::from_be_bytes(read_array(&mut reader)?);
+        let [root_len] = read_array(&mut reader)?;
+        if usize::from(root_len) != hasher.output_len() {
+            let expected = hasher.output_len();
+            return Err(invalid(&format!("a {} root is {} bytes, found {}", hasher.id(), expected, root_len)));
+        }
+        let mut root = vec![0; usize::from(root_len)];
+        read_header(&mut reader, &mut root)?;
+        if leaf_count == 0 {
+            return Err(invalid("no leaves"));
+        }
+
+        let index_start = reader.stream_position()?;
+        let data_start = leaf_count
+            .checked_add(1)
+            .and_then(|offsets| offsets.checked_mul(8))
+            .and_then(|index_len| index_start.checked_add(index_len))
+            .ok_or_else(|| invalid("the index does not fit in a file"))?;
+        let mut file = reader.into_inner();
+        let file_len = file.metadata()?.len();
+        if file_len < data_start {
+            return Err(invalid("truncated index"));
+        }
+        file.seek(SeekFrom::Start(data_start - 8))?;
+        let data_len = u64::from_be_bytes(read_array(&mut file)?);
+        if data_start.checked_add(data_len) != Some(file_len) {
+            return Err(invalid(&format!(
+                "the index covers {} bytes of records, the file holds {}",
+                data_len,
+                file_len - data_start
+            )));
+        }
+
+        let leaf_count = to_usize(leaf_count, "leaf count")?;
+        Ok(ProofArchive {
+            file: Mutex::new(file),
+            version,
+            hasher,
+            leaf_mode,
+            leaf_count,
+            root,
+            defaults: DefaultHashes::for_leaf_with(&hasher, &Vec::new(), tree_depth(leaf_count)),
+            index_start,
+            data_start,
+            data_len,
+        })
+    }
+
+    /// Like `open`, first failing with `LibError::HasherMismatch` unless the header names the
+    /// hasher and leaf mode of `config`
+    pub fn open_with<P: AsRef<Path>>(path: P, config: &TreeConfig) -> Result<ProofArchive, LibError> {
+        let archive = ProofArchive::open(path)?;
+        config.check_artifact("proof archive", archive.hasher.id(), archive.leaf_mode)?;
+        Ok(archive)
+    }
+
+    /// Format version the archive was written with
+    pub fn version(&self) -> ProofVersion {
+        self.version
+    }
+
+    pub fn hasher(&self) -> HashAlgorithm {
+        self.hasher
+    }
+
+    /// How the leaves were made, `None` for an archive written before this was recorded
+    pub fn leaf_mode(&self) -> Option<LeafMode> {
+        self.leaf_mode
+    }
+
+    pub fn root(&self) -> &Hash {
+        &self.root
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaf_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    /// The proof of leaf `index`, tagged with the tree size
+    pub fn get(&self, index: impl Into<LeafIndex>) -> Result<OwnedProof, LibError> {
+        self.get_with_leaf(index).map(|(_, proof)| proof)
+    }
+
+    /// The hash of leaf `index` and its proof
+    ///
+    /// Fails with `LibError::IndexOutOfRange` past the last leaf and `LibError::InvalidFormat` for
+    /// a record the index does not delimit properly or that does not decode. Nothing is allocated
+    /// for a record longer than the longest proof can be.
+    pub fn get_with_leaf(&self, index: impl Into<LeafIndex>) -> Result<(Hash, OwnedProof), LibError> {
+        let index = index.into().saturating_usize();
+        if index >= self.leaf_count {
+            return Err(LibError::IndexOutOfRange { index, size: self.leaf_count });
+        }
+        let record = {
+            let mut file = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            file.seek(SeekFrom::Start(self.index_start + 8 * index as u64))?;
+            let [start, end] = [read_array(&mut *file)?, read_array(&mut *file)?].map(u64::from_be_bytes);
+            if start > end || end > self.data_len {
+                return Err(invalid(&format!("record {} spans bytes {} to {} of {}", index, start, end, self.data_len)));
+            }
+            if end - start > max_record_len(self.hasher.output_len()) {
+                return Err(invalid(&format!("record {} is {} bytes, longer than any proof", index, end - start)));
+            }
+            let mut record = vec![0; to_usize(end - start, "record length")?];
+            file.seek(SeekFrom::Start(self.data_start + start))?;
+            file.read_exact(&mut record)?;
+            record
+        };
+
+        let (leaf, proof) = record
+            .split_at_checked(self.hasher.output_len())
+            .ok_or_else(|| invalid(&format!("record {} is shorter than its leaf hash", index)))?;
+        let proof = OwnedProof::from_bytes_compressed(proof, &self.defaults)?;
+        Ok((leaf.to_vec(), proof.with_tree_size(self.leaf_count)))
+    }
+}
+
+fn header_bytes(hasher: HashAlgorithm, leaf_mode: LeafMode, leaf_count: u64, root: &Hash) -> Vec<u8> {
+    let tag = format!("{}/{}", hasher.id(), leaf_mode.id());
+    let mut header = ARCHIVE_MAGIC.to_vec();
+    header.extend([ProofVersion::CURRENT.number() as u8, tag.len() as u8]);
+    header.extend_from_slice(tag.as_bytes());
+    header.extend(leaf_count.to_be_bytes());
+    header.push(root.len() as u8);
+    header.extend_from_slice(root);
+    header
+}
+
+/// Length of the record of a proof with `MAX_PROOF_STEPS` steps, all written out in full
+fn max_record_len(hash_len: usize) -> u64 {
+    (hash_len + 2 + MAX_PROOF_STEPS.div_ceil(8) + MAX_PROOF_STEPS * (1 + hash_len)) as u64
+}
+
+fn invalid(message: &str) -> LibError {
+    LibError::InvalidFormat(format!("proof archive: {}", message))
+}
+
+/// Reads a header field, a file ending inside it being a truncated archive rather than an I/O error
+fn read_header<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), LibError> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => invalid("truncated header"),
+        _ => LibError::Io(e),
+    })
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], LibError> {
+    let mut bytes = [0; N];
+    read_header(reader, &mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{Data, LeafMode, TreeConfig};
+
+    use std::fs;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("merkle-archive-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_random_proofs_verify() {
+        let data: Vec<Data> = (0..10_000u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let tree = MerkleTree::construct(&data);
+        let path = temp_path("10k");
+        let archive = ProofArchive::build(&tree, LeafMode::HashData, &path).unwrap();
+        assert_eq!((archive.len(), archive.root(), archive.hasher()), (10_000, &tree.root(), HashAlgorithm::Sha256));
+
+        let archive = ProofArchive::open(&path).unwrap();
+        for index in (0..100).map(|_| rand::random_range(0..10_000)).chain([0, 9_999]) {
+            let (leaf, proof) = archive.get_with_leaf(index).unwrap();
+            assert_eq!(leaf, tree.leaves()[index]);
+            assert_eq!(proof, tree.prove_by_index(index).unwrap().into_owned().with_tree_size(10_000));
+            assert!(MerkleTree::verify_proof_at_index(&data[index], &proof.as_proof(), index, 10_000, &tree.root()));
+        }
+        assert!(matches!(archive.get(10_000), Err(LibError::IndexOutOfRange { index: 10_000, size: 10_000 })));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_other_hashers() {
+        let leaves: Vec<Data> = (0..5u8).map(|i| vec![i; 20]).collect();
+        let config = TreeConfig::new(LeafMode::PreHashed).with_hasher(HashAlgorithm::Ripemd160);
+        let tree = config.construct(&leaves).unwrap();
+        let path = temp_path("ripemd160");
+        let archive = ProofArchive::build(&tree, LeafMode::PreHashed, &path).unwrap();
+        assert_eq!((archive.hasher(), archive.leaf_mode()), (HashAlgorithm::Ripemd160, Some(LeafMode::PreHashed)));
+        assert!(ProofArchive::open_with(&path, &config).is_ok());
+        let err = ProofArchive::open_with(&path, &TreeConfig::new(LeafMode::PreHashed)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "hasher mismatch in proof archive: expected `sha256/pre-hashed`, found `ripemd160/pre-hashed`"
+        );
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = archive.get(index).unwrap();
+            let proof = proof.as_proof();
+            assert!(MerkleTree::verify_proof_hash_with(&HashAlgorithm::Ripemd160, leaf, &proof, archive.root()));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_validates_the_header() {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i]).collect();
+        let path = temp_path("header");
+        ProofArchive::build(&MerkleTree::construct(&data), LeafMode::HashData, &path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let reopen = |bytes: &[u8]| {
+            fs::write(&path, bytes).unwrap();
+            ProofArchive::open(&path).unwrap_err().to_string()
+        };
+        let mut other = bytes.clone();
+        other[0] = b'X';
+        assert_eq!(reopen(&other), "invalid format: proof archive: not a proof archive");
+        assert_eq!(reopen(&bytes[..12]), "invalid format: proof archive: truncated header");
+        // a SHA-256 header of hash-data leaves is 67 bytes, followed by 8 offsets
+        let records = bytes.len() - 67 - 64;
+        let err = reopen(&bytes[..bytes.len() - 1]);
+        let expected = format!("the index covers {} bytes of records, the file holds {}", records, records - 1);
+        assert_eq!(err, format!("invalid format: proof archive: {}", expected));
+        assert_eq!(reopen(&bytes[..100]), "invalid format: proof archive: truncated index");
+
+        // the hasher id sits right after the version and its length
+        let mut other = bytes.clone();
+        other[10..16].copy_from_slice(b"sha999");
+        assert_eq!(reopen(&other), "unknown hasher `sha999`");
+        let mut other = bytes.clone();
+        other[8] = 9;
+        assert!(reopen(&other).starts_with("unsupported format version 9"));
+        let mut other = bytes.clone();
+        other[17..20].copy_from_slice(b"raw");
+        assert_eq!(reopen(&other), "invalid format: proof archive: unknown leaf mode `rawh-data`");
+
+        // archives written before leaf modes were recorded tag the hasher alone
+        let untagged = [&bytes[..9], &[6], b"sha256", &bytes[26..]].concat();
+        fs::write(&path, untagged).unwrap();
+        let archive = ProofArchive::open(&path).unwrap();
+        assert_eq!((archive.leaf_mode(), archive.len()), (None, 7));
+        let proof = MerkleTree::construct(&data).prove_by_index(6).unwrap().into_owned();
+        assert_eq!(archive.get(6).unwrap(), proof.with_tree_size(7));
+        assert!(matches!(
+            ProofArchive::open_with(&path, &TreeConfig::default()),
+            Err(LibError::HasherMismatch { found, .. }) if found == "sha256/unrecorded"
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_get_validates_the_index() {
+        let data: Vec<Data> = (0..200u8).map(|i| vec![i]).collect();
+        let path = temp_path("index");
+        ProofArchive::build(&MerkleTree::construct(&data), LeafMode::HashData, &path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        // offset `i` of a SHA-256 archive of hash-data leaves sits at byte 67 + 8 * i
+        let with_offset = |i: usize, offset: u64| {
+            let mut bytes = bytes.clone();
+            bytes[67 + 8 * i..75 + 8 * i].copy_from_slice(&offset.to_be_bytes());
+            fs::write(&path, bytes).unwrap();
+            ProofArchive::open(&path).unwrap()
+        };
+
+        let err = with_offset(2, 0).get(1).unwrap_err().to_string();
+        assert!(err.starts_with("invalid format: proof archive: record 1 spans bytes"), "{}", err);
+        assert!(err.contains(" to 0 of"), "{}", err);
+        let err = with_offset(1, 1 << 40).get(0).unwrap_err().to_string();
+        assert!(err.contains("to 1099511627776 of"), "{}", err);
+        // the first record reaching to the end of the file is in bounds, but not a proof
+        let data_len = bytes.len() as u64 - 67 - 8 * 201;
+        let err = with_offset(1, data_len).get(0).unwrap_err().to_string();
+        let expected = format!("record 0 is {} bytes, longer than any proof", data_len);
+        assert_eq!(err, format!("invalid format: proof archive: {}", expected));
+        let err = with_offset(1, 40).get(0).unwrap_err().to_string();
+        assert!(err.starts_with("invalid format: compressed proof"), "{}", err);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,325 @@
+//! An append-only log of timestamped events whose root is anchored at the end of every epoch
+//!
+//! Every event becomes a leaf encoded with `LeafEncoder` as its timestamp followed by its bytes.
+//! Sealing an epoch records the root and size of the log so far. An event is then proven against
+//! the root of any epoch that covers it, with a `ConsistencyProof` from that epoch to the latest,
+//! so a verifier holding only the latest anchored root can check an event of an older epoch.
+//!
+//! `AuditLog::save` writes a header line followed by one JSON line per event and per sealed
+//! epoch, in the order they happened, so a saved log can be extended by appending lines.
+
+use serde::{Deserialize, Serialize};
+
+use std::io::{BufRead, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::encoder::LeafEncoder;
+use super::error::LibError;
+use crate::merkel::{
+    ConsistencyProof, Data, Hash, MerkleHasher, MerkleTree, OwnedProof, ProofVersion, Sha256Hasher, TreeSnapshot,
+    HASHER_ID,
+};
+
+/// One appended event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct AuditEvent {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    #[serde(with = "crate::util::hex_serde")]
+    pub data: Data,
+}
+
+/// The root and size of the log when an epoch was sealed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Epoch {
+    /// Position of the epoch among the sealed epochs, from 0
+    pub number: usize,
+    /// Number of events the epoch covers, all events appended before it was sealed
+    pub size: usize,
+    #[serde(with = "crate::util::hex_serde")]
+    pub root: Hash,
+}
+
+/// Proof that an event is in the log, see `AuditLog::prove_event`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EventProof {
+    pub index: usize,
+    /// The epoch the event is proven in
+    pub epoch: Epoch,
+    /// Against the root of `epoch`
+    pub inclusion: OwnedProof,
+    /// The latest sealed epoch and the proof that it extends `epoch`, None if `epoch` is the latest
+    pub consistency: Option<(Epoch, ConsistencyProof)>,
+}
+
+impl EventProof {
+    /// Checks the event at its index in the epoch, and that the latest epoch extends it
+    ///
+    /// `leaf` is the encoded event, see `AuditLog::encode_event`. The caller still has to trust
+    /// the root of the latest epoch, by comparing it with an anchored one.
+    pub fn verify(&self, leaf: &Data) -> bool {
+        let epoch = &self.epoch;
+        let inclusion = self.inclusion.as_proof();
+        let included = MerkleTree::verify_proof_at_index(leaf, &inclusion, self.index, epoch.size, &epoch.root);
+        match &self.consistency {
+            None => included,
+            Some((latest, proof)) => {
+                included
+                    && (proof.old_size(), proof.new_size()) == (epoch.size, latest.size)
+                    && proof.verify(&epoch.root, &latest.root)
+            }
+        }
+    }
+
+    /// Root of the latest epoch, the one a verifier compares with its anchor
+    pub fn latest_root(&self) -> &Hash {
+        self.consistency.as_ref().map_or(&self.epoch.root, |(latest, _)| &latest.root)
+    }
+}
+
+/// First line of a saved log
+#[derive(Debug, Serialize, Deserialize)]
+struct AuditHeader {
+    version: ProofVersion,
+    hasher: String,
+}
+
+/// Every following line of a saved log
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AuditRecord {
+    Event(AuditEvent),
+    Epoch(Epoch),
+}
+
+/// An append-only event log over a `TreeSnapshot`, built with SHA-256
+#[derive(Clone, Default)]
+pub struct AuditLog {
+    events: Vec<AuditEvent>,
+    tree: Option<TreeSnapshot>,
+    /// Sealed epochs with the snapshot of the log at the time, which shares its chunks with later ones
+    epochs: Vec<(Epoch, TreeSnapshot)>,
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        AuditLog::default()
+    }
+
+    /// The leaf of an event, its timestamp and bytes encoded with `LeafEncoder`
+    pub fn encode_event(timestamp: u64, data: &[u8]) -> Data {
+        LeafEncoder::new().push_u64(timestamp).push_bytes(data).finish()
+    }
+
+    /// Appends an event stamped with the current time, returning its index and leaf hash
+    pub fn append(&mut self, data: &[u8]) -> (usize, Hash) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        self.append_at(timestamp, data)
+    }
+
+    /// Like `append`, with the given timestamp
+    pub fn append_at(&mut self, timestamp: u64, data: &[u8]) -> (usize, Hash) {
+        let leaf = AuditLog::encode_event(timestamp, data);
+        let tree = match &self.tree {
+            None => MerkleTree::construct(std::slice::from_ref(&leaf)).snapshot(),
+            Some(tree) => tree.push(&leaf),
+        };
+        self.tree = Some(tree);
+        self.events.push(AuditEvent { timestamp, data: data.to_vec() });
+        (self.events.len() - 1, Sha256Hasher.leaf_hash(&leaf))
+    }
+
+    /// Records the current root and size as a new epoch, failing with `LibError::EmptyInput`
+    /// before the first event
+    pub fn seal_epoch(&mut self) -> Result<&Epoch, LibError> {
+        let tree = self.tree.clone().ok_or(LibError::EmptyInput)?;
+        let epoch = Epoch {
+            number: self.epochs.len(),
+            size: tree.len(),
+            root: tree.root(),
+        };
+        self.epochs.push((epoch, tree));
+        Ok(&self.epochs[self.epochs.len() - 1].0)
+    }
+
+    /// Proves the event at `index` against the root of `epoch` and that epoch against the latest
+    ///
+    /// Fails with `LibError::UnknownEpoch` for an epoch that was not sealed and
+    /// `LibError::IndexOutOfRange` for an event appended after the epoch was sealed.
+    pub fn prove_event(&self, index: usize, epoch: usize) -> Result<EventProof, LibError> {
+        let (target, tree) = self
+            .epochs
+            .get(epoch)
+            .ok_or(LibError::UnknownEpoch { epoch, sealed: self.epochs.len() })?;
+        let inclusion = tree.prove_by_index(index)?.into_owned();
+        let consistency = match self.epochs.last() {
+            Some((latest, latest_tree)) if latest.number != epoch => {
+                Some((latest.clone(), latest_tree.prove_consistency(target.size)?))
+            }
+            _ => None,
+        };
+        Ok(EventProof { index, epoch: target.clone(), inclusion, consistency })
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn events(&self) -> &[AuditEvent] {
+        &self.events
+    }
+
+    /// Root over every event, sealed or not, None for an empty log
+    pub fn root(&self) -> Option<Hash> {
+        self.tree.as_ref().map(TreeSnapshot::root)
+    }
+
+    pub fn epochs(&self) -> impl Iterator<Item = &Epoch> + '_ {
+        self.epochs.iter().map(|(epoch, _)| epoch)
+    }
+
+    /// Writes the header line, then every event and sealed epoch as a line of its own
+    pub fn save<W: Write>(&self, mut writer: W) -> Result<(), LibError> {
+        let header = AuditHeader { version: ProofVersion::CURRENT, hasher: HASHER_ID.to_string() };
+        let mut line = serde_json::to_vec(&header).map_err(std::io::Error::from)?;
+        line.push(b'\n');
+        writer.write_all(&line)?;
+
+        let mut epochs = self.epochs().peekable();
+        for (index, event) in self.events.iter().enumerate() {
+            write_record(&mut writer, &AuditRecord::Event(event.clone()))?;
+            while let Some(epoch) = epochs.next_if(|epoch| epoch.size == index + 1) {
+                write_record(&mut writer, &AuditRecord::Epoch(epoch.clone()))?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a log written by `save`, replaying its events and checking every epoch against them
+    ///
+    /// Fails with `LibError::InvalidFormat` naming the 1-based line of a record that does not
+    /// parse or an epoch whose number, size or root is not that of the events before it.
+    pub fn load<R: BufRead>(reader: R) -> Result<AuditLog, LibError> {
+        let invalid = |line: usize, message: String| LibError::InvalidFormat(format!("line {}: {}", line, message));
+        let mut lines = reader.lines();
+        let header = lines.next().ok_or_else(|| invalid(1, "the log has no header".to_string()))??;
+        let header: AuditHeader = serde_json::from_str(&header).map_err(|e| invalid(1, e.to_string()))?;
+        if header.hasher != HASHER_ID {
+            let (expected, found) = (HASHER_ID.to_string(), header.hasher);
+            return Err(LibError::HasherMismatch { artifact: "audit log", expected, found });
+        }
+
+        let mut log = AuditLog::new();
+        for (number, line) in lines.enumerate().map(|(i, line)| (i + 2, line)) {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line).map_err(|e| invalid(number, e.to_string()))? {
+                AuditRecord::Event(event) => {
+                    log.append_at(event.timestamp, &event.data);
+                }
+                AuditRecord::Epoch(epoch) => {
+                    let sealed = log.seal_epoch().map_err(|_| invalid(number, "an epoch before any event".into()))?;
+                    if *sealed != epoch {
+                        let message = format!("epoch {} does not match the events before it", epoch.number);
+                        return Err(invalid(number, message));
+                    }
+                }
+            }
+        }
+        Ok(log)
+    }
+}
+
+fn write_record<W: Write>(writer: &mut W, record: &AuditRecord) -> Result<(), LibError> {
+    let mut line = serde_json::to_vec(record).map_err(std::io::Error::from)?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 100 events sealed into epochs after events 30, 70 and 100
+    fn example_log() -> AuditLog {
+        let mut log = AuditLog::new();
+        for i in 0..100u64 {
+            let (index, leaf) = log.append_at(1_700_000_000 + i, format!("event {}", i).as_bytes());
+            let event = &log.events()[index];
+            assert_eq!(leaf, Sha256Hasher.leaf_hash(&AuditLog::encode_event(event.timestamp, &event.data)));
+            if [29, 69, 99].contains(&i) {
+                log.seal_epoch().unwrap();
+            }
+        }
+        log
+    }
+
+    #[test]
+    fn test_events_verify_in_every_epoch() {
+        let log = example_log();
+        let sizes: Vec<usize> = log.epochs().map(|epoch| epoch.size).collect();
+        assert_eq!(sizes, [30, 70, 100]);
+        let latest = log.epochs().last().unwrap().root.clone();
+        assert_eq!(Some(&latest), log.root().as_ref());
+
+        for (index, event) in log.events().iter().enumerate() {
+            let leaf = AuditLog::encode_event(event.timestamp, &event.data);
+            let first = sizes.iter().position(|&size| index < size).unwrap();
+            for epoch in first..3 {
+                let proof = log.prove_event(index, epoch).unwrap();
+                assert!(proof.verify(&leaf), "event {} in epoch {}", index, epoch);
+                assert_eq!(proof.latest_root(), &latest);
+                assert_eq!(proof.consistency.is_some(), epoch < 2);
+                assert!(!proof.verify(&AuditLog::encode_event(event.timestamp + 1, &event.data)));
+            }
+            if first > 0 {
+                let err = log.prove_event(index, first - 1).unwrap_err();
+                assert!(matches!(err, LibError::IndexOutOfRange { .. }), "{}", err);
+            }
+        }
+
+        // an epoch proven against a rewritten history does not verify
+        let mut proof = log.prove_event(3, 0).unwrap();
+        let (latest_epoch, _) = proof.consistency.clone().unwrap();
+        let leaf = AuditLog::encode_event(log.events()[3].timestamp, &log.events()[3].data);
+        proof.consistency = Some((Epoch { root: vec![0; 32], ..latest_epoch }, proof.consistency.unwrap().1));
+        assert!(!proof.verify(&leaf));
+        assert!(matches!(log.prove_event(0, 3), Err(LibError::UnknownEpoch { epoch: 3, sealed: 3 })));
+        assert!(matches!(AuditLog::new().seal_epoch(), Err(LibError::EmptyInput)));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut log = example_log();
+        log.append_at(1, b"unsealed");
+        let mut saved = Vec::new();
+        log.save(&mut saved).unwrap();
+        let text = String::from_utf8(saved).unwrap();
+        assert_eq!(text.lines().count(), 1 + 101 + 3);
+        assert!(text.lines().nth(31).unwrap().starts_with(r#"{"epoch":{"number":0,"size":30,"root":""#));
+
+        let loaded = AuditLog::load(text.as_bytes()).unwrap();
+        assert_eq!((loaded.events(), loaded.root()), (log.events(), log.root()));
+        assert!(loaded.epochs().eq(log.epochs()));
+        let leaf = AuditLog::encode_event(log.events()[50].timestamp, &log.events()[50].data);
+        assert!(loaded.prove_event(50, 1).unwrap().verify(&leaf));
+
+        // rewriting an event breaks every epoch sealed after it
+        let tampered = text.replacen("6576656e74203130", "6576656e74203131", 1);
+        let err = AuditLog::load(tampered.as_bytes()).err().unwrap();
+        assert_eq!(err.to_string(), "invalid format: line 32: epoch 0 does not match the events before it");
+        let foreign = text.replacen(HASHER_ID, "sha512", 1);
+        assert!(matches!(AuditLog::load(foreign.as_bytes()), Err(LibError::HasherMismatch { .. })));
+    }
+}
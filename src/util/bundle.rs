@@ -0,0 +1,609 @@
+//! Proof bundles: a header naming the root and hasher, followed by one proof per line
+//!
+//! The first line is a JSON `BundleHeader` and every following non-blank line a JSON `BundleRow`,
+//! the same `{"index", "leaf", "proof"}` rows `export_proofs_jsonl` writes.
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Lines, Write};
+use std::num::NonZero;
+use std::sync::mpsc::sync_channel;
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::encoding::Encoding;
+use super::error::LibError;
+use crate::merkel::{
+    Hash, HashAlgorithm, LeafMode, MerkleHasher, MerkleTree, OwnedProof, ProofVersion, SourceId, TreeConfig,
+    TreeDescriptor, TreeShape, VerifyContext, CRATE_VERSION,
+};
+
+/// First line of a bundle, describing the tree every row was proven against
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct BundleHeader {
+    #[serde(default)]
+    pub version: ProofVersion,
+    /// `MerkleHasher::id` of the hasher the tree was built with
+    pub hasher: String,
+    /// How the entries the tree was built from became its leaves
+    pub leaf_mode: LeafMode,
+    /// Missing from bundles written before shapes were recorded, which are all paired
+    #[serde(default)]
+    pub shape: TreeShape,
+    #[serde(with = "crate::util::hex_serde")]
+    pub root: Hash,
+    pub leaf_count: u64,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    /// Version of the crate that wrote the bundle, missing from bundles written before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crate_version: Option<String>,
+}
+
+/// One proof of a bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct BundleRow {
+    pub index: usize,
+    /// Hash of the leaf
+    #[serde(with = "crate::util::hex_serde")]
+    pub leaf: Hash,
+    pub proof: OwnedProof,
+    /// Informational only, the proof does not cover it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceId>,
+}
+
+/// A header and the proofs of some leaves under its root
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProofBundle {
+    header: BundleHeader,
+    /// The tree the header describes, which the rows are checked against
+    context: VerifyContext,
+    rows: Vec<BundleRow>,
+}
+
+impl BundleHeader {
+    /// The tree the bundle was proven against, as `TreeConfig::descriptor` describes it
+    ///
+    /// Fails with `LibError::UnknownHasher` for a hasher this crate does not have.
+    pub fn descriptor(&self) -> Result<TreeDescriptor, LibError> {
+        Ok(TreeDescriptor {
+            root: self.root.clone(),
+            leaf_count: self.leaf_count,
+            hasher: header_hasher(self)?,
+            leaf_mode: Some(self.leaf_mode),
+            odd_leaf_policy: self.shape.odd_leaf_policy(),
+            shape: self.shape,
+            crate_version: self.crate_version.clone(),
+            canonical_ordering: None,
+            encoding: Encoding::Hex,
+        })
+    }
+
+    /// The context verifying rows against the tree of the header under a trusted `root`, which
+    /// takes the place of the header's own root
+    pub fn context(&self, root: &Hash) -> Result<VerifyContext, LibError> {
+        Ok(VerifyContext::new(TreeDescriptor { root: root.clone(), ..self.descriptor()? }))
+    }
+}
+
+impl ProofBundle {
+    /// Bundles the proof of every leaf of `tree`, stamped with the current time
+    ///
+    /// The tree does not know how its leaves were made, so the caller names the `leaf_mode`.
+    pub fn from_tree(tree: &MerkleTree, leaf_mode: LeafMode) -> Result<ProofBundle, LibError> {
+        let rows = (0..tree.len())
+            .map(|index| {
+                Ok(BundleRow {
+                    index,
+                    leaf: tree.leaves()[index].clone(),
+                    proof: tree.prove_by_index(index)?.into_owned(),
+                    source: tree.source_of(index).cloned(),
+                })
+            })
+            .collect::<Result<_, LibError>>()?;
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        let header = BundleHeader {
+            version: ProofVersion::CURRENT,
+            hasher: tree.hasher().id().to_string(),
+            leaf_mode,
+            shape: tree.shape(),
+            root: tree.root(),
+            leaf_count: tree.len() as u64,
+            created_at,
+            crate_version: Some(CRATE_VERSION.to_string()),
+        };
+        Ok(ProofBundle { context: header.context(&header.root)?, header, rows })
+    }
+
+    /// Replaces the creation time, for reproducible bundles
+    pub fn with_created_at(mut self, created_at: u64) -> Self {
+        self.header.created_at = created_at;
+        self
+    }
+
+    pub fn header(&self) -> &BundleHeader {
+        &self.header
+    }
+
+    pub fn root(&self) -> &Hash {
+        &self.header.root
+    }
+
+    pub fn hasher(&self) -> HashAlgorithm {
+        self.context.descriptor().hasher
+    }
+
+    pub fn rows(&self) -> &[BundleRow] {
+        &self.rows
+    }
+
+    /// Writes the header line followed by one line per row
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), LibError> {
+        let mut line = serde_json::to_vec(&self.header).map_err(std::io::Error::from)?;
+        line.push(b'\n');
+        writer.write_all(&line)?;
+        for row in &self.rows {
+            line.clear();
+            serde_json::to_writer(&mut line, row).map_err(std::io::Error::from)?;
+            line.push(b'\n');
+            writer.write_all(&line)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a bundle, checking every row against the header root unless `validate` is false
+    ///
+    /// Each proof is held to the index of its row in a tree of `leaf_count` leaves, as
+    /// `MerkleTree::verify_proof_at_index` holds proofs, under the hasher and shape of the header.
+    /// Fails with `LibError::UnknownHasher` for a bundle of a hasher this crate does not have and
+    /// `LibError::InvalidFormat` naming the 1-based row for a row that does not parse, lies outside
+    /// the tree, repeats the index of an earlier row or, when validating, does not lead to the root
+    /// from its index. Skipped validation can be done later with `validate`.
+    pub fn read<R: BufRead>(reader: R, validate: bool) -> Result<ProofBundle, LibError> {
+        ProofBundle::read_checked(reader, validate, None)
+    }
+
+    /// Like `read`, first failing with `LibError::HasherMismatch` unless the header names the
+    /// hasher and leaf mode of `config`
+    pub fn read_with<R: BufRead>(reader: R, validate: bool, config: &TreeConfig) -> Result<ProofBundle, LibError> {
+        ProofBundle::read_checked(reader, validate, Some(config))
+    }
+
+    fn read_checked<R: BufRead>(
+        reader: R,
+        validate: bool,
+        config: Option<&TreeConfig>,
+    ) -> Result<ProofBundle, LibError> {
+        let mut lines = reader.lines();
+        let header = read_header(&mut lines)?;
+        if let Some(config) = config {
+            config.check_artifact("proof bundle", &header.hasher, Some(header.leaf_mode))?;
+        }
+        let context = header.context(&header.root)?;
+
+        let mut bundle = ProofBundle { header, context, rows: vec![] };
+        let mut seen = HashMap::new();
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let number = bundle.rows.len() + 1;
+            let row: BundleRow = serde_json::from_str(&line).map_err(|e| invalid(format!("row {}: {}", number, e)))?;
+            check_row(&bundle.context, number, &row, validate, &mut seen)?;
+            bundle.rows.push(row);
+        }
+        Ok(bundle)
+    }
+
+    /// Checks every row against the header, as `read` does when validating
+    ///
+    /// This trusts the root of the header; check rows against a trusted root with
+    /// `verify_with(&header.context(root)?)`.
+    pub fn validate(&self) -> Result<(), LibError> {
+        self.check_rows(&self.context)
+    }
+
+    /// Checks that the header describes the tree of `context` and every row against it
+    ///
+    /// Fails with `LibError::ContextMismatch` for a header of another tree, and as `validate` does
+    /// for a row that does not verify, such as one tagged with another tree size.
+    pub fn verify_with(&self, context: &VerifyContext) -> Result<(), LibError> {
+        context.check_descriptor("proof bundle", &self.header.descriptor()?)?;
+        self.check_rows(context)
+    }
+
+    fn check_rows(&self, context: &VerifyContext) -> Result<(), LibError> {
+        let mut seen = HashMap::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            check_row(context, i + 1, row, true, &mut seen)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that the row at 1-based `number` lies in the tree of `context` and repeats no index of
+/// the rows in `seen`, and when `verify` is set that its proof leads from its index to the root
+fn check_row(
+    context: &VerifyContext,
+    number: usize,
+    row: &BundleRow,
+    verify: bool,
+    seen: &mut HashMap<usize, usize>,
+) -> Result<(), LibError> {
+    if row.index >= context.tree_size() {
+        let size = context.tree_size();
+        return Err(invalid(format!("row {}: leaf {} is outside a tree of {} leaves", number, row.index, size)));
+    }
+    if let Some(first) = seen.insert(row.index, number) {
+        return Err(invalid(format!("row {}: leaf {} is already proven by row {}", number, row.index, first)));
+    }
+    // a proof tagged with another tree size, or deeper than the tree, leads nowhere in it
+    if verify && !context.verify_leaf_hash(&row.leaf, row.index, &row.proof).unwrap_or(false) {
+        let message = format!("row {}: the proof of leaf {} does not lead to the bundle root", number, row.index);
+        return Err(invalid(message));
+    }
+    Ok(())
+}
+
+/// Rows the reading thread of `verify_bundle_parallel` hands to a worker at a time
+const PARALLEL_BATCH: usize = 256;
+
+/// A row of a bundle that does not parse, as collected by `verify_bundle_parallel`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct MalformedRow {
+    /// 1-based line of the bundle, the header being line 1
+    pub line: usize,
+    pub error: String,
+}
+
+/// Outcome of `verify_bundle_parallel`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct BundleReport {
+    /// Non-blank lines after the header, whether they parse or not
+    pub rows: usize,
+    pub valid: usize,
+    /// Lines of the rows that lie outside the tree, repeat the index of an earlier row or whose
+    /// proof does not lead to the root from their index, in order
+    pub invalid: Vec<usize>,
+    /// In order of their lines
+    pub malformed: Vec<MalformedRow>,
+}
+
+impl BundleReport {
+    /// Whether every row parsed and verified
+    pub fn is_valid(&self) -> bool {
+        self.invalid.is_empty() && self.malformed.is_empty()
+    }
+}
+
+/// Verifies every row of a bundle against a trusted `root` on `threads` worker threads, one per
+/// core for 0, without holding the whole bundle in memory
+///
+/// Rows are parsed on the calling thread and handed to the workers in batches through a bounded
+/// channel, so only a few batches per worker are in memory at once, besides the indices seen. The
+/// header names the hasher, shape and tree size, but its own root is not trusted: rows are checked
+/// against `BundleHeader::context` of `root`, each proof held to the index of its row.
+/// Fails like `ProofBundle::read` on the header and with `LibError::Io` if reading fails; rows
+/// that do not parse or verify are reported instead.
+pub fn verify_bundle_parallel<R: BufRead>(reader: R, root: &Hash, threads: usize) -> Result<BundleReport, LibError> {
+    let mut lines = reader.lines();
+    let header = read_header(&mut lines)?;
+    let context = header.context(root)?;
+    verify_rows_parallel(lines, threads, |row| {
+        context.verify_leaf_hash(&row.leaf, row.index, &row.proof).unwrap_or(false)
+    })
+}
+
+/// Like `verify_bundle_parallel`, trusting the tree `context` describes rather than a root
+///
+/// Fails with `LibError::ContextMismatch` unless the header describes the same tree. Rows are
+/// checked as `ProofBundle::verify_with` checks them, and a row tagged with another tree size is
+/// reported invalid.
+pub fn verify_bundle_parallel_with<R: BufRead>(
+    reader: R,
+    context: &VerifyContext,
+    threads: usize,
+) -> Result<BundleReport, LibError> {
+    let mut lines = reader.lines();
+    let header = read_header(&mut lines)?;
+    context.check_descriptor("proof bundle", &header.descriptor()?)?;
+    verify_rows_parallel(lines, threads, |row| {
+        context.verify_leaf_hash(&row.leaf, row.index, &row.proof).unwrap_or(false)
+    })
+}
+
+/// Parses the rows after the header on the calling thread and checks them with `check` on
+/// `threads` workers, one per core for 0
+///
+/// A row repeating the index of an earlier row is invalid without being checked.
+fn verify_rows_parallel<R: BufRead, F: Fn(&BundleRow) -> bool + Sync>(
+    lines: Lines<R>,
+    threads: usize,
+    check: F,
+) -> Result<BundleReport, LibError> {
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, NonZero::get),
+        threads => threads,
+    };
+
+    let (sender, receiver) = sync_channel::<Vec<(usize, BundleRow)>>(2 * threads);
+    let receiver = Mutex::new(receiver);
+    let verify = || {
+        let (mut valid, mut invalid) = (0, Vec::new());
+        // the lock is only held while waiting for a batch, not while verifying it
+        while let Ok(batch) = receiver.lock().unwrap_or_else(PoisonError::into_inner).recv() {
+            for (line, row) in batch {
+                if check(&row) {
+                    valid += 1;
+                } else {
+                    invalid.push(line);
+                }
+            }
+        }
+        (valid, invalid)
+    };
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|_| scope.spawn(verify)).collect();
+        let mut report = BundleReport::default();
+        let read = || -> Result<(), LibError> {
+            let mut batch = Vec::with_capacity(PARALLEL_BATCH);
+            let mut seen = HashSet::new();
+            for (i, line) in lines.enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                report.rows += 1;
+                match serde_json::from_str::<BundleRow>(&line) {
+                    Ok(row) if !seen.insert(row.index) => report.invalid.push(i + 2),
+                    Ok(row) => batch.push((i + 2, row)),
+                    Err(e) => report.malformed.push(MalformedRow { line: i + 2, error: e.to_string() }),
+                }
+                // a send only fails once every worker has panicked, which the joins below resume
+                if batch.len() == PARALLEL_BATCH && sender.send(std::mem::take(&mut batch)).is_err() {
+                    return Ok(());
+                }
+            }
+            let _ = sender.send(batch);
+            Ok(())
+        };
+        let read = read();
+        drop(sender);
+
+        for worker in workers {
+            let (valid, invalid) = worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            report.valid += valid;
+            report.invalid.extend(invalid);
+        }
+        report.invalid.sort_unstable();
+        read.map(|()| report)
+    })
+}
+
+fn read_header<R: BufRead>(lines: &mut Lines<R>) -> Result<BundleHeader, LibError> {
+    let header = lines.next().ok_or_else(|| invalid("the bundle has no header".to_string()))??;
+    serde_json::from_str(&header).map_err(|e| invalid(format!("header: {}", e)))
+}
+
+fn header_hasher(header: &BundleHeader) -> Result<HashAlgorithm, LibError> {
+    HashAlgorithm::from_id(&header.hasher).ok_or_else(|| LibError::UnknownHasher(header.hasher.clone()))
+}
+
+fn invalid(message: String) -> LibError {
+    LibError::InvalidFormat(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::Data;
+
+    fn example_bundle(n: usize) -> ProofBundle {
+        let data: Vec<Data> = (0..n).map(|i| vec![i as u8]).collect();
+        let tree = MerkleTree::construct(&data);
+        ProofBundle::from_tree(&tree, LeafMode::HashData).unwrap().with_created_at(1_700_000_000)
+    }
+
+    fn written(bundle: &ProofBundle) -> String {
+        let mut out = Vec::new();
+        bundle.write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let bundle = example_bundle(7);
+        let text = written(&bundle);
+        assert_eq!(text.lines().count(), 8);
+        let header: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(header["hasher"], "sha256");
+        assert_eq!((header["leaf_mode"].clone(), header["shape"].clone()), ("hash-data".into(), "paired".into()));
+        assert_eq!((header["version"].clone(), header["leaf_count"].clone()), (2.into(), 7.into()));
+        assert_eq!(header["created_at"], 1_700_000_000);
+
+        let read = ProofBundle::read(text.as_bytes(), true).unwrap();
+        assert_eq!(read, bundle);
+        assert_eq!(read.rows().iter().map(|row| row.index).collect::<Vec<_>>(), (0..7).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_descriptor_round_trip() {
+        let data: Vec<Data> = (0..7).map(|i| vec![i as u8]).collect();
+        for config in [
+            TreeConfig::default().with_hasher(HashAlgorithm::Sha512),
+            TreeConfig::default().with_shape(TreeShape::LeftBalanced),
+        ] {
+            let tree = config.construct(&data).unwrap();
+            let bundle = ProofBundle::from_tree(&tree, config.leaf_mode()).unwrap();
+            let read = ProofBundle::read(written(&bundle).as_bytes(), true).unwrap();
+            assert_eq!(read.header().descriptor().unwrap(), config.descriptor(&tree).unwrap());
+        }
+
+        // bundles written before the crate version was recorded still describe their tree
+        let text = written(&example_bundle(3));
+        let header: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        let old = text.replacen(&format!(",\"crate_version\":{}", header["crate_version"]), "", 1);
+        let descriptor = ProofBundle::read(old.as_bytes(), true).unwrap().header().descriptor().unwrap();
+        assert_eq!((descriptor.crate_version, descriptor.leaf_count), (None, 3));
+    }
+
+    #[test]
+    fn test_roundtrip_with_other_hashers() {
+        let data: Vec<Data> = (0..5).map(|i| vec![i as u8]).collect();
+        for hasher in [HashAlgorithm::Sha512, HashAlgorithm::Ripemd160] {
+            let config = TreeConfig::default().with_hasher(hasher);
+            let tree = config.construct(&data).unwrap();
+            let bundle = ProofBundle::from_tree(&tree, LeafMode::HashData).unwrap();
+            assert_eq!(bundle.header().hasher, hasher.id());
+
+            let read = ProofBundle::read(written(&bundle).as_bytes(), true).unwrap();
+            assert_eq!((read.hasher(), read.root()), (hasher, &tree.root()));
+            assert_eq!(ProofBundle::read_with(written(&bundle).as_bytes(), true, &config).unwrap(), read);
+            let err = ProofBundle::read_with(written(&bundle).as_bytes(), true, &TreeConfig::default()).unwrap_err();
+            assert!(matches!(err, LibError::HasherMismatch { artifact: "proof bundle", .. }));
+            // the same rows do not verify as SHA-256 proofs
+            let relabeled = written(&bundle).replacen(hasher.id(), "sha256", 1);
+            assert!(ProofBundle::read(relabeled.as_bytes(), true).is_err());
+        }
+    }
+
+    #[test]
+    fn test_rejects_rows_of_another_root() {
+        let bundle = example_bundle(5);
+        let other = example_bundle(6);
+        // the header of one tree over the rows of another
+        let text = written(&bundle);
+        let mut lines: Vec<&str> = text.lines().collect();
+        let other_text = written(&other);
+        lines[3] = other_text.lines().nth(3).unwrap();
+        let text = lines.join("\n");
+
+        let err = ProofBundle::read(text.as_bytes(), true).unwrap_err();
+        assert_eq!(err.to_string(), "invalid format: row 3: the proof of leaf 2 does not lead to the bundle root");
+        let deferred = ProofBundle::read(text.as_bytes(), false).unwrap();
+        assert_eq!(deferred.validate().unwrap_err().to_string(), err.to_string());
+        assert!(ProofBundle::read(written(&other).as_bytes(), true).unwrap().validate().is_ok());
+    }
+
+    #[test]
+    fn test_rejects_rows_of_another_index() {
+        let text = written(&example_bundle(7));
+        let message = |text: &str| ProofBundle::read(text.as_bytes(), true).unwrap_err().to_string();
+        // the proof of leaf 0 relabeled as that of leaf 6, which the bundle then has twice
+        let relabeled = text.replacen("\"index\":0,", "\"index\":6,", 1);
+        assert_eq!(message(&relabeled), "invalid format: row 1: the proof of leaf 6 does not lead to the bundle root");
+        let deferred = ProofBundle::read(relabeled.as_bytes(), false);
+        assert_eq!(deferred.unwrap_err().to_string(), "invalid format: row 7: leaf 6 is already proven by row 1");
+
+        // a row repeated whole verifies again, but proves nothing new
+        let second = text.lines().nth(2).unwrap();
+        let repeated = format!("{}{}\n", text, second);
+        assert_eq!(message(&repeated), "invalid format: row 8: leaf 1 is already proven by row 2");
+        let mut bundle = ProofBundle::read(text.as_bytes(), true).unwrap();
+        bundle.rows.push(bundle.rows[1].clone());
+        assert_eq!(bundle.validate().unwrap_err().to_string(), message(&repeated));
+    }
+
+    #[test]
+    fn test_rejects_bad_headers_and_rows() {
+        let text = written(&example_bundle(3));
+        let message = |text: &str| ProofBundle::read(text.as_bytes(), false).unwrap_err().to_string();
+
+        assert_eq!(message(""), "invalid format: the bundle has no header");
+        assert_eq!(message(&text.replacen("sha256", "keccak256", 1)), "unknown hasher `keccak256`");
+        assert!(message(&text.replacen("\"version\":2", "\"version\":9", 1)).contains("unsupported format version 9"));
+        let shrunk = text.replacen("\"leaf_count\":3", "\"leaf_count\":2", 1);
+        assert!(message(&shrunk).contains("row 3: leaf 2 is outside"));
+        assert!(message(&format!("{}{{}}\n", text)).starts_with("invalid format: row 4: missing field"));
+    }
+
+    #[test]
+    fn test_verify_parallel() {
+        let data: Vec<Data> = (0..10_000u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let tree = MerkleTree::construct(&data);
+        let text = written(&ProofBundle::from_tree(&tree, LeafMode::HashData).unwrap());
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        let verify = |text: &str, threads| verify_bundle_parallel(text.as_bytes(), &tree.root(), threads).unwrap();
+
+        let report = verify(&text, 4);
+        assert!(report.is_valid());
+        assert_eq!((report.rows, report.valid), (10_000, 10_000));
+
+        // line n + 2 holds the proof of leaf n, and the blank line shifts the lines after it
+        let other = MerkleTree::construct(&data[..5]);
+        lines[9_001] = lines[9_001].replacen(&hex::encode(&tree.leaves()[9_000]), &hex::encode([0; 32]), 1);
+        lines[42] = lines[42].replacen("\"index\":41", "\"index\":10000", 1);
+        lines[7] = format!(r#"{{"index":6,"leaf":"{}","proof":[]}}"#, hex::encode(&other.leaves()[0]));
+        // two proofs with their indices swapped, and a proof repeated on the next line
+        lines[200] = lines[200].replacen("\"index\":199,", "\"index\":200,", 1);
+        lines[201] = lines[201].replacen("\"index\":200,", "\"index\":199,", 1);
+        lines[301] = lines[300].clone();
+        lines[3_000].truncate(20);
+        lines.insert(500, String::new());
+        let text = lines.join("\n");
+        let report = verify(&text, 8);
+        assert_eq!((report.rows, report.valid), (10_000, 9_993));
+        assert_eq!(report.invalid, [8, 43, 201, 202, 302, 9_003]);
+        assert_eq!(report.malformed.iter().map(|row| row.line).collect::<Vec<_>>(), [3_002]);
+        assert!(report.malformed[0].error.starts_with("EOF while parsing"), "{:?}", report.malformed);
+        assert_eq!(verify(&text, 1), report);
+        assert_eq!(verify(&text, 0), report);
+
+        // the header root is not trusted
+        let untrusted = verify_bundle_parallel(text.as_bytes(), &other.root(), 2).unwrap();
+        assert_eq!(untrusted.valid, 0);
+        assert!(matches!(verify_bundle_parallel(&b""[..], &tree.root(), 2), Err(LibError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn test_verify_with_context() {
+        let data: Vec<Data> = (0..7).map(|i| vec![i as u8]).collect();
+        for config in [TreeConfig::default(), TreeConfig::default().with_shape(TreeShape::LeftBalanced)] {
+            let tree = config.construct(&data).unwrap();
+            let context = VerifyContext::new(config.descriptor(&tree).unwrap());
+            let bundle = ProofBundle::from_tree(&tree, config.leaf_mode()).unwrap();
+            assert!(bundle.verify_with(&context).is_ok());
+            let report = verify_bundle_parallel_with(written(&bundle).as_bytes(), &context, 2).unwrap();
+            assert_eq!((report.valid, report.is_valid()), (7, true));
+
+            // a row claiming the proof of another leaf
+            let mut moved = bundle.clone();
+            moved.rows.remove(2);
+            moved.rows[2].index = 2;
+            let error = moved.verify_with(&context).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "invalid format: row 3: the proof of leaf 2 does not lead to the bundle root"
+            );
+            assert_eq!(moved.validate().unwrap_err().to_string(), error.to_string());
+            let report = verify_bundle_parallel_with(written(&moved).as_bytes(), &context, 2).unwrap();
+            assert_eq!(report.invalid, [4]);
+        }
+
+        // the context of a trusted root other than the header's
+        let tree = MerkleTree::construct(&data);
+        let bundle = ProofBundle::from_tree(&tree, LeafMode::HashData).unwrap();
+        assert_eq!(bundle.header().context(&tree.root()).unwrap(), bundle.context);
+        let untrusted = bundle.header().context(&[0; 32].to_vec()).unwrap();
+        assert!(matches!(bundle.verify_with(&untrusted), Err(LibError::ContextMismatch { field: "root", .. })));
+
+        // the bundle of another tree
+        let context = VerifyContext::new(TreeConfig::default().descriptor(&tree).unwrap());
+        let other = ProofBundle::from_tree(&MerkleTree::construct(&data[..6]), LeafMode::HashData).unwrap();
+        let error = other.verify_with(&context).unwrap_err();
+        assert!(matches!(error, LibError::ContextMismatch { artifact: "proof bundle", field: "leaf count", .. }));
+        let parallel = verify_bundle_parallel_with(written(&other).as_bytes(), &context, 2);
+        assert!(matches!(parallel, Err(LibError::ContextMismatch { field: "leaf count", .. })));
+        let pre_hashed = ProofBundle::from_tree(&tree, LeafMode::PreHashed).unwrap();
+        assert!(matches!(pre_hashed.verify_with(&context), Err(LibError::ContextMismatch { field: "leaf mode", .. })));
+    }
+}
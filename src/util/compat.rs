@@ -0,0 +1,219 @@
+//! Proofs in the layout of `rs_merkle`, for reading proofs persisted by that crate
+//!
+//! `rs_merkle` keeps a proof as the index of its leaf, the number of leaves and the sibling
+//! hashes from the leaf up, without directions; `MerkleProof::to_bytes` writes the 32-byte
+//! hashes one after another. Its trees are built over SHA-256 leaf hashes and carry the lone last
+//! node of an odd level up unhashed, which here is `LeafMode::PreHashed` with
+//! `OddLeafPolicy::Promote`, so levels where the path passes through that node have no sibling.
+//!
+//! Proofs from trees built with `OddLeafPolicy::Duplicate` convert as long as their path never
+//! passes through a padded node, and then still lead to the root of the tree they came from.
+//! A path that does has a step the layout has no place for, and converting it fails rather than
+//! giving a proof of another position.
+
+use crate::merkel::{check_step_count, tree_depth, Hash, HashDirection, IndexedProof, OwnedProof, StepHash, HASH_LEN};
+use crate::util::error::LibError;
+
+/// The proof of one leaf as `rs_merkle` lays it out, see the module docs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlatProof {
+    pub leaf_index: usize,
+    pub total_leaves: usize,
+    /// Siblings from the leaf up, none for the levels where the path is on a promoted node
+    pub proof_hashes: Vec<Hash>,
+}
+
+impl FlatProof {
+    pub fn new(leaf_index: usize, total_leaves: usize, proof_hashes: Vec<Hash>) -> Self {
+        FlatProof { leaf_index, total_leaves, proof_hashes }
+    }
+
+    /// Reads the hashes as `MerkleProof::to_bytes` writes them, failing with
+    /// `LibError::InvalidHashLength` unless `bytes` splits into 32-byte hashes and
+    /// `LibError::ProofTooDeep` for more than `MAX_PROOF_STEPS` of them
+    pub fn from_bytes(leaf_index: usize, total_leaves: usize, bytes: &[u8]) -> Result<Self, LibError> {
+        if !bytes.len().is_multiple_of(HASH_LEN) {
+            return Err(LibError::InvalidHashLength { expected: HASH_LEN, found: bytes.len() % HASH_LEN });
+        }
+        check_step_count(bytes.len() / HASH_LEN)?;
+        Ok(FlatProof::new(leaf_index, total_leaves, bytes.chunks(HASH_LEN).map(<[u8]>::to_vec).collect()))
+    }
+
+    /// The hashes as `MerkleProof::to_bytes` writes them
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.proof_hashes.concat()
+    }
+}
+
+impl TryFrom<FlatProof> for OwnedProof {
+    type Error = LibError;
+
+    /// The proof with its directions, tagged with the tree size and declared
+    /// `ProofOrder::LeafToRoot`; see `OwnedProof::from_audit_path`
+    fn try_from(flat: FlatProof) -> Result<Self, LibError> {
+        OwnedProof::from_audit_path(flat.leaf_index, flat.total_leaves, flat.proof_hashes)
+    }
+}
+
+impl TryFrom<&OwnedProof> for FlatProof {
+    type Error = LibError;
+
+    /// Fails with `LibError::InvalidFormat` for a proof not tagged with its tree size or whose
+    /// steps are not a path of a tree with promoted lone nodes, and `LibError::InvalidHashLength`
+    /// for hashes other than 32 bytes
+    fn try_from(proof: &OwnedProof) -> Result<Self, LibError> {
+        let total_leaves = proof
+            .tree_size()
+            .ok_or_else(|| LibError::InvalidFormat("the proof is not tagged with its tree size".into()))?;
+        if let Some((_, hash)) = proof.hashes().iter().find(|(_, hash)| hash.len() != HASH_LEN) {
+            return Err(LibError::InvalidHashLength { expected: HASH_LEN, found: hash.len() });
+        }
+        let leaf_index = promoted_path_index(proof.hashes(), total_leaves)?;
+        let proof_hashes = proof.hashes().iter().map(|(_, hash)| hash.to_vec()).collect();
+        Ok(FlatProof { leaf_index, total_leaves, proof_hashes })
+    }
+}
+
+impl TryFrom<OwnedProof> for FlatProof {
+    type Error = LibError;
+
+    fn try_from(proof: OwnedProof) -> Result<Self, LibError> {
+        FlatProof::try_from(&proof)
+    }
+}
+
+impl TryFrom<&IndexedProof> for FlatProof {
+    type Error = LibError;
+
+    /// Fails with `LibError::InvalidFormat` if the path passes through a padded node
+    fn try_from(proof: &IndexedProof) -> Result<Self, LibError> {
+        FlatProof::try_from(&proof.to_proof())
+    }
+}
+
+impl TryFrom<FlatProof> for IndexedProof {
+    type Error = LibError;
+
+    /// Fails with `LibError::InvalidFormat` if the path passes through a promoted node, which a
+    /// tree built with `OddLeafPolicy::Duplicate` would have padded
+    fn try_from(flat: FlatProof) -> Result<Self, LibError> {
+        let (index, len, depth) = (flat.leaf_index, flat.proof_hashes.len(), tree_depth(flat.total_leaves));
+        if index < flat.total_leaves && len < depth {
+            return Err(LibError::InvalidFormat(format!(
+                "the path of leaf {} passes through {} promoted nodes, which padded trees pair with themselves",
+                index,
+                depth - len
+            )));
+        }
+        OwnedProof::try_from(flat)?.to_indexed()
+    }
+}
+
+/// Index of the leaf whose path in a tree of `tree_size` leaves, with lone nodes promoted, has
+/// the directions of `steps`
+///
+/// Walks down from the root, taking a step wherever the node on the path has two children.
+fn promoted_path_index(steps: &[(HashDirection, StepHash)], tree_size: usize) -> Result<usize, LibError> {
+    let not_a_path = || {
+        LibError::InvalidFormat(format!(
+            "the steps of the proof are not a path in a tree of {} leaves whose lone nodes are promoted",
+            tree_size
+        ))
+    };
+    let mut sizes = vec![tree_size];
+    while sizes[sizes.len() - 1] > 1 {
+        sizes.push(sizes[sizes.len() - 1].div_ceil(2));
+    }
+    if tree_size == 0 {
+        return Err(not_a_path());
+    }
+    if let Some(step) = steps.iter().position(|(direction, _)| *direction == HashDirection::Duplicate) {
+        return Err(LibError::InvalidFormat(format!(
+            "step {} pairs a node with itself, which the flat layout has no place for",
+            step + 1
+        )));
+    }
+    let (mut node, mut remaining) = (0usize, steps.len());
+    for &size in sizes[..sizes.len() - 1].iter().rev() {
+        let left = 2 * node;
+        if left + 1 >= size {
+            node = left;
+            continue;
+        }
+        remaining = remaining.checked_sub(1).ok_or_else(not_a_path)?;
+        node = match steps[remaining].0 {
+            HashDirection::Left => left + 1,
+            _ => left,
+        };
+    }
+    match remaining {
+        0 => Ok(node),
+        _ => Err(not_a_path()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{Data, LeafMode, MerkleTree, OddLeafPolicy, TreeConfig, MAX_PROOF_STEPS};
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| vec![i as u8]).collect()
+    }
+
+    #[test]
+    fn test_promoted_proofs_round_trip() {
+        let config = TreeConfig::default().with_odd_leaf_policy(OddLeafPolicy::Promote);
+        for size in 1..=9 {
+            let tree = config.construct(&example_data(size)).unwrap();
+            for index in 0..size {
+                let proof = tree.prove_by_index(index).unwrap().into_owned();
+                let flat = FlatProof::try_from(&proof).unwrap();
+                assert_eq!((flat.leaf_index, flat.total_leaves), (index, size), "leaf {} of {}", index, size);
+                let bytes = FlatProof::from_bytes(index, size, &flat.to_bytes()).unwrap();
+                assert_eq!(OwnedProof::try_from(bytes).unwrap(), proof);
+            }
+        }
+    }
+
+    #[test]
+    fn test_padded_paths_do_not_convert() {
+        let data = example_data(5);
+        let tree = MerkleTree::construct(&data);
+        // leaf 1 never meets the padded last node, leaf 4 is that node
+        let inner = tree.prove_by_index(1).unwrap().into_owned();
+        let flat = FlatProof::try_from(&inner).unwrap();
+        let back = OwnedProof::try_from(flat.clone()).unwrap();
+        assert!(MerkleTree::verify_proof(&data[1], &back.as_proof(), &tree.root()));
+        assert_eq!(IndexedProof::try_from(flat).unwrap(), inner.to_indexed().unwrap());
+
+        let edge = tree.prove_by_index(4).unwrap().into_owned();
+        let error = FlatProof::try_from(&edge).unwrap_err().to_string();
+        assert_eq!(error, "invalid format: step 1 pairs a node with itself, which the flat layout has no place for");
+        assert!(FlatProof::try_from(&edge.to_indexed().unwrap()).is_err());
+
+        let promoted = TreeConfig::new(LeafMode::PreHashed).with_odd_leaf_policy(OddLeafPolicy::Promote);
+        let promoted = promoted.construct(tree.leaves()).unwrap().prove_by_index(4).unwrap().into_owned();
+        let error = IndexedProof::try_from(FlatProof::try_from(promoted).unwrap()).unwrap_err().to_string();
+        assert!(error.contains("leaf 4 passes through 2 promoted nodes"), "{}", error);
+    }
+
+    #[test]
+    fn test_rejects_malformed_proofs() {
+        let tree = MerkleTree::construct(&example_data(4));
+        let proof = tree.prove_by_index(2).unwrap().into_owned();
+        let untagged = OwnedProof::new(proof.hashes().to_vec());
+        assert!(matches!(FlatProof::try_from(&untagged), Err(LibError::InvalidFormat(_))));
+        let short = OwnedProof::new(proof.hashes()[..1].to_vec()).with_tree_size(4);
+        assert!(FlatProof::try_from(short).unwrap_err().to_string().contains("not a path in a tree of 4 leaves"));
+        let wide = OwnedProof::new(vec![(HashDirection::Right, vec![0; 64])]).with_tree_size(2);
+        assert!(matches!(FlatProof::try_from(&wide), Err(LibError::InvalidHashLength { expected: 32, found: 64 })));
+
+        assert!(matches!(FlatProof::from_bytes(0, 4, &[0; 33]), Err(LibError::InvalidHashLength { found: 1, .. })));
+        let too_many = MAX_PROOF_STEPS + 1;
+        let deep = FlatProof::from_bytes(0, 4, &vec![0; too_many * HASH_LEN]);
+        assert!(matches!(deep, Err(LibError::ProofTooDeep { max: MAX_PROOF_STEPS, got }) if got == too_many));
+        let past = FlatProof::new(4, 4, vec![vec![0; 32]; 2]);
+        assert!(matches!(OwnedProof::try_from(past), Err(LibError::IndexOutOfRange { index: 4, size: 4 })));
+    }
+}
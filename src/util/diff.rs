@@ -0,0 +1,263 @@
+use serde::Serialize;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use super::error::LibError;
+use super::generate::decode_hashes_with;
+use super::hex_policy::HexPolicy;
+use crate::merkel::{Hash, HashAlgorithm, LeafMode, MerkleHasher, MerkleTree, NodeId, TreeConfig};
+
+/// Largest number of differing leaf indices listed by default
+pub const DIFF_MAX_REPORTED: usize = 1000;
+
+/// How `diff_files` reads its inputs and how much it reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffOptions {
+    hasher: HashAlgorithm,
+    hex_policy: HexPolicy,
+    max_reported: usize,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            hasher: HashAlgorithm::default(),
+            hex_policy: HexPolicy::default(),
+            max_reported: DIFF_MAX_REPORTED,
+        }
+    }
+}
+
+impl DiffOptions {
+    /// Sets the hasher of the hashes in both files
+    pub fn with_hasher(mut self, hasher: HashAlgorithm) -> Self {
+        self.hasher = hasher;
+        self
+    }
+
+    /// Sets which spellings of hex both files may use
+    pub fn with_hex_policy(mut self, hex_policy: HexPolicy) -> Self {
+        self.hex_policy = hex_policy;
+        self
+    }
+
+    /// Sets how many differing indices are listed; all of them are still counted
+    pub fn with_max_reported(mut self, max_reported: usize) -> Self {
+        self.max_reported = max_reported;
+        self
+    }
+
+    pub fn hasher(&self) -> HashAlgorithm {
+        self.hasher
+    }
+
+    pub fn hex_policy(&self) -> HexPolicy {
+        self.hex_policy
+    }
+
+    pub fn max_reported(&self) -> usize {
+        self.max_reported
+    }
+}
+
+/// How the leaves of the new tree relate to those of the old one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum DiffKind {
+    /// The same leaves in the same order
+    Identical,
+    /// As many leaves, some of them different
+    Changed,
+    /// The new tree starts with every leaf of the old one, shown by a `ConsistencyProof`
+    Extended,
+    /// The old tree starts with every leaf of the new one
+    Truncated,
+    /// Different lengths, and some leaves both trees have differ
+    Diverged,
+}
+
+/// Outcome of `diff_trees` and `diff_files`
+///
+/// Roots can match without the trees being identical: under duplicate padding a tree whose last
+/// leaf is repeated once more may keep its root, which `kind` still reports as `Extended`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct DiffReport {
+    pub kind: DiffKind,
+    pub roots_match: bool,
+    #[serde(with = "crate::util::hex_serde")]
+    pub old_root: Hash,
+    #[serde(with = "crate::util::hex_serde")]
+    pub new_root: Hash,
+    pub old_leaf_count: usize,
+    pub new_leaf_count: usize,
+    /// Indices present in both trees whose leaves differ, ascending and at most `max_reported`
+    pub differing: Vec<usize>,
+    /// Number of such indices, including those not listed
+    pub differing_count: usize,
+    /// Leaves past the end of the old tree
+    pub added: usize,
+    /// Leaves past the end of the new tree
+    pub removed: usize,
+}
+
+/// Compares two trees built with the same hasher, leaf by leaf
+///
+/// Subtrees with equal hashes are skipped. When the lengths differ, a consistency proof first
+/// checks whether the shorter tree is a prefix of the longer one; only if it is not are the
+/// common leaves compared through a tree over the first leaves of the longer one.
+pub fn diff_trees(old: &MerkleTree, new: &MerkleTree, max_reported: usize) -> Result<DiffReport, LibError> {
+    if old.hasher() != new.hasher() {
+        return Err(LibError::HasherMismatch {
+            artifact: "compared tree",
+            expected: old.hasher().id().to_string(),
+            found: new.hasher().id().to_string(),
+        });
+    }
+    let mut report = DiffReport {
+        kind: DiffKind::Identical,
+        roots_match: old.root() == new.root(),
+        old_root: old.root(),
+        new_root: new.root(),
+        old_leaf_count: old.len(),
+        new_leaf_count: new.len(),
+        differing: Vec::new(),
+        differing_count: 0,
+        added: new.len().saturating_sub(old.len()),
+        removed: old.len().saturating_sub(new.len()),
+    };
+
+    let (shorter, longer) = if old.len() <= new.len() { (old, new) } else { (new, old) };
+    if shorter.len() == longer.len() {
+        compare_nodes(old, new, old.root_id(), &mut report, max_reported);
+        if report.differing_count > 0 {
+            report.kind = DiffKind::Changed;
+        }
+        return Ok(report);
+    }
+
+    let prefix = match longer.prove_consistency(shorter.len()) {
+        // trees not padded by duplication have no consistency proofs, so compare their leaves
+        Ok(proof) => proof.verify_with(&longer.hasher(), &shorter.root(), &longer.root()),
+        Err(_) => shorter.leaves() == &longer.leaves()[..shorter.len()],
+    };
+    if prefix {
+        report.kind = if old.len() < new.len() { DiffKind::Extended } else { DiffKind::Truncated };
+        return Ok(report);
+    }
+    let head = TreeConfig::new(LeafMode::PreHashed)
+        .with_hasher(longer.hasher())
+        .with_odd_leaf_policy(longer.odd_leaf_policy())
+        .construct(&longer.leaves()[..shorter.len()])?;
+    compare_nodes(shorter, &head, shorter.root_id(), &mut report, max_reported);
+    report.kind = DiffKind::Diverged;
+    Ok(report)
+}
+
+/// Reads two files of hex hashes, one leaf per line, and compares their trees with `diff_trees`
+///
+/// Fails like `TreeConfig::construct` in `LeafMode::PreHashed` for an empty file or a line that is
+/// not one hash of the configured hasher, spelled as the configured `HexPolicy` accepts.
+pub fn diff_files<P, Q>(old: P, new: Q, options: &DiffOptions) -> Result<DiffReport, LibError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let config = TreeConfig::new(LeafMode::PreHashed).with_hasher(options.hasher);
+    let read = |path: &Path| -> Result<MerkleTree, LibError> {
+        config.construct(&decode_hashes_with(BufReader::new(File::open(path)?), options.hex_policy)?)
+    };
+    diff_trees(&read(old.as_ref())?, &read(new.as_ref())?, options.max_reported)
+}
+
+/// Records the leaves below `id` that differ between two trees of the same size
+fn compare_nodes(a: &MerkleTree, b: &MerkleTree, id: NodeId, report: &mut DiffReport, max_reported: usize) {
+    if a.node(id) == b.node(id) {
+        return;
+    }
+    match a.children(id) {
+        Some((left, right)) => {
+            compare_nodes(a, b, left, report, max_reported);
+            if right != left {
+                compare_nodes(a, b, right, report, max_reported);
+            }
+        }
+        None => {
+            report.differing_count += 1;
+            if report.differing.len() < max_reported {
+                report.differing.push(id.index());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::OddLeafPolicy;
+
+    fn hashes(seeds: &[u32]) -> Vec<Hash> {
+        seeds.iter().map(|seed| HashAlgorithm::Sha256.leaf_hash(&seed.to_be_bytes())).collect()
+    }
+
+    fn tree(seeds: &[u32]) -> MerkleTree {
+        MerkleTree::from_leaf_hashes(hashes(seeds))
+    }
+
+    #[test]
+    fn test_identical_and_changed() {
+        let seeds: Vec<u32> = (0..13).collect();
+        let report = diff_trees(&tree(&seeds), &tree(&seeds), 10).unwrap();
+        assert_eq!((report.kind, report.roots_match, report.differing_count), (DiffKind::Identical, true, 0));
+
+        let mut mutated = seeds.clone();
+        for index in [2, 3, 12] {
+            mutated[index] += 100;
+        }
+        let report = diff_trees(&tree(&seeds), &tree(&mutated), 10).unwrap();
+        assert_eq!((report.kind, report.roots_match), (DiffKind::Changed, false));
+        assert_eq!((report.differing, report.added, report.removed), (vec![2, 3, 12], 0, 0));
+
+        let report = diff_trees(&tree(&seeds), &tree(&mutated), 2).unwrap();
+        assert_eq!((report.differing, report.differing_count), (vec![2, 3], 3));
+    }
+
+    #[test]
+    fn test_prefixes() {
+        let seeds: Vec<u32> = (0..11).collect();
+        let report = diff_trees(&tree(&seeds[..6]), &tree(&seeds), 10).unwrap();
+        assert_eq!((report.kind, report.added, report.removed), (DiffKind::Extended, 5, 0));
+        assert!(report.differing.is_empty());
+        let report = diff_trees(&tree(&seeds), &tree(&seeds[..6]), 10).unwrap();
+        assert_eq!((report.kind, report.added, report.removed), (DiffKind::Truncated, 0, 5));
+
+        // repeating the last leaf keeps the root, but the tree is still one leaf longer
+        let padded = [&seeds[..3], &[2]].concat();
+        let report = diff_trees(&tree(&seeds[..3]), &tree(&padded), 10).unwrap();
+        assert_eq!((report.kind, report.roots_match, report.added), (DiffKind::Extended, true, 1));
+
+        let config = TreeConfig::new(LeafMode::PreHashed).with_odd_leaf_policy(OddLeafPolicy::Promote);
+        let promoted = |seeds: &[u32]| config.construct(&hashes(seeds)).unwrap();
+        let report = diff_trees(&promoted(&seeds[..6]), &promoted(&seeds), 10).unwrap();
+        assert_eq!(report.kind, DiffKind::Extended);
+    }
+
+    #[test]
+    fn test_diverged_and_mismatched() {
+        let seeds: Vec<u32> = (0..9).collect();
+        let mut longer = [&seeds[..], &[50, 51]].concat();
+        longer[4] = 40;
+        let report = diff_trees(&tree(&seeds), &tree(&longer), 10).unwrap();
+        assert_eq!((report.kind, report.differing, report.added), (DiffKind::Diverged, vec![4], 2));
+
+        let other: Vec<u32> = (100..105).collect();
+        let report = diff_trees(&tree(&seeds), &tree(&other), 10).unwrap();
+        assert_eq!((report.kind, report.differing, report.removed), (DiffKind::Diverged, vec![0, 1, 2, 3, 4], 4));
+
+        let sha512 = TreeConfig::default().with_hasher(HashAlgorithm::Sha512).construct(&[vec![1]]).unwrap();
+        assert!(matches!(diff_trees(&tree(&seeds), &sha512, 10), Err(LibError::HasherMismatch { .. })));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::merkel::Data;
+
+/// Builds unambiguous leaf bytes out of several fields
+///
+/// Naively concatenating fields lets different values collide (`"ab" + "c"` and `"a" + "bc"`
+/// both give `"abc"`), which makes two different records share a leaf. Every field pushed here
+/// is written as its length (8 bytes, little-endian) followed by its bytes, so distinct field
+/// sequences always produce distinct leaves. This is the recommended way to build the input of
+/// `MerkleTree::construct` and `MerkleTree::from_items` from structured records.
+///
+/// Readers must know the field order, since types are not recorded. With the `zeroize` feature
+/// the staging buffer is zeroed when the encoder is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct LeafEncoder {
+    buf: Vec<u8>,
+}
+
+impl LeafEncoder {
+    pub fn new() -> Self {
+        LeafEncoder::default()
+    }
+
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn push_str(&mut self, s: &str) -> &mut Self {
+        self.push_bytes(s.as_bytes())
+    }
+
+    /// Pushes the number as an 8-byte little-endian field
+    pub fn push_u64(&mut self, value: u64) -> &mut Self {
+        self.push_bytes(&value.to_le_bytes())
+    }
+
+    /// Returns the encoded leaf, leaving the encoder empty for the next record
+    pub fn finish(&mut self) -> Data {
+        std::mem::take(&mut self.buf)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for LeafEncoder {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::MerkleTree;
+
+    #[test]
+    fn test_naive_concatenation_is_ambiguous() {
+        let naive = |a: &str, b: &str| [a.as_bytes(), b.as_bytes()].concat();
+        assert_eq!(naive("ab", "c"), naive("a", "bc"));
+
+        let first = LeafEncoder::new().push_str("ab").push_str("c").finish();
+        let second = LeafEncoder::new().push_str("a").push_str("bc").finish();
+        assert_ne!(first, second);
+        assert_ne!(
+            MerkleTree::construct(&[first]).root(),
+            MerkleTree::construct(&[second]).root()
+        );
+    }
+
+    #[test]
+    fn test_from_items_with_encoded_records() {
+        let records = [("alice", 10), ("bob", 20), ("carol", 30)];
+        let leaves: Vec<Data> = records
+            .iter()
+            .map(|(name, amount)| LeafEncoder::new().push_str(name).push_u64(*amount).finish())
+            .collect();
+        let tree = MerkleTree::from_items(&leaves);
+        assert_eq!(tree.root(), MerkleTree::construct(&leaves).root());
+        assert!(MerkleTree::verify_proof(&leaves[1], &tree.prove_by_index(1).unwrap(), &tree.root()));
+    }
+
+    #[test]
+    fn test_encoding_layout() {
+        let mut encoder = LeafEncoder::new();
+        let leaf = encoder.push_str("hi").push_u64(258).push_bytes(&[]).finish();
+        assert_eq!(
+            hex::encode(&leaf),
+            concat!("0200000000000000", "6869", "0800000000000000", "0201000000000000", "0000000000000000")
+        );
+        // the field boundaries also separate empty and non-empty fields
+        let shifted = encoder.push_str("").push_str("hi").finish();
+        assert_ne!(shifted, LeafEncoder::new().push_str("hi").push_str("").finish());
+        assert!(encoder.finish().is_empty());
+    }
+}
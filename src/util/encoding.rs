@@ -0,0 +1,226 @@
+//! Text encodings of hashes other than hex, for systems that want roots in their own spelling
+//!
+//! `Base64Url` is the unpadded alphabet of RFC 4648 section 5, as JOSE writes it, `Base58Btc` the
+//! alphabet of Bitcoin addresses, and `Multibase` a base58btc string behind the multibase prefix
+//! `z`. Decoding `Multibase` reads the prefix to pick the base, so `f` hex and `u` base64url
+//! strings decode too. Hex is always lowercase, as the rest of the crate writes it.
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+use super::error::LibError;
+use super::hex_policy::parse_hex_strict;
+use crate::merkel::Hash;
+
+/// The digits of base64url, in order of their values
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// How a hash is spelled as text, see `HashEncoding`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Encoding {
+    #[default]
+    Hex,
+    Base64Url,
+    Base58Btc,
+    Multibase,
+}
+
+impl Encoding {
+    /// Every encoding, the default first
+    pub const ALL: [Encoding; 4] = [Encoding::Hex, Encoding::Base64Url, Encoding::Base58Btc, Encoding::Multibase];
+
+    /// The name of the encoding, such as `base64url`, as serialized and taken by `--encoding`
+    pub fn id(self) -> &'static str {
+        match self {
+            Encoding::Hex => "hex",
+            Encoding::Base64Url => "base64url",
+            Encoding::Base58Btc => "base58btc",
+            Encoding::Multibase => "multibase",
+        }
+    }
+
+    /// The encoding with the given `id`
+    pub fn from_id(id: &str) -> Option<Encoding> {
+        Encoding::ALL.into_iter().find(|encoding| encoding.id() == id)
+    }
+
+    /// The multibase prefix of the encoding, None for `Multibase` itself
+    pub fn multibase_prefix(self) -> Option<char> {
+        match self {
+            Encoding::Hex => Some('f'),
+            Encoding::Base64Url => Some('u'),
+            Encoding::Base58Btc => Some('z'),
+            Encoding::Multibase => None,
+        }
+    }
+
+    /// The encoding a multibase string starting with `prefix` is written in
+    pub fn from_multibase_prefix(prefix: char) -> Option<Encoding> {
+        Encoding::ALL.into_iter().find(|encoding| encoding.multibase_prefix() == Some(prefix))
+    }
+
+    pub(crate) fn is_hex(&self) -> bool {
+        *self == Encoding::Hex
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+/// Encoding and decoding hashes in any `Encoding`, as `Hash::encode` and `Hash::decode`
+pub trait HashEncoding: Sized {
+    fn encode(&self, encoding: Encoding) -> String;
+
+    /// Decodes `text`, which must be spelled exactly as `encode` writes it
+    ///
+    /// Fails with `LibError::InvalidHexString` for bad hex and `LibError::InvalidEncoding` for the
+    /// other encodings. The length is not checked against any hasher.
+    fn decode(encoding: Encoding, text: &str) -> Result<Self, LibError>;
+}
+
+impl HashEncoding for Hash {
+    fn encode(&self, encoding: Encoding) -> String {
+        match encoding {
+            Encoding::Hex => hex::encode(self),
+            Encoding::Base64Url => encode_base64url(self),
+            Encoding::Base58Btc => bs58::encode(self).into_string(),
+            Encoding::Multibase => format!("z{}", bs58::encode(self).into_string()),
+        }
+    }
+
+    fn decode(encoding: Encoding, text: &str) -> Result<Self, LibError> {
+        let invalid = |reason: String| LibError::InvalidEncoding { encoding: encoding.id(), reason };
+        match encoding {
+            Encoding::Hex => parse_hex_strict(text),
+            Encoding::Base64Url => decode_base64url(text).map_err(invalid),
+            Encoding::Base58Btc => bs58::decode(text).into_vec().map_err(|e| invalid(e.to_string())),
+            Encoding::Multibase => {
+                let mut chars = text.chars();
+                let prefix = chars.next().ok_or_else(|| invalid("the string is empty".into()))?;
+                match Encoding::from_multibase_prefix(prefix) {
+                    Some(base) => Hash::decode(base, chars.as_str()),
+                    None => Err(invalid(format!(
+                        "prefix `{}` is not `f` (hex), `u` (base64url) or `z` (base58btc)",
+                        prefix
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+fn encode_base64url(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | ((byte as u32) << (16 - 8 * i)));
+        // a digit for every started six bits: 2, 3 or 4 of them
+        for i in 0..=chunk.len() {
+            text.push(BASE64URL[((bits >> (18 - 6 * i)) & 63) as usize] as char);
+        }
+    }
+    text
+}
+
+/// Decodes unpadded base64url, rejecting unused bits that are not zero so every hash has one spelling
+fn decode_base64url(text: &str) -> Result<Vec<u8>, String> {
+    let mut values = Vec::with_capacity(text.len());
+    for (offset, c) in text.char_indices() {
+        match BASE64URL.iter().position(|&digit| digit as char == c) {
+            Some(value) => values.push(value as u32),
+            None if c == '=' => return Err(format!("padding at offset {}, base64url is written without it", offset)),
+            None => return Err(format!("`{}` at offset {} is not a base64url digit", c, offset)),
+        }
+    }
+    if values.len() % 4 == 1 {
+        return Err(format!("{} digits do not encode a whole number of bytes", values.len()));
+    }
+    let mut bytes = Vec::with_capacity(values.len() * 3 / 4);
+    for chunk in values.chunks(4) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, value)| bits | (value << (18 - 6 * i)));
+        let len = chunk.len() - 1;
+        if bits & (0xff_ffff >> (8 * len)) != 0 {
+            return Err("the unused bits of the last digit are not zero".into());
+        }
+        bytes.extend_from_slice(&bits.to_be_bytes()[1..=len]);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::error::ExitStatus;
+
+    #[test]
+    fn test_round_trips() {
+        for len in 0..=65 {
+            let hash: Hash = (0..len).map(|i| (i * 37 + 11) as u8).collect();
+            for encoding in Encoding::ALL {
+                let text = hash.encode(encoding);
+                assert_eq!(Hash::decode(encoding, &text).unwrap(), hash, "{} of {} bytes", encoding, len);
+            }
+        }
+        for encoding in Encoding::ALL {
+            assert_eq!(Encoding::from_id(encoding.id()), Some(encoding));
+            assert_eq!(serde_json::to_value(encoding).unwrap(), encoding.id());
+        }
+    }
+
+    #[test]
+    fn test_known_spellings() {
+        // the test vectors of RFC 4648
+        let spelled = ["", "Zg", "Zm8", "Zm9v", "Zm9vYg", "Zm9vYmE", "Zm9vYmFy"];
+        for (len, text) in spelled.iter().enumerate() {
+            assert_eq!(b"foobar"[..len].to_vec().encode(Encoding::Base64Url), *text);
+        }
+        assert_eq!([0xfb, 0xff].to_vec().encode(Encoding::Base64Url), "-_8");
+        let hello = b"Hello World!".to_vec();
+        assert_eq!(hello.encode(Encoding::Base58Btc), "2NEpo7TZRRrLZSi2U");
+        assert_eq!(hello.encode(Encoding::Multibase), "z2NEpo7TZRRrLZSi2U");
+        assert_eq!(vec![0, 0, 1].encode(Encoding::Base58Btc), "112");
+    }
+
+    #[test]
+    fn test_multibase_decodes_every_base() {
+        let hash: Hash = (0..32).collect();
+        let spellings: Vec<String> = [Encoding::Hex, Encoding::Base64Url, Encoding::Base58Btc]
+            .into_iter()
+            .map(|base| format!("{}{}", base.multibase_prefix().unwrap(), hash.encode(base)))
+            .collect();
+        assert!(spellings[0].starts_with("f000102") && spellings[1].starts_with("uAAEC"));
+        assert_eq!(spellings[2], hash.encode(Encoding::Multibase));
+        for text in &spellings {
+            assert_eq!(Hash::decode(Encoding::Multibase, text).unwrap(), hash, "{}", text);
+        }
+        // the prefix is not stripped by the other encodings
+        assert!(Hash::decode(Encoding::Hex, &spellings[0]).is_err());
+        assert_ne!(Hash::decode(Encoding::Base58Btc, &spellings[2]).unwrap(), hash);
+    }
+
+    #[test]
+    fn test_rejects_bad_spellings() {
+        let reason = |encoding, text| match Hash::decode(encoding, text) {
+            Err(LibError::InvalidEncoding { reason, .. }) => reason,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(reason(Encoding::Base64Url, "Zm9v+g"), "`+` at offset 4 is not a base64url digit");
+        assert_eq!(reason(Encoding::Base64Url, "Zg=="), "padding at offset 2, base64url is written without it");
+        assert_eq!(reason(Encoding::Base64Url, "Zm9vY"), "5 digits do not encode a whole number of bytes");
+        // `Zh` is `f` with a stray low bit
+        assert_eq!(reason(Encoding::Base64Url, "Zh"), "the unused bits of the last digit are not zero");
+        assert!(reason(Encoding::Base58Btc, "0OIl").contains("invalid"));
+        assert_eq!(reason(Encoding::Multibase, ""), "the string is empty");
+        assert_eq!(reason(Encoding::Multibase, "m"), "prefix `m` is not `f` (hex), `u` (base64url) or `z` (base58btc)");
+        assert!(matches!(Hash::decode(Encoding::Multibase, "fABC"), Err(LibError::InvalidHexString(_))));
+
+        let error = Hash::decode(Encoding::Base64Url, "!").unwrap_err();
+        assert_eq!(error.to_string(), "invalid base64url: `!` at offset 0 is not a base64url digit");
+        assert_eq!((error.code(), error.exit_status()), ("INVALID_ENCODING", ExitStatus::Usage));
+    }
+}
@@ -0,0 +1,282 @@
+use std::process::ExitCode;
+
+use thiserror::Error;
+
+use crate::merkel::{ResolveError, SelfTestError};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum LibError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("line {line}: invalid hex: {source}")]
+    InvalidHex { line: usize, source: hex::FromHexError },
+
+    #[error("line {line}: not valid UTF-8")]
+    InvalidUtf8 { line: usize },
+
+    #[error("invalid hex: {0}")]
+    InvalidHexString(hex::FromHexError),
+
+    #[error("invalid {encoding}: {reason}")]
+    InvalidEncoding { encoding: &'static str, reason: String },
+
+    #[error("expected a {expected}-byte hash, found {found} bytes")]
+    InvalidHashLength { expected: usize, found: usize },
+
+    #[error("invalid format: {0}")]
+    InvalidFormat(String),
+
+    #[error("hasher mismatch in {artifact}: expected `{expected}`, found `{found}`")]
+    HasherMismatch { artifact: &'static str, expected: String, found: String },
+
+    #[error("{artifact} does not match the verification context: its {field} is {found}, expected {expected}")]
+    ContextMismatch { artifact: &'static str, field: &'static str, expected: String, found: String },
+
+    #[error("unknown hasher `{0}`")]
+    UnknownHasher(String),
+
+    #[error("cannot build a merkle tree without leaves")]
+    EmptyInput,
+
+    #[error("leaf {index} is {found} bytes long, expected a {expected}-byte hash")]
+    InvalidLeafLength { index: usize, expected: usize, found: usize },
+
+    #[error("leaf {index} is empty")]
+    EmptyLeaf { index: usize },
+
+    #[error("input is not sorted at index {index}")]
+    UnsortedInput { index: usize },
+
+    #[error("input contains a duplicate leaf at index {index}")]
+    DuplicateLeaf { index: usize },
+
+    #[error("data is present in the tree at index {index}")]
+    LeafPresent { index: usize },
+
+    #[error("leaf {0} is not in the tree")]
+    LeafNotFound(String),
+
+    #[error("leaf {index} was pruned from the tree")]
+    Pruned { index: usize },
+
+    #[error("leaf index {index} is out of range for a tree of {size} leaves")]
+    IndexOutOfRange { index: usize, size: usize },
+
+    #[error("level {level} is above the root of a tree {depth} levels deep")]
+    LevelOutOfRange { level: usize, depth: usize },
+
+    #[error("no candidate roots to verify against")]
+    EmptyRootSet,
+
+    #[error("unsupported format version {found}, supported versions are {supported:?}")]
+    UnsupportedVersion { found: u64, supported: Vec<u64> },
+
+    #[error("chunk size {size} is below the minimum of {min} bytes")]
+    InvalidChunkSize { size: usize, min: usize },
+
+    #[error("memory budget {budget} is below the minimum of {min} bytes")]
+    InvalidMemoryBudget { budget: usize, min: usize },
+
+    #[error("path `{path}` {reason}")]
+    UnportablePath { path: String, reason: &'static str },
+
+    #[error("a proof has at most {max} steps, found {got}")]
+    ProofTooDeep { max: usize, got: usize },
+
+    #[error("{leaves} leaves exceed the limit of {max}")]
+    LimitExceeded { leaves: u64, max: u64 },
+
+    #[error("{0} does not fit in the sizes of this platform")]
+    Overflow(&'static str),
+
+    #[error("the values sum to more than {}", u64::MAX)]
+    SumOverflow,
+
+    #[error("export stopped after {rows} rows: {source}")]
+    ExportInterrupted { rows: usize, source: std::io::Error },
+
+    #[error("epoch {epoch} was not sealed, the log has {sealed} epochs")]
+    UnknownEpoch { epoch: usize, sealed: usize },
+
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+
+    #[error("database error: {0}")]
+    Database(String),
+
+    #[error("the signature does not cover the signed root and leaf count")]
+    BadSignature,
+
+    #[error("the input has root {found} over {found_leaves} leaves, not the signed {expected} over {expected_leaves}")]
+    RootMismatch {
+        expected: String,
+        expected_leaves: u64,
+        found: String,
+        found_leaves: u64,
+    },
+
+    #[error("the entries do not reproduce the leaves of the tree from index {index}")]
+    LeafMismatch { index: usize },
+
+    #[error(transparent)]
+    SelfTest(#[from] SelfTestError),
+
+    #[error(transparent)]
+    Resolve(#[from] ResolveError),
+}
+
+/// Exit statuses of the `merkle` binary, one for each kind of failure
+///
+/// The numbers are stable, so scripts can tell a failed check from a missing leaf or an
+/// unreadable file without parsing messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExitStatus {
+    Success = 0,
+    /// Bad arguments, or input that does not parse
+    Usage = 1,
+    /// A proof, signature, comparison or lint did not pass
+    VerificationFailed = 2,
+    /// The requested leaf, index or epoch is not in the tree
+    NotFound = 3,
+    Io = 4,
+    /// The input is of another format version or hasher than expected
+    FormatMismatch = 5,
+    /// Lines were rejected under `--fail-on-reject`
+    Rejected = 6,
+    /// The input is too large for a limit or for the platform
+    LimitExceeded = 7,
+    /// A hasher gave a wrong digest in the self-test, see `self_test_hashers`
+    SelfTestFailed = 8,
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status as u8)
+    }
+}
+
+impl LibError {
+    /// The exit status of the binary when it fails with this error
+    pub fn exit_status(&self) -> ExitStatus {
+        // no wildcard arm, so a new variant cannot be added without choosing its status
+        match self {
+            LibError::InvalidHex { .. }
+            | LibError::InvalidUtf8 { .. }
+            | LibError::InvalidHexString(_)
+            | LibError::InvalidEncoding { .. }
+            | LibError::InvalidHashLength { .. }
+            | LibError::InvalidFormat(_)
+            | LibError::EmptyInput
+            | LibError::InvalidLeafLength { .. }
+            | LibError::EmptyLeaf { .. }
+            | LibError::UnsortedInput { .. }
+            | LibError::DuplicateLeaf { .. }
+            | LibError::EmptyRootSet
+            | LibError::InvalidChunkSize { .. }
+            | LibError::InvalidMemoryBudget { .. }
+            | LibError::UnportablePath { .. }
+            | LibError::ProofTooDeep { .. }
+            | LibError::InvalidKey(_) => ExitStatus::Usage,
+            LibError::LeafPresent { .. }
+            | LibError::BadSignature
+            | LibError::RootMismatch { .. }
+            | LibError::LeafMismatch { .. } => ExitStatus::VerificationFailed,
+            LibError::LeafNotFound(_)
+            | LibError::Pruned { .. }
+            | LibError::IndexOutOfRange { .. }
+            | LibError::LevelOutOfRange { .. }
+            | LibError::UnknownEpoch { .. } => ExitStatus::NotFound,
+            LibError::Io(_) | LibError::ExportInterrupted { .. } | LibError::Database(_) => ExitStatus::Io,
+            LibError::HasherMismatch { .. }
+            | LibError::ContextMismatch { .. }
+            | LibError::UnknownHasher(_)
+            | LibError::UnsupportedVersion { .. } => ExitStatus::FormatMismatch,
+            LibError::LimitExceeded { .. } | LibError::Overflow(_) | LibError::SumOverflow => ExitStatus::LimitExceeded,
+            LibError::SelfTest(_) => ExitStatus::SelfTestFailed,
+            LibError::Resolve(ResolveError::NotFound { .. }) => ExitStatus::NotFound,
+            LibError::Resolve(ResolveError::Ambiguous { .. }) => ExitStatus::Usage,
+        }
+    }
+
+    /// A stable name for the kind of error, such as `INVALID_HEX`, for machine-readable reports
+    pub fn code(&self) -> &'static str {
+        match self {
+            LibError::Io(_) => "IO",
+            LibError::InvalidHex { .. } | LibError::InvalidHexString(_) => "INVALID_HEX",
+            LibError::InvalidUtf8 { .. } => "INVALID_UTF8",
+            LibError::InvalidEncoding { .. } => "INVALID_ENCODING",
+            LibError::InvalidHashLength { .. } => "INVALID_HASH_LENGTH",
+            LibError::InvalidFormat(_) => "INVALID_FORMAT",
+            LibError::HasherMismatch { .. } => "HASHER_MISMATCH",
+            LibError::ContextMismatch { .. } => "CONTEXT_MISMATCH",
+            LibError::UnknownHasher(_) => "UNKNOWN_HASHER",
+            LibError::EmptyInput => "EMPTY_INPUT",
+            LibError::InvalidLeafLength { .. } => "INVALID_LEAF_LENGTH",
+            LibError::EmptyLeaf { .. } => "EMPTY_LEAF",
+            LibError::UnsortedInput { .. } => "UNSORTED_INPUT",
+            LibError::DuplicateLeaf { .. } => "DUPLICATE_LEAF",
+            LibError::LeafPresent { .. } => "LEAF_PRESENT",
+            LibError::LeafNotFound(_) => "LEAF_NOT_FOUND",
+            LibError::Pruned { .. } => "PRUNED",
+            LibError::IndexOutOfRange { .. } => "INDEX_OUT_OF_RANGE",
+            LibError::LevelOutOfRange { .. } => "LEVEL_OUT_OF_RANGE",
+            LibError::EmptyRootSet => "EMPTY_ROOT_SET",
+            LibError::UnsupportedVersion { .. } => "UNSUPPORTED_VERSION",
+            LibError::InvalidChunkSize { .. } => "INVALID_CHUNK_SIZE",
+            LibError::InvalidMemoryBudget { .. } => "INVALID_MEMORY_BUDGET",
+            LibError::UnportablePath { .. } => "UNPORTABLE_PATH",
+            LibError::ProofTooDeep { .. } => "PROOF_TOO_DEEP",
+            LibError::LimitExceeded { .. } => "LIMIT_EXCEEDED",
+            LibError::Overflow(_) => "OVERFLOW",
+            LibError::SumOverflow => "SUM_OVERFLOW",
+            LibError::ExportInterrupted { .. } => "EXPORT_INTERRUPTED",
+            LibError::UnknownEpoch { .. } => "UNKNOWN_EPOCH",
+            LibError::InvalidKey(_) => "INVALID_KEY",
+            LibError::Database(_) => "DATABASE",
+            LibError::BadSignature => "BAD_SIGNATURE",
+            LibError::RootMismatch { .. } => "ROOT_MISMATCH",
+            LibError::LeafMismatch { .. } => "LEAF_MISMATCH",
+            LibError::SelfTest(_) => "SELF_TEST_FAILED",
+            LibError::Resolve(ResolveError::NotFound { .. }) => "ROOT_NOT_FOUND",
+            LibError::Resolve(ResolveError::Ambiguous { .. }) => "AMBIGUOUS_ROOT",
+        }
+    }
+
+    /// The 1-based input line the error is about, for errors reading line-based files
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            LibError::InvalidHex { line, .. } | LibError::InvalidUtf8 { line } => Some(*line),
+            _ => None,
+        }
+    }
+}
+
+impl From<LibError> for ExitCode {
+    fn from(error: LibError) -> Self {
+        error.exit_status().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_statuses_and_codes() {
+        let invalid = LibError::InvalidHex { line: 17, source: hex::FromHexError::OddLength };
+        let status = (invalid.exit_status(), invalid.code(), invalid.line());
+        assert_eq!(status, (ExitStatus::Usage, "INVALID_HEX", Some(17)));
+        let out_of_range = LibError::IndexOutOfRange { index: 9, size: 4 };
+        assert_eq!((out_of_range.exit_status(), out_of_range.line()), (ExitStatus::NotFound, None));
+        assert_eq!(LibError::LevelOutOfRange { level: 11, depth: 10 }.code(), "LEVEL_OUT_OF_RANGE");
+        assert_eq!(LibError::BadSignature.exit_status(), ExitStatus::VerificationFailed);
+        assert_eq!(LibError::UnknownHasher("md5".into()).exit_status(), ExitStatus::FormatMismatch);
+        let too_deep = LibError::ProofTooDeep { max: 64, got: 65 };
+        assert_eq!((too_deep.exit_status(), too_deep.code()), (ExitStatus::Usage, "PROOF_TOO_DEEP"));
+        assert_eq!(LibError::Io(std::io::ErrorKind::NotFound.into()).exit_status() as u8, 4);
+        assert_eq!(ExitCode::from(LibError::EmptyInput), ExitCode::from(1));
+    }
+}
@@ -0,0 +1,239 @@
+//! Estimates of what building a tree will cost, computed before building it
+//!
+//! The structure of a tree only depends on its number of leaves, so `estimate_build` gives its
+//! depth, node count and number of hashes exactly. Memory is estimated from the size of the hashes
+//! and the input leaves held while building, and time is extrapolated from a `Calibration` that
+//! builds a tree over a small sample of the input.
+
+use serde::Serialize;
+
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+
+use super::error::LibError;
+use super::generate::{decode_hashes, read_data_lines};
+use crate::merkel::{checked_node_count, Data, Hash, LeafMode, MerkleHasher, OddLeafPolicy, TreeConfig};
+
+/// Number of leaves `calibrate` samples by default
+pub const CALIBRATION_SAMPLES: usize = 4096;
+
+/// What building a tree over some input will take, see `estimate_build`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct BuildEstimate {
+    pub leaf_count: u64,
+    /// Number of levels above the leaves
+    pub depth: usize,
+    /// Number of hashes over all levels, leaves included
+    pub nodes: u64,
+    /// Bytes held by the hashes of every level and by the input leaves while building
+    pub est_memory_bytes: u64,
+    /// Number of leaf and node hashes computed, the leaves only being hashed in `LeafMode::HashData`
+    pub est_hashes: u64,
+}
+
+/// Time taken to build a tree over a sample of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Calibration {
+    /// Number of sampled leaves
+    pub leaves: usize,
+    /// Number of hashes the sample tree computed
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+/// Non-blank lines of an input and their total length, see `count_lines`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LineCount {
+    pub lines: u64,
+    /// Bytes of the non-blank lines, without their line endings or surrounding whitespace
+    pub bytes: u64,
+}
+
+impl LineCount {
+    /// Average length of a non-blank line, rounded down
+    pub fn average_len(&self) -> u64 {
+        self.bytes.checked_div(self.lines).unwrap_or(0)
+    }
+}
+
+/// Exact structure and estimated cost of a tree with `leaf_count` leaves built under `config`
+///
+/// `avg_leaf_len` is the average length of the input entries in `LeafMode::HashData`; pre-hashed
+/// entries are as long as the hashes of the configured hasher. Fails with `LibError::EmptyInput`
+/// for no leaves and `LibError::Overflow` if the numbers do not fit in a `u64`.
+pub fn estimate_build(leaf_count: u64, avg_leaf_len: u64, config: &TreeConfig) -> Result<BuildEstimate, LibError> {
+    if leaf_count == 0 {
+        return Err(LibError::EmptyInput);
+    }
+    let hash_len = config.hasher().output_len() as u64;
+    let nodes = checked_node_count(leaf_count)?;
+
+    let (mut len, mut hashes) = (leaf_count, 0u64);
+    let mut depth = 0;
+    while len > 1 {
+        // a lone last node is hashed with itself, or carried up without hashing
+        let lone = if config.odd_leaf_policy() == OddLeafPolicy::Duplicate { len % 2 } else { 0 };
+        hashes += len / 2 + lone;
+        len = len.div_ceil(2);
+        depth += 1;
+    }
+    let input_leaf_len = match config.leaf_mode() {
+        LeafMode::PreHashed => hash_len,
+        LeafMode::HashData => {
+            hashes = hashes.checked_add(leaf_count).ok_or(LibError::Overflow("hash count"))?;
+            avg_leaf_len
+        }
+    };
+
+    let per_node = std::mem::size_of::<Hash>() as u64 + hash_len;
+    let per_input = (std::mem::size_of::<Data>() as u64).checked_add(input_leaf_len);
+    let memory = nodes
+        .checked_mul(per_node)
+        .zip(per_input.and_then(|per_input| leaf_count.checked_mul(per_input)))
+        .and_then(|(tree, input)| tree.checked_add(input))
+        .ok_or(LibError::Overflow("memory estimate"))?;
+    Ok(BuildEstimate {
+        leaf_count,
+        depth,
+        nodes,
+        est_memory_bytes: memory,
+        est_hashes: hashes,
+    })
+}
+
+impl BuildEstimate {
+    /// Time the build should take at the speed of `calibration`, None if the sample computed no hashes
+    pub fn est_duration(&self, calibration: &Calibration) -> Option<Duration> {
+        if calibration.hashes == 0 {
+            return None;
+        }
+        let per_hash = calibration.elapsed.as_secs_f64() / calibration.hashes as f64;
+        Duration::try_from_secs_f64(per_hash * self.est_hashes as f64).ok()
+    }
+}
+
+/// Counts the non-blank lines of an input without decoding them
+pub fn count_lines<R: BufRead>(mut reader: R) -> Result<LineCount, LibError> {
+    let mut count = LineCount::default();
+    let mut buf = Vec::new();
+    while reader.read_until(b'\n', &mut buf)? > 0 {
+        let len = buf.trim_ascii().len() as u64;
+        if len > 0 {
+            count.lines += 1;
+            count.bytes += len;
+        }
+        buf.clear();
+    }
+    Ok(count)
+}
+
+/// Builds a tree over the first `samples` lines of `reader` under `config` and times it
+///
+/// Lines are read like `merkle root` reads them: hex hashes in `LeafMode::PreHashed` and raw
+/// UTF-8 data otherwise. Nothing past the sampled lines is consumed beyond what the reader has
+/// buffered. Fails with `LibError::EmptyInput` if there is nothing to sample.
+pub fn calibrate<R: BufRead>(mut reader: R, config: &TreeConfig, samples: usize) -> Result<Calibration, LibError> {
+    let mut sample = Vec::new();
+    let mut lines = 0;
+    while lines < samples && reader.read_until(b'\n', &mut sample)? > 0 {
+        lines += 1;
+    }
+    let leaves = match config.leaf_mode() {
+        LeafMode::PreHashed => decode_hashes(sample.as_slice())?,
+        LeafMode::HashData => read_data_lines(sample.as_slice())?,
+    };
+
+    let start = Instant::now();
+    let tree = config.construct(&leaves)?;
+    let elapsed = start.elapsed();
+    Ok(Calibration {
+        leaves: tree.len(),
+        hashes: estimate_build(tree.len() as u64, 0, config)?.est_hashes,
+        elapsed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::HashAlgorithm;
+
+    use std::io::{BufReader, Read};
+
+    /// Reader that counts the bytes handed out
+    struct CountingReader<R> {
+        inner: R,
+        consumed: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.consumed += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_structure_is_exact() {
+        let pre_hashed = TreeConfig::new(LeafMode::PreHashed);
+        let cases = [(1, 0, 1, 0), (2, 1, 3, 1), (5, 3, 11, 6), (8, 3, 15, 7), (1000, 10, 2001, 1001)];
+        for (leaves, depth, nodes, hashes) in cases {
+            let estimate = estimate_build(leaves, 0, &pre_hashed).unwrap();
+            assert_eq!((estimate.depth, estimate.nodes, estimate.est_hashes), (depth, nodes, hashes), "{}", leaves);
+            let levels = pre_hashed.construct(&vec![vec![0; 32]; leaves as usize]).unwrap().levels().to_vec();
+            assert_eq!(levels.iter().map(|level| level.len() as u64).sum::<u64>(), nodes);
+        }
+
+        // promoted nodes are carried up without hashing, and raw leaves are hashed too
+        let promote = pre_hashed.with_odd_leaf_policy(OddLeafPolicy::Promote);
+        assert_eq!(estimate_build(5, 0, &promote).unwrap().est_hashes, 4);
+        assert_eq!(estimate_build(5, 10, &TreeConfig::default()).unwrap().est_hashes, 11);
+
+        let estimate = estimate_build(1 << 40, 0, &pre_hashed).unwrap();
+        assert_eq!((estimate.depth, estimate.nodes), (40, (1 << 41) - 1));
+        assert!(matches!(estimate_build(0, 0, &pre_hashed), Err(LibError::EmptyInput)));
+        assert!(matches!(estimate_build(u64::MAX, 0, &pre_hashed), Err(LibError::Overflow(_))));
+    }
+
+    #[test]
+    fn test_memory_follows_the_hash_width() {
+        let (hash, data) = (std::mem::size_of::<Hash>() as u64, std::mem::size_of::<Data>() as u64);
+        let estimate = estimate_build(5, 0, &TreeConfig::new(LeafMode::PreHashed)).unwrap();
+        assert_eq!(estimate.est_memory_bytes, 11 * (hash + 32) + 5 * (data + 32));
+
+        let config = TreeConfig::default().with_hasher(HashAlgorithm::Ripemd160);
+        let estimate = estimate_build(5, 100, &config).unwrap();
+        assert_eq!(estimate.est_memory_bytes, 11 * (hash + 20) + 5 * (data + 100));
+    }
+
+    #[test]
+    fn test_calibration_only_reads_a_sample() {
+        let line = format!("{}\n", "ab".repeat(32));
+        let input = line.repeat(100_000);
+        let mut reader = CountingReader { inner: input.as_bytes(), consumed: 0 };
+
+        let config = TreeConfig::new(LeafMode::PreHashed);
+        let calibration = calibrate(BufReader::new(&mut reader), &config, 1000).unwrap();
+        assert_eq!((calibration.leaves, calibration.hashes), (1000, 1001));
+        assert!(reader.consumed < 1000 * line.len() + 8192, "read {} bytes", reader.consumed);
+
+        let estimate = estimate_build(100_000, 0, &config).unwrap();
+        let slow = Calibration { leaves: 1000, hashes: 1000, elapsed: Duration::from_millis(10) };
+        let duration = estimate.est_duration(&slow).unwrap().as_secs_f64();
+        assert!((duration - 1e-5 * estimate.est_hashes as f64).abs() < 1e-6, "{}", duration);
+        assert_eq!(estimate.est_duration(&Calibration { hashes: 0, ..slow }), None);
+        assert!(matches!(calibrate(&b""[..], &config, 10), Err(LibError::EmptyInput)));
+    }
+
+    #[test]
+    fn test_count_lines() {
+        let count = count_lines("abcd\n\n  ef  \r\nlast".as_bytes()).unwrap();
+        assert_eq!(count, LineCount { lines: 3, bytes: 10 });
+        assert_eq!(count.average_len(), 3);
+        assert_eq!(count_lines(&b""[..]).unwrap().average_len(), 0);
+    }
+}
@@ -0,0 +1,185 @@
+use serde::Serialize;
+
+use std::io::Write;
+use std::ops::Range;
+
+use super::error::LibError;
+use crate::merkel::{Hash, MerkleTree, OwnedProof, SourceId};
+
+/// Number of rows written between flushes of the writer
+pub const EXPORT_FLUSH_EVERY: usize = 1024;
+
+/// Outcome of a successful `export_proofs_jsonl`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ExportSummary {
+    /// Number of lines written
+    pub rows: usize,
+    /// Number of leaves of the exported tree
+    pub leaf_count: usize,
+    pub root: Hash,
+}
+
+/// One line of the export
+#[derive(Serialize)]
+struct ExportRow {
+    index: usize,
+    #[serde(with = "crate::util::hex_serde")]
+    leaf: Hash,
+    proof: OwnedProof,
+    /// Informational only, the proof does not cover it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<SourceId>,
+}
+
+/// Writes one `{"index", "leaf", "proof"}` JSON line for every leaf in `range`
+///
+/// The leaf is the hex leaf hash and the proof is a serialized `OwnedProof`. Rows of a tree built
+/// with `MerkleTree::construct_from_sources` also name the leaf's `"source"`. The writer is
+/// flushed every `EXPORT_FLUSH_EVERY` rows and at the end. An empty range writes nothing and
+/// returns a summary with zero rows; a range reaching past the last leaf fails before anything
+/// is written. If the writer fails, the error reports how many complete rows it accepted.
+pub fn export_proofs_jsonl<W: Write>(
+    tree: &MerkleTree,
+    range: Range<usize>,
+    mut writer: W,
+) -> Result<ExportSummary, LibError> {
+    if range.end > tree.len() && !range.is_empty() {
+        return Err(LibError::IndexOutOfRange {
+            index: range.end - 1,
+            size: tree.len(),
+        });
+    }
+
+    let mut rows = 0;
+    let mut line = Vec::new();
+    for index in range {
+        let row = ExportRow {
+            index,
+            leaf: tree.leaves()[index].clone(),
+            proof: tree.prove_by_index(index)?.into_owned(),
+            source: tree.source_of(index).cloned(),
+        };
+        line.clear();
+        serde_json::to_writer(&mut line, &row).map_err(|e| interrupted(rows)(e.into()))?;
+        line.push(b'\n');
+
+        writer.write_all(&line).map_err(interrupted(rows))?;
+        rows += 1;
+        if rows % EXPORT_FLUSH_EVERY == 0 {
+            writer.flush().map_err(interrupted(rows))?;
+        }
+    }
+    writer.flush().map_err(interrupted(rows))?;
+
+    Ok(ExportSummary {
+        rows,
+        leaf_count: tree.len(),
+        root: tree.root(),
+    })
+}
+
+fn interrupted(rows: usize) -> impl FnOnce(std::io::Error) -> LibError {
+    move |source| LibError::ExportInterrupted { rows, source }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::Data;
+
+    use std::io;
+
+    fn example_tree(n: usize) -> MerkleTree {
+        let data: Vec<Data> = (0..n).map(|i| vec![i as u8]).collect();
+        MerkleTree::construct(&data)
+    }
+
+    /// Parses the export back and checks every proof against the root
+    fn verify_export(bytes: &[u8], root: &Hash) -> Vec<usize> {
+        let text = std::str::from_utf8(bytes).unwrap();
+        text.lines()
+            .map(|line| {
+                let row: serde_json::Value = serde_json::from_str(line).unwrap();
+                let leaf = hex::decode(row["leaf"].as_str().unwrap()).unwrap();
+                let proof: OwnedProof = serde_json::from_value(row["proof"].clone()).unwrap();
+                assert!(MerkleTree::verify_proof_hash(&leaf, &proof.as_proof(), root));
+                row["index"].as_u64().unwrap() as usize
+            })
+            .collect()
+    }
+
+    /// Accepts a fixed number of writes, then fails
+    struct FailingWriter {
+        writes_left: usize,
+        written: Vec<u8>,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.writes_left == 0 {
+                return Err(io::Error::other("disk full"));
+            }
+            self.writes_left -= 1;
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_full_and_partial_ranges() {
+        let tree = example_tree(11);
+        let mut out = Vec::new();
+        let summary = export_proofs_jsonl(&tree, 0..11, &mut out).unwrap();
+        assert_eq!(summary, ExportSummary { rows: 11, leaf_count: 11, root: tree.root() });
+        assert_eq!(verify_export(&out, &tree.root()), (0..11).collect::<Vec<_>>());
+
+        let mut out = Vec::new();
+        assert_eq!(export_proofs_jsonl(&tree, 4..7, &mut out).unwrap().rows, 3);
+        assert_eq!(verify_export(&out, &tree.root()), vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn test_rows_name_their_source() {
+        let sources = vec![("a.txt".to_string(), vec![vec![1], vec![2]]), ("b.txt".to_string(), vec![vec![3]])];
+        let tree = MerkleTree::construct_from_sources(&sources).unwrap();
+        let mut out = Vec::new();
+        export_proofs_jsonl(&tree, 0..3, &mut out).unwrap();
+        assert_eq!(verify_export(&out, &tree.root()), vec![0, 1, 2]);
+        let text = String::from_utf8(out).unwrap();
+        let sources: Vec<serde_json::Value> =
+            text.lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["source"].clone()).collect();
+        assert_eq!(sources, ["a.txt", "a.txt", "b.txt"]);
+
+        let mut out = Vec::new();
+        export_proofs_jsonl(&example_tree(2), 0..2, &mut out).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("source"));
+    }
+
+    #[test]
+    fn test_empty_and_out_of_range() {
+        let tree = example_tree(5);
+        let mut out = Vec::new();
+        assert_eq!(export_proofs_jsonl(&tree, 3..3, &mut out).unwrap().rows, 0);
+        assert!(out.is_empty());
+
+        let err = export_proofs_jsonl(&tree, 2..6, &mut out);
+        assert!(matches!(err, Err(LibError::IndexOutOfRange { index: 5, size: 5 })));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_writer_error_reports_rows_written() {
+        let tree = example_tree(8);
+        let mut writer = FailingWriter { writes_left: 3, written: vec![] };
+        match export_proofs_jsonl(&tree, 0..8, &mut writer) {
+            Err(LibError::ExportInterrupted { rows: 3, source }) => assert_eq!(source.to_string(), "disk full"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(verify_export(&writer.written, &tree.root()), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,213 @@
+//! Merkle trees over the fixed-size chunks of a file
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use super::error::LibError;
+use crate::merkel::{checked_node_count, Hash, MerkleBuilder, MerkleTree, TreeConfig, HASH_LEN};
+
+/// Smallest chunk size accepted by default, so a typo cannot turn every byte into a leaf
+pub const DEFAULT_MIN_CHUNK_SIZE: usize = 1024;
+
+/// Largest number of chunks accepted by default, about 7 GiB of hashes
+pub const DEFAULT_MAX_LEAVES: u64 = 1 << 26;
+
+/// Bounds on how a file may be split into leaves, checked before anything is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLimits {
+    min_chunk_size: usize,
+    max_leaves: u64,
+}
+
+impl Default for ChunkLimits {
+    fn default() -> Self {
+        ChunkLimits {
+            min_chunk_size: DEFAULT_MIN_CHUNK_SIZE,
+            max_leaves: DEFAULT_MAX_LEAVES,
+        }
+    }
+}
+
+impl ChunkLimits {
+    /// Sets the smallest accepted chunk size; a size of 0 is always rejected
+    pub fn with_min_chunk_size(mut self, size: usize) -> Self {
+        self.min_chunk_size = size;
+        self
+    }
+
+    pub fn with_max_leaves(mut self, leaves: u64) -> Self {
+        self.max_leaves = leaves;
+        self
+    }
+
+    pub fn min_chunk_size(&self) -> usize {
+        self.min_chunk_size
+    }
+
+    pub fn max_leaves(&self) -> u64 {
+        self.max_leaves
+    }
+
+    /// Number of leaves a file of `file_len` bytes splits into, if the split is within the limits
+    ///
+    /// Fails with `LibError::InvalidChunkSize` for a chunk size of 0 or below the minimum, and
+    /// `LibError::LimitExceeded` if there would be more leaves than allowed.
+    pub fn check(&self, file_len: u64, chunk_size: usize) -> Result<u64, LibError> {
+        let min = self.min_chunk_size.max(1);
+        if chunk_size < min {
+            return Err(LibError::InvalidChunkSize { size: chunk_size, min });
+        }
+        let leaves = file_len.div_ceil(chunk_size as u64);
+        if leaves > self.max_leaves {
+            return Err(LibError::LimitExceeded {
+                leaves,
+                max: self.max_leaves,
+            });
+        }
+        Ok(leaves)
+    }
+}
+
+/// Rough number of bytes a tree with `leaf_count` leaves keeps in memory for its hashes
+pub fn estimated_tree_bytes(leaf_count: u64) -> Result<u64, LibError> {
+    let per_node = (std::mem::size_of::<Hash>() + HASH_LEN) as u64;
+    checked_node_count(leaf_count)?
+        .checked_mul(per_node)
+        .ok_or(LibError::Overflow("tree size"))
+}
+
+/// A tree whose leaves are the hashes of consecutive `chunk_size` byte chunks of a file
+///
+/// The last chunk holds whatever is left and may be shorter.
+#[derive(Clone)]
+pub struct MerkleFile {
+    tree: MerkleTree,
+    chunk_size: usize,
+    len: u64,
+}
+
+impl MerkleFile {
+    /// Chunks the file at `path` within the default `ChunkLimits`
+    pub fn from_path<P: AsRef<Path>>(path: P, chunk_size: usize) -> Result<MerkleFile, LibError> {
+        MerkleFile::from_path_with_limits(path, chunk_size, &ChunkLimits::default())
+    }
+
+    /// Chunks the file at `path`, checking the limits against its size before reading it
+    ///
+    /// An empty file has no chunks and fails with `LibError::EmptyInput`.
+    pub fn from_path_with_limits<P: AsRef<Path>>(
+        path: P,
+        chunk_size: usize,
+        limits: &ChunkLimits,
+    ) -> Result<MerkleFile, LibError> {
+        let file = File::open(path)?;
+        limits.check(file.metadata()?.len(), chunk_size)?;
+        MerkleFile::from_reader(file, chunk_size, limits)
+    }
+
+    /// Chunks everything `reader` yields, failing as soon as the limits are exceeded
+    ///
+    /// Each chunk is hashed as it is read with `MerkleBuilder::add_leaf_from_reader`, so large
+    /// chunks are not buffered whole.
+    pub fn from_reader<R: Read>(reader: R, chunk_size: usize, limits: &ChunkLimits) -> Result<MerkleFile, LibError> {
+        limits.check(0, chunk_size)?;
+        let (mut reader, mut builder, mut len) = (BufReader::new(reader), MerkleBuilder::new(TreeConfig::default()), 0);
+        while !fill_buf(&mut reader)?.is_empty() {
+            let mut chunk = (&mut reader).take(chunk_size as u64);
+            builder.add_leaf_from_reader(&mut chunk)?;
+            len += chunk_size as u64 - chunk.limit();
+            limits.check(len, chunk_size)?;
+        }
+        Ok(MerkleFile {
+            tree: builder.build()?,
+            chunk_size,
+            len,
+        })
+    }
+
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Number of bytes read from the file
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// The buffered bytes of `reader`, empty only at the end of its input
+fn fill_buf<R: Read>(reader: &mut BufReader<R>) -> Result<&[u8], LibError> {
+    loop {
+        match reader.fill_buf() {
+            Ok(_) => return Ok(reader.buffer()),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::Data;
+
+    const FIXTURE: &str = "tests/fixtures/hashes.txt";
+
+    fn permissive() -> ChunkLimits {
+        ChunkLimits::default().with_min_chunk_size(1)
+    }
+
+    #[test]
+    fn test_rejects_zero_and_tiny_chunks() {
+        let zero = MerkleFile::from_path(FIXTURE, 0);
+        assert!(matches!(zero, Err(LibError::InvalidChunkSize { size: 0, min: 1024 })));
+        let tiny = MerkleFile::from_path(FIXTURE, 16);
+        assert!(matches!(tiny, Err(LibError::InvalidChunkSize { size: 16, min: 1024 })));
+        let zero = MerkleFile::from_path_with_limits(FIXTURE, 0, &permissive());
+        assert!(matches!(zero, Err(LibError::InvalidChunkSize { size: 0, min: 1 })));
+
+        // 455 bytes in single bytes are too many leaves for a limit of 100
+        let limits = permissive().with_max_leaves(100);
+        let tiny = MerkleFile::from_path_with_limits(FIXTURE, 1, &limits);
+        assert!(matches!(tiny, Err(LibError::LimitExceeded { leaves: 455, max: 100 })));
+        let tiny = MerkleFile::from_reader(&[0u8; 455][..], 1, &limits);
+        assert!(matches!(tiny, Err(LibError::LimitExceeded { leaves: 101, max: 100 })));
+    }
+
+    #[test]
+    fn test_sane_chunk_sizes() {
+        let bytes = std::fs::read(FIXTURE).unwrap();
+        assert_eq!(bytes.len(), 455);
+
+        let file = MerkleFile::from_path(FIXTURE, 4096).unwrap();
+        assert_eq!((file.len(), file.tree().len()), (455, 1));
+        assert_eq!(file.root(), MerkleTree::construct(std::slice::from_ref(&bytes)).root());
+
+        let file = MerkleFile::from_path_with_limits(FIXTURE, 100, &permissive()).unwrap();
+        let chunks: Vec<Data> = bytes.chunks(100).map(<[u8]>::to_vec).collect();
+        assert_eq!(file.root(), MerkleTree::construct(&chunks).root());
+        assert_eq!((file.tree().len(), file.chunk_size()), (5, 100));
+
+        assert!(matches!(MerkleFile::from_reader(&[][..], 1024, &permissive()), Err(LibError::EmptyInput)));
+    }
+
+    #[test]
+    fn test_estimated_tree_bytes() {
+        let per_node = (std::mem::size_of::<Hash>() + HASH_LEN) as u64;
+        assert_eq!(estimated_tree_bytes(4).unwrap(), 7 * per_node);
+        assert!(matches!(estimated_tree_bytes(u64::MAX / 2), Err(LibError::Overflow(_))));
+    }
+}
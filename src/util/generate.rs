@@ -0,0 +1,355 @@
+use rand::Rng;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use super::error::LibError;
+use super::hex_policy::{decode_hex, HexPolicy};
+use crate::merkel::{Data, Hash, HASH_LEN};
+
+const CHARSET: &[u8] = b"0123456789abcdef";
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Generates a random lowercase hex string of the given length
+pub fn generate_string(length: usize) -> String {
+    let mut rng = rand::rng();
+    (0..length)
+        .map(|_| CHARSET[rng.random_range(0..CHARSET.len())] as char)
+        .collect()
+}
+
+/// Generates `n` random transaction ids, each 64 lowercase hex characters like a bitcoin txid
+///
+/// The supported way to generate fixtures, behind the `test-utils` feature. The ids only depend
+/// on `rng`, so a seeded one such as `StdRng::seed_from_u64` gives the same ids on every run.
+#[cfg(any(test, feature = "test-utils"))]
+pub fn random_txids(n: usize, rng: &mut impl Rng) -> Vec<String> {
+    (0..n).map(|_| random_hex(64, rng).into_iter().map(char::from).collect()).collect()
+}
+
+/// Generates `n` random leaves, each the bytes of `len` lowercase hex characters
+///
+/// Like `random_txids`, deterministic for a seeded `rng`.
+#[cfg(any(test, feature = "test-utils"))]
+pub fn random_leaves(n: usize, len: usize, rng: &mut impl Rng) -> Vec<Data> {
+    (0..n).map(|_| random_hex(len, rng)).collect()
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+fn random_hex(len: usize, rng: &mut impl Rng) -> Vec<u8> {
+    (0..len).map(|_| CHARSET[rng.random_range(0..CHARSET.len())]).collect()
+}
+
+/// Reads one hex hash per line from the given file
+///
+/// See `read_hashes` for how lines are normalized.
+pub fn read_hashes_from_file<P: AsRef<Path>>(path: P) -> Result<Vec<String>, LibError> {
+    read_hashes(BufReader::new(File::open(path)?))
+}
+
+/// Reads one hex hash per line, returning them as lowercase hex
+///
+/// A leading UTF-8 byte order mark, the line endings (`\n` or `\r\n`) and blank lines are
+/// ignored, and the last line needs no trailing newline. Every other line must be a hash as
+/// `HexPolicy::Strict` spells it, or fails with its 1-based line number and the byte offset of the
+/// first bad character within the line.
+pub fn read_hashes<R: BufRead>(reader: R) -> Result<Vec<String>, LibError> {
+    read_hashes_with(reader, HexPolicy::Strict)
+}
+
+/// Like `read_hashes`, accepting the hex `policy` accepts
+///
+/// Under `HexPolicy::Lenient` uppercase hashes are returned in lowercase.
+pub fn read_hashes_with<R: BufRead>(reader: R, policy: HexPolicy) -> Result<Vec<String>, LibError> {
+    let mut hashes = vec![];
+    for_each_hash_line(reader, policy, |hex, _| {
+        hashes.push(hex.to_string());
+        Ok(())
+    })?;
+    Ok(hashes)
+}
+
+/// Like `read_hashes`, but decodes every line into its bytes
+pub fn decode_hashes<R: BufRead>(reader: R) -> Result<Vec<Hash>, LibError> {
+    decode_hashes_with(reader, HexPolicy::Strict)
+}
+
+/// Like `decode_hashes`, accepting the hex `policy` accepts
+pub fn decode_hashes_with<R: BufRead>(reader: R, policy: HexPolicy) -> Result<Vec<Hash>, LibError> {
+    let mut hashes = vec![];
+    for_each_hash_line(reader, policy, |_, bytes| {
+        hashes.push(bytes);
+        Ok(())
+    })?;
+    Ok(hashes)
+}
+
+/// A line `decode_hashes_lenient` could not decode, set aside instead of failing the whole input
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct RejectedLine {
+    /// 1-based line number
+    pub line: usize,
+    pub reason: RejectReason,
+    /// The line without its line ending, invalid UTF-8 replaced by U+FFFD
+    pub content: String,
+}
+
+/// Why a line was rejected, see `RejectedLine`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum RejectReason {
+    InvalidUtf8,
+    InvalidHex(hex::FromHexError),
+    /// Valid hex, but not as long as the hashes of the tree
+    WrongLength { expected: usize, found: usize },
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectReason::InvalidUtf8 => write!(f, "invalid UTF-8"),
+            RejectReason::InvalidHex(source) => write!(f, "invalid hex: {}", source),
+            RejectReason::WrongLength { expected, found } => write!(f, "expected {} bytes, found {}", expected, found),
+        }
+    }
+}
+
+/// Like `decode_hashes`, but sets invalid lines aside instead of failing on the first one
+///
+/// Lines are normalized the same way, and hashes that are not `HASH_LEN` bytes long are rejected
+/// too. Only reading can still fail.
+pub fn decode_hashes_lenient<R: BufRead>(reader: R) -> Result<(Vec<Hash>, Vec<RejectedLine>), LibError> {
+    decode_hashes_lenient_of_len(reader, HASH_LEN)
+}
+
+/// Like `decode_hashes_lenient`, for hashes of `hash_len` bytes
+pub fn decode_hashes_lenient_of_len<R: BufRead>(
+    reader: R,
+    hash_len: usize,
+) -> Result<(Vec<Hash>, Vec<RejectedLine>), LibError> {
+    decode_hashes_lenient_with(reader, hash_len, HexPolicy::Strict)
+}
+
+/// Like `decode_hashes_lenient_of_len`, accepting the hex `policy` accepts
+///
+/// Lines are set aside either way; `policy` only decides which spellings of a hash are valid.
+pub fn decode_hashes_lenient_with<R: BufRead>(
+    mut reader: R,
+    hash_len: usize,
+    policy: HexPolicy,
+) -> Result<(Vec<Hash>, Vec<RejectedLine>), LibError> {
+    let (mut hashes, mut rejected) = (vec![], vec![]);
+    let mut buf = Vec::new();
+    let mut line = 0;
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok((hashes, rejected));
+        }
+        line += 1;
+
+        let reason = match decode_hash_line(&buf, line, policy) {
+            Ok(None) => continue,
+            Ok(Some((_, bytes))) if bytes.len() == hash_len => {
+                hashes.push(bytes);
+                continue;
+            }
+            Ok(Some((_, bytes))) => RejectReason::WrongLength { expected: hash_len, found: bytes.len() },
+            Err(LibError::InvalidHex { source, .. }) => RejectReason::InvalidHex(source),
+            Err(_) => RejectReason::InvalidUtf8,
+        };
+        let content = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        let content = content.strip_suffix(b"\r").unwrap_or(content);
+        rejected.push(RejectedLine { line, reason, content: String::from_utf8_lossy(content).into_owned() });
+    }
+}
+
+/// Reads one raw UTF-8 string per line as leaf data
+///
+/// Only the line ending (`\n` or `\r\n`) and a leading byte order mark are removed; every other
+/// byte, including surrounding whitespace, is part of the leaf. Blank lines, at the end of the
+/// input too, are empty entries. Lines that are not valid UTF-8 fail with their 1-based line number.
+pub fn read_data_lines<R: BufRead>(mut reader: R) -> Result<Vec<Data>, LibError> {
+    let mut lines = vec![];
+    let mut buf = Vec::new();
+    while reader.read_until(b'\n', &mut buf)? > 0 {
+        let line = lines.len() + 1;
+        let mut bytes = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        if line == 1 {
+            bytes = bytes.strip_prefix(BOM).unwrap_or(bytes);
+        }
+        if std::str::from_utf8(bytes).is_err() {
+            return Err(LibError::InvalidUtf8 { line });
+        }
+        lines.push(bytes.to_vec());
+        buf.clear();
+    }
+    Ok(lines)
+}
+
+/// Calls `f` with the normalized text and decoded bytes of every non-blank line, stopping at the
+/// first error it returns
+pub(crate) fn for_each_hash_line<R, F>(mut reader: R, policy: HexPolicy, mut f: F) -> Result<(), LibError>
+where
+    R: BufRead,
+    F: FnMut(&str, Vec<u8>) -> Result<(), LibError>,
+{
+    let mut buf = Vec::new();
+    let mut line = 0;
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(());
+        }
+        line += 1;
+        if let Some((text, bytes)) = decode_hash_line(&buf, line, policy)? {
+            f(&text, bytes)?;
+        }
+    }
+}
+
+/// Decodes line number `line` under `policy`, None for a blank line
+fn decode_hash_line(buf: &[u8], line: usize, policy: HexPolicy) -> Result<Option<(String, Vec<u8>)>, LibError> {
+    let mut bytes = buf.strip_suffix(b"\n").unwrap_or(buf);
+    bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+    if line == 1 {
+        bytes = bytes.strip_prefix(BOM).unwrap_or(bytes);
+    }
+    let text = std::str::from_utf8(bytes).map_err(|_| LibError::InvalidUtf8 { line })?;
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    let bytes = decode_hex(text, policy).map_err(|source| LibError::InvalidHex { line, source })?;
+    Ok(Some((hex::encode(&bytes), bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASHES: &str = include_str!("../../tests/fixtures/hashes.txt");
+    const HASHES_CRLF_BOM: &[u8] = include_bytes!("../../tests/fixtures/hashes_crlf_bom.txt");
+
+    #[test]
+    fn test_crlf_and_bom_are_normalized() {
+        let expected: Vec<String> = HASHES.lines().map(String::from).collect();
+        let crlf_bom = HASHES_CRLF_BOM.strip_prefix(BOM).unwrap();
+        let lowercase = [BOM, crlf_bom.to_ascii_lowercase().as_slice()].concat();
+        assert_eq!(read_hashes(lowercase.as_slice()).unwrap(), expected);
+        assert_eq!(read_hashes(HASHES.as_bytes()).unwrap(), expected);
+        assert_eq!(read_hashes_with(HASHES_CRLF_BOM, HexPolicy::Lenient).unwrap(), expected);
+        assert_eq!(
+            decode_hashes_with(HASHES_CRLF_BOM, HexPolicy::Lenient).unwrap(),
+            expected.iter().map(|h| hex::decode(h).unwrap()).collect::<Vec<_>>()
+        );
+
+        // the second line of the fixture is uppercase, which only the lenient policy accepts
+        let strict = decode_hashes(HASHES_CRLF_BOM);
+        let bad = hex::FromHexError::InvalidHexCharacter { c: 'B', index: 1 };
+        assert!(matches!(strict, Err(LibError::InvalidHex { line: 2, source }) if source == bad));
+    }
+
+    #[test]
+    fn test_blank_lines_and_missing_newline() {
+        let input = "\n  \r\nabcd\r\n\r\n\t\n0011";
+        assert_eq!(read_hashes(input.as_bytes()).unwrap(), vec!["abcd", "0011"]);
+        assert!(read_hashes(&b""[..]).unwrap().is_empty());
+
+        let padded = "\n  AbCd \r\n\r\n\t0011";
+        assert_eq!(read_hashes_with(padded.as_bytes(), HexPolicy::Lenient).unwrap(), vec!["abcd", "0011"]);
+        let bad = hex::FromHexError::InvalidHexCharacter { c: ' ', index: 0 };
+        let strict = read_hashes(padded.as_bytes());
+        assert!(matches!(strict, Err(LibError::InvalidHex { line: 2, source }) if source == bad));
+    }
+
+    #[test]
+    fn test_invalid_lines_report_line_numbers() {
+        let input = "abcd\r\n00zz\r\n";
+        assert!(matches!(read_hashes(input.as_bytes()), Err(LibError::InvalidHex { line: 2, .. })));
+
+        let odd = "\u{feff}abc\n";
+        let result = decode_hashes(odd.as_bytes());
+        assert!(matches!(result, Err(LibError::InvalidHex { line: 1, source: hex::FromHexError::OddLength })));
+
+        // a byte order mark is only expected at the very start, and offsets count from after it
+        let late_bom = "abcd\n\u{feff}abcd\n";
+        let source = hex::FromHexError::InvalidHexCharacter { c: '\u{feff}', index: 0 };
+        let result = read_hashes_with(late_bom.as_bytes(), HexPolicy::Lenient);
+        assert!(matches!(result, Err(LibError::InvalidHex { line: 2, source: s }) if s == source));
+        let source = hex::FromHexError::InvalidHexCharacter { c: 'z', index: 2 };
+        let result = read_hashes("\u{feff}00zz\n".as_bytes());
+        assert!(matches!(result, Err(LibError::InvalidHex { line: 1, source: s }) if s == source));
+
+        let binary = b"abcd\n\xff\xfe\n";
+        assert!(matches!(read_hashes(&binary[..]), Err(LibError::InvalidUtf8 { line: 2 })));
+    }
+
+    #[test]
+    fn test_lenient_decoding_sets_bad_lines_aside() {
+        let input = b"\xef\xbb\xbfAA\n\n00zz\r\nabc\n\xff\xfe\n0011223344\r\nbb";
+        let (hashes, rejected) = decode_hashes_lenient_with(&input[..], 1, HexPolicy::Lenient).unwrap();
+        assert_eq!(hashes, vec![vec![0xaa], vec![0xbb]]);
+        let lines: Vec<_> = rejected.iter().map(|rejected| (rejected.line, rejected.content.as_str())).collect();
+        assert_eq!(lines, [(3, "00zz"), (4, "abc"), (5, "\u{fffd}\u{fffd}"), (6, "0011223344")]);
+
+        let reasons: Vec<_> = rejected.iter().map(|rejected| rejected.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            [
+                "invalid hex: Invalid character 'z' at position 2",
+                "invalid hex: Odd number of digits",
+                "invalid UTF-8",
+                "expected 1 bytes, found 5",
+            ]
+        );
+
+        // the strict policy also sets the uppercase line aside
+        let (hashes, rejected) = decode_hashes_lenient_of_len(&input[..], 1).unwrap();
+        assert_eq!((hashes, rejected[0].line), (vec![vec![0xbb]], 1));
+        assert_eq!(rejected[0].reason.to_string(), "invalid hex: Invalid character 'A' at position 0");
+
+        // the valid lines decode like `decode_hashes` decodes them
+        let (hashes, rejected) = decode_hashes_lenient_with(HASHES_CRLF_BOM, HASH_LEN, HexPolicy::Lenient).unwrap();
+        assert_eq!((hashes, rejected.len()), (decode_hashes(HASHES.as_bytes()).unwrap(), 0));
+        let (hashes, rejected) = decode_hashes_lenient(HASHES_CRLF_BOM).unwrap();
+        let lines: Vec<usize> = rejected.iter().map(|rejected| rejected.line).collect();
+        assert_eq!((hashes.len(), lines), (4, vec![2, 4, 6]));
+    }
+
+    #[test]
+    fn test_random_txids_are_seeded() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let txids = random_txids(100, &mut StdRng::seed_from_u64(42));
+        assert_eq!(txids, random_txids(100, &mut StdRng::seed_from_u64(42)));
+        assert_ne!(txids, random_txids(100, &mut StdRng::seed_from_u64(43)));
+        assert!(txids.iter().all(|txid| txid.len() == 64 && read_hashes(txid.as_bytes()).unwrap() == [txid.clone()]));
+
+        let leaves = random_leaves(3, 10, &mut StdRng::seed_from_u64(42));
+        assert_eq!(leaves.iter().map(Vec::len).collect::<Vec<_>>(), [10, 10, 10]);
+    }
+
+    #[test]
+    fn test_read_data_lines() {
+        let input = "\u{feff}doc-1\r\n  padded \n\ndoc-1\nlast";
+        let lines = read_data_lines(input.as_bytes()).unwrap();
+        assert_eq!(lines, vec![&b"doc-1"[..], b"  padded ", b"", b"doc-1", b"last"]);
+
+        let binary = b"ok\nstill ok\n\xff\n";
+        assert!(matches!(read_data_lines(&binary[..]), Err(LibError::InvalidUtf8 { line: 3 })));
+    }
+
+    #[test]
+    fn test_read_from_file() {
+        let hashes = read_hashes_from_file("tests/fixtures/hashes.txt").unwrap();
+        assert_eq!(hashes.len(), 7);
+        let uppercase = read_hashes_from_file("tests/fixtures/hashes_crlf_bom.txt");
+        assert!(matches!(uppercase, Err(LibError::InvalidHex { line: 2, .. })));
+        assert!(matches!(read_hashes_from_file("tests/fixtures/missing.txt"), Err(LibError::Io(_))));
+    }
+}
@@ -0,0 +1,195 @@
+//! The registry of golden roots: the root every supported scheme gives for a set of fixed inputs
+//!
+//! Roots are long-lived commitments, so a change to hashing or padding that moves any of them is
+//! a break, however small the refactor behind it. `tests/golden_roots.rs` checks every entry of
+//! the committed registry against the roots this crate builds today, and `regen-goldens`
+//! rewrites the registry where a format change is deliberate. Behind the `test-utils` feature.
+
+use serde::{Deserialize, Serialize};
+
+use std::path::Path;
+
+use super::error::LibError;
+use crate::merkel::{CanonicalOrdering, Data, Hash, HashAlgorithm, LeafMode, MerkleHasher, OddLeafPolicy, TreeConfig};
+
+/// Where the registry is committed, relative to the crate root
+pub const GOLDEN_ROOTS_FILE: &str = "tests/fixtures/golden_roots.json";
+
+/// Leaf counts of the fixture inputs: one leaf, powers of two and odd levels at several depths
+pub const FIXTURE_LEAF_COUNTS: [usize; 6] = [1, 2, 3, 5, 8, 13];
+
+/// Format version of the registry file
+const REGISTRY_VERSION: u64 = 1;
+
+/// Root of one scheme over one fixture input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct GoldenRoot {
+    pub hasher: HashAlgorithm,
+    pub leaf_mode: LeafMode,
+    pub odd_leaf_policy: OddLeafPolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical_ordering: Option<CanonicalOrdering>,
+    /// Name of the input, see `fixture_input`
+    pub fixture: String,
+    #[serde(with = "crate::util::hex_serde")]
+    pub root: Hash,
+}
+
+/// The registry file
+#[derive(Serialize, Deserialize)]
+struct Registry {
+    version: u64,
+    roots: Vec<GoldenRoot>,
+}
+
+impl GoldenRoot {
+    /// The configuration of the scheme
+    pub fn config(&self) -> TreeConfig {
+        let config =
+            TreeConfig::new(self.leaf_mode).with_hasher(self.hasher).with_odd_leaf_policy(self.odd_leaf_policy);
+        match self.canonical_ordering {
+            Some(ordering) => config.with_canonical_ordering(ordering),
+            None => config,
+        }
+    }
+
+    /// The root the scheme gives today for the fixture, failing with `LibError::InvalidFormat`
+    /// for a fixture that is not one of `FIXTURE_LEAF_COUNTS`
+    pub fn current_root(&self) -> Result<Hash, LibError> {
+        let input = fixture_leaf_count(&self.fixture)
+            .map(|leaves| fixture_input(leaves, &self.config()))
+            .ok_or_else(|| LibError::InvalidFormat(format!("unknown fixture `{}`", self.fixture)))?;
+        Ok(self.config().construct(&input)?.root())
+    }
+
+    /// The scheme and fixture, such as `sha256/hash-data/duplicate/by-leaf-hash/leaves-5`
+    pub fn name(&self) -> String {
+        let ordering = match self.canonical_ordering {
+            Some(CanonicalOrdering::ByLeafHash) => "by-leaf-hash",
+            Some(CanonicalOrdering::ByData) => "by-data",
+            None => "arrival",
+        };
+        let policy = match self.odd_leaf_policy {
+            OddLeafPolicy::Duplicate => "duplicate",
+            OddLeafPolicy::Promote => "promote",
+        };
+        format!("{}/{}/{}/{}/{}", self.hasher.id(), self.leaf_mode.id(), policy, ordering, self.fixture)
+    }
+}
+
+/// Name of the fixture input of `leaves` leaves
+pub fn fixture_name(leaves: usize) -> String {
+    format!("leaves-{}", leaves)
+}
+
+fn fixture_leaf_count(name: &str) -> Option<usize> {
+    let leaves = name.strip_prefix("leaves-")?.parse().ok()?;
+    FIXTURE_LEAF_COUNTS.contains(&leaves).then_some(leaves)
+}
+
+/// The entries of the fixture input of `leaves` leaves under `config`
+///
+/// Entries are short text for raw-data mode and hash-wide byte patterns for pre-hashed mode, in
+/// neither order so canonical orderings move them. Neither is derived from a hasher, so a change
+/// of hashing cannot also change the input it is checked on.
+pub fn fixture_input(leaves: usize, config: &TreeConfig) -> Vec<Data> {
+    let width = config.hasher().output_len();
+    (0..leaves)
+        .map(|i| {
+            let label = (i * 37 + 53) % 101;
+            match config.leaf_mode() {
+                LeafMode::PreHashed => (0..width).map(|j| (label * 31 + j * 5) as u8).collect(),
+                LeafMode::HashData => format!("leaf {}", label).into_bytes(),
+            }
+        })
+        .collect()
+}
+
+/// Every supported scheme over every fixture input, with the roots this crate gives them today
+///
+/// The schemes are each hasher in each leaf mode, under each odd leaf policy, with and without
+/// each canonical ordering.
+pub fn golden_roots() -> Result<Vec<GoldenRoot>, LibError> {
+    let mut roots = vec![];
+    for hasher in HashAlgorithm::ALL {
+        for leaf_mode in [LeafMode::HashData, LeafMode::PreHashed] {
+            for odd_leaf_policy in [OddLeafPolicy::Duplicate, OddLeafPolicy::Promote] {
+                let orderings = [None, Some(CanonicalOrdering::ByLeafHash), Some(CanonicalOrdering::ByData)];
+                for canonical_ordering in orderings {
+                    for leaves in FIXTURE_LEAF_COUNTS {
+                        let mut golden = GoldenRoot {
+                            hasher,
+                            leaf_mode,
+                            odd_leaf_policy,
+                            canonical_ordering,
+                            fixture: fixture_name(leaves),
+                            root: vec![],
+                        };
+                        golden.root = golden.current_root()?;
+                        roots.push(golden);
+                    }
+                }
+            }
+        }
+    }
+    Ok(roots)
+}
+
+/// Reads a registry written by `write_golden_roots`
+pub fn read_golden_roots<P: AsRef<Path>>(path: P) -> Result<Vec<GoldenRoot>, LibError> {
+    let registry: Registry = serde_json::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|e| LibError::InvalidFormat(format!("golden roots: {}", e)))?;
+    if registry.version != REGISTRY_VERSION {
+        return Err(LibError::UnsupportedVersion { found: registry.version, supported: vec![REGISTRY_VERSION] });
+    }
+    Ok(registry.roots)
+}
+
+/// Writes `roots` as the registry at `path`, one entry per line
+pub fn write_golden_roots<P: AsRef<Path>>(path: P, roots: &[GoldenRoot]) -> Result<(), LibError> {
+    let mut json = format!("{{\n  \"version\": {},\n  \"roots\": [\n", REGISTRY_VERSION);
+    for (i, root) in roots.iter().enumerate() {
+        let line = serde_json::to_string(root).map_err(|e| LibError::InvalidFormat(e.to_string()))?;
+        let separator = if i + 1 == roots.len() { "" } else { "," };
+        json.push_str(&format!("    {}{}\n", line, separator));
+    }
+    json.push_str("  ]\n}\n");
+    Ok(super::io::write_file_atomic(path, json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_round_trip() {
+        let roots = golden_roots().unwrap();
+        assert_eq!(roots.len(), HashAlgorithm::ALL.len() * 2 * 2 * 3 * FIXTURE_LEAF_COUNTS.len());
+        let path = std::env::temp_dir().join(format!("merkle-golden-{}", std::process::id()));
+        write_golden_roots(&path, &roots).unwrap();
+        assert_eq!(read_golden_roots(&path).unwrap(), roots);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut unknown = roots[0].clone();
+        unknown.fixture = "leaves-4".into();
+        assert!(matches!(unknown.current_root(), Err(LibError::InvalidFormat(_))));
+        assert_eq!(roots[0].name(), "sha256/hash-data/duplicate/arrival/leaves-1");
+    }
+
+    #[test]
+    fn test_fixtures_are_unordered() {
+        for leaves in FIXTURE_LEAF_COUNTS {
+            for mode in [LeafMode::HashData, LeafMode::PreHashed] {
+                let config = TreeConfig::new(mode).with_hasher(HashAlgorithm::Ripemd160);
+                let mut input = fixture_input(leaves, &config);
+                assert!(input.iter().all(|entry| mode == LeafMode::HashData || entry.len() == 20));
+                input.dedup();
+                assert_eq!(input.len(), leaves);
+                if leaves > 2 {
+                    assert!(!input.is_sorted(), "{} leaves", leaves);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use super::error::LibError;
+use crate::merkel::Hash;
+
+/// Which spellings of hex are accepted from hash files and command-line arguments
+///
+/// `Strict`, the default, only accepts what this crate writes: an even number of lowercase hex
+/// digits and nothing around them. `Lenient` also accepts uppercase digits and surrounding
+/// whitespace, as some other producers write them. Odd lengths and characters that are not hex
+/// digits are rejected under both, with the byte offset of the first bad character.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HexPolicy {
+    #[default]
+    Strict,
+    Lenient,
+}
+
+impl HexPolicy {
+    /// Decodes `text` under this policy
+    pub fn parse(self, text: &str) -> Result<Vec<u8>, LibError> {
+        decode_hex(text, self).map_err(LibError::InvalidHexString)
+    }
+
+    /// Like `parse`, for a hash that must be exactly `len` bytes long
+    pub fn parse_hash(self, text: &str, len: usize) -> Result<Hash, LibError> {
+        let hash = self.parse(text)?;
+        if hash.len() != len {
+            return Err(LibError::InvalidHashLength { expected: len, found: hash.len() });
+        }
+        Ok(hash)
+    }
+}
+
+/// Decodes `text` under `HexPolicy::Strict`
+pub fn parse_hex_strict(text: &str) -> Result<Vec<u8>, LibError> {
+    HexPolicy::Strict.parse(text)
+}
+
+/// Decodes `text` under `HexPolicy::Lenient`, ignoring case and surrounding whitespace
+///
+/// Offsets in errors count from the start of `text`, leading whitespace included.
+pub fn parse_hex_lenient(text: &str) -> Result<Vec<u8>, LibError> {
+    HexPolicy::Lenient.parse(text)
+}
+
+/// Decodes `text` under `policy`, reporting a bad character before an odd length
+///
+/// The `index` of `FromHexError::InvalidHexCharacter` is the byte offset of the character in `text`.
+pub(crate) fn decode_hex(text: &str, policy: HexPolicy) -> Result<Vec<u8>, hex::FromHexError> {
+    let (offset, digits) = match policy {
+        HexPolicy::Strict => (0, text),
+        HexPolicy::Lenient => {
+            let trimmed = text.trim_start();
+            (text.len() - trimmed.len(), trimmed.trim_end())
+        }
+    };
+    let valid = |c: char| match policy {
+        HexPolicy::Strict => c.is_ascii_digit() || ('a'..='f').contains(&c),
+        HexPolicy::Lenient => c.is_ascii_hexdigit(),
+    };
+    if let Some((index, c)) = digits.char_indices().find(|&(_, c)| !valid(c)) {
+        return Err(hex::FromHexError::InvalidHexCharacter { c, index: offset + index });
+    }
+    hex::decode(digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bad_character(result: Result<Vec<u8>, LibError>) -> (char, usize) {
+        match result {
+            Err(LibError::InvalidHexString(hex::FromHexError::InvalidHexCharacter { c, index })) => (c, index),
+            other => panic!("expected a bad character, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_strict_accepts_only_lowercase_digits() {
+        assert_eq!(parse_hex_strict("00ff1a").unwrap(), [0x00, 0xff, 0x1a]);
+        assert_eq!(parse_hex_strict("").unwrap(), Vec::<u8>::new());
+
+        assert_eq!(bad_character(parse_hex_strict("00Ff")), ('F', 2));
+        assert_eq!(bad_character(parse_hex_strict(" 00ff")), (' ', 0));
+        assert_eq!(bad_character(parse_hex_strict("00ff\n")), ('\n', 4));
+        assert_eq!(bad_character(parse_hex_strict("0x00")), ('x', 1));
+        assert!(matches!(parse_hex_strict("abc"), Err(LibError::InvalidHexString(hex::FromHexError::OddLength))));
+    }
+
+    #[test]
+    fn test_lenient_normalizes_case_and_whitespace() {
+        for text in ["abcdef", "ABCDEF", "AbCdEf", "  abcdef", "abcdef\r\n", "\t ABCdef \n"] {
+            assert_eq!(parse_hex_lenient(text).unwrap(), [0xab, 0xcd, 0xef], "{:?}", text);
+        }
+        assert_eq!(parse_hex_lenient("   ").unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_lenient_rejections_report_offsets() {
+        // offsets count the leading whitespace and bytes, not characters
+        assert_eq!(bad_character(parse_hex_lenient("  0g")), ('g', 3));
+        assert_eq!(bad_character(parse_hex_lenient("ab cd")), (' ', 2));
+        assert_eq!(bad_character(parse_hex_lenient("abé0")), ('é', 2));
+        assert_eq!(bad_character(parse_hex_lenient("é0ZZ")), ('é', 0));
+        assert_eq!(bad_character(parse_hex_lenient(" \u{feff}00")), ('\u{feff}', 1));
+        // a bad character is reported before an odd length
+        assert_eq!(bad_character(parse_hex_lenient("abcz1")), ('z', 3));
+        assert!(matches!(parse_hex_lenient(" ABC "), Err(LibError::InvalidHexString(hex::FromHexError::OddLength))));
+        let message = parse_hex_lenient(" 0g").unwrap_err().to_string();
+        assert_eq!(message, "invalid hex: Invalid character 'g' at position 2");
+    }
+
+    #[test]
+    fn test_parse_hash_checks_the_width() {
+        let hex = "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d";
+        assert_eq!(HexPolicy::Strict.parse_hash(hex, 32).unwrap(), hex::decode(hex).unwrap());
+        let upper = hex.to_ascii_uppercase();
+        assert!(matches!(HexPolicy::Strict.parse_hash(&upper, 32), Err(LibError::InvalidHexString(_))));
+        assert_eq!(HexPolicy::Lenient.parse_hash(&upper, 32).unwrap(), hex::decode(hex).unwrap());
+
+        let short = HexPolicy::Strict.parse_hash(&hex[..40], 32);
+        assert!(matches!(short, Err(LibError::InvalidHashLength { expected: 32, found: 20 })));
+    }
+}
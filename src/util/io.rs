@@ -0,0 +1,178 @@
+//! Writes that leave a file either as it was or fully written, never truncated
+//!
+//! A file is written to a temporary file beside it, synced and then renamed over it, so a crash
+//! mid-write cannot leave a shorter file that still parses, such as a hash file missing its last
+//! lines and so giving another root. A crash before the rename leaves the temporary file behind;
+//! commands remove it with `remove_orphaned_temp` when they start.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Ending of the temporary file `write_atomic` writes next to its destination
+pub const TEMP_SUFFIX: &str = ".merkle-tmp";
+
+/// The temporary file of writes to `path`: `.<name>.merkle-tmp` in the same directory, so the
+/// rename stays on one file system
+pub fn temp_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or(path.as_os_str()));
+    name.push(TEMP_SUFFIX);
+    path.with_file_name(name)
+}
+
+/// Writes `path` with `write`, replacing it only once everything was written and synced
+///
+/// `write` is handed the temporary file, truncated; an error from it or from syncing leaves
+/// `path` as it was and removes the temporary file. Two writers of one destination at the same
+/// time share the temporary file and must not be run.
+pub fn write_atomic<P, T, E, F>(path: P, write: F) -> Result<T, E>
+where
+    P: AsRef<Path>,
+    E: From<io::Error>,
+    F: FnOnce(&mut File) -> Result<T, E>,
+{
+    write_atomic_with(path.as_ref(), write, |_| Ok(()))
+}
+
+/// Like `write_atomic`, running `before_rename` on the synced temporary file just before the
+/// rename, where a crash shows whether the destination is safe
+fn write_atomic_with<T, E, F, H>(path: &Path, write: F, before_rename: H) -> Result<T, E>
+where
+    E: From<io::Error>,
+    F: FnOnce(&mut File) -> Result<T, E>,
+    H: FnOnce(&Path) -> io::Result<()>,
+{
+    let temp = temp_path(path);
+    let written = File::create(&temp).map_err(E::from).and_then(|mut file| {
+        let value = write(&mut file)?;
+        file.sync_all()?;
+        Ok(value)
+    });
+    let renamed = written.and_then(|value| {
+        before_rename(&temp)?;
+        fs::rename(&temp, path)?;
+        Ok(value)
+    });
+    match renamed {
+        Ok(value) => {
+            sync_parent(path)?;
+            Ok(value)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+/// `write_atomic` of a whole buffer, like `fs::write`
+pub fn write_file_atomic<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> io::Result<()> {
+    write_atomic(path, |file| io::Write::write_all(file, contents.as_ref()))
+}
+
+/// Removes the temporary file a crashed write to `path` left behind, returning whether there
+/// was one
+pub fn remove_orphaned_temp<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    match fs::remove_file(temp_path(path)) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Syncs the directory of `path`, so the rename itself survives a crash
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+/// Directories cannot be opened to sync them here
+#[cfg(not(unix))]
+fn sync_parent(_: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::error::LibError;
+    use crate::util::generate::read_hashes_from_file;
+
+    use std::io::Write;
+    use std::panic::{self, AssertUnwindSafe};
+
+    const HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("merkle-io-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn hash_lines(count: usize) -> String {
+        format!("{}\n", HASH).repeat(count)
+    }
+
+    #[test]
+    fn test_failure_before_rename_keeps_the_destination() {
+        let dir = temp_dir("failure");
+        let path = dir.join("ts_hashes.json");
+
+        // a destination that did not exist stays absent
+        let failed = write_atomic_with(
+            &path,
+            |file| file.write_all(hash_lines(4).as_bytes()),
+            |temp| {
+                assert_eq!(fs::read_to_string(temp).unwrap(), hash_lines(4));
+                Err(io::ErrorKind::Interrupted.into())
+            },
+        );
+        assert_eq!(failed.unwrap_err().kind(), io::ErrorKind::Interrupted);
+        assert!(!path.exists() && !temp_path(&path).exists());
+
+        // one that did is either the old file or the new one, in full
+        write_file_atomic(&path, hash_lines(4)).unwrap();
+        let failed: Result<(), LibError> = write_atomic_with(
+            &path,
+            |file| {
+                file.write_all(HASH.as_bytes())?;
+                Err(LibError::InvalidFormat("stopped after one line".into()))
+            },
+            |_| unreachable!(),
+        );
+        assert!(failed.is_err());
+        assert_eq!(read_hashes_from_file(&path).unwrap().len(), 4);
+        write_file_atomic(&path, hash_lines(6)).unwrap();
+        assert_eq!(read_hashes_from_file(&path).unwrap().len(), 6);
+        assert!(!temp_path(&path).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_crash_leaves_an_orphan_that_is_removed() {
+        let dir = temp_dir("crash");
+        let path = dir.join("tree.mk");
+        write_file_atomic(&path, hash_lines(3)).unwrap();
+
+        // a panic stands in for a crash between the write and the rename
+        let crashed = panic::catch_unwind(AssertUnwindSafe(|| {
+            write_atomic_with(&path, |file| file.write_all(&hash_lines(8).as_bytes()[..100]), |_| panic!("crash"))
+        }));
+        assert!(crashed.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), hash_lines(3));
+        assert!(temp_path(&path).exists());
+        assert!(remove_orphaned_temp(&path).unwrap());
+        assert!(!remove_orphaned_temp(&path).unwrap());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        assert_eq!(temp_path("out/tree.mk"), Path::new("out/.tree.mk.merkle-tmp"));
+        assert_eq!(temp_path("tree.mk"), Path::new(".tree.mk.merkle-tmp"));
+        assert!(write_file_atomic(dir.join("missing").join("tree.mk"), "").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,392 @@
+//! An append-only file of leaf hashes, replayed after a restart to rebuild the same tree
+//!
+//! The file starts with a header holding a magic tag, the format version, the hasher id and the
+//! odd leaf policy. One record per leaf follows: the length of the hash as a big-endian `u32`, the
+//! hash, and a big-endian CRC-32 of the whole file up to and including that hash. Each checksum
+//! carries on from the one before it, so a record copied from elsewhere in the file fails too.
+//!
+//! A writer killed mid-record leaves a torn last record: shorter than a record, or complete but
+//! failing its checksum. Recovery drops it, truncating the file back to the last whole record. A
+//! bad record followed by others is corruption rather than a torn write and fails to recover.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+use super::error::LibError;
+use crate::merkel::{Data, Hash, HashAlgorithm, MerkleHasher, MerkleTree, OddLeafPolicy, TreeSnapshot};
+
+/// First bytes of every journal
+pub const JOURNAL_MAGIC: &[u8; 8] = b"MRKLJRNL";
+
+const JOURNAL_VERSION: u8 = 1;
+
+/// A `TreeSnapshot` whose every leaf is appended to a journal file, see the module docs
+///
+/// Records are buffered: `sync` writes them out and flushes them to disk, and only leaves pushed
+/// before the last `sync` are sure to survive a crash. Dropping the tree writes out the buffer
+/// without syncing it.
+#[derive(Debug)]
+pub struct JournaledTree {
+    out: BufWriter<File>,
+    tree: Option<TreeSnapshot>,
+    hasher: HashAlgorithm,
+    policy: OddLeafPolicy,
+    crc: u32,
+    truncated_bytes: u64,
+}
+
+impl JournaledTree {
+    /// Creates a journal at `path` for a tree without leaves, failing if the file exists
+    pub fn create<P: AsRef<Path>>(path: P, hasher: HashAlgorithm, policy: OddLeafPolicy) -> Result<Self, LibError> {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+        let header = header_bytes(hasher, policy);
+        file.write_all(&header)?;
+        file.sync_all()?;
+        Ok(JournaledTree {
+            out: BufWriter::new(file),
+            tree: None,
+            hasher,
+            policy,
+            crc: crc32(0, &header),
+            truncated_bytes: 0,
+        })
+    }
+
+    /// Opens the journal at `path`, replaying every whole record and dropping a torn last one
+    ///
+    /// Fails with `LibError::InvalidFormat` for a file that is not a journal or has a bad record
+    /// before its last, and `LibError::UnknownHasher` for a hasher this build does not know.
+    pub fn recover<P: AsRef<Path>>(path: P) -> Result<Self, LibError> {
+        let replayed = replay(path.as_ref())?;
+        let tree = (!replayed.leaves.is_empty())
+            .then(|| MerkleTree::build(replayed.leaves, replayed.policy, replayed.hasher).snapshot());
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(JournaledTree {
+            out: BufWriter::new(file),
+            tree,
+            hasher: replayed.hasher,
+            policy: replayed.policy,
+            crc: replayed.crc,
+            truncated_bytes: replayed.truncated_bytes,
+        })
+    }
+
+    /// Hashes `data` as a new last leaf, appends it to the journal and returns the leaf hash
+    pub fn push(&mut self, data: &Data) -> Result<Hash, LibError> {
+        let leaf_hash = self.hasher.leaf_hash(data);
+        let len = u32::try_from(leaf_hash.len()).map_err(|_| LibError::Overflow("journal record"))?;
+        let mut record = len.to_be_bytes().to_vec();
+        record.extend_from_slice(&leaf_hash);
+        let crc = crc32(self.crc, &record);
+        record.extend_from_slice(&crc.to_be_bytes());
+        self.out.write_all(&record)?;
+        self.crc = crc;
+        self.tree = Some(match &self.tree {
+            Some(tree) => tree.push_leaf_hash(leaf_hash.clone()),
+            None => MerkleTree::build(vec![leaf_hash.clone()], self.policy, self.hasher).snapshot(),
+        });
+        Ok(leaf_hash)
+    }
+
+    /// Writes out the buffered records and flushes the journal to disk
+    pub fn sync(&mut self) -> Result<(), LibError> {
+        self.out.flush()?;
+        self.out.get_ref().sync_data()?;
+        Ok(())
+    }
+
+    /// Root of the leaves pushed so far, `None` before the first
+    pub fn root(&self) -> Option<Hash> {
+        self.tree.as_ref().map(TreeSnapshot::root)
+    }
+
+    /// The tree of the leaves pushed so far, for proofs
+    pub fn snapshot(&self) -> Option<&TreeSnapshot> {
+        self.tree.as_ref()
+    }
+
+    /// Number of leaves pushed so far, those replayed by `recover` included
+    pub fn len(&self) -> usize {
+        self.tree.as_ref().map_or(0, TreeSnapshot::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn hasher(&self) -> HashAlgorithm {
+        self.hasher
+    }
+
+    pub fn odd_leaf_policy(&self) -> OddLeafPolicy {
+        self.policy
+    }
+
+    /// Bytes of a torn last record that `recover` cut from the file, 0 if there was none
+    pub fn truncated_bytes(&self) -> u64 {
+        self.truncated_bytes
+    }
+}
+
+impl MerkleTree {
+    /// Rebuilds the tree of the leaves in the journal at `path`, as `JournaledTree::recover` does
+    ///
+    /// A torn last record is cut from the file. Fails as `JournaledTree::recover` does, or with
+    /// `LibError::EmptyInput` for a journal without leaves.
+    pub fn recover_from_journal<P: AsRef<Path>>(path: P) -> Result<MerkleTree, LibError> {
+        let replayed = replay(path.as_ref())?;
+        if replayed.leaves.is_empty() {
+            return Err(LibError::EmptyInput);
+        }
+        Ok(MerkleTree::build(replayed.leaves, replayed.policy, replayed.hasher))
+    }
+}
+
+/// What reading a journal found
+struct Replayed {
+    hasher: HashAlgorithm,
+    policy: OddLeafPolicy,
+    leaves: Vec<Hash>,
+    /// Checksum of the last whole record, or of the header without records
+    crc: u32,
+    truncated_bytes: u64,
+}
+
+/// Reads every whole record of the journal at `path`, truncating the file after the last one
+fn replay(path: &Path) -> Result<Replayed, LibError> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    let (hasher, policy, header_len) = parse_header(&bytes)?;
+    let width = hasher.output_len();
+    let record_len = 4 + width + 4;
+
+    let mut crc = crc32(0, &bytes[..header_len]);
+    let mut leaves = vec![];
+    let mut offset = header_len;
+    while offset < bytes.len() {
+        let Some(record) = bytes.get(offset..offset + record_len) else {
+            // shorter than a record, so cut off mid-write
+            break;
+        };
+        let (body, stored) = record.split_at(4 + width);
+        let next = crc32(crc, body);
+        let whole = body[..4] == (width as u32).to_be_bytes() && stored == next.to_be_bytes();
+        if !whole {
+            if offset + record_len == bytes.len() {
+                break;
+            }
+            return Err(invalid(&format!("record {} at offset {} is corrupt", leaves.len(), offset)));
+        }
+        leaves.push(body[4..].to_vec());
+        crc = next;
+        offset += record_len;
+    }
+
+    let truncated_bytes = (bytes.len() - offset) as u64;
+    if truncated_bytes > 0 {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(offset as u64)?;
+        file.sync_all()?;
+    }
+    Ok(Replayed { hasher, policy, leaves, crc, truncated_bytes })
+}
+
+fn header_bytes(hasher: HashAlgorithm, policy: OddLeafPolicy) -> Vec<u8> {
+    let id = hasher.id().as_bytes();
+    let mut header = JOURNAL_MAGIC.to_vec();
+    // hasher ids are short names
+    header.extend_from_slice(&[JOURNAL_VERSION, id.len() as u8]);
+    header.extend_from_slice(id);
+    header.push(match policy {
+        OddLeafPolicy::Duplicate => 0,
+        OddLeafPolicy::Promote => 1,
+    });
+    header
+}
+
+/// The hasher and odd leaf policy of a journal, and the length of its header
+fn parse_header(bytes: &[u8]) -> Result<(HashAlgorithm, OddLeafPolicy, usize), LibError> {
+    let truncated = || invalid("truncated header");
+    if bytes.get(..8).ok_or_else(truncated)? != JOURNAL_MAGIC {
+        return Err(invalid("not a leaf journal"));
+    }
+    let [version, id_len] = bytes.get(8..10).ok_or_else(truncated)? else {
+        return Err(truncated());
+    };
+    if *version != JOURNAL_VERSION {
+        return Err(invalid(&format!("unknown version {}", version)));
+    }
+    let id_end = 10 + usize::from(*id_len);
+    let id = bytes.get(10..id_end).ok_or_else(truncated)?;
+    let id = std::str::from_utf8(id).map_err(|_| invalid("the hasher id is not UTF-8"))?;
+    let hasher = HashAlgorithm::from_id(id).ok_or_else(|| LibError::UnknownHasher(id.to_string()))?;
+    let policy = match bytes.get(id_end).ok_or_else(truncated)? {
+        0 => OddLeafPolicy::Duplicate,
+        1 => OddLeafPolicy::Promote,
+        other => return Err(invalid(&format!("unknown odd leaf policy {}", other))),
+    };
+    Ok((hasher, policy, id_end + 1))
+}
+
+/// CRC-32 (IEEE) of bytes following ones whose CRC-32 is `crc`, 0 for none
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn invalid(message: &str) -> LibError {
+    LibError::InvalidFormat(format!("leaf journal: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_journal(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("merkle-journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn example_data(n: usize) -> Vec<Data> {
+        (0..n).map(|i| format!("leaf {}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(0, b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xcbf4_3926);
+        assert_eq!(crc32(0, b""), 0);
+    }
+
+    #[test]
+    fn test_recovers_the_same_tree() {
+        let path = temp_journal("same");
+        let data = example_data(1000);
+        let mut journal = JournaledTree::create(&path, HashAlgorithm::Sha256, OddLeafPolicy::Duplicate).unwrap();
+        assert_eq!((journal.root(), journal.len()), (None, 0));
+        for entry in &data[..600] {
+            journal.push(entry).unwrap();
+        }
+        journal.sync().unwrap();
+        drop(journal);
+
+        let mut journal = JournaledTree::recover(&path).unwrap();
+        assert_eq!((journal.len(), journal.truncated_bytes()), (600, 0));
+        assert_eq!(journal.root(), Some(MerkleTree::construct(&data[..600]).root()));
+        for entry in &data[600..] {
+            journal.push(entry).unwrap();
+        }
+        drop(journal);
+        let tree = MerkleTree::construct(&data);
+        assert_eq!(MerkleTree::recover_from_journal(&path).unwrap().root(), tree.root());
+        let recovered = JournaledTree::recover(&path).unwrap();
+        let proof = recovered.snapshot().unwrap().prove_by_index(999).unwrap().into_owned();
+        assert_eq!(proof, tree.prove_by_index(999).unwrap().into_owned());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_drops_a_torn_last_record() {
+        let path = temp_journal("torn");
+        let data = example_data(1000);
+        let mut journal = JournaledTree::create(&path, HashAlgorithm::Sha256, OddLeafPolicy::Duplicate).unwrap();
+        for entry in &data {
+            journal.push(entry).unwrap();
+        }
+        journal.sync().unwrap();
+        drop(journal);
+        let whole = fs::read(&path).unwrap();
+        let header_len = whole.len() - 1000 * 40;
+
+        // killed inside the length, the hash and the checksum of a record
+        for cut in [header_len + 1, header_len + 40 * 517 + 3, header_len + 40 * 998 + 21, whole.len() - 1] {
+            fs::write(&path, &whole[..cut]).unwrap();
+            let written = (cut - header_len) / 40;
+            let journal = JournaledTree::recover(&path).unwrap();
+            assert_eq!(journal.len(), written);
+            assert_eq!(journal.truncated_bytes() as usize, (cut - header_len) % 40);
+            let expected = (written > 0).then(|| MerkleTree::construct(&data[..written]).root());
+            assert_eq!(journal.root(), expected, "cut at {}", cut);
+            assert_eq!(fs::metadata(&path).unwrap().len() as usize, header_len + 40 * written);
+        }
+
+        // a whole last record with a bad checksum, as a torn write of garbage leaves it
+        let mut bytes = whole.clone();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        let mut journal = JournaledTree::recover(&path).unwrap();
+        assert_eq!((journal.len(), journal.truncated_bytes()), (999, 40));
+        // pushing after recovery carries the chain of checksums on
+        journal.push(&data[999]).unwrap();
+        drop(journal);
+        assert_eq!(fs::read(&path).unwrap(), whole);
+        assert_eq!(MerkleTree::recover_from_journal(&path).unwrap().root(), MerkleTree::construct(&data).root());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_other_hashers_and_policies() {
+        let path = temp_journal("promote");
+        let data = example_data(11);
+        let mut journal = JournaledTree::create(&path, HashAlgorithm::Sha512, OddLeafPolicy::Promote).unwrap();
+        for entry in &data {
+            journal.push(entry).unwrap();
+        }
+        drop(journal);
+        let recovered = MerkleTree::recover_from_journal(&path).unwrap();
+        let config = crate::merkel::TreeConfig::default()
+            .with_hasher(HashAlgorithm::Sha512)
+            .with_odd_leaf_policy(OddLeafPolicy::Promote);
+        assert_eq!(recovered.root(), config.construct(&data).unwrap().root());
+        assert_eq!((recovered.hasher(), recovered.odd_leaf_policy()), (HashAlgorithm::Sha512, OddLeafPolicy::Promote));
+        assert!(JournaledTree::create(&path, HashAlgorithm::Sha256, OddLeafPolicy::Duplicate).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_corruption() {
+        let path = temp_journal("corrupt");
+        let mut journal = JournaledTree::create(&path, HashAlgorithm::Sha256, OddLeafPolicy::Duplicate).unwrap();
+        assert!(matches!(MerkleTree::recover_from_journal(&path), Err(LibError::EmptyInput)));
+        for entry in &example_data(3) {
+            journal.push(entry).unwrap();
+        }
+        drop(journal);
+        let good = fs::read(&path).unwrap();
+        let header_len = good.len() - 3 * 40;
+
+        // a flipped bit in a record before the last
+        let mut bytes = good.clone();
+        bytes[header_len + 10] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        let error = JournaledTree::recover(&path).unwrap_err();
+        let message = format!("invalid format: leaf journal: record 0 at offset {} is corrupt", header_len);
+        assert_eq!(error.to_string(), message);
+        // records swapped, each whole, fail their chained checksums
+        let mut bytes = good.clone();
+        bytes[header_len..header_len + 80].rotate_left(40);
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(JournaledTree::recover(&path), Err(LibError::InvalidFormat(_))));
+        // and nothing is truncated on failure
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+
+        fs::write(&path, b"MRKLARCH").unwrap();
+        assert!(matches!(JournaledTree::recover(&path), Err(LibError::InvalidFormat(_))));
+        let mut bytes = good[..header_len].to_vec();
+        bytes[11] = b'x';
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(JournaledTree::recover(&path), Err(LibError::UnknownHasher(_))));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use super::error::LibError;
+use crate::merkel::HASH_LEN;
+
+/// Maximum number of offending line numbers recorded per problem kind
+pub const MAX_REPORTED_LINES: usize = 5;
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Problems found in a hash file by `lint_hashes`
+///
+/// Line numbers are 1-based. Invalid hex, wrong-length hashes and non-UTF-8 lines are errors;
+/// everything else is a warning that does not change how the file would be read. Every list is in
+/// line order, so the same file always gives the same report.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct LintReport {
+    pub total_lines: usize,
+    pub valid_lines: usize,
+    pub blank_lines: usize,
+    /// Blank lines after the last other line, also counted in `blank_lines`
+    ///
+    /// They are skipped when the file is read as hashes, but would each be an empty leaf if its
+    /// lines were hashed as text, as when a file gains a second newline at its end.
+    pub trailing_blank_lines: usize,
+    pub invalid_hex: LineIssue,
+    pub wrong_length: LineIssue,
+    pub non_utf8: LineIssue,
+    pub crlf_lines: usize,
+    pub byte_order_mark: bool,
+    /// Hashes occurring more than once, in order of first occurrence
+    pub duplicates: Vec<Duplicate>,
+}
+
+/// Number of lines with one kind of problem and the first few of their line numbers
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct LineIssue {
+    pub count: usize,
+    pub lines: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct Duplicate {
+    pub hash: String,
+    pub first_line: usize,
+    pub count: usize,
+}
+
+impl LineIssue {
+    fn record(&mut self, line: usize) {
+        self.count += 1;
+        if self.lines.len() < MAX_REPORTED_LINES {
+            self.lines.push(line);
+        }
+    }
+}
+
+impl LintReport {
+    pub fn error_count(&self) -> usize {
+        self.invalid_hex.count + self.wrong_length.count + self.non_utf8.count
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    pub fn has_warnings(&self) -> bool {
+        self.blank_lines > 0 || self.crlf_lines > 0 || self.byte_order_mark || !self.duplicates.is_empty()
+    }
+}
+
+/// Checks a file of one hex hash per line without building a tree
+pub fn lint_hashes<R: BufRead>(reader: R) -> Result<LintReport, LibError> {
+    lint_hashes_of_len(reader, HASH_LEN)
+}
+
+/// Like `lint_hashes`, for hashes of `hash_len` bytes, e.g. `MerkleHasher::output_len` of another hasher
+pub fn lint_hashes_of_len<R: BufRead>(mut reader: R, hash_len: usize) -> Result<LintReport, LibError> {
+    let mut report = LintReport::default();
+    // first line and number of occurrences of every valid hash
+    let mut seen: HashMap<Vec<u8>, (usize, usize)> = HashMap::new();
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        report.total_lines += 1;
+        let line_no = report.total_lines;
+
+        let mut line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        if let Some(stripped) = line.strip_suffix(b"\r") {
+            report.crlf_lines += 1;
+            line = stripped;
+        }
+        if line_no == 1 {
+            if let Some(stripped) = line.strip_prefix(BOM) {
+                report.byte_order_mark = true;
+                line = stripped;
+            }
+        }
+
+        let Ok(text) = std::str::from_utf8(line) else {
+            report.trailing_blank_lines = 0;
+            report.non_utf8.record(line_no);
+            continue;
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            report.blank_lines += 1;
+            report.trailing_blank_lines += 1;
+            continue;
+        }
+        report.trailing_blank_lines = 0;
+        let Ok(hash) = hex::decode(text) else {
+            report.invalid_hex.record(line_no);
+            continue;
+        };
+        if hash.len() != hash_len {
+            report.wrong_length.record(line_no);
+            continue;
+        }
+
+        report.valid_lines += 1;
+        seen.entry(hash).or_insert((line_no, 0)).1 += 1;
+    }
+
+    report.duplicates = seen
+        .into_iter()
+        .filter(|(_, (_, count))| *count > 1)
+        .map(|(hash, (first_line, count))| Duplicate {
+            hash: hex::encode(hash),
+            first_line,
+            count,
+        })
+        .collect();
+    // first lines are distinct, so this fixes the order whatever order the map iterated in
+    report.duplicates.sort_by_key(|d| d.first_line);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &[u8] = include_bytes!("../../tests/fixtures/messy_hashes.txt");
+
+    #[test]
+    fn test_messy_fixture() {
+        let report = lint_hashes(MESSY).unwrap();
+
+        assert_eq!(report.total_lines, 12);
+        assert_eq!(report.valid_lines, 6);
+        assert_eq!((report.blank_lines, report.trailing_blank_lines), (2, 0));
+        assert!(report.byte_order_mark);
+        assert_eq!(report.crlf_lines, 2);
+        assert_eq!(report.invalid_hex, LineIssue { count: 2, lines: vec![5, 9] });
+        assert_eq!(report.wrong_length, LineIssue { count: 1, lines: vec![6] });
+        assert_eq!(report.non_utf8, LineIssue { count: 1, lines: vec![8] });
+        assert_eq!(
+            report.duplicates,
+            vec![Duplicate {
+                hash: "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d".to_string(),
+                first_line: 1,
+                count: 3,
+            }]
+        );
+        assert_eq!(report.error_count(), 4);
+        assert!(report.has_warnings());
+    }
+
+    #[test]
+    fn test_clean_input() {
+        let input = "4bf5122f344554c53bde2ebb8cd2b7e3d1600ad631c385a5d7cce23c7785459a\n\
+                     dbc1b4c900ffe48d575b5da5c638040125f65db0fe3e24494b76ea986457d986";
+        let report = lint_hashes(input.as_bytes()).unwrap();
+        assert_eq!(report.total_lines, 2);
+        assert_eq!(report.valid_lines, 2);
+        assert!(!report.has_errors());
+        assert!(!report.has_warnings());
+
+        // the same lines are the wrong length for 20-byte hashes
+        let report = lint_hashes_of_len(input.as_bytes(), 20).unwrap();
+        assert_eq!((report.valid_lines, report.wrong_length.lines.clone()), (0, vec![1, 2]));
+    }
+
+    #[test]
+    fn test_trailing_blank_lines() {
+        let hash = "4bf5122f344554c53bde2ebb8cd2b7e3d1600ad631c385a5d7cce23c7785459a";
+        let cases = [(format!("{}\n", hash), 0), (format!("\n{}\n\n \r\n", hash), 2), ("\n\n".into(), 2)];
+        for (input, trailing) in cases {
+            let report = lint_hashes(input.as_bytes()).unwrap();
+            assert_eq!(report.trailing_blank_lines, trailing, "{:?}", input);
+            assert_eq!(report.has_warnings(), report.blank_lines > 0);
+        }
+        // a line that is not UTF-8 is not blank
+        assert_eq!(lint_hashes(&b"\n\xff"[..]).unwrap().trailing_blank_lines, 0);
+    }
+
+    #[test]
+    fn test_reports_first_lines_only() {
+        let input = "zz\n".repeat(MAX_REPORTED_LINES + 3);
+        let report = lint_hashes(input.as_bytes()).unwrap();
+        assert_eq!(report.invalid_hex.count, MAX_REPORTED_LINES + 3);
+        assert_eq!(report.invalid_hex.lines, (1..=MAX_REPORTED_LINES).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,206 @@
+//! Trees over files of back-to-back fixed-width records, such as 32-byte txids, read through a
+//! memory mapping
+//!
+//! The file has no framing: record `i` is bytes `i * width` to `(i + 1) * width`. Records are
+//! hashed straight from the mapping, so no record is read into a buffer of its own first. In
+//! pre-hashed mode each record is copied once, into the leaf level of the tree: a tree owns its
+//! leaves and outlives the mapping, so it cannot borrow them. On platforms without `mmap` the file
+//! is read into memory instead.
+
+use std::fs::File;
+use std::path::Path;
+
+use super::error::LibError;
+use crate::merkel::{canonical_order, CanonicalOrdering, Hash, LeafMode, MerkleHasher, MerkleTree, TreeConfig};
+
+/// Builds the tree of the records of `path`, `width` bytes each, as `TreeConfig::new(mode)` does
+///
+/// The file is mapped while the tree is built: if another process truncates it in that time, the
+/// build dies of `SIGBUS`. See `from_fixed_width_file_with`.
+pub fn from_fixed_width_file<P: AsRef<Path>>(path: P, width: usize, mode: LeafMode) -> Result<MerkleTree, LibError> {
+    from_fixed_width_file_with(path, width, &TreeConfig::new(mode))
+}
+
+/// Builds the tree of the records of `path`, `width` bytes each, as `config` builds it from the
+/// records as entries
+///
+/// Fails with `LibError::InvalidFormat` naming the remainder if the file is not a whole number of
+/// records, `LibError::EmptyInput` for an empty file and, in pre-hashed mode,
+/// `LibError::InvalidLeafLength` unless `width` is the length of the hashes of the configured
+/// hasher.
+///
+/// The file is mapped, not read, while the tree is built. If another process truncates it in that
+/// time, reading the lost pages raises `SIGBUS` and kills the process; bytes written to it in that
+/// time may or may not reach the leaves. Only build from files nothing else changes, or copy the
+/// file first.
+pub fn from_fixed_width_file_with<P: AsRef<Path>>(
+    path: P,
+    width: usize,
+    config: &TreeConfig,
+) -> Result<MerkleTree, LibError> {
+    if width == 0 {
+        return Err(LibError::InvalidFormat("records must be at least one byte wide".into()));
+    }
+    let file = File::open(path.as_ref())?;
+    let len = usize::try_from(file.metadata()?.len()).map_err(|_| LibError::Overflow("file length"))?;
+    let remainder = len % width;
+    if remainder != 0 {
+        return Err(LibError::InvalidFormat(format!(
+            "{} is {} bytes, not a whole number of {}-byte records: {} bytes are left over",
+            path.as_ref().display(),
+            len,
+            width,
+            remainder
+        )));
+    }
+    if len == 0 {
+        return Err(LibError::EmptyInput);
+    }
+    let hasher = config.hasher();
+    if config.leaf_mode() == LeafMode::PreHashed && width != hasher.output_len() {
+        return Err(LibError::InvalidLeafLength { index: 0, expected: hasher.output_len(), found: width });
+    }
+
+    let mapping = Mapping::new(&file, len)?;
+    let records = mapping.bytes().chunks_exact(width);
+    let mut leaves: Vec<Hash> = match config.leaf_mode() {
+        LeafMode::PreHashed => records.map(<[u8]>::to_vec).collect(),
+        _ => records.map(|record| hasher.leaf_hash(record)).collect(),
+    };
+    match config.canonical_ordering() {
+        None => {}
+        Some(CanonicalOrdering::ByData) if config.leaf_mode() == LeafMode::HashData => {
+            let records: Vec<&[u8]> = mapping.bytes().chunks_exact(width).collect();
+            let order = canonical_order(CanonicalOrdering::ByData, &records, &leaves);
+            leaves = order.into_iter().map(|index| std::mem::take(&mut leaves[index])).collect();
+        }
+        // pre-hashed records are their own leaf hashes, so both orderings sort the leaves
+        Some(_) => leaves.sort(),
+    }
+    Ok(MerkleTree::build(leaves, config.odd_leaf_policy(), hasher))
+}
+
+/// A read-only private mapping of a whole file, unmapped on drop
+#[cfg(unix)]
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Mapping {
+    /// Maps the first `len` bytes of `file`, which must be more than none
+    fn new(file: &File, len: usize) -> Result<Mapping, LibError> {
+        use std::os::unix::io::AsRawFd;
+
+        // SAFETY: a new read-only mapping at an address the kernel picks aliases no memory of ours
+        let ptr =
+            unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Mapping { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` readable bytes until it is dropped. Another process can still
+        // truncate or write the file under it, which `from_fixed_width_file_with` documents
+        unsafe { std::slice::from_raw_parts(self.ptr.cast::<u8>(), self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was made by `Mapping::new` and no borrow of it outlives `self`
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// The contents of the file, read into memory where it cannot be mapped
+#[cfg(not(unix))]
+struct Mapping(Vec<u8>);
+
+#[cfg(not(unix))]
+impl Mapping {
+    fn new(mut file: &File, len: usize) -> Result<Mapping, LibError> {
+        let mut bytes = Vec::with_capacity(len);
+        std::io::Read::read_to_end(&mut file, &mut bytes)?;
+        Ok(Mapping(bytes))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{HashAlgorithm, OddLeafPolicy};
+
+    use std::fs;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("merkle-mmap-{}-{}", std::process::id(), name))
+    }
+
+    /// 10,000 txids back to back, with their hex lines
+    fn txids() -> (Vec<u8>, String) {
+        let txids: Vec<Hash> = (0..10_000u32).map(|i| HashAlgorithm::Sha256d.leaf_hash(&i.to_le_bytes())).collect();
+        (txids.concat(), txids.iter().map(|txid| hex::encode(txid) + "\n").collect())
+    }
+
+    #[test]
+    fn test_matches_the_hex_lines() {
+        let (bytes, lines) = txids();
+        let path = temp_path("txids");
+        fs::write(&path, &bytes).unwrap();
+        let hashes: Vec<Hash> = lines.lines().map(|line| hex::decode(line).unwrap()).collect();
+
+        let tree = from_fixed_width_file(&path, 32, LeafMode::PreHashed).unwrap();
+        assert_eq!((tree.len(), tree.root()), (10_000, MerkleTree::from_leaf_hashes(hashes.clone()).root()));
+        let config = TreeConfig::new(LeafMode::HashData).with_hasher(HashAlgorithm::Sha512);
+        let hashed = from_fixed_width_file_with(&path, 32, &config).unwrap();
+        assert_eq!(hashed.root(), config.construct(&hashes).unwrap().root());
+        // 64-byte records are pairs of txids
+        let pairs = from_fixed_width_file(&path, 64, LeafMode::HashData).unwrap();
+        assert_eq!(
+            pairs.root(),
+            MerkleTree::construct(&hashes.chunks(2).map(<[Hash]>::concat).collect::<Vec<_>>()).root()
+        );
+
+        for ordering in [CanonicalOrdering::ByLeafHash, CanonicalOrdering::ByData] {
+            for mode in [LeafMode::PreHashed, LeafMode::HashData] {
+                let config = TreeConfig::new(mode).with_odd_leaf_policy(OddLeafPolicy::Promote);
+                let config = config.with_canonical_ordering(ordering);
+                let tree = from_fixed_width_file_with(&path, 32, &config).unwrap();
+                assert_eq!(tree.root(), config.construct(&hashes).unwrap().root(), "{:?} {:?}", ordering, mode);
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_misaligned_files() {
+        let (bytes, _) = txids();
+        let path = temp_path("misaligned");
+        fs::write(&path, &bytes[..32 * 3 + 5]).unwrap();
+        let error = from_fixed_width_file(&path, 32, LeafMode::PreHashed).err().unwrap().to_string();
+        assert!(
+            error.ends_with("is 101 bytes, not a whole number of 32-byte records: 5 bytes are left over"),
+            "{}",
+            error
+        );
+
+        let wrong_width = from_fixed_width_file(&path, 101, LeafMode::PreHashed);
+        assert!(matches!(wrong_width, Err(LibError::InvalidLeafLength { index: 0, expected: 32, found: 101 })));
+        assert!(from_fixed_width_file(&path, 0, LeafMode::HashData).is_err());
+        fs::write(&path, b"").unwrap();
+        assert!(matches!(from_fixed_width_file(&path, 32, LeafMode::PreHashed), Err(LibError::EmptyInput)));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(from_fixed_width_file(&path, 32, LeafMode::PreHashed), Err(LibError::Io(_))));
+    }
+}
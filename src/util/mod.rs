@@ -0,0 +1,34 @@
+pub mod archive;
+pub mod audit;
+pub mod bundle;
+pub mod compat;
+pub mod diff;
+pub mod encoder;
+pub mod encoding;
+pub mod error;
+pub mod estimate;
+pub mod export;
+pub mod file;
+pub mod generate;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod golden;
+pub mod hex_policy;
+pub mod hex_serde;
+pub mod io;
+pub mod journal;
+pub mod lint;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+mod nfc;
+pub mod path;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod pipeline;
+#[cfg(feature = "sign")]
+pub mod sign;
+pub mod spill;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "solidity")]
+pub mod solidity;
+#[cfg(feature = "watch")]
+pub mod watch;
@@ -0,0 +1,172 @@
+//! Unicode Normalization Form C, for names that must be spelled the same on every platform
+//!
+//! macOS file systems hand out names decomposed (close to NFD) while others keep what was typed,
+//! usually NFC, so the same name can arrive as different code points. `to_nfc` composes them as
+//! UAX #15 does: canonical decomposition, canonical ordering of combining marks, then canonical
+//! composition. Hangul syllables are decomposed and composed arithmetically.
+//!
+//! The tables in `tables` are generated from the Unicode 14.0 character database: the canonical
+//! decomposition mappings, the non-zero combining classes, and the pairs of every two code point
+//! decomposition whose composite is its own NFC, which leaves out the composition exclusions.
+//! Code points assigned after 14.0 have no mappings here and are kept as they are.
+
+mod tables;
+
+use tables::{COMBINING_CLASSES, COMPOSITIONS, DECOMPOSITIONS};
+
+const HANGUL_S_BASE: u32 = 0xac00;
+const HANGUL_L_BASE: u32 = 0x1100;
+const HANGUL_V_BASE: u32 = 0x1161;
+const HANGUL_T_BASE: u32 = 0x11a7;
+const HANGUL_L_COUNT: u32 = 19;
+const HANGUL_V_COUNT: u32 = 21;
+const HANGUL_T_COUNT: u32 = 28;
+const HANGUL_N_COUNT: u32 = HANGUL_V_COUNT * HANGUL_T_COUNT;
+const HANGUL_S_COUNT: u32 = HANGUL_L_COUNT * HANGUL_N_COUNT;
+
+/// `text` in Normalization Form C
+pub(crate) fn to_nfc(text: &str) -> String {
+    if text.is_ascii() {
+        return text.to_string();
+    }
+
+    let mut decomposed = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        decompose(c, &mut decomposed);
+    }
+    // a stable sort of every run of combining marks by class; starters stay where they are
+    for i in 1..decomposed.len() {
+        let class = combining_class(decomposed[i]);
+        let mut j = i;
+        while j > 0 && class != 0 && combining_class(decomposed[j - 1]) > class {
+            decomposed.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+
+    let mut chars: Vec<char> = Vec::with_capacity(decomposed.len());
+    // the last starter, and the class of the last mark after it, None if nothing follows it
+    let mut starter: Option<usize> = None;
+    let mut last_class: Option<u8> = None;
+    for c in decomposed {
+        let class = combining_class(c);
+        if let Some(position) = starter {
+            // a mark is blocked from the starter by an earlier mark of the same or a higher class
+            if last_class.is_none_or(|last| last < class) {
+                if let Some(composite) = compose(chars[position], c) {
+                    chars[position] = composite;
+                    continue;
+                }
+            }
+        }
+        if class == 0 {
+            starter = Some(chars.len());
+            last_class = None;
+        } else {
+            last_class = Some(class);
+        }
+        chars.push(c);
+    }
+    chars.into_iter().collect()
+}
+
+/// Appends the full canonical decomposition of `c`
+fn decompose(c: char, out: &mut Vec<char>) {
+    let code = u32::from(c);
+    if (HANGUL_S_BASE..HANGUL_S_BASE + HANGUL_S_COUNT).contains(&code) {
+        let index = code - HANGUL_S_BASE;
+        let jamo = [
+            Some(HANGUL_L_BASE + index / HANGUL_N_COUNT),
+            Some(HANGUL_V_BASE + (index % HANGUL_N_COUNT) / HANGUL_T_COUNT),
+            Some(HANGUL_T_BASE + index % HANGUL_T_COUNT).filter(|&t| t != HANGUL_T_BASE),
+        ];
+        out.extend(jamo.into_iter().flatten().filter_map(char::from_u32));
+        return;
+    }
+    match DECOMPOSITIONS.binary_search_by_key(&c, |&(composite, _, _)| composite) {
+        Ok(found) => {
+            let (_, first, second) = DECOMPOSITIONS[found];
+            decompose(first, out);
+            if let Some(second) = second {
+                decompose(second, out);
+            }
+        }
+        Err(_) => out.push(c),
+    }
+}
+
+/// The primary composite of `first` followed by `second`, if there is one
+fn compose(first: char, second: char) -> Option<char> {
+    let (first_code, second_code) = (u32::from(first), u32::from(second));
+    if (HANGUL_L_BASE..HANGUL_L_BASE + HANGUL_L_COUNT).contains(&first_code)
+        && (HANGUL_V_BASE..HANGUL_V_BASE + HANGUL_V_COUNT).contains(&second_code)
+    {
+        let index = (first_code - HANGUL_L_BASE) * HANGUL_N_COUNT + (second_code - HANGUL_V_BASE) * HANGUL_T_COUNT;
+        return char::from_u32(HANGUL_S_BASE + index);
+    }
+    let syllable = first_code.wrapping_sub(HANGUL_S_BASE);
+    if syllable < HANGUL_S_COUNT
+        && syllable % HANGUL_T_COUNT == 0
+        && (HANGUL_T_BASE + 1..HANGUL_T_BASE + HANGUL_T_COUNT).contains(&second_code)
+    {
+        return char::from_u32(first_code + second_code - HANGUL_T_BASE);
+    }
+    COMPOSITIONS
+        .binary_search_by_key(&(first, second), |&(first, second, _)| (first, second))
+        .ok()
+        .map(|found| COMPOSITIONS[found].2)
+}
+
+/// Canonical combining class of `c`, 0 for starters
+fn combining_class(c: char) -> u8 {
+    match COMBINING_CLASSES.binary_search_by(|&(start, end, _)| {
+        if end < c {
+            std::cmp::Ordering::Less
+        } else if start > c {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    }) {
+        Ok(found) => COMBINING_CLASSES[found].2,
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_composes() {
+        assert_eq!(to_nfc("a\u{308}"), "ä");
+        assert_eq!(to_nfc("docs/u\u{308}ni\u{308}code"), "docs/ünïcode");
+        // marks are put in canonical order first: the cedilla (202) before the acute (230)
+        assert_eq!(to_nfc("e\u{301}\u{327}"), "\u{229}\u{301}");
+        assert_eq!(to_nfc("e\u{327}\u{301}"), "\u{229}\u{301}");
+        // a mark of the same class in between blocks the second one
+        assert_eq!(to_nfc("a\u{301}\u{301}"), "\u{e1}\u{301}");
+        // singletons and composition exclusions stay decomposed
+        assert_eq!(to_nfc("\u{212b}"), "\u{c5}");
+        assert_eq!(to_nfc("\u{958}"), "\u{915}\u{93c}");
+        assert_eq!(to_nfc("ASCII only"), "ASCII only");
+        assert_eq!(to_nfc("日本語"), "日本語");
+    }
+
+    #[test]
+    fn test_hangul() {
+        assert_eq!(to_nfc("\u{1100}\u{1161}"), "\u{ac00}");
+        assert_eq!(to_nfc("\u{1100}\u{1161}\u{11a8}"), "\u{ac01}");
+        assert_eq!(to_nfc("\u{ac00}\u{11a8}"), "\u{ac01}");
+        // a syllable that already has a final consonant takes no other
+        assert_eq!(to_nfc("\u{ac01}\u{11a8}"), "\u{ac01}\u{11a8}");
+        assert_eq!(to_nfc("\u{d7a3}"), "\u{d7a3}");
+    }
+
+    #[test]
+    fn test_tables_are_sorted() {
+        assert!(DECOMPOSITIONS.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(COMPOSITIONS.windows(2).all(|w| (w[0].0, w[0].1) < (w[1].0, w[1].1)));
+        assert!(COMBINING_CLASSES.windows(2).all(|w| w[0].0 <= w[0].1 && w[0].1 < w[1].0));
+    }
+}
@@ -0,0 +1,186 @@
+//! Paths of files below a directory, spelled the same way on every platform
+//!
+//! `canonical_rel_path` turns a file found under a root directory into the path that names it in
+//! a commitment: its components below the root, joined with `/` whatever the platform separator.
+//! Sorting these strings as they are orders them by their UTF-8 bytes, as `String` compares, which
+//! does not depend on the platform either.
+//!
+//! Names are put in Unicode Normalization Form C, so a name stored decomposed on one system and
+//! composed on another gives the same path, and so the same root; see `nfc` for the Unicode
+//! version of its tables. Names differing only in case are kept apart, so a directory holding both
+//! `README` and `readme` cannot be copied to a case-insensitive file system without changing its
+//! paths.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+use super::error::LibError;
+use super::nfc::to_nfc;
+
+/// Most symbolic links followed from one path before giving up on it as a cycle
+const MAX_LINK_HOPS: usize = 40;
+
+/// The path of `entry` below `root`, with components in NFC joined by `/`
+///
+/// Fails with `LibError::UnportablePath` for an entry that is not below `root` or climbs out of it
+/// with `..`, a name that is not UTF-8 (shown lossily), a name holding `\`, which Windows reads as
+/// a separator, or a path through a symbolic link cycle: links leading back to themselves, or a
+/// link to a directory that holds the link. Components of `entry` that do not exist are not
+/// checked for links, and other failures reading them are `LibError::Io`.
+pub fn canonical_rel_path(root: &Path, entry: &Path) -> Result<String, LibError> {
+    let unportable = |reason| LibError::UnportablePath { path: entry.to_string_lossy().into_owned(), reason };
+    let rel = entry.strip_prefix(root).map_err(|_| unportable("is not below the root directory"))?;
+    let mut names = vec![];
+    for component in rel.components() {
+        match component {
+            Component::Normal(name) => {
+                let name = name.to_str().ok_or_else(|| unportable("has a name that is not UTF-8"))?;
+                if name.contains('\\') {
+                    return Err(unportable("has a name holding `\\`, a separator on Windows"));
+                }
+                names.push(name);
+            }
+            Component::CurDir => {}
+            _ => return Err(unportable("climbs out of the root directory")),
+        }
+    }
+    if names.is_empty() {
+        return Err(unportable("is the root directory itself"));
+    }
+
+    let mut prefix = root.to_path_buf();
+    for name in &names {
+        prefix.push(name);
+        match fs::symlink_metadata(&prefix) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                if link_cycle(&prefix)? {
+                    return Err(unportable("goes through a cycle of symbolic links"));
+                }
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(names.iter().map(|name| to_nfc(name)).collect::<Vec<_>>().join("/"))
+}
+
+/// Whether the symbolic link `link` leads back to itself, or to a directory that holds it
+fn link_cycle(link: &Path) -> Result<bool, LibError> {
+    let mut seen = HashSet::new();
+    let mut current = link.to_path_buf();
+    for _ in 0..MAX_LINK_HOPS {
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {}
+            // a dangling link leads nowhere, which is no cycle
+            Ok(_) | Err(_) => return held_by(link, &current),
+        }
+        if !seen.insert(current.clone()) {
+            return Ok(true);
+        }
+        let target = fs::read_link(&current)?;
+        current = match current.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+    }
+    Ok(true)
+}
+
+/// Whether `target` is a directory that holds `link`
+fn held_by(link: &Path, target: &Path) -> Result<bool, LibError> {
+    let (Ok(target), Some(parent)) = (fs::canonicalize(target), link.parent()) else {
+        return Ok(false);
+    };
+    let parent: PathBuf = fs::canonicalize(parent)?;
+    Ok(target.is_dir() && parent.starts_with(&target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::error::ExitStatus;
+
+    fn temp_tree(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("merkle-path-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_joins_with_slashes() {
+        let dir = temp_tree("names");
+        let files = ["Readme", "readme", "docs/ünïcode/файл.txt", "docs/日本語.md", "a/b/c.txt", "ä"];
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, file).unwrap();
+        }
+        let mut paths: Vec<String> =
+            files.iter().map(|file| canonical_rel_path(&dir, &dir.join(file)).unwrap()).collect();
+        paths.sort();
+        // ordered by UTF-8 bytes: ASCII capitals first, then lowercase, then the rest
+        assert_eq!(paths, ["Readme", "a/b/c.txt", "docs/ünïcode/файл.txt", "docs/日本語.md", "readme", "ä"]);
+        let dotted = dir.join(".").join("a").join("b").join("c.txt");
+        assert_eq!(canonical_rel_path(&dir, &dotted).unwrap(), "a/b/c.txt");
+        // names are normalized, so a decomposed name is the same path, whichever is on disk
+        let decomposed = canonical_rel_path(&dir, &dir.join("a\u{308}")).unwrap();
+        assert_eq!(decomposed, canonical_rel_path(&dir, &dir.join("ä")).unwrap());
+        assert_eq!(decomposed, "ä");
+        let nested = canonical_rel_path(&dir, &dir.join("docs/u\u{308}ni\u{308}code/файл.txt")).unwrap();
+        assert_eq!(nested, "docs/ünïcode/файл.txt");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_unportable_paths() {
+        let dir = temp_tree("unportable");
+        let reason = |entry: &Path| match canonical_rel_path(&dir, entry) {
+            Err(LibError::UnportablePath { reason, .. }) => reason,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(reason(Path::new("/elsewhere/file")), "is not below the root directory");
+        assert_eq!(reason(&dir.join("a/../../b")), "climbs out of the root directory");
+        assert_eq!(reason(&dir), "is the root directory itself");
+        assert_eq!(reason(&dir.join("a\\b")), "has a name holding `\\`, a separator on Windows");
+        let error = canonical_rel_path(&dir, &dir.join("a\\b")).unwrap_err();
+        assert_eq!((error.code(), error.exit_status()), ("UNPORTABLE_PATH", ExitStatus::Usage));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_names_and_link_cycles() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::symlink;
+
+        let dir = temp_tree("links");
+        let error = canonical_rel_path(&dir, &dir.join(OsStr::from_bytes(b"bad\xffname"))).unwrap_err();
+        let message = format!("path `{}/bad\u{fffd}name` has a name that is not UTF-8", dir.display());
+        assert_eq!(error.to_string(), message);
+
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/b/file"), b"").unwrap();
+        // a link to a sibling file or directory is fine, and keeps its own name
+        symlink("b", dir.join("a/alias")).unwrap();
+        assert_eq!(canonical_rel_path(&dir, &dir.join("a/alias/file")).unwrap(), "a/alias/file");
+        symlink("missing", dir.join("a/dangling")).unwrap();
+        assert_eq!(canonical_rel_path(&dir, &dir.join("a/dangling")).unwrap(), "a/dangling");
+
+        let cycle = "goes through a cycle of symbolic links";
+        // a link to a directory above it
+        symlink("..", dir.join("a/b/up")).unwrap();
+        let error = canonical_rel_path(&dir, &dir.join("a/b/up/b/file")).unwrap_err();
+        assert!(matches!(error, LibError::UnportablePath { reason, .. } if reason == cycle));
+        // links leading to each other
+        symlink("y", dir.join("x")).unwrap();
+        symlink("x", dir.join("y")).unwrap();
+        let error = canonical_rel_path(&dir, &dir.join("x")).unwrap_err();
+        assert!(matches!(error, LibError::UnportablePath { reason, .. } if reason == cycle));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,471 @@
+//! End-to-end cases of the build, prove, verify and serialize pipeline, and the invariants every
+//! run of it must keep
+//!
+//! A `PipelineCase` describes a scheme, the entries to build over, the leaves to prove and the
+//! mutations to apply to each proof. `tests/pipeline_replay.rs` replays the cases committed under
+//! `tests/corpus`, and the `pipeline_replay` fuzz target runs cases decoded from arbitrary bytes
+//! with `PipelineCase::from_fuzz_bytes`. Behind the `test-utils` feature.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::merkel::{
+    CanonicalOrdering, Data, DefaultHashes, Hash, HashAlgorithm, LeafMode, MerkleHasher, MerkleTree, OddLeafPolicy,
+    OwnedProof, TreeConfig,
+};
+use crate::util::error::LibError;
+
+/// Most leaves `PipelineCase::from_fuzz_bytes` gives a case
+pub const MAX_FUZZ_LEAVES: usize = 64;
+
+/// One run of the pipeline: the scheme, its entries, and what to prove and tamper with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PipelineCase {
+    pub leaf_mode: LeafMode,
+    #[serde(default)]
+    pub hasher: HashAlgorithm,
+    #[serde(default)]
+    pub odd_leaf_policy: OddLeafPolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical_ordering: Option<CanonicalOrdering>,
+    /// The entries, in hex, in the order they arrive
+    #[serde(with = "hex_entries")]
+    pub leaves: Vec<Data>,
+    /// Indices of the leaves to prove, in the built tree; those past the end must fail to prove
+    #[serde(default)]
+    pub prove: Vec<usize>,
+    /// Applied to every proof in turn, each to the untouched proof
+    #[serde(default)]
+    pub mutations: Vec<Mutation>,
+}
+
+/// A change to a proof, or to the entry it is checked against, that must make it fail to verify
+///
+/// Step numbers count from the leaf and are taken modulo the number of steps, leaf indices modulo
+/// the number of leaves and bits modulo the number of bits, so any values apply to any proof. A
+/// mutation that leaves the proof as it was is skipped, as is one on an empty proof.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Mutation {
+    /// Flips one bit of the sibling hash of a step
+    FlipBit { step: usize, bit: usize },
+    /// Moves the sibling of a step to the other side, a duplicate one to the left
+    ///
+    /// Skipped where the sibling is the running hash itself, since either side then gives the
+    /// same parent.
+    FlipDirection { step: usize },
+    DropStep { step: usize },
+    /// Applies a step twice
+    RepeatStep { step: usize },
+    SwapSteps { a: usize, b: usize },
+    /// Applies the steps root-first, as a proof listed in the other order would be read
+    Reverse,
+    /// Checks the proof against the entry of another leaf, skipped where that leaf is the same
+    OtherEntry { index: usize },
+    /// Flips one bit of the entry; an empty entry gets one byte instead
+    FlipEntryBit { bit: usize },
+}
+
+/// An invariant a pipeline run broke
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{invariant}: {detail}")]
+#[non_exhaustive]
+pub struct InvariantViolation {
+    /// Which invariant, such as `proof verifies` or `order sensitivity`
+    pub invariant: &'static str,
+    pub detail: String,
+}
+
+/// What a run that kept every invariant went through, so a corpus can be checked to exercise it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PipelineRun {
+    /// Whether the entries built a tree; a pre-hashed entry that is not one hash long fails to
+    pub built: bool,
+    /// Proofs that verified and round-tripped through every encoding
+    pub proofs: usize,
+    /// Indices past the end of the tree that failed to prove
+    pub out_of_range: usize,
+    /// Mutated proofs that failed to verify
+    pub rejected: usize,
+    /// Mutations skipped for leaving the proof as it was
+    pub skipped: usize,
+}
+
+impl PipelineCase {
+    /// The configuration of the scheme
+    pub fn config(&self) -> TreeConfig {
+        let config =
+            TreeConfig::new(self.leaf_mode).with_hasher(self.hasher).with_odd_leaf_policy(self.odd_leaf_policy);
+        match self.canonical_ordering {
+            Some(ordering) => config.with_canonical_ordering(ordering),
+            None => config,
+        }
+    }
+
+    /// Decodes a case from arbitrary bytes, the same case for the same bytes
+    ///
+    /// The first byte picks the scheme. The next three give the numbers of leaves, of indices to
+    /// prove and of mutations, at most `MAX_FUZZ_LEAVES`, 8 and 8. Then come the entries: in
+    /// raw-data mode a length byte, at most 16, and that many bytes; in pre-hashed mode one hash
+    /// of bytes. Then one byte per index and a kind byte and two parameter bytes per mutation.
+    /// Input that runs out reads as zeros.
+    pub fn from_fuzz_bytes(bytes: &[u8]) -> PipelineCase {
+        let mut bytes = FuzzBytes(bytes);
+        let scheme = bytes.next();
+        let leaf_mode = if scheme & 1 == 0 { LeafMode::HashData } else { LeafMode::PreHashed };
+        let odd_leaf_policy = if scheme & 2 == 0 { OddLeafPolicy::Duplicate } else { OddLeafPolicy::Promote };
+        let canonical_ordering = match (scheme >> 2) & 3 {
+            1 => Some(CanonicalOrdering::ByLeafHash),
+            2 => Some(CanonicalOrdering::ByData),
+            _ => None,
+        };
+        let hasher = HashAlgorithm::ALL[usize::from(scheme >> 4) % HashAlgorithm::ALL.len()];
+        let leaf_count = usize::from(bytes.next()) % (MAX_FUZZ_LEAVES + 1);
+        let (prove_count, mutation_count) = (bytes.next() % 9, bytes.next() % 9);
+
+        let leaves = (0..leaf_count)
+            .map(|_| {
+                let len = match leaf_mode {
+                    LeafMode::PreHashed => hasher.output_len(),
+                    _ => usize::from(bytes.next() % 17),
+                };
+                (0..len).map(|_| bytes.next()).collect()
+            })
+            .collect();
+        let prove = (0..prove_count).map(|_| usize::from(bytes.next())).collect();
+        let mutations = (0..mutation_count)
+            .map(|_| {
+                let (kind, a, b) = (bytes.next(), usize::from(bytes.next()), usize::from(bytes.next()));
+                match kind % 8 {
+                    0 => Mutation::FlipBit { step: a, bit: b },
+                    1 => Mutation::FlipDirection { step: a },
+                    2 => Mutation::DropStep { step: a },
+                    3 => Mutation::RepeatStep { step: a },
+                    4 => Mutation::SwapSteps { a, b },
+                    5 => Mutation::Reverse,
+                    6 => Mutation::OtherEntry { index: a },
+                    _ => Mutation::FlipEntryBit { bit: a },
+                }
+            })
+            .collect();
+        PipelineCase { leaf_mode, hasher, odd_leaf_policy, canonical_ordering, leaves, prove, mutations }
+    }
+
+    /// Runs the pipeline, failing at the first invariant it breaks
+    ///
+    /// The entries must build a tree unless there are none, or a pre-hashed one is not one hash
+    /// long. Then:
+    ///
+    /// - Every index in the tree proves, and the proof verifies against the entry of that leaf.
+    ///   Every index past the end fails with `LibError::IndexOutOfRange`.
+    /// - Every proof decodes back to itself from JSON, listed either way round, from compressed
+    ///   bytes and, with the `cbor` feature, from CBOR, as the tree does from CBOR.
+    /// - No mutated proof verifies.
+    /// - Reversing or rotating the entries moves the root unless it leaves the leaves as they
+    ///   were, and never moves it under a canonical ordering.
+    pub fn run(&self) -> Result<PipelineRun, InvariantViolation> {
+        let config = self.config();
+        let mut run = PipelineRun::default();
+        let tree = match config.construct(&self.leaves) {
+            Ok(tree) => tree,
+            Err(LibError::EmptyInput) if self.leaves.is_empty() => return Ok(run),
+            Err(LibError::InvalidLeafLength { .. }) if self.leaf_mode == LeafMode::PreHashed => return Ok(run),
+            Err(e) => return Err(violation("build", format!("the entries fail to build: {}", e))),
+        };
+        run.built = true;
+        self.check_tree_encoding(&config, &tree)?;
+        self.check_order_sensitivity(&config, &tree)?;
+
+        for &index in &self.prove {
+            let proof = match tree.prove_by_index(index) {
+                Ok(proof) if index < tree.len() => proof.into_owned(),
+                Err(LibError::IndexOutOfRange { .. }) if index >= tree.len() => {
+                    run.out_of_range += 1;
+                    continue;
+                }
+                Ok(_) => return Err(violation("proof bounds", format!("index {} past the end proves", index))),
+                Err(e) => return Err(violation("proof bounds", format!("index {} fails to prove: {}", index, e))),
+            };
+            let entry = self.entry_of(&config, &tree, index)?;
+            if !verifies(&config, entry, &proof, &tree.root()) {
+                return Err(violation("proof verifies", format!("the proof of leaf {} does not verify", index)));
+            }
+            check_proof_encodings(&config, &proof, index)?;
+            run.proofs += 1;
+
+            for mutation in &self.mutations {
+                let Some((entry, mutated)) = self.mutate(&config, &tree, entry, &proof, *mutation)? else {
+                    run.skipped += 1;
+                    continue;
+                };
+                if verifies(&config, &entry, &mutated, &tree.root()) {
+                    let detail = format!("the proof of leaf {} still verifies after {:?}", index, mutation);
+                    return Err(violation("mutated proofs fail", detail));
+                }
+                run.rejected += 1;
+            }
+        }
+        Ok(run)
+    }
+
+    /// The entry the leaf at `index` of the built tree came from, which canonical orderings move
+    fn entry_of<'a>(
+        &'a self,
+        config: &TreeConfig,
+        tree: &MerkleTree,
+        index: usize,
+    ) -> Result<&'a Data, InvariantViolation> {
+        let leaf = &tree.leaves()[index];
+        self.leaves
+            .iter()
+            .find(|entry| &leaf_hash(config, entry) == leaf)
+            .ok_or_else(|| violation("leaves", format!("leaf {} is not the hash of any entry", index)))
+    }
+
+    /// The entry and proof `mutation` gives, or None where it leaves them as they were
+    fn mutate(
+        &self,
+        config: &TreeConfig,
+        tree: &MerkleTree,
+        entry: &Data,
+        proof: &OwnedProof,
+        mutation: Mutation,
+    ) -> Result<Option<(Data, OwnedProof)>, InvariantViolation> {
+        let original = entry;
+        let mut steps = proof.hashes().to_vec();
+        let mut entry = original.clone();
+        let step_count = steps.len();
+        let steps_affected = !matches!(mutation, Mutation::OtherEntry { .. } | Mutation::FlipEntryBit { .. });
+        if steps_affected && step_count == 0 {
+            return Ok(None);
+        }
+        match mutation {
+            Mutation::FlipBit { step, bit } => {
+                let hash = &mut steps[step % step_count].1;
+                let bit = bit % (hash.len() * 8);
+                hash[bit / 8] ^= 1 << (bit % 8);
+            }
+            Mutation::FlipDirection { step } => {
+                let step = step % step_count;
+                let leaf = leaf_hash(config, &entry);
+                let before = OwnedProof::new(steps[..step].to_vec());
+                let running = before.as_proof().root_from_with(&config.hasher(), &leaf);
+                let (direction, sibling) = &mut steps[step];
+                if *sibling == running {
+                    return Ok(None);
+                }
+                *direction = direction.opposite();
+            }
+            Mutation::DropStep { step } => {
+                steps.remove(step % step_count);
+            }
+            Mutation::RepeatStep { step } => {
+                let step = step % step_count;
+                steps.insert(step, steps[step]);
+            }
+            Mutation::SwapSteps { a, b } => steps.swap(a % step_count, b % step_count),
+            Mutation::Reverse => steps.reverse(),
+            Mutation::OtherEntry { index } => {
+                entry = self.entry_of(config, tree, index % tree.len())?.clone();
+            }
+            Mutation::FlipEntryBit { bit } => match entry.len() {
+                0 => entry.push(1 << (bit % 8)),
+                len => {
+                    let bit = bit % (len * 8);
+                    entry[bit / 8] ^= 1 << (bit % 8);
+                }
+            },
+        }
+        let unchanged = steps == proof.hashes() && leaf_hash(config, &entry) == leaf_hash(config, original);
+        let mutated = match proof.tree_size() {
+            Some(size) => OwnedProof::new(steps).with_tree_size(size),
+            None => OwnedProof::new(steps),
+        };
+        Ok((!unchanged).then_some((entry, mutated)))
+    }
+}
+
+impl PipelineCase {
+    /// The tree reads back from CBOR as it was built
+    #[cfg(feature = "cbor")]
+    fn check_tree_encoding(&self, config: &TreeConfig, tree: &MerkleTree) -> Result<(), InvariantViolation> {
+        let round_trip = |e: LibError| violation("tree round-trip", format!("the tree fails to round-trip: {}", e));
+        let mut bytes = vec![];
+        config.save_cbor(tree, &mut bytes).map_err(round_trip)?;
+        let loaded = config.load_cbor(bytes.as_slice()).map_err(round_trip)?;
+        let same = (loaded.root(), loaded.leaves(), loaded.odd_leaf_policy())
+            == (tree.root(), tree.leaves(), tree.odd_leaf_policy());
+        if !same {
+            return Err(violation("tree round-trip", "the tree reads back from CBOR changed".into()));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "cbor"))]
+    fn check_tree_encoding(&self, _: &TreeConfig, _: &MerkleTree) -> Result<(), InvariantViolation> {
+        Ok(())
+    }
+
+    /// Reordered entries give another root exactly when their leaves are in another order, and
+    /// the same root under a canonical ordering
+    fn check_order_sensitivity(&self, config: &TreeConfig, tree: &MerkleTree) -> Result<(), InvariantViolation> {
+        let arrival: Vec<Hash> = self.leaves.iter().map(|entry| leaf_hash(config, entry)).collect();
+        let mut reversed = self.leaves.clone();
+        reversed.reverse();
+        let mut rotated = self.leaves.clone();
+        rotated.rotate_left(1);
+        for (name, entries) in [("reversed", reversed), ("rotated", rotated)] {
+            let reordered = config
+                .construct(&entries)
+                .map_err(|e| violation("order sensitivity", format!("the {} entries fail to build: {}", name, e)))?;
+            let same_leaves = entries.iter().map(|entry| leaf_hash(config, entry)).eq(arrival.iter().cloned());
+            let expected_same = same_leaves || config.canonical_ordering().is_some();
+            if (reordered.root() == tree.root()) != expected_same {
+                let moved = if expected_same { "moves" } else { "does not move" };
+                return Err(violation("order sensitivity", format!("the root {} with the entries {}", moved, name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the proof verifies, a proof too deep to be checked counting as one that does not
+fn verifies(config: &TreeConfig, entry: &Data, proof: &OwnedProof, root: &Hash) -> bool {
+    config.verify_proof(entry, &proof.as_proof(), root).unwrap_or(false)
+}
+
+fn leaf_hash(config: &TreeConfig, entry: &Data) -> Hash {
+    match config.leaf_mode() {
+        LeafMode::PreHashed => entry.clone(),
+        _ => config.hasher().leaf_hash(entry),
+    }
+}
+
+/// The proof of leaf `index` decodes back to itself from each encoding
+fn check_proof_encodings(config: &TreeConfig, proof: &OwnedProof, index: usize) -> Result<(), InvariantViolation> {
+    let failed = |encoding: &str, detail: String| {
+        let detail = format!("the proof of leaf {} fails to round-trip {}: {}", index, encoding, detail);
+        violation("proof round-trip", detail)
+    };
+    let changed = |encoding: &str| failed(encoding, "it decodes to another proof".into());
+
+    for listed in [proof.clone(), proof.reversed()] {
+        let encoding = if listed == *proof { "through JSON" } else { "through JSON listed root-first" };
+        let json = serde_json::to_string(&listed).map_err(|e| failed(encoding, e.to_string()))?;
+        let decoded: OwnedProof = serde_json::from_str(&json).map_err(|e| failed(encoding, e.to_string()))?;
+        if decoded != listed || decoded.hashes() != proof.hashes() {
+            return Err(changed(encoding));
+        }
+    }
+
+    // compressed proofs do not record the tree size
+    let defaults = DefaultHashes::for_leaf_with(&config.hasher(), &Data::new(), proof.hashes().len());
+    let bytes = proof.to_bytes_compressed(&defaults).map_err(|e| failed("compressed", e.to_string()))?;
+    let decoded =
+        OwnedProof::from_bytes_compressed(&bytes, &defaults).map_err(|e| failed("compressed", e.to_string()))?;
+    let decoded = match proof.tree_size() {
+        Some(size) => decoded.with_tree_size(size),
+        None => decoded,
+    };
+    if decoded != *proof {
+        return Err(changed("compressed"));
+    }
+
+    #[cfg(feature = "cbor")]
+    for listed in [proof.clone(), proof.reversed()] {
+        let bytes = listed.to_cbor().map_err(|e| failed("through CBOR", e.to_string()))?;
+        if OwnedProof::from_cbor(&bytes).map_err(|e| failed("through CBOR", e.to_string()))? != listed {
+            return Err(changed("through CBOR"));
+        }
+    }
+    Ok(())
+}
+
+fn violation(invariant: &'static str, detail: String) -> InvariantViolation {
+    InvariantViolation { invariant, detail }
+}
+
+/// Fuzz input read a byte at a time, as zeros once it runs out
+struct FuzzBytes<'a>(&'a [u8]);
+
+impl FuzzBytes<'_> {
+    fn next(&mut self) -> u8 {
+        match self.0.split_first() {
+            Some((byte, rest)) => {
+                self.0 = rest;
+                *byte
+            }
+            None => 0,
+        }
+    }
+}
+
+/// A list of entries as hex strings
+mod hex_entries {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::merkel::Data;
+
+    pub fn serialize<S: Serializer>(entries: &[Data], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(entries.iter().map(hex::encode))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Data>, D::Error> {
+        let entries = Vec::<String>::deserialize(deserializer)?;
+        entries.iter().map(hex::decode).collect::<Result<_, _>>().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skips_mutations_that_change_nothing() {
+        let case = PipelineCase {
+            leaf_mode: LeafMode::HashData,
+            hasher: HashAlgorithm::Sha256,
+            odd_leaf_policy: OddLeafPolicy::Duplicate,
+            canonical_ordering: None,
+            leaves: vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()],
+            // the last leaf is paired with itself
+            prove: vec![2, 3],
+            mutations: vec![
+                Mutation::FlipDirection { step: 0 },
+                Mutation::FlipDirection { step: 1 },
+                Mutation::SwapSteps { a: 1, b: 3 },
+                Mutation::SwapSteps { a: 0, b: 1 },
+                Mutation::OtherEntry { index: 5 },
+                Mutation::OtherEntry { index: 0 },
+                Mutation::Reverse,
+            ],
+        };
+        let run = case.run().unwrap();
+        assert_eq!((run.proofs, run.out_of_range, run.rejected, run.skipped), (1, 1, 4, 3));
+
+        let mut single = case.clone();
+        single.leaves.truncate(1);
+        single.prove = vec![0];
+        let run = single.run().unwrap();
+        // an empty proof has no steps to change, and there is no other leaf to swap in
+        assert_eq!((run.proofs, run.rejected, run.skipped), (1, 0, 7));
+    }
+
+    #[test]
+    fn test_fuzz_bytes() {
+        // pre-hashed, promote, by data, RIPEMD-160; two leaves, one index, no mutations
+        let bytes = [0b0010_1011, 2, 1, 0, 7];
+        let case = PipelineCase::from_fuzz_bytes(&bytes);
+        let config = TreeConfig::new(LeafMode::PreHashed)
+            .with_hasher(HashAlgorithm::Ripemd160)
+            .with_odd_leaf_policy(OddLeafPolicy::Promote)
+            .with_canonical_ordering(CanonicalOrdering::ByData);
+        assert_eq!(case.config(), config);
+        let first: Data = [vec![7], vec![0; 19]].concat();
+        assert_eq!((case.leaves, case.prove, case.mutations), (vec![first, vec![0; 20]], vec![0], vec![]));
+        assert_eq!(PipelineCase::from_fuzz_bytes(&[]).leaves, Vec::<Data>::new());
+    }
+}
@@ -0,0 +1,155 @@
+//! Detached ed25519 signatures of a tree root, behind the `sign` feature
+//!
+//! What is signed is the commitment `sha256(root || leaf_count)`, with the leaf count as 8
+//! big-endian bytes, so a signature also pins the size of the tree: under
+//! `OddLeafPolicy::Duplicate`, `[a, b, c]` and `[a, b, c, c]` have the same root. Keys are
+//! stored as the hex of their 32 bytes, the private key being the ed25519 seed.
+
+use ed25519_dalek::{Signature, Signer, Verifier};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use super::error::LibError;
+use crate::merkel::{Hash, MerkleTree, ProofVersion};
+
+/// A root, its leaf count and the signature of their commitment, as written by `merkle sign-root`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SignedRoot {
+    #[serde(default)]
+    pub version: ProofVersion,
+    #[serde(with = "crate::util::hex_serde")]
+    pub root: Hash,
+    pub leaf_count: u64,
+    #[serde(with = "crate::util::hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+/// The hash a signature covers, `sha256(root || leaf_count)`
+pub fn commitment(root: &Hash, leaf_count: u64) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(root);
+    hasher.update(leaf_count.to_be_bytes());
+    hasher.finalize().to_vec()
+}
+
+/// Signs the commitment to `root` and `leaf_count`
+pub fn sign_commitment(key: &SigningKey, root: &Hash, leaf_count: u64) -> SignedRoot {
+    SignedRoot {
+        version: ProofVersion::CURRENT,
+        root: root.clone(),
+        leaf_count,
+        signature: key.sign(&commitment(root, leaf_count)).to_bytes().to_vec(),
+    }
+}
+
+/// Checks that `signed` was signed by `key`, failing with `LibError::BadSignature` otherwise
+pub fn verify_commitment(key: &VerifyingKey, signed: &SignedRoot) -> Result<(), LibError> {
+    let signature = Signature::from_slice(&signed.signature).map_err(|_| LibError::BadSignature)?;
+    key.verify(&commitment(&signed.root, signed.leaf_count), &signature)
+        .map_err(|_| LibError::BadSignature)
+}
+
+/// Checks the signature, then that `tree` is the tree that was signed
+///
+/// Fails with `LibError::BadSignature` for a signature `key` did not make and
+/// `LibError::RootMismatch` for a validly signed root that is not the root of `tree`.
+pub fn verify_tree(key: &VerifyingKey, signed: &SignedRoot, tree: &MerkleTree) -> Result<(), LibError> {
+    verify_commitment(key, signed)?;
+    let (root, leaf_count) = (tree.root(), tree.len() as u64);
+    if root != signed.root || leaf_count != signed.leaf_count {
+        return Err(LibError::RootMismatch {
+            expected: hex::encode(&signed.root),
+            expected_leaves: signed.leaf_count,
+            found: hex::encode(root),
+            found_leaves: leaf_count,
+        });
+    }
+    Ok(())
+}
+
+/// Parses a private key file, the hex of the 32-byte seed
+pub fn read_signing_key(text: &str) -> Result<SigningKey, LibError> {
+    read_key_bytes(text, "private").map(|bytes| SigningKey::from_bytes(&bytes))
+}
+
+/// Parses a public key file, the hex of the 32-byte key
+pub fn read_verifying_key(text: &str) -> Result<VerifyingKey, LibError> {
+    let bytes = read_key_bytes(text, "public")?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| LibError::InvalidKey("not an ed25519 public key".to_string()))
+}
+
+fn read_key_bytes(text: &str, kind: &str) -> Result<[u8; 32], LibError> {
+    hex::decode(text.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| LibError::InvalidKey(format!("a {} key must be 32 bytes of hex", kind)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::Data;
+
+    fn example_tree(n: usize) -> MerkleTree {
+        let data: Vec<Data> = (0..n).map(|i| vec![i as u8]).collect();
+        MerkleTree::construct(&data)
+    }
+
+    fn keypair(seed: u8) -> (SigningKey, VerifyingKey) {
+        let key = SigningKey::from_bytes(&[seed; 32]);
+        let public = key.verifying_key();
+        (key, public)
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let (key, public) = keypair(1);
+        let tree = example_tree(5);
+        let signed = sign_commitment(&key, &tree.root(), 5);
+        assert_eq!(signed.signature.len(), 64);
+        assert!(verify_commitment(&public, &signed).is_ok());
+        assert!(verify_tree(&public, &signed, &tree).is_ok());
+
+        let json = serde_json::to_string(&signed).unwrap();
+        assert_eq!(serde_json::from_str::<SignedRoot>(&json).unwrap(), signed);
+
+        // another signer, and a leaf count that was not signed
+        assert!(matches!(verify_commitment(&keypair(2).1, &signed), Err(LibError::BadSignature)));
+        let resized = SignedRoot { leaf_count: 6, ..signed.clone() };
+        assert!(matches!(verify_commitment(&public, &resized), Err(LibError::BadSignature)));
+        let truncated = SignedRoot { signature: signed.signature[1..].to_vec(), ..signed.clone() };
+        assert!(matches!(verify_commitment(&public, &truncated), Err(LibError::BadSignature)));
+    }
+
+    #[test]
+    fn test_root_mismatch() {
+        let (key, public) = keypair(1);
+        let signed = sign_commitment(&key, &example_tree(5).root(), 5);
+        let err = verify_tree(&public, &signed, &example_tree(6)).unwrap_err();
+        assert!(matches!(err, LibError::RootMismatch { expected_leaves: 5, found_leaves: 6, .. }));
+
+        // the same root over a different number of leaves is a different tree
+        let mut data: Vec<Data> = (0..3).map(|i| vec![i as u8]).collect();
+        let three = MerkleTree::construct(&data);
+        data.push(data[2].clone());
+        let four = MerkleTree::construct(&data);
+        assert_eq!(three.root(), four.root());
+        let signed = sign_commitment(&key, &three.root(), 3);
+        assert!(matches!(verify_tree(&public, &signed, &four), Err(LibError::RootMismatch { .. })));
+    }
+
+    #[test]
+    fn test_read_keys() {
+        let (key, public) = keypair(7);
+        let text = format!("{}\n", hex::encode(key.to_bytes()));
+        assert_eq!(read_signing_key(&text).unwrap().verifying_key(), public);
+        assert_eq!(read_verifying_key(&hex::encode(public.to_bytes())).unwrap(), public);
+
+        let err = read_signing_key("abcd").unwrap_err();
+        assert_eq!(err.to_string(), "invalid key: a private key must be 32 bytes of hex");
+        assert!(matches!(read_verifying_key("not hex"), Err(LibError::InvalidKey(_))));
+    }
+}
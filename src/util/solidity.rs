@@ -0,0 +1,251 @@
+//! Airdrop claims over a keccak tree that OpenZeppelin's `MerkleProof` can verify on chain,
+//! behind the `solidity` feature
+//!
+//! Leaves are `keccak256(keccak256(abi.encode(address, uint256)))`, like OpenZeppelin's
+//! `StandardMerkleTree`, and every node hashes its two children in sorted order, so proofs carry
+//! no directions. A lone last node is promoted to the next level unchanged.
+
+use serde::Serialize;
+use sha3::{Digest, Keccak256};
+
+use std::collections::HashSet;
+use std::io::BufRead;
+
+use super::error::LibError;
+use crate::merkel::Hash;
+
+/// One recipient of an airdrop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Claim {
+    pub address: [u8; 20],
+    /// Encoded as a `uint256`, though amounts beyond `u128` are not supported
+    pub amount: u128,
+}
+
+/// The claims JSON handed to the frontend: the root and every claim with its proof
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ClaimsFile {
+    #[serde(serialize_with = "serialize_word")]
+    pub root: Hash,
+    pub claims: Vec<ClaimEntry>,
+}
+
+/// One claim of the claims JSON, at its position in the input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ClaimEntry {
+    pub index: usize,
+    #[serde(serialize_with = "serialize_word")]
+    pub address: Vec<u8>,
+    /// Decimal string, since JSON numbers lose precision above 2^53
+    #[serde(serialize_with = "serialize_decimal")]
+    pub amount: u128,
+    #[serde(serialize_with = "serialize_words")]
+    pub proof: Vec<Hash>,
+}
+
+/// Reads `address,amount` lines, with an optional `address,amount` header
+///
+/// Addresses are 0x-prefixed hex and amounts decimal. Blank lines are skipped; anything else that
+/// does not parse fails with its 1-based line number.
+pub fn read_claims_csv<R: BufRead>(reader: R) -> Result<Vec<Claim>, LibError> {
+    let mut claims = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || (i == 0 && line.eq_ignore_ascii_case("address,amount")) {
+            continue;
+        }
+        let invalid = |what: &str| LibError::InvalidFormat(format!("line {}: {}", i + 1, what));
+        let (address, amount) = line.split_once(',').ok_or_else(|| invalid("expected `address,amount`"))?;
+        let address = address.trim().strip_prefix("0x").ok_or_else(|| invalid("address must start with 0x"))?;
+        let address = hex::decode(address)
+            .ok()
+            .and_then(|bytes| <[u8; 20]>::try_from(bytes).ok())
+            .ok_or_else(|| invalid("address must be 20 bytes of hex"))?;
+        let amount = amount.trim().parse().map_err(|_| invalid("amount must be a decimal integer"))?;
+        claims.push(Claim { address, amount });
+    }
+    Ok(claims)
+}
+
+/// Leaf of a claim, `keccak256(bytes.concat(keccak256(abi.encode(address, amount))))`
+pub fn leaf_hash(claim: &Claim) -> Hash {
+    let mut encoded = [0u8; 64];
+    encoded[12..32].copy_from_slice(&claim.address);
+    encoded[48..].copy_from_slice(&claim.amount.to_be_bytes());
+    keccak(&[&keccak(&[&encoded])])
+}
+
+/// Parent of two nodes, hashed in sorted order like `MerkleProof` does
+pub fn node_hash(a: &Hash, b: &Hash) -> Hash {
+    if a <= b {
+        keccak(&[a, b])
+    } else {
+        keccak(&[b, a])
+    }
+}
+
+/// Builds the tree over `claims` in their order and returns every claim with its proof
+///
+/// Fails with `LibError::EmptyInput` for no claims and `LibError::DuplicateLeaf` if an address
+/// appears twice, since a contract usually marks claims as spent by address.
+pub fn build_claims(claims: &[Claim]) -> Result<ClaimsFile, LibError> {
+    if claims.is_empty() {
+        return Err(LibError::EmptyInput);
+    }
+    let mut addresses = HashSet::with_capacity(claims.len());
+    if let Some(index) = claims.iter().position(|claim| !addresses.insert(claim.address)) {
+        return Err(LibError::DuplicateLeaf { index });
+    }
+
+    let mut levels = vec![claims.iter().map(leaf_hash).collect::<Vec<_>>()];
+    while levels[levels.len() - 1].len() > 1 {
+        let parents = levels[levels.len() - 1]
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node_hash(left, right),
+                [lone] => lone.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+        levels.push(parents);
+    }
+
+    let entries = claims
+        .iter()
+        .enumerate()
+        .map(|(index, claim)| ClaimEntry {
+            index,
+            address: claim.address.to_vec(),
+            amount: claim.amount,
+            proof: levels[..levels.len() - 1]
+                .iter()
+                .enumerate()
+                .filter_map(|(k, level)| level.get((index >> k) ^ 1).cloned())
+                .collect(),
+        })
+        .collect();
+    Ok(ClaimsFile {
+        root: levels[levels.len() - 1][0].clone(),
+        claims: entries,
+    })
+}
+
+/// Checks a claim against `root` the way `MerkleProof.verify` does on chain
+pub fn verify_claim(claim: &Claim, proof: &[Hash], root: &Hash) -> bool {
+    &proof.iter().fold(leaf_hash(claim), |hash, sibling| node_hash(&hash, sibling)) == root
+}
+
+/// Solidity source of a contract that checks claims against `root` with OpenZeppelin's `MerkleProof`
+pub fn render_verifier(root: &Hash) -> String {
+    VERIFIER_TEMPLATE.replace("{root}", &format!("0x{}", hex::encode(root)))
+}
+
+const VERIFIER_TEMPLATE: &str = r#"// SPDX-License-Identifier: MIT
+// Generated by `merkle export-solidity`, regenerate it instead of editing
+pragma solidity ^0.8.20;
+
+import {MerkleProof} from "@openzeppelin/contracts/utils/cryptography/MerkleProof.sol";
+
+contract MerkleClaimVerifier {
+    bytes32 public constant MERKLE_ROOT = {root};
+
+    /// @notice Whether `account` may claim `amount`, given its proof from the claims file
+    function verify(bytes32[] calldata proof, address account, uint256 amount) public pure returns (bool) {
+        bytes32 leaf = keccak256(bytes.concat(keccak256(abi.encode(account, amount))));
+        return MerkleProof.verifyCalldata(proof, MERKLE_ROOT, leaf);
+    }
+}
+"#;
+
+fn keccak(parts: &[&[u8]]) -> Hash {
+    let mut hasher = Keccak256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().to_vec()
+}
+
+fn serialize_word<S: serde::Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+}
+
+fn serialize_words<S: serde::Serializer>(words: &[Hash], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(words.iter().map(|word| format!("0x{}", hex::encode(word))))
+}
+
+fn serialize_decimal<S: serde::Serializer>(amount: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&amount.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(byte: u8) -> [u8; 20] {
+        [byte; 20]
+    }
+
+    #[test]
+    fn test_matches_standard_merkle_tree() {
+        assert_eq!(
+            hex::encode(keccak(&[b""])),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        // the example of OpenZeppelin's merkle-tree README
+        let claims = [
+            Claim { address: address(0x11), amount: 5_000_000_000_000_000_000 },
+            Claim { address: address(0x22), amount: 2_500_000_000_000_000_000 },
+        ];
+        let file = build_claims(&claims).unwrap();
+        assert_eq!(
+            hex::encode(&file.root),
+            "d4dee0beab2d53f2cc83e567171bd2820e49898130a22622b10ead383e90bd77"
+        );
+    }
+
+    #[test]
+    fn test_claims_verify() {
+        let claims: Vec<Claim> = (1..=7).map(|i| Claim { address: address(i), amount: i as u128 * 1000 }).collect();
+        let file = build_claims(&claims).unwrap();
+        for (claim, entry) in claims.iter().zip(&file.claims) {
+            assert!(verify_claim(claim, &entry.proof, &file.root));
+            let wrong = Claim { amount: claim.amount + 1, ..*claim };
+            assert!(!verify_claim(&wrong, &entry.proof, &file.root));
+        }
+        // the lone seventh leaf is promoted, so its proof is one sibling shorter
+        assert_eq!((file.claims[0].proof.len(), file.claims[6].proof.len()), (3, 2));
+
+        let json = serde_json::to_value(&file).unwrap();
+        assert_eq!(json["claims"][2]["address"], format!("0x{}", "03".repeat(20)));
+        assert_eq!(json["claims"][2]["amount"], "3000");
+        assert_eq!(json["root"], format!("0x{}", hex::encode(&file.root)));
+
+        let mut duplicate = claims.clone();
+        duplicate.push(claims[1]);
+        assert!(matches!(build_claims(&duplicate), Err(LibError::DuplicateLeaf { index: 7 })));
+        assert!(matches!(build_claims(&[]), Err(LibError::EmptyInput)));
+    }
+
+    #[test]
+    fn test_render_verifier() {
+        let root = build_claims(&[Claim { address: address(1), amount: 1 }]).unwrap().root;
+        let source = render_verifier(&root);
+        assert!(source.contains(&format!("bytes32 public constant MERKLE_ROOT = 0x{};", hex::encode(&root))));
+        assert!(source.contains("function verify(bytes32[] calldata proof, address account, uint256 amount)"));
+    }
+
+    #[test]
+    fn test_read_claims_csv() {
+        let csv = format!("address,amount\n0x{},10\n\n0x{}, 20\n", "ab".repeat(20), "cd".repeat(20));
+        let claims = read_claims_csv(csv.as_bytes()).unwrap();
+        assert_eq!(claims, [Claim { address: [0xab; 20], amount: 10 }, Claim { address: [0xcd; 20], amount: 20 }]);
+
+        let err = read_claims_csv(format!("0x{},ten\n", "ab".repeat(20)).as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "invalid format: line 1: amount must be a decimal integer");
+        let err = read_claims_csv("0xabcd,1\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "invalid format: line 1: address must be 20 bytes of hex");
+    }
+}
@@ -0,0 +1,285 @@
+//! External merge sort of leaf hashes, for putting more leaves into canonical order than fit in
+//! memory at once
+//!
+//! `canonicalize_to_tempfiles` reads hex leaf hashes as `decode_hashes` does and sorts them in
+//! runs that fill the memory budget. A full run is written to a file of its own in the temporary
+//! directory and the next one started. The `SortedRunIterator` then merges the runs back in
+//! ascending order, reading each through an equal share of the budget, so the whole input is
+//! never held at once. Input that fits the budget is sorted in memory and never written out.
+//!
+//! The order is that of `CanonicalOrdering::ByLeafHash` over pre-hashed leaves.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::error::LibError;
+use super::generate::for_each_hash_line;
+use super::hex_policy::HexPolicy;
+use crate::merkel::{Hash, LeafMode, MerkleBuilder, MerkleTree, TreeConfig};
+
+/// Smallest memory budget accepted, so that a run holds more than a handful of hashes
+pub const MIN_MEMORY_BUDGET: usize = 4096;
+
+/// Distinguishes the run files of sorts running at the same time in one process
+static NEXT_SORT: AtomicU64 = AtomicU64::new(0);
+
+/// Sorts the hex leaf hashes `reader` yields, spilling sorted runs to files in `tmp_dir` whenever
+/// more than `memory_budget_bytes` of them would be held
+///
+/// A held hash counts as its bytes and the `Vec` holding them. Every hash must be as long as the
+/// first, failing with `LibError::InvalidLeafLength` otherwise; lines fail as in
+/// `decode_hashes`. Fails with `LibError::InvalidMemoryBudget` below `MIN_MEMORY_BUDGET` and
+/// with `LibError::Io` if a run cannot be written, in which case the runs already written are
+/// removed.
+pub fn canonicalize_to_tempfiles<R: BufRead>(
+    reader: R,
+    tmp_dir: &Path,
+    memory_budget_bytes: usize,
+) -> Result<SortedRunIterator, LibError> {
+    canonicalize_to_tempfiles_with(reader, HexPolicy::Strict, tmp_dir, memory_budget_bytes)
+}
+
+/// Like `canonicalize_to_tempfiles`, accepting the hex `policy` accepts
+pub fn canonicalize_to_tempfiles_with<R: BufRead>(
+    reader: R,
+    policy: HexPolicy,
+    tmp_dir: &Path,
+    memory_budget_bytes: usize,
+) -> Result<SortedRunIterator, LibError> {
+    if memory_budget_bytes < MIN_MEMORY_BUDGET {
+        return Err(LibError::InvalidMemoryBudget { budget: memory_budget_bytes, min: MIN_MEMORY_BUDGET });
+    }
+    let sort = NEXT_SORT.fetch_add(1, Ordering::Relaxed);
+    let mut files = RunFiles(vec![]);
+    let (mut run, mut hash_len, mut leaf_count) = (Vec::<Hash>::new(), None, 0);
+    for_each_hash_line(reader, policy, |_, hash| {
+        let expected = *hash_len.get_or_insert(hash.len());
+        if hash.len() != expected {
+            return Err(LibError::InvalidLeafLength { index: leaf_count, expected, found: hash.len() });
+        }
+        if run.len() == run_capacity(memory_budget_bytes, expected) {
+            files.spill(tmp_dir, sort, &mut run)?;
+        }
+        run.push(hash);
+        leaf_count += 1;
+        Ok(())
+    })?;
+    let hash_len = hash_len.unwrap_or(0);
+    let source = if files.0.is_empty() {
+        run.sort_unstable();
+        Source::Memory(run.into_iter())
+    } else {
+        if !run.is_empty() {
+            files.spill(tmp_dir, sort, &mut run)?;
+        }
+        drop(run);
+        let buffer = (memory_budget_bytes / files.0.len()).max(hash_len);
+        let mut readers = files
+            .0
+            .iter()
+            .map(|path| Ok(BufReader::with_capacity(buffer, File::open(path)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut heads = BinaryHeap::with_capacity(readers.len());
+        for (index, reader) in readers.iter_mut().enumerate() {
+            if let Some(hash) = read_hash(reader, hash_len)? {
+                heads.push(Reverse((hash, index)));
+            }
+        }
+        Source::Runs { readers, heads }
+    };
+    Ok(SortedRunIterator { source, hash_len, leaf_count, files })
+}
+
+/// The hashes of `canonicalize_to_tempfiles` in ascending order, merged from their runs as they
+/// are asked for
+///
+/// The run files are removed when the iterator is dropped. After a failure to read a run the
+/// iterator ends.
+pub struct SortedRunIterator {
+    source: Source,
+    hash_len: usize,
+    leaf_count: usize,
+    files: RunFiles,
+}
+
+enum Source {
+    Memory(std::vec::IntoIter<Hash>),
+    /// The next hash of every run not yet exhausted, with the index of its run
+    Runs {
+        readers: Vec<BufReader<File>>,
+        heads: BinaryHeap<Reverse<(Hash, usize)>>,
+    },
+}
+
+impl SortedRunIterator {
+    /// Number of hashes read, all of which the iterator yields
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Number of runs spilled to files, 0 if the input fit the memory budget
+    pub fn run_count(&self) -> usize {
+        self.files.0.len()
+    }
+
+    /// Builds the tree of `config` over the remaining hashes, as leaves already in canonical order
+    ///
+    /// The hashes are leaf hashes, so `config` must be of `LeafMode::PreHashed`, failing with
+    /// `LibError::InvalidFormat` otherwise. Fails as `MerkleBuilder::add_leaf` does on a hash that
+    /// is not as long as the hashes of the configured hasher, and with `LibError::EmptyInput`
+    /// without any.
+    pub fn build(self, config: TreeConfig) -> Result<MerkleTree, LibError> {
+        if config.leaf_mode() != LeafMode::PreHashed {
+            return Err(LibError::InvalidFormat("sorted runs hold leaf hashes, which need pre-hashed leaves".into()));
+        }
+        let mut builder = MerkleBuilder::new(config);
+        for hash in self {
+            builder.add_leaf(&hash?)?;
+        }
+        builder.build()
+    }
+}
+
+impl Iterator for SortedRunIterator {
+    type Item = Result<Hash, LibError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (readers, heads) = match &mut self.source {
+            Source::Memory(hashes) => return hashes.next().map(Ok),
+            Source::Runs { readers, heads } => (readers, heads),
+        };
+        let Reverse((hash, index)) = heads.pop()?;
+        match read_hash(&mut readers[index], self.hash_len) {
+            Ok(Some(next)) => heads.push(Reverse((next, index))),
+            Ok(None) => {}
+            Err(e) => {
+                self.source = Source::Memory(Vec::new().into_iter());
+                return Some(Err(e.into()));
+            }
+        }
+        Some(Ok(hash))
+    }
+}
+
+/// The run files written so far, removed when dropped
+struct RunFiles(Vec<PathBuf>);
+
+impl RunFiles {
+    /// Sorts `run` and writes it to a new file, leaving `run` empty
+    fn spill(&mut self, tmp_dir: &Path, sort: u64, run: &mut Vec<Hash>) -> io::Result<()> {
+        run.sort_unstable();
+        let path = tmp_dir.join(format!("merkle-run-{}-{}-{}", std::process::id(), sort, self.0.len()));
+        let mut writer = BufWriter::new(File::create_new(&path)?);
+        self.0.push(path);
+        for hash in run.iter() {
+            writer.write_all(hash)?;
+        }
+        writer.flush()?;
+        run.clear();
+        Ok(())
+    }
+}
+
+impl Drop for RunFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Number of hashes of `hash_len` bytes a run holds within `budget`
+fn run_capacity(budget: usize, hash_len: usize) -> usize {
+    (budget / (hash_len + std::mem::size_of::<Hash>())).max(1)
+}
+
+/// The next hash of a run, None at its end
+fn read_hash(reader: &mut BufReader<File>, hash_len: usize) -> io::Result<Option<Hash>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let mut hash = vec![0; hash_len];
+    reader.read_exact(&mut hash)?;
+    Ok(Some(hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{CanonicalOrdering, Data, HashAlgorithm, MerkleHasher};
+
+    /// `n` leaf hashes of `hasher`, in no particular order
+    fn leaves(n: usize, hasher: HashAlgorithm) -> Vec<Data> {
+        (0..n).map(|i| hasher.leaf_hash(format!("leaf {}", i).as_bytes())).collect()
+    }
+
+    fn tmp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("merkle-spill-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn hex_lines(leaves: &[Data]) -> Vec<u8> {
+        leaves.iter().map(|leaf| hex::encode(leaf) + "\n").collect::<String>().into_bytes()
+    }
+
+    #[test]
+    fn test_spilled_sort_matches_in_memory() {
+        let leaves = leaves(100_000, HashAlgorithm::Sha256);
+        let dir = tmp_dir("large");
+        let runs = canonicalize_to_tempfiles(hex_lines(&leaves).as_slice(), &dir, 64 * 1024).unwrap();
+        // 64 KB holds 1170 hashes of 32 bytes, each in a `Vec`
+        assert_eq!((runs.leaf_count(), runs.run_count()), (100_000, 86));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 86);
+
+        let config = TreeConfig::new(LeafMode::PreHashed);
+        let expected = config.with_canonical_ordering(CanonicalOrdering::ByLeafHash).construct(&leaves).unwrap();
+        assert_eq!(runs.build(config).unwrap().root(), expected.root());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merges_in_order() {
+        let mut leaves = leaves(5_000, HashAlgorithm::Ripemd160);
+        let repeated = leaves[..100].to_vec();
+        leaves.extend(repeated);
+        let dir = tmp_dir("order");
+        let runs = canonicalize_to_tempfiles(hex_lines(&leaves).as_slice(), &dir, MIN_MEMORY_BUDGET).unwrap();
+        assert!(runs.run_count() > 1);
+        let merged: Vec<Hash> = runs.collect::<Result<_, _>>().unwrap();
+        leaves.sort();
+        assert_eq!(merged, leaves);
+
+        // input that fits the budget is never written out
+        let small = canonicalize_to_tempfiles(hex_lines(&leaves[..10]).as_slice(), &dir, 1 << 20).unwrap();
+        assert_eq!((small.run_count(), fs::read_dir(&dir).unwrap().count()), (0, 0));
+        let ripemd160 = TreeConfig::new(LeafMode::PreHashed).with_hasher(HashAlgorithm::Ripemd160);
+        assert_eq!(small.build(ripemd160).unwrap().root(), ripemd160.construct(&leaves[..10]).unwrap().root());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        let dir = tmp_dir("bad");
+        let tiny = canonicalize_to_tempfiles(&b""[..], &dir, 100);
+        assert!(matches!(tiny, Err(LibError::InvalidMemoryBudget { budget: 100, min: MIN_MEMORY_BUDGET })));
+
+        let mut lines = hex_lines(&leaves(1_000, HashAlgorithm::Sha256));
+        lines.extend_from_slice(b"abcd\n");
+        let mixed = canonicalize_to_tempfiles(lines.as_slice(), &dir, MIN_MEMORY_BUDGET);
+        assert!(matches!(mixed, Err(LibError::InvalidLeafLength { index: 1000, expected: 32, found: 2 })));
+        // the runs written before the failure are gone
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        let empty = canonicalize_to_tempfiles(&b"\n\n"[..], &dir, MIN_MEMORY_BUDGET).unwrap();
+        assert!(matches!(empty.build(TreeConfig::new(LeafMode::PreHashed)), Err(LibError::EmptyInput)));
+        let runs = canonicalize_to_tempfiles(hex_lines(&[vec![1; 32]]).as_slice(), &dir, MIN_MEMORY_BUDGET).unwrap();
+        assert!(matches!(runs.build(TreeConfig::default()), Err(LibError::InvalidFormat(_))));
+        fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+//! Hashes read from a SQLite database, behind the `sqlite` feature
+//!
+//! A source is either a query, which must have an `ORDER BY` clause since the order of the rows
+//! is part of the root, or the name of a table, read in `rowid` order. Each row holds one hash,
+//! as hex text or as a blob of its bytes. Rows are numbered from 1 in errors, where line numbers
+//! would be for a hash file.
+
+use std::path::Path;
+
+use rusqlite::types::ValueRef;
+use rusqlite::OpenFlags;
+
+pub use rusqlite::Connection;
+
+use super::error::LibError;
+use super::hex_policy::{decode_hex, HexPolicy};
+use crate::merkel::Hash;
+
+impl From<rusqlite::Error> for LibError {
+    fn from(error: rusqlite::Error) -> Self {
+        LibError::Database(error.to_string())
+    }
+}
+
+/// Opens the database at `path` read-only
+pub fn open_sqlite<P: AsRef<Path>>(path: P) -> Result<Connection, LibError> {
+    Ok(Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?)
+}
+
+/// Reads one hash per row of `query_or_table` in the database at `path`
+///
+/// `column` names the column holding the hashes, the first column if None. Hex text is read under
+/// `HexPolicy::Strict`; see `for_each_sqlite_hash`.
+pub fn read_hashes_from_sqlite<P: AsRef<Path>>(
+    path: P,
+    query_or_table: &str,
+    column: Option<&str>,
+) -> Result<Vec<Hash>, LibError> {
+    let mut hashes = Vec::new();
+    for_each_sqlite_hash(&open_sqlite(path)?, query_or_table, column, HexPolicy::Strict, |hash| hashes.push(hash))?;
+    Ok(hashes)
+}
+
+/// Calls `f` with the hash of every row in order as it is read, returning the number of rows
+///
+/// Fails with `LibError::InvalidFormat` for a query without `ORDER BY` or a value that is neither
+/// text nor a blob, `LibError::InvalidHex` for text that is not hex under `policy` and
+/// `LibError::Database` for SQL that does not run. Whether a query is ordered is decided from its
+/// text, so an `ORDER BY` that only orders a subquery is not caught.
+pub fn for_each_sqlite_hash<F: FnMut(Hash)>(
+    connection: &Connection,
+    query_or_table: &str,
+    column: Option<&str>,
+    policy: HexPolicy,
+    mut f: F,
+) -> Result<usize, LibError> {
+    let (sql, is_query) = source_sql(query_or_table, column)?;
+    let mut statement = connection.prepare(&sql)?;
+    let index = match column {
+        Some(column) if is_query => statement.column_index(column)?,
+        _ => 0,
+    };
+
+    let mut rows = statement.query([])?;
+    let mut row = 0;
+    while let Some(values) = rows.next()? {
+        row += 1;
+        f(decode_value(values.get_ref(index)?, row, policy)?);
+    }
+    Ok(row)
+}
+
+/// The SQL reading `source`, and whether `source` was a query rather than a table name
+fn source_sql(source: &str, column: Option<&str>) -> Result<(String, bool), LibError> {
+    let words: Vec<String> = source.split_whitespace().map(str::to_ascii_lowercase).collect();
+    if !matches!(words.first().map(String::as_str), Some("select" | "with")) {
+        let column = column.map_or("*".to_string(), quote_identifier);
+        return Ok((format!("SELECT {} FROM {} ORDER BY rowid", column, quote_identifier(source)), false));
+    }
+    if !words.windows(2).any(|pair| pair[0] == "order" && pair[1] == "by") {
+        return Err(LibError::InvalidFormat(
+            "the query has no ORDER BY, so the order of its rows and the root are not defined".into(),
+        ));
+    }
+    Ok((source.to_string(), true))
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn decode_value(value: ValueRef, row: usize, policy: HexPolicy) -> Result<Hash, LibError> {
+    match value {
+        ValueRef::Blob(bytes) => Ok(bytes.to_vec()),
+        ValueRef::Text(text) => {
+            let text = std::str::from_utf8(text).map_err(|_| LibError::InvalidUtf8 { line: row })?;
+            decode_hex(text, policy).map_err(|source| LibError::InvalidHex { line: row, source })
+        }
+        other => Err(LibError::InvalidFormat(format!(
+            "row {}: expected hex text or a blob, found {}",
+            row,
+            other.data_type()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkel::{LeafMode, TreeConfig};
+    use crate::util::generate::decode_hashes;
+
+    const HASHES: &str = include_str!("../../tests/fixtures/hashes.txt");
+
+    /// `txs` holds the hashes of `hashes.txt` as hex, inserted out of order, and `blobs` holds
+    /// their bytes in order
+    fn fixture() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch("CREATE TABLE txs (id INTEGER, txid TEXT); CREATE TABLE blobs (hash BLOB);").unwrap();
+        let lines: Vec<&str> = HASHES.lines().collect();
+        for id in (0..lines.len()).rev() {
+            connection.execute("INSERT INTO txs VALUES (?1, ?2)", (id as i64, lines[id])).unwrap();
+        }
+        for line in &lines {
+            connection.execute("INSERT INTO blobs VALUES (?1)", [hex::decode(line).unwrap()]).unwrap();
+        }
+        connection
+    }
+
+    fn read(connection: &Connection, source: &str, column: Option<&str>) -> Result<Vec<Hash>, LibError> {
+        let mut hashes = Vec::new();
+        let rows = for_each_sqlite_hash(connection, source, column, HexPolicy::Strict, |hash| hashes.push(hash))?;
+        assert_eq!(rows, hashes.len());
+        Ok(hashes)
+    }
+
+    #[test]
+    fn test_sources_match_the_hash_file() {
+        let expected = decode_hashes(HASHES.as_bytes()).unwrap();
+        let connection = fixture();
+        let query = "SELECT id, txid FROM txs ORDER BY id";
+        assert_eq!(read(&connection, query, Some("txid")).unwrap(), expected);
+        assert_eq!(read(&connection, "select txid\nfrom txs\norder\tby id", None).unwrap(), expected);
+        assert_eq!(read(&connection, "blobs", None).unwrap(), expected);
+        assert_eq!(read(&connection, "blobs", Some("hash")).unwrap(), expected);
+
+        // a table is read in insertion order, here the reverse of the file
+        let mut reversed = read(&connection, "txs", Some("txid")).unwrap();
+        reversed.reverse();
+        assert_eq!(reversed, expected);
+
+        let config = TreeConfig::new(LeafMode::PreHashed);
+        let root = config.construct(&read(&connection, query, Some("txid")).unwrap()).unwrap().root();
+        assert_eq!(root, config.construct(&expected).unwrap().root());
+    }
+
+    #[test]
+    fn test_rejections() {
+        let connection = fixture();
+        let unordered = read(&connection, "SELECT txid FROM txs", None).unwrap_err();
+        assert!(unordered.to_string().contains("no ORDER BY"), "{}", unordered);
+        let unknown = read(&connection, "SELECT txid FROM txs ORDER BY id", Some("hash"));
+        assert!(matches!(unknown, Err(LibError::Database(_))));
+        assert!(matches!(read(&connection, "missing", None), Err(LibError::Database(_))));
+
+        connection.execute("UPDATE txs SET txid = upper(txid) WHERE id = 2", []).unwrap();
+        let uppercase = read(&connection, "SELECT txid FROM txs ORDER BY id", None);
+        assert!(matches!(uppercase, Err(LibError::InvalidHex { line: 3, .. })));
+        let mut lenient = 0;
+        let query = "SELECT txid FROM txs ORDER BY id";
+        for_each_sqlite_hash(&connection, query, None, HexPolicy::Lenient, |_| lenient += 1).unwrap();
+        assert_eq!(lenient, 7);
+
+        let numbers = read(&connection, "SELECT id FROM txs ORDER BY id", None).unwrap_err();
+        assert_eq!(numbers.to_string(), "invalid format: row 1: expected hex text or a blob, found Integer");
+    }
+
+    #[test]
+    fn test_read_from_file() {
+        let path = std::env::temp_dir().join(format!("merkle-sqlite-{}.db", std::process::id()));
+        fixture().execute("VACUUM INTO ?1", [path.to_str().unwrap()]).unwrap();
+        let hashes = read_hashes_from_sqlite(&path, "SELECT txid FROM txs ORDER BY id", None).unwrap();
+        assert_eq!(hashes, decode_hashes(HASHES.as_bytes()).unwrap());
+
+        // the database is opened read-only
+        let connection = open_sqlite(&path).unwrap();
+        assert!(connection.execute("DELETE FROM txs", []).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(read_hashes_from_sqlite(&path, "txs", None), Err(LibError::Database(_))));
+    }
+}
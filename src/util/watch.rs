@@ -0,0 +1,159 @@
+//! Recomputing the root of a hash file whenever it changes, behind the `watch` feature
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use std::fs::File;
+use std::io::BufReader;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use super::error::LibError;
+use super::generate::decode_hashes;
+use crate::merkel::{Hash, LeafMode, MerkleTree, TreeConfig};
+
+/// How long the file has to stay quiet after a change before its root is recomputed
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Root of the watched file after it was read
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RootUpdate {
+    pub root: Hash,
+    pub leaf_count: usize,
+    /// Root of the previous successful read, None for the first one
+    pub previous_root: Option<Hash>,
+    /// Indices of the leaves that differ from the previous read, including added and removed ones
+    pub changed_leaves: Vec<usize>,
+}
+
+impl RootUpdate {
+    /// Whether the root differs from the previous one
+    pub fn changed(&self) -> bool {
+        self.previous_root.as_ref().is_some_and(|previous| previous != &self.root)
+    }
+}
+
+/// Calls `callback` with the root of the hash file at `path`, then again after every change to it
+///
+/// Bursts of events, like an editor writing and renaming the file, are debounced by
+/// `WATCH_DEBOUNCE`. A read that fails, e.g. of a half-written file, is passed to the callback as
+/// an error and watching continues. Returns once the callback breaks.
+pub fn watch_root<P, F>(path: P, mut callback: F) -> Result<(), LibError>
+where
+    P: AsRef<Path>,
+    F: FnMut(Result<RootUpdate, LibError>) -> ControlFlow<()>,
+{
+    let path = path.as_ref();
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+    // editors often replace the file instead of writing to it, so the directory is watched
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    watcher.watch(dir, RecursiveMode::NonRecursive).map_err(watch_error)?;
+
+    let mut previous: Option<MerkleTree> = None;
+    loop {
+        let update = read_tree(path).map(|tree| {
+            let update = RootUpdate {
+                root: tree.root(),
+                leaf_count: tree.len(),
+                previous_root: previous.as_ref().map(MerkleTree::root),
+                changed_leaves: previous.as_ref().map(|old| changed_leaves(old, &tree)).unwrap_or_default(),
+            };
+            previous = Some(tree);
+            update
+        });
+        if callback(update).is_break() {
+            return Ok(());
+        }
+
+        while !touches(&events.recv().map_err(watch_error)?.map_err(watch_error)?, path) {}
+        let mut quiet_until = Instant::now() + WATCH_DEBOUNCE;
+        while let Ok(event) = events.recv_timeout(quiet_until.saturating_duration_since(Instant::now())) {
+            if touches(&event.map_err(watch_error)?, path) {
+                quiet_until = Instant::now() + WATCH_DEBOUNCE;
+            }
+        }
+    }
+}
+
+fn read_tree(path: &Path) -> Result<MerkleTree, LibError> {
+    let leaves = decode_hashes(BufReader::new(File::open(path)?))?;
+    TreeConfig::new(LeafMode::PreHashed).construct(&leaves)
+}
+
+/// Whether the event may have changed the contents of the watched file
+fn touches(event: &Event, path: &Path) -> bool {
+    !matches!(event.kind, EventKind::Access(_)) && event.paths.iter().any(|p| p.file_name() == path.file_name())
+}
+
+fn changed_leaves(old: &MerkleTree, new: &MerkleTree) -> Vec<usize> {
+    (0..old.len().max(new.len()))
+        .filter(|&index| old.leaves().get(index) != new.leaves().get(index))
+        .collect()
+}
+
+fn watch_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> LibError {
+    LibError::Io(std::io::Error::other(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc::Sender;
+    use std::thread;
+
+    const LEAF_A: &str = "084fed08b978af4d7d196a7446a86b58009e636b611db16211b65a9aadff29c5";
+    const LEAF_B: &str = "dbc1b4c900ffe48d575b5da5c638040125f65db0fe3e24494b76ea986457d986";
+
+    fn root_of(lines: &[&str]) -> Hash {
+        let leaves: Vec<Hash> = lines.iter().map(|line| hex::decode(line).unwrap()).collect();
+        MerkleTree::from_leaf_hashes(leaves).root()
+    }
+
+    /// Runs `watch_root` on its own thread, forwarding every update
+    fn spawn_watcher(path: &Path, updates: Sender<Result<RootUpdate, String>>) {
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            watch_root(&path, |update| match updates.send(update.map_err(|e| e.to_string())) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
+            })
+            .unwrap()
+        });
+    }
+
+    #[test]
+    fn test_reports_updated_root() {
+        let dir = std::env::temp_dir().join(format!("merkle-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hashes.txt");
+        std::fs::write(&path, format!("{}\n{}\n", LEAF_A, LEAF_B)).unwrap();
+
+        let (sender, updates) = mpsc::channel();
+        spawn_watcher(&path, sender);
+        let recv = || updates.recv_timeout(Duration::from_secs(10)).unwrap();
+
+        let first = recv().unwrap();
+        assert_eq!((first.root.clone(), first.leaf_count), (root_of(&[LEAF_A, LEAF_B]), 2));
+        assert!(!first.changed() && first.changed_leaves.is_empty());
+
+        std::fs::write(&path, format!("{}\n{}\n{}\n", LEAF_A, LEAF_A, LEAF_B)).unwrap();
+        let second = recv().unwrap();
+        assert_eq!(second.root, root_of(&[LEAF_A, LEAF_A, LEAF_B]));
+        assert_eq!(second.previous_root, Some(first.root));
+        assert!(second.changed());
+        assert_eq!(second.changed_leaves, vec![1, 2]);
+
+        // a broken file is reported and the watch goes on
+        std::fs::write(&path, "zz\n").unwrap();
+        assert!(recv().unwrap_err().contains("invalid hex"));
+        std::fs::write(&path, format!("{}\n", LEAF_B)).unwrap();
+        let third = recv().unwrap();
+        assert_eq!((third.root.clone(), third.changed_leaves), (root_of(&[LEAF_B]), vec![0, 1, 2]));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
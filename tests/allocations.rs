@@ -0,0 +1,105 @@
+//! Counts heap allocations of proof generation, verification and tree construction with a counting
+//! global allocator
+
+use merkle::merkel::{
+    verify_proof_raw, Data, Hash, HashAlgorithm, HashDirection, LeafMode, MerkleHasher, MerkleTree, TreeArena,
+    TreeConfig, HASH_LEN, PROOF_INLINE_STEPS,
+};
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Runs `f` and returns its result with the number of allocations it made on this thread
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+#[test]
+fn test_proofs_allocate_a_constant_amount() {
+    let leaves: Vec<Hash> = (0..1u32 << 20).map(|i| [i.to_be_bytes(); 8].concat()).collect();
+    let tree = MerkleTree::from_leaf_hashes(leaves);
+    assert_eq!(tree.depth(), 20);
+    assert!(tree.depth() <= PROOF_INLINE_STEPS);
+
+    for index in [0, 1, 4_097, 524_288, (1 << 20) - 1] {
+        let (proof, allocations) = count_allocations(|| tree.prove_by_index(index).unwrap());
+        assert_eq!((proof.len(), allocations), (20, 0), "leaf {}", index);
+
+        // owning the proof copies its hashes inline, so up to `PROOF_INLINE_STEPS` steps it allocates nothing
+        let (owned, allocations) = count_allocations(|| proof.into_owned());
+        assert_eq!((owned.hashes().len(), allocations), (20, 0));
+        let (verified, allocations) = count_allocations(|| owned.as_proof().len());
+        assert_eq!((verified, allocations), (20, 0));
+    }
+}
+
+#[test]
+fn test_arena_builds_allocate_nothing() {
+    let blocks: Vec<Vec<Data>> =
+        (0..20u32).map(|block| (0..128u32).map(|i| [block, i].map(u32::to_be_bytes).concat()).collect()).collect();
+    let mut arena = TreeArena::with_capacity(128);
+    for config in [TreeConfig::default(), TreeConfig::default().with_hasher(HashAlgorithm::Sha512)] {
+        for block in &blocks {
+            let (root, allocations) =
+                count_allocations(|| config.construct_in(block, &mut arena).unwrap().root().len());
+            assert_eq!((root, allocations), (config.hasher().output_len(), 0));
+        }
+        // a plain build allocates every hash
+        let (_, allocations) = count_allocations(|| config.construct(&blocks[0]).unwrap());
+        assert!(allocations > 255, "{}", allocations);
+    }
+
+    // a tree past the capacity grows the arena once
+    let config = TreeConfig::new(LeafMode::PreHashed).with_hasher(HashAlgorithm::Sha512);
+    let txids: Vec<Hash> = blocks[..2].concat().iter().map(|data| HashAlgorithm::Sha512.leaf_hash(data)).collect();
+    let (_, allocations) = count_allocations(|| config.construct_in(&txids, &mut arena).map(|tree| tree.len()));
+    assert_eq!(allocations, 1);
+    let (len, allocations) = count_allocations(|| config.construct_in(&txids, &mut arena).unwrap().len());
+    assert_eq!((len, allocations), (256, 0));
+}
+
+#[test]
+fn test_verification_allocates_nothing() {
+    let data: Vec<Data> = (0..1000u32).map(|i| i.to_be_bytes().to_vec()).collect();
+    let tree = MerkleTree::construct(&data);
+    let root: [u8; HASH_LEN] = tree.root().try_into().unwrap();
+    for index in [0, 1, 511, 999] {
+        let proof = tree.prove_by_index(index).unwrap();
+        let owned = tree.prove_by_index(index).unwrap().into_owned();
+        let path: Vec<(HashDirection, [u8; HASH_LEN])> =
+            owned.hashes().iter().map(|(direction, hash)| (*direction, hash[..].try_into().unwrap())).collect();
+        let leaf: [u8; HASH_LEN] = tree.leaves()[index].clone().try_into().unwrap();
+        let (verified, allocations) = count_allocations(|| verify_proof_raw(&leaf, &path, &root));
+        assert_eq!((verified, allocations), (true, 0), "leaf {}", index);
+
+        // the higher-level verifiers fold the proof the same way
+        let root = tree.root();
+        let (verified, allocations) = count_allocations(|| MerkleTree::verify_proof(&data[index], &proof, &root));
+        assert_eq!((verified, allocations), (true, 0));
+        let (verified, allocations) =
+            count_allocations(|| MerkleTree::verify_proof_hash(&tree.leaves()[index], &proof, &root));
+        assert_eq!((verified, allocations), (true, 0));
+    }
+}
@@ -0,0 +1,772 @@
+//! Compiles every documented call pattern using public imports only
+//!
+//! A signature change or an accidentally privatized item breaks this file before it breaks users.
+
+use merkle::merkel::{
+    canonical_json_leaf, canonicalize_leaves, checked_node_count, detect_scheme, expected_proof_len, fold_root,
+    peak_rss_bytes, verify_proof_raw, AbsenceProof, ArenaTree, BuildMetrics, CacheStats, CanonicalOrdering,
+    CompositeProof, ConsistencyProof, DefaultHashes, EmptyLeafPolicy, FailurePoint, IndexedProof, MemoryReport,
+    MerkleBuilder, MerkleForest, MetricsCollector, MultiCommitment, MultiRoots, MultisetCommitment, Neighbor,
+    NodeEvent, ObservedConfig, OddLeafPolicy, PairOrder, PairProof, PartialTree, ProofFailure, ProofOrder,
+    ProofService, ProofVerifySink, ProofVersion, PruneSpec, PrunedTree, ResolveError, Rfc6962Hasher, Ripemd160Hasher,
+    RootSet, SchemeGuess, ServiceError, ServiceLimits, ServiceStats, Sha256dHasher, Sha512Hasher, SinkItem,
+    SinkSummary, SortedMerkleTree, SourceId, StepHash, SubtreeCache, SumProof, SumStep, SumTree, TreeArena,
+    TreeDescriptor, TreeShape, TreeSnapshot, Verification, VerifyContext, VerifyWarning, CRATE_VERSION,
+    DEFAULT_YIELD_EVERY, HASHER_ID, HASH_LEN, MAX_HASH_LEN, MAX_PROOF_STEPS, MIN_ABBREVIATION_LEN, PROOF_INLINE_STEPS,
+    SINK_BLOCKING_LEAF_LEN, SNAPSHOT_CHUNK,
+};
+use merkle::prelude::*;
+use merkle::util::archive::{ProofArchive, ARCHIVE_MAGIC};
+use merkle::util::audit::{AuditEvent, AuditLog, Epoch, EventProof};
+use merkle::util::bundle::{
+    verify_bundle_parallel, verify_bundle_parallel_with, BundleHeader, BundleReport, BundleRow, MalformedRow,
+    ProofBundle,
+};
+use merkle::util::compat::FlatProof;
+use merkle::util::diff::{diff_files, diff_trees, DiffKind, DiffOptions, DiffReport, DIFF_MAX_REPORTED};
+use merkle::util::export::{export_proofs_jsonl, ExportSummary, EXPORT_FLUSH_EVERY};
+use merkle::util::encoding::{Encoding, HashEncoding};
+use merkle::util::error::ExitStatus;
+use merkle::util::estimate::{
+    calibrate, count_lines, estimate_build, BuildEstimate, Calibration, LineCount, CALIBRATION_SAMPLES,
+};
+use merkle::util::file::{estimated_tree_bytes, ChunkLimits, MerkleFile, DEFAULT_MAX_LEAVES, DEFAULT_MIN_CHUNK_SIZE};
+use merkle::util::generate::{
+    decode_hashes, decode_hashes_lenient, decode_hashes_lenient_of_len, decode_hashes_lenient_with, decode_hashes_with,
+    read_hashes, read_hashes_from_file, read_hashes_with, RejectReason, RejectedLine,
+};
+use merkle::util::hex_policy::{parse_hex_lenient, parse_hex_strict, HexPolicy};
+use merkle::util::io::{remove_orphaned_temp, temp_path, write_atomic, write_file_atomic, TEMP_SUFFIX};
+use merkle::util::journal::{JournaledTree, JOURNAL_MAGIC};
+use merkle::util::lint::{lint_hashes, lint_hashes_of_len, LintReport};
+use merkle::util::path::canonical_rel_path;
+use merkle::util::spill::{
+    canonicalize_to_tempfiles, canonicalize_to_tempfiles_with, SortedRunIterator, MIN_MEMORY_BUDGET,
+};
+
+type Source = (SourceId, Vec<Data>);
+
+fn data(n: u8) -> Vec<Data> {
+    (0..n).map(|i| vec![i]).collect()
+}
+
+#[test]
+fn test_signatures() {
+    let _: fn(&[Data]) -> MerkleTree = MerkleTree::construct;
+    let _: fn(Vec<Hash>) -> MerkleTree = MerkleTree::from_leaf_hashes;
+    let _: fn(&[Data]) -> Vec<Vec<Hash>> = MerkleTree::construct_with_levels;
+    let _: fn(Vec<Data>) -> MerkleTree = MerkleTree::construct_retaining;
+    let _: fn(&MerkleTree, usize) -> Option<&[u8]> = MerkleTree::leaf_data;
+    let _: fn(&mut MerkleTree) = MerkleTree::wipe_leaf_data;
+    let _: fn(&MerkleTree) -> TreeSnapshot = MerkleTree::snapshot;
+    let _: fn(&[Data], usize, usize) -> Result<PartialTree, LibError> = MerkleTree::construct_partial;
+    let _: fn(Vec<PartialTree>) -> Result<MerkleTree, LibError> = MerkleTree::join;
+    let _: fn(&TreeSnapshot, &Data) -> TreeSnapshot = TreeSnapshot::push;
+    let _: fn(&TreeSnapshot, usize, &Data) -> Result<TreeSnapshot, LibError> = TreeSnapshot::update;
+    let _: fn(&[Source]) -> Result<MerkleTree, LibError> = MerkleTree::construct_from_sources;
+    let _: fn(&[Data], &MerkleTree) -> Option<usize> = MerkleTree::find_divergence;
+    let tree = MerkleTree::construct(&data(3));
+    let proven: Vec<Result<(usize, OwnedProof), LibError>> = tree.prove_many(&[data(3)[2].clone(), vec![9]]);
+    assert!(matches!((&proven[0], &proven[1]), (Ok((2, _)), Err(LibError::LeafNotFound(_)))));
+    assert_eq!(tree.prove_many_leaves(&tree.leaves()[..1]).len(), 1);
+    let _: fn(&MerkleTree, &MerkleTree) -> Option<usize> = MerkleTree::first_difference;
+    let _: fn(&MerkleTree, usize) -> Option<&SourceId> = MerkleTree::source_of;
+    let _: fn(&[Data], &Hash) -> bool = MerkleTree::verify_ordered;
+    let _: fn(&[Data], &Hash) -> bool = MerkleTree::verify_multiset;
+    let _: fn(&Data, &Proof, &Hash) -> bool = MerkleTree::verify_proof;
+    let _: fn(&Hash, &Proof, &Hash) -> bool = MerkleTree::verify_proof_hash;
+    let _: fn(&Hash, &Proof, usize, usize, &Hash) -> Result<(), ProofFailure> = MerkleTree::verify_proof_detailed;
+    let _: fn(&MerkleTree, &Hash, &Proof, usize, &Hash) -> Result<(), ProofFailure> = MerkleTree::explain_proof;
+    let _: fn(&Data, &Proof, usize, usize, &Hash) -> bool = MerkleTree::verify_proof_at_index;
+    let _: fn(&HashAlgorithm, &Hash, &Proof, usize, usize, &Hash) -> bool = MerkleTree::verify_proof_hash_at_index_with;
+    let _: fn(&Data, &Proof, &Hash, usize) -> bool = MerkleTree::verify_proof_to_anchor;
+    let _: fn(&Data, &Proof, usize, usize, &Hash, usize) -> bool = MerkleTree::verify_proof_to_anchor_at_index;
+    let (partial, anchor): (Proof, &Hash) = tree.prove_to_level(2, 1).unwrap();
+    assert!(MerkleTree::verify_proof_to_anchor(&data(3)[2], &partial, anchor, 1));
+    let _: fn(&Data, &ChainedProof, &Hash) -> bool = MerkleTree::verify_chained;
+    let _: fn(&Data, &Proof, &[Hash]) -> Option<usize> = MerkleTree::verify_proof_multi_root;
+    let _: fn(&RootSet, &Data, &Proof) -> Result<Option<usize>, LibError> = RootSet::verify_proof;
+    let _: fn(&Data, &CompositeProof, &Hash) -> bool = MerkleForest::verify_composite;
+    let _: fn(&Data, &AbsenceProof, &Hash, usize) -> bool = SortedMerkleTree::verify_absence;
+    let _: fn(Vec<(HashDirection, Hash)>) -> OwnedProof = OwnedProof::new;
+    let _: fn(&OwnedProof) -> Result<IndexedProof, LibError> = OwnedProof::to_indexed;
+    let _: fn(usize, usize, Vec<Hash>) -> Result<IndexedProof, LibError> = IndexedProof::new;
+    let _: fn(LeafIndex, TreeSize, Vec<Hash>) -> Result<IndexedProof, LibError> = IndexedProof::new;
+    let _: fn(LeafIndex) -> LeafIndex = LeafIndex::sibling;
+    let _: fn(LeafIndex, TreeSize) -> Option<LeafIndex> = LeafIndex::sibling_in;
+    let _: fn(&IndexedProof) -> OwnedProof = IndexedProof::to_proof;
+    let _: fn(&Data, usize) -> DefaultHashes = DefaultHashes::for_leaf;
+    let _: fn(&OwnedProof, &DefaultHashes) -> Result<Vec<u8>, LibError> = OwnedProof::to_bytes_compressed;
+    let _: fn(&[u8], &DefaultHashes) -> Result<OwnedProof, LibError> = OwnedProof::from_bytes_compressed;
+    let _: fn(OwnedProof, HashAlgorithm) -> OwnedProof = OwnedProof::with_hasher;
+    let _: fn(&OwnedProof) -> Option<HashAlgorithm> = OwnedProof::hasher;
+    let _: fn(OwnedProof, LeafMode) -> OwnedProof = OwnedProof::with_leaf_mode;
+    let _: fn(&OwnedProof) -> Option<LeafMode> = OwnedProof::leaf_mode;
+    let _: fn(LeafMode) -> TreeConfig = TreeConfig::new;
+    let _: fn(MerkleTree, usize) -> ProofCache = ProofCache::new;
+    let _: fn(&MerkleTree, usize) -> Result<usize, LibError> = MerkleTree::proof_len;
+    let _: fn(usize, usize, OddLeafPolicy) -> usize = expected_proof_len;
+    let _: fn(u64) -> Result<u64, LibError> = checked_node_count;
+    let _: fn(u64) -> Result<ProofVersion, LibError> = ProofVersion::from_number;
+    let _: fn(HashDirection) -> HashDirection = HashDirection::opposite;
+    let _: fn(HashDirection, &Sha256Hasher, &[u8], &[u8]) -> Hash = HashDirection::combine;
+    let _: fn(HashDirection) -> bool = HashDirection::to_bit;
+    let _: fn(bool) -> HashDirection = HashDirection::from_bit;
+    assert_eq!((HASH_LEN, HASHER_ID, DEFAULT_YIELD_EVERY, PROOF_INLINE_STEPS), (32, "sha256", 1024, 40));
+    assert_eq!(MAX_PROOF_STEPS, usize::BITS as usize);
+    assert_eq!(ProofVersion::CURRENT.number(), 2);
+    assert_eq!(Sha256Hasher.id(), HASHER_ID);
+    let leaf = Sha256Hasher.leaf_hash(&[1]);
+    assert_eq!(Sha256Hasher.node_hash(&leaf, &leaf).len(), HASH_LEN);
+}
+
+#[test]
+fn test_standard_traits() {
+    fn ordered<T: Eq + std::hash::Hash + Ord + Clone>() {}
+    fn hashable<T: Eq + std::hash::Hash + Clone>() {}
+    ordered::<Hash>();
+    ordered::<HashDirection>();
+    ordered::<OwnedProof>();
+    ordered::<LeafIndex>();
+    ordered::<TreeSize>();
+    hashable::<Proof<'static>>();
+    hashable::<CompositeProof>();
+    hashable::<AbsenceProof>();
+    hashable::<Neighbor>();
+}
+
+#[test]
+fn test_hashers() {
+    let _: fn(TreeConfig, HashAlgorithm) -> TreeConfig = TreeConfig::with_hasher;
+    let _: fn(&MerkleTree) -> HashAlgorithm = MerkleTree::hasher;
+    let _: fn(&str) -> Option<HashAlgorithm> = HashAlgorithm::from_id;
+    let _: fn(&HashAlgorithm, &Hash, &Proof, &Hash) -> bool = MerkleTree::verify_proof_hash_with;
+    let _: fn(&Sha512Hasher, &Hash, &Proof, &[Hash]) -> Option<usize> = MerkleTree::verify_proof_hash_multi_root_with;
+    let _: fn(&Ripemd160Hasher, &Data, usize) -> DefaultHashes = DefaultHashes::for_leaf_with;
+    let _: fn(&'static [u8], usize) -> Result<LintReport, LibError> = lint_hashes_of_len;
+    assert_eq!((Sha512Hasher.output_len(), Ripemd160Hasher.output_len()), (64, 20));
+
+    let tree = TreeConfig::default().with_hasher(HashAlgorithm::Sha512).construct(&data(3)).unwrap();
+    let hasher: &dyn MerkleHasher = &tree.hasher();
+    let proof = tree.prove_by_index(2).unwrap();
+    assert_eq!(proof.root_from_with(hasher, &tree.leaves()[2]), tree.root());
+    let mut roots = RootSet::new();
+    roots.insert(tree.root(), "");
+    let matched: Result<Option<usize>, LibError> = roots.verify_proof_hash_with(hasher, &tree.leaves()[2], &proof);
+    assert_eq!(matched.unwrap(), Some(0));
+    let abbreviation: String = roots.unambiguous_abbreviation(&tree.root());
+    assert_eq!(abbreviation, tree.root().short(MIN_ABBREVIATION_LEN));
+    let resolved: Result<Hash, ResolveError> = roots.resolve_prefix(&abbreviation);
+    assert_eq!(resolved, Ok(tree.root()));
+    let not_found: LibError = roots.resolve_prefix("not hex").unwrap_err().into();
+    assert_eq!((not_found.code(), not_found.exit_status()), ("ROOT_NOT_FOUND", ExitStatus::NotFound));
+    let ids = ["sha256", "sha512", "ripemd160", "sha256-rfc6962", "sha256d"];
+    assert_eq!(HashAlgorithm::ALL.map(|hasher| hasher.id()), ids);
+    assert_eq!(Rfc6962Hasher.id(), HashAlgorithm::Sha256Rfc6962.id());
+    assert_eq!(Sha256dHasher.id(), HashAlgorithm::Sha256d.id());
+    let root: Result<Hash, LibError> = fold_root(tree.leaves().to_vec(), tree.hasher());
+    assert_eq!(root.unwrap(), tree.root());
+
+    let guess: SchemeGuess = detect_scheme(&data(3), &tree.root()).unwrap();
+    let expected = (HashAlgorithm::Sha512, LeafMode::HashData, OddLeafPolicy::Duplicate);
+    assert_eq!((guess.hasher, guess.leaf_mode, guess.odd_leaf_policy), expected);
+    assert_eq!((guess.pair_order, PairOrder::default()), (PairOrder::LeftRight, PairOrder::LeftRight));
+    let config: Option<TreeConfig> = guess.config();
+    assert_eq!(config, Some(TreeConfig::default().with_hasher(HashAlgorithm::Sha512)));
+
+    let self_test: Result<(), merkle::SelfTestError> = merkle::self_test_hashers();
+    assert!(self_test.is_ok());
+    assert_eq!(merkle::merkel::self_test_hashers(), Ok(()));
+    assert_eq!(ExitStatus::SelfTestFailed as u8, 8);
+}
+
+#[test]
+fn test_shapes() {
+    let _: fn(TreeConfig, TreeShape) -> TreeConfig = TreeConfig::with_shape;
+    let _: fn(usize, usize, Vec<Hash>) -> Result<OwnedProof, LibError> = OwnedProof::from_audit_path;
+    let config = TreeConfig::default().with_shape(TreeShape::LeftBalanced);
+    assert_eq!((config.shape(), config.odd_leaf_policy()), (TreeShape::LeftBalanced, OddLeafPolicy::Promote));
+    let tree = config.construct(&data(3)).unwrap();
+    assert_eq!((tree.shape(), MerkleTree::construct(&data(3)).shape()), (TreeShape::LeftBalanced, TreeShape::Paired));
+
+    let flat = FlatProof::try_from(&tree.prove_by_index(2).unwrap().into_owned()).unwrap();
+    assert_eq!((flat.leaf_index, flat.total_leaves, flat.proof_hashes.len()), (2, 3, 1));
+    let rebuilt = FlatProof::new(2, 3, flat.proof_hashes.clone());
+    let read: Result<FlatProof, LibError> = FlatProof::from_bytes(2, 3, &rebuilt.to_bytes());
+    let proof: Result<OwnedProof, LibError> = OwnedProof::try_from(read.unwrap());
+    assert!(MerkleTree::verify_proof_hash(&tree.leaves()[2], &proof.unwrap().as_proof(), &tree.root()));
+    let indexed = MerkleTree::construct(&data(4)).prove_by_index(1).unwrap().into_owned().to_indexed().unwrap();
+    let flat: Result<FlatProof, LibError> = FlatProof::try_from(&indexed);
+    assert_eq!(IndexedProof::try_from(flat.unwrap()).unwrap(), indexed);
+}
+
+#[test]
+fn test_descriptor() {
+    let tree = MerkleTree::construct(&data(3));
+    let descriptor: TreeDescriptor = tree.descriptor();
+    assert_eq!((descriptor.leaf_count, descriptor.leaf_mode, &descriptor.root), (3, None, &tree.root()));
+    let scheme = (descriptor.hasher, descriptor.odd_leaf_policy, descriptor.shape);
+    assert_eq!(scheme, (HashAlgorithm::Sha256, OddLeafPolicy::Duplicate, TreeShape::Paired));
+    let described: Result<TreeDescriptor, LibError> = TreeConfig::default().descriptor(&tree);
+    assert_eq!(described.unwrap().leaf_mode, Some(LeafMode::HashData));
+    assert!(descriptor.fingerprint().starts_with("sha256/dup/3:"));
+    assert_eq!(descriptor.crate_version.as_deref(), Some(CRATE_VERSION));
+    let encoded: TreeDescriptor = descriptor.clone().with_encoding(Encoding::Base64Url);
+    assert_eq!((descriptor.encoding, encoded.encoding), (Encoding::Hex, Encoding::Base64Url));
+
+    let context = VerifyContext::new(descriptor.clone());
+    assert_eq!((context.descriptor(), context.root(), context.tree_size()), (&descriptor, &tree.root(), 3));
+    let proof = tree.prove_by_index(1).unwrap().into_owned();
+    assert_eq!(context.leaf_hash(&data(3)[1]), tree.leaves()[1]);
+    assert!(context.verify(&data(3)[1], 1, &proof).unwrap());
+    assert!(context.verify_leaf_hash(&tree.leaves()[1], LeafIndex::from(1), &proof).unwrap());
+    let detailed: Result<(), ProofFailure> = context.verify_detailed(&data(3)[0], 1, &proof);
+    assert_eq!(detailed.unwrap_err().expected_len, Some(2));
+    let batch: Vec<Result<bool, LibError>> = context.verify_batch(&[(1, data(3)[1].clone(), proof.clone())]);
+    assert!(batch[0].as_ref().is_ok_and(|valid| *valid));
+    let inner = MerkleTree::construct(&data(2));
+    let chained = ChainedProof::new(inner.prove_by_index(0).unwrap().into_owned(), inner.root(), proof);
+    assert!(!context.verify_chained(&data(2)[0], 1, &chained).unwrap());
+    let mismatch = context.check_descriptor("tree", &inner.descriptor()).unwrap_err();
+    assert!(matches!(mismatch, LibError::ContextMismatch { artifact: "tree", field: "leaf count", .. }));
+    assert_eq!((mismatch.code(), mismatch.exit_status()), ("CONTEXT_MISMATCH", ExitStatus::FormatMismatch));
+    let tagged = tree.prove_by_index(1).unwrap().into_owned().with_tree_size(4);
+    assert_eq!(context.verify_detailed(&data(3)[1], 1, &tagged).unwrap_err().point, Some(FailurePoint::TreeSize(4)));
+}
+
+#[test]
+fn test_empty_leaf_policy() {
+    let _: fn(TreeConfig, EmptyLeafPolicy) -> TreeConfig = TreeConfig::with_empty_leaf_policy;
+    let _: fn(EmptyLeafPolicy, &[Data]) -> Result<(), LibError> = EmptyLeafPolicy::check;
+    let config = TreeConfig::default().with_empty_leaf_policy(EmptyLeafPolicy::Reject);
+    let policies = (config.empty_leaf_policy(), EmptyLeafPolicy::default());
+    assert_eq!(policies, (EmptyLeafPolicy::Reject, EmptyLeafPolicy::Allow));
+    let err = EmptyLeafPolicy::Reject.check(&[b"a".to_vec(), vec![]]).unwrap_err();
+    assert!(matches!(err, LibError::EmptyLeaf { index: 1 }));
+    assert!(config.construct(&[vec![]]).is_err());
+    assert_eq!((err.code(), err.exit_status()), ("EMPTY_LEAF", ExitStatus::Usage));
+}
+
+#[test]
+fn test_tree_and_proofs() {
+    let input = data(5);
+    let tree = MerkleTree::construct(&input);
+    assert_eq!(tree.root(), MerkleTree::from_items(&input).root());
+    assert_eq!((tree.len(), tree.depth(), tree.is_empty()), (5, 3, false));
+    let usage: MemoryReport = tree.memory_usage();
+    assert_eq!((usage.node_count, usage.leaf_count, usage.hash_bytes, usage.retained_data_bytes), (11, 5, 352, 0));
+    assert_eq!(usage.total_bytes(), usage.hash_bytes + usage.overhead_bytes_estimate);
+    assert_eq!(tree.levels().len(), MerkleTree::construct_with_levels(&input).len());
+    assert!(MerkleTree::verify_ordered(&input, &tree.root()));
+    #[allow(deprecated)]
+    let verified: bool = MerkleTree::verify(&input, &tree.root());
+    assert!(verified);
+
+    let proof: Proof = tree.prove(&input[4]).unwrap();
+    assert_eq!((proof.len(), proof.implied_index().unwrap()), (3, 4));
+    assert!(MerkleTree::verify_proof(&input[4], &proof, &tree.root()));
+    assert!(MerkleTree::verify_proof_at_index(&input[4], &proof, 4, 5, &tree.root()));
+    assert!(matches!(tree.prove_by_index(5), Err(LibError::IndexOutOfRange { .. })));
+    let (index, size) = (LeafIndex(4), TreeSize::from(tree.len()));
+    let typed = tree.prove_by_index(index).unwrap();
+    assert!(MerkleTree::verify_proof_at_index(&input[4], &typed, index, size, &tree.root()));
+    assert_eq!((size.depth(), size.parent(), size.last()), (3, TreeSize(3), Some(index)));
+    assert_eq!((index.sibling_in(size), index.parent(), index.is_left()), (None, LeafIndex(2), true));
+    assert_eq!((expected_proof_len(size, index, OddLeafPolicy::Promote), index.to_string()), (1, "4".to_string()));
+    assert_eq!((u64::from(index), usize::try_from(size).unwrap()), (4, 5));
+    let failure = tree.explain_proof(&tree.leaves()[4], &proof, 4, &vec![0; 32]).unwrap_err();
+    assert_eq!((failure.point, failure.expected_len), (Some(FailurePoint::Root), Some(3)));
+
+    let owned: OwnedProof = tree.prove_by_index(2).unwrap().into_owned();
+    let json = serde_json::to_string(&owned).unwrap();
+    let owned: OwnedProof = serde_json::from_str(&json).unwrap();
+    let rebuilt = OwnedProof::new(owned.hashes().to_vec()).with_tree_size(tree.len());
+    let step: &StepHash = &owned.hashes()[0].1;
+    assert_eq!((StepHash::new(step).unwrap(), step.as_slice()), (*step, &tree.leaves()[3][..]));
+    assert!(matches!(StepHash::new(&[0; MAX_HASH_LEN + 1]), Err(LibError::InvalidHashLength { expected: 64, .. })));
+    assert!(MerkleTree::verify_proof_hash(&tree.leaves()[2], &rebuilt.as_proof(), &tree.root()));
+    let raw: Vec<(HashDirection, [u8; 32])> =
+        owned.hashes().iter().map(|(direction, hash)| (*direction, hash[..].try_into().unwrap())).collect();
+    let leaf: [u8; 32] = tree.leaves()[2][..].try_into().unwrap();
+    assert!(verify_proof_raw(&leaf, &raw, &tree.root()[..].try_into().unwrap()));
+    assert_eq!((rebuilt.tree_size(), rebuilt.as_proof().tree_size()), (owned.tree_size(), Some(tree.len())));
+    let _: fn(Proof<'static>, usize) -> Proof<'static> = Proof::with_tree_size;
+    let root_first: OwnedProof = owned.reversed();
+    assert_eq!((root_first.order(), root_first.order().reversed()), (ProofOrder::RootToLeaf, owned.order()));
+    let listed = root_first.hashes().iter().rev().cloned().collect();
+    assert_eq!(OwnedProof::from_ordered_steps(listed, ProofOrder::RootToLeaf).hashes(), owned.hashes());
+    assert_ne!(failure.point, Some(FailurePoint::Order));
+
+    let leaf: NodeId = tree.leaf_id(0).unwrap();
+    let parent = tree.parent(leaf).unwrap();
+    assert_eq!(tree.children(parent).unwrap().0, leaf);
+    assert_eq!(tree.sibling(leaf).unwrap().index(), 1);
+    assert!(tree.node(tree.root_id()).is_some());
+}
+
+#[test]
+fn test_config_and_cache() {
+    let input = data(4);
+    let config = TreeConfig::new(LeafMode::PreHashed);
+    assert_eq!(config.leaf_mode(), LeafMode::PreHashed);
+    assert_eq!(LeafMode::from_id(LeafMode::PreHashed.id()), Some(LeafMode::PreHashed));
+    assert!(config.check_artifact("tree", "sha256", Some(LeafMode::PreHashed)).is_ok());
+    let proof = config.tag_proof(MerkleTree::construct(&input).prove_by_index(0).unwrap().into_owned());
+    assert!(config.check_proof("proof", &proof).is_ok());
+    let _: fn(&TreeConfig, &str) -> Result<OwnedProof, LibError> = TreeConfig::load_proof_json;
+    let _: fn(&TreeConfig, &[u8], &DefaultHashes) -> Result<OwnedProof, LibError> = TreeConfig::load_proof_compressed;
+    let hashed = MerkleTree::construct(&input);
+    let tree = config.construct(hashed.leaves()).unwrap();
+    assert_eq!(tree.root(), hashed.root());
+
+    let verification: Verification = TreeConfig::default().verify(hashed.leaves(), &hashed.root()).unwrap();
+    assert!(!verification.matches);
+    assert!(verification.warnings.contains(&VerifyWarning::LooksPreHashed));
+    let _: fn(TreeConfig, usize) -> TreeConfig = TreeConfig::with_max_proof_depth;
+    let bounded = config.with_max_proof_depth(hashed.depth());
+    assert_eq!(bounded.max_proof_depth(), 2);
+    let proof = hashed.prove_by_index(1).unwrap();
+    let verified: Result<bool, LibError> = bounded.verify_proof(&hashed.leaves()[1], &proof, &hashed.root());
+    assert!(verified.unwrap());
+    let deep = OwnedProof::new(vec![(HashDirection::Left, hashed.root()); MAX_PROOF_STEPS + 1]);
+    let too_deep = config.verify_proof(&hashed.leaves()[1], &deep.as_proof(), &hashed.root());
+    assert!(matches!(too_deep, Err(LibError::ProofTooDeep { max: MAX_PROOF_STEPS, got: 65 })));
+
+    let built: Result<(MerkleTree, BuildMetrics), LibError> = TreeConfig::default().construct_with_metrics(&data(3));
+    let (built, metrics) = built.unwrap();
+    assert_eq!((built.len(), metrics.leaf_count, metrics.leaf_hashes, metrics.node_hashes), (3, 3, 3, 3));
+    assert!(metrics.leaves_per_second() > 0.0 && metrics.hashes_per_second() > 0.0);
+    assert!(metrics.elapsed.as_secs() < 60 && !metrics.to_string().is_empty());
+    let mut collector = MetricsCollector::start();
+    TreeConfig::default().on_node(|event| collector.record(event)).with_leaves().construct(&data(3)).unwrap();
+    assert_eq!(collector.finish(LeafMode::PreHashed).leaf_hashes, 0);
+    let _: fn() -> Option<u64> = peak_rss_bytes;
+
+    let cache = ProofCache::new(tree, 2);
+    cache.get_or_generate(1).unwrap();
+    cache.get_or_generate(1).unwrap();
+    let stats: CacheStats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.len), (1, 1, 1));
+    assert_eq!(cache.tree().len(), 4);
+
+    let limits = ServiceLimits { max_proofs_per_call: 2, max_batch_indices: 4, allow_range_proofs: true };
+    let service = ProofService::with_cache(cache, limits);
+    let proofs: Result<Vec<(usize, std::sync::Arc<OwnedProof>)>, ServiceError> = service.prove_batch(&[1, 3, 1]);
+    assert_eq!(proofs.unwrap().len(), 2);
+    let range: Vec<std::sync::Arc<OwnedProof>> = service.prove_range(2..4).unwrap();
+    assert_eq!(*range[0], *service.prove(2).unwrap());
+    let rejected: ServiceError = service.prove_batch(&[0, 1, 2]).unwrap_err();
+    assert_eq!(rejected.code(), "TOO_MANY_PROOFS");
+    let stats: ServiceStats = service.stats();
+    assert_eq!((stats.proofs_served, stats.rejected_too_many_proofs, stats.rejected()), (5, 1, 1));
+    assert_eq!((service.limits(), service.tree().len(), service.cache().stats().hits), (&limits, 4, 4));
+    let _: fn(MerkleTree, ServiceLimits) -> ProofService = ProofService::new;
+
+    let mut subtrees = SubtreeCache::new(16);
+    let first = TreeConfig::default().construct_cached(&data(4), &mut subtrees).unwrap();
+    let again = TreeConfig::default().construct_cached(&data(4), &mut subtrees).unwrap();
+    let stats: CacheStats = subtrees.stats();
+    assert_eq!((first.root(), stats.hits, stats.misses, subtrees.capacity()), (again.root(), 3, 3, 16));
+
+    let mut arena = TreeArena::with_capacity(8);
+    let built: ArenaTree = TreeConfig::default().construct_in(&data(4), &mut arena).unwrap();
+    assert_eq!((built.root(), built.len(), built.depth()), (&first.root()[..], 4, 2));
+    assert_eq!((built.node(0, 3), built.to_tree().root()), (Some(&first.leaves()[3][..]), first.root()));
+    assert!(TreeConfig::default().construct_in(&data(9), &mut TreeArena::new()).is_ok());
+
+    let canonical = TreeConfig::default().with_canonical_ordering(CanonicalOrdering::ByLeafHash);
+    let sorted: Vec<Data> = canonicalize_leaves(data(4), CanonicalOrdering::ByLeafHash);
+    let (tree, positions): (MerkleTree, Vec<usize>) = canonical.construct_canonical(&data(4)).unwrap();
+    assert_eq!(tree.root(), MerkleTree::construct(&sorted).root());
+    assert_eq!(canonical.descriptor(&tree).unwrap().canonical_ordering, canonical.canonical_ordering());
+    assert_eq!(positions.len(), 4);
+
+    let mut nodes = Vec::new();
+    let observed: ObservedConfig<_> = TreeConfig::default().on_node(|event: &NodeEvent| {
+        nodes.push((event.level, event.index_in_level, event.hash.clone(), event.left_child.is_some()));
+        assert_eq!(event.right_child.is_some(), event.left_child.is_some());
+    });
+    let tree = observed.with_leaves().construct(&data(4)).unwrap();
+    assert_eq!((nodes.len(), nodes[6].0, nodes[6].2.clone(), nodes[0].3), (7, 2, tree.root(), false));
+}
+
+#[test]
+fn test_composite_structures() {
+    let construct: Result<SumTree, LibError> = SumTree::construct(&[(vec![0], 1)]);
+    assert_eq!(construct.unwrap().root().1, 1);
+    let _: fn(&Data, u64, &SumProof, &Hash, u64) -> Result<bool, LibError> = SumTree::verify;
+    let sums = SumTree::construct(&[(vec![1], 5), (vec![2], 7), (vec![3], 9)]).unwrap();
+    let (root_hash, root_sum) = sums.root();
+    let proof: SumProof = sums.prove(LeafIndex(2)).unwrap();
+    let steps: &[SumStep] = proof.steps();
+    assert_eq!((root_sum, sums.len(), steps.len(), steps[0].sum), (21, 3, 1, 12));
+    let rebuilt = SumProof::new(vec![SumStep::new(steps[0].direction, steps[0].hash.clone(), 12)]);
+    assert!(SumTree::verify(&vec![3], 9, &rebuilt, &root_hash, 21).unwrap());
+    assert_eq!(sums.leaf(0).map(|leaf| leaf.1), Some(5));
+    assert!(matches!(SumTree::construct(&[(vec![], u64::MAX), (vec![], 1)]), Err(LibError::SumOverflow)));
+
+    let forest = MerkleForest::construct(&[data(3), data(4)]);
+    let composite = forest.prove(1, 3).unwrap();
+    assert_eq!((composite.shard_index(), composite.leaf_index()), (1, 3));
+    let rebuilt = CompositeProof::new(1, 3, composite.leaf().clone(), composite.shard().clone());
+    assert!(MerkleForest::verify_composite(&vec![3], &rebuilt, &forest.root()));
+
+    let inner = MerkleTree::construct(&data(3));
+    let outer = MerkleTree::construct(&[inner.root(), vec![9]]);
+    let chained = ChainedProof::from_trees(&inner, 1, &outer, 0).unwrap();
+    let rebuilt = ChainedProof::new(chained.inner().clone(), chained.inner_root().clone(), chained.outer().clone());
+    assert!(MerkleTree::verify_chained(&vec![1], &rebuilt, &outer.root()));
+    assert!(TreeConfig::new(LeafMode::HashData).verify_chained(&vec![1], &rebuilt, &outer.root()));
+
+    let pair: PairProof = inner.prove_pair(1).unwrap();
+    assert_eq!((pair.index(), pair.tree_size(), pair.hash_count(), pair.upper().hashes().len()), (0, 3, 3, 1));
+    assert_eq!((pair.left(), pair.right()), (&inner.leaves()[0], Some(&inner.leaves()[1])));
+    let _: fn(&Data, Option<&Data>, usize, usize, &PairProof, &Hash) -> bool = MerkleTree::verify_pair;
+    assert!(MerkleTree::verify_pair(&vec![0], Some(&vec![1]), 1, 3, &pair, &inner.root()));
+    assert!(MerkleTree::verify_pair(&vec![2], None, 2, 3, &inner.prove_pair(2).unwrap(), &inner.root()));
+
+    let sorted = SortedMerkleTree::construct(&[vec![1], vec![3], vec![5]]).unwrap();
+    let absence = sorted.prove_absence(&vec![4]).unwrap();
+    let neighbor: &Neighbor = absence.successor().unwrap();
+    assert_eq!((neighbor.index(), neighbor.data()), (2, &vec![5]));
+    let rebuilt = AbsenceProof::new(absence.predecessor().cloned(), Some(neighbor.clone()));
+    assert!(SortedMerkleTree::verify_absence(&vec![4], &rebuilt, &sorted.root(), sorted.len()));
+    assert!(sorted.tree().prove(neighbor.data()).is_some());
+    assert_eq!(neighbor.proof().hashes().len(), 2);
+
+    let tree = MerkleTree::construct(&data(8));
+    let pruned: PrunedTree = tree.prune(&PruneSpec::new([2]).with_top_levels(2)).unwrap();
+    assert_eq!(pruned.root(), tree.root());
+    assert!(pruned.node_count() < tree.node_count());
+    assert_eq!(pruned.retained_leaves().collect::<Vec<_>>(), vec![2]);
+    assert!(MerkleTree::verify_proof(&vec![2], &pruned.prove_by_index(2).unwrap(), &tree.root()));
+
+    let shards = vec![
+        MerkleTree::construct_partial(&data(8)[..4], 0, 2).unwrap(),
+        MerkleTree::construct_partial(&data(8)[4..], 1, 2).unwrap(),
+    ];
+    assert_eq!((shards[1].shard_index(), shards[1].shard_count(), shards[1].leaf_count()), (1, 2, 4));
+    assert_eq!(shards[0].levels().len(), 3);
+    assert_eq!(&tree.levels()[2][1], shards[1].subroot());
+    assert_eq!(MerkleTree::join(shards).unwrap().root(), tree.root());
+
+    let snapshot: TreeSnapshot = tree.snapshot().push(&vec![8]);
+    assert_eq!(snapshot.root(), MerkleTree::construct(&data(9)).root());
+    assert_eq!((snapshot.len(), snapshot.depth(), SNAPSHOT_CHUNK), (9, 4, 256));
+    assert!(MerkleTree::verify_proof(&vec![8], &snapshot.prove_by_index(8).unwrap(), &snapshot.root()));
+
+    let hashers = [HashAlgorithm::Sha256, HashAlgorithm::Sha512];
+    let commitment = MultiCommitment::build(&data(5), &hashers).unwrap();
+    assert_eq!((commitment.len(), commitment.hashers().count()), (5, 2));
+    assert_eq!(commitment.tree(HashAlgorithm::Sha256).unwrap().root(), MerkleTree::construct(&data(5)).root());
+    let roots: MultiRoots = commitment.roots();
+    let proof = commitment.prove(HashAlgorithm::Sha512, 4).unwrap();
+    assert!(roots.verify_proof(HashAlgorithm::Sha512, &vec![4], &proof));
+    assert_eq!((roots.leaf_count(), roots.roots().len()), (5, 2));
+    assert_eq!(roots.root(HashAlgorithm::Sha512), commitment.root(HashAlgorithm::Sha512).as_ref());
+
+    let feed = [data(3), data(2)].concat();
+    let values = [serde_json::json!({ "b": 1, "a": [0.5, null] }), serde_json::json!("leaf")];
+    let leaf: Result<Data, LibError> = canonical_json_leaf(&values[0]);
+    assert_eq!(leaf.unwrap(), br#"{"a":[0.5,null],"b":1}"#.to_vec());
+    let json_tree: Result<MerkleTree, LibError> = MerkleTree::from_json_values(&values);
+    assert_eq!(json_tree.unwrap().len(), 2);
+
+    let multiset = MultisetCommitment::construct(&feed).unwrap();
+    assert_eq!((multiset.len(), multiset.tree().len(), multiset.count(&vec![1])), (5, 3, 2));
+    let repeated: Vec<(Hash, u64)> = multiset.duplicates();
+    assert_eq!((repeated.len(), multiset.counts().len()), (2, 3));
+    let proof: Proof = multiset.prove(&vec![1], 2).unwrap();
+    assert!(MultisetCommitment::verify(&vec![1], 2, &proof, &multiset.root()));
+    assert!(MerkleTree::verify_multiset(&feed, &multiset.root()));
+
+    let mut builder = MerkleBuilder::new(TreeConfig::default());
+    let leaf: Result<Hash, LibError> = builder.add_leaf_from_reader(std::io::Cursor::new(vec![0u8]));
+    assert_eq!(leaf.unwrap(), Sha256Hasher.leaf_hash(&[0]));
+    assert_eq!(builder.add_leaf(&vec![1]).unwrap(), Sha256Hasher.leaf_hash(&[1]));
+    assert_eq!((builder.len(), builder.is_empty()), (2, false));
+    let built: Result<MerkleTree, LibError> = builder.build();
+    assert_eq!(built.unwrap().root(), MerkleTree::construct(&data(2)).root());
+
+    let sha256 = MerkleTree::construct(&data(3));
+    let recommitted: Result<Hash, LibError> = sha256.recommit_leaves::<Sha512Hasher>(&data(3));
+    let sha512 = TreeConfig::default().with_hasher(HashAlgorithm::Sha512).construct(&data(3)).unwrap();
+    assert_eq!(recommitted.unwrap(), sha512.root());
+    let hasher: &dyn MerkleHasher = &HashAlgorithm::Sha512;
+    let mismatch = sha256.recommit_leaves_with(hasher, &data(2)).unwrap_err();
+    assert!(matches!(mismatch, LibError::LeafMismatch { index: 2 }));
+    assert_eq!((mismatch.code(), mismatch.exit_status()), ("LEAF_MISMATCH", ExitStatus::VerificationFailed));
+}
+
+#[test]
+fn test_async_construction() {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let input = data(10);
+    let tree = runtime.block_on(MerkleTree::construct_async(&input, DEFAULT_YIELD_EVERY)).unwrap();
+    let streamed = runtime
+        .block_on(MerkleTree::from_stream(futures::stream::iter(input.clone())))
+        .unwrap();
+    assert_eq!(tree.root(), streamed.root());
+
+    let context = VerifyContext::new(tree.descriptor());
+    let _: ProofVerifySink = ProofVerifySink::with_context(context);
+    let mut sink = ProofVerifySink::new(tree.root(), 10).with_blocking_leaf_len(SINK_BLOCKING_LEAF_LEN);
+    let item = SinkItem { index: 3, leaf: input[3].clone(), proof: tree.prove_by_index(3).unwrap().into_owned() };
+    let verified: Result<(), ProofFailure> = runtime.block_on(sink.verify_next(item));
+    assert!(verified.is_ok());
+    let summary: SinkSummary = sink.finish();
+    assert_eq!((summary.verified, summary.failed, summary.duplicates), (1, 0, 0));
+}
+
+#[test]
+fn test_utilities() {
+    let leaf = LeafEncoder::new().push_str("alice").push_u64(10).push_bytes(b"x").finish();
+    assert_eq!(leaf.len(), 8 + 5 + 8 + 8 + 8 + 1);
+
+    let text = "00ff\r\nABCD\n";
+    assert_eq!(read_hashes_with(text.as_bytes(), HexPolicy::Lenient).unwrap(), vec!["00ff", "abcd"]);
+    let lenient = decode_hashes_with(text.as_bytes(), HexPolicy::Lenient).unwrap();
+    assert_eq!(lenient, vec![vec![0, 255], vec![0xab, 0xcd]]);
+    assert!(matches!(read_hashes(text.as_bytes()), Err(LibError::InvalidHex { line: 2, .. })));
+    assert_eq!(decode_hashes("00ff\r\n".as_bytes()).unwrap(), vec![vec![0, 255]]);
+    assert_eq!(HexPolicy::default(), HexPolicy::Strict);
+    assert_eq!(parse_hex_lenient(" ABCD ").unwrap(), parse_hex_strict("abcd").unwrap());
+    let tmp = std::env::temp_dir();
+    let strict = canonicalize_to_tempfiles(text.as_bytes(), &tmp, 1 << 16);
+    assert!(matches!(strict, Err(LibError::InvalidHex { line: 2, .. })));
+    let runs: SortedRunIterator =
+        canonicalize_to_tempfiles_with(text.as_bytes(), HexPolicy::Lenient, &tmp, MIN_MEMORY_BUDGET).unwrap();
+    assert_eq!((runs.leaf_count(), runs.run_count()), (2, 0));
+    assert_eq!(runs.collect::<Result<Vec<Hash>, LibError>>().unwrap(), lenient);
+    let _: fn(SortedRunIterator, TreeConfig) -> Result<MerkleTree, LibError> = SortedRunIterator::build;
+    let budget = LibError::InvalidMemoryBudget { budget: 0, min: MIN_MEMORY_BUDGET };
+    assert_eq!((budget.code(), budget.exit_status()), ("INVALID_MEMORY_BUDGET", ExitStatus::Usage));
+    let hash: Hash = HexPolicy::Lenient.parse_hash("ABCD", 2).unwrap();
+    assert_eq!(hash, [0xab, 0xcd]);
+    assert!(matches!(HexPolicy::Strict.parse("0g"), Err(LibError::InvalidHexString(_))));
+    assert!(matches!(HexPolicy::Strict.parse_hash("ab", 2), Err(LibError::InvalidHashLength { .. })));
+    let error = LibError::InvalidHex { line: 17, source: hex::FromHexError::OddLength };
+    assert_eq!((error.exit_status(), error.code(), error.line()), (ExitStatus::Usage, "INVALID_HEX", Some(17)));
+    let _: std::process::ExitCode = error.into();
+    let _: std::process::ExitCode = ExitStatus::NotFound.into();
+    let (_, rejected) = decode_hashes_lenient_with("ABCD\n".as_bytes(), 2, HexPolicy::Lenient).unwrap();
+    assert!(rejected.is_empty());
+    assert_eq!(read_hashes_from_file("tests/fixtures/hashes.txt").unwrap().len(), 7);
+    let options = DiffOptions::default().with_hasher(HashAlgorithm::Sha256).with_max_reported(3);
+    assert_eq!((options.hasher(), options.max_reported(), DIFF_MAX_REPORTED), (HashAlgorithm::Sha256, 3, 1000));
+    assert_eq!(options.with_hex_policy(HexPolicy::Lenient).hex_policy(), HexPolicy::Lenient);
+    let (head, hashes) = ("tests/fixtures/hashes_head.txt", "tests/fixtures/hashes.txt");
+    let report: DiffReport = diff_files(head, hashes, &options).unwrap();
+    assert_eq!((report.kind, report.added, report.differing_count), (DiffKind::Extended, 4, 0));
+    let same = diff_trees(&MerkleTree::construct(&data(3)), &MerkleTree::construct(&data(3)), 3).unwrap();
+    assert!(same.roots_match && same.kind == DiffKind::Identical);
+    let (hashes, rejected): (Vec<Hash>, Vec<RejectedLine>) = decode_hashes_lenient(text.as_bytes()).unwrap();
+    assert_eq!((hashes.len(), rejected[0].line), (0, 1));
+    assert_eq!(rejected[0].reason.to_string(), "expected 32 bytes, found 2");
+    let (_, rejected) = decode_hashes_lenient_of_len("zz\n".as_bytes(), 2).unwrap();
+    assert!(matches!(rejected[0].reason, RejectReason::InvalidHex(_)));
+
+    let tree = MerkleTree::construct(&data(3));
+    let mut jsonl = Vec::new();
+    let summary: ExportSummary = export_proofs_jsonl(&tree, 1..3, &mut jsonl).unwrap();
+    assert_eq!((summary.rows, summary.leaf_count, summary.root), (2, 3, tree.root()));
+    assert_eq!((jsonl.iter().filter(|&&b| b == b'\n').count(), EXPORT_FLUSH_EVERY), (2, 1024));
+
+    let report: LintReport = lint_hashes("zz\n".as_bytes()).unwrap();
+    assert!(report.has_errors());
+    assert_eq!(report.invalid_hex.lines, vec![1]);
+
+    let limits = ChunkLimits::default().with_min_chunk_size(64).with_max_leaves(10);
+    assert_eq!((DEFAULT_MIN_CHUNK_SIZE, DEFAULT_MAX_LEAVES), (1024, 1 << 26));
+    assert_eq!((limits.min_chunk_size(), limits.max_leaves()), (64, 10));
+    assert_eq!(limits.check(455, 100).unwrap(), 5);
+    let file = MerkleFile::from_path_with_limits("tests/fixtures/hashes.txt", 100, &limits).unwrap();
+    assert_eq!((file.len(), file.chunk_size(), file.tree().len()), (455, 100, 5));
+    assert!(MerkleFile::from_path("tests/fixtures/hashes.txt", 0).is_err());
+    assert!(estimated_tree_bytes(5).unwrap() > 0);
+
+    let config = TreeConfig::new(LeafMode::PreHashed);
+    let count: LineCount = count_lines("00\n\n01\n".as_bytes()).unwrap();
+    let estimate: BuildEstimate = estimate_build(count.lines, count.average_len(), &config).unwrap();
+    assert_eq!((estimate.leaf_count, estimate.depth, estimate.nodes, estimate.est_hashes), (2, 1, 3, 1));
+    let sample = format!("{}\n{}\n", "00".repeat(32), "01".repeat(32));
+    let calibration: Calibration = calibrate(sample.as_bytes(), &config, CALIBRATION_SAMPLES).unwrap();
+    assert!(estimate.est_duration(&calibration).is_some());
+
+    let path = std::env::temp_dir().join(format!("merkle-api-archive-{}", std::process::id()));
+    let _: ProofArchive = ProofArchive::build(&tree, LeafMode::HashData, &path).unwrap();
+    let archive = ProofArchive::open_with(&path, &TreeConfig::default()).unwrap();
+    assert_eq!(archive.leaf_mode(), Some(LeafMode::HashData));
+    assert_eq!((archive.len(), archive.root(), archive.version()), (3, &tree.root(), ProofVersion::CURRENT));
+    let (leaf, proof): (Hash, OwnedProof) = archive.get_with_leaf(2).unwrap();
+    assert_eq!((leaf, archive.get(LeafIndex(2)).unwrap()), (tree.leaves()[2].clone(), proof));
+    assert_eq!(&std::fs::read(&path).unwrap()[..8], ARCHIVE_MAGIC);
+    let written: Result<usize, LibError> = write_atomic(&path, |file| Ok(std::io::Write::write(file, b"00")?));
+    write_file_atomic(&path, b"0000").unwrap();
+    assert_eq!((written.unwrap(), remove_orphaned_temp(&path).unwrap()), (2, false));
+    assert!(temp_path(&path).to_string_lossy().ends_with(TEMP_SUFFIX));
+    std::fs::remove_file(&path).unwrap();
+
+    let mut journal = JournaledTree::create(&path, HashAlgorithm::Sha256, OddLeafPolicy::Duplicate).unwrap();
+    let leaf: Result<Hash, LibError> = journal.push(&data(3)[0]);
+    assert_eq!((leaf.unwrap(), journal.len(), journal.is_empty()), (tree.leaves()[0].clone(), 1, false));
+    journal.sync().unwrap();
+    let snapshot: Option<&TreeSnapshot> = journal.snapshot();
+    assert_eq!((journal.root(), snapshot.map(TreeSnapshot::len)), (Some(tree.leaves()[0].clone()), Some(1)));
+    assert_eq!((journal.hasher(), journal.odd_leaf_policy()), (HashAlgorithm::Sha256, OddLeafPolicy::Duplicate));
+    drop(journal);
+    assert_eq!(JournaledTree::recover(&path).unwrap().truncated_bytes(), 0);
+    assert_eq!(MerkleTree::recover_from_journal(&path).unwrap().len(), 1);
+    assert_eq!(&std::fs::read(&path).unwrap()[..8], JOURNAL_MAGIC);
+    std::fs::remove_file(&path).unwrap();
+    let dir = std::env::temp_dir();
+    let rel: Result<String, LibError> = canonical_rel_path(&dir, &dir.join("a").join("b"));
+    assert_eq!(rel.unwrap(), "a/b");
+    let unportable = canonical_rel_path(&dir, &dir).unwrap_err();
+    assert!(matches!(unportable, LibError::UnportablePath { reason: "is the root directory itself", .. }));
+    let encoded: String = tree.root().encode(Encoding::Multibase);
+    let decoded: Result<Hash, LibError> = Hash::decode(Encoding::Multibase, &encoded);
+    assert_eq!(decoded.unwrap(), tree.root());
+    let ids = (Encoding::ALL.len(), Encoding::default(), Encoding::from_id("base58btc"));
+    assert_eq!(ids, (4, Encoding::Hex, Some(Encoding::Base58Btc)));
+    let prefixes = (Encoding::Base64Url.multibase_prefix(), Encoding::from_multibase_prefix('z'));
+    assert_eq!(prefixes, (Some('u'), Some(Encoding::Base58Btc)));
+    assert_eq!((Encoding::Hex.id(), Encoding::Multibase.to_string()), ("hex", "multibase".to_string()));
+    let invalid = Hash::decode(Encoding::Base64Url, "=").unwrap_err();
+    assert!(matches!(invalid, LibError::InvalidEncoding { encoding: "base64url", .. }));
+
+    let mut log = AuditLog::new();
+    let (index, _): (usize, Hash) = log.append(b"login");
+    log.append_at(1_700_000_000, b"logout");
+    let epoch: &Epoch = log.seal_epoch().unwrap();
+    assert_eq!((epoch.number, epoch.size), (0, 2));
+    let proof: EventProof = log.prove_event(index, 0).unwrap();
+    let event: &AuditEvent = &log.events()[index];
+    assert!(proof.verify(&AuditLog::encode_event(event.timestamp, &event.data)));
+    let mut saved = Vec::new();
+    log.save(&mut saved).unwrap();
+    assert_eq!(AuditLog::load(saved.as_slice()).map(|loaded| loaded.root()).ok(), Some(log.root()));
+    let consistency: ConsistencyProof = tree.prove_consistency(2).unwrap();
+    assert!(consistency.verify(&MerkleTree::construct(&data(2)).root(), &tree.root()));
+
+    let bundle = ProofBundle::from_tree(&tree, LeafMode::HashData).unwrap().with_created_at(7);
+    let mut ndjson = Vec::new();
+    bundle.write(&mut ndjson).unwrap();
+    let read = ProofBundle::read(ndjson.as_slice(), false).unwrap();
+    assert_eq!(ProofBundle::read_with(ndjson.as_slice(), false, &TreeConfig::default()).unwrap(), read);
+    assert!(read.validate().is_ok());
+    let header: &BundleHeader = read.header();
+    assert_eq!((header.leaf_count, header.created_at, &header.root), (3, 7, read.root()));
+    let descriptor: TreeDescriptor = header.descriptor().unwrap();
+    let recorded = (descriptor.leaf_mode, header.crate_version.as_deref());
+    assert_eq!(recorded, (Some(LeafMode::HashData), Some(CRATE_VERSION)));
+    let row: &BundleRow = &read.rows()[2];
+    assert_eq!((row.index, &row.leaf, &row.source), (2, &tree.leaves()[2], &None));
+    let report: BundleReport = verify_bundle_parallel(ndjson.as_slice(), &tree.root(), 2).unwrap();
+    assert!(report.is_valid());
+    assert_eq!((report.rows, report.valid, report.invalid.len()), (3, 3, 0));
+    let malformed: &[MalformedRow] = &report.malformed;
+    assert!(malformed.iter().all(|row| row.line > 1 && !row.error.is_empty()));
+    let context = VerifyContext::new(descriptor);
+    assert_eq!(header.context(&tree.root()).unwrap(), context);
+    assert!(read.verify_with(&context).is_ok());
+    let report: BundleReport = verify_bundle_parallel_with(ndjson.as_slice(), &context, 2).unwrap();
+    assert_eq!(report.valid, 3);
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn test_cbor() {
+    let tree = MerkleTree::construct(&data(3));
+    let proof = tree.prove_by_index(1).unwrap().into_owned();
+    assert_eq!(OwnedProof::from_cbor(&proof.to_cbor().unwrap()).unwrap(), proof);
+
+    let mut bytes = vec![];
+    tree.save_cbor(&mut bytes).unwrap();
+    assert_eq!(MerkleTree::load_cbor(bytes.as_slice()).unwrap().root(), tree.root());
+    let (config, mut tagged) = (TreeConfig::default(), vec![]);
+    config.save_cbor(&tree, &mut tagged).unwrap();
+    assert_eq!(config.load_cbor(tagged.as_slice()).unwrap().root(), tree.root());
+    let (loaded, descriptor) = MerkleTree::load_cbor_with_descriptor(tagged.as_slice()).unwrap();
+    assert_eq!((loaded.root(), descriptor), (tree.root(), config.descriptor(&tree).unwrap()));
+    let tagged = config.tag_proof(proof);
+    assert_eq!(config.load_proof_cbor(&tagged.to_cbor().unwrap()).unwrap(), tagged);
+}
+
+#[cfg(feature = "sign")]
+#[test]
+fn test_sign() {
+    use merkle::util::sign::{
+        commitment, read_signing_key, read_verifying_key, sign_commitment, verify_commitment, verify_tree, SignedRoot,
+    };
+
+    let tree = MerkleTree::construct(&data(3));
+    let key = read_signing_key(&"01".repeat(32)).unwrap();
+    let public = read_verifying_key(&hex::encode(key.verifying_key().to_bytes())).unwrap();
+    let signed: SignedRoot = sign_commitment(&key, &tree.root(), 3);
+    assert_eq!((signed.leaf_count, commitment(&signed.root, 3).len()), (3, HASH_LEN));
+    assert!(verify_commitment(&public, &signed).is_ok());
+    assert!(verify_tree(&public, &signed, &tree).is_ok());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite() {
+    use merkle::util::sqlite::{for_each_sqlite_hash, open_sqlite, read_hashes_from_sqlite, Connection};
+    use std::path::PathBuf;
+
+    let connection = Connection::open_in_memory().unwrap();
+    connection.execute_batch("CREATE TABLE leaves (hash BLOB); INSERT INTO leaves VALUES (x'00ff');").unwrap();
+    let mut hashes: Vec<Hash> = vec![];
+    let rows = for_each_sqlite_hash(&connection, "leaves", Some("hash"), HexPolicy::Strict, |hash| hashes.push(hash));
+    assert_eq!((rows.unwrap(), hashes), (1, vec![vec![0x00, 0xff]]));
+    let _: fn(PathBuf) -> Result<Connection, LibError> = open_sqlite::<PathBuf>;
+    let missing: Result<Vec<Hash>, LibError> = read_hashes_from_sqlite(PathBuf::from("no.db"), "leaves", None);
+    assert!(matches!(missing, Err(LibError::Database(_))));
+    assert_eq!(LibError::Database("locked".into()).exit_status(), ExitStatus::Io);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn test_mmap() {
+    use merkle::util::mmap::{from_fixed_width_file, from_fixed_width_file_with};
+    use std::path::PathBuf;
+
+    let path = std::env::temp_dir().join(format!("merkle-api-mmap-{}", std::process::id()));
+    std::fs::write(&path, [[7u8; 32], [9; 32]].concat()).unwrap();
+    let tree: MerkleTree = from_fixed_width_file(&path, 32, LeafMode::PreHashed).unwrap();
+    assert_eq!(tree.root(), MerkleTree::from_leaf_hashes(vec![vec![7; 32], vec![9; 32]]).root());
+    let _: fn(PathBuf, usize, &TreeConfig) -> Result<MerkleTree, LibError> = from_fixed_width_file_with::<PathBuf>;
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_test_utils() {
+    use merkle::util::generate::{random_leaves, random_txids};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let txids: Vec<String> = random_txids(4, &mut StdRng::seed_from_u64(7));
+    assert!(txids.iter().all(|txid| txid.len() == 64));
+    let leaves: Vec<Data> = random_leaves(4, 64, &mut StdRng::seed_from_u64(7));
+    assert_eq!(leaves, txids.iter().map(|txid| txid.as_bytes().to_vec()).collect::<Vec<_>>());
+
+    use merkle::util::golden::{fixture_input, fixture_name, golden_roots, GoldenRoot, FIXTURE_LEAF_COUNTS};
+    let roots: Vec<GoldenRoot> = golden_roots().unwrap();
+    let config: TreeConfig = roots[0].config();
+    let input: Vec<Data> = fixture_input(FIXTURE_LEAF_COUNTS[0], &config);
+    assert_eq!(roots[0].fixture, fixture_name(input.len()));
+    assert_eq!(roots[0].current_root().unwrap(), config.construct(&input).unwrap().root());
+    use std::path::PathBuf;
+    let _: fn(PathBuf, &[GoldenRoot]) -> Result<(), LibError> = merkle::util::golden::write_golden_roots::<PathBuf>;
+    let _: fn(PathBuf) -> Result<Vec<GoldenRoot>, LibError> = merkle::util::golden::read_golden_roots::<PathBuf>;
+
+    use merkle::util::pipeline::{InvariantViolation, Mutation, PipelineCase, PipelineRun, MAX_FUZZ_LEAVES};
+    let case: PipelineCase = PipelineCase::from_fuzz_bytes(&[0, 1, 1, 1, 1, b'a', 0, 5]);
+    assert!(case.leaves.len() <= MAX_FUZZ_LEAVES);
+    assert_eq!(case.mutations, [Mutation::Reverse]);
+    let _: TreeConfig = case.config();
+    let run: Result<PipelineRun, InvariantViolation> = case.run();
+    assert!(run.unwrap().built);
+}
@@ -0,0 +1,826 @@
+//! Golden-file tests of the CLI output
+//!
+//! Each case runs the binary and compares its exit code, stdout and stderr with
+//! `tests/golden/<case>.txt`. Run with `UPDATE_GOLDEN=1` to rewrite the golden files after an
+//! intended output change, and review the resulting diff.
+
+use assert_cmd::cargo::cargo_bin_cmd;
+
+use merkle::merkel::{Hash, LeafMode, MerkleHasher, MerkleTree, OwnedProof, RootSet, Sha256Hasher};
+use merkle::util::bundle::ProofBundle;
+use merkle::util::encoding::{Encoding, HashEncoding};
+use merkle::util::generate::read_data_lines;
+
+use std::fs;
+use std::path::Path;
+
+const ROOT: &str = "e263b77a6d80c1c56f3f67d1e0d803ad8eb2ac9d66c82f78735207c886a1592c";
+const STRINGS_ROOT: &str = "82f2dc88b8b6a0de98b3b49ccf70421a20ab4fa8ba9b2c30741ed42d0b05b8ed";
+
+fn check_golden(case: &str, args: &[&str]) {
+    let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+    let actual = format!(
+        "$ merkle {}\nexit: {}\n--- stdout\n{}--- stderr\n{}",
+        args.join(" "),
+        output.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+    );
+
+    let path = Path::new("tests/golden").join(format!("{}.txt", case));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_default();
+    if expected != actual {
+        panic!(
+            "output of `{}` differs from {} (- golden, + actual):\n{}",
+            case,
+            path.display(),
+            diff(&expected, &actual)
+        );
+    }
+}
+
+/// Line diff based on the longest common subsequence, good enough for short outputs
+fn diff(expected: &str, actual: &str) -> String {
+    let (old, new): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    let (mut i, mut j, mut out) = (0, 0, String::new());
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out += &format!("  {}\n", old[i]);
+            (i, j) = (i + 1, j + 1);
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out += &format!("+ {}\n", new[j]);
+            j += 1;
+        } else {
+            out += &format!("- {}\n", old[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+#[test]
+fn test_root() {
+    check_golden("root", &["root", "tests/fixtures/hashes.txt"]);
+    check_golden("root_json", &["root", "tests/fixtures/hashes.txt", "--output", "json"]);
+
+    // the hasher self-test runs before every command and changes nothing when it passes
+    let root = |args: &[&str]| cargo_bin_cmd!("merkle").args(args).output().unwrap().stdout;
+    let skipped = root(&["root", "--skip-self-test", "tests/fixtures/hashes.txt"]);
+    assert_eq!(skipped, root(&["root", "tests/fixtures/hashes.txt"]));
+}
+
+#[test]
+fn test_root_of_several_files() {
+    // the first three and last four lines of hashes.txt, so in this order they give the same root
+    let (head, tail) = ("tests/fixtures/hashes_head.txt", "tests/fixtures/hashes_tail.txt");
+    check_golden("root_files_json", &["root", head, tail, "--output", "json"]);
+    check_golden("root_files_swapped", &["root", tail, head]);
+    check_golden("root_files_watch", &["root", head, tail, "--watch"]);
+}
+
+#[test]
+fn test_root_metrics() {
+    let run = |args: &[&str]| {
+        let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let human = run(&["root", "tests/fixtures/hashes.txt", "--metrics"]);
+    let lines: Vec<&str> = human.lines().collect();
+    assert_eq!((lines.len(), lines[0]), (3, ROOT));
+    assert!(lines[2].starts_with("built: 7 leaves and 7 hashes in "), "{}", lines[2]);
+    assert!(lines[2].contains(" leaves/s, ") && lines[2].contains(", peak RSS "), "{}", lines[2]);
+
+    // the counts are exact, the timings only plausible
+    let json = run(&["root", "tests/fixtures/hashes.txt", "--metrics", "--output", "json"]);
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let metrics = &json["metrics"];
+    assert_eq!((json["root"].as_str(), &metrics["leaf_count"]), (Some(ROOT), &serde_json::json!(7)));
+    assert_eq!((&metrics["leaf_hashes"], &metrics["node_hashes"]), (&serde_json::json!(0), &serde_json::json!(7)));
+    let elapsed = metrics["elapsed_secs"].as_f64().unwrap();
+    assert!(elapsed > 0.0 && elapsed < 60.0, "{}", elapsed);
+    for rate in ["leaves_per_second", "hashes_per_second"] {
+        assert!(metrics[rate].as_f64().is_some_and(|rate| rate > 0.0), "{}", metrics);
+    }
+    if cfg!(target_os = "linux") {
+        assert!(metrics["peak_rss_bytes"].as_u64().is_some_and(|bytes| bytes > 1 << 20), "{}", metrics);
+    }
+    let csv = run(&["root", "--format", "csv", "tests/fixtures/hashes.txt", "--metrics", "--output", "json"]);
+    let csv: serde_json::Value = serde_json::from_str(&csv).unwrap();
+    assert_eq!(csv["metrics"]["leaf_hashes"], 7);
+
+    let args = ["root", "tests/fixtures/hashes.txt", "--metrics", "--dry-run"];
+    assert_eq!(cargo_bin_cmd!("merkle").args(args).output().unwrap().status.code(), Some(1));
+}
+
+#[test]
+fn test_encodings() {
+    check_golden("root_base58btc", &["root", "tests/fixtures/hashes.txt", "--encoding", "base58btc"]);
+    check_golden("prove_base64url", &["prove", "tests/fixtures/hashes.txt", "--index", "3", "--encoding", "base64url"]);
+
+    let run = |args: &[&str]| cargo_bin_cmd!("merkle").args(args).output().unwrap();
+    let root = hex::decode(ROOT).unwrap();
+    for encoding in Encoding::ALL {
+        let output = run(&["root", "tests/fixtures/hashes.txt", "--encoding", encoding.id(), "--output", "json"]);
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let spelled = json["root"].as_str().unwrap();
+        assert_eq!(Hash::decode(encoding, spelled).unwrap(), root);
+        assert_eq!(json["descriptor"]["root"], json["root"]);
+
+        let args = ["verify-proof", "tests/fixtures/proof_3.json", "--root", spelled, "--encoding", encoding.id()];
+        let output = run(&args);
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout, format!("valid: the leaf is included under root {}\n", spelled));
+    }
+    // a multibase root may be given in any base
+    let hex_multibase = format!("f{}", ROOT);
+    let args = ["verify-proof", "tests/fixtures/proof_3.json", "--root", &hex_multibase, "--encoding", "multibase"];
+    assert!(run(&args).status.success());
+
+    // a root in another encoding than the one named does not parse
+    let base58 = root.encode(Encoding::Base58Btc);
+    let output = run(&["verify-proof", "tests/fixtures/proof_3.json", "--root", &base58]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = run(&["verify-proof", "tests/fixtures/proof_3.json", "--root", ROOT, "--encoding", "base58btc"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--root expects a base58btc hash: invalid base58btc: "), "{}", stderr);
+
+    let refused = [
+        &["root", "tests/fixtures/hashes.txt", "--encoding", "base32"][..],
+        &["prove", "tests/fixtures/hashes.txt", "--index", "3", "--encoding", "base58btc", "--output", "json"],
+        &["root", "tests/fixtures/hashes.txt", "--encoding", "multibase", "--watch"],
+    ];
+    for args in refused {
+        assert_eq!(run(args).status.code(), Some(1), "{:?}", args);
+    }
+}
+
+#[test]
+fn test_root_dry_run() {
+    // the timing varies from run to run, so only the structure is compared
+    let args = ["root", "tests/fixtures/hashes.txt", "--dry-run", "--output", "json"];
+    let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!((&json["leaf_count"], &json["depth"], &json["nodes"]), (&7.into(), &3.into(), &14.into()));
+    assert_eq!((&json["est_hashes"], &json["calibration_leaves"]), (&7.into(), &7.into()));
+    assert!(json["est_seconds"].is_number());
+
+    let output = cargo_bin_cmd!("merkle").args(&args[..3]).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("dry run, nothing was built\nleaves:         7\n"), "{}", stdout);
+    check_golden("root_dry_run_watch", &["root", "tests/fixtures/hashes.txt", "--dry-run", "--watch"]);
+}
+
+#[test]
+fn test_root_sort_leaves() {
+    let hashes: Vec<String> = (0..20_000u32).map(|i| hex::encode(Sha256Hasher.leaf_hash(&i.to_le_bytes()))).collect();
+    let [path, reversed] = ["sorted", "reversed"].map(|name| {
+        std::env::temp_dir().join(format!("merkle-sort-leaves-{}-{}.txt", name, std::process::id()))
+    });
+    fs::write(&path, hashes.join("\n")).unwrap();
+    fs::write(&reversed, hashes.iter().rev().cloned().collect::<Vec<_>>().join("\n")).unwrap();
+    let root = |path: &Path, extra: &[&str]| {
+        let output = cargo_bin_cmd!("merkle")
+            .args(["root", path.to_str().unwrap(), "--sort-leaves", "--output", "json"])
+            .args(extra)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+    let in_memory = root(&path, &[]);
+    assert_eq!(in_memory["descriptor"]["canonical_ordering"], "by-leaf-hash");
+    assert_eq!(root(&reversed, &[])["root"], in_memory["root"]);
+    // 64 KB of hashes is about a twentieth of them, so the sort goes through run files
+    assert_eq!(root(&reversed, &["--memory-budget", "65536"]), in_memory);
+
+    let sorted = ["root", "tests/fixtures/hashes.txt", "--sort-leaves"];
+    let output = cargo_bin_cmd!("merkle").args(sorted).output().unwrap();
+    assert_ne!(String::from_utf8(output.stdout).unwrap().lines().next(), Some(ROOT));
+    let tiny = ["root", path.to_str().unwrap(), "--sort-leaves", "--memory-budget", "100"];
+    let output = cargo_bin_cmd!("merkle").args(tiny).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("memory budget 100 is below the minimum of 4096 bytes"), "{}", stderr);
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&reversed).unwrap();
+
+    let (head, tail) = ("tests/fixtures/hashes_head.txt", "tests/fixtures/hashes_tail.txt");
+    check_golden("root_memory_budget_files", &["root", head, tail, "--sort-leaves", "--memory-budget", "4096"]);
+    check_golden("root_memory_budget_unsorted", &["root", head, "--memory-budget", "4096"]);
+}
+
+#[test]
+fn test_root_quarantine() {
+    let path = std::env::temp_dir().join(format!("merkle-quarantine-{}.txt", std::process::id()));
+    let (file, quarantine) = ("tests/fixtures/hashes_corrupted.txt", path.to_str().unwrap());
+    let output = cargo_bin_cmd!("merkle").args(["root", file, "--quarantine", quarantine]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    // the corrupted fixture is hashes.txt with bad lines added, so the root is the same
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().next(), Some(ROOT));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("warning: rejected 3 of 10 lines, the root only covers the other 7"), "{}", stderr);
+    let expected = [
+        "2\tinvalid hex: Odd number of digits\t4bf5122f344554c53bde2ebb8cd2b7e3d1600ad631c385a5d7cce23c7785459",
+        "5\tinvalid hex: Invalid character 'n' at position 0\tnot a hash",
+        "9\texpected 32 bytes, found 20\te77b9a9ae9e30b0dbdb6f510a264ef9de781501d",
+    ];
+    let expected: String = expected.iter().map(|line| format!("{}:{}\n", file, line)).collect();
+    assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+
+    let args = ["root", file, "--quarantine", quarantine, "--fail-on-reject", "--output", "json"];
+    let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+    assert_eq!((output.status.code(), output.stdout.as_slice()), (Some(6), &b""[..]));
+    assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+
+    // a clean file empties the quarantine
+    let clean = "tests/fixtures/hashes.txt";
+    let args = ["root", clean, "--quarantine", quarantine, "--fail-on-reject", "--output", "json"];
+    let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!((&json["root"], &json["rejected"]), (&ROOT.into(), &0.into()));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    fs::remove_file(&path).unwrap();
+
+    check_golden("root_fail_on_reject_alone", &["root", file, "--fail-on-reject"]);
+    check_golden("root_quarantine_csv", &["root", file, "--format", "csv", "--quarantine", "bad.txt"]);
+}
+
+#[test]
+fn test_lenient_hex() {
+    // hashes.txt with a byte order mark, CRLF line endings and every other hash in uppercase
+    let file = "tests/fixtures/hashes_crlf_bom.txt";
+    check_golden("root_uppercase_strict", &["root", file]);
+    let output = cargo_bin_cmd!("merkle").args(["root", file, "--lenient-hex"]).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!((output.status.code(), stdout.lines().next()), (Some(0), Some(ROOT)));
+
+    let root = format!(" {}\t", ROOT.to_ascii_uppercase());
+    let args = ["verify-proof", "tests/fixtures/proof_3.json", "--root", &root];
+    assert_eq!(cargo_bin_cmd!("merkle").args(args).output().unwrap().status.code(), Some(1));
+    let output = cargo_bin_cmd!("merkle").args(args).arg("--lenient-hex").output().unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn test_error_json() {
+    let error = |args: &[&str]| {
+        let output = cargo_bin_cmd!("merkle").args(args).arg("--error-json").output().unwrap();
+        assert_eq!(output.stdout, b"", "{:?}", args);
+        let json: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+        (output.status.code().unwrap(), json)
+    };
+    let body = |code: &str, line: Option<usize>, message: &str| {
+        serde_json::json!({ "code": code, "line": line, "message": message })
+    };
+
+    let strict = error(&["root", "tests/fixtures/hashes_crlf_bom.txt"]);
+    let message = "line 2: invalid hex: Invalid character 'B' at position 1";
+    assert_eq!(strict, (1, body("INVALID_HEX", Some(2), message)));
+    let unknown = error(&["nope"]);
+    assert_eq!(unknown, (1, body("USAGE", None, "unknown command `nope`, see `merkle --help`")));
+    let out_of_range = error(&["prove", "tests/fixtures/hashes.txt", "--index", "7"]);
+    let message = "leaf index 7 is out of range for a tree of 7 leaves";
+    assert_eq!(out_of_range, (3, body("INDEX_OUT_OF_RANGE", None, message)));
+    let missing = error(&["prove-data", "tests/fixtures/strings.txt", "--leaf", "doc-omega"]);
+    let message = "`doc-omega` is not a line of tests/fixtures/strings.txt";
+    assert_eq!(missing, (3, body("LEAF_NOT_FOUND", None, message)));
+    let unreadable = error(&["root", "tests/fixtures/no_such_file.txt"]);
+    assert_eq!(unreadable, (4, body("IO", None, "I/O error: No such file or directory (os error 2)")));
+    let future = error(&["verify-proof", "tests/fixtures/proof_3_future.json", "--root", ROOT]);
+    let message = "unsupported format version 3, supported versions are [1, 2]";
+    assert_eq!(future, (5, body("UNSUPPORTED_VERSION", None, message)));
+    let proof = fs::read_to_string("tests/fixtures/proof_3.json").unwrap();
+    let mut deep: serde_json::Value = serde_json::from_str(&proof).unwrap();
+    deep["proof"] = serde_json::Value::Array(vec![deep["proof"][0].clone(); 65]);
+    let deep_path = std::env::temp_dir().join(format!("merkle-deep-proof-{}.json", std::process::id()));
+    fs::write(&deep_path, deep.to_string()).unwrap();
+    let too_deep = error(&["verify-proof", deep_path.to_str().unwrap(), "--root", ROOT]);
+    assert_eq!(too_deep, (1, body("PROOF_TOO_DEEP", None, "a proof has at most 64 steps, found 65")));
+    fs::remove_file(deep_path).unwrap();
+
+    // proof files record the hasher and leaf mode of their tree, and are only read under those
+    let mut relabeled: serde_json::Value = serde_json::from_str(&proof).unwrap();
+    relabeled["leaf_mode"] = "hash-data".into();
+    let relabeled_path = std::env::temp_dir().join(format!("merkle-relabeled-proof-{}.json", std::process::id()));
+    fs::write(&relabeled_path, relabeled.to_string()).unwrap();
+    let mismatch = error(&["verify-proof", relabeled_path.to_str().unwrap(), "--root", ROOT]);
+    let message = "hasher mismatch in proof file: expected `sha256/pre-hashed`, found `sha256/hash-data`";
+    assert_eq!(mismatch, (5, body("HASHER_MISMATCH", None, message)));
+    let mut relabeled: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string("tests/fixtures/strings_proof_gamma.json").unwrap()).unwrap();
+    relabeled["hasher"] = "sha512".into();
+    fs::write(&relabeled_path, relabeled.to_string()).unwrap();
+    let args = ["verify-data", relabeled_path.to_str().unwrap(), "--leaf", "doc-gamma", "--root", STRINGS_ROOT];
+    let message = "hasher mismatch in proof file: expected `sha256/hash-data`, found `sha512/hash-data`";
+    assert_eq!(error(&args), (5, body("HASHER_MISMATCH", None, message)));
+    fs::remove_file(relabeled_path).unwrap();
+
+    let quarantine = std::env::temp_dir().join(format!("merkle-error-json-{}", std::process::id()));
+    let quarantine = quarantine.to_str().unwrap();
+    let args = ["root", "tests/fixtures/hashes_corrupted.txt", "--quarantine", quarantine, "--fail-on-reject"];
+    let message = format!("rejected 3 of 10 lines, written to {}", quarantine);
+    assert_eq!(error(&args), (6, body("REJECTED_LINES", Some(2), &message)));
+    fs::remove_file(quarantine).unwrap();
+
+    // a failed check is a result on stdout, not an error
+    let output = cargo_bin_cmd!("merkle")
+        .args(["verify-proof", "tests/fixtures/proof_3_bad_leaf.json", "--root", ROOT, "--error-json"])
+        .output()
+        .unwrap();
+    assert_eq!((output.status.code(), output.stderr.as_slice()), (Some(2), &b""[..]));
+}
+
+#[test]
+fn test_archive() {
+    let path = std::env::temp_dir().join(format!("merkle-archive-{}", std::process::id()));
+    let (file, archive) = ("tests/fixtures/hashes.txt", path.to_str().unwrap());
+    let output = cargo_bin_cmd!("merkle").args(["archive", "build", file, "--out", archive]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let expected = format!("wrote 7 proofs under root {} to {}\n", ROOT, archive);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+
+    // every proof reads back as `merkle prove` prints it
+    for index in ["0", "6"] {
+        for output in ["human", "json"] {
+            let get = ["archive", "get", archive, "--index", index, "--output", output];
+            let prove = ["prove", file, "--index", index, "--output", output];
+            let got = cargo_bin_cmd!("merkle").args(get).output().unwrap();
+            assert_eq!(got.stdout, cargo_bin_cmd!("merkle").args(prove).output().unwrap().stdout);
+        }
+    }
+    let output = cargo_bin_cmd!("merkle").args(["archive", "get", archive, "--index", "7"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    fs::remove_file(&path).unwrap();
+
+    check_golden("archive_get_not_an_archive", &["archive", "get", file, "--index", "0"]);
+    check_golden("archive_unknown_command", &["archive", "list", file]);
+}
+
+#[test]
+fn test_chunk_root_sizes() {
+    let file = "tests/fixtures/hashes.txt";
+    check_golden("chunk_root_zero", &["chunk-root", file, "--chunk-size", "0"]);
+    check_golden("chunk_root_tiny", &["chunk-root", file, "--chunk-size", "16"]);
+    check_golden(
+        "chunk_root_json",
+        &["chunk-root", file, "--chunk-size", "100", "--min-chunk-size", "64", "--output", "json"],
+    );
+
+    // large splits are only built after confirming
+    let path = std::env::temp_dir().join(format!("merkle-chunks-{}", std::process::id()));
+    fs::write(&path, vec![0u8; (1 << 20) + 1]).unwrap();
+    let args = ["chunk-root", path.to_str().unwrap(), "--chunk-size", "1", "--min-chunk-size", "1"];
+    let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("splits into 1048577 leaves of 1 bytes, using about"), "{}", stderr);
+    assert!(stderr.ends_with("error: pass --yes to build a tree this large\n"), "{}", stderr);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_prove() {
+    check_golden("prove_json", &["prove", "tests/fixtures/hashes.txt", "--index", "3", "--output", "json"]);
+    check_golden("prove_out_of_range", &["prove", "tests/fixtures/hashes.txt", "--index", "7"]);
+    // the last of 7 leaves is paired with itself
+    check_golden("prove_lone_leaf", &["prove", "tests/fixtures/hashes.txt", "--index", "6"]);
+}
+
+#[test]
+fn test_prove_many_leaves() {
+    let args = ["prove", "tests/fixtures/hashes.txt", "--leaves-file", "tests/fixtures/prove_leaves.txt"];
+    check_golden("prove_leaves_file", &args);
+
+    // every proof line verifies like the output of `merkle prove --output json`
+    let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<serde_json::Value> = stdout.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let numbers: Vec<u64> = lines.iter().map(|line| line["line"].as_u64().unwrap()).collect();
+    assert_eq!(numbers, [1, 2, 3, 4, 6, 7]);
+    for line in lines.iter().filter(|line| line.get("error").is_none()) {
+        let proof: OwnedProof = serde_json::from_value(line["proof"].clone()).unwrap();
+        let leaf = hex::decode(line["leaf"].as_str().unwrap()).unwrap();
+        assert!(MerkleTree::verify_proof_hash(&leaf, &proof.as_proof(), &hex::decode(ROOT).unwrap()));
+    }
+    let codes: Vec<&str> = lines.iter().filter_map(|line| line["error"]["code"].as_str()).collect();
+    assert_eq!(codes, ["LEAF_NOT_FOUND", "INVALID_HEX", "INVALID_HASH_LENGTH"]);
+    check_golden("prove_leaves_file_output", &[&args[..], &["--output", "human"]].concat());
+}
+
+#[test]
+fn test_verify_proof() {
+    check_golden("verify_proof_valid", &["verify-proof", "tests/fixtures/proof_3.json", "--root", ROOT]);
+    let other_root = "00".repeat(32);
+    check_golden(
+        "verify_proof_invalid",
+        &["verify-proof", "tests/fixtures/proof_3.json", "--root", &other_root],
+    );
+    check_golden("verify_proof_usage", &["verify-proof", "tests/fixtures/proof_3.json"]);
+    check_golden(
+        "verify_proof_future_version",
+        &["verify-proof", "tests/fixtures/proof_3_future.json", "--root", ROOT],
+    );
+}
+
+#[test]
+fn test_verify_proof_explains_failures() {
+    let tree = ["--tree", "tests/fixtures/hashes.txt"];
+    check_golden(
+        "verify_proof_bad_leaf",
+        &["verify-proof", "tests/fixtures/proof_3_bad_leaf.json", "--root", ROOT, tree[0], tree[1]],
+    );
+    check_golden(
+        "verify_proof_bad_step2",
+        &["verify-proof", "tests/fixtures/proof_3_bad_step2.json", "--root", ROOT, tree[0], tree[1]],
+    );
+    let other_root = "00".repeat(32);
+    check_golden(
+        "verify_proof_bad_root",
+        &["verify-proof", "tests/fixtures/proof_3.json", "--root", &other_root, tree[0], tree[1], "--output", "json"],
+    );
+    // without the tree, only the recorded tree size and the final comparison are known
+    check_golden(
+        "verify_proof_bad_step2_no_tree",
+        &["verify-proof", "tests/fixtures/proof_3_bad_step2.json", "--root", ROOT],
+    );
+    // the proof of leaf 3 relabeled as leaf 5 does lead to the root, but not from index 5
+    let forged = "tests/fixtures/proof_3_forged_index.json";
+    check_golden("verify_proof_forged_index", &["verify-proof", forged, "--root", ROOT]);
+    check_golden("verify_proof_forged_index_tree", &["verify-proof", forged, "--root", ROOT, tree[0], tree[1]]);
+}
+
+#[test]
+fn test_verify_proof_multiple_roots() {
+    let proof = "tests/fixtures/proof_3.json";
+    let other_root = "00".repeat(32);
+    check_golden("verify_proof_second_root", &["verify-proof", proof, "--root", &other_root, "--root", ROOT]);
+    check_golden(
+        "verify_proof_no_root",
+        &["verify-proof", proof, "--root", &other_root, "--root", &"11".repeat(32), "--output", "json"],
+    );
+    check_golden("verify_proof_roots_file", &["verify-proof", proof, "--roots-file", "tests/fixtures/roots.json"]);
+    let empty = "tests/fixtures/roots_empty.json";
+    check_golden("verify_proof_empty_roots", &["verify-proof", proof, "--roots-file", empty]);
+}
+
+#[test]
+fn test_verify_proof_root_prefix() {
+    // a second root sharing the first 8 hex characters of ROOT
+    let mut set = RootSet::new();
+    for (root, epoch) in [("00".repeat(32), 1), (ROOT.to_string(), 2), (format!("e263b77a{}", "00".repeat(28)), 3)] {
+        set.insert(hex::decode(root).unwrap(), format!("epoch {}", epoch));
+    }
+    let path = std::env::temp_dir().join(format!("merkle-root-prefix-{}.json", std::process::id()));
+    fs::write(&path, serde_json::to_string(&set).unwrap()).unwrap();
+    let verify = |root: &str| {
+        let roots_file = path.to_str().unwrap();
+        let args = ["verify-proof", "tests/fixtures/proof_3.json", "--root", root, "--roots-file", roots_file];
+        let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+        let text = String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap();
+        (output.status.code().unwrap(), text)
+    };
+
+    let (status, output) = verify("E263B77A6");
+    assert_eq!(status, 0, "{}", output);
+    assert!(output.contains(ROOT), "{}", output);
+    let expected = format!("`e263b77a` is the start of 2 roots: {}, e263b77a{}", ROOT, "00".repeat(28));
+    let (status, output) = verify("e263b77a");
+    assert_eq!(status, 1);
+    assert!(output.contains(&expected), "{}", output);
+    // a resolved prefix picks its root out of the file, rather than adding the whole file
+    assert_eq!(verify("00").0, 2);
+    let (status, output) = verify("ff");
+    assert_eq!(status, 3);
+    assert!(output.contains("no root starts with `ff`"), "{}", output);
+    assert_eq!(verify("e26z").0, 1);
+
+    let args = ["verify-proof", "tests/fixtures/proof_3.json", "--root", "e263"];
+    let stderr = String::from_utf8(cargo_bin_cmd!("merkle").args(args).output().unwrap().stderr).unwrap();
+    assert!(stderr.contains("needs --roots-file"), "{}", stderr);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_other_hashers() {
+    let sha512 = "tests/fixtures/hashes_sha512.txt";
+    check_golden("root_sha512_json", &["root", sha512, "--hash", "sha512", "--output", "json"]);
+    check_golden("root_wrong_width", &["root", "tests/fixtures/hashes.txt", "--hash", "ripemd160"]);
+    check_golden("lint_sha512", &["lint", sha512, "--hash", "sha512"]);
+
+    let input = "tests/fixtures/hashes_ripemd160.txt";
+    let output = cargo_bin_cmd!("merkle")
+        .args(["prove", input, "--index", "4", "--hash", "ripemd160", "--output", "json"])
+        .output()
+        .unwrap();
+    let (proof, root) = ("tests/fixtures/proof_ripemd160.json", "9714692c1521060a38b12e5776d5fbc9115bea5e");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), fs::read_to_string(proof).unwrap());
+    check_golden("verify_proof_ripemd160", &["verify-proof", proof, "--root", root, "--tree", input]);
+    check_golden("verify_proof_ripemd160_wrong_root", &["verify-proof", proof, "--root", &root.replace('9', "8")]);
+}
+
+#[test]
+fn test_lint() {
+    check_golden("lint_messy", &["lint", "tests/fixtures/messy_hashes.txt"]);
+    check_golden("lint_messy_json", &["lint", "tests/fixtures/messy_hashes.txt", "--output", "json"]);
+}
+
+#[test]
+fn test_stats() {
+    check_golden("stats", &["stats", "tests/fixtures/hashes.txt"]);
+    check_golden("stats_json", &["stats", "tests/fixtures/hashes.txt", "--output", "json"]);
+    check_golden("stats_messy", &["stats", "tests/fixtures/messy_hashes.txt"]);
+}
+
+#[test]
+fn test_prove_data() {
+    check_golden("prove_data", &["prove-data", "tests/fixtures/strings.txt", "--leaf", "doc-beta"]);
+    check_golden("prove_data_missing", &["prove-data", "tests/fixtures/strings.txt", "--leaf", "doc-omega"]);
+    check_golden("prove_data_bad_utf8", &["prove-data", "tests/fixtures/strings_bad_utf8.txt", "--leaf", "doc-alpha"]);
+    // a blank line is refused as a leaf unless it is asked for
+    let blank = "tests/fixtures/strings_blank.txt";
+    check_golden("prove_data_blank_line", &["prove-data", blank, "--leaf", "doc-beta"]);
+    check_golden("prove_data_allow_empty", &["prove-data", blank, "--leaf", "", "--allow-empty"]);
+
+    // the emitted proof verifies against the root computed by the library
+    let lines = read_data_lines(fs::read("tests/fixtures/strings.txt").unwrap().as_slice()).unwrap();
+    let root = MerkleTree::construct(&lines).root();
+    assert_eq!(hex::encode(&root), STRINGS_ROOT);
+    for leaf in ["doc-alpha", "doc-beta", "doc-delta"] {
+        let output = cargo_bin_cmd!("merkle")
+            .args(["prove-data", "tests/fixtures/strings.txt", "--leaf", leaf])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let proof: OwnedProof = serde_json::from_value(json["proof"].clone()).unwrap();
+        assert_eq!(json["root"], hex::encode(&root));
+        assert!(MerkleTree::verify_proof(&leaf.as_bytes().to_vec(), &proof.as_proof(), &root));
+        // duplicates are proven at their first line
+        assert_eq!(&lines[json["index"].as_u64().unwrap() as usize], leaf.as_bytes());
+    }
+}
+
+/// Trailing newlines are dropped from text by default, so 0, 1 and 2 of them give the same root, and
+/// only kept with `--keep-trailing-empty`, where the second is an empty leaf
+#[test]
+fn test_trailing_newlines() {
+    let dir = std::env::temp_dir().join(format!("merkle-trailing-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let root_of = |ending: &str, extra: &[&str]| {
+        let file = dir.join(format!("strings-{}.txt", ending.len()));
+        fs::write(&file, format!("doc-alpha\ndoc-beta{}", ending)).unwrap();
+        let args = [&["prove-data", file.to_str().unwrap(), "--leaf", "doc-alpha"], extra].concat();
+        let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        json["root"].as_str().unwrap().to_string()
+    };
+    let data = [b"doc-alpha".to_vec(), b"doc-beta".to_vec()];
+    let root = hex::encode(MerkleTree::construct(&data).root());
+    for ending in ["", "\n", "\n\n"] {
+        assert_eq!(root_of(ending, &[]), root, "{:?}", ending);
+    }
+    let keep = ["--keep-trailing-empty"];
+    assert_eq!((root_of("", &keep), root_of("\n", &keep)), (root.clone(), root.clone()));
+    let with_empty = hex::encode(MerkleTree::construct(&[&data[..], &[vec![]]].concat()).root());
+    assert_eq!(root_of("\n\n", &keep), with_empty);
+
+    // hash files skip blank lines wherever they are, and lint reports the trailing ones
+    let hashes = fs::read_to_string("tests/fixtures/hashes.txt").unwrap();
+    let file = dir.join("hashes.txt");
+    for ending in ["", "\n", "\n\n"] {
+        fs::write(&file, format!("{}{}", hashes.trim_end(), ending)).unwrap();
+        let output = cargo_bin_cmd!("merkle").args(["root", file.to_str().unwrap()]).output().unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap().lines().next(), Some(ROOT));
+        let args = ["lint", file.to_str().unwrap(), "--output", "json"];
+        let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(report["trailing_blank_lines"], (ending.len() / 2), "{:?}", ending);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_verify_data() {
+    let proof = "tests/fixtures/strings_proof_gamma.json";
+    check_golden("verify_data_valid", &["verify-data", proof, "--leaf", "doc-gamma", "--root", STRINGS_ROOT]);
+    check_golden("verify_data_wrong_leaf", &["verify-data", proof, "--leaf", "doc-beta", "--root", STRINGS_ROOT]);
+}
+
+#[test]
+fn test_compare() {
+    let reference = "tests/fixtures/strings_hashes.txt";
+    check_golden("compare_identical", &["compare", "tests/fixtures/strings.txt", "--tree", reference]);
+    check_golden("compare_edited", &["compare", "tests/fixtures/strings_edited.txt", "--tree", reference]);
+    check_golden(
+        "compare_shorter_json",
+        &["compare", "tests/fixtures/strings_head.txt", "--tree", reference, "--output", "json"],
+    );
+    let blank = ["--error-json", "compare", "tests/fixtures/strings_blank.txt", "--tree", reference];
+    check_golden("compare_blank_line_json", &blank);
+}
+
+#[test]
+fn test_diff() {
+    let hashes = "tests/fixtures/hashes.txt";
+    let (head, tail) = ("tests/fixtures/hashes_head.txt", "tests/fixtures/hashes_tail.txt");
+    check_golden("diff_identical", &["diff", hashes, hashes]);
+    check_golden("diff_edited", &["diff", hashes, "tests/fixtures/hashes_edited.txt"]);
+    check_golden("diff_extended", &["diff", head, hashes]);
+    check_golden("diff_truncated_json", &["diff", hashes, head, "--output", "json"]);
+    check_golden("diff_unrelated", &["diff", head, tail]);
+    check_golden("diff_wrong_hasher", &["diff", hashes, hashes, "--hash", "sha512"]);
+}
+
+#[test]
+fn test_proof_bundles() {
+    let output = cargo_bin_cmd!("merkle")
+        .args(["export-proofs", "tests/fixtures/hashes.txt"])
+        .env("SOURCE_DATE_EPOCH", "1700000000")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), fs::read_to_string("tests/fixtures/bundle.ndjson").unwrap());
+
+    check_golden("verify_bundle_valid", &["verify-proof", "--bundle", "tests/fixtures/bundle.ndjson", "--root", ROOT]);
+    let other_root = "00".repeat(32);
+    check_golden(
+        "verify_bundle_untrusted_json",
+        &["verify-proof", "--bundle", "tests/fixtures/bundle.ndjson", "--root", &other_root, "--output", "json"],
+    );
+    check_golden(
+        "verify_bundle_bad_row",
+        &["verify-proof", "--bundle", "tests/fixtures/bundle_bad_row.ndjson", "--root", ROOT],
+    );
+}
+
+#[test]
+fn test_verify_bundle_command() {
+    check_golden("verify_bundle_command", &["verify-bundle", "tests/fixtures/bundle.ndjson", "--root", ROOT]);
+    check_golden(
+        "verify_bundle_command_bad_row",
+        &["verify-bundle", "tests/fixtures/bundle_bad_row.ndjson", "--root", ROOT, "--threads", "2"],
+    );
+
+    // a bundle of 10,000 proofs with a corrupted proof, a row outside the tree and a truncated row
+    let data: Vec<Vec<u8>> = (0..10_000u32).map(|i| i.to_be_bytes().to_vec()).collect();
+    let tree = MerkleTree::construct(&data);
+    let mut bundle = Vec::new();
+    ProofBundle::from_tree(&tree, LeafMode::HashData).unwrap().write(&mut bundle).unwrap();
+    let mut lines: Vec<String> = String::from_utf8(bundle).unwrap().lines().map(str::to_string).collect();
+    // the first step of the proof of leaf 7,000 is its sibling
+    let step = hex::encode(&tree.leaves()[7_001]);
+    lines[7_001] = lines[7_001].replacen(&step, &"00".repeat(32), 1);
+    lines[20] = lines[20].replacen("\"index\":19,", "\"index\":10000,", 1);
+    lines[9_999].truncate(40);
+    let path = std::env::temp_dir().join(format!("merkle-verify-bundle-{}.ndjson", std::process::id()));
+    fs::write(&path, lines.join("\n")).unwrap();
+
+    let root = hex::encode(tree.root());
+    let args = ["verify-bundle", path.to_str().unwrap(), "--root", &root, "--threads", "4", "--output", "json"];
+    let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!((&json["valid"], &json["rows"], &json["valid_rows"]), (&false.into(), &10_000.into(), &9_997.into()));
+    assert_eq!(json["invalid_lines"], serde_json::json!([21, 7_002]));
+    assert_eq!(json["malformed"][0]["line"], 10_000);
+
+    let human = ["verify-bundle", path.to_str().unwrap(), "--root", &root];
+    let output = cargo_bin_cmd!("merkle").args(human).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("invalid: 3 of 10000 rows of the bundle do not lead to root"), "{}", stdout);
+    assert!(stdout.contains("  line 21: the proof does not lead to the root\n  line 7002:"), "{}", stdout);
+    fs::remove_file(&path).unwrap();
+    check_golden("verify_bundle_command_usage", &["verify-bundle", "tests/fixtures/bundle.ndjson", "--threads", "2"]);
+}
+
+#[test]
+fn test_detect() {
+    let file = "tests/fixtures/hashes.txt";
+    check_golden("detect", &["detect", "--input", file, "--root", ROOT]);
+    check_golden("detect_unknown_json", &["detect", "--input", file, "--root", &"00".repeat(32), "--output", "json"]);
+
+    // the root of a partner's tree hashing `right || left`
+    let hashes = fs::read_to_string(file).unwrap();
+    let mut level: Vec<Vec<u8>> = hashes.lines().map(|line| hex::decode(line).unwrap()).collect();
+    while level.len() > 1 {
+        if level.len() & 1 == 1 {
+            level.push(level[level.len() - 1].clone());
+        }
+        level = level.chunks(2).map(|pair| Sha256Hasher.node_hash(&pair[1], &pair[0])).collect();
+    }
+    check_golden("detect_mirrored", &["detect", "--input", file, "--root", &hex::encode(&level[0])]);
+    let strings = ["detect", "--input", "tests/fixtures/strings.txt", "--format", "lines", "--root", STRINGS_ROOT];
+    check_golden("detect_strings_json", &[&strings[..], &["--output", "json"]].concat());
+}
+
+#[test]
+fn test_json_output_is_deterministic() {
+    // 40 hashes each repeated, so a report ordered by a hash map would differ between runs
+    let hashes = fs::read_to_string("tests/fixtures/hashes.txt").unwrap();
+    let hashes: Vec<&str> = hashes.lines().collect();
+    let repeated: String = (0..120).map(|i| format!("{}{:02x}\n", &hashes[i % 40 % 7][..62], i % 40)).collect();
+    let path = std::env::temp_dir().join(format!("merkle-deterministic-{}.txt", std::process::id()));
+    fs::write(&path, repeated).unwrap();
+    let file = path.to_str().unwrap();
+
+    let (edited, bundle) = ("tests/fixtures/hashes_edited.txt", "tests/fixtures/bundle_bad_row.ndjson");
+    let (strings, reference) = ("tests/fixtures/strings_edited.txt", "tests/fixtures/strings_hashes.txt");
+    let commands: [&[&str]; 6] = [
+        &["lint", file, "--output", "json"],
+        &["root", file, "--output", "json"],
+        &["diff", "tests/fixtures/hashes.txt", edited, "--output", "json"],
+        &["compare", strings, "--tree", reference, "--output", "json"],
+        &["verify-bundle", bundle, "--root", ROOT, "--output", "json"],
+        &["prove", file, "--index", "77", "--output", "json"],
+    ];
+    for args in commands {
+        let first = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+        let second = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+        assert!(!first.stdout.is_empty(), "{:?}", args);
+        assert_eq!((first.stdout, first.status), (second.stdout, second.status), "{:?}", args);
+    }
+
+    let lint = cargo_bin_cmd!("merkle").args(commands[0]).output().unwrap();
+    let lint: serde_json::Value = serde_json::from_slice(&lint.stdout).unwrap();
+    let duplicates = lint["duplicates"].as_array().unwrap();
+    let first_lines: Vec<u64> = duplicates.iter().map(|d| d["first_line"].as_u64().unwrap()).collect();
+    assert_eq!(first_lines, (1..=40).collect::<Vec<_>>());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_diff_is_readable() {
+    assert_eq!(diff("a\nb\nc\n", "a\nx\nc\n"), "  a\n+ x\n- b\n  c\n");
+}
+
+#[test]
+fn test_dispatch() {
+    check_golden("unknown_command", &["frobnicate", "tests/fixtures/hashes.txt"]);
+    check_golden("help", &["--help"]);
+
+    let help = cargo_bin_cmd!("merkle").arg("-h").output().unwrap();
+    assert!(help.status.success());
+    let help = String::from_utf8(help.stdout).unwrap();
+    let commands = [
+        "root", "chunk-root", "prove", "verify-proof", "prove-data", "verify-data", "compare", "diff", "detect",
+        "verify-bundle", "export-proofs", "lint", "stats", "generate", "export-solidity", "sign-root", "verify-signed",
+        "serve",
+    ];
+    for command in commands {
+        assert!(help.lines().any(|line| line.starts_with(&format!("  {} ", command))), "{} missing", command);
+    }
+
+    let version = cargo_bin_cmd!("merkle").arg("--version").output().unwrap();
+    assert!(version.status.success());
+    assert_eq!(String::from_utf8(version.stdout).unwrap(), format!("merkle {}\n", env!("CARGO_PKG_VERSION")));
+}
+
+#[test]
+fn test_generate_and_deprecated_modes() {
+    let dir = std::env::temp_dir().join(format!("merkle-cli-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let file = dir.join("generated.txt");
+    // a crashed earlier run left its temporary file behind, which the next run removes
+    let orphan = merkle::util::io::temp_path(&file);
+    fs::write(&orphan, "e3b0c442").unwrap();
+    let output = cargo_bin_cmd!("merkle").args(["generate", file.to_str().unwrap(), "--count", "3"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&file).unwrap().lines().count(), 3);
+    assert!(!orphan.exists());
+    assert!(cargo_bin_cmd!("merkle").args(["root", file.to_str().unwrap()]).output().unwrap().status.success());
+
+    // the historical modes still write ts_hashes.json in the working directory, with a warning
+    for mode in ["1", "2"] {
+        let output = cargo_bin_cmd!("merkle").arg(mode).current_dir(&dir).output().unwrap();
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("deprecated, use `merkle generate ts_hashes.json`"), "{}", stderr);
+        assert_eq!(fs::read_to_string(dir.join("ts_hashes.json")).unwrap().lines().count(), 10);
+    }
+
+    // running without a command folds ts_hashes.json into the root `merkle root --hash sha256d` prints
+    let output = cargo_bin_cmd!("merkle").current_dir(&dir).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let folded = stdout.lines().find_map(|line| line.strip_prefix("Merkle Root: ")).unwrap();
+    let args = ["root", "ts_hashes.json", "--hash", "sha256d"];
+    let output = cargo_bin_cmd!("merkle").args(args).current_dir(&dir).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().next(), Some(folded));
+    fs::remove_dir_all(&dir).unwrap();
+}
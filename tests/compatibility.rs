@@ -0,0 +1,108 @@
+//! Artifacts saved under one hasher and leaf mode only load under that same configuration
+//!
+//! Every artifact is saved under each configuration and loaded under every other one; the
+//! configuration that wrote it must be the only one that reads it back.
+
+use merkle::merkel::DefaultHashes;
+use merkle::prelude::*;
+use merkle::util::archive::ProofArchive;
+use merkle::util::bundle::ProofBundle;
+
+const MODES: [LeafMode; 2] = [LeafMode::HashData, LeafMode::PreHashed];
+
+fn configs() -> Vec<TreeConfig> {
+    HashAlgorithm::ALL
+        .into_iter()
+        .flat_map(|hasher| MODES.map(|mode| TreeConfig::new(mode).with_hasher(hasher)))
+        .collect()
+}
+
+fn tag(config: &TreeConfig) -> String {
+    format!("{}/{}", config.hasher().id(), config.leaf_mode().id())
+}
+
+/// A tree of 5 leaves, hashes of the hasher's width when the leaves are pre-hashed
+fn tree(config: &TreeConfig) -> MerkleTree {
+    let data: Vec<Data> = (0..5u8).map(|i| vec![i; config.hasher().output_len()]).collect();
+    config.construct(&data).unwrap()
+}
+
+/// Checks that `load` succeeds exactly under `saved`, naming both tags in every mismatch
+fn check_matrix<T>(artifact: &str, saved: &TreeConfig, load: impl Fn(&TreeConfig) -> Result<T, LibError>) {
+    for config in configs() {
+        match load(&config) {
+            Ok(_) => assert_eq!(config, *saved, "{} of {} loaded as {}", artifact, tag(saved), tag(&config)),
+            Err(LibError::HasherMismatch { artifact: found_in, expected, found }) => {
+                assert_ne!(config, *saved, "{} of {} rejected by its own configuration", artifact, tag(saved));
+                assert_eq!((found_in, expected, found), (artifact, tag(&config), tag(saved)));
+            }
+            Err(e) => panic!("{} of {} loaded as {}: {}", artifact, tag(saved), tag(&config), e),
+        }
+    }
+}
+
+#[test]
+fn test_bundles() {
+    for saved in configs() {
+        let mut bytes = Vec::new();
+        ProofBundle::from_tree(&tree(&saved), saved.leaf_mode()).unwrap().write(&mut bytes).unwrap();
+        check_matrix("proof bundle", &saved, |config| ProofBundle::read_with(bytes.as_slice(), true, config));
+    }
+}
+
+#[test]
+fn test_archives() {
+    for (i, saved) in configs().into_iter().enumerate() {
+        let path = std::env::temp_dir().join(format!("merkle-compatibility-{}-{}", std::process::id(), i));
+        ProofArchive::build(&tree(&saved), saved.leaf_mode(), &path).unwrap();
+        check_matrix("proof archive", &saved, |config| ProofArchive::open_with(&path, config));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn test_cbor_trees() {
+    for saved in configs() {
+        let mut bytes = Vec::new();
+        saved.save_cbor(&tree(&saved), &mut bytes).unwrap();
+        check_matrix("CBOR tree", &saved, |config| config.load_cbor(bytes.as_slice()));
+    }
+}
+
+/// A proof of leaf 2, tagged with the hasher and leaf mode of `config`
+fn proof(config: &TreeConfig) -> OwnedProof {
+    config.tag_proof(tree(config).prove_by_index(2).unwrap().into_owned())
+}
+
+#[test]
+fn test_json_proofs() {
+    for saved in configs() {
+        let json = serde_json::to_string(&proof(&saved)).unwrap();
+        check_matrix("JSON proof", &saved, |config| config.load_proof_json(&json));
+    }
+}
+
+#[test]
+fn test_compressed_proofs() {
+    for saved in configs() {
+        let defaults = DefaultHashes::for_leaf_with(&saved.hasher(), &vec![0], tree(&saved).depth());
+        let bytes = proof(&saved).to_bytes_compressed(&defaults).unwrap();
+        check_matrix("compressed proof", &saved, |config| config.load_proof_compressed(&bytes, &defaults));
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn test_cbor_proofs() {
+    for saved in configs() {
+        let bytes = proof(&saved).to_cbor().unwrap();
+        check_matrix("CBOR proof", &saved, |config| config.load_proof_cbor(&bytes));
+        // an untagged proof loads under no configuration
+        let untagged = tree(&saved).prove_by_index(2).unwrap().into_owned().to_cbor().unwrap();
+        assert!(matches!(
+            saved.load_proof_cbor(&untagged),
+            Err(LibError::HasherMismatch { found, .. }) if found == "unrecorded/unrecorded"
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+//! Every way of building a tree from the same leaves must agree on its root and proofs
+//!
+//! Odd sizes are the interesting part: each construction path has to pad the lone last node of
+//! every odd level in the same way.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use merkle::prelude::*;
+
+fn random_data(rng: &mut StdRng, n: usize) -> Vec<Data> {
+    (0..n)
+        .map(|_| {
+            let len = rng.random_range(0..48);
+            (0..len).map(|_| rng.random()).collect()
+        })
+        .collect()
+}
+
+/// Proof of every leaf, as owned hashes
+fn all_proofs(tree: &MerkleTree) -> Vec<OwnedProof> {
+    (0..tree.len()).map(|i| tree.prove_by_index(i).unwrap().into_owned()).collect()
+}
+
+#[test]
+fn test_construction_paths_agree() {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let mut rng = StdRng::seed_from_u64(0x6d65726b6c65);
+    let mut sizes: Vec<usize> = (0..64).map(|_| rng.random_range(1..=300)).collect();
+    sizes.extend([1, 2, 3, 255, 256, 257, 299, 300]);
+
+    for n in sizes {
+        let data = random_data(&mut rng, n);
+        let leaves: Vec<Hash> = data.iter().map(|d| Sha256Hasher.leaf_hash(d)).collect();
+
+        let expected = MerkleTree::construct(&data);
+        let trees = [
+            ("from_items", MerkleTree::from_items(&data)),
+            ("from_leaf_hashes", MerkleTree::from_leaf_hashes(leaves.clone())),
+            ("config hash-data", TreeConfig::default().construct(&data).unwrap()),
+            ("config pre-hashed", TreeConfig::new(LeafMode::PreHashed).construct(&leaves).unwrap()),
+            ("construct_retaining", MerkleTree::construct_retaining(data.clone())),
+            ("construct_async", runtime.block_on(MerkleTree::construct_async(&data, 7)).unwrap()),
+            (
+                "from_stream",
+                runtime.block_on(MerkleTree::from_stream(futures::stream::iter(data.clone()))).unwrap(),
+            ),
+        ];
+
+        let expected_proofs = all_proofs(&expected);
+        for (path, tree) in trees {
+            assert_eq!(tree.levels(), expected.levels(), "{} with {} leaves", path, n);
+            assert_eq!(all_proofs(&tree), expected_proofs, "{} with {} leaves", path, n);
+        }
+        for (i, proof) in expected_proofs.iter().enumerate() {
+            assert!(MerkleTree::verify_proof(&data[i], &proof.as_proof(), &expected.root()));
+        }
+    }
+}
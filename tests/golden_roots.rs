@@ -0,0 +1,75 @@
+#![cfg(feature = "test-utils")]
+
+//! Every scheme still gives the roots recorded in the committed registry
+//!
+//! A failure here means a root users have committed to can no longer be rebuilt. If the change
+//! is deliberate, rewrite the registry with
+//! `cargo run --features test-utils --bin regen-goldens -- --i-know-this-breaks-compat`.
+
+use assert_cmd::cargo::cargo_bin_cmd;
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use merkle::util::golden::{golden_roots, read_golden_roots, GoldenRoot, GOLDEN_ROOTS_FILE};
+
+fn registry_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(GOLDEN_ROOTS_FILE)
+}
+
+fn registry() -> Vec<GoldenRoot> {
+    read_golden_roots(registry_path()).unwrap()
+}
+
+#[test]
+fn test_roots_are_unchanged() {
+    let moved: Vec<String> = registry()
+        .iter()
+        .filter_map(|golden| {
+            let root = golden.current_root().unwrap();
+            (root != golden.root).then(|| {
+                format!("{}: recorded {}, now {}", golden.name(), hex::encode(&golden.root), hex::encode(root))
+            })
+        })
+        .collect();
+    assert!(moved.is_empty(), "{} roots moved:\n{}", moved.len(), moved.join("\n"));
+}
+
+#[test]
+fn test_registry_covers_every_scheme() {
+    let recorded: BTreeSet<String> = registry().iter().map(GoldenRoot::name).collect();
+    let supported: BTreeSet<String> = golden_roots().unwrap().iter().map(GoldenRoot::name).collect();
+    let missing: Vec<&String> = supported.difference(&recorded).collect();
+    let stale: Vec<&String> = recorded.difference(&supported).collect();
+    assert!(missing.is_empty() && stale.is_empty(), "missing {:?}, no longer built {:?}", missing, stale);
+    assert_eq!(registry().len(), recorded.len(), "the registry names a scheme twice");
+}
+
+#[test]
+fn test_anchors() {
+    // SHA-256 of the entries `leaf 53` and `leaf 90`, and of their concatenation, worked out
+    // without this crate
+    let registry = registry();
+    let root = |name: &str| hex::encode(&registry.iter().find(|golden| golden.name() == name).unwrap().root);
+    let one = "cb7c66e85aee2fc1a63e18745d8508ade384759e315bf41b61a436a3acc40ee9";
+    let two = "f3353fa1851b0073f7dd78eee660dbe7249ec28fa06c6015b9769bac438f9d3f";
+    assert_eq!(root("sha256/hash-data/duplicate/arrival/leaves-1"), one);
+    assert_eq!(root("sha256/hash-data/promote/arrival/leaves-2"), two);
+}
+
+#[test]
+fn test_regeneration_needs_confirmation() {
+    let before = std::fs::read(registry_path()).unwrap();
+    let out = std::env::temp_dir().join(format!("merkle-regen-goldens-{}", std::process::id()));
+    let output = cargo_bin_cmd!("regen-goldens").args(["--out", out.to_str().unwrap()]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("--i-know-this-breaks-compat"));
+    assert!(!out.exists());
+
+    let args = ["--i-know-this-breaks-compat", "--out", out.to_str().unwrap()];
+    let output = cargo_bin_cmd!("regen-goldens").args(args).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(read_golden_roots(&out).unwrap(), registry());
+    assert_eq!(std::fs::read(registry_path()).unwrap(), before);
+    std::fs::remove_file(out).unwrap();
+}
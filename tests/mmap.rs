@@ -0,0 +1,51 @@
+#![cfg(feature = "mmap")]
+
+use assert_cmd::cargo::cargo_bin_cmd;
+
+use std::fs;
+
+fn run(args: &[&str]) -> (i32, String, String) {
+    let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+    (
+        output.status.code().unwrap_or(-1),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_root_from_raw_fixed_records() {
+    let dir = std::env::temp_dir().join(format!("merkle-mmap-cli-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (lines, raw) = (dir.join("txids.txt"), dir.join("txids.bin"));
+    // 10,000 records, so the mapping spans many pages
+    let txids: Vec<[u8; 32]> = (0..10_000u32).map(|i| [i.to_be_bytes(); 8].concat().try_into().unwrap()).collect();
+    fs::write(&lines, txids.iter().map(|txid| hex::encode(txid) + "\n").collect::<String>()).unwrap();
+    fs::write(&raw, txids.concat()).unwrap();
+    let (lines, raw) = (lines.to_str().unwrap(), raw.to_str().unwrap());
+
+    let (status, expected, _) = run(&["root", lines, "--output", "json"]);
+    assert_eq!(status, 0);
+    assert_eq!(run(&["root", raw, "--input-format", "raw-fixed:32", "--output", "json"]), (0, expected, String::new()));
+
+    // records must be as wide as the hashes of --hash
+    let (status, stdout, _) = run(&["root", raw, "--input-format", "raw-fixed:20", "--hash", "ripemd160"]);
+    assert_eq!((status, stdout.lines().count()), (0, 2));
+    let (status, _, stderr) = run(&["root", raw, "--input-format", "raw-fixed:20"]);
+    assert_eq!((status, stderr.as_str()), (1, "error: leaf 0 is 20 bytes long, expected a 32-byte hash\n"));
+
+    fs::write(dir.join("txids.bin"), &txids.concat()[..32 * 9 + 7]).unwrap();
+    let (status, _, stderr) = run(&["root", raw, "--input-format", "raw-fixed:32"]);
+    assert_eq!(status, 1);
+    assert!(
+        stderr.contains("is 295 bytes, not a whole number of 32-byte records: 7 bytes are left over"),
+        "{}",
+        stderr
+    );
+
+    let (status, _, stderr) = run(&["root", raw, lines, "--input-format", "raw-fixed:32"]);
+    assert_eq!((status, stderr.contains("--input-format only supports a single file")), (1, true));
+    let (status, _, stderr) = run(&["root", raw, "--input-format", "raw:32"]);
+    assert_eq!((status, stderr.contains("--input-format expects raw-fixed:<width>, got `raw:32`")), (1, true));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,64 @@
+#![cfg(feature = "test-utils")]
+
+//! Replays the committed pipeline cases, each built, proved, tampered with and serialized
+//!
+//! Every file under `tests/corpus` is one `PipelineCase` in JSON. A case the `pipeline_replay`
+//! fuzz target fails on is printed as such a file, and is committed here once fixed.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::fs;
+use std::path::PathBuf;
+
+use merkle::util::pipeline::{PipelineCase, PipelineRun};
+
+fn corpus() -> Vec<(String, PipelineCase)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let case = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            (name, case)
+        })
+        .collect()
+}
+
+#[test]
+fn test_corpus_keeps_invariants() {
+    let mut total = PipelineRun::default();
+    let mut unbuilt = vec![];
+    for (name, case) in corpus() {
+        let json = serde_json::to_string(&case).unwrap();
+        assert_eq!(serde_json::from_str::<PipelineCase>(&json).unwrap(), case, "{}", name);
+        let run = case.run().unwrap_or_else(|violation| panic!("{}: {}", name, violation));
+        if !run.built {
+            unbuilt.push(name);
+        }
+        total.proofs += run.proofs;
+        total.out_of_range += run.out_of_range;
+        total.rejected += run.rejected;
+        total.skipped += run.skipped;
+    }
+    assert_eq!(unbuilt, ["empty.json", "pre_hashed_wrong_width.json"]);
+    assert!(total.proofs >= 15 && total.out_of_range >= 4, "{:?}", total);
+    // duplicate steps and repeated entries make some mutations no-ops, which are skipped
+    assert!(total.rejected >= 150 && total.skipped > 0, "{:?}", total);
+}
+
+#[test]
+fn test_random_cases_keep_invariants() {
+    let mut rng = StdRng::seed_from_u64(193);
+    for _ in 0..300 {
+        let len = rng.random_range(0..600);
+        let bytes: Vec<u8> = (0..len).map(|_| rng.random()).collect();
+        let case = PipelineCase::from_fuzz_bytes(&bytes);
+        assert_eq!(PipelineCase::from_fuzz_bytes(&bytes), case);
+        if let Err(violation) = case.run() {
+            panic!("{}\n{}", violation, serde_json::to_string_pretty(&case).unwrap());
+        }
+    }
+}
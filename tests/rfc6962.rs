@@ -0,0 +1,101 @@
+//! Left-balanced trees with RFC 6962 hashing reproduce the roots and audit paths of RFC 6962 and
+//! Tendermint
+//!
+//! `fixtures/rfc6962_vectors.json` holds the published RFC 6962 roots of the first leaves of its
+//! test data, with the audit path of every leaf in the order Tendermint's `Proof.Aunts` lists it.
+
+use merkle::merkel::TreeShape;
+use merkle::prelude::*;
+
+fn config() -> TreeConfig {
+    TreeConfig::default().with_shape(TreeShape::LeftBalanced).with_hasher(HashAlgorithm::Sha256Rfc6962)
+}
+
+fn bytes(value: &serde_json::Value) -> Vec<u8> {
+    hex::decode(value.as_str().unwrap()).unwrap()
+}
+
+/// Largest power of two below `n`, for `n` of at least 2
+fn split_point(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+/// The root by recursion on the split point, as RFC 6962 defines it
+fn recursive_root(hasher: &impl MerkleHasher, leaves: &[Hash]) -> Hash {
+    match leaves {
+        [leaf] => leaf.clone(),
+        _ => {
+            let (left, right) = leaves.split_at(split_point(leaves.len()));
+            hasher.node_hash(&recursive_root(hasher, left), &recursive_root(hasher, right))
+        }
+    }
+}
+
+/// Tendermint's `computeHashFromAunts`: the last aunt is the sibling just below the root
+fn root_from_aunts(hasher: &impl MerkleHasher, index: usize, total: usize, leaf: &Hash, aunts: &[Hash]) -> Hash {
+    if total == 1 {
+        return leaf.clone();
+    }
+    let (below, top) = aunts.split_at(aunts.len() - 1);
+    let left = split_point(total);
+    if index < left {
+        hasher.node_hash(&root_from_aunts(hasher, index, left, leaf, below), &top[0])
+    } else {
+        hasher.node_hash(&top[0], &root_from_aunts(hasher, index - left, total - left, leaf, below))
+    }
+}
+
+#[test]
+fn test_vectors() {
+    let vectors: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("tests/fixtures/rfc6962_vectors.json").unwrap()).unwrap();
+    let hasher = HashAlgorithm::Sha256Rfc6962;
+
+    for case in vectors.as_array().unwrap() {
+        let items: Vec<Data> = case["items"].as_array().unwrap().iter().map(bytes).collect();
+        let root = bytes(&case["root"]);
+        if items.is_empty() {
+            // trees here have at least one leaf; RFC 6962 defines the empty root as the hash of nothing
+            assert!(matches!(config().construct(&items), Err(LibError::EmptyInput)));
+            assert_eq!(Sha256Hasher.leaf_hash(b""), root);
+            continue;
+        }
+
+        let tree = config().construct(&items).unwrap();
+        assert_eq!(hex::encode(tree.root()), hex::encode(&root), "{} leaves", items.len());
+        for vector in case["proofs"].as_array().unwrap() {
+            let index = vector["index"].as_u64().unwrap() as usize;
+            let aunts: Vec<Hash> = vector["aunts"].as_array().unwrap().iter().map(bytes).collect();
+            assert_eq!(tree.leaves()[index], bytes(&vector["leaf_hash"]));
+
+            let proof = tree.prove_by_index(index).unwrap().into_owned();
+            let path: Vec<Hash> = proof.hashes().iter().map(|(_, hash)| hash.to_vec()).collect();
+            assert_eq!(path, aunts, "leaf {} of {}", index, items.len());
+            assert_eq!(root_from_aunts(&hasher, index, items.len(), &tree.leaves()[index], &path), root);
+
+            let received = OwnedProof::from_audit_path(index, items.len(), aunts).unwrap();
+            assert!(MerkleTree::verify_proof_hash_with(&hasher, &tree.leaves()[index], &received.as_proof(), &root));
+        }
+
+        let paired = TreeConfig::default().with_hasher(hasher).construct(&items).unwrap();
+        assert_eq!(paired.root() == root, items.len().is_power_of_two());
+    }
+}
+
+#[test]
+fn test_levels_match_the_recursion() {
+    for hasher in HashAlgorithm::ALL {
+        let config = TreeConfig::default().with_shape(TreeShape::LeftBalanced).with_hasher(hasher);
+        for n in 1..=70u32 {
+            let data: Vec<Data> = (0..n).map(|i| i.to_be_bytes().to_vec()).collect();
+            let tree = config.construct(&data).unwrap();
+            assert_eq!(tree.root(), recursive_root(&hasher, tree.leaves()), "{} with {} leaves", hasher.id(), n);
+            for index in [0, n as usize / 2, n as usize - 1] {
+                let proof = tree.prove_by_index(index).unwrap().into_owned();
+                let path: Vec<Hash> = proof.hashes().iter().map(|(_, hash)| hash.to_vec()).collect();
+                let root = root_from_aunts(&hasher, index, data.len(), &tree.leaves()[index], &path);
+                assert_eq!(root, tree.root());
+            }
+        }
+    }
+}
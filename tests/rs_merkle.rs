@@ -0,0 +1,53 @@
+//! Proofs persisted by `rs_merkle` verify against the roots of trees built here
+//!
+//! `fixtures/rs_merkle_proofs.json` holds trees of the SHA-256 hashes of `tx-0`, `tx-1`, ..., with
+//! their roots and the proof of every leaf as `MerkleProof::to_bytes` writes it. They were computed
+//! with Python's `hashlib` following `rs_merkle`'s construction, not by this crate. The trees are
+//! of 1 to 8 leaves, so their right edges have lone nodes at several depths.
+
+use merkle::merkel::{IndexedProof, OddLeafPolicy, Sha256Hasher};
+use merkle::prelude::*;
+use merkle::util::compat::FlatProof;
+
+fn bytes(value: &serde_json::Value) -> Vec<u8> {
+    hex::decode(value.as_str().unwrap()).unwrap()
+}
+
+#[test]
+fn test_fixtures_verify_end_to_end() {
+    let fixtures: serde_json::Value = serde_json::from_str(include_str!("fixtures/rs_merkle_proofs.json")).unwrap();
+    let config = TreeConfig::new(LeafMode::PreHashed).with_odd_leaf_policy(OddLeafPolicy::Promote);
+    for fixture in fixtures.as_array().unwrap() {
+        let items: Vec<&str> = fixture["items"].as_array().unwrap().iter().map(|item| item.as_str().unwrap()).collect();
+        let leaves: Vec<Hash> = items.iter().map(|item| Sha256Hasher.leaf_hash(item.as_bytes())).collect();
+        let tree = config.construct(&leaves).unwrap();
+        assert_eq!(tree.root(), bytes(&fixture["root"]), "{} leaves", items.len());
+
+        for (entry, leaf) in fixture["proofs"].as_array().unwrap().iter().zip(&leaves) {
+            let (index, total) = (entry["leaf_index"].as_u64().unwrap(), entry["total_leaves"].as_u64().unwrap());
+            let flat = FlatProof::from_bytes(index as usize, total as usize, &bytes(&entry["proof_bytes"])).unwrap();
+            let proof = OwnedProof::try_from(flat.clone()).unwrap();
+            assert!(MerkleTree::verify_proof_hash(leaf, &proof.as_proof(), &tree.root()));
+            assert_eq!(proof, tree.prove_by_index(index as usize).unwrap().into_owned());
+            assert_eq!(FlatProof::try_from(&proof).unwrap(), flat);
+            assert_eq!(flat.to_bytes(), bytes(&entry["proof_bytes"]));
+
+            // a path past no lone node has the shape of a padded proof, still leading to this root
+            let padded = TreeConfig::new(LeafMode::PreHashed).construct(&leaves).unwrap();
+            match IndexedProof::try_from(flat.clone()) {
+                Ok(indexed) => {
+                    assert!(MerkleTree::verify_proof_hash(leaf, &indexed.to_proof().as_proof(), &tree.root()));
+                    assert_eq!(FlatProof::try_from(&indexed).unwrap(), flat);
+                    if total.is_power_of_two() {
+                        assert_eq!(indexed.to_proof(), padded.prove_by_index(index as usize).unwrap().into_owned());
+                    }
+                }
+                Err(e) => {
+                    assert!(matches!(e, LibError::InvalidFormat(_)));
+                    let indexed = padded.prove_by_index(index as usize).unwrap().into_owned().to_indexed().unwrap();
+                    assert!(FlatProof::try_from(&indexed).is_err());
+                }
+            }
+        }
+    }
+}
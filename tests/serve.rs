@@ -0,0 +1,183 @@
+#![cfg(feature = "http")]
+
+use serde_json::{json, Value};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+const ROOT: &str = "e263b77a6d80c1c56f3f67d1e0d803ad8eb2ac9d66c82f78735207c886a1592c";
+
+/// The running `merkle serve` process, killed when dropped
+struct Server {
+    child: Child,
+    addr: String,
+}
+
+impl Server {
+    fn start(file: &str) -> Server {
+        Server::start_with(file, &[])
+    }
+
+    fn start_with(file: &str, options: &[&str]) -> Server {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_merkle"))
+            .args(["serve", file, "--addr", "127.0.0.1:0", "--cache", "4"])
+            .args(options)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+        let addr = line.trim().strip_prefix("listening on ").expect(&line).to_string();
+        Server { child, addr }
+    }
+
+    /// Sends one request and returns the status code and JSON body
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> (u16, Value) {
+        let body = body.map(Value::to_string).unwrap_or_default();
+        let mut stream = TcpStream::connect(&self.addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            self.addr,
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap_or(Value::Null))
+    }
+
+    fn get(&self, path: &str) -> (u16, Value) {
+        self.request("GET", path, None)
+    }
+
+    fn post(&self, path: &str, body: &Value) -> (u16, Value) {
+        self.request("POST", path, Some(body))
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn test_root() {
+    let server = Server::start("tests/fixtures/hashes.txt");
+    assert_eq!(server.get("/root"), (200, json!({ "root": ROOT, "leaf_count": 7 })));
+}
+
+#[test]
+fn test_proof_and_verify() {
+    let server = Server::start("tests/fixtures/hashes.txt");
+
+    for index in [0, 3, 6] {
+        let (status, body) = server.get(&format!("/proof/{}", index));
+        assert_eq!(status, 200);
+        assert_eq!(body["index"], index);
+        assert_eq!(body["proof"]["steps"].as_array().unwrap().len(), 3);
+
+        let request = json!({ "leaf": body["leaf"], "proof": body["proof"] });
+        assert_eq!(server.post("/verify", &request), (200, json!({ "valid": true })));
+    }
+
+    // a proof for another leaf does not verify
+    let (_, first) = server.get("/proof/0");
+    let (_, second) = server.get("/proof/1");
+    let request = json!({ "leaf": first["leaf"], "proof": second["proof"] });
+    assert_eq!(server.post("/verify", &request), (200, json!({ "valid": false })));
+
+    // nor does a proof sent with an index its directions do not lead to
+    let request = json!({ "leaf": first["leaf"], "index": 2, "proof": first["proof"] });
+    assert_eq!(server.post("/verify", &request), (200, json!({ "valid": false })));
+    let request = json!({ "leaf": first["leaf"], "index": 0, "proof": first["proof"] });
+    assert_eq!(server.post("/verify", &request), (200, json!({ "valid": true })));
+
+    // a proof tagged with the size of another tree is refused
+    let mut tagged = first["proof"].clone();
+    tagged["tree_size"] = json!(8);
+    let (status, body) = server.post("/verify", &json!({ "leaf": first["leaf"], "proof": tagged }));
+    let message = "proof does not match the verification context: its tree size is 8, expected 7";
+    assert_eq!((status, body["error"].as_str().unwrap()), (400, message));
+}
+
+#[test]
+fn test_bad_requests() {
+    let server = Server::start("tests/fixtures/hashes.txt");
+
+    let (status, body) = server.get("/proof/7");
+    assert_eq!(status, 404);
+    assert!(body["error"].as_str().unwrap().contains("out of range"));
+    assert_eq!(server.get("/proof/abc").0, 400);
+
+    assert_eq!(server.post("/verify", &json!({ "leaf": "zz", "proof": [] })).0, 422);
+    assert_eq!(server.post("/verify", &json!({ "proof": [] })).0, 422);
+
+    // the tree is 3 levels deep, and no proof decodes with more than 64 steps
+    let (_, first) = server.get("/proof/0");
+    let deep = |steps: usize| {
+        let step = &first["proof"]["steps"][0];
+        json!({ "leaf": first["leaf"], "proof": { "version": 2, "steps": vec![step.clone(); steps] } })
+    };
+    let (status, body) = server.post("/verify", &deep(4));
+    assert_eq!((status, body["error"].as_str().unwrap()), (400, "a proof has at most 3 steps, found 4"));
+    assert_eq!(server.post("/verify", &deep(65)).0, 422);
+}
+
+#[test]
+fn test_service_limits() {
+    let server = Server::start_with("tests/fixtures/hashes.txt", &["--max-proofs", "3", "--max-batch", "6"]);
+
+    let (status, body) = server.post("/proofs", &json!({ "indices": [4, 1, 4] }));
+    assert_eq!(status, 200);
+    let indices: Vec<&Value> = body["proofs"].as_array().unwrap().iter().map(|proof| &proof["index"]).collect();
+    assert_eq!(indices, [4, 1]);
+    assert_eq!(body["proofs"][0], server.get("/proof/4").1);
+
+    let rejected = |indices: Value| {
+        let (status, body) = server.post("/proofs", &json!({ "indices": indices }));
+        (status, body["code"].as_str().unwrap().to_string())
+    };
+    assert_eq!(rejected(json!([0, 1, 2, 3, 4, 5, 6])), (400, "BATCH_TOO_LARGE".into()));
+    assert_eq!(rejected(json!([0, 1, 2, 3])), (400, "TOO_MANY_PROOFS".into()));
+    assert_eq!(rejected(json!([0, 0, 0, 0, 0, 1])), (400, "DUPLICATE_HEAVY".into()));
+    assert_eq!(rejected(json!([0, 7])), (400, "INDEX_OUT_OF_RANGE".into()));
+    assert_eq!(server.get("/proof/7").0, 404);
+    let (status, body) = server.get("/proofs/0/2");
+    assert_eq!((status, body["code"].as_str().unwrap()), (400, "RANGE_PROOFS_DISABLED"));
+
+    let metrics = json!({
+        "proofs_served": 3,
+        "rejected": {
+            "batch_too_large": 1,
+            "too_many_proofs": 1,
+            "duplicate_heavy": 1,
+            "out_of_range": 2,
+            "range_disabled": 1,
+        },
+    });
+    assert_eq!(server.get("/metrics"), (200, metrics));
+}
+
+#[test]
+fn test_range_proofs() {
+    let server = Server::start_with("tests/fixtures/hashes.txt", &["--allow-ranges", "--max-proofs", "4"]);
+    let (status, body) = server.get("/proofs/2/6");
+    assert_eq!(status, 200);
+    let proofs = body["proofs"].as_array().unwrap();
+    assert_eq!(proofs.iter().map(|proof| proof["index"].as_u64().unwrap()).collect::<Vec<_>>(), [2, 3, 4, 5]);
+    assert_eq!(proofs[3], server.get("/proof/5").1);
+    assert_eq!(server.get("/proofs/2/7").1["code"], "TOO_MANY_PROOFS");
+    assert_eq!(server.get("/proofs/5/8").1["code"], "INDEX_OUT_OF_RANGE");
+    assert_eq!(server.get("/proofs/5/5"), (200, json!({ "proofs": [] })));
+    assert_eq!(server.get("/metrics").1["proofs_served"], 5);
+}
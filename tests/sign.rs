@@ -0,0 +1,60 @@
+#![cfg(feature = "sign")]
+
+use assert_cmd::cargo::cargo_bin_cmd;
+
+use merkle::util::sign::SigningKey;
+
+use std::fs;
+
+fn run(args: &[&str]) -> (i32, String, String) {
+    let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+    (
+        output.status.code().unwrap_or(-1),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_sign_and_verify_root() {
+    let dir = std::env::temp_dir().join(format!("merkle-sign-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+    let key = SigningKey::from_bytes(&rand::random());
+    fs::write(path("key.priv"), hex::encode(key.to_bytes()) + "\n").unwrap();
+    fs::write(path("key.pub"), hex::encode(key.verifying_key().to_bytes()) + "\n").unwrap();
+    let other = SigningKey::from_bytes(&rand::random());
+    fs::write(path("other.pub"), hex::encode(other.verifying_key().to_bytes())).unwrap();
+    fs::write(path("broken.pub"), "not a key").unwrap();
+    let hashes = fs::read_to_string("tests/fixtures/hashes.txt").unwrap();
+    fs::write(path("hashes.txt"), &hashes).unwrap();
+    let tampered = hashes.replacen(&hashes[..1], if hashes.starts_with('0') { "1" } else { "0" }, 1);
+    fs::write(path("tampered.txt"), tampered).unwrap();
+
+    let (hashes, key, sig) = (path("hashes.txt"), path("key.priv"), path("root.sig"));
+    let (status, stdout, _) = run(&["sign-root", &hashes, "--key", &key, "--out", &sig]);
+    assert_eq!((status, stdout.as_str()), (0, ""));
+    let signed: serde_json::Value = serde_json::from_str(&fs::read_to_string(path("root.sig")).unwrap()).unwrap();
+    assert_eq!(signed["leaf_count"], 7);
+    assert_eq!(run(&["sign-root", &hashes, "--key", &key]).1, fs::read_to_string(&sig).unwrap());
+
+    let verify = |input: &str, key: &str| {
+        run(&["verify-signed", "--input", &path(input), "--root-sig", &sig, "--pubkey", &path(key)])
+    };
+    let (status, stdout, _) = verify("hashes.txt", "key.pub");
+    assert_eq!(status, 0);
+    assert!(stdout.starts_with("valid: "), "{}", stdout);
+
+    let (status, _, stderr) = verify("tampered.txt", "key.pub");
+    assert_eq!(status, 2);
+    assert!(stderr.contains("over 7 leaves, not the signed"), "{}", stderr);
+    let (status, _, stderr) = verify("hashes.txt", "other.pub");
+    assert_eq!(status, 2);
+    assert!(stderr.contains("the signature does not cover"), "{}", stderr);
+    let (status, _, stderr) = verify("hashes.txt", "broken.pub");
+    assert_eq!(status, 1);
+    assert!(stderr.contains("invalid key"), "{}", stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,41 @@
+#![cfg(feature = "solidity")]
+
+use assert_cmd::cargo::cargo_bin_cmd;
+
+use merkle::util::solidity::{verify_claim, Claim};
+
+use std::fs;
+
+#[test]
+fn test_export_solidity() {
+    let dir = std::env::temp_dir().join(format!("merkle-solidity-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (claims_path, verifier_path) = (dir.join("claims.json"), dir.join("verifier.sol"));
+
+    let output = cargo_bin_cmd!("merkle")
+        .args(["export-solidity", "--input", "tests/fixtures/claims.csv", "--out"])
+        .args([&verifier_path, &claims_path])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let root = String::from_utf8(output.stdout).unwrap().trim().to_string();
+
+    let claims: serde_json::Value = serde_json::from_slice(&fs::read(&claims_path).unwrap()).unwrap();
+    assert_eq!(claims["root"], root);
+    let source = fs::read_to_string(&verifier_path).unwrap();
+    assert!(source.contains(&format!("bytes32 public constant MERKLE_ROOT = {};", root)));
+
+    let decode = |value: &serde_json::Value| hex::decode(&value.as_str().unwrap()[2..]).unwrap();
+    let entries = claims["claims"].as_array().unwrap();
+    assert_eq!(entries.len(), 3);
+    for (index, entry) in entries.iter().enumerate() {
+        assert_eq!(entry["index"], index);
+        let claim = Claim {
+            address: decode(&entry["address"]).try_into().unwrap(),
+            amount: entry["amount"].as_str().unwrap().parse().unwrap(),
+        };
+        let proof: Vec<Vec<u8>> = entry["proof"].as_array().unwrap().iter().map(decode).collect();
+        assert!(verify_claim(&claim, &proof, &decode(&claims["root"])));
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,56 @@
+#![cfg(feature = "sqlite")]
+
+use assert_cmd::cargo::cargo_bin_cmd;
+
+use merkle::util::sqlite::Connection;
+
+use std::fs;
+
+fn run(args: &[&str]) -> (i32, String, String) {
+    let output = cargo_bin_cmd!("merkle").args(args).output().unwrap();
+    (
+        output.status.code().unwrap_or(-1),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_root_from_sqlite() {
+    let path = std::env::temp_dir().join(format!("merkle-sqlite-cli-{}.db", std::process::id()));
+    let db = path.to_str().unwrap();
+    let (status, expected, _) = run(&["root", "tests/fixtures/hashes.txt"]);
+    assert_eq!(status, 0);
+
+    // the hashes of hashes.txt, inserted last first so the table order is not the file order
+    let connection = Connection::open_in_memory().unwrap();
+    connection.execute_batch("CREATE TABLE txs (id INTEGER, txid TEXT)").unwrap();
+    let hashes = fs::read_to_string("tests/fixtures/hashes.txt").unwrap();
+    for (id, txid) in hashes.lines().collect::<Vec<_>>().into_iter().enumerate().rev() {
+        connection.execute("INSERT INTO txs VALUES (?1, ?2)", (id as i64, txid)).unwrap();
+    }
+    connection.execute("VACUUM INTO ?1", [db]).unwrap();
+
+    let input = format!("sqlite:{}?query=SELECT txid FROM txs ORDER BY id", db);
+    assert_eq!(run(&["root", "--input", &input]), (0, expected.clone(), String::new()));
+    let reversed = format!("sqlite:{}?query=SELECT txid FROM txs ORDER BY id DESC", db);
+    let (status, root, _) = run(&["root", "--input", &reversed]);
+    assert_eq!(status, 0);
+    assert_ne!(root, expected);
+    let table = format!("sqlite:{}?table=txs&column=txid", db);
+    let (status, root, _) = run(&["root", "--input", &table, "--output", "json"]);
+    let json: serde_json::Value = serde_json::from_str(&root).unwrap();
+    assert_eq!((status, json["leaf_count"].as_u64()), (0, Some(7)));
+
+    let unordered = format!("sqlite:{}?query=SELECT txid FROM txs", db);
+    let (status, _, stderr) = run(&["root", "--input", &unordered]);
+    assert_eq!(status, 1);
+    assert!(stderr.starts_with("error: invalid format: the query has no ORDER BY"), "{}", stderr);
+    let (status, _, stderr) = run(&["root", "--input", &format!("sqlite:{}?table=missing", db)]);
+    assert_eq!(status, 4);
+    assert!(stderr.starts_with("error: database error: no such table"), "{}", stderr);
+    let (status, _, stderr) = run(&["root", "tests/fixtures/hashes.txt", "--input", &input]);
+    assert!(stderr.starts_with("error: usage: merkle root"), "{}", stderr);
+    assert_eq!(status, 1);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,133 @@
+#![cfg(feature = "test-utils")]
+
+//! Proof shapes, index math, consistency and diffs of large trees
+//!
+//! The structure of a tree does not depend on its hasher, so these run over millions of leaves
+//! with the cheap `XorFoldHasher`, and again at a smaller size with SHA-256 so the real hasher
+//! goes through the same checks.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use merkle::merkel::{OddLeafPolicy, XorFoldHasher};
+use merkle::prelude::*;
+use merkle::util::diff::{diff_trees, DiffKind};
+
+const LARGE: usize = 1 << 20;
+
+fn leaves(hasher: HashAlgorithm, n: usize) -> Vec<Hash> {
+    (0..n as u64).map(|i| hasher.leaf_hash(&i.to_le_bytes())).collect()
+}
+
+fn config(hasher: HashAlgorithm, policy: OddLeafPolicy) -> TreeConfig {
+    TreeConfig::new(LeafMode::PreHashed).with_hasher(hasher).with_odd_leaf_policy(policy)
+}
+
+/// Every leaf of the tree has a proof no longer than the tree is deep that leads to the root, and
+/// whose directions spell out its index
+fn check_every_proof(tree: &MerkleTree, indices: impl Iterator<Item = usize>) {
+    let (hasher, root) = (tree.hasher(), tree.root());
+    for index in indices {
+        let proof = tree.prove_by_index(index).unwrap();
+        assert_eq!(proof.len(), tree.proof_len(index).unwrap(), "leaf {} of {}", index, tree.len());
+        assert!(proof.len() <= tree.depth());
+        let leaf = &tree.leaves()[index];
+        assert!(MerkleTree::verify_proof_hash_with(&hasher, leaf, &proof, &root), "leaf {} of {}", index, tree.len());
+        if tree.odd_leaf_policy() == OddLeafPolicy::Duplicate {
+            assert_eq!(proof.implied_index().unwrap(), index, "leaf {} of {}", index, tree.len());
+        }
+    }
+}
+
+#[test]
+fn test_every_proof_of_a_large_tree() {
+    let hasher = HashAlgorithm::XorFold;
+    let tree = config(hasher, OddLeafPolicy::Duplicate).construct(&leaves(hasher, LARGE)).unwrap();
+    assert_eq!((tree.len(), tree.depth()), (LARGE, 20));
+    check_every_proof(&tree, 0..LARGE);
+
+    // one leaf past a power of two pads every level, so its proof is one step longer
+    let mut rng = StdRng::seed_from_u64(0x7374727563);
+    for (size, policy) in [(LARGE + 1, OddLeafPolicy::Duplicate), (LARGE - 3, OddLeafPolicy::Promote)] {
+        let tree = config(hasher, policy).construct(&leaves(hasher, size)).unwrap();
+        let sampled = (0..2_000).map(|_| rng.random_range(0..size));
+        check_every_proof(&tree, sampled.chain([0, size / 2, size - 2, size - 1]));
+    }
+}
+
+#[test]
+fn test_every_proof_with_sha256() {
+    let hasher = HashAlgorithm::Sha256;
+    for size in [1, 2, 3, 1023, 1024, 1025] {
+        for policy in [OddLeafPolicy::Duplicate, OddLeafPolicy::Promote] {
+            let tree = config(hasher, policy).construct(&leaves(hasher, size)).unwrap();
+            check_every_proof(&tree, 0..size);
+        }
+    }
+}
+
+fn check_consistency(hasher: HashAlgorithm, size: usize, old_sizes: &[usize]) {
+    let config = config(hasher, OddLeafPolicy::Duplicate);
+    let leaves = leaves(hasher, size);
+    let tree = config.construct(&leaves).unwrap();
+    for &old_size in old_sizes {
+        let old_root = config.construct(&leaves[..old_size]).unwrap().root();
+        let proof = tree.prove_consistency(old_size).unwrap();
+        assert_eq!(proof.subtrees().len(), old_size.count_ones() as usize);
+        assert!(proof.verify_with(&hasher, &old_root, &tree.root()), "{} of {}", old_size, size);
+        let mut other = leaves[..old_size].to_vec();
+        other[old_size - 1] = hasher.leaf_hash(b"rewritten");
+        let rewritten = config.construct(&other).unwrap().root();
+        assert!(!proof.verify_with(&hasher, &rewritten, &tree.root()), "{} of {}", old_size, size);
+    }
+}
+
+#[test]
+fn test_consistency_of_large_prefixes() {
+    let size = (1 << 17) + 5;
+    let mut old_sizes: Vec<usize> =
+        (0..17).flat_map(|k| [(1 << k) - 1, 1 << k, (1 << k) + 1]).filter(|&m| m > 0).collect();
+    let mut rng = StdRng::seed_from_u64(0x636f6e73);
+    old_sizes.extend((0..16).map(|_| rng.random_range(1..=size)));
+    old_sizes.push(size);
+    check_consistency(HashAlgorithm::XorFold, size, &old_sizes);
+    // and against every prefix of a small tree with the real hasher
+    check_consistency(HashAlgorithm::Sha256, 70, &(1..=70).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_diff_of_large_trees() {
+    let hasher = HashAlgorithm::XorFold;
+    let config = config(hasher, OddLeafPolicy::Duplicate);
+    let old = leaves(hasher, 1 << 18);
+    let old_tree = config.construct(&old).unwrap();
+
+    let mut changed = old.clone();
+    let edits = [0, 777, 1 << 17, (1 << 18) - 1];
+    for &index in &edits {
+        changed[index] = XorFoldHasher.leaf_hash(b"edited");
+    }
+    let report = diff_trees(&old_tree, &config.construct(&changed).unwrap(), 10).unwrap();
+    assert_eq!((report.kind, report.differing_count), (DiffKind::Changed, 4));
+    assert_eq!(report.differing, edits);
+
+    let mut extended = old.clone();
+    extended.extend((0..1000).map(|i: u32| XorFoldHasher.leaf_hash(&i.to_be_bytes())));
+    let extended = config.construct(&extended).unwrap();
+    let report = diff_trees(&old_tree, &extended, 10).unwrap();
+    assert_eq!((report.kind, report.added, report.differing_count), (DiffKind::Extended, 1000, 0));
+    let report = diff_trees(&extended, &old_tree, 10).unwrap();
+    assert_eq!((report.kind, report.removed), (DiffKind::Truncated, 1000));
+
+    // a truncated tree with a changed leaf has diverged at exactly that leaf
+    changed.truncate(100_001);
+    let report = diff_trees(&old_tree, &config.construct(&changed).unwrap(), 10).unwrap();
+    assert_eq!((report.kind, report.removed), (DiffKind::Diverged, 162_143));
+    assert_eq!(report.differing, [0, 777]);
+
+    let sha256 = TreeConfig::new(LeafMode::PreHashed);
+    let small = leaves(HashAlgorithm::Sha256, 33);
+    let (whole, prefix) = (sha256.construct(&small).unwrap(), sha256.construct(&small[..20]).unwrap());
+    let report = diff_trees(&whole, &prefix, 10).unwrap();
+    assert_eq!((report.kind, report.removed), (DiffKind::Truncated, 13));
+}